@@ -0,0 +1,124 @@
+// This is free and unencumbered software released into the public domain.
+
+package sh.asimov.camera
+
+import android.view.Surface
+import java.io.Closeable
+import java.nio.ByteBuffer
+import java.util.concurrent.locks.ReentrantReadWriteLock
+import kotlin.concurrent.read
+import kotlin.concurrent.write
+
+/**
+ * Kotlin wrapper around the Camera2 NDK driver in `libasimov_camera_module.so`
+ * (built with `--features=asimov_camera_jni`).
+ *
+ * `format` is one of the `FORMAT_` constants to receive frames in, converting
+ * them where the camera delivers another, or [FORMAT_ANY] for whatever it
+ * captures.
+ *
+ * Every method is thread-safe: calls from different threads take turns on the
+ * camera, [start], [stop] and [close] may be repeated, and calls after [close]
+ * throw `IllegalStateException` (except [stop] and [close], which do nothing).
+ * Avoid [stop] and [close] inside the frame callback, since they wait for it to
+ * return.
+ */
+class AsimovCamera(
+    device: String? = null,
+    width: Int = 640,
+    height: Int = 480,
+    fps: Double = 30.0,
+    format: Int = FORMAT_ANY,
+) : Closeable {
+    fun interface FrameCallback {
+        /**
+         * Called on the camera dispatch thread. `data` is only valid for the
+         * duration of the call, and `format` is the `FORMAT_` constant it's in.
+         */
+        fun onFrame(data: ByteBuffer, width: Int, height: Int, stride: Int, format: Int, timestampNs: Long)
+    }
+
+    fun interface EventCallback {
+        /**
+         * Called on a thread of its own for each camera event. `kind` names
+         * it (`Started`, `Stopped`, `Warning`, `Stalled`, `Error`, ...) and
+         * `message` describes it, or is empty.
+         */
+        fun onEvent(kind: String, message: String)
+    }
+
+    /** Frame counters since the camera was opened. */
+    data class Stats(val framesCaptured: Long, val framesDropped: Long, val framesDelivered: Long)
+
+    /** Held for reading by every native call, and for writing to close. */
+    private val lock = ReentrantReadWriteLock()
+    private var handle: Long = nativeOpen(device, width, height, fps, format)
+
+    fun setFrameCallback(callback: FrameCallback) = withHandle { nativeSetFrameCallback(it, callback) }
+
+    /** Can only be set once; errors and device loss arrive as `Error` events. */
+    fun setEventCallback(callback: EventCallback) = withHandle { nativeSetEventCallback(it, callback) }
+
+    fun stats(): Stats {
+        val (captured, dropped, delivered) = withHandle { nativeGetStats(it) }
+        return Stats(captured, dropped, delivered)
+    }
+
+    fun setPreviewSurface(surface: Surface) = withHandle { nativeSetPreviewSurface(it, surface) }
+
+    fun start(): Boolean = withHandle { nativeStart(it) }
+
+    fun stop() = lock.read {
+        if (handle != 0L) nativeStop(handle)
+    }
+
+    /** Stops delivering frames while keeping the camera open; see [resume]. */
+    fun pause(): Boolean = withHandle { nativePause(it) }
+
+    fun resume(): Boolean = withHandle { nativeResume(it) }
+
+    /**
+     * Takes a full-resolution still through the still-capture pipeline and
+     * returns the encoded JPEG (or HEIC, with `heif`, on Android 10+).
+     */
+    fun capturePhoto(heif: Boolean = false): ByteArray = withHandle { nativeCapturePhoto(it, heif) }
+
+    override fun close() {
+        // Calls in progress finish first; later ones see the camera closed
+        // without waiting for it to stop.
+        val closing = lock.write { handle.also { handle = 0L } }
+        if (closing != 0L) nativeClose(closing)
+    }
+
+    private inline fun <T> withHandle(call: (Long) -> T): T = lock.read {
+        check(handle != 0L) { "camera is closed" }
+        call(handle)
+    }
+
+    companion object {
+        const val FORMAT_ANY = -1
+        const val FORMAT_RGB8 = 0
+        const val FORMAT_BGRA8 = 1
+        const val FORMAT_RGBA8 = 2
+        const val FORMAT_GRAY16 = 3
+        const val FORMAT_Z16 = 4
+        const val FORMAT_P010 = 5
+        const val FORMAT_RGBA1010102 = 6
+
+        init {
+            System.loadLibrary("asimov_camera_module")
+        }
+
+        @JvmStatic private external fun nativeOpen(device: String?, width: Int, height: Int, fps: Double, format: Int): Long
+        @JvmStatic private external fun nativeSetFrameCallback(handle: Long, callback: FrameCallback)
+        @JvmStatic private external fun nativeSetEventCallback(handle: Long, callback: EventCallback)
+        @JvmStatic private external fun nativeGetStats(handle: Long): LongArray
+        @JvmStatic private external fun nativeSetPreviewSurface(handle: Long, surface: Surface)
+        @JvmStatic private external fun nativeStart(handle: Long): Boolean
+        @JvmStatic private external fun nativeStop(handle: Long)
+        @JvmStatic private external fun nativePause(handle: Long): Boolean
+        @JvmStatic private external fun nativeResume(handle: Long): Boolean
+        @JvmStatic private external fun nativeCapturePhoto(handle: Long, heif: Boolean): ByteArray
+        @JvmStatic private external fun nativeClose(handle: Long)
+    }
+}
@@ -0,0 +1,75 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Compares hashing a 1080p RGB frame for the debounce at full size with
+//! hashing the box-scaled thumbnail the debouncer makes by default.
+//!
+//! Run with `cargo bench --bench debounce`.
+
+use asimov_camera_module::shared::{
+    DEFAULT_PRESCALE_WIDTH, DebounceConfig, Debouncer, Frame, PixelFormat,
+};
+use bytes::Bytes;
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+const ROUNDS: u32 = 10;
+
+fn main() {
+    let frame = test_frame();
+    println!("{WIDTH}x{HEIGHT} rgb8, {ROUNDS} rounds");
+
+    let full = Debouncer::new(DebounceConfig::default().with_prescale(None));
+    let prescaled = Debouncer::new(DebounceConfig::default());
+    let full_time = time(|| full.hash(&frame).unwrap());
+    let prescaled_time = time(|| prescaled.hash(&frame).unwrap());
+    println!(
+        "full size {:>8.2} ms   prescaled to {DEFAULT_PRESCALE_WIDTH}px {:>8.2} ms   {:.1}x",
+        full_time.as_secs_f64() * 1e3,
+        prescaled_time.as_secs_f64() * 1e3,
+        full_time.as_secs_f64() / prescaled_time.as_secs_f64()
+    );
+    let distance = full
+        .hash(&frame)
+        .unwrap()
+        .dist(&prescaled.hash(&frame).unwrap());
+    println!("hashes differ by {distance} bits");
+}
+
+/// A smooth scene with some per-pixel noise, like a camera sees it.
+fn test_frame() -> Frame {
+    let mut state = 0x9E37_79B9u32;
+    let mut data = Vec::with_capacity((WIDTH * HEIGHT * 3) as usize);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let noise = state % 16;
+            data.extend_from_slice(&[
+                (x * 200 / WIDTH + noise) as u8,
+                (y * 200 / HEIGHT + noise) as u8,
+                ((x + y) * 100 / (WIDTH + HEIGHT) + noise) as u8,
+            ]);
+        }
+    }
+    Frame::new(
+        Bytes::from(data),
+        WIDTH,
+        HEIGHT,
+        WIDTH * 3,
+        PixelFormat::Rgb8,
+    )
+}
+
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    black_box(f());
+    let started = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(f());
+    }
+    started.elapsed() / ROUNDS
+}
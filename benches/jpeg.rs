@@ -0,0 +1,86 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Compares `Frame::to_jpeg` with the pure-Rust encoder of the `image`
+//! crate on a 1080p BGRA frame, as `--serve-mjpeg` encodes them.
+//!
+//! Run with `cargo bench --bench jpeg`, and again with `--features mozjpeg`
+//! to see libjpeg-turbo's SIMD encoder.
+
+use asimov_camera_module::shared::{Frame, JPEG_ENCODER, PixelFormat};
+use bytes::Bytes;
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+const QUALITY: u8 = 80;
+const ROUNDS: u32 = 20;
+
+fn main() {
+    let frame = test_frame();
+    println!(
+        "{WIDTH}x{HEIGHT} bgra8 at quality {QUALITY}, {ROUNDS} rounds, encoder {JPEG_ENCODER}"
+    );
+
+    let image = time(|| image_jpeg(&frame));
+    let crate_time = time(|| frame.to_jpeg(QUALITY).unwrap());
+    for (name, elapsed) in [("image", image), ("crate", crate_time)] {
+        println!(
+            "{name:<6} {:>7.2} ms per frame, {:>3.0}% of a core at 30 fps",
+            elapsed.as_secs_f64() * 1e3,
+            elapsed.as_secs_f64() * 30.0 * 100.0
+        );
+    }
+}
+
+/// A smooth scene with some per-pixel noise, like a camera sees it.
+fn test_frame() -> Frame {
+    let mut state = 0x9E37_79B9u32;
+    let mut data = Vec::with_capacity((WIDTH * HEIGHT * 4) as usize);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let noise = state % 16;
+            data.extend_from_slice(&[
+                ((x + y) * 100 / (WIDTH + HEIGHT) + noise) as u8,
+                (y * 200 / HEIGHT + noise) as u8,
+                (x * 200 / WIDTH + noise) as u8,
+                255,
+            ]);
+        }
+    }
+    Frame::new(
+        Bytes::from(data),
+        WIDTH,
+        HEIGHT,
+        WIDTH * 4,
+        PixelFormat::Bgra8,
+    )
+}
+
+fn image_jpeg(frame: &Frame) -> Vec<u8> {
+    let rgb = frame.to_rgb8().unwrap();
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, QUALITY)
+        .encode(
+            &rgb.data,
+            rgb.width,
+            rgb.height,
+            image::ExtendedColorType::Rgb8,
+        )
+        .unwrap();
+    jpeg
+}
+
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    black_box(f());
+    let started = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(f());
+    }
+    started.elapsed() / ROUNDS
+}
@@ -3,18 +3,54 @@
 #[cfg(not(feature = "std"))]
 compile_error!("asimov-camera-cataloger requires the 'std' feature");
 
-use asimov_camera_module::{cli, shared::CameraError};
+use asimov_camera_module::{
+    cli,
+    shared::{CameraError, devices::controls::CameraControl},
+};
 use asimov_module::SysexitsError::{self, *};
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use clientele::StandardOptions;
 use serde_json::json;
-use std::error::Error as StdError;
+use std::{error::Error as StdError, path::PathBuf};
 
 #[derive(Debug, Parser)]
 struct Options {
     #[clap(flatten)]
     flags: StandardOptions,
 
+    /// Read option defaults from this TOML file (default: ./asimov-camera.toml)
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Also list each device's controls (brightness, exposure, focus, …) with their ranges and values
+    #[arg(long)]
+    controls: bool,
+
+    /// Also discover NDI sources on the network, through the NDI runtime
+    #[cfg(feature = "ndi")]
+    #[arg(long)]
+    ndi: bool,
+
+    /// How long to wait for NDI sources to announce themselves, in seconds
+    #[cfg(feature = "ndi")]
+    #[arg(long, value_name = "SECS", default_value = "3")]
+    ndi_timeout: u64,
+
+    /// Also discover ONVIF cameras on the local network (WS-Discovery) and list their RTSP streams
+    #[cfg(feature = "onvif")]
+    #[arg(long)]
+    onvif: bool,
+
+    /// How long to wait for ONVIF cameras to answer, in seconds
+    #[cfg(feature = "onvif")]
+    #[arg(long, value_name = "SECS", default_value = "3")]
+    onvif_timeout: u64,
+
+    /// Ask ONVIF cameras for their streams as this user, with the password in $ONVIF_PASSWORD
+    #[cfg(feature = "onvif")]
+    #[arg(long, value_name = "NAME")]
+    onvif_user: Option<String>,
+
     #[arg(
         value_name = "FORMAT",
         short = 'o',
@@ -34,6 +70,14 @@ enum OutputFormat {
 pub fn main() -> Result<SysexitsError, Box<dyn StdError>> {
     asimov_module::dotenv().ok();
     let args = asimov_module::args_os()?;
+    let (args, config_path) =
+        match cli::apply_config_defaults(Options::command(), "cataloger", args) {
+            Ok(v) => v,
+            Err(err) => {
+                eprintln!("ERROR: {err}");
+                return Ok(EX_CONFIG);
+            },
+        };
     let options = Options::parse_from(args);
 
     if options.flags.version {
@@ -49,6 +93,12 @@ pub fn main() -> Result<SysexitsError, Box<dyn StdError>> {
     #[cfg(feature = "tracing")]
     asimov_module::init_tracing_subscriber(&options.flags).expect("failed to initialize logging");
 
+    if let Some(path) = &config_path
+        && (options.flags.debug || options.flags.verbose >= 1)
+    {
+        eprintln!("INFO: using configuration from {}", path.display());
+    }
+
     let exit_code = match run_cataloger(&options) {
         Ok(()) => EX_OK,
         Err(err) => handle_error(&err, &options.flags),
@@ -63,33 +113,292 @@ fn run_cataloger(options: &Options) -> Result<(), CameraError> {
     }
 
     let mut devices = cli::list_video_devices(&options.flags)?;
-    if devices.is_empty() {
-        if options.flags.debug || options.flags.verbose >= 1 {
-            eprintln!("WARN: no camera devices found");
-        }
-        return Ok(());
+    if devices.is_empty() && (options.flags.debug || options.flags.verbose >= 1) {
+        eprintln!("WARN: no camera devices found");
     }
 
     devices.sort_by(|a, b| a.id.cmp(&b.id).then_with(|| a.name.cmp(&b.name)));
 
     for d in devices {
+        let controls = if options.controls {
+            device_controls(&d)
+        } else {
+            None
+        };
         match options.output {
             OutputFormat::Text => {
-                if d.is_usb {
-                    println!("{}: {} [usb]", d.id, d.name);
-                } else {
-                    println!("{}: {}", d.id, d.name);
+                let usb = d.usb_tag();
+                match &d.unique_id {
+                    Some(uid) => {
+                        println!("{}: {}{usb} ({}{uid})", d.id, d.name, cli::UNIQUE_ID_PREFIX)
+                    },
+                    None => println!("{}: {}{usb}", d.id, d.name),
+                }
+                for control in controls.iter().flatten() {
+                    println!("    {}", describe_control(control));
                 }
             },
             OutputFormat::Jsonl => {
-                println!("{}", json!({ "id": d.id, "name": d.name, "usb": d.is_usb }));
+                let usb_device = d.usb.as_ref().map(|usb| {
+                    json!({
+                        "vendorId": format!("{:04x}", usb.vendor_id),
+                        "productId": format!("{:04x}", usb.product_id),
+                        "serialNumber": usb.serial,
+                        "busPath": usb.bus_path,
+                    })
+                });
+                let mut record = json!({
+                    "id": d.id,
+                    "name": d.name,
+                    "usb": d.is_usb,
+                    "uniqueId": d.unique_id,
+                    "builtin": d.builtin,
+                    "usbDevice": usb_device,
+                });
+                if options.controls {
+                    record["controls"] = controls
+                        .map(|controls| controls.iter().map(control_json).collect())
+                        .unwrap_or_default();
+                }
+                println!("{record}");
             },
         }
     }
 
+    #[cfg(feature = "ndi")]
+    if options.ndi {
+        list_ndi_sources(options)?;
+    }
+
+    #[cfg(feature = "onvif")]
+    if options.onvif {
+        list_onvif_cameras(options)?;
+    }
+
     Ok(())
 }
 
+/// Lists the NDI sources that announce themselves on the network.
+#[cfg(feature = "ndi")]
+fn list_ndi_sources(options: &Options) -> Result<(), CameraError> {
+    use asimov_camera_module::shared::find_ndi_sources;
+
+    let verbose = options.flags.debug || options.flags.verbose >= 1;
+    if verbose {
+        eprintln!("INFO: discovering NDI sources for {}s", options.ndi_timeout);
+    }
+    let sources = find_ndi_sources(std::time::Duration::from_secs(options.ndi_timeout), None)?;
+    if sources.is_empty() && verbose {
+        eprintln!("WARN: no NDI sources found");
+    }
+
+    for source in sources {
+        match options.output {
+            OutputFormat::Text => match &source.address {
+                Some(address) => println!("{}: NDI source ({address})", source.device_id()),
+                None => println!("{}: NDI source", source.device_id()),
+            },
+            OutputFormat::Jsonl => {
+                let record = json!({
+                    "id": source.device_id(),
+                    "name": source.name,
+                    "ndi": { "address": source.address },
+                });
+                println!("{record}");
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Lists the ONVIF cameras that answer a WS-Discovery probe, with the RTSP
+/// URI of each of their media profiles where they give them.
+#[cfg(feature = "onvif")]
+fn list_onvif_cameras(options: &Options) -> Result<(), CameraError> {
+    use asimov_camera_module::shared::discover;
+
+    let verbose = options.flags.debug || options.flags.verbose >= 1;
+    if verbose {
+        eprintln!(
+            "INFO: discovering ONVIF cameras for {}s",
+            options.onvif_timeout
+        );
+    }
+    let cameras = discover(std::time::Duration::from_secs(options.onvif_timeout))?;
+    if cameras.is_empty() && verbose {
+        eprintln!("WARN: no ONVIF cameras answered");
+    }
+
+    for camera in cameras {
+        let Some(id) = camera.device_id() else {
+            if verbose {
+                eprintln!("WARN: {} has no http:// device service", camera.endpoint);
+            }
+            continue;
+        };
+        let name = camera
+            .name()
+            .or_else(|| camera.hardware())
+            .unwrap_or_else(|| "ONVIF camera".to_string());
+        let streams = onvif_streams(&id, options.onvif_user.as_deref());
+        match options.output {
+            OutputFormat::Text => {
+                match camera.hardware().filter(|hardware| *hardware != name) {
+                    Some(hardware) => println!("{id}: {name} [{hardware}]"),
+                    None => println!("{id}: {name}"),
+                }
+                for (profile, uri) in &streams {
+                    let mut line = format!("    {id}?profile={}: {}", profile.token, profile.name);
+                    if let (Some(width), Some(height)) = (profile.width, profile.height) {
+                        line += &format!(" {width}x{height}");
+                    }
+                    if let Some(encoding) = &profile.encoding {
+                        line += &format!(" {encoding}");
+                    }
+                    if let Some(uri) = uri {
+                        line += &format!(" ({uri})");
+                    }
+                    println!("{line}");
+                }
+            },
+            OutputFormat::Jsonl => {
+                let record = json!({
+                    "id": id,
+                    "name": name,
+                    "onvif": {
+                        "endpoint": camera.endpoint,
+                        "xaddrs": camera.xaddrs,
+                        "hardware": camera.hardware(),
+                        "location": camera.location(),
+                    },
+                    "streams": streams
+                        .iter()
+                        .map(|(profile, uri)| json!({
+                            "device": format!("{id}?profile={}", profile.token),
+                            "profile": profile.token,
+                            "name": profile.name,
+                            "encoding": profile.encoding,
+                            "width": profile.width,
+                            "height": profile.height,
+                            "uri": uri,
+                        }))
+                        .collect::<Vec<_>>(),
+                });
+                println!("{record}");
+            },
+        }
+    }
+    Ok(())
+}
+
+/// The media profiles of the ONVIF camera `id`, each with its RTSP URI, as
+/// far as the camera answers; most need a user for either.
+#[cfg(feature = "onvif")]
+fn onvif_streams(
+    id: &str,
+    user: Option<&str>,
+) -> Vec<(asimov_camera_module::shared::OnvifProfile, Option<String>)> {
+    use asimov_camera_module::shared::OnvifClient;
+
+    let client = match OnvifClient::from_device(id) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("WARN: {err}");
+            return Vec::new();
+        },
+    };
+    let client = match user {
+        Some(user) => {
+            client.with_credentials(user, std::env::var("ONVIF_PASSWORD").unwrap_or_default())
+        },
+        None => client,
+    };
+    let profiles = match client.profiles() {
+        Ok(profiles) => profiles,
+        Err(err) => {
+            eprintln!("WARN: {err}");
+            return Vec::new();
+        },
+    };
+    profiles
+        .into_iter()
+        .map(|profile| {
+            let uri = client
+                .stream_uri(&profile.token)
+                .map_err(|err| eprintln!("WARN: {err}"))
+                .ok();
+            (profile, uri)
+        })
+        .collect()
+}
+
+/// The controls of `device`, where the platform can enumerate them.
+fn device_controls(device: &cli::DeviceInfo) -> Option<Vec<CameraControl>> {
+    #[cfg(target_os = "linux")]
+    if let Some(path) = device.id.strip_prefix("file:") {
+        match asimov_camera_module::shared::devices::controls::v4l2_controls(path) {
+            Ok(controls) => return Some(controls),
+            Err(err) => eprintln!("WARN: {}: cannot list controls: {err}", device.id),
+        }
+    }
+    let _ = device;
+    None
+}
+
+/// A control in the style of `v4l2-ctl -L`, e.g.
+/// `exposure_auto (menu): min=0 max=3 default=3 value=3 [1: Manual Mode, 3: Aperture Priority Mode]`.
+fn describe_control(control: &CameraControl) -> String {
+    let mut line = format!(
+        "{} ({}): min={} max={}",
+        control.name, control.kind, control.minimum, control.maximum
+    );
+    if control.step > 1 {
+        line += &format!(" step={}", control.step);
+    }
+    line += &format!(" default={}", control.default);
+    if let Some(value) = control.value {
+        line += &format!(" value={value}");
+    }
+    for (flag, set) in [
+        ("read-only", control.read_only),
+        ("inactive", control.inactive),
+    ] {
+        if set {
+            line += &format!(" {flag}");
+        }
+    }
+    if !control.menu.is_empty() {
+        let items = control
+            .menu
+            .iter()
+            .map(|(index, name)| format!("{index}: {name}"))
+            .collect::<Vec<_>>();
+        line += &format!(" [{}]", items.join(", "));
+    }
+    line
+}
+
+fn control_json(control: &CameraControl) -> serde_json::Value {
+    json!({
+        "id": control.id,
+        "name": control.name,
+        "label": control.label,
+        "type": control.kind.as_str(),
+        "minimum": control.minimum,
+        "maximum": control.maximum,
+        "step": control.step,
+        "default": control.default,
+        "value": control.value,
+        "readOnly": control.read_only,
+        "inactive": control.inactive,
+        "menu": control
+            .menu
+            .iter()
+            .map(|(index, name)| json!({ "index": index, "name": name }))
+            .collect::<Vec<_>>(),
+    })
+}
+
 fn handle_error(err: &CameraError, flags: &StandardOptions) -> SysexitsError {
     use std::error::Error as _;
     use std::io::Write;
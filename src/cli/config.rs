@@ -0,0 +1,302 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::CameraError;
+use clap::{ArgAction, Command, parser::ValueSource};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// File name looked up in the working and user configuration directories.
+pub const CONFIG_FILE_NAME: &str = "asimov-camera.toml";
+
+/// Prefix of the environment variables that override configuration keys,
+/// e.g. `ASIMOV_CAMERA_SAVE_DIR` for `save-dir`.
+pub const CONFIG_ENV_PREFIX: &str = "ASIMOV_CAMERA_";
+
+/// Option defaults read from an `asimov-camera.toml` file.
+///
+/// Keys are the binaries' long option names. Top-level keys apply to every
+/// binary that has the option; a `[reader]`, `[cataloger]` or `[doctor]`
+/// table applies to that binary only and takes precedence. Named
+/// `[profiles.<name>]` tables, selected with `--profile`, override both:
+///
+/// ```toml
+/// device = "file:/dev/video0"
+/// size = "1280x720"
+///
+/// [reader]
+/// frequency = 5
+/// debounce-distance = 4
+/// output = "metadata"
+/// save-dir = "/var/lib/asimov/camera"
+/// notify = ["lost=system"]
+///
+/// [profiles.door-cam]
+/// device = "dshow:video=USB Video Device"
+/// rotate = 180
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    table: toml::Table,
+}
+
+impl ConfigFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CameraError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            CameraError::invalid_config(format!("cannot read {}: {e}", path.display()))
+        })?;
+        let table = text
+            .parse::<toml::Table>()
+            .map_err(|e| CameraError::invalid_config(format!("{}: {e}", path.display())))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            table,
+        })
+    }
+
+    /// Loads the file named by `ASIMOV_CAMERA_CONFIG`, else the first
+    /// `asimov-camera.toml` found in the working directory or the user's
+    /// configuration directory.
+    pub fn discover() -> Result<Option<Self>, CameraError> {
+        if let Some(path) = std::env::var_os("ASIMOV_CAMERA_CONFIG") {
+            return Self::load(path).map(Some);
+        }
+        let candidates = [
+            Some(PathBuf::from(CONFIG_FILE_NAME)),
+            user_config_dir().map(|dir| dir.join(CONFIG_FILE_NAME)),
+        ];
+        match candidates.into_iter().flatten().find(|path| path.is_file()) {
+            Some(path) => Self::load(path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the value for `key`, with the `[profiles.<profile>]` table
+    /// overriding the binary's `section` table, and that the top level.
+    fn get(&self, section: &str, profile: Option<&str>, key: &str) -> Option<&toml::Value> {
+        let profiled = profile
+            .and_then(|name| self.profile(name))
+            .and_then(|t| t.get(key));
+        let scoped = self
+            .table
+            .get(section)
+            .and_then(|t| t.as_table())
+            .and_then(|t| t.get(key));
+        profiled
+            .or(scoped)
+            .or_else(|| self.table.get(key).filter(|v| !v.is_table()))
+    }
+
+    fn profile(&self, name: &str) -> Option<&toml::Table> {
+        self.profiles()?.get(name)?.as_table()
+    }
+
+    /// Names of the `[profiles.<name>]` tables, sorted.
+    pub fn profile_names(&self) -> Vec<&str> {
+        self.profiles()
+            .map(|t| t.keys().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    fn profiles(&self) -> Option<&toml::Table> {
+        self.table.get("profiles")?.as_table()
+    }
+
+    fn section_keys(&self, section: &str) -> impl Iterator<Item = &str> {
+        self.table
+            .get(section)
+            .and_then(|t| t.as_table())
+            .into_iter()
+            .flat_map(|t| t.keys().map(String::as_str))
+    }
+}
+
+fn user_config_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        return std::env::var_os("APPDATA").map(PathBuf::from);
+    }
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+/// Options that configure the configuration lookup itself, not capture.
+const RESERVED: &[&str] = &["config", "help", "version", "license"];
+
+/// Extends `args` with defaults for every option of `command` that wasn't
+/// given on the command line, taken from `ASIMOV_CAMERA_<KEY>` environment
+/// variables or the configuration file, in that order.
+///
+/// The file is the `--config PATH` argument when `command` has one, else
+/// whatever [`ConfigFile::discover`] finds. `section` names the binary's own
+/// table in the file. When `command` has a `--profile NAME` option, the
+/// named `[profiles.NAME]` table overrides the rest of the file. Returns the
+/// rewritten arguments and the file used.
+pub fn apply_config_defaults(
+    command: Command,
+    section: &str,
+    args: Vec<OsString>,
+) -> Result<(Vec<OsString>, Option<PathBuf>), CameraError> {
+    // Let the real parse report malformed arguments, --help and the like.
+    let Ok(matches) = command.clone().try_get_matches_from(&args) else {
+        return Ok((args, None));
+    };
+
+    let file = match matches.try_get_one::<PathBuf>("config").ok().flatten() {
+        Some(path) => Some(ConfigFile::load(path)?),
+        None => ConfigFile::discover()?,
+    };
+
+    if let Some(file) = &file
+        && let Some(key) = file.section_keys(section).find(|key| {
+            RESERVED.contains(key) || !command.get_arguments().any(|a| a.get_long() == Some(key))
+        })
+    {
+        return Err(CameraError::invalid_config(format!(
+            "{}: unknown option '{key}' in [{section}]",
+            file.path.display()
+        )));
+    }
+
+    let profile = if command.get_arguments().any(|a| a.get_id() == "profile") {
+        matches
+            .try_get_one::<String>("profile")
+            .ok()
+            .flatten()
+            .cloned()
+            .or_else(|| std::env::var(env_key("profile")).ok())
+            .or_else(|| {
+                let file = file.as_ref()?;
+                Some(file.get(section, None, "profile")?.as_str()?.to_string())
+            })
+    } else {
+        None
+    };
+    if let Some(name) = &profile {
+        match &file {
+            Some(f) if f.profile(name).is_some() => {},
+            Some(f) => {
+                let names = f.profile_names();
+                return Err(CameraError::invalid_config(format!(
+                    "{}: no profile '{name}' (defined: {})",
+                    f.path.display(),
+                    if names.is_empty() {
+                        "none".to_string()
+                    } else {
+                        names.join(", ")
+                    }
+                )));
+            },
+            None => {
+                return Err(CameraError::invalid_config(format!(
+                    "profile '{name}' requested, but no {CONFIG_FILE_NAME} was found"
+                )));
+            },
+        }
+    }
+
+    let mut defaults = Vec::new();
+    for arg in command.get_arguments() {
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        if RESERVED.contains(&long)
+            || matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+        {
+            continue;
+        }
+
+        let env_key = env_key(long);
+        let (value, origin) = match std::env::var(&env_key) {
+            Ok(s) => (toml::Value::String(s), env_key),
+            Err(_) => match &file {
+                Some(f) => match f.get(section, profile.as_deref(), long) {
+                    Some(v) => (v.clone(), format!("{}: '{long}'", f.path.display())),
+                    None => continue,
+                },
+                None => continue,
+            },
+        };
+        let args = to_args(long, arg.get_action(), &value)
+            .map_err(|msg| CameraError::invalid_config(format!("{origin}: {msg}")))?;
+        defaults.extend(args);
+    }
+
+    let path = file.map(|f| f.path);
+    if defaults.is_empty() {
+        return Ok((args, path));
+    }
+    let mut args = args.into_iter();
+    let rewritten = args
+        .next()
+        .into_iter()
+        .chain(defaults)
+        .chain(args)
+        .collect();
+    Ok((rewritten, path))
+}
+
+fn env_key(long: &str) -> String {
+    format!(
+        "{CONFIG_ENV_PREFIX}{}",
+        long.to_ascii_uppercase().replace('-', "_")
+    )
+}
+
+/// Converts a configuration value into the arguments clap expects for `long`.
+fn to_args(long: &str, action: &ArgAction, value: &toml::Value) -> Result<Vec<OsString>, String> {
+    let flag = || OsString::from(format!("--{long}"));
+    match action {
+        ArgAction::SetTrue | ArgAction::SetFalse => {
+            let on = as_bool(value).ok_or("expected true or false")?;
+            let set = on == matches!(action, ArgAction::SetTrue);
+            Ok(if set { vec![flag()] } else { Vec::new() })
+        },
+        ArgAction::Count => {
+            let n = match value {
+                toml::Value::Integer(n) => u8::try_from(*n).ok(),
+                toml::Value::String(s) if s.trim().parse::<u8>().is_ok() => s.trim().parse().ok(),
+                other => as_bool(other).map(u8::from),
+            }
+            .ok_or("expected a count or true/false")?;
+            Ok(vec![flag(); n as usize])
+        },
+        ArgAction::Append => {
+            let values = match value {
+                toml::Value::Array(values) => values.iter().collect(),
+                other => vec![other],
+            };
+            values
+                .into_iter()
+                .map(|v| scalar(v).map(|s| OsString::from(format!("--{long}={s}"))))
+                .collect()
+        },
+        _ => Ok(vec![OsString::from(format!("--{long}={}", scalar(value)?))]),
+    }
+}
+
+fn as_bool(value: &toml::Value) -> Option<bool> {
+    match value {
+        toml::Value::Boolean(b) => Some(*b),
+        toml::Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Some(true),
+            "0" | "false" | "no" | "off" | "" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn scalar(value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        toml::Value::Datetime(d) => Ok(d.to_string()),
+        toml::Value::Array(_) | toml::Value::Table(_) => Err("expected a single value".into()),
+    }
+}
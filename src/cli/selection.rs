@@ -0,0 +1,94 @@
+// This is free and unencumbered software released into the public domain.
+
+use super::DeviceInfo;
+
+/// Which camera auto-selection picks when `--device` isn't given.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DevicePreference {
+    /// A USB camera, else the first one
+    #[default]
+    Usb,
+    /// The camera built into the machine, e.g. a laptop's, else the first one
+    Builtin,
+    /// A plugged-in camera, else the first one
+    External,
+    /// The first camera listed, whatever it is
+    First,
+}
+
+/// How auto-selection chooses among the connected cameras: which kind it
+/// prefers, and which names it may or may not pick at all. An explicit
+/// `--device` bypasses it.
+///
+/// Names in `allow` and `deny` match device names as a case-insensitive
+/// substring, or device ids exactly. `deny` wins over `allow`, and an empty
+/// `allow` permits every camera.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelectionPolicy {
+    pub prefer: DevicePreference,
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl SelectionPolicy {
+    pub fn new(prefer: DevicePreference) -> Self {
+        Self {
+            prefer,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_allow(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.allow.extend(names);
+        self
+    }
+
+    pub fn with_deny(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.deny.extend(names);
+        self
+    }
+
+    /// Whether the lists let auto-selection pick `device`.
+    pub fn permits(&self, device: &DeviceInfo) -> bool {
+        let matches = |pattern: &String| {
+            device.id == *pattern || device.name.to_lowercase().contains(&pattern.to_lowercase())
+        };
+        !self.deny.iter().any(matches) && (self.allow.is_empty() || self.allow.iter().any(matches))
+    }
+
+    /// The permitted devices, most preferred first; devices the preference
+    /// doesn't tell apart keep their order.
+    pub fn rank<'a>(&self, devices: &'a [DeviceInfo]) -> Vec<&'a DeviceInfo> {
+        let mut ranked: Vec<_> = devices.iter().filter(|d| self.permits(d)).collect();
+        ranked.sort_by_key(|d| self.score(d));
+        ranked
+    }
+
+    /// The device auto-selection picks, if any is permitted.
+    pub fn select<'a>(&self, devices: &'a [DeviceInfo]) -> Option<&'a DeviceInfo> {
+        self.rank(devices).into_iter().next()
+    }
+
+    /// Lower is better. Cameras whose kind is unknown rank between those
+    /// known to match and those known not to.
+    fn score(&self, device: &DeviceInfo) -> u8 {
+        let usb = device.usb.is_some() || device.is_usb;
+        match self.prefer {
+            DevicePreference::First => 0,
+            DevicePreference::Usb if device.usb.is_some() => 0,
+            DevicePreference::Usb if device.is_usb => 1,
+            DevicePreference::Usb => 2,
+            DevicePreference::Builtin | DevicePreference::External => {
+                let wanted = self.prefer == DevicePreference::Builtin;
+                match device.builtin {
+                    Some(builtin) if builtin == wanted => 0,
+                    // Unknown: guess built-in cameras aren't USB, which holds
+                    // more often than not outside Linux laptops.
+                    None if usb != wanted => 1,
+                    None => 2,
+                    Some(_) => 3,
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::CameraError;
+use core::{fmt, str::FromStr};
+
+/// Prefix selecting a device by [`UsbInfo`], e.g. `usb:046d:0893` or, to tell
+/// identical cameras apart, `usb:046d:0893:1A2B3C4D`.
+pub const USB_PREFIX: &str = "usb:";
+
+/// What the USB stack reports about a camera, as opposed to what its name
+/// suggests.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsbInfo {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// The `iSerialNumber` string, for cameras that have one.
+    pub serial: Option<String>,
+    /// Where the camera is plugged in: the sysfs port name (e.g. `1-2.3`) on
+    /// Linux and the IOKit `locationID` (e.g. `0x14100000`) on macOS.
+    pub bus_path: Option<String>,
+}
+
+impl UsbInfo {
+    /// Reads the ids and serial number out of a Windows device interface
+    /// path, as SetupAPI reports it and DirectShow lists it, e.g.
+    /// `\\?\usb#vid_046d&pid_0893&mi_00#7&1a2b3c&0&0000#{65e8773d-…}\global`.
+    ///
+    /// The instance id segment is the serial number only for single-interface
+    /// devices; Windows makes one up, with `&`s, where there is none.
+    pub fn from_interface_path(path: &str) -> Option<Self> {
+        // Lowercasing ASCII keeps byte offsets, so `start` indexes both.
+        let start = path.to_ascii_lowercase().find("usb#")? + "usb#".len();
+        let mut segments = path[start..].split('#');
+        let ids = segments.next()?.to_ascii_lowercase();
+        let hex = |key: &str| {
+            ids.split('&')
+                .find_map(|part| part.strip_prefix(key))
+                .and_then(|id| u16::from_str_radix(id.get(..4)?, 16).ok())
+        };
+        let vendor_id = hex("vid_")?;
+        let product_id = hex("pid_")?;
+        let composite = ids.split('&').any(|part| part.starts_with("mi_"));
+        let serial = segments
+            .next()
+            .filter(|instance| !composite && !instance.is_empty() && !instance.contains('&'))
+            .map(str::to_string);
+        Some(Self {
+            vendor_id,
+            product_id,
+            serial,
+            bus_path: None,
+        })
+    }
+
+    /// Whether this is the device `selector` names.
+    pub fn matches(&self, selector: &UsbSelector) -> bool {
+        self.vendor_id == selector.vendor_id
+            && self.product_id == selector.product_id
+            && selector
+                .serial
+                .as_ref()
+                .is_none_or(|serial| self.serial.as_ref() == Some(serial))
+    }
+
+    /// The `usb:` selector for just this device: with its serial number if
+    /// it has one.
+    pub fn selector(&self) -> UsbSelector {
+        UsbSelector {
+            vendor_id: self.vendor_id,
+            product_id: self.product_id,
+            serial: self.serial.clone(),
+        }
+    }
+}
+
+/// A `usb:VID:PID[:SERIAL]` device selector, with the ids in hex.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UsbSelector {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub serial: Option<String>,
+}
+
+impl FromStr for UsbSelector {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            CameraError::invalid_config(format!(
+                "invalid USB device '{s}' (expected VID:PID or VID:PID:SERIAL in hex, e.g. 046d:0893)"
+            ))
+        };
+        let mut parts = s.splitn(3, ':');
+        let mut id = || {
+            parts
+                .next()
+                .filter(|id| !id.is_empty() && id.len() <= 4)
+                .and_then(|id| u16::from_str_radix(id, 16).ok())
+        };
+        let vendor_id = id().ok_or_else(invalid)?;
+        let product_id = id().ok_or_else(invalid)?;
+        let serial = parts.next().map(str::to_string);
+        if serial.as_deref() == Some("") {
+            return Err(invalid());
+        }
+        Ok(Self {
+            vendor_id,
+            product_id,
+            serial,
+        })
+    }
+}
+
+impl fmt::Display for UsbSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vendor_id, self.product_id)?;
+        if let Some(serial) = &self.serial {
+            write!(f, ":{serial}")?;
+        }
+        Ok(())
+    }
+}
+
+/// `(product name, device)` pairs from `ioreg -p IOUSB -l`, which prints
+/// each device as a `+-o Name@location` line followed by its properties.
+pub fn parse_ioreg(stdout: &str) -> Vec<(String, UsbInfo)> {
+    #[derive(Default)]
+    struct Entry {
+        name: Option<String>,
+        vendor_id: Option<u16>,
+        product_id: Option<u16>,
+        serial: Option<String>,
+        location: Option<u32>,
+    }
+
+    fn finish(entry: Entry, out: &mut Vec<(String, UsbInfo)>) {
+        if let (Some(name), Some(vendor_id), Some(product_id)) =
+            (entry.name, entry.vendor_id, entry.product_id)
+        {
+            out.push((
+                name,
+                UsbInfo {
+                    vendor_id,
+                    product_id,
+                    serial: entry.serial,
+                    bus_path: entry.location.map(|l| format!("{l:#010x}")),
+                },
+            ));
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut entry: Option<Entry> = None;
+    for line in stdout.lines() {
+        let line = line.trim_start_matches([' ', '|']).trim_end();
+        if let Some(node) = line.strip_prefix("+-o ") {
+            if let Some(entry) = entry.take() {
+                finish(entry, &mut out);
+            }
+            let name = node.split_once('<').map_or(node, |(n, _)| n).trim_end();
+            let name = name.rsplit_once('@').map_or(name, |(n, _)| n);
+            entry = Some(Entry {
+                name: (!name.is_empty()).then(|| name.to_string()),
+                ..Entry::default()
+            });
+            continue;
+        }
+        let Some(entry) = entry.as_mut() else {
+            continue;
+        };
+        let Some((key, value)) = line.split_once(" = ") else {
+            continue;
+        };
+        let quoted = || {
+            Some(
+                value
+                    .trim()
+                    .strip_prefix('"')?
+                    .strip_suffix('"')?
+                    .to_string(),
+            )
+        };
+        let number = || value.trim().parse::<u64>().ok();
+        match key.trim() {
+            "\"USB Product Name\"" | "\"kUSBProductString\"" => {
+                if let Some(name) = quoted() {
+                    entry.name = Some(name);
+                }
+            },
+            "\"idVendor\"" => entry.vendor_id = number().and_then(|n| n.try_into().ok()),
+            "\"idProduct\"" => entry.product_id = number().and_then(|n| n.try_into().ok()),
+            "\"USB Serial Number\"" | "\"kUSBSerialNumberString\"" => entry.serial = quoted(),
+            "\"locationID\"" => entry.location = number().and_then(|n| n.try_into().ok()),
+            _ => {},
+        }
+    }
+    if let Some(entry) = entry {
+        finish(entry, &mut out);
+    }
+    out
+}
@@ -0,0 +1,512 @@
+// This is free and unencumbered software released into the public domain.
+
+#[cfg(not(feature = "std"))]
+compile_error!("asimov-camera-doctor requires the 'std' feature");
+
+use asimov_camera_module::{
+    cli,
+    shared::{CameraBackend, CameraConfig, CameraError, CameraEvent, Frame, open_camera},
+};
+use asimov_module::SysexitsError::{self, *};
+use clap::{CommandFactory, Parser};
+use clientele::StandardOptions;
+use serde_json::json;
+use std::{
+    error::Error as StdError,
+    path::PathBuf,
+    process::Command,
+    sync::{Arc, mpsc::sync_channel},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Parser)]
+struct Options {
+    #[clap(flatten)]
+    flags: StandardOptions,
+
+    /// Read option defaults from this TOML file (default: ./asimov-camera.toml)
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Apply the named `[profiles.NAME]` table from the configuration file
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Device to test-open (default: the one the reader would pick)
+    #[arg(long)]
+    device: Option<String>,
+
+    /// Which camera to pick without --device: usb, builtin, external or first
+    #[arg(long, value_name = "KIND", value_enum, default_value = "usb")]
+    prefer: cli::DevicePreference,
+
+    /// Only auto-select cameras with this name or id (repeatable)
+    #[arg(long = "allow-device", value_name = "NAME")]
+    allow_device: Vec<String>,
+
+    /// Never auto-select cameras with this name or id (repeatable)
+    #[arg(long = "deny-device", value_name = "NAME")]
+    deny_device: Vec<String>,
+
+    /// Backend to test-open the device with (default: the first built in)
+    #[arg(long, value_name = "NAME", value_parser = parse_backend)]
+    backend: Option<CameraBackend>,
+
+    /// How long to wait for the first frame, in seconds
+    #[arg(long, value_name = "SECS", default_value = "5")]
+    timeout: u64,
+
+    /// Skip the test capture and only check the environment
+    #[arg(long)]
+    no_capture: bool,
+
+    /// Output format
+    #[arg(
+        value_name = "FORMAT",
+        short = 'o',
+        long = "output",
+        value_enum,
+        default_value = "text"
+    )]
+    output: OutputFormat,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    Jsonl,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Status::Ok => "✓",
+            Status::Warn => "!",
+            Status::Fail => "✗",
+        }
+    }
+}
+
+/// One line of the report, with a suggested fix for anything not `Ok`.
+#[derive(Debug)]
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    hint: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+pub fn main() -> Result<SysexitsError, Box<dyn StdError>> {
+    asimov_module::dotenv().ok();
+    let args = asimov_module::args_os()?;
+    let (args, config_path) = match cli::apply_config_defaults(Options::command(), "doctor", args) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("ERROR: {err}");
+            return Ok(EX_CONFIG);
+        },
+    };
+    let options = Options::parse_from(args);
+
+    if options.flags.version {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return Ok(EX_OK);
+    }
+
+    if options.flags.license {
+        print!("{}", include_str!("../../UNLICENSE"));
+        return Ok(EX_OK);
+    }
+
+    #[cfg(feature = "tracing")]
+    asimov_module::init_tracing_subscriber(&options.flags).expect("failed to initialize logging");
+
+    if let Some(path) = &config_path
+        && (options.flags.debug || options.flags.verbose >= 1)
+    {
+        match &options.profile {
+            Some(name) => eprintln!("INFO: using profile {name} from {}", path.display()),
+            None => eprintln!("INFO: using configuration from {}", path.display()),
+        }
+    }
+
+    let checks = run_doctor(&options);
+    for check in &checks {
+        match options.output {
+            OutputFormat::Text => {
+                println!("{} {}: {}", check.status.symbol(), check.name, check.detail);
+                if let Some(hint) = &check.hint {
+                    println!("    → {hint}");
+                }
+            },
+            OutputFormat::Jsonl => {
+                println!(
+                    "{}",
+                    json!({
+                        "check": check.name,
+                        "status": check.status.as_str(),
+                        "detail": check.detail,
+                        "hint": check.hint,
+                    })
+                );
+            },
+        }
+    }
+
+    if checks.iter().any(|c| c.status == Status::Fail) {
+        Ok(EX_UNAVAILABLE)
+    } else {
+        Ok(EX_OK)
+    }
+}
+
+fn run_doctor(options: &Options) -> Vec<Check> {
+    let mut checks = vec![check_ffmpeg()];
+    #[cfg(feature = "gstreamer")]
+    checks.push(check_gstreamer());
+
+    let devices = match cli::list_video_devices(&options.flags) {
+        Ok(devices) if devices.is_empty() => {
+            checks.push(
+                Check::new("devices", Status::Fail, "no camera devices found")
+                    .with_hint("connect a camera, or check that its driver is loaded"),
+            );
+            Vec::new()
+        },
+        Ok(devices) => {
+            let names = devices
+                .iter()
+                .map(|d| format!("{} ({})", d.id, d.name))
+                .collect::<Vec<_>>()
+                .join(", ");
+            checks.push(Check::new(
+                "devices",
+                Status::Ok,
+                format!("{} found: {names}", devices.len()),
+            ));
+            devices
+        },
+        Err(err) => {
+            checks.push(Check::new("devices", Status::Fail, err.to_string()));
+            Vec::new()
+        },
+    };
+
+    checks.extend(check_permissions(&devices));
+
+    if !options.no_capture {
+        checks.push(check_capture(options));
+    }
+    checks
+}
+
+fn check_ffmpeg() -> Check {
+    match Command::new("ffmpeg").arg("-version").output() {
+        Ok(out) if out.status.success() => {
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            let version = stdout
+                .lines()
+                .next()
+                .and_then(|l| l.strip_prefix("ffmpeg version "))
+                .and_then(|l| l.split_whitespace().next())
+                .unwrap_or("unknown version");
+            Check::new("ffmpeg", Status::Ok, format!("ffmpeg {version}"))
+        },
+        Ok(out) => Check::new(
+            "ffmpeg",
+            Status::Fail,
+            format!("`ffmpeg -version` failed ({})", out.status),
+        )
+        .with_hint("reinstall ffmpeg"),
+        Err(err) => Check::new("ffmpeg", Status::Fail, format!("ffmpeg not found: {err}"))
+            .with_hint(install_ffmpeg_hint()),
+    }
+}
+
+/// The GStreamer backend captures in-process, so check the library and the
+/// plugins its pipeline is built from.
+#[cfg(feature = "gstreamer")]
+fn check_gstreamer() -> Check {
+    use gstreamer as gst;
+    if let Err(err) = gst::init() {
+        return Check::new(
+            "gstreamer",
+            Status::Fail,
+            format!("cannot initialize: {err}"),
+        )
+        .with_hint("install the GStreamer 1.x runtime");
+    }
+    let source = if cfg!(target_os = "macos") {
+        "avfvideosrc"
+    } else if cfg!(target_os = "windows") {
+        "ksvideosrc"
+    } else {
+        "v4l2src"
+    };
+    let pipewire = cfg!(all(feature = "pipewire", target_os = "linux")).then_some("pipewiresrc");
+    let missing = [source, "videoconvert", "videoscale", "videorate", "appsink"]
+        .into_iter()
+        .chain(pipewire)
+        .filter(|name| gst::ElementFactory::find(name).is_none())
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        let (major, minor, micro, _) = gst::version();
+        Check::new(
+            "gstreamer",
+            Status::Ok,
+            format!("GStreamer {major}.{minor}.{micro}"),
+        )
+    } else {
+        Check::new(
+            "gstreamer",
+            Status::Fail,
+            format!("missing elements: {}", missing.join(", ")),
+        )
+        .with_hint("install the gst-plugins-base and gst-plugins-good packages")
+    }
+}
+
+fn install_ffmpeg_hint() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "install it with `brew install ffmpeg`"
+    } else if cfg!(target_os = "windows") {
+        "install it with `winget install ffmpeg` and reopen the terminal"
+    } else {
+        "install it with your package manager, e.g. `sudo apt install ffmpeg`"
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check_permissions(devices: &[cli::DeviceInfo]) -> Vec<Check> {
+    use std::{ffi::CString, os::unix::fs::MetadataExt};
+
+    let mut checks = Vec::new();
+    for device in devices {
+        let path = device.id.strip_prefix("file:").unwrap_or(&device.id);
+        let Ok(c_path) = CString::new(path) else {
+            continue;
+        };
+        // SAFETY: `c_path` is a valid NUL-terminated string.
+        if unsafe { libc::access(c_path.as_ptr(), libc::R_OK | libc::W_OK) } == 0 {
+            checks.push(Check::new(
+                "permissions",
+                Status::Ok,
+                format!("{path} is readable and writable"),
+            ));
+            continue;
+        }
+
+        let group = std::fs::metadata(path)
+            .ok()
+            .and_then(|m| group_name(m.gid()).map(|name| (m.gid(), name)));
+        let check = Check::new(
+            "permissions",
+            Status::Fail,
+            format!("{path} is not accessible by the current user"),
+        );
+        checks.push(match group {
+            Some((gid, name)) if !in_group(gid) => check.with_hint(format!(
+                "add yourself to the `{name}` group: `sudo usermod -aG {name} $USER`, then log in again"
+            )),
+            _ => check.with_hint(format!("check the ownership and mode of {path}")),
+        });
+    }
+    checks
+}
+
+#[cfg(target_os = "linux")]
+fn group_name(gid: u32) -> Option<String> {
+    // SAFETY: getgrgid returns a pointer to static storage or null.
+    let group = unsafe { libc::getgrgid(gid) };
+    if group.is_null() {
+        return None;
+    }
+    // SAFETY: gr_name is a valid C string for a non-null group entry.
+    let name = unsafe { std::ffi::CStr::from_ptr((*group).gr_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(target_os = "linux")]
+fn in_group(gid: u32) -> bool {
+    // SAFETY: getegid has no preconditions; getgroups writes at most `len` entries.
+    unsafe {
+        if libc::getegid() == gid {
+            return true;
+        }
+        let len = libc::getgroups(0, core::ptr::null_mut());
+        if len <= 0 {
+            return false;
+        }
+        let mut groups = vec![0 as libc::gid_t; len as usize];
+        let n = libc::getgroups(len, groups.as_mut_ptr());
+        groups.truncate(n.max(0) as usize);
+        groups.contains(&gid)
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn check_permissions(_devices: &[cli::DeviceInfo]) -> Vec<Check> {
+    // TCC has no query API for other apps; the test capture below is the
+    // real check, and this tells the user where to look if it fails.
+    vec![
+        Check::new(
+            "permissions",
+            Status::Ok,
+            "camera access is granted per app by macOS",
+        )
+        .with_hint(
+            "if capture fails, allow your terminal in System Settings → Privacy & Security → Camera",
+        ),
+    ]
+}
+
+#[cfg(target_os = "windows")]
+fn check_permissions(_devices: &[cli::DeviceInfo]) -> Vec<Check> {
+    const KEY: &str = r"HKCU\Software\Microsoft\Windows\CurrentVersion\CapabilityAccessManager\ConsentStore\webcam";
+    let denied = Command::new("reg")
+        .args(["query", KEY, "/v", "Value"])
+        .output()
+        .ok()
+        .map(|out| String::from_utf8_lossy(&out.stdout).contains("Deny"))
+        .unwrap_or(false);
+    let check = if denied {
+        Check::new("permissions", Status::Fail, "camera access is turned off").with_hint(
+            "enable Settings → Privacy & security → Camera → Let desktop apps access your camera",
+        )
+    } else {
+        Check::new(
+            "permissions",
+            Status::Ok,
+            "desktop apps may access the camera",
+        )
+    };
+    vec![check]
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn check_permissions(_devices: &[cli::DeviceInfo]) -> Vec<Check> {
+    Vec::new()
+}
+
+fn check_capture(options: &Options) -> Check {
+    let policy = cli::SelectionPolicy::new(options.prefer)
+        .with_allow(options.allow_device.clone())
+        .with_deny(options.deny_device.clone());
+    let device = match cli::auto_select_device(&options.flags, options.device.clone(), &policy) {
+        Ok(Some(device)) => device,
+        Ok(None) => {
+            return Check::new("capture", Status::Fail, "no device to test")
+                .with_hint("pass --device explicitly");
+        },
+        Err(err) => return Check::new("capture", Status::Fail, err.to_string()),
+    };
+
+    match capture_one(
+        &device,
+        options.backend,
+        Duration::from_secs(options.timeout),
+    ) {
+        Ok((frame, latency)) => {
+            let detail = format!(
+                "{device}: first {}x{} {} frame after {} ms",
+                frame.width,
+                frame.height,
+                frame.pixel_format.as_str(),
+                latency.as_millis()
+            );
+            // A uniformly black frame usually means a closed privacy shutter.
+            if frame.data.iter().all(|&b| b < 8) {
+                Check::new(
+                    "capture",
+                    Status::Warn,
+                    format!("{detail}, but it is black"),
+                )
+                .with_hint("check the lens cap or privacy shutter, and the room lighting")
+            } else {
+                Check::new("capture", Status::Ok, detail)
+            }
+        },
+        Err(err) => {
+            let check = Check::new("capture", Status::Fail, format!("{device}: {err}"));
+            if cfg!(target_os = "macos") {
+                check.with_hint("grant camera access to your terminal and try again")
+            } else {
+                check.with_hint("close other apps using the camera, or try another --device")
+            }
+        },
+    }
+}
+
+fn capture_one(
+    device: &str,
+    backend: Option<CameraBackend>,
+    timeout: Duration,
+) -> Result<(Frame, Duration), CameraError> {
+    let (tx, rx) = sync_channel::<Frame>(1);
+    let config = CameraConfig::default().with_device(device);
+    let config = match backend {
+        Some(backend) => config.with_backend(backend),
+        None => config,
+    };
+    let mut camera = open_camera("", config)?;
+    camera.add_sink(Arc::new(move |frame: Frame| {
+        let _ = tx.try_send(frame);
+    }));
+
+    let started = Instant::now();
+    camera.start()?;
+    let result = loop {
+        if let Ok(frame) = rx.recv_timeout(Duration::from_millis(50)) {
+            break Ok((frame, started.elapsed()));
+        }
+        if let Some(error) = camera.events().try_iter().find_map(|ev| match ev {
+            CameraEvent::Error { error, .. } => Some(error),
+            _ => None,
+        }) {
+            break Err(error);
+        }
+        if started.elapsed() >= timeout {
+            break Err(CameraError::other(format!(
+                "no frame within {} s",
+                timeout.as_secs()
+            )));
+        }
+    };
+    let _ = camera.stop();
+    result
+}
+
+fn parse_backend(s: &str) -> Result<CameraBackend, String> {
+    s.parse().map_err(|e: CameraError| e.to_string())
+}
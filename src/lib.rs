@@ -4,4 +4,6 @@ extern crate alloc;
 
 #[cfg(feature = "cli")]
 pub mod cli;
+#[cfg(feature = "python")]
+pub mod python;
 pub mod shared;
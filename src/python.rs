@@ -0,0 +1,409 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::{
+    cli,
+    shared::{Camera, CameraConfig, CameraError, Frame, PhotoFormat, open_camera},
+};
+use clap::{Args, Command, FromArgMatches};
+use clientele::StandardOptions;
+use pyo3::{
+    exceptions::{PyBufferError, PyRuntimeError},
+    ffi,
+    prelude::*,
+    types::PyBytes,
+};
+use std::{
+    collections::VecDeque,
+    ffi::{c_int, c_void},
+    ptr::null_mut,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+fn py_err(err: CameraError) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+/// A captured frame. Supports the buffer protocol, so `numpy.asarray(frame)`
+/// yields a read-only `(height, width, channels)` array without copying:
+/// uint8 for colour frames, uint16 for `gray16`, `z16` and the luma plane of
+/// `p010`, and one uint32 per pixel for `rgba1010102`.
+#[pyclass(name = "Frame", module = "asimov_camera_module", frozen)]
+pub struct PyFrame {
+    frame: Frame,
+    shape: [ffi::Py_ssize_t; 3],
+    strides: [ffi::Py_ssize_t; 3],
+}
+
+impl From<Frame> for PyFrame {
+    fn from(frame: Frame) -> Self {
+        let channels = frame.pixel_format.channels() as ffi::Py_ssize_t;
+        let sample = frame.pixel_format.bytes_per_sample() as ffi::Py_ssize_t;
+        Self {
+            shape: [
+                frame.height as ffi::Py_ssize_t,
+                frame.width as ffi::Py_ssize_t,
+                channels,
+            ],
+            strides: [frame.stride as ffi::Py_ssize_t, channels * sample, sample],
+            frame,
+        }
+    }
+}
+
+#[pymethods]
+impl PyFrame {
+    #[getter]
+    fn width(&self) -> u32 {
+        self.frame.width
+    }
+
+    #[getter]
+    fn height(&self) -> u32 {
+        self.frame.height
+    }
+
+    #[getter]
+    fn stride(&self) -> u32 {
+        self.frame.stride
+    }
+
+    #[getter]
+    fn pixel_format(&self) -> &'static str {
+        self.frame.pixel_format.as_str()
+    }
+
+    #[getter]
+    fn bit_depth(&self) -> u32 {
+        self.frame.bit_depth()
+    }
+
+    /// `color`, `depth` or `infrared`.
+    #[getter]
+    fn stream(&self) -> &'static str {
+        self.frame.stream.as_str()
+    }
+
+    #[getter]
+    fn timestamp_ns(&self) -> u64 {
+        self.frame.timestamp_ns
+    }
+
+    /// A copy of the raw frame bytes.
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.frame.data)
+    }
+
+    /// Equivalent to `numpy.asarray(frame)`.
+    fn numpy<'py>(slf: Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let numpy = slf.py().import("numpy")?;
+        numpy.call_method1("asarray", (slf,))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Frame({}x{}, {}, timestamp_ns={})",
+            self.frame.width,
+            self.frame.height,
+            self.frame.pixel_format.as_str(),
+            self.frame.timestamp_ns
+        )
+    }
+
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("view is null"));
+        }
+        if (flags & ffi::PyBUF_WRITABLE) == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("frame buffers are read-only"));
+        }
+
+        let this = slf.get();
+        let packed = this.strides[0] == this.shape[1] * this.shape[2];
+        let want_strides = (flags & ffi::PyBUF_STRIDES) == ffi::PyBUF_STRIDES;
+        if !packed && !want_strides {
+            return Err(PyBufferError::new_err(
+                "frame rows are padded; request a strided buffer",
+            ));
+        }
+
+        // The shape and strides arrays live inside the frame object, which the
+        // view keeps alive through `obj`.
+        unsafe {
+            let view = &mut *view;
+            view.buf = this.frame.data.as_ptr() as *mut c_void;
+            view.len = this.frame.data.len() as ffi::Py_ssize_t;
+            view.readonly = 1;
+            view.itemsize = this.frame.pixel_format.bytes_per_sample() as ffi::Py_ssize_t;
+            view.format = if (flags & ffi::PyBUF_FORMAT) == ffi::PyBUF_FORMAT {
+                match view.itemsize {
+                    2 => c"<H".as_ptr() as *mut _,
+                    4 => c"<I".as_ptr() as *mut _,
+                    _ => c"B".as_ptr() as *mut _,
+                }
+            } else {
+                null_mut()
+            };
+            view.ndim = 3;
+            view.shape = if (flags & ffi::PyBUF_ND) == ffi::PyBUF_ND {
+                this.shape.as_ptr() as *mut _
+            } else {
+                null_mut()
+            };
+            view.strides = if want_strides {
+                this.strides.as_ptr() as *mut _
+            } else {
+                null_mut()
+            };
+            view.suboffsets = null_mut();
+            view.internal = null_mut();
+            view.obj = slf.into_any().into_ptr();
+        }
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, _view: *mut ffi::Py_buffer) {}
+}
+
+#[pyclass(name = "DeviceInfo", module = "asimov_camera_module", frozen, get_all)]
+pub struct PyDeviceInfo {
+    id: String,
+    name: String,
+    is_usb: bool,
+    unique_id: Option<String>,
+    /// USB ids as 4-digit hex, e.g. `"046d"`.
+    vendor_id: Option<String>,
+    product_id: Option<String>,
+    serial: Option<String>,
+    bus_path: Option<String>,
+    builtin: Option<bool>,
+}
+
+#[pymethods]
+impl PyDeviceInfo {
+    fn __repr__(&self) -> String {
+        format!("DeviceInfo(id={:?}, name={:?})", self.id, self.name)
+    }
+}
+
+/// Bounded latest-wins queue between the dispatcher thread and `read()`.
+struct FrameQueue {
+    frames: Mutex<VecDeque<Frame>>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+impl FrameQueue {
+    fn push(&self, frame: Frame) {
+        let mut frames = self.frames.lock().unwrap_or_else(|p| p.into_inner());
+        while frames.len() >= self.capacity {
+            frames.pop_front();
+        }
+        frames.push_back(frame);
+        self.ready.notify_one();
+    }
+
+    fn pop(&self, timeout: Option<Duration>) -> Option<Frame> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut frames = self.frames.lock().unwrap_or_else(|p| p.into_inner());
+        loop {
+            if let Some(frame) = frames.pop_front() {
+                return Some(frame);
+            }
+            frames = match deadline {
+                None => self.ready.wait(frames).unwrap_or_else(|p| p.into_inner()),
+                Some(deadline) => {
+                    let left = deadline.checked_duration_since(Instant::now())?;
+                    self.ready
+                        .wait_timeout(frames, left)
+                        .unwrap_or_else(|p| p.into_inner())
+                        .0
+                },
+            };
+        }
+    }
+}
+
+#[pyclass(name = "Camera", module = "asimov_camera_module")]
+pub struct PyCamera {
+    camera: Mutex<Option<Camera>>,
+    queue: Arc<FrameQueue>,
+}
+
+impl PyCamera {
+    fn with_camera<R>(&self, f: impl FnOnce(&mut Camera) -> R) -> PyResult<R> {
+        let mut guard = self.camera.lock().unwrap_or_else(|p| p.into_inner());
+        let camera = guard.as_mut().ok_or_else(|| py_err(CameraError::Closed))?;
+        Ok(f(camera))
+    }
+}
+
+#[pymethods]
+impl PyCamera {
+    #[new]
+    #[pyo3(signature = (device=None, width=640, height=480, fps=30.0, buffer_frames=4))]
+    fn new(
+        device: Option<String>,
+        width: u32,
+        height: u32,
+        fps: f64,
+        buffer_frames: usize,
+    ) -> PyResult<Self> {
+        let mut config = CameraConfig::new(width, height, fps);
+        if let Some(device) = device {
+            config = config.with_device(device);
+        }
+
+        let camera = open_camera("", config).map_err(py_err)?;
+        let queue = Arc::new(FrameQueue {
+            frames: Mutex::new(VecDeque::new()),
+            ready: Condvar::new(),
+            capacity: buffer_frames.max(1),
+        });
+        let queue_sink = Arc::clone(&queue);
+        camera.add_sink(Arc::new(move |frame| queue_sink.push(frame)));
+
+        Ok(Self {
+            camera: Mutex::new(Some(camera)),
+            queue,
+        })
+    }
+
+    #[getter]
+    fn backend(&self) -> PyResult<String> {
+        self.with_camera(|c| format!("{:?}", c.backend()).to_lowercase())
+    }
+
+    fn start(&self) -> PyResult<()> {
+        self.with_camera(|c| c.start())?.map_err(py_err)
+    }
+
+    fn stop(&self) -> PyResult<()> {
+        self.with_camera(|c| c.stop())?.map_err(py_err)
+    }
+
+    /// Stops delivering frames, keeping the device open for `resume()`.
+    fn pause(&self) -> PyResult<()> {
+        self.with_camera(|c| c.pause())?.map_err(py_err)
+    }
+
+    fn resume(&self) -> PyResult<()> {
+        self.with_camera(|c| c.resume())?.map_err(py_err)
+    }
+
+    #[getter]
+    fn is_paused(&self) -> PyResult<bool> {
+        self.with_camera(|c| c.is_paused())
+    }
+
+    /// Captures `count` frames at least `interval` seconds apart from the running stream.
+    #[pyo3(signature = (count, interval=0.0))]
+    fn capture_burst(&self, py: Python<'_>, count: usize, interval: f64) -> PyResult<Vec<PyFrame>> {
+        let interval = Duration::from_secs_f64(interval.max(0.0));
+        let frames = py.detach(|| self.with_camera(|c| c.capture_burst(count, interval)))?;
+        Ok(frames
+            .map_err(py_err)?
+            .into_iter()
+            .map(PyFrame::from)
+            .collect())
+    }
+
+    /// Captures one frame per exposure compensation in `ev` (in stops), e.g. `[-2, 0, 2]`.
+    fn capture_bracket(&self, py: Python<'_>, ev: Vec<f32>) -> PyResult<Vec<PyFrame>> {
+        let frames = py.detach(|| self.with_camera(|c| c.capture_bracket(&ev)))?;
+        Ok(frames
+            .map_err(py_err)?
+            .into_iter()
+            .map(PyFrame::from)
+            .collect())
+    }
+
+    /// Takes a full-quality still and returns the encoded "jpeg" or "heif" file as bytes.
+    #[pyo3(signature = (format="jpeg"))]
+    fn capture_photo<'py>(&self, py: Python<'py>, format: &str) -> PyResult<Bound<'py, PyBytes>> {
+        let format: PhotoFormat = format.parse().map_err(py_err)?;
+        let photo = py.detach(|| self.with_camera(|c| c.capture_photo(format)))?;
+        Ok(PyBytes::new(py, &photo.map_err(py_err)?.data))
+    }
+
+    /// Closes the device; the camera can't be restarted afterwards.
+    fn close(&self) {
+        let camera = self.camera.lock().unwrap_or_else(|p| p.into_inner()).take();
+        drop(camera);
+    }
+
+    /// Waits for the next frame, or up to `timeout` seconds; returns `None` on timeout.
+    #[pyo3(signature = (timeout=None))]
+    fn read(&self, py: Python<'_>, timeout: Option<f64>) -> Option<PyFrame> {
+        let timeout = timeout.map(|t| Duration::from_secs_f64(t.max(0.0)));
+        let queue = Arc::clone(&self.queue);
+        py.detach(move || queue.pop(timeout)).map(PyFrame::from)
+    }
+
+    /// Calls `callback(frame)` on the camera's dispatch thread for every frame.
+    fn on_frame(&self, callback: Py<PyAny>) -> PyResult<()> {
+        let callback = Arc::new(callback);
+        self.with_camera(|c| {
+            c.add_sink(Arc::new(move |frame| {
+                Python::attach(|py| {
+                    if let Err(err) = callback.call1(py, (PyFrame::from(frame),)) {
+                        err.print(py);
+                    }
+                });
+            }));
+        })
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyResult<PyRef<'_, Self>> {
+        slf.start()?;
+        Ok(slf)
+    }
+
+    #[pyo3(signature = (*_args))]
+    fn __exit__(&self, _args: &Bound<'_, pyo3::types::PyTuple>) {
+        self.close();
+    }
+}
+
+#[pyfunction]
+fn list_devices() -> PyResult<Vec<PyDeviceInfo>> {
+    let matches = StandardOptions::augment_args(Command::new("asimov-camera"))
+        .try_get_matches_from(["asimov-camera"])
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    let flags = StandardOptions::from_arg_matches(&matches)
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+
+    let devices = cli::list_video_devices(&flags).map_err(py_err)?;
+    Ok(devices
+        .into_iter()
+        .map(|d| {
+            let known = d.usb.is_some();
+            let usb = d.usb.unwrap_or_default();
+            PyDeviceInfo {
+                id: d.id,
+                name: d.name,
+                is_usb: d.is_usb,
+                unique_id: d.unique_id,
+                vendor_id: known.then(|| format!("{:04x}", usb.vendor_id)),
+                product_id: known.then(|| format!("{:04x}", usb.product_id)),
+                serial: usb.serial,
+                bus_path: usb.bus_path,
+                builtin: d.builtin,
+            }
+        })
+        .collect())
+}
+
+#[pymodule]
+fn asimov_camera_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCamera>()?;
+    m.add_class::<PyDeviceInfo>()?;
+    m.add_class::<PyFrame>()?;
+    m.add_function(wrap_pyfunction!(list_devices, m)?)?;
+    Ok(())
+}
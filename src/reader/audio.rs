@@ -0,0 +1,78 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::AudioFrame;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    sync::mpsc::Receiver,
+    thread::JoinHandle,
+};
+
+/// Writes 16-bit PCM to a WAV file, patching the header sizes on finish.
+struct WavWriter {
+    file: BufWriter<File>,
+    data_len: u32,
+}
+
+impl WavWriter {
+    fn create(path: &Path, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let block_align = channels * 2;
+        file.write_all(b"RIFF")?;
+        file.write_all(&36u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?; // PCM
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        Ok(Self { file, data_len: 0 })
+    }
+
+    fn write(&mut self, chunk: &AudioFrame) -> io::Result<()> {
+        // WAV sizes are 32-bit; stop appending rather than corrupt the file.
+        let Some(len) = u32::try_from(chunk.data.len())
+            .ok()
+            .and_then(|n| self.data_len.checked_add(n))
+            .filter(|&n| n <= u32::MAX - 36)
+        else {
+            return Ok(());
+        };
+        self.file.write_all(&chunk.data)?;
+        self.data_len = len;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<u32> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_len.to_le_bytes())?;
+        self.file.flush()?;
+        Ok(self.data_len)
+    }
+}
+
+/// Records audio chunks into `path` until the camera is dropped, returning
+/// the number of PCM bytes written.
+pub fn spawn_recorder(
+    rx: Receiver<AudioFrame>,
+    path: &Path,
+    sample_rate: u32,
+    channels: u16,
+) -> io::Result<JoinHandle<io::Result<u32>>> {
+    let mut wav = WavWriter::create(path, sample_rate, channels)?;
+    std::thread::Builder::new()
+        .name("reader-audio".into())
+        .spawn(move || {
+            for chunk in rx {
+                wav.write(&chunk)?;
+            }
+            wav.finish()
+        })
+}
@@ -0,0 +1,162 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::{Camera, CameraError, CameraEvent, CameraStats, Frame};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Per-frame measurements taken in the benchmark sink.
+#[derive(Debug, Default)]
+struct Samples {
+    /// Capture timestamp to sink invocation, in nanoseconds.
+    latencies_ns: Vec<u64>,
+    bytes: u64,
+    copy_time: Duration,
+}
+
+/// Result of `--benchmark`.
+#[derive(Debug)]
+pub struct BenchReport {
+    pub elapsed: Duration,
+    pub stats: CameraStats,
+    pub errors: Vec<String>,
+    /// Capture-to-sink latency, if the backend timestamps frames.
+    pub latency: Option<LatencySummary>,
+    pub bytes: u64,
+    pub copy_time: Duration,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LatencySummary {
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    pub fn from_ns(mut samples: Vec<u64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let at = |q: f64| {
+            let i = ((samples.len() - 1) as f64 * q).round() as usize;
+            Duration::from_nanos(samples[i])
+        };
+        let mean = samples.iter().sum::<u64>() / samples.len() as u64;
+        Some(Self {
+            mean: Duration::from_nanos(mean),
+            p50: at(0.50),
+            p95: at(0.95),
+            p99: at(0.99),
+            max: Duration::from_nanos(*samples.last().unwrap_or(&0)),
+        })
+    }
+}
+
+/// Captures from `camera` for `duration`, measuring the dispatch path with
+/// a sink that copies every frame but doesn't encode or write anything.
+pub fn run_benchmark(
+    camera: &mut Camera,
+    duration: Duration,
+    quit: &AtomicBool,
+    now_ns: fn() -> u64,
+) -> Result<BenchReport, CameraError> {
+    let samples = Arc::new(Mutex::new(Samples::default()));
+    let samples_cb = Arc::clone(&samples);
+    camera.add_sink(Arc::new(move |frame: Frame| {
+        let received = now_ns();
+        let copy_start = Instant::now();
+        let copy = frame.data.to_vec();
+        let copy_time = copy_start.elapsed();
+
+        let mut samples = samples_cb.lock().unwrap_or_else(|p| p.into_inner());
+        if frame.timestamp_ns != 0 {
+            samples
+                .latencies_ns
+                .push(received.saturating_sub(frame.timestamp_ns));
+        }
+        samples.bytes += copy.len() as u64;
+        samples.copy_time += copy_time;
+    }));
+
+    let mut errors = Vec::new();
+    let mut ended = false;
+    camera.start()?;
+    let started = Instant::now();
+    while started.elapsed() < duration && !quit.load(Ordering::SeqCst) {
+        for ev in camera.events().try_iter() {
+            match ev {
+                CameraEvent::Error { error, .. } => errors.push(error.to_string()),
+                CameraEvent::EndOfStream { .. } => ended = true,
+                _ => {},
+            }
+        }
+        if !errors.is_empty() || ended {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let elapsed = started.elapsed();
+    let stats = camera.stats();
+    let _ = camera.stop();
+
+    let samples = core::mem::take(&mut *samples.lock().unwrap_or_else(|p| p.into_inner()));
+    Ok(BenchReport {
+        elapsed,
+        stats,
+        errors,
+        latency: LatencySummary::from_ns(samples.latencies_ns),
+        bytes: samples.bytes,
+        copy_time: samples.copy_time,
+    })
+}
+
+impl BenchReport {
+    pub fn fps(&self) -> f64 {
+        self.stats.frames_delivered as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Prints the human-readable report; `label` describes the configuration.
+    pub fn print(&self, label: &str) {
+        let secs = self.elapsed.as_secs_f64().max(f64::EPSILON);
+        let mib = |bytes: f64| bytes / (1024.0 * 1024.0);
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+
+        println!("benchmark: {label}");
+        println!("  duration     {secs:.2} s");
+        println!(
+            "  frames       {} delivered, {} dropped ({:.1} fps)",
+            self.stats.frames_delivered,
+            self.stats.frames_dropped,
+            self.fps()
+        );
+        match &self.latency {
+            Some(l) => println!(
+                "  latency      mean {:.2} ms, p50 {:.2} ms, p95 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+                ms(l.mean),
+                ms(l.p50),
+                ms(l.p95),
+                ms(l.p99),
+                ms(l.max)
+            ),
+            None => println!("  latency      n/a (backend provides no capture timestamps)"),
+        }
+        println!("  throughput   {:.1} MiB/s", mib(self.bytes as f64) / secs);
+        if !self.copy_time.is_zero() {
+            println!(
+                "  copy         {:.0} MiB/s",
+                mib(self.bytes as f64) / self.copy_time.as_secs_f64()
+            );
+        }
+        for error in &self.errors {
+            println!("  error        {error}");
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Lens calibration, for `--calibrate`.
+//!
+//! Frames are searched for a checkerboard until enough distinct views of
+//! it are in, then the camera's intrinsics and distortion are solved for
+//! and written as a configuration file, whose `undistort` key the reader
+//! takes as `--undistort`. No frames are emitted.
+
+use asimov_camera_module::shared::{
+    Calibrator, Camera, CameraError, CameraEvent, Checkerboard, Frame,
+};
+use asimov_module::SysexitsError::{self, *};
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::sync_channel,
+    },
+    time::{Duration, Instant},
+};
+
+/// How far, as a fraction of the frame's diagonal, the board's corners
+/// have to have moved on average since the last view for a new one.
+const MIN_VIEW_CHANGE: f64 = 0.02;
+
+/// What to calibrate from and where the result goes.
+pub struct CalibrationRun {
+    pub board: Checkerboard,
+    pub views: usize,
+    pub output: PathBuf,
+    pub duration: Option<Duration>,
+    pub verbose: bool,
+}
+
+impl CalibrationRun {
+    /// Captures from `camera` until enough views are in, then writes the
+    /// calibration. A source that ends, `quit` or the deadline before
+    /// then is `EX_UNAVAILABLE`.
+    pub fn run(self, camera: &mut Camera, quit: &AtomicBool) -> Result<SysexitsError, CameraError> {
+        // Searching a frame takes longer than capturing one, so frames
+        // that arrive meanwhile are skipped.
+        let (tx, rx) = sync_channel::<Frame>(1);
+        camera.add_sink(Arc::new(move |frame: Frame| {
+            let _ = tx.try_send(frame);
+        }));
+        camera.start()?;
+
+        let deadline = self.duration.map(|d| Instant::now() + d);
+        let mut calibrator: Option<Calibrator> = None;
+        let code = 'capture: loop {
+            if quit.load(Ordering::SeqCst) || deadline.is_some_and(|d| Instant::now() >= d) {
+                break EX_UNAVAILABLE;
+            }
+            for event in camera.events().try_iter() {
+                match event {
+                    // As in capture, a backend error is an I/O error.
+                    CameraEvent::Error { error, .. } => {
+                        eprintln!("ERROR: {error}");
+                        break 'capture EX_IOERR;
+                    },
+                    CameraEvent::EndOfStream { .. } => break 'capture EX_UNAVAILABLE,
+                    CameraEvent::Warning { message, .. } if self.verbose => {
+                        eprintln!("WARN: {message}");
+                    },
+                    _ => {},
+                }
+            }
+            let Ok(frame) = rx.recv_timeout(Duration::from_millis(50)) else {
+                continue;
+            };
+            let calibrator = calibrator.get_or_insert_with(|| {
+                let (cols, rows) = (self.board.cols, self.board.rows);
+                Calibrator::for_checkerboard(frame.width, frame.height, cols, rows)
+            });
+            // Frames of another size than the first don't fit its views.
+            if (frame.width, frame.height) != calibrator.size() {
+                continue;
+            }
+            let Some(corners) = self.board.find(&frame) else {
+                continue;
+            };
+            let diagonal = (frame.width as f64).hypot(frame.height as f64);
+            if calibrator
+                .last_view()
+                .is_some_and(|last| mean_distance(last, &corners) < MIN_VIEW_CHANGE * diagonal)
+            {
+                continue;
+            }
+            calibrator.add_view(corners)?;
+            eprintln!("INFO: view {}/{}", calibrator.views(), self.views);
+            if calibrator.views() >= self.views {
+                break EX_OK;
+            }
+        };
+        if let Err(err) = camera.stop() {
+            eprintln!("WARN: stopping capture: {err}");
+        }
+        if code != EX_OK {
+            if code == EX_UNAVAILABLE {
+                let found = calibrator.as_ref().map_or(0, Calibrator::views);
+                eprintln!(
+                    "ERROR: found {found} of {} views of a {} checkerboard",
+                    self.views, self.board
+                );
+            }
+            return Ok(code);
+        }
+
+        let Some(calibrator) = calibrator else {
+            return Ok(EX_UNAVAILABLE);
+        };
+        let report = calibrator.calibrate()?;
+        let text = format!(
+            "# Lens calibration from {} views of a {} checkerboard,\n\
+             # with an RMS reprojection error of {:.3} pixels.\n\
+             undistort = \"{}\"\n",
+            report.views, self.board, report.rms_error, report.calibration
+        );
+        std::fs::write(&self.output, text)
+            .map_err(|e| CameraError::other(format!("writing {}: {e}", self.output.display())))?;
+        eprintln!(
+            "INFO: wrote the calibration to {} (RMS error {:.3} px)",
+            self.output.display(),
+            report.rms_error
+        );
+        Ok(EX_OK)
+    }
+}
+
+/// The mean distance between corresponding points.
+fn mean_distance(a: &[[f64; 2]], b: &[[f64; 2]]) -> f64 {
+    let sum: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(p, q)| (p[0] - q[0]).hypot(p[1] - q[1]))
+        .sum();
+    sum / a.len().max(1) as f64
+}
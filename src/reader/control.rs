@@ -0,0 +1,278 @@
+// This is free and unencumbered software released into the public domain.
+
+//! A control socket for `--control`, so long-running readers can be
+//! inspected and steered without restarting them or sending signals.
+//!
+//! Clients connect to a Unix socket and send one command per line; each
+//! gets one line back, `ok` with any details or `error: ` and the reason:
+//!
+//! - `status`: the `Status` record, as JSON-LD
+//! - `pause`, `resume`: hold capture with the device kept open, and let it
+//!   run again
+//! - `snapshot FILE`: write the next frame, prepared like those of the
+//!   other outputs, to FILE as a PNG or a JPEG (`.jpg`); with
+//!   `--encrypt-key`, encrypted to `FILE.enc`
+//! - `reconfigure [size=WxH] [fps=N] [pixel-format=F]`: switch the capture
+//!   format
+//! - `rotate-recording [FILE]`: finish the `--record` file and start the
+//!   next
+
+#[cfg(feature = "encryption")]
+use crate::output::write_encrypted_image;
+use crate::{output::write_image, parse_dimensions, parse_frequency, parse_pixel_format};
+#[cfg(feature = "encryption")]
+use asimov_camera_module::shared::EncryptionKey;
+use asimov_camera_module::shared::{CameraError, Frame, FrameSink, PixelFormat};
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, Sender, SyncSender},
+    },
+    time::Duration,
+};
+
+/// How long `snapshot` waits for a frame.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A command for the capture loop; `snapshot` is answered by the socket.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Status,
+    Pause,
+    Resume,
+    Reconfigure(Reconfigure),
+    RotateRecording(Option<PathBuf>),
+}
+
+/// The parts of the capture format `reconfigure` changes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reconfigure {
+    pub size: Option<(u32, u32)>,
+    pub fps: Option<f64>,
+    pub pixel_format: Option<PixelFormat>,
+}
+
+/// A command waiting for the capture loop's answer.
+pub struct Request {
+    pub command: Command,
+    reply: Sender<Result<String, String>>,
+}
+
+impl Request {
+    /// Answers the client with `ok` and `details`, or the error.
+    pub fn reply(self, result: Result<String, CameraError>) {
+        let _ = self.reply.send(result.map_err(|e| e.to_string()));
+    }
+}
+
+/// The listening socket, removed again when dropped.
+pub struct ControlSocket {
+    path: PathBuf,
+    requests: Receiver<Request>,
+    snapshots: Arc<Snapshots>,
+}
+
+impl ControlSocket {
+    /// Listens on `path`, replacing a socket no reader serves any more.
+    pub fn bind(path: &Path, debug: bool) -> Result<Self, CameraError> {
+        let in_use = |e: &dyn core::fmt::Display| {
+            CameraError::invalid_config(format!("binding --control {}: {e}", path.display()))
+        };
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(in_use(&"another reader is listening"));
+            }
+            std::fs::remove_file(path).map_err(|e| in_use(&e))?;
+        }
+        let listener = UnixListener::bind(path).map_err(|e| in_use(&e))?;
+        let (tx, requests) = std::sync::mpsc::channel();
+        let snapshots = Arc::new(Snapshots::default());
+        let snapshots_cb = Arc::clone(&snapshots);
+        spawn("control", move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let (tx, snapshots) = (tx.clone(), Arc::clone(&snapshots_cb));
+                let client = move || {
+                    if let Err(err) = serve(stream, &tx, &snapshots)
+                        && debug
+                    {
+                        eprintln!("WARN: --control client: {err}");
+                    }
+                };
+                if let Err(err) = spawn("control-client", client) {
+                    eprintln!("WARN: {err}");
+                }
+            }
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            requests,
+            snapshots,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The commands that arrived since the last call.
+    pub fn requests(&self) -> impl Iterator<Item = Request> + '_ {
+        self.requests.try_iter()
+    }
+
+    /// A sink that hands frames to waiting `snapshot` commands, passed
+    /// through `prepare` first, so snapshots are masked like every output.
+    pub fn snapshot_sink(
+        &self,
+        prepare: Arc<dyn Fn(Frame) -> Option<Frame> + Send + Sync>,
+    ) -> FrameSink {
+        let snapshots = Arc::clone(&self.snapshots);
+        Arc::new(move |frame: Frame| snapshots.deliver(frame, &*prepare))
+    }
+
+    /// Encrypts the files `snapshot` writes with `key`.
+    #[cfg(feature = "encryption")]
+    pub fn set_key(&self, key: EncryptionKey) {
+        let _ = self.snapshots.key.set(key);
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// `snapshot` commands waiting for a frame.
+#[derive(Default)]
+struct Snapshots {
+    waiting: Mutex<Vec<SyncSender<Frame>>>,
+    #[cfg(feature = "encryption")]
+    key: std::sync::OnceLock<EncryptionKey>,
+}
+
+impl Snapshots {
+    fn deliver(&self, frame: Frame, prepare: &(dyn Fn(Frame) -> Option<Frame> + Send + Sync)) {
+        let mut waiting = self.waiting.lock().unwrap_or_else(|p| p.into_inner());
+        if waiting.is_empty() {
+            return;
+        }
+        // A frame that can't be prepared leaves the commands waiting for
+        // the next one.
+        let Some(frame) = prepare(frame) else {
+            return;
+        };
+        for tx in core::mem::take(&mut *waiting) {
+            let _ = tx.try_send(frame.clone());
+        }
+    }
+
+    /// Waits for the next frame and writes it to `path`.
+    fn take(&self, path: &Path) -> Result<String, String> {
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        self.waiting
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .push(tx);
+        let frame = rx
+            .recv_timeout(SNAPSHOT_TIMEOUT)
+            .map_err(|_| format!("no frame within {SNAPSHOT_TIMEOUT:?}"))?;
+        #[cfg(feature = "encryption")]
+        if let Some(key) = self.key.get() {
+            let path = write_encrypted_image(&frame, path, key).map_err(|e| e.to_string())?;
+            return Ok(path.display().to_string());
+        }
+        write_image(&frame, path).map_err(|e| e.to_string())?;
+        Ok(path.display().to_string())
+    }
+}
+
+/// Answers one client's commands until it disconnects.
+fn serve(stream: UnixStream, tx: &Sender<Request>, snapshots: &Snapshots) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let result = match parse(line) {
+            Ok(Parsed::Snapshot(path)) => snapshots.take(&path),
+            Ok(Parsed::Command(command)) => {
+                let (reply, rx) = std::sync::mpsc::channel();
+                match tx.send(Request { command, reply }) {
+                    Ok(()) => rx
+                        .recv()
+                        .unwrap_or_else(|_| Err("the reader is shutting down".into())),
+                    Err(_) => Err("the reader is shutting down".into()),
+                }
+            },
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(details) if details.is_empty() => writeln!(writer, "ok")?,
+            Ok(details) => writeln!(writer, "ok {details}")?,
+            Err(err) => writeln!(writer, "error: {err}")?,
+        }
+    }
+    Ok(())
+}
+
+enum Parsed {
+    Command(Command),
+    Snapshot(PathBuf),
+}
+
+fn parse(line: &str) -> Result<Parsed, String> {
+    let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let args = args.trim();
+    let no_args = |command: Command| match args {
+        "" => Ok(Parsed::Command(command)),
+        _ => Err(format!("'{name}' takes no arguments")),
+    };
+    match name {
+        "status" => no_args(Command::Status),
+        "pause" => no_args(Command::Pause),
+        "resume" => no_args(Command::Resume),
+        "snapshot" if args.is_empty() => Err("'snapshot' needs a FILE".into()),
+        "snapshot" => Ok(Parsed::Snapshot(args.into())),
+        "reconfigure" => {
+            let mut change = Reconfigure::default();
+            for arg in args.split_whitespace() {
+                match arg.split_once('=') {
+                    Some(("size", v)) => change.size = Some(parse_dimensions(v)?),
+                    Some(("fps", v)) => change.fps = Some(parse_frequency(v)?),
+                    Some(("pixel-format", v)) => change.pixel_format = Some(parse_pixel_format(v)?),
+                    _ => {
+                        return Err(format!(
+                            "unknown reconfigure setting '{arg}' (expected size=WxH, fps=N or pixel-format=F)"
+                        ));
+                    },
+                }
+            }
+            if change == Reconfigure::default() {
+                return Err("'reconfigure' needs size=WxH, fps=N or pixel-format=F".into());
+            }
+            Ok(Parsed::Command(Command::Reconfigure(change)))
+        },
+        "rotate-recording" => Ok(Parsed::Command(Command::RotateRecording(
+            (!args.is_empty()).then(|| args.into()),
+        ))),
+        other => Err(format!(
+            "unknown command '{other}' (expected status, pause, resume, snapshot, reconfigure or rotate-recording)"
+        )),
+    }
+}
+
+fn spawn(name: &str, f: impl FnOnce() + Send + 'static) -> Result<(), CameraError> {
+    std::thread::Builder::new()
+        .name(name.into())
+        .spawn(f)
+        .map(drop)
+        .map_err(|e| CameraError::driver("spawning the control thread", e))
+}
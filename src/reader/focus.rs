@@ -0,0 +1,120 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Live focus score, for `--focus-assist`.
+//!
+//! Each frame's sharpness is printed against the best seen so far, so a
+//! lens without autofocus can be turned until the score peaks. On a
+//! terminal the line updates in place; otherwise every update is a line
+//! of its own. No frames are emitted.
+
+use asimov_camera_module::shared::{Camera, CameraError, CameraEvent, FocusStats, Frame};
+use asimov_module::SysexitsError::{self, *};
+use std::{
+    io::{IsTerminal, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::sync_channel,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often the score is printed, at most.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Width of the bar showing the score against the peak, in characters.
+const BAR_WIDTH: usize = 40;
+
+/// How long to assist for.
+pub struct FocusAssist {
+    pub duration: Option<Duration>,
+    pub verbose: bool,
+}
+
+impl FocusAssist {
+    /// Captures from `camera`, printing its focus score until `quit`, the
+    /// deadline or the end of the source.
+    pub fn run(self, camera: &mut Camera, quit: &AtomicBool) -> Result<SysexitsError, CameraError> {
+        // Every frame counts towards the peak, even those not printed.
+        let (tx, rx) = sync_channel::<FocusStats>(64);
+        camera.add_sink(Arc::new(move |frame: Frame| {
+            if let Some(stats) = frame.metadata.focus {
+                let _ = tx.try_send(stats);
+            }
+        }));
+        camera.start()?;
+
+        let terminal = std::io::stdout().is_terminal();
+        let deadline = self.duration.map(|d| Instant::now() + d);
+        let mut peak: Option<f32> = None;
+        let mut printed: Option<Instant> = None;
+        let code = 'capture: loop {
+            if quit.load(Ordering::SeqCst) || deadline.is_some_and(|d| Instant::now() >= d) {
+                break EX_OK;
+            }
+            for event in camera.events().try_iter() {
+                match event {
+                    // As in capture, a backend error is an I/O error.
+                    CameraEvent::Error { error, .. } => {
+                        eprintln!("ERROR: {error}");
+                        break 'capture EX_IOERR;
+                    },
+                    CameraEvent::EndOfStream { .. } => break 'capture EX_OK,
+                    CameraEvent::Warning { message, .. } if self.verbose => {
+                        eprintln!("WARN: {message}");
+                    },
+                    _ => {},
+                }
+            }
+            let Ok(mut stats) = rx.recv_timeout(Duration::from_millis(50)) else {
+                continue;
+            };
+            let mut best = peak.unwrap_or(0.0).max(stats.sharpness);
+            for later in rx.try_iter() {
+                best = best.max(later.sharpness);
+                stats = later;
+            }
+            peak = Some(best);
+            if printed.is_some_and(|t| t.elapsed() < UPDATE_INTERVAL) {
+                continue;
+            }
+            printed = Some(Instant::now());
+            let line = score_line(&stats, best);
+            let mut stdout = std::io::stdout().lock();
+            let written = if terminal {
+                write!(stdout, "\r{line}\x1b[K")
+            } else {
+                writeln!(stdout, "{line}")
+            };
+            if written.and_then(|()| stdout.flush()).is_err() {
+                break EX_IOERR;
+            }
+        };
+        if terminal && printed.is_some() {
+            println!();
+        }
+        if let Err(err) = camera.stop() {
+            eprintln!("WARN: stopping capture: {err}");
+        }
+        if let Some(peak) = peak {
+            eprintln!("INFO: peak sharpness {peak:.1}");
+        }
+        Ok(code)
+    }
+}
+
+/// The current score, the peak and a bar of the one against the other.
+fn score_line(stats: &FocusStats, peak: f32) -> String {
+    let filled = if peak > 0.0 {
+        ((stats.sharpness / peak) * BAR_WIDTH as f32).round() as usize
+    } else {
+        0
+    };
+    format!(
+        "sharpness {:>10.1}  peak {:>10.1}  contrast {:>3.0}%  |{:<BAR_WIDTH$}|",
+        stats.sharpness,
+        peak,
+        stats.contrast * 100.0,
+        "#".repeat(filled.min(BAR_WIDTH)),
+    )
+}
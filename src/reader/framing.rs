@@ -0,0 +1,84 @@
+// This is free and unencumbered software released into the public domain.
+
+//! How records are delimited on stdout.
+//!
+//! Records are newline-delimited by default. A module runner that would
+//! rather not scan for newlines sets `ASIMOV_MODULE_FRAMING` to the
+//! framings it accepts, in order of preference (e.g. `length-prefixed,ndjson`);
+//! the reader picks the first one it supports. With `length-prefixed`, stdout
+//! starts with the line `asimov-framing: length-prefixed/1`, so the runner
+//! can tell an older reader's NDJSON apart, followed by each record as a
+//! 4-byte big-endian length and that many bytes.
+
+use crate::output::OutputFormat;
+use std::io::{self, Write};
+
+/// The environment variable a module runner negotiates framing with.
+pub const FRAMING_ENV: &str = "ASIMOV_MODULE_FRAMING";
+
+const LENGTH_PREFIXED_HEADER: &[u8] = b"asimov-framing: length-prefixed/1\n";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Framing {
+    /// Records as --output writes them, one line each for JSON
+    #[default]
+    Ndjson,
+    /// A header line, then each record behind its 4-byte big-endian length
+    LengthPrefixed,
+}
+
+impl Framing {
+    /// The first framing named in `$ASIMOV_MODULE_FRAMING` that the reader
+    /// supports, if any.
+    pub fn from_env() -> Option<Self> {
+        let offered = std::env::var(FRAMING_ENV).ok()?;
+        offered
+            .split(',')
+            .find_map(|name| clap::ValueEnum::from_str(name.trim(), true).ok())
+    }
+}
+
+/// Writes records to stdout in the negotiated framing.
+#[derive(Clone, Copy, Debug)]
+pub struct RecordWriter {
+    framing: Framing,
+    /// Whether records end in a newline that length prefixes make redundant.
+    ndjson: bool,
+}
+
+impl RecordWriter {
+    pub fn new(framing: Framing, format: OutputFormat) -> Self {
+        Self {
+            framing,
+            ndjson: matches!(
+                format,
+                OutputFormat::Jsonld | OutputFormat::Metadata | OutputFormat::JsonldRef
+            ),
+        }
+    }
+
+    /// Writes what precedes the first record, if the framing has a header.
+    pub fn header(&self, out: &mut impl Write) -> io::Result<()> {
+        match self.framing {
+            Framing::Ndjson => Ok(()),
+            Framing::LengthPrefixed => out.write_all(LENGTH_PREFIXED_HEADER),
+        }
+    }
+
+    pub fn write(&self, out: &mut impl Write, record: &[u8]) -> io::Result<()> {
+        match self.framing {
+            Framing::Ndjson => out.write_all(record),
+            Framing::LengthPrefixed => {
+                let record = if self.ndjson {
+                    record.strip_suffix(b"\n").unwrap_or(record)
+                } else {
+                    record
+                };
+                let len = u32::try_from(record.len())
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "record too large"))?;
+                out.write_all(&len.to_be_bytes())?;
+                out.write_all(record)
+            },
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// This is free and unencumbered software released into the public domain.
+
+//! `--latency-report`: where emitted frames spent their time, stage by
+//! stage, so lag can be pinned on the device, the dispatcher or the output.
+
+use crate::bench::LatencySummary;
+use asimov_camera_module::shared::{Frame, StageLatency, monotonic_ns};
+use std::{sync::Mutex, time::Duration};
+
+/// Frames each stage keeps; longer runs report on the most recent ones.
+const MAX_SAMPLES: usize = 100_000;
+
+/// The stages of one frame, taken as a worker picks it up.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameStages {
+    capture_ns: u64,
+    dispatched_ns: u64,
+    latency: StageLatency,
+}
+
+impl FrameStages {
+    /// `None` for frames that didn't pass through the dispatcher's queue.
+    pub fn of(frame: &Frame) -> Option<Self> {
+        Some(Self {
+            capture_ns: frame.monotonic_ns,
+            dispatched_ns: frame.metadata.stages.dispatched_ns?,
+            latency: frame.stage_latency()?,
+        })
+    }
+}
+
+/// Nanosecond samples in a ring of at most `MAX_SAMPLES`.
+#[derive(Debug, Default)]
+struct Samples {
+    values: Vec<u64>,
+    next: usize,
+}
+
+impl Samples {
+    fn push(&mut self, value: u64) {
+        if self.values.len() < MAX_SAMPLES {
+            self.values.push(value);
+        } else {
+            self.values[self.next] = value;
+            self.next = (self.next + 1) % MAX_SAMPLES;
+        }
+    }
+
+    fn summary(&self) -> Option<LatencySummary> {
+        LatencySummary::from_ns(self.values.clone())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Stages {
+    driver: Samples,
+    dispatch: Samples,
+    sink: Samples,
+    total: Samples,
+    frames: u64,
+}
+
+/// Collects the latencies of the frames the reader emits.
+#[derive(Debug, Default)]
+pub struct LatencyRecorder {
+    stages: Mutex<Stages>,
+}
+
+impl LatencyRecorder {
+    /// Records a frame the output finished with just now.
+    pub fn record(&self, frame: &FrameStages) {
+        let now = monotonic_ns();
+        let nanos = |d: Duration| d.as_nanos() as u64;
+        let mut stages = self.stages.lock().unwrap_or_else(|p| p.into_inner());
+        stages.driver.push(nanos(frame.latency.driver));
+        stages.dispatch.push(nanos(frame.latency.dispatch));
+        stages.sink.push(now.saturating_sub(frame.dispatched_ns));
+        stages.total.push(now.saturating_sub(frame.capture_ns));
+        stages.frames += 1;
+    }
+
+    /// Prints p50/p95/p99 per stage to stderr, which stdout's records
+    /// don't share.
+    pub fn print(&self) {
+        let stages = self.stages.lock().unwrap_or_else(|p| p.into_inner());
+        if stages.frames == 0 {
+            eprintln!("latency: no frames emitted");
+            return;
+        }
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        eprintln!("latency: {} frames", stages.frames);
+        for (name, samples) in [
+            ("driver", &stages.driver),
+            ("dispatch", &stages.dispatch),
+            ("sink", &stages.sink),
+            ("total", &stages.total),
+        ] {
+            if let Some(l) = samples.summary() {
+                eprintln!(
+                    "  {name:<10} p50 {:.2} ms, p95 {:.2} ms, p99 {:.2} ms",
+                    ms(l.p50),
+                    ms(l.p95),
+                    ms(l.p99)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,333 @@
+// This is free and unencumbered software released into the public domain.
+
+//! How the reader starts and stops on Windows, where ctrlc only sees
+//! Ctrl-C and Ctrl-Break: closing the console window, logging off or
+//! shutting down would otherwise end the reader before it stops its ffmpeg
+//! children, and the service control manager (SCM) expects a conversation
+//! of its own.
+//!
+//! - Children the reader starts join a job object that ends them when the
+//!   reader exits, however it exits.
+//! - Console close, logoff and shutdown events stop capture as Ctrl-C
+//!   does, holding the event while the reader shuts down.
+//! - Under `--service`, started by the SCM, the reader reports starting,
+//!   running once the first frame arrives, and stopping, and stops when
+//!   the SCM asks.
+
+use asimov_camera_module::shared::CameraError;
+use asimov_module::SysexitsError::{self, EX_OK, EX_SOFTWARE};
+use core::{ffi::c_void, time::Duration};
+use std::sync::{
+    Arc, Mutex, OnceLock,
+    atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering},
+};
+
+/// How long a console close, logoff or shutdown event holds the reader
+/// open to shut down; Windows ends the process after five seconds anyway.
+const CLOSE_GRACE: Duration = Duration::from_millis(4500);
+
+/// How long the SCM should wait for the next report while the reader
+/// starts or stops.
+const WAIT_HINT_MS: u32 = 10_000;
+
+mod win32 {
+    use core::ffi::c_void;
+
+    pub type Handle = *mut c_void;
+
+    pub const CTRL_C_EVENT: u32 = 0;
+    pub const CTRL_BREAK_EVENT: u32 = 1;
+    pub const CTRL_LOGOFF_EVENT: u32 = 5;
+
+    pub const JOB_OBJECT_EXTENDED_LIMIT_INFORMATION: i32 = 9;
+    pub const JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE: u32 = 0x2000;
+
+    pub const SERVICE_WIN32_OWN_PROCESS: u32 = 0x10;
+    pub const SERVICE_STOPPED: u32 = 1;
+    pub const SERVICE_START_PENDING: u32 = 2;
+    pub const SERVICE_STOP_PENDING: u32 = 3;
+    pub const SERVICE_RUNNING: u32 = 4;
+    pub const SERVICE_ACCEPT_STOP: u32 = 0x1;
+    pub const SERVICE_ACCEPT_SHUTDOWN: u32 = 0x4;
+    pub const SERVICE_CONTROL_STOP: u32 = 1;
+    pub const SERVICE_CONTROL_INTERROGATE: u32 = 4;
+    pub const SERVICE_CONTROL_SHUTDOWN: u32 = 5;
+
+    pub const NO_ERROR: u32 = 0;
+    pub const ERROR_CALL_NOT_IMPLEMENTED: u32 = 120;
+    pub const ERROR_SERVICE_SPECIFIC_ERROR: u32 = 1066;
+
+    /// `JOBOBJECT_EXTENDED_LIMIT_INFORMATION`, of which the reader sets
+    /// only the limit flags.
+    #[allow(dead_code)]
+    #[repr(C)]
+    pub struct ExtendedLimits {
+        pub _time_limits: [i64; 2],
+        pub limit_flags: u32,
+        pub _working_set: [usize; 2],
+        pub _active_process_limit: u32,
+        pub _affinity: usize,
+        pub _classes: [u32; 2],
+        pub _io_counters: [u64; 6],
+        pub _memory: [usize; 4],
+    }
+
+    // Only Windows reads these.
+    #[allow(dead_code)]
+    #[repr(C)]
+    pub struct ServiceStatus {
+        pub service_type: u32,
+        pub current_state: u32,
+        pub controls_accepted: u32,
+        pub win32_exit_code: u32,
+        pub service_specific_exit_code: u32,
+        pub check_point: u32,
+        pub wait_hint: u32,
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    pub struct ServiceTableEntry {
+        pub service_name: *mut u16,
+        pub service_proc: Option<unsafe extern "system" fn(u32, *mut *mut u16)>,
+    }
+
+    pub type HandlerEx = unsafe extern "system" fn(u32, u32, *mut c_void, *mut c_void) -> u32;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        pub fn SetConsoleCtrlHandler(
+            handler: Option<unsafe extern "system" fn(u32) -> i32>,
+            add: i32,
+        ) -> i32;
+        pub fn CreateJobObjectW(attributes: *const c_void, name: *const u16) -> Handle;
+        pub fn SetInformationJobObject(
+            job: Handle,
+            class: i32,
+            info: *const c_void,
+            length: u32,
+        ) -> i32;
+        pub fn AssignProcessToJobObject(job: Handle, process: Handle) -> i32;
+        pub fn GetCurrentProcess() -> Handle;
+    }
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        pub fn StartServiceCtrlDispatcherW(table: *const ServiceTableEntry) -> i32;
+        pub fn RegisterServiceCtrlHandlerExW(
+            name: *const u16,
+            handler: Option<HandlerEx>,
+            context: *mut c_void,
+        ) -> Handle;
+        pub fn SetServiceStatus(handle: Handle, status: *const ServiceStatus) -> i32;
+    }
+}
+
+/// The reader's quit flag, once `watch` has it.
+static QUIT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+/// A stop that arrived before `watch`.
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The SCM's handle for reporting status; null unless running as a service.
+static SERVICE: AtomicPtr<c_void> = AtomicPtr::new(core::ptr::null_mut());
+/// The state last reported to the SCM.
+static STATE: AtomicU32 = AtomicU32::new(0);
+/// Progress reports so far in the current pending state.
+static CHECKPOINT: AtomicU32 = AtomicU32::new(0);
+/// The reader, until the SCM starts the service that runs it.
+type Run = Box<dyn FnOnce() -> SysexitsError + Send>;
+static SERVICE_RUN: Mutex<Option<Run>> = Mutex::new(None);
+static SERVICE_EXIT: Mutex<Option<SysexitsError>> = Mutex::new(None);
+
+/// Stops capture by setting `quit` on console close, logoff and shutdown
+/// events (or SCM stop requests), and puts the children the reader starts
+/// from now on into a job that ends them with it. Call after installing
+/// the Ctrl-C handler, which keeps handling Ctrl-C and Ctrl-Break.
+pub fn watch(quit: &Arc<AtomicBool>) -> Result<(), CameraError> {
+    let _ = QUIT.set(Arc::clone(quit));
+    if STOP_REQUESTED.load(Ordering::SeqCst) {
+        quit.store(true, Ordering::SeqCst);
+    }
+    // Handlers run newest first, so this one sees events before ctrlc's.
+    if unsafe { win32::SetConsoleCtrlHandler(Some(on_console_event), 1) } == 0 {
+        return Err(CameraError::driver(
+            "installing the console event handler",
+            std::io::Error::last_os_error(),
+        ));
+    }
+    kill_children_on_exit()
+}
+
+/// Runs `run` in the service the SCM started this process for, reporting
+/// its exit code as the service's, or right away outside the SCM (e.g.
+/// from a console).
+pub fn run_service(run: impl FnOnce() -> SysexitsError + Send + 'static) -> SysexitsError {
+    *lock(&SERVICE_RUN) = Some(Box::new(run));
+    // A service that runs in its own process may go without a name.
+    let mut name = [0u16];
+    let table = [
+        win32::ServiceTableEntry {
+            service_name: name.as_mut_ptr(),
+            service_proc: Some(service_main),
+        },
+        win32::ServiceTableEntry {
+            service_name: core::ptr::null_mut(),
+            service_proc: None,
+        },
+    ];
+    // Blocks until the service stopped, or fails at once outside the SCM.
+    if unsafe { win32::StartServiceCtrlDispatcherW(table.as_ptr()) } != 0 {
+        return lock(&SERVICE_EXIT).take().unwrap_or(EX_SOFTWARE);
+    }
+    match lock(&SERVICE_RUN).take() {
+        Some(run) => run(),
+        None => EX_SOFTWARE,
+    }
+}
+
+/// Reports the service running, once the first frame arrived.
+pub fn service_running() {
+    report(win32::SERVICE_RUNNING, EX_OK);
+}
+
+/// Tells the SCM the service is still starting, so it doesn't give up
+/// while the camera opens.
+pub fn service_starting() {
+    report(win32::SERVICE_START_PENDING, EX_OK);
+}
+
+/// Reports the service stopping.
+pub fn service_stopping() {
+    report(win32::SERVICE_STOP_PENDING, EX_OK);
+}
+
+fn request_stop() {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+    if let Some(quit) = QUIT.get() {
+        quit.store(true, Ordering::SeqCst);
+    }
+}
+
+unsafe extern "system" fn on_console_event(event: u32) -> i32 {
+    match event {
+        // Left to ctrlc's handler, with its second-press exit.
+        win32::CTRL_C_EVENT | win32::CTRL_BREAK_EVENT => 0,
+        // Services outlive the sessions of the users logging off.
+        win32::CTRL_LOGOFF_EVENT if !SERVICE.load(Ordering::SeqCst).is_null() => 1,
+        _ => {
+            request_stop();
+            // Returning ends the process, so wait for the reader's own
+            // exit, which ends it sooner.
+            std::thread::sleep(CLOSE_GRACE);
+            1
+        },
+    }
+}
+
+unsafe extern "system" fn service_main(_argc: u32, _argv: *mut *mut u16) {
+    let name = [0u16];
+    let handle = unsafe {
+        win32::RegisterServiceCtrlHandlerExW(
+            name.as_ptr(),
+            Some(on_service_control),
+            core::ptr::null_mut(),
+        )
+    };
+    if handle.is_null() {
+        return;
+    }
+    SERVICE.store(handle, Ordering::SeqCst);
+    service_starting();
+    let code = lock(&SERVICE_RUN).take().map_or(EX_SOFTWARE, |run| run());
+    *lock(&SERVICE_EXIT) = Some(code);
+    report(win32::SERVICE_STOPPED, code);
+}
+
+unsafe extern "system" fn on_service_control(
+    control: u32,
+    _event_type: u32,
+    _event_data: *mut c_void,
+    _context: *mut c_void,
+) -> u32 {
+    match control {
+        win32::SERVICE_CONTROL_STOP | win32::SERVICE_CONTROL_SHUTDOWN => {
+            service_stopping();
+            request_stop();
+            win32::NO_ERROR
+        },
+        win32::SERVICE_CONTROL_INTERROGATE => win32::NO_ERROR,
+        _ => win32::ERROR_CALL_NOT_IMPLEMENTED,
+    }
+}
+
+/// Sends the SCM `state`, with `code` as the exit code once stopped.
+fn report(state: u32, code: SysexitsError) {
+    let handle = SERVICE.load(Ordering::SeqCst);
+    if handle.is_null() {
+        return;
+    }
+    // Checkpoints count progress within a pending state.
+    let previous = STATE.swap(state, Ordering::SeqCst);
+    let pending = matches!(
+        state,
+        win32::SERVICE_START_PENDING | win32::SERVICE_STOP_PENDING
+    );
+    let checkpoint = match (pending, previous == state) {
+        (false, _) => 0,
+        (true, true) => CHECKPOINT.fetch_add(1, Ordering::SeqCst) + 1,
+        (true, false) => {
+            CHECKPOINT.store(1, Ordering::SeqCst);
+            1
+        },
+    };
+    let (win32_code, specific) = match code {
+        EX_OK => (win32::NO_ERROR, 0),
+        code => (win32::ERROR_SERVICE_SPECIFIC_ERROR, u32::from(code.as_u8())),
+    };
+    let status = win32::ServiceStatus {
+        service_type: win32::SERVICE_WIN32_OWN_PROCESS,
+        current_state: state,
+        controls_accepted: match state {
+            win32::SERVICE_RUNNING => win32::SERVICE_ACCEPT_STOP | win32::SERVICE_ACCEPT_SHUTDOWN,
+            _ => 0,
+        },
+        win32_exit_code: win32_code,
+        service_specific_exit_code: specific,
+        check_point: checkpoint,
+        wait_hint: if pending { WAIT_HINT_MS } else { 0 },
+    };
+    unsafe { win32::SetServiceStatus(handle, &status) };
+}
+
+/// Puts the reader into a job that ends every process in it once the
+/// reader's handle to it closes, as it does when the reader exits. The
+/// children the reader starts join the job too.
+fn kill_children_on_exit() -> Result<(), CameraError> {
+    let failed = |context| CameraError::driver(context, std::io::Error::last_os_error());
+    let job = unsafe { win32::CreateJobObjectW(core::ptr::null(), core::ptr::null()) };
+    if job.is_null() {
+        return Err(failed("creating a job object for child processes"));
+    }
+    let mut limits: win32::ExtendedLimits = unsafe { core::mem::zeroed() };
+    limits.limit_flags = win32::JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+    let set = unsafe {
+        win32::SetInformationJobObject(
+            job,
+            win32::JOB_OBJECT_EXTENDED_LIMIT_INFORMATION,
+            (&raw const limits).cast(),
+            size_of::<win32::ExtendedLimits>() as u32,
+        )
+    };
+    if set == 0 {
+        return Err(failed("limiting the child process job"));
+    }
+    // The handle stays open for as long as the reader runs.
+    if unsafe { win32::AssignProcessToJobObject(job, win32::GetCurrentProcess()) } == 0 {
+        return Err(failed("joining the child process job"));
+    }
+    Ok(())
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|p| p.into_inner())
+}
@@ -0,0 +1,241 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Serving frames as an MJPEG stream over HTTP.
+//!
+//! `GET /` answers with a `multipart/x-mixed-replace` stream of JPEGs, which
+//! browsers, VLC and ffmpeg play as is, and `GET /snapshot.jpg` with the
+//! next frame. Frames are only encoded while someone is connected.
+
+use asimov_camera_module::shared::{CameraError, Frame, HttpRequest};
+use std::{
+    io::Write,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+/// Concurrent connections; further requests get `503 Service Unavailable`.
+const MAX_CLIENTS: usize = 8;
+
+const JPEG_QUALITY: u8 = 80;
+
+const BOUNDARY: &str = "asimov-frame";
+
+/// How long a request may take to arrive, and a snapshot to be taken.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The `--serve-mjpeg` endpoint.
+pub struct MjpegServer {
+    shared: Arc<Shared>,
+    local_addr: SocketAddr,
+}
+
+struct Shared {
+    latest: Mutex<Latest>,
+    updated: Condvar,
+    clients: AtomicUsize,
+    stopped: AtomicBool,
+    debug: bool,
+}
+
+#[derive(Default)]
+struct Latest {
+    jpeg: Option<Arc<Vec<u8>>>,
+    /// Counts encoded frames, so clients can wait for the next one.
+    seq: u64,
+}
+
+impl MjpegServer {
+    pub fn bind(addr: SocketAddr, debug: bool) -> Result<Self, CameraError> {
+        let listener = TcpListener::bind(addr).map_err(|e| {
+            CameraError::invalid_config(format!("binding --serve-mjpeg {addr}: {e}"))
+        })?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(|e| CameraError::driver("binding --serve-mjpeg", e))?;
+        let shared = Arc::new(Shared {
+            latest: Mutex::new(Latest::default()),
+            updated: Condvar::new(),
+            clients: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+            debug,
+        });
+        let accepting = Arc::clone(&shared);
+        std::thread::Builder::new()
+            .name("mjpeg-accept".into())
+            .spawn(move || accept(listener, accepting))
+            .map_err(|e| CameraError::driver("spawning the MJPEG server thread", e))?;
+        Ok(Self { shared, local_addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Hands `frame` to the connected clients.
+    pub fn send(&self, frame: &Frame) {
+        if self.shared.clients.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let jpeg = match frame.to_jpeg(JPEG_QUALITY) {
+            Ok(jpeg) => jpeg,
+            Err(err) => {
+                if self.shared.debug {
+                    eprintln!("WARN: --serve-mjpeg: {err}");
+                }
+                return;
+            },
+        };
+        let mut latest = self.shared.lock();
+        latest.jpeg = Some(Arc::new(jpeg));
+        latest.seq += 1;
+        self.shared.updated.notify_all();
+    }
+}
+
+impl Drop for MjpegServer {
+    /// Ends the streams; the accept thread lives until the process exits.
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        self.shared.updated.notify_all();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Latest> {
+        self.latest.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    /// Blocks until a frame newer than `seq` is encoded, or `timeout`
+    /// passes; `None` once the server stops.
+    fn next_frame(&self, seq: u64, timeout: Duration) -> Option<(u64, Arc<Vec<u8>>)> {
+        let deadline = std::time::Instant::now() + timeout;
+        let mut latest = self.lock();
+        loop {
+            if self.stopped.load(Ordering::SeqCst) {
+                return None;
+            }
+            if latest.seq > seq
+                && let Some(jpeg) = &latest.jpeg
+            {
+                return Some((latest.seq, Arc::clone(jpeg)));
+            }
+            let left = deadline.saturating_duration_since(std::time::Instant::now());
+            if left.is_zero() {
+                return None;
+            }
+            latest = self
+                .updated
+                .wait_timeout(latest, left)
+                .unwrap_or_else(|p| p.into_inner())
+                .0;
+        }
+    }
+}
+
+fn accept(listener: TcpListener, shared: Arc<Shared>) {
+    for stream in listener.incoming() {
+        if shared.stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let client = Arc::clone(&shared);
+        let spawned = std::thread::Builder::new()
+            .name("mjpeg-client".into())
+            .spawn(move || {
+                if let Err(err) = handle(stream, &client)
+                    && client.debug
+                {
+                    eprintln!("WARN: --serve-mjpeg client: {err}");
+                }
+            });
+        if let Err(err) = spawned
+            && shared.debug
+        {
+            eprintln!("WARN: --serve-mjpeg: {err}");
+        }
+    }
+}
+
+/// Decrements the client count when a connection ends.
+struct Client<'a>(&'a Shared);
+
+impl Drop for Client<'_> {
+    fn drop(&mut self) {
+        self.0.clients.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Answers one HTTP/1.1 request, then closes the connection.
+fn handle(mut stream: TcpStream, shared: &Shared) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let (request, _) = HttpRequest::read(&mut stream, 0)?;
+
+    if shared.clients.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+        shared.clients.fetch_sub(1, Ordering::SeqCst);
+        return respond(
+            &mut stream,
+            "503 Service Unavailable",
+            "text/plain",
+            format!("at most {MAX_CLIENTS} clients\n").as_bytes(),
+        );
+    }
+    let _client = Client(shared);
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={BOUNDARY}\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n"
+            )?;
+            let mut seq = 0;
+            while !shared.stopped.load(Ordering::SeqCst) {
+                let Some((next, jpeg)) = shared.next_frame(seq, Duration::from_secs(1)) else {
+                    continue;
+                };
+                seq = next;
+                write!(
+                    stream,
+                    "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                    jpeg.len()
+                )?;
+                stream.write_all(&jpeg)?;
+                stream.write_all(b"\r\n")?;
+                stream.flush()?;
+            }
+            Ok(())
+        },
+        ("GET", "/snapshot.jpg") => {
+            let seq = shared.lock().seq;
+            match shared.next_frame(seq, REQUEST_TIMEOUT) {
+                Some((_, jpeg)) => respond(&mut stream, "200 OK", "image/jpeg", &jpeg),
+                None => respond(
+                    &mut stream,
+                    "503 Service Unavailable",
+                    "text/plain",
+                    b"no frame\n",
+                ),
+            }
+        },
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"not found\n"),
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
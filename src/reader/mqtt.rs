@@ -0,0 +1,290 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Publishing motion, camera events, status and snapshots to an MQTT broker.
+//!
+//! Under the topic prefix, `status` holds the retained availability
+//! (`online`, or `offline` once the reader exits or the broker loses it),
+//! `health` the latest retained `Status` record, `snapshot` the latest
+//! retained JPEG, while `motion` and `event` carry JSON records as they
+//! happen.
+
+use asimov_camera_module::shared::{CameraError, CameraEvent, Frame};
+use core::str::FromStr;
+use rumqttc::{
+    Client, Event, Incoming, LastWill, MqttOptions, Outgoing, QoS, TlsConfiguration, Transport,
+};
+use std::{
+    path::Path,
+    sync::Mutex,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Requests queued for the connection thread before publishing drops.
+const QUEUE_CAPACITY: usize = 16;
+
+/// Largest packet sent or accepted; snapshots are the big ones.
+const MAX_PACKET_BYTES: usize = 8 << 20;
+
+/// Pause between reconnection attempts.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How long shutdown waits for `offline` to reach the broker.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+const SNAPSHOT_QUALITY: u8 = 80;
+
+/// A broker URL: `mqtt://[USER[:PASS]@]HOST[:PORT]`, or `mqtts://` for TLS.
+/// Without a password in the URL, `MQTT_PASSWORD` is used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MqttUrl {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl FromStr for MqttUrl {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (tls, rest) = match s.split_once("://") {
+            Some(("mqtt" | "tcp", rest)) => (false, rest),
+            Some(("mqtts" | "ssl", rest)) => (true, rest),
+            _ => {
+                return Err(CameraError::invalid_config(format!(
+                    "unknown MQTT URL '{s}' (expected mqtt://HOST[:PORT] or mqtts://HOST[:PORT])"
+                )));
+            },
+        };
+        let rest = rest.trim_end_matches('/');
+        let (auth, address) = match rest.rsplit_once('@') {
+            Some((auth, address)) => (Some(auth), address),
+            None => (None, rest),
+        };
+        let (username, password) = match auth.map(|a| a.split_once(':').unwrap_or((a, ""))) {
+            Some((user, pass)) => (
+                Some(user.to_string()),
+                (!pass.is_empty()).then(|| pass.to_string()),
+            ),
+            None => (None, None),
+        };
+        let default_port = if tls { 8883 } else { 1883 };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse().map_err(|_| {
+                    CameraError::invalid_config(format!("invalid MQTT port '{port}'"))
+                })?,
+            ),
+            None => (address, default_port),
+        };
+        if host.is_empty() {
+            return Err(CameraError::invalid_config(format!(
+                "MQTT URL '{s}' has no host"
+            )));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            tls,
+            username,
+            password,
+        })
+    }
+}
+
+/// The default topic prefix for `device`: `asimov/camera/` and the device
+/// with everything but letters, digits, `-` and `_` replaced by `_`.
+pub fn default_topic(device: &str) -> String {
+    let device: String = device
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("asimov/camera/{device}")
+}
+
+/// A connection to the `--mqtt` broker. Publishing never blocks: with the
+/// broker unreachable, messages are dropped while the connection thread
+/// keeps reconnecting.
+pub struct MqttSink {
+    client: Client,
+    topic: String,
+    snapshot_interval: Option<Duration>,
+    last_snapshot: Mutex<Option<Instant>>,
+    connection: Option<JoinHandle<()>>,
+    debug: bool,
+}
+
+impl MqttSink {
+    pub fn connect(
+        url: &MqttUrl,
+        topic: String,
+        ca: Option<&Path>,
+        snapshot_interval: Option<Duration>,
+        debug: bool,
+    ) -> Result<Self, CameraError> {
+        if topic.is_empty() || topic.contains(['+', '#']) {
+            return Err(CameraError::invalid_config(format!(
+                "invalid MQTT topic '{topic}' (must be non-empty, without + or #)"
+            )));
+        }
+        let client_id = format!("asimov-camera-{}", std::process::id());
+        let mut options = MqttOptions::new(client_id, &url.host, url.port);
+        options
+            .set_max_packet_size(MAX_PACKET_BYTES, MAX_PACKET_BYTES)
+            .set_last_will(LastWill::new(
+                format!("{topic}/status"),
+                "offline",
+                QoS::AtLeastOnce,
+                true,
+            ));
+        if let Some(username) = &url.username {
+            let password = url
+                .password
+                .clone()
+                .or_else(|| std::env::var("MQTT_PASSWORD").ok())
+                .unwrap_or_default();
+            options.set_credentials(username, password);
+        }
+        match (url.tls, ca) {
+            (true, Some(path)) => {
+                let ca = std::fs::read(path).map_err(|e| {
+                    CameraError::invalid_config(format!("reading {}: {e}", path.display()))
+                })?;
+                options.set_transport(Transport::tls_with_config(TlsConfiguration::Simple {
+                    ca,
+                    alpn: None,
+                    client_auth: None,
+                }));
+            },
+            (true, None) => {
+                options.set_transport(Transport::tls_with_default_config());
+            },
+            (false, Some(_)) => {
+                return Err(CameraError::invalid_config(
+                    "--mqtt-ca needs an mqtts:// broker URL",
+                ));
+            },
+            (false, None) => {},
+        }
+
+        let (client, mut connection) = Client::new(options, QUEUE_CAPACITY);
+        let online = client.clone();
+        let status_topic = format!("{topic}/status");
+        let broker = format!("{}:{}", url.host, url.port);
+        let connection = std::thread::Builder::new()
+            .name("mqtt".into())
+            .spawn(move || {
+                let mut connected = None;
+                for notification in connection.iter() {
+                    match notification {
+                        Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                            if debug {
+                                eprintln!("INFO: connected to MQTT broker {broker}");
+                            }
+                            connected = Some(true);
+                            // Replaces the last will left by an earlier connection.
+                            let _ =
+                                online.try_publish(&status_topic, QoS::AtLeastOnce, true, "online");
+                        },
+                        Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                        Ok(_) => {},
+                        Err(err) => {
+                            // Warn when the broker is first unreachable or lost, not on every retry.
+                            if connected != Some(false) {
+                                eprintln!("WARN: MQTT broker {broker}: {err}");
+                            }
+                            connected = Some(false);
+                            std::thread::sleep(RECONNECT_DELAY);
+                        },
+                    }
+                }
+            })
+            .map_err(|e| CameraError::driver("spawning MQTT connection thread", e))?;
+
+        Ok(Self {
+            client,
+            topic,
+            snapshot_interval,
+            last_snapshot: Mutex::new(None),
+            connection: Some(connection),
+            debug,
+        })
+    }
+
+    /// Publishes a motion `Observation` record to `TOPIC/motion`.
+    pub fn motion(&self, record: &[u8]) {
+        self.send("motion", QoS::AtLeastOnce, false, trim_newline(record));
+    }
+
+    /// Publishes an encoded camera event to `TOPIC/event`. Frame drops are
+    /// left to the health records, which count them.
+    pub fn event(&self, event: &CameraEvent, record: &[u8]) {
+        if !matches!(event, CameraEvent::FrameDropped { .. }) {
+            self.send("event", QoS::AtLeastOnce, false, trim_newline(record));
+        }
+    }
+
+    /// Publishes a `Status` record as the retained `TOPIC/health`.
+    pub fn health(&self, record: &[u8]) {
+        self.send("health", QoS::AtMostOnce, true, trim_newline(record));
+    }
+
+    /// Publishes `frame` as the retained JPEG `TOPIC/snapshot`, if the
+    /// snapshot interval has passed since the last one.
+    pub fn snapshot(&self, frame: &Frame) {
+        let Some(interval) = self.snapshot_interval else {
+            return;
+        };
+        {
+            let mut last = self.last_snapshot.lock().unwrap_or_else(|p| p.into_inner());
+            if last.is_some_and(|t| t.elapsed() < interval) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+        match frame.to_jpeg(SNAPSHOT_QUALITY) {
+            Ok(jpeg) => self.send("snapshot", QoS::AtMostOnce, true, jpeg),
+            Err(err) if self.debug => eprintln!("WARN: MQTT snapshot: {err}"),
+            Err(_) => {},
+        }
+    }
+
+    fn send(&self, subtopic: &str, qos: QoS, retain: bool, payload: impl Into<Vec<u8>>) {
+        let topic = format!("{}/{subtopic}", self.topic);
+        if let Err(err) = self.client.try_publish(topic, qos, retain, payload)
+            && self.debug
+        {
+            eprintln!("WARN: MQTT publish to {}/{subtopic}: {err}", self.topic);
+        }
+    }
+}
+
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        let status = format!("{}/status", self.topic);
+        let _ = self
+            .client
+            .try_publish(status, QoS::AtLeastOnce, true, "offline");
+        let _ = self.client.try_disconnect();
+        if let Some(connection) = self.connection.take() {
+            let deadline = Instant::now() + DISCONNECT_TIMEOUT;
+            while !connection.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+}
+
+fn trim_newline(record: &[u8]) -> Vec<u8> {
+    record.strip_suffix(b"\n").unwrap_or(record).to_vec()
+}
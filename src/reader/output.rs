@@ -0,0 +1,799 @@
+// This is free and unencumbered software released into the public domain.
+
+#[cfg(feature = "provenance")]
+use crate::provenance::Attestation;
+use crate::status::StatusSnapshot;
+use asimov_camera_module::shared::{
+    CameraError, CameraEvent, Frame, FrameBundle, FrameStream, Observation, PixelFormat, RdfFormat,
+    to_rdf,
+};
+#[cfg(feature = "stereo")]
+use asimov_camera_module::shared::{DISPARITY_SCALE, StereoResult};
+#[cfg(feature = "encryption")]
+use asimov_camera_module::shared::{EncryptionKey, encrypted_path};
+use ciborium::Value as CborValue;
+use know::traits::ToJsonLd;
+use serde_json::{Map, Value, json};
+use std::path::{Path, PathBuf};
+
+/// The `@context` of `--vocab schema` records.
+const SCHEMA_CONTEXT: &str = "https://schema.org";
+
+/// Quality of `.jpg` files from `write_image`.
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// JSON-LD Image with the pixels, as raw RGB8, embedded as a data URL
+    #[default]
+    Jsonld,
+    /// Frame metadata only: device, timestamp, dimensions, hash, saved file
+    Metadata,
+    /// JSON-LD Image referencing the file written to --save-dir
+    JsonldRef,
+    /// CBOR sequence of Image maps with the pixels as a binary payload
+    Cbor,
+    /// RDF statements of the JSON-LD records, one per line
+    Nquads,
+    /// RDF of the JSON-LD records, one Turtle document each
+    Turtle,
+}
+
+impl OutputFormat {
+    fn rdf(self) -> Option<RdfFormat> {
+        match self {
+            OutputFormat::Nquads => Some(RdfFormat::NQuads),
+            OutputFormat::Turtle => Some(RdfFormat::Turtle),
+            _ => None,
+        }
+    }
+}
+
+/// Which classes emitted frames are described with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Vocab {
+    /// ASIMOV's `know` Image
+    #[default]
+    Know,
+    /// schema.org ImageObject
+    Schema,
+}
+
+/// How records are identified and annotated, from --vocab, --base-iri and --property.
+#[derive(Clone, Debug, Default)]
+pub struct Vocabulary {
+    pub vocab: Vocab,
+    /// Prefix for record ids in place of `DEVICE#`.
+    pub base_iri: Option<String>,
+    /// Extra properties added to every record.
+    pub properties: Map<String, Value>,
+}
+
+impl Vocabulary {
+    /// The id of a record named `local` among `source`'s records.
+    pub fn id(&self, source: &str, local: &str) -> String {
+        match &self.base_iri {
+            Some(base) => format!("{base}{local}"),
+            None => format!("{source}#{local}"),
+        }
+    }
+
+    /// Adds the extra properties to `value`, never replacing the record's own.
+    fn annotate(&self, mut value: Value) -> Value {
+        if let Some(object) = value.as_object_mut() {
+            for (key, property) in &self.properties {
+                object
+                    .entry(key.as_str())
+                    .or_insert_with(|| property.clone());
+            }
+        }
+        value
+    }
+}
+
+/// What gets serialized for one emitted frame.
+pub struct FrameRecord<'a> {
+    pub frame: &'a Frame,
+    pub vocab: &'a Vocabulary,
+    pub source: &'a str,
+    pub timestamp_ns: u64,
+    pub hash: Option<String>,
+    pub file: Option<PathBuf>,
+    /// The `--provenance` digest and signature.
+    #[cfg(feature = "provenance")]
+    pub attestation: Option<Attestation>,
+}
+
+impl FrameRecord<'_> {
+    pub fn id(&self) -> String {
+        let local = match self.frame.stream {
+            FrameStream::Color => self.timestamp_ns.to_string(),
+            stream => format!("{}-{}", stream.as_str(), self.timestamp_ns),
+        };
+        self.vocab.id(self.source, &local)
+    }
+
+    /// Encodes the record as one line of NDJSON, one CBOR data item, or its RDF.
+    pub fn encode(&self, format: OutputFormat) -> Result<Vec<u8>, CameraError> {
+        if format == OutputFormat::Cbor {
+            return self.to_cbor();
+        }
+        if let Some(rdf) = format.rdf() {
+            return to_rdf(&self.to_json(format)?, rdf).map(String::into_bytes);
+        }
+        let mut line = serde_json::to_vec(&self.to_json(format)?)
+            .map_err(|e| CameraError::driver("serializing JSON", e))?;
+        line.push(b'\n');
+        Ok(line)
+    }
+
+    pub fn to_json(&self, format: OutputFormat) -> Result<Value, CameraError> {
+        match format {
+            OutputFormat::Jsonld
+            | OutputFormat::Cbor
+            | OutputFormat::Nquads
+            | OutputFormat::Turtle => {
+                // The data URL's `image/rgb` is 8-bit RGB, whatever the frame's format.
+                let rgb = match self.frame.pixel_format {
+                    PixelFormat::Rgb8 => self.frame.clone().ensure_packed()?,
+                    _ => self.frame.to_rgb8()?,
+                };
+                self.image(rgb.data.to_vec())
+            },
+            OutputFormat::Metadata => {
+                let mut value = json!({
+                    "id": self.id(),
+                    "source": self.source,
+                    "timestamp": self.timestamp_ns,
+                    "width": self.frame.width,
+                    "height": self.frame.height,
+                    "format": self.frame.pixel_format.as_str(),
+                });
+                if self.frame.monotonic_ns != 0 {
+                    value["monotonicTimestamp"] = self.frame.monotonic_ns.into();
+                }
+                if self.frame.stream != FrameStream::Color {
+                    value["stream"] = self.frame.stream.as_str().into();
+                }
+                if self.frame.bit_depth() != 8 {
+                    value["bitDepth"] = self.frame.bit_depth().into();
+                }
+                let colorimetry = &self.frame.colorimetry;
+                if !colorimetry.is_unknown() {
+                    value["colorimetry"] = json!({
+                        "matrix": colorimetry.matrix.map(|m| m.as_str()),
+                        "range": colorimetry.range.map(|r| r.as_str()),
+                        "srgb": colorimetry.srgb,
+                    });
+                }
+                if let Some(hash) = &self.hash {
+                    value["hash"] = hash.as_str().into();
+                }
+                if let Some(stats) = &self.frame.metadata.luminance {
+                    value["luminance"] = json!({
+                        "mean": stats.mean,
+                        "dark": stats.dark_fraction,
+                        "bright": stats.bright_fraction,
+                        "histogram": stats.histogram,
+                    });
+                }
+                if let Some(stats) = &self.frame.metadata.focus {
+                    value["focus"] = json!({
+                        "sharpness": stats.sharpness,
+                        "contrast": stats.contrast,
+                        "histogram": stats.histogram,
+                    });
+                }
+                if let Some(telemetry) = &self.frame.metadata.telemetry {
+                    value["telemetry"] = telemetry.fields.clone().into();
+                }
+                if let Some(trigger) = self.trigger() {
+                    value["trigger"] = trigger;
+                }
+                if let Some(file) = &self.file {
+                    value["file"] = file.display().to_string().into();
+                }
+                #[cfg(feature = "provenance")]
+                self.attest(&mut value);
+                Ok(self.vocab.annotate(value))
+            },
+            OutputFormat::JsonldRef => {
+                let file = self
+                    .file
+                    .as_deref()
+                    .ok_or_else(|| CameraError::other("frame was not saved to --save-dir"))?;
+                let mut value = self.image(Vec::new())?;
+                if let Some(object) = value.as_object_mut() {
+                    if object.remove("data").is_some() {
+                        object.insert("url".into(), file_url(file).into());
+                    } else {
+                        object.insert("contentUrl".into(), file_url(file).into());
+                        object.insert("encodingFormat".into(), "image/png".into());
+                    }
+                }
+                Ok(value)
+            },
+        }
+    }
+
+    fn to_cbor(&self) -> Result<Vec<u8>, CameraError> {
+        let packed = self.frame.clone().ensure_packed()?;
+        let text = |s: &str| CborValue::Text(s.to_string());
+        let mut entries = Vec::new();
+        if self.vocab.vocab == Vocab::Schema {
+            entries.push((text("@context"), text(SCHEMA_CONTEXT)));
+        }
+        entries.extend([
+            (text("@type"), text(self.image_type())),
+            (text("@id"), CborValue::Text(self.id())),
+            (text("width"), self.frame.width.into()),
+            (text("height"), self.frame.height.into()),
+            (text("stride"), packed.stride.into()),
+            (text("format"), text(self.frame.pixel_format.as_str())),
+            (text("timestamp"), self.timestamp_ns.into()),
+            (text("source"), text(self.source)),
+            (text("data"), CborValue::Bytes(packed.data.to_vec())),
+        ]);
+        if self.frame.stream != FrameStream::Color {
+            entries.push((text("stream"), text(self.frame.stream.as_str())));
+        }
+        if self.frame.bit_depth() != 8 {
+            entries.push((text("bitDepth"), self.frame.bit_depth().into()));
+        }
+        if let Some(telemetry) = &self.frame.metadata.telemetry {
+            let telemetry = CborValue::serialized(&telemetry.fields)
+                .map_err(|e| CameraError::other(format!("serializing CBOR: {e}")))?;
+            entries.push((text("telemetry"), telemetry));
+        }
+        if let Some(trigger) = self.trigger() {
+            let trigger = CborValue::serialized(&trigger)
+                .map_err(|e| CameraError::other(format!("serializing CBOR: {e}")))?;
+            entries.push((text("trigger"), trigger));
+        }
+        #[cfg(feature = "provenance")]
+        if let Some(attestation) = &self.attestation {
+            entries.push((
+                text("sha256"),
+                CborValue::Bytes(attestation.sha256.to_vec()),
+            ));
+            if let Some((signature, public_key)) = &attestation.signature {
+                entries.push((text("signature"), CborValue::Bytes(signature.to_vec())));
+                entries.push((text("publicKey"), CborValue::Bytes(public_key.to_vec())));
+            }
+        }
+        for (key, property) in &self.vocab.properties {
+            if !entries.iter().any(|(k, _)| k.as_text() == Some(key)) {
+                let property = CborValue::serialized(property)
+                    .map_err(|e| CameraError::other(format!("serializing CBOR: {e}")))?;
+                entries.push((text(key), property));
+            }
+        }
+        let value = CborValue::Map(entries);
+        let mut buf = Vec::with_capacity(packed.data.len() + 128);
+        ciborium::into_writer(&value, &mut buf)
+            .map_err(|e| CameraError::other(format!("serializing CBOR: {e}")))?;
+        Ok(buf)
+    }
+
+    fn image_type(&self) -> &'static str {
+        match self.vocab.vocab {
+            Vocab::Know => "Image",
+            Vocab::Schema => "ImageObject",
+        }
+    }
+
+    fn image(&self, data: Vec<u8>) -> Result<Value, CameraError> {
+        let img = know::classes::Image {
+            id: Some(self.id()),
+            width: Some(self.frame.width as _),
+            height: Some(self.frame.height as _),
+            data,
+            source: Some(self.source.to_string()),
+        };
+        let mut value = img
+            .to_jsonld()
+            .map_err(|e| CameraError::driver("serializing JSON-LD image", e))?;
+        if self.vocab.vocab == Vocab::Schema {
+            let created =
+                jiff::Timestamp::from_nanosecond(self.timestamp_ns.into()).unwrap_or_default();
+            value = json!({
+                "@context": SCHEMA_CONTEXT,
+                "@type": self.image_type(),
+                "@id": value["@id"].take(),
+                "width": self.frame.width,
+                "height": self.frame.height,
+                "contentUrl": value["data"].take(),
+                "dateCreated": created.to_string(),
+                "isBasedOn": self.source,
+            });
+        }
+        if let Some(telemetry) = &self.frame.metadata.telemetry {
+            let fields = &telemetry.fields;
+            if self.vocab.vocab == Vocab::Schema
+                && let (Some(latitude), Some(longitude)) = (
+                    fields.get("latitude").and_then(Value::as_f64),
+                    fields.get("longitude").and_then(Value::as_f64),
+                )
+            {
+                value["contentLocation"] = json!({
+                    "@type": "Place",
+                    "geo": {
+                        "@type": "GeoCoordinates",
+                        "latitude": latitude,
+                        "longitude": longitude,
+                    },
+                });
+            }
+            value["telemetry"] = fields.clone().into();
+        }
+        if let Some(trigger) = self.trigger() {
+            value["trigger"] = trigger;
+        }
+        #[cfg(feature = "provenance")]
+        self.attest(&mut value);
+        Ok(self.vocab.annotate(value))
+    }
+
+    /// The record with the frame's packed samples, in its own pixel format,
+    /// embedded as an `application/octet-stream` data URL.
+    #[cfg(feature = "stereo")]
+    fn raw_image(&self) -> Result<Value, CameraError> {
+        let packed = self.frame.clone().ensure_packed()?;
+        let mut value = self.image(packed.data.to_vec())?;
+        for key in ["data", "contentUrl"] {
+            if let Some(url) = value[key].as_str() {
+                value[key] = url
+                    .replacen("data:image/rgb", "data:application/octet-stream", 1)
+                    .into();
+            }
+        }
+        Ok(value)
+    }
+
+    /// The frame's place among the triggered exposures, and when the
+    /// software trigger it answers was fired.
+    fn trigger(&self) -> Option<Value> {
+        let trigger = self.frame.metadata.trigger?;
+        let mut value = json!({"sequence": trigger.sequence});
+        if let Some(fired_ns) = trigger.fired_ns {
+            value["firedAt"] = fired_ns.into();
+        }
+        Some(value)
+    }
+
+    /// Adds the attestation's properties to `value`.
+    #[cfg(feature = "provenance")]
+    fn attest(&self, value: &mut Value) {
+        if let (Some(attestation), Some(object)) = (&self.attestation, value.as_object_mut())
+            && let Value::Object(properties) = attestation.to_json()
+        {
+            object.extend(properties);
+        }
+    }
+}
+
+impl StatusSnapshot {
+    /// Encodes a `Status` record in the same framing as `format`'s frames.
+    pub fn encode(
+        &self,
+        source: &str,
+        timestamp_ns: u64,
+        vocab: &Vocabulary,
+        format: OutputFormat,
+    ) -> Result<Vec<u8>, CameraError> {
+        let mut value = json!({
+            "@type": "Status",
+            "@id": vocab.id(source, &format!("status-{timestamp_ns}")),
+            "source": source,
+            "timestamp": timestamp_ns,
+            "uptime": self.uptime.as_secs_f64(),
+            "framesEmitted": self.frames_emitted,
+            "framesDropped": self.frames_dropped,
+            "mode": self.mode,
+        });
+        if let Some(error) = &self.last_error {
+            value["lastError"] = error.as_str().into();
+        }
+        encode_value(&vocab.annotate(value), format)
+    }
+}
+
+/// Encodes an analyzer `Observation` record in the same framing as `format`'s frames.
+pub fn encode_observation(
+    source: &str,
+    analyzer: &str,
+    timestamp_ns: u64,
+    observation: &Observation,
+    vocab: &Vocabulary,
+    format: OutputFormat,
+) -> Result<Vec<u8>, CameraError> {
+    let mut value = json!({
+        "@type": "Observation",
+        "@id": vocab.id(
+            source,
+            &format!("{analyzer}-{}-{timestamp_ns}", observation.label)
+        ),
+        "source": source,
+        "timestamp": timestamp_ns,
+        "analyzer": analyzer,
+        "label": observation.label,
+    });
+    if let Some(v) = observation.value {
+        value["value"] = v.into();
+    }
+    if let Some(c) = observation.confidence {
+        value["confidence"] = c.into();
+    }
+    if let Some(bbox) = observation.bbox {
+        value["boundingBox"] = json!({
+            "x": bbox.x,
+            "y": bbox.y,
+            "width": bbox.width,
+            "height": bbox.height,
+        });
+    }
+    encode_value(&vocab.annotate(value), format)
+}
+
+/// Encodes a `FrameBundle` record, holding the record of each frame (as
+/// `format` would encode it alone), in the same framing as `format`'s frames.
+/// `sources[i]` names the camera of `bundle.frames[i]`; `stereo` is the
+/// bundle's `stereo_value`, if any.
+pub fn encode_bundle(
+    sources: &[String],
+    bundle: &FrameBundle,
+    vocab: &Vocabulary,
+    format: OutputFormat,
+    now_ns: u64,
+    stereo: Option<Value>,
+) -> Result<Vec<u8>, CameraError> {
+    let timestamp_ns = |frame: &Frame| match frame.timestamp_ns {
+        0 => now_ns,
+        ns => ns,
+    };
+    let frames = bundle
+        .frames
+        .iter()
+        .zip(sources)
+        .map(|(frame, source)| {
+            let record = FrameRecord {
+                frame,
+                vocab,
+                source,
+                timestamp_ns: timestamp_ns(frame),
+                hash: None,
+                file: None,
+                #[cfg(feature = "provenance")]
+                attestation: None,
+            };
+            // Embedded frames keep their pixels in CBOR and RDF too.
+            let frame_format = match format {
+                OutputFormat::Metadata => OutputFormat::Metadata,
+                _ => OutputFormat::Jsonld,
+            };
+            record.to_json(frame_format)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let first = bundle.frames.first().map_or(now_ns, timestamp_ns);
+    let source = sources.first().map_or("", String::as_str);
+    let mut value = json!({
+        "@type": "FrameBundle",
+        "@id": vocab.id(source, &format!("bundle-{first}")),
+        "timestamp": first,
+        "spread": bundle.spread().as_secs_f64(),
+        "frames": frames,
+    });
+    if let Some(stereo) = stereo {
+        value["stereo"] = stereo;
+    }
+    encode_value(&vocab.annotate(value), format)
+}
+
+/// The `stereo` object of a `--stereo` bundle: the disparity statistics, in
+/// pixels, and with `map`, the record of the depth map (or, without a
+/// calibration, the disparity map), pixels included.
+#[cfg(feature = "stereo")]
+pub fn stereo_value(
+    result: &StereoResult,
+    source: &str,
+    vocab: &Vocabulary,
+    timestamp_ns: u64,
+    map: bool,
+) -> Result<Value, CameraError> {
+    let stats = &result.stats;
+    let mut value = json!({
+        "validFraction": stats.valid_fraction,
+        "disparity": {
+            "min": stats.min_disparity,
+            "median": stats.median_disparity,
+            "max": stats.max_disparity,
+        },
+    });
+    if let Some(depth) = stats.median_depth_m {
+        value["medianDepth"] = depth.into();
+    }
+    if map {
+        let frame = result.depth.as_ref().unwrap_or(&result.disparity);
+        let record = FrameRecord {
+            frame,
+            vocab,
+            source,
+            timestamp_ns,
+            hash: None,
+            file: None,
+            #[cfg(feature = "provenance")]
+            attestation: None,
+        };
+        let mut map = record.raw_image()?;
+        // The data holds the raw little-endian samples: millimetres of depth,
+        // or disparity in `scale` pixels.
+        map["format"] = frame.pixel_format.as_str().into();
+        if result.depth.is_none() {
+            map["scale"] = (1.0 / DISPARITY_SCALE).into();
+        }
+        value["map"] = map;
+    }
+    Ok(value)
+}
+
+/// Encodes a camera event as one NDJSON line, for `--events` on stderr.
+pub fn encode_event(
+    source: &str,
+    timestamp_ns: u64,
+    event: &CameraEvent,
+) -> Result<Vec<u8>, CameraError> {
+    let (name, backend, details) = match event {
+        CameraEvent::Started { backend } => ("Started", backend, json!({})),
+        CameraEvent::Stopped { backend, stats } => (
+            "Stopped",
+            backend,
+            json!({
+                "framesCaptured": stats.frames_captured,
+                "framesDelivered": stats.frames_delivered,
+                "framesDropped": stats.frames_dropped,
+                "framesMalformed": stats.frames_malformed,
+                "framesShed": stats.frames_shed,
+                "queuePeak": stats.queue_peak,
+                "queueCapacity": stats.queue_capacity,
+            }),
+        ),
+        CameraEvent::FrameDropped { backend } => ("FrameDropped", backend, json!({})),
+        CameraEvent::Warning { backend, message } => {
+            ("Warning", backend, json!({ "message": message }))
+        },
+        CameraEvent::PipelineChanged {
+            backend,
+            label,
+            previous_sinks,
+            sinks,
+        } => (
+            "PipelineChanged",
+            backend,
+            json!({ "label": label, "previousSinks": previous_sinks, "sinks": sinks }),
+        ),
+        CameraEvent::PrivacyChanged { backend, active } => {
+            ("PrivacyChanged", backend, json!({ "active": active }))
+        },
+        CameraEvent::PauseChanged { backend, paused } => {
+            ("PauseChanged", backend, json!({ "paused": paused }))
+        },
+        CameraEvent::DeviceChanged { backend, device } => {
+            ("DeviceChanged", backend, json!({ "device": device }))
+        },
+        CameraEvent::Backpressure {
+            backend,
+            depth,
+            capacity,
+            after,
+        } => (
+            "Backpressure",
+            backend,
+            json!({ "depth": depth, "capacity": capacity, "afterMs": after.as_millis() as u64 }),
+        ),
+        CameraEvent::LowDiskSpace {
+            backend,
+            path,
+            available,
+            min_free,
+        } => (
+            "LowDiskSpace",
+            backend,
+            json!({ "path": path, "available": available, "minFree": min_free }),
+        ),
+        CameraEvent::Stalled { backend, after } => (
+            "Stalled",
+            backend,
+            json!({ "stalledMs": after.as_millis() as u64 }),
+        ),
+        CameraEvent::FormatChanged {
+            backend,
+            width,
+            height,
+            fps,
+        } => (
+            "FormatChanged",
+            backend,
+            json!({ "width": width, "height": height, "fps": fps }),
+        ),
+        CameraEvent::Downgraded {
+            backend,
+            reason,
+            previous_width,
+            previous_height,
+            previous_fps,
+            width,
+            height,
+            fps,
+        } => (
+            "Downgraded",
+            backend,
+            json!({
+                "reason": reason.to_string(),
+                "previousWidth": previous_width,
+                "previousHeight": previous_height,
+                "previousFps": previous_fps,
+                "width": width,
+                "height": height,
+                "fps": fps,
+            }),
+        ),
+        CameraEvent::AutoLockChanged {
+            backend,
+            control,
+            locked,
+        } => (
+            "AutoLockChanged",
+            backend,
+            json!({ "control": control.as_str(), "locked": locked }),
+        ),
+        CameraEvent::EndOfStream { backend } => ("EndOfStream", backend, json!({})),
+        CameraEvent::Observed {
+            backend,
+            analyzer,
+            observations,
+            ..
+        } => (
+            "Observed",
+            backend,
+            json!({ "analyzer": analyzer, "observations": observations.len() }),
+        ),
+        CameraEvent::Error { backend, error } => {
+            ("Error", backend, json!({ "message": error.to_string() }))
+        },
+    };
+    let mut value = json!({
+        "event": name,
+        "source": source,
+        "backend": format!("{backend:?}").to_lowercase(),
+        "timestamp": timestamp_ns,
+    });
+    if let (Some(value), Value::Object(details)) = (value.as_object_mut(), details) {
+        value.extend(details);
+    }
+    encode_value(&value, OutputFormat::Jsonld)
+}
+
+fn encode_value(value: &Value, format: OutputFormat) -> Result<Vec<u8>, CameraError> {
+    if let Some(rdf) = format.rdf() {
+        return to_rdf(value, rdf).map(String::into_bytes);
+    }
+    let mut buf = Vec::new();
+    if format == OutputFormat::Cbor {
+        ciborium::into_writer(value, &mut buf)
+            .map_err(|e| CameraError::other(format!("serializing CBOR: {e}")))?;
+    } else {
+        serde_json::to_writer(&mut buf, value)
+            .map_err(|e| CameraError::driver("serializing JSON", e))?;
+        buf.push(b'\n');
+    }
+    Ok(buf)
+}
+
+/// Writes `frame` as `<dir>/<timestamp_ns>.png` and returns the path.
+pub fn save_frame(dir: &Path, frame: &Frame, timestamp_ns: u64) -> Result<PathBuf, CameraError> {
+    let path = dir.join(format!("{timestamp_ns}.png"));
+    write_image(frame, &path)?;
+    Ok(path)
+}
+
+/// Writes `frame` as `<dir>/<timestamp_ns>.png.enc`, encrypted with `key`,
+/// and returns the path.
+#[cfg(feature = "encryption")]
+pub fn save_encrypted_frame(
+    dir: &Path,
+    frame: &Frame,
+    timestamp_ns: u64,
+    key: &EncryptionKey,
+) -> Result<PathBuf, CameraError> {
+    write_encrypted_image(frame, &dir.join(format!("{timestamp_ns}.png")), key)
+}
+
+/// Writes `frame` like `write_image`, but encrypted with `key` to
+/// `encrypted_path(path)`, and returns that path.
+#[cfg(feature = "encryption")]
+pub fn write_encrypted_image(
+    frame: &Frame,
+    path: &Path,
+    key: &EncryptionKey,
+) -> Result<PathBuf, CameraError> {
+    let (plain, path) = (path, encrypted_path(path));
+    let failed = |e: &dyn core::fmt::Display| {
+        CameraError::other(format!("saving frame to {}: {e}", path.display()))
+    };
+    let data = if is_jpeg_path(plain) {
+        frame.to_jpeg(JPEG_QUALITY)?
+    } else {
+        let image =
+            to_image(frame).ok_or_else(|| CameraError::other("frame buffer is too short"))?;
+        let mut png = Vec::new();
+        image
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .map_err(|e| failed(&e))?;
+        png
+    };
+    std::fs::write(&path, key.encrypt(&data)).map_err(|e| failed(&e))?;
+    Ok(path)
+}
+
+/// Writes `frame` to `path` in the image format its extension names, as
+/// an 8-bit JPEG for `.jpg`.
+pub fn write_image(frame: &Frame, path: &Path) -> Result<(), CameraError> {
+    let failed = |e: &dyn core::fmt::Display| {
+        CameraError::other(format!("saving frame to {}: {e}", path.display()))
+    };
+    if is_jpeg_path(path) {
+        let data = frame.to_jpeg(JPEG_QUALITY)?;
+        return std::fs::write(path, data).map_err(|e| failed(&e));
+    }
+    let image = to_image(frame).ok_or_else(|| CameraError::other("frame buffer is too short"))?;
+    image.save(path).map_err(|e| failed(&e))
+}
+
+fn is_jpeg_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"))
+}
+
+fn to_image(frame: &Frame) -> Option<image::DynamicImage> {
+    if !frame.validate() {
+        return None;
+    }
+    match frame.pixel_format {
+        // Images of 10-bit frames are 8-bit; raw and CBOR output keep the depth.
+        PixelFormat::Rgb8 | PixelFormat::P010 => {
+            let rgb = frame.to_rgb8().ok()?;
+            image::RgbImage::from_raw(rgb.width, rgb.height, rgb.data.into())
+                .map(image::DynamicImage::ImageRgb8)
+        },
+        PixelFormat::Rgba8 | PixelFormat::Bgra8 | PixelFormat::Rgba1010102 => {
+            let rgba = frame.to_rgba8().ok()?;
+            image::RgbaImage::from_raw(rgba.width, rgba.height, rgba.data.into())
+                .map(image::DynamicImage::ImageRgba8)
+        },
+        // 16-bit PNGs keep the full depth/IR range.
+        PixelFormat::Gray16 | PixelFormat::Z16 => {
+            let packed = frame.clone().ensure_packed().ok()?;
+            let samples = packed
+                .data
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect();
+            image::ImageBuffer::<image::Luma<u16>, Vec<u16>>::from_raw(
+                frame.width,
+                frame.height,
+                samples,
+            )
+            .map(image::DynamicImage::ImageLuma16)
+        },
+    }
+}
+
+fn file_url(path: &Path) -> String {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{path}")
+    } else {
+        format!("file:///{path}")
+    }
+}
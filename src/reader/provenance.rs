@@ -0,0 +1,93 @@
+// This is free and unencumbered software released into the public domain.
+
+//! `--provenance` and `--signing-key`: a SHA-256 digest, and with a key an
+//! ed25519 signature, on each emitted frame's record, and a manifest next
+//! to each finished `--record` file.
+
+use asimov_camera_module::shared::{
+    CameraError, Frame, SigningKey, frame_message, segment_message, sha256_file, to_hex,
+};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+
+/// Digests frames and signs them if there's a key.
+#[derive(Debug)]
+pub struct Provenance {
+    key: Option<SigningKey>,
+}
+
+/// What a record gets.
+pub struct Attestation {
+    pub sha256: [u8; 32],
+    /// The signature and the public key that checks it.
+    pub signature: Option<([u8; 64], [u8; 32])>,
+}
+
+impl Attestation {
+    /// The record properties, with everything in lowercase hex.
+    pub fn to_json(&self) -> Value {
+        let mut value = json!({ "sha256": to_hex(&self.sha256) });
+        if let Some((signature, public_key)) = &self.signature {
+            value["signature"] = to_hex(signature).into();
+            value["publicKey"] = to_hex(public_key).into();
+        }
+        value
+    }
+}
+
+impl Provenance {
+    pub fn new(key: Option<SigningKey>) -> Self {
+        Self { key }
+    }
+
+    /// Digests `frame`, the one of the record `id`, and signs the digest.
+    pub fn attest(&self, id: &str, frame: &Frame) -> Result<Attestation, CameraError> {
+        let sha256 = frame.sha256()?;
+        let signature = self.key.as_ref().map(|key| {
+            let message = frame_message(id, &sha256);
+            (key.sign(message.as_bytes()), key.public_key())
+        });
+        Ok(Attestation { sha256, signature })
+    }
+
+    /// Writes `<file>.manifest.json`: the file's digest and those of the
+    /// `frames` recorded into it, by capture time, signed as a whole.
+    pub fn write_manifest(
+        &self,
+        file: &Path,
+        frames: &[(u64, [u8; 32])],
+    ) -> Result<PathBuf, CameraError> {
+        let failed = |e: &dyn core::fmt::Display| {
+            CameraError::other(format!("writing the manifest of {}: {e}", file.display()))
+        };
+        let name = file
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let sha256 = sha256_file(file).map_err(|e| failed(&e))?;
+        let mut manifest = json!({
+            "file": name,
+            "sha256": to_hex(&sha256),
+            "frames": frames
+                .iter()
+                .map(|(timestamp, digest)| json!({
+                    "timestamp": timestamp,
+                    "sha256": to_hex(digest),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(key) = &self.key {
+            let message = segment_message(&name, &sha256, frames);
+            manifest["signature"] = to_hex(&key.sign(message.as_bytes())).into();
+            manifest["publicKey"] = to_hex(&key.public_key()).into();
+        }
+        let mut path = file.as_os_str().to_owned();
+        path.push(".manifest.json");
+        let path = PathBuf::from(path);
+        let mut data = serde_json::to_vec_pretty(&manifest).map_err(|e| failed(&e))?;
+        data.push(b'\n');
+        std::fs::write(&path, data).map_err(|e| failed(&e))?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,113 @@
+// This is free and unencumbered software released into the public domain.
+
+#[cfg(feature = "zmq")]
+use asimov_camera_module::shared::ZmqPublisher;
+use asimov_camera_module::shared::{CameraError, Frame};
+#[cfg(all(feature = "shm", unix))]
+use asimov_camera_module::shared::{SHM_PREFIX, ShmPublisher};
+use core::str::FromStr;
+
+/// Slots per shared-memory ring: how many frames a subscriber may lag
+/// before it starts skipping.
+#[cfg(all(feature = "shm", unix))]
+const SHM_SLOTS: u32 = 4;
+
+/// Where `--publish` sends emitted frames besides stdout.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PublishTarget {
+    /// A shared-memory ring that `shm:NAME` devices subscribe to.
+    #[cfg(all(feature = "shm", unix))]
+    Shm(String),
+    /// A ZeroMQ PUB socket; `metadata_only` drops the pixel part.
+    #[cfg(feature = "zmq")]
+    Zmq {
+        endpoint: String,
+        metadata_only: bool,
+    },
+}
+
+impl FromStr for PublishTarget {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        #[cfg(all(feature = "shm", unix))]
+        if let Some(name) = s.strip_prefix(SHM_PREFIX) {
+            return Ok(PublishTarget::Shm(name.to_string()));
+        }
+        #[cfg(feature = "zmq")]
+        if s.contains("://") {
+            let (endpoint, query) = s.split_once('?').unwrap_or((s, ""));
+            let metadata_only = match query {
+                "" | "content=frames" => false,
+                "content=metadata" => true,
+                other => {
+                    return Err(CameraError::invalid_config(format!(
+                        "unknown publish option '{other}' (expected content=frames or content=metadata)"
+                    )));
+                },
+            };
+            return Ok(PublishTarget::Zmq {
+                endpoint: endpoint.to_string(),
+                metadata_only,
+            });
+        }
+        let expected: &[&str] = &[
+            #[cfg(all(feature = "shm", unix))]
+            "shm:NAME",
+            #[cfg(feature = "zmq")]
+            "a ZeroMQ endpoint like tcp://*:5555",
+        ];
+        Err(CameraError::invalid_config(format!(
+            "unknown publish target '{s}' (expected {})",
+            expected.join(" or ")
+        )))
+    }
+}
+
+/// An opened `--publish` target.
+pub enum Publisher {
+    #[cfg(all(feature = "shm", unix))]
+    Shm(ShmPublisher),
+    #[cfg(feature = "zmq")]
+    Zmq {
+        socket: ZmqPublisher,
+        metadata_only: bool,
+    },
+}
+
+impl Publisher {
+    /// Opens `target` for frames of up to `max_frame_bytes` packed bytes.
+    pub fn open(target: &PublishTarget, max_frame_bytes: usize) -> Result<Self, CameraError> {
+        let _ = max_frame_bytes;
+        match target {
+            #[cfg(all(feature = "shm", unix))]
+            PublishTarget::Shm(name) => {
+                ShmPublisher::create(name, max_frame_bytes, SHM_SLOTS).map(Publisher::Shm)
+            },
+            #[cfg(feature = "zmq")]
+            PublishTarget::Zmq {
+                endpoint,
+                metadata_only,
+            } => Ok(Publisher::Zmq {
+                socket: ZmqPublisher::bind(endpoint)?,
+                metadata_only: *metadata_only,
+            }),
+        }
+    }
+
+    /// Publishes `frame`; `metadata` is its encoded record, for targets
+    /// that carry one.
+    pub fn publish(&self, frame: &Frame, metadata: &[u8]) -> Result<(), CameraError> {
+        let _ = metadata;
+        match self {
+            #[cfg(all(feature = "shm", unix))]
+            Publisher::Shm(ring) => ring.publish(frame),
+            #[cfg(feature = "zmq")]
+            Publisher::Zmq {
+                socket,
+                metadata_only,
+            } => socket.publish(frame, !metadata_only, Some(metadata)),
+        }
+    }
+}
@@ -0,0 +1,450 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Recording frames into a video file.
+//!
+//! ffmpeg encodes RGB8 frames from its stdin, choosing the container and
+//! codec from the file extension (e.g. H.264 in `.mp4`, VP9 in `.webm`). It
+//! starts with the first frame, whose size the recording keeps; frames of
+//! another size, as after a device switch, are skipped until the recording
+//! is rotated.
+//!
+//! With `--encrypt-key`, ffmpeg writes the container to a pipe instead, and
+//! `FILE.enc` receives it encrypted as it arrives; MP4 and MOV are then
+//! fragmented, as they can't be finished without seeking back. With
+//! `--provenance`, each finished file gets a manifest of the frames in it;
+//! with `--s3`, both are uploaded once finished.
+
+#[cfg(feature = "provenance")]
+use crate::provenance::Provenance;
+#[cfg(feature = "s3")]
+use crate::upload::Uploader;
+use asimov_camera_module::shared::{CameraError, Frame};
+#[cfg(feature = "encryption")]
+use asimov_camera_module::shared::{EncryptionKey, encrypted_path};
+#[cfg(any(feature = "provenance", feature = "s3"))]
+use std::sync::Arc;
+use std::{
+    env,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::Mutex,
+    thread::JoinHandle,
+};
+
+/// The `--record` file and the encoder writing it.
+pub struct Recorder {
+    fps: f64,
+    debug: bool,
+    #[cfg(feature = "encryption")]
+    key: Option<EncryptionKey>,
+    #[cfg(feature = "provenance")]
+    provenance: Option<Arc<Provenance>>,
+    #[cfg(feature = "s3")]
+    uploader: Option<Arc<Uploader>>,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    path: PathBuf,
+    state: State,
+}
+
+enum State {
+    Waiting,
+    Recording(Encoder),
+    /// Finished, or failed with a warning.
+    Stopped,
+}
+
+struct Encoder {
+    child: Child,
+    stdin: ChildStdin,
+    /// Encrypts what ffmpeg writes to its stdout into the file.
+    encrypter: Option<JoinHandle<std::io::Result<()>>>,
+    size: (u32, u32),
+    /// The capture time and digest of each frame written, for the manifest.
+    #[cfg(feature = "provenance")]
+    frames: Vec<(u64, [u8; 32])>,
+    /// Whether a frame of another size was already reported.
+    warned: bool,
+}
+
+impl Recorder {
+    /// Records frames arriving at up to `fps` into `path`, replacing it.
+    pub fn new(path: &Path, fps: f64, debug: bool) -> Self {
+        Self {
+            fps,
+            debug,
+            #[cfg(feature = "encryption")]
+            key: None,
+            #[cfg(feature = "provenance")]
+            provenance: None,
+            #[cfg(feature = "s3")]
+            uploader: None,
+            inner: Mutex::new(Inner {
+                path: path.to_path_buf(),
+                state: State::Waiting,
+            }),
+        }
+    }
+
+    /// Encrypts the recordings with `key`, into `FILE.enc`.
+    #[cfg(feature = "encryption")]
+    pub fn with_key(mut self, key: EncryptionKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Writes a manifest next to each finished recording.
+    #[cfg(feature = "provenance")]
+    pub fn with_provenance(mut self, provenance: Arc<Provenance>) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+
+    /// Uploads each finished recording, and its manifest.
+    #[cfg(feature = "s3")]
+    pub fn with_uploader(mut self, uploader: Arc<Uploader>) -> Self {
+        self.uploader = Some(uploader);
+        self
+    }
+
+    fn encrypted(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        return self.key.is_some();
+        #[cfg(not(feature = "encryption"))]
+        false
+    }
+
+    /// Where the recording of `path` is written.
+    fn file(&self, path: &Path) -> PathBuf {
+        #[cfg(feature = "encryption")]
+        if self.encrypted() {
+            return encrypted_path(path);
+        }
+        path.to_path_buf()
+    }
+
+    pub fn send(&self, frame: &Frame) {
+        let mut inner = self.lock();
+        let Inner { path, state } = &mut *inner;
+        let rgb = match frame.to_rgb8() {
+            Ok(rgb) => rgb,
+            Err(err) => {
+                if self.debug {
+                    eprintln!("WARN: --record: {err}");
+                }
+                return;
+            },
+        };
+        let file = self.file(path);
+        if matches!(*state, State::Waiting) {
+            *state = match self.spawn(&file, (rgb.width, rgb.height)) {
+                Ok(encoder) => State::Recording(encoder),
+                Err(err) => {
+                    eprintln!("WARN: not recording to {}: {err}", file.display());
+                    State::Stopped
+                },
+            };
+        }
+        let State::Recording(encoder) = state else {
+            return;
+        };
+        if encoder.size != (rgb.width, rgb.height) {
+            if !encoder.warned {
+                encoder.warned = true;
+                eprintln!(
+                    "WARN: skipping {}x{} frames in the {}x{} recording {}",
+                    rgb.width,
+                    rgb.height,
+                    encoder.size.0,
+                    encoder.size.1,
+                    file.display()
+                );
+            }
+            return;
+        }
+        if let Err(err) = encoder.stdin.write_all(&rgb.data) {
+            eprintln!("WARN: recording to {} failed: {err}", file.display());
+            if let State::Recording(mut encoder) = core::mem::replace(state, State::Stopped) {
+                let _ = encoder.child.kill();
+                let _ = encoder.child.wait();
+            }
+            return;
+        }
+        self.remember(encoder, frame);
+    }
+
+    /// Notes the digest of `frame`, just recorded, for the manifest.
+    #[cfg_attr(not(feature = "provenance"), allow(unused_variables))]
+    fn remember(&self, encoder: &mut Encoder, frame: &Frame) {
+        #[cfg(feature = "provenance")]
+        if self.provenance.is_some() {
+            match frame.sha256() {
+                Ok(digest) => encoder.frames.push((frame.timestamp_ns, digest)),
+                Err(err) => eprintln!("WARN: --provenance: {err}"),
+            }
+        }
+    }
+
+    /// Writes the manifest of the finished recording `file` and its
+    /// `frames`, and queues both for upload.
+    #[cfg_attr(not(feature = "provenance"), allow(unused_variables))]
+    fn finished(&self, file: &Path, frames: Vec<(u64, [u8; 32])>) -> Result<(), CameraError> {
+        #[cfg(feature = "provenance")]
+        #[cfg_attr(not(feature = "s3"), allow(unused_variables))]
+        let manifest = match &self.provenance {
+            Some(provenance) => Some(provenance.write_manifest(file, &frames)?),
+            None => None,
+        };
+        #[cfg(feature = "s3")]
+        if let Some(uploader) = &self.uploader {
+            uploader.upload(file);
+            #[cfg(feature = "provenance")]
+            if let Some(manifest) = &manifest {
+                uploader.upload(manifest);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends the recording, waiting for ffmpeg to finish writing the file,
+    /// which players can't open without its trailer.
+    pub fn finish(&self) -> Result<(), CameraError> {
+        let mut inner = self.lock();
+        let mut state = core::mem::replace(&mut inner.state, State::Stopped);
+        let frames = take_frames(&mut state);
+        let file = self.file(&inner.path);
+        if close(state, &file)? {
+            self.finished(&file, frames)?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the current file and records the next frames into `next`,
+    /// or, without one, moves the finished file aside to a name stamped
+    /// with the time (`out-20240501T100000Z.mp4`, or
+    /// `out-20240501T100000Z.mp4.enc` when encrypted) and starts over at the
+    /// same path. The next frame sets the new recording's size, which may
+    /// differ from the last one's. Returns where the finished recording
+    /// is, if one was under way.
+    pub fn rotate(&self, next: Option<&Path>) -> Result<Option<PathBuf>, CameraError> {
+        let mut inner = self.lock();
+        let mut state = core::mem::replace(&mut inner.state, State::Waiting);
+        let frames = take_frames(&mut state);
+        let file = self.file(&inner.path);
+        let finished = match (close(state, &file)?, next) {
+            (false, _) => None,
+            (true, Some(_)) => Some(file),
+            (true, None) => {
+                let stamped = self.file(&stamped_path(&inner.path));
+                std::fs::rename(&file, &stamped)
+                    .map_err(|e| CameraError::driver("moving the finished recording aside", e))?;
+                Some(stamped)
+            },
+        };
+        if let Some(finished) = &finished {
+            self.finished(finished, frames)?;
+        }
+        if let Some(next) = next {
+            inner.path = next.to_path_buf();
+        }
+        Ok(finished)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    /// Starts ffmpeg recording into `file`.
+    fn spawn(&self, file: &Path, size: (u32, u32)) -> Result<Encoder, CameraError> {
+        let mut ffargs: Vec<String> = vec![
+            "-hide_banner".into(),
+            "-nostats".into(),
+            "-loglevel".into(),
+            "error".into(),
+            "-y".into(),
+            "-f".into(),
+            "rawvideo".into(),
+            "-pix_fmt".into(),
+            "rgb24".into(),
+            "-s".into(),
+            format!("{}x{}", size.0, size.1),
+            "-framerate".into(),
+            self.fps.to_string(),
+            "-i".into(),
+            "pipe:0".into(),
+            // 4:2:0 needs even dimensions.
+            "-vf".into(),
+            "pad=ceil(iw/2)*2:ceil(ih/2)*2".into(),
+            "-pix_fmt".into(),
+            "yuv420p".into(),
+        ];
+        if self.encrypted() {
+            ffargs.extend(pipe_format(file).into_iter().map(String::from));
+            ffargs.push("pipe:1".into());
+        } else {
+            ffargs.push(file.display().to_string());
+        }
+        let stderr = if self.debug || env::var_os("ASIMOV_CAMERA_FFMPEG_STDERR").is_some() {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        };
+        let mut child = Command::new("ffmpeg")
+            .args(&ffargs)
+            .stdin(Stdio::piped())
+            .stdout(if self.encrypted() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stderr(stderr)
+            .spawn()
+            .map_err(|e| CameraError::driver("spawning ffmpeg for --record", e))?;
+        let Some(stdin) = child.stdin.take() else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(CameraError::other("ffmpeg for --record has no stdin"));
+        };
+        #[cfg(feature = "encryption")]
+        let encrypter = match (&self.key, child.stdout.take()) {
+            (Some(key), Some(stdout)) => match spawn_encrypter(key, stdout, file) {
+                Ok(encrypter) => Some(encrypter),
+                Err(err) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(err);
+                },
+            },
+            _ => None,
+        };
+        #[cfg(not(feature = "encryption"))]
+        let encrypter = None;
+        Ok(Encoder {
+            child,
+            stdin,
+            encrypter,
+            size,
+            #[cfg(feature = "provenance")]
+            frames: Vec::new(),
+            warned: false,
+        })
+    }
+}
+
+/// The frames the recording of `state`, if any, holds, for its manifest.
+fn take_frames(state: &mut State) -> Vec<(u64, [u8; 32])> {
+    match state {
+        #[cfg(feature = "provenance")]
+        State::Recording(encoder) => core::mem::take(&mut encoder.frames),
+        _ => Vec::new(),
+    }
+}
+
+/// Waits for the encoder of `state`, if any, to finish writing `path`;
+/// returns whether there was one.
+fn close(state: State, path: &Path) -> Result<bool, CameraError> {
+    let State::Recording(Encoder {
+        mut child,
+        stdin,
+        encrypter,
+        ..
+    }) = state
+    else {
+        return Ok(false);
+    };
+    drop(stdin);
+    let status = child
+        .wait()
+        .map_err(|e| CameraError::driver("waiting for the --record encoder", e))?;
+    if let Some(encrypter) = encrypter {
+        encrypter
+            .join()
+            .unwrap_or_else(|_| Err(std::io::Error::other("the encrypting thread panicked")))
+            .map_err(|e| {
+                CameraError::other(format!("encrypting the recording {}: {e}", path.display()))
+            })?;
+    }
+    if !status.success() {
+        return Err(CameraError::other(format!(
+            "ffmpeg recording to {} exited with {status}",
+            path.display()
+        )));
+    }
+    Ok(true)
+}
+
+/// The ffmpeg muxer for the container `file`'s extension names, ahead of an
+/// `.enc`, with what it needs to write to a pipe.
+fn pipe_format(file: &Path) -> Vec<&'static str> {
+    let plain = file.with_extension("");
+    let ext = plain
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "mp4" | "m4v" => vec!["-movflags", "frag_keyframe+empty_moov", "-f", "mp4"],
+        "mov" => vec!["-movflags", "frag_keyframe+empty_moov", "-f", "mov"],
+        "webm" => vec!["-f", "webm"],
+        "ts" => vec!["-f", "mpegts"],
+        "avi" => vec!["-f", "avi"],
+        "flv" => vec!["-f", "flv"],
+        _ => vec!["-f", "matroska"],
+    }
+}
+
+/// Copies ffmpeg's output into `file`, encrypted with `key`.
+#[cfg(feature = "encryption")]
+fn spawn_encrypter(
+    key: &EncryptionKey,
+    mut stdout: std::process::ChildStdout,
+    file: &Path,
+) -> Result<JoinHandle<std::io::Result<()>>, CameraError> {
+    let out = std::fs::File::create(file)
+        .map_err(|e| CameraError::driver("creating the --record file", e))?;
+    let mut writer = key.encryptor(std::io::BufWriter::new(out));
+    std::thread::Builder::new()
+        .name("record-encrypt".into())
+        .spawn(move || {
+            std::io::copy(&mut stdout, &mut writer)?;
+            writer.finish()?.into_inner()?.sync_all()
+        })
+        .map_err(|e| CameraError::driver("spawning the --record encryption thread", e))
+}
+
+/// Whether `name` is that of a recording `rotate` moved aside from `path`,
+/// encrypted or not, or its manifest.
+pub fn is_rotated(path: &Path, name: &str) -> bool {
+    let name = name.strip_suffix(".manifest.json").unwrap_or(name);
+    let name = name.strip_suffix(".enc").unwrap_or(name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let Some(rest) = name.strip_prefix(&*stem).and_then(|r| r.strip_prefix('-')) else {
+        return false;
+    };
+    let stamp = match path.extension() {
+        Some(ext) => rest.strip_suffix(&*format!(".{}", ext.to_string_lossy())),
+        None => Some(rest),
+    };
+    // `20240501T100000Z`, as `stamped_path` writes it.
+    stamp.is_some_and(|stamp| {
+        let b = stamp.as_bytes();
+        b.len() == 16
+            && b[8] == b'T'
+            && b[15] == b'Z'
+            && b[..8].iter().chain(&b[9..15]).all(u8::is_ascii_digit)
+    })
+}
+
+/// `path` with the current UTC time before its extension.
+fn stamped_path(path: &Path) -> PathBuf {
+    let stamp = jiff::Timestamp::now().strftime("%Y%m%dT%H%M%SZ");
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{stamp}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{stamp}"),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,99 @@
+// This is free and unencumbered software released into the public domain.
+
+//! `--max-disk` and `--retention` for the frames `--save-dir` keeps and the
+//! finished `--record` files, swept in the background, with a warning once
+//! their disk runs low.
+
+use crate::record::is_rotated;
+use asimov_camera_module::shared::{
+    CameraBackend, CameraError, CameraEvent, Retention, RetentionPolicy,
+};
+use core::time::Duration;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::SyncSender,
+    },
+    time::Instant,
+};
+
+/// How often the directories are swept.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The files the reader writes: `<timestamp_ns>.png` frames in `save_dir`,
+/// and the recordings `rotate-recording` moved aside next to `record`, as
+/// well as their `.enc` counterparts.
+pub fn retention(
+    policy: RetentionPolicy,
+    save_dir: Option<&Path>,
+    record: Option<&Path>,
+) -> Retention {
+    let mut retention = Retention::new(policy);
+    if let Some(dir) = save_dir {
+        retention = retention.with_files(dir, |name| {
+            let name = name.strip_suffix(".enc").unwrap_or(name);
+            name.strip_suffix(".png")
+                .is_some_and(|stem| !stem.is_empty() && stem.bytes().all(|b| b.is_ascii_digit()))
+        });
+    }
+    if let Some(path) = record {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let path = path.to_path_buf();
+        retention = retention.with_files(dir, move |name| is_rotated(&path, name));
+    }
+    retention
+}
+
+/// Sweeps now and then every `SWEEP_INTERVAL` until `quit`, reporting low
+/// space through `events`.
+pub fn spawn(
+    retention: Retention,
+    events: SyncSender<CameraEvent>,
+    backend: CameraBackend,
+    quit: Arc<AtomicBool>,
+    debug: bool,
+) -> Result<(), CameraError> {
+    let min_free = retention.policy().min_free;
+    let sweep = move || {
+        let mut next = Instant::now();
+        while !quit.load(Ordering::SeqCst) {
+            if Instant::now() < next {
+                std::thread::sleep(Duration::from_millis(250));
+                continue;
+            }
+            next = Instant::now() + SWEEP_INTERVAL;
+            match retention.sweep() {
+                Ok(sweep) => {
+                    if debug && sweep.deleted > 0 {
+                        eprintln!(
+                            "DEBUG: deleted {} old files ({} bytes); {} bytes kept",
+                            sweep.deleted, sweep.freed_bytes, sweep.retained_bytes
+                        );
+                    }
+                    if sweep.failed > 0 {
+                        eprintln!("WARN: couldn't delete {} old files", sweep.failed);
+                    }
+                    for (path, available) in sweep.low_space {
+                        let _ = events.try_send(CameraEvent::LowDiskSpace {
+                            backend,
+                            path,
+                            available,
+                            min_free,
+                        });
+                    }
+                },
+                Err(err) => eprintln!("WARN: {err}"),
+            }
+        }
+    };
+    std::thread::Builder::new()
+        .name("retention".into())
+        .spawn(sweep)
+        .map(drop)
+        .map_err(|e| CameraError::driver("spawning the retention thread", e))
+}
@@ -0,0 +1,112 @@
+// This is free and unencumbered software released into the public domain.
+
+//! systemd integration for `--service`: readiness, status and watchdog
+//! notifications sent to `$NOTIFY_SOCKET` (see `sd_notify(3)`).
+//!
+//! Outside systemd, or on platforms without Unix sockets, nothing is sent.
+//! On Windows the same milestones go to the service control manager.
+
+#[cfg(unix)]
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::{Duration, Instant};
+
+pub struct ServiceNotifier {
+    #[cfg(unix)]
+    socket: Option<(UnixDatagram, SocketAddr)>,
+    /// Half of `$WATCHDOG_USEC`, as `sd_watchdog_enabled(3)` recommends.
+    watchdog_interval: Option<Duration>,
+    last_ping: Instant,
+    /// Whether frames arrived since the last watchdog ping.
+    progressed: bool,
+    ready: bool,
+}
+
+impl ServiceNotifier {
+    /// Connects to the notification socket systemd passed in the
+    /// environment, if any.
+    pub fn from_env() -> Self {
+        let pid_matches = std::env::var("WATCHDOG_PID")
+            .ok()
+            .and_then(|pid| pid.parse::<u32>().ok())
+            .is_none_or(|pid| pid == std::process::id());
+        let watchdog_interval = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|&usec| usec > 0 && pid_matches)
+            .map(|usec| Duration::from_micros(usec) / 2);
+        Self {
+            #[cfg(unix)]
+            socket: std::env::var_os("NOTIFY_SOCKET").and_then(|path| connect(&path)),
+            watchdog_interval,
+            last_ping: Instant::now(),
+            progressed: false,
+            ready: false,
+        }
+    }
+
+    /// Reports readiness once, when the first frame has arrived.
+    pub fn frame_arrived(&mut self, device: &str) {
+        self.progressed = true;
+        if !self.ready {
+            self.ready = true;
+            self.notify(&format!("READY=1\nSTATUS=Capturing from {device}"));
+            #[cfg(windows)]
+            crate::lifecycle::service_running();
+        }
+    }
+
+    /// Pings the watchdog if it's due and capture made progress since the
+    /// last ping (or is idle on purpose, e.g. in a privacy window), so
+    /// systemd restarts a reader whose stream stalled.
+    pub fn tick(&mut self, idle: bool) {
+        // The SCM gives up on services that stay silent while starting.
+        #[cfg(windows)]
+        if !self.ready {
+            crate::lifecycle::service_starting();
+        }
+        let Some(interval) = self.watchdog_interval else {
+            return;
+        };
+        if (self.progressed || idle) && self.last_ping.elapsed() >= interval {
+            self.notify("WATCHDOG=1");
+            self.last_ping = Instant::now();
+            self.progressed = false;
+        }
+    }
+
+    pub fn stopping(&self, status: &str) {
+        self.notify(&format!("STOPPING=1\nSTATUS={status}"));
+        #[cfg(windows)]
+        crate::lifecycle::service_stopping();
+    }
+
+    fn notify(&self, state: &str) {
+        #[cfg(unix)]
+        if let Some((socket, addr)) = &self.socket {
+            let _ = socket.send_to_addr(state.as_bytes(), addr);
+        }
+        #[cfg(not(unix))]
+        let _ = state;
+    }
+}
+
+/// Opens a datagram socket to `path`, where a leading `@` names a Linux
+/// abstract socket.
+#[cfg(unix)]
+fn connect(path: &std::ffi::OsStr) -> Option<(UnixDatagram, SocketAddr)> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let bytes = path.as_bytes();
+    let addr = match bytes.strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            SocketAddr::from_abstract_name(name).ok()?
+        },
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => return None,
+        None => SocketAddr::from_pathname(path).ok()?,
+    };
+    let socket = UnixDatagram::unbound().ok()?;
+    Some((socket, addr))
+}
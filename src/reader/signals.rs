@@ -0,0 +1,56 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Unix signals the reader acts on while capturing, as daemons are
+//! expected to: `SIGHUP` reloads the configuration, `SIGUSR1` emits the
+//! next frame (or, with `--trigger signal`, fires a trigger) and `SIGUSR2`
+//! dumps stats to stderr.
+//!
+//! Handlers only count deliveries; the capture loop takes the counts.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    Hangup,
+    User1,
+    User2,
+}
+
+/// Deliveries since the last `take`, per `Signal`.
+static RECEIVED: [AtomicU64; 3] = [const { AtomicU64::new(0) }; 3];
+
+impl Signal {
+    fn number(self) -> libc::c_int {
+        match self {
+            Signal::Hangup => libc::SIGHUP,
+            Signal::User1 => libc::SIGUSR1,
+            Signal::User2 => libc::SIGUSR2,
+        }
+    }
+
+    fn from_number(signal: libc::c_int) -> Option<Self> {
+        match signal {
+            libc::SIGHUP => Some(Signal::Hangup),
+            libc::SIGUSR1 => Some(Signal::User1),
+            libc::SIGUSR2 => Some(Signal::User2),
+            _ => None,
+        }
+    }
+
+    /// Counts this signal from now on instead of its default action.
+    pub fn install(self) {
+        let handler = on_signal as extern "C" fn(libc::c_int);
+        unsafe { libc::signal(self.number(), handler as libc::sighandler_t) };
+    }
+
+    /// How often the signal arrived since the last call.
+    pub fn take(self) -> u64 {
+        RECEIVED[self as usize].swap(0, Ordering::SeqCst)
+    }
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    if let Some(signal) = Signal::from_number(signal) {
+        RECEIVED[signal as usize].fetch_add(1, Ordering::SeqCst);
+    }
+}
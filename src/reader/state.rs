@@ -0,0 +1,70 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The `--state-dir` file carrying debounce state across restarts: one
+//! JSON object per device, rewritten after every emitted frame.
+
+use asimov_camera_module::shared::{CameraError, DebounceState};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+
+/// The state file for `device` in `dir`: the device with everything but
+/// letters, digits, `-` and `_` replaced by `_`, plus `.json`.
+pub fn state_path(dir: &Path, device: &str) -> PathBuf {
+    let name: String = device
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    dir.join(format!("{name}.json"))
+}
+
+/// Reads the state saved at `path`, or `None` if there's none yet.
+pub fn load(path: &Path) -> Result<Option<DebounceState>, CameraError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(CameraError::invalid_config(format!(
+                "reading {}: {err}",
+                path.display()
+            )));
+        },
+    };
+    let invalid = || CameraError::invalid_config(format!("{} is not a state file", path.display()));
+    let value: Value = serde_json::from_str(&text).map_err(|_| invalid())?;
+    let debounce = value.get("debounce").ok_or_else(invalid)?;
+    Ok(Some(DebounceState {
+        alg: debounce["alg"]
+            .as_str()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?,
+        hash_size: debounce["hashSize"].as_u64().ok_or_else(invalid)? as u32,
+        hash: debounce["hash"].as_str().map(str::to_string),
+        emitted: debounce["emitted"].as_u64().unwrap_or_default(),
+        timestamp_ns: debounce["timestamp"].as_u64().unwrap_or_default(),
+    }))
+}
+
+/// Writes `state` to `path` through a temporary file, so a crash mid-write
+/// leaves the previous state intact.
+pub fn save(path: &Path, state: &DebounceState) -> Result<(), CameraError> {
+    let record = json!({
+        "debounce": {
+            "alg": state.alg.as_str(),
+            "hashSize": state.hash_size,
+            "hash": state.hash,
+            "emitted": state.emitted,
+            "timestamp": state.timestamp_ns,
+        }
+    });
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, format!("{record}\n"))
+        .and_then(|()| std::fs::rename(&tmp, path))
+        .map_err(|e| CameraError::other(format!("writing {}: {e}", path.display())))
+}
@@ -0,0 +1,81 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::CameraEvent;
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Counters behind the periodic status record.
+#[derive(Debug)]
+pub struct Health {
+    started: Instant,
+    frames_emitted: AtomicU64,
+    frames_dropped: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            frames_emitted: AtomicU64::new(0),
+            frames_dropped: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        }
+    }
+}
+
+impl Health {
+    pub fn frame_emitted(&self) {
+        self.frames_emitted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn frame_dropped(&self) {
+        self.frames_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn observe(&self, event: &CameraEvent) {
+        match event {
+            CameraEvent::FrameDropped { .. } => {
+                self.frames_dropped.fetch_add(1, Ordering::Relaxed);
+            },
+            CameraEvent::Error { backend, error } => {
+                let mut last = self.last_error.lock().unwrap_or_else(|p| p.into_inner());
+                *last = Some(format!("{backend:?}: {error}"));
+            },
+            CameraEvent::Stalled { backend, after } => {
+                let mut last = self.last_error.lock().unwrap_or_else(|p| p.into_inner());
+                *last = Some(format!("{backend:?}: no frames for {after:.1?}"));
+            },
+            _ => {},
+        }
+    }
+
+    pub fn snapshot(&self, mode: &'static str) -> StatusSnapshot {
+        StatusSnapshot {
+            uptime: self.started.elapsed(),
+            frames_emitted: self.frames_emitted.load(Ordering::Relaxed),
+            frames_dropped: self.frames_dropped.load(Ordering::Relaxed),
+            mode,
+            last_error: self
+                .last_error
+                .lock()
+                .unwrap_or_else(|p| p.into_inner())
+                .clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StatusSnapshot {
+    pub uptime: Duration,
+    pub frames_emitted: u64,
+    pub frames_dropped: u64,
+    /// `capturing`, `paused` or `private`.
+    pub mode: &'static str,
+    pub last_error: Option<String>,
+}
@@ -0,0 +1,99 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Buffered stdout, shared by the workers writing records.
+//!
+//! Flushing after every record gets each one to the consumer at once, but at
+//! high frame rates the write per record dominates; `--flush` trades latency
+//! for throughput.
+
+use crate::framing::RecordWriter;
+use std::{
+    io::{self, BufWriter, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Bytes buffered before records reach stdout, when not flushing each one.
+const BUFFER_BYTES: usize = 256 << 10;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// After every record.
+    #[default]
+    EveryFrame,
+    /// Whenever records have waited this long, even if no more arrive.
+    Interval(Duration),
+    /// Only when the buffer fills, and at exit.
+    Never,
+}
+
+/// Stdout in the negotiated framing, flushed per the policy.
+pub struct RecordOutput {
+    records: RecordWriter,
+    policy: FlushPolicy,
+    buffer: Mutex<Buffer>,
+}
+
+struct Buffer {
+    out: BufWriter<io::Stdout>,
+    /// When the buffer last went from empty to holding a record.
+    pending_since: Option<Instant>,
+}
+
+impl Buffer {
+    fn flush(&mut self) -> io::Result<()> {
+        self.pending_since = None;
+        self.out.flush()
+    }
+}
+
+impl RecordOutput {
+    pub fn new(records: RecordWriter, policy: FlushPolicy) -> Self {
+        Self {
+            records,
+            policy,
+            buffer: Mutex::new(Buffer {
+                out: BufWriter::with_capacity(BUFFER_BYTES, io::stdout()),
+                pending_since: None,
+            }),
+        }
+    }
+
+    /// Writes what precedes the first record, right away.
+    pub fn header(&self) -> io::Result<()> {
+        let mut buffer = self.lock();
+        self.records.header(&mut buffer.out)?;
+        buffer.flush()
+    }
+
+    pub fn write(&self, record: &[u8]) -> io::Result<()> {
+        let mut buffer = self.lock();
+        self.records.write(&mut buffer.out, record)?;
+        let since = *buffer.pending_since.get_or_insert_with(Instant::now);
+        match self.policy {
+            FlushPolicy::EveryFrame => buffer.flush(),
+            FlushPolicy::Interval(interval) if since.elapsed() >= interval => buffer.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flushes records that have waited out the interval; the run loop
+    /// calls this so the last records before a lull aren't held back.
+    pub fn tick(&self) -> io::Result<()> {
+        let mut buffer = self.lock();
+        match (self.policy, buffer.pending_since) {
+            (FlushPolicy::Interval(interval), Some(since)) if since.elapsed() >= interval => {
+                buffer.flush()
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.lock().flush()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Buffer> {
+        self.buffer.lock().unwrap_or_else(|p| p.into_inner())
+    }
+}
@@ -0,0 +1,106 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Capturing from several cameras at once, for `--sync-device`.
+//!
+//! Each camera's frames go to one `FrameSynchronizer`, and every set of
+//! frames captured within the tolerance of each other is emitted as one
+//! `FrameBundle` record, its frames masked, cropped, scaled and overlaid
+//! as on stdout. Debouncing and saving don't apply.
+
+use asimov_camera_module::shared::{
+    CameraConfig, CameraError, CameraEvent, FrameBundle, FrameSynchronizer, open_camera,
+};
+use asimov_module::SysexitsError::{self, *};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// How the synchronized cameras are captured and when to stop.
+pub struct SyncRun {
+    /// One configuration per camera, differing only in their device.
+    pub configs: Vec<CameraConfig>,
+    pub tolerance: Duration,
+    pub duration: Option<Duration>,
+    /// Stop once this many bundles have been emitted.
+    pub max_bundles: Option<u64>,
+    pub verbose: bool,
+}
+
+impl SyncRun {
+    /// Captures until `quit`, a deadline, an error or the end of a source,
+    /// handing each bundle to `emit`, which returns whether it was written.
+    pub fn run(
+        self,
+        quit: &Arc<AtomicBool>,
+        emit: impl Fn(FrameBundle) -> bool + Send + Sync + 'static,
+    ) -> Result<SysexitsError, CameraError> {
+        let synchronizer = Arc::new(FrameSynchronizer::new(self.configs.len(), self.tolerance));
+        let emitted = Arc::new(AtomicU64::new(0));
+        let on_bundle = {
+            let (quit, emitted, max_bundles) =
+                (Arc::clone(quit), Arc::clone(&emitted), self.max_bundles);
+            Arc::new(move |bundle: FrameBundle| {
+                if quit.load(Ordering::SeqCst) || !emit(bundle) {
+                    return;
+                }
+                let n = emitted.fetch_add(1, Ordering::SeqCst) + 1;
+                if max_bundles.is_some_and(|max| n >= max) {
+                    quit.store(true, Ordering::SeqCst);
+                }
+            })
+        };
+
+        let mut cameras = Vec::with_capacity(self.configs.len());
+        for (source, config) in self.configs.into_iter().enumerate() {
+            let cam = open_camera("", config)?;
+            cam.add_sink(synchronizer.sink(source, on_bundle.clone()));
+            cameras.push(cam);
+        }
+        for cam in &mut cameras {
+            cam.start()?;
+        }
+
+        let deadline = self.duration.map(|d| Instant::now() + d);
+        let code = 'capture: loop {
+            if quit.load(Ordering::SeqCst) || deadline.is_some_and(|d| Instant::now() >= d) {
+                break EX_OK;
+            }
+            for cam in &cameras {
+                for event in cam.events().try_iter() {
+                    match event {
+                        // As in capture, a backend error is an I/O error.
+                        CameraEvent::Error { error, .. } => {
+                            eprintln!("ERROR: {error}");
+                            break 'capture EX_IOERR;
+                        },
+                        CameraEvent::EndOfStream { .. } => break 'capture EX_OK,
+                        CameraEvent::Warning { message, .. } if self.verbose => {
+                            eprintln!("WARN: {message}");
+                        },
+                        _ => {},
+                    }
+                }
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        for cam in &mut cameras {
+            if let Err(err) = cam.stop() {
+                eprintln!("WARN: stopping capture: {err}");
+            }
+        }
+        if self.verbose {
+            eprintln!(
+                "INFO: emitted {} bundles; {} frames had no match within {:?}",
+                emitted.load(Ordering::SeqCst),
+                synchronizer.unpaired(),
+                self.tolerance
+            );
+        }
+        Ok(code)
+    }
+}
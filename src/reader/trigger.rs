@@ -0,0 +1,178 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Capturing only on request, for `--trigger`.
+//!
+//! The camera stays paused until a trigger arrives: a `capture` line on
+//! stdin, `SIGUSR1`, or a `POST /capture` to a local HTTP endpoint. Each
+//! trigger lets through the next frame captured after it. With
+//! `--hardware-trigger`, each one fires the camera's software trigger
+//! instead.
+
+#[cfg(unix)]
+use crate::signals::Signal;
+use asimov_camera_module::shared::{CameraError, HttpRequest, monotonic_ns};
+use std::{
+    collections::VecDeque,
+    io::{BufRead, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// How long a trigger request may take to arrive.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The most triggers that wait for frames at once; past it, the oldest is
+/// dropped.
+const MAX_PENDING: usize = 64;
+
+/// Where triggers come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerSource {
+    /// A `capture` line on stdin.
+    Stdin,
+    /// `SIGUSR1`.
+    #[cfg(unix)]
+    Signal,
+    /// `POST /capture` on this address.
+    Http(SocketAddr),
+}
+
+/// Triggers received but not yet answered with a frame.
+#[derive(Debug, Default)]
+pub struct Triggers {
+    /// When each pending trigger arrived, on the monotonic clock.
+    pending: Mutex<VecDeque<u64>>,
+}
+
+impl Triggers {
+    /// Starts listening on every source.
+    pub fn listen(sources: &[TriggerSource], debug: bool) -> Result<Arc<Self>, CameraError> {
+        let triggers = Arc::new(Self::default());
+        for &source in sources {
+            match source {
+                TriggerSource::Stdin => {
+                    let triggers = Arc::clone(&triggers);
+                    spawn("trigger-stdin", move || triggers.read_stdin())?;
+                },
+                #[cfg(unix)]
+                TriggerSource::Signal => Signal::User1.install(),
+                TriggerSource::Http(addr) => {
+                    let listener = TcpListener::bind(addr).map_err(|e| {
+                        CameraError::invalid_config(format!("binding --trigger http:{addr}: {e}"))
+                    })?;
+                    if debug && let Ok(addr) = listener.local_addr() {
+                        eprintln!("INFO: triggers at http://{addr}/capture");
+                    }
+                    let triggers = Arc::clone(&triggers);
+                    spawn("trigger-http", move || triggers.accept(listener, debug))?;
+                },
+            }
+        }
+        Ok(triggers)
+    }
+
+    pub fn fire(&self) {
+        let mut pending = self.lock();
+        if pending.len() >= MAX_PENDING {
+            pending.pop_front();
+        }
+        pending.push_back(monotonic_ns());
+    }
+
+    /// Whether a trigger is waiting for a frame.
+    pub fn is_pending(&self) -> bool {
+        self.take_signals();
+        !self.lock().is_empty()
+    }
+
+    /// Takes every pending trigger, for a camera in trigger mode, which
+    /// answers each with a frame itself; returns how many there were.
+    pub fn drain(&self) -> usize {
+        self.take_signals();
+        let mut pending = self.lock();
+        let n = pending.len();
+        pending.clear();
+        n
+    }
+
+    fn take_signals(&self) {
+        #[cfg(unix)]
+        for _ in 0..Signal::User1.take() {
+            self.fire();
+        }
+    }
+
+    /// Answers the oldest pending trigger with a frame captured at
+    /// `monotonic_ns`, unless the frame predates it; returns whether the
+    /// frame should be emitted.
+    pub fn take(&self, monotonic_ns: u64) -> bool {
+        let mut pending = self.lock();
+        match pending.front() {
+            Some(&since) if monotonic_ns >= since => {
+                pending.pop_front();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<u64>> {
+        self.pending.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    fn read_stdin(&self) {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            match line.trim() {
+                "capture" => self.fire(),
+                "" => {},
+                other => eprintln!("WARN: ignoring unknown trigger command '{other}'"),
+            }
+        }
+    }
+
+    fn accept(&self, listener: TcpListener, debug: bool) {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if let Err(err) = self.handle(stream)
+                && debug
+            {
+                eprintln!("WARN: --trigger http client: {err}");
+            }
+        }
+    }
+
+    /// Answers one HTTP/1.1 request, then closes the connection.
+    fn handle(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        // The body, if any, is read so closing doesn't reset the connection.
+        let (request, _) = HttpRequest::read(&mut stream, 64 * 1024)?;
+        let (status, body) = match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/capture") => {
+                self.fire();
+                ("202 Accepted", "capture queued\n")
+            },
+            (_, "/capture") => ("405 Method Not Allowed", "use POST\n"),
+            _ => ("404 Not Found", "not found\n"),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        stream.flush()
+    }
+}
+
+fn spawn(name: &str, f: impl FnOnce() + Send + 'static) -> Result<(), CameraError> {
+    std::thread::Builder::new()
+        .name(name.into())
+        .spawn(f)
+        .map(drop)
+        .map_err(|e| CameraError::driver("spawning the trigger thread", e))
+}
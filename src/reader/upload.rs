@@ -0,0 +1,179 @@
+// This is free and unencumbered software released into the public domain.
+
+//! `--s3`: uploading the frames `--save-dir` keeps and the finished
+//! `--record` files, with their manifests, to S3-compatible storage.
+//!
+//! Uploads run on a thread of their own, in the order the files were
+//! written, so a slow or unreachable service holds back no frame. With
+//! `--s3-manifest`, each uploaded object is appended to an NDJSON file.
+
+use asimov_camera_module::shared::{CameraError, S3Client, S3Object};
+use core::str::FromStr;
+use serde_json::json;
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        mpsc::{self, SyncSender, TrySendError},
+    },
+    thread::JoinHandle,
+};
+
+/// Files queued for upload before new ones are skipped.
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Where `--s3` uploads to: `s3://BUCKET[/PREFIX]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct S3Url {
+    pub bucket: String,
+    /// Empty, or ending in `/`.
+    pub prefix: String,
+}
+
+impl FromStr for S3Url {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || {
+            CameraError::invalid_config(format!(
+                "invalid S3 URL '{s}' (expected s3://BUCKET[/PREFIX])"
+            ))
+        };
+        let rest = s.strip_prefix("s3://").ok_or_else(invalid)?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(invalid());
+        }
+        let prefix = prefix.trim_matches('/');
+        Ok(Self {
+            bucket: bucket.to_string(),
+            prefix: match prefix {
+                "" => String::new(),
+                prefix => format!("{prefix}/"),
+            },
+        })
+    }
+}
+
+/// The upload thread and its queue.
+pub struct Uploader {
+    queue: Mutex<Option<SyncSender<PathBuf>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Uploader {
+    /// Starts uploading with `client`, appending each object to `manifest`
+    /// if there's one.
+    pub fn spawn(
+        client: S3Client,
+        manifest: Option<&Path>,
+        debug: bool,
+    ) -> Result<Self, CameraError> {
+        let mut manifest = match manifest {
+            Some(path) => Some(
+                File::options()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| CameraError::driver("opening --s3-manifest", e))?,
+            ),
+            None => None,
+        };
+        let (tx, rx) = mpsc::sync_channel::<PathBuf>(QUEUE_CAPACITY);
+        let upload = move || {
+            for path in rx {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let key = client.key(&name);
+                match client.put_file(&key, &path, content_type(&name)) {
+                    Ok(object) => {
+                        if debug {
+                            eprintln!(
+                                "DEBUG: uploaded {} to s3://{}/{} ({} bytes, {} attempts)",
+                                path.display(),
+                                object.bucket,
+                                object.key,
+                                object.size,
+                                object.attempts
+                            );
+                        }
+                        if let Some(file) = &mut manifest
+                            && let Err(err) = append(file, &path, &object)
+                        {
+                            eprintln!("WARN: writing --s3-manifest: {err}");
+                        }
+                    },
+                    Err(err) => eprintln!("WARN: {err}"),
+                }
+            }
+        };
+        let thread = std::thread::Builder::new()
+            .name("upload".into())
+            .spawn(upload)
+            .map_err(|e| CameraError::driver("spawning the upload thread", e))?;
+        Ok(Self {
+            queue: Mutex::new(Some(tx)),
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    /// Queues the file at `path` for upload.
+    pub fn upload(&self, path: &Path) {
+        let queue = self.queue.lock().unwrap_or_else(|p| p.into_inner());
+        let Some(queue) = queue.as_ref() else {
+            return;
+        };
+        match queue.try_send(path.to_path_buf()) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => {},
+            Err(TrySendError::Full(path)) => {
+                eprintln!(
+                    "WARN: --s3 is {QUEUE_CAPACITY} files behind, not uploading {}",
+                    path.display()
+                );
+            },
+        }
+    }
+
+    /// Waits for the files already queued to be uploaded.
+    pub fn finish(&self) {
+        drop(self.queue.lock().unwrap_or_else(|p| p.into_inner()).take());
+        let thread = self.thread.lock().unwrap_or_else(|p| p.into_inner()).take();
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The type of the object uploaded from the file `name`; encrypted ones
+/// are opaque.
+fn content_type(name: &str) -> &'static str {
+    let extension = Path::new(name).extension().unwrap_or_default();
+    match extension.to_string_lossy().to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        "json" => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Appends the line of `object`, uploaded from `path`, to the manifest.
+fn append(file: &mut File, path: &Path, object: &S3Object) -> std::io::Result<()> {
+    let line = json!({
+        "file": path.display().to_string(),
+        "bucket": object.bucket,
+        "key": object.key,
+        "url": format!("s3://{}/{}", object.bucket, object.key),
+        "size": object.size,
+        "etag": object.etag,
+        "timestamp": crate::unix_time_ns(),
+    });
+    let mut line = line.to_string();
+    line.push('\n');
+    file.write_all(line.as_bytes())
+}
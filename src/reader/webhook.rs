@@ -0,0 +1,221 @@
+// This is free and unencumbered software released into the public domain.
+
+//! `--webhook`: POSTing motion and camera events, with a thumbnail of the
+//! frame that moved, to webhook URLs.
+//!
+//! Deliveries run on a thread of their own, so a slow or unreachable server
+//! holds back no frame; with too many waiting, new ones are dropped. Motion
+//! is posted at most every `--webhook-interval`.
+
+use asimov_camera_module::shared::{CameraError, CameraEvent, Frame, Webhook};
+use base64::Engine;
+use serde_json::{Value, json};
+use std::{
+    sync::{
+        Mutex,
+        mpsc::{self, SyncSender, TrySendError},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Payloads waiting for delivery before new ones are dropped.
+const QUEUE_CAPACITY: usize = 64;
+
+const THUMBNAIL_QUALITY: u8 = 75;
+
+/// What `--webhook-on` takes: `motion`, and the events of `encode_event`.
+pub const WEBHOOK_EVENTS: &[&str] = &[
+    "motion",
+    "started",
+    "stopped",
+    "warning",
+    "pipelinechanged",
+    "privacychanged",
+    "pausechanged",
+    "devicechanged",
+    "backpressure",
+    "lowdiskspace",
+    "stalled",
+    "formatchanged",
+    "downgraded",
+    "endofstream",
+    "observed",
+    "error",
+];
+
+/// `--webhook-on` without one: what someone away from the camera wants to
+/// hear about.
+pub const DEFAULT_WEBHOOK_EVENTS: &str = "motion,error,stalled,lowdiskspace,endofstream";
+
+/// An event name for `--webhook-on`, in any case, with or without `-`
+/// and `_`.
+pub fn parse_event(s: &str) -> Result<String, CameraError> {
+    let name: String = s
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, '-' | '_'))
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match WEBHOOK_EVENTS.contains(&name.as_str()) {
+        true => Ok(name),
+        false => Err(CameraError::invalid_config(format!(
+            "unknown webhook event '{s}' (expected motion, or an event such as error or stalled)"
+        ))),
+    }
+}
+
+/// The webhooks and the thread delivering to them.
+pub struct WebhookSink {
+    source: String,
+    events: Vec<String>,
+    interval: Duration,
+    thumbnail: Option<u32>,
+    last_motion: Mutex<Option<Instant>>,
+    queue: Mutex<Option<SyncSender<Value>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    debug: bool,
+}
+
+impl WebhookSink {
+    /// Starts delivering `events` of `source` to `hooks`, motion at most
+    /// every `interval`, with a thumbnail `thumbnail` pixels wide if asked.
+    pub fn spawn(
+        hooks: Vec<Webhook>,
+        source: &str,
+        events: Vec<String>,
+        interval: Duration,
+        thumbnail: Option<u32>,
+        debug: bool,
+    ) -> Result<Self, CameraError> {
+        let (tx, rx) = mpsc::sync_channel::<Value>(QUEUE_CAPACITY);
+        let deliver = move || {
+            for payload in rx {
+                for hook in &hooks {
+                    match hook.post(&payload) {
+                        Ok(attempts) if debug => eprintln!(
+                            "DEBUG: posted {} to {} ({attempts} attempts)",
+                            payload["event"],
+                            hook.url()
+                        ),
+                        Ok(_) => {},
+                        Err(err) => eprintln!("WARN: {err}"),
+                    }
+                }
+            }
+        };
+        let thread = std::thread::Builder::new()
+            .name("webhook".into())
+            .spawn(deliver)
+            .map_err(|e| CameraError::driver("spawning the webhook thread", e))?;
+        Ok(Self {
+            source: source.to_string(),
+            events,
+            interval,
+            thumbnail,
+            last_motion: Mutex::new(None),
+            queue: Mutex::new(Some(tx)),
+            thread: Mutex::new(Some(thread)),
+            debug,
+        })
+    }
+
+    fn wants(&self, event: &str) -> bool {
+        self.events.iter().any(|e| e.eq_ignore_ascii_case(event))
+    }
+
+    /// Posts motion in `frame` at `timestamp_ns`, `score` of it changed,
+    /// with its `Observation` record, unless motion was posted less than
+    /// the interval ago.
+    pub fn motion(&self, frame: &Frame, timestamp_ns: u64, score: f32, record: &[u8]) {
+        if !self.wants("motion") {
+            return;
+        }
+        {
+            let mut last = self.last_motion.lock().unwrap_or_else(|p| p.into_inner());
+            if last.is_some_and(|t| t.elapsed() < self.interval) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+        let mut payload = json!({
+            "kind": "motion",
+            "event": "Motion",
+            "source": self.source,
+            "timestamp": timestamp_ns,
+            "message": format!("motion on {} ({:.1}%)", self.source, score * 100.0),
+            "score": (f64::from(score) * 1e4).round() / 1e4,
+            "record": serde_json::from_slice::<Value>(record).unwrap_or_default(),
+        });
+        if let Some(width) = self.thumbnail {
+            match thumbnail(frame, width) {
+                Ok(data) => payload["thumbnail"] = data.into(),
+                Err(err) if self.debug => eprintln!("WARN: webhook thumbnail: {err}"),
+                Err(_) => {},
+            }
+        }
+        self.send(payload);
+    }
+
+    /// Posts a camera event, as `encode_event` encoded it into `record`.
+    pub fn event(&self, event: &CameraEvent, record: &[u8]) {
+        if matches!(event, CameraEvent::FrameDropped { .. }) {
+            return;
+        }
+        let record: Value = serde_json::from_slice(record).unwrap_or_default();
+        let name = record["event"].as_str().unwrap_or_default().to_string();
+        if !self.wants(&name) {
+            return;
+        }
+        let message = match record["message"].as_str() {
+            Some(message) => format!("{name} on {}: {message}", self.source),
+            None => format!("{name} on {}", self.source),
+        };
+        self.send(json!({
+            "kind": "event",
+            "event": name,
+            "source": self.source,
+            "timestamp": record["timestamp"],
+            "message": message,
+            "record": record,
+        }));
+    }
+
+    fn send(&self, payload: Value) {
+        let queue = self.queue.lock().unwrap_or_else(|p| p.into_inner());
+        if let Some(queue) = queue.as_ref()
+            && let Err(TrySendError::Full(payload)) = queue.try_send(payload)
+        {
+            eprintln!(
+                "WARN: --webhook is {QUEUE_CAPACITY} deliveries behind, dropping {}",
+                payload["event"]
+            );
+        }
+    }
+
+    /// Waits for the payloads already queued to be delivered.
+    pub fn finish(&self) {
+        drop(self.queue.lock().unwrap_or_else(|p| p.into_inner()).take());
+        let thread = self.thread.lock().unwrap_or_else(|p| p.into_inner()).take();
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// `frame` as a JPEG data URL, shrunk to `width` pixels wide if it's wider.
+fn thumbnail(frame: &Frame, width: u32) -> Result<String, CameraError> {
+    let jpeg = match frame.width > width {
+        true => {
+            let height = (frame.height as u64 * width as u64 / frame.width as u64).max(1);
+            frame
+                .scale(width, height as u32)?
+                .to_jpeg(THUMBNAIL_QUALITY)?
+        },
+        false => frame.to_jpeg(THUMBNAIL_QUALITY)?,
+    };
+    Ok(format!(
+        "data:image/jpeg;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(jpeg)
+    ))
+}
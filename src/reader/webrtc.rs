@@ -0,0 +1,491 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Streaming emitted frames to browsers over WebRTC.
+//!
+//! A small HTTP endpoint serves a viewer page at `/` and answers SDP offers
+//! POSTed to `/offer`, without trickle ICE. While at least one viewer is
+//! connected, frames are piped into an ffmpeg VP8 encoder whose IVF output
+//! feeds a single track shared by every peer connection.
+
+use asimov_camera_module::shared::{CameraError, Frame, HttpRequest};
+use std::{
+    collections::HashMap,
+    env,
+    io::{BufReader, Write},
+    net::SocketAddr,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use webrtc::{
+    api::{
+        API, APIBuilder,
+        interceptor_registry::register_default_interceptors,
+        media_engine::{MIME_TYPE_VP8, MediaEngine},
+    },
+    interceptor::registry::Registry,
+    media::{Sample, io::ivf_reader::IVFReader},
+    peer_connection::{
+        RTCPeerConnection, configuration::RTCConfiguration,
+        peer_connection_state::RTCPeerConnectionState,
+        sdp::session_description::RTCSessionDescription,
+    },
+    rtp_transceiver::rtp_codec::RTCRtpCodecCapability,
+    track::track_local::{TrackLocal, track_local_static_sample::TrackLocalStaticSample},
+};
+
+/// Concurrent viewers; further offers get `503 Service Unavailable`.
+const MAX_VIEWERS: usize = 4;
+
+/// Largest SDP offer accepted.
+const MAX_OFFER_BYTES: usize = 64 << 10;
+
+/// Target encoder bitrate, in kbit/s.
+const BITRATE_KBPS: u32 = 2000;
+
+/// Seconds between keyframes, bounding how long a new viewer waits when
+/// joining a running stream.
+const KEYFRAME_INTERVAL_SECS: f64 = 2.0;
+
+/// How long shutdown waits for peer connections to close.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+const VIEWER_PAGE: &str = include_str!("webrtc.html");
+
+/// The `--webrtc` endpoint and the encoder behind it.
+pub struct WebrtcServer {
+    runtime: tokio::runtime::Runtime,
+    shared: Arc<Shared>,
+    encoder: Mutex<Option<Encoder>>,
+    local_addr: SocketAddr,
+    fps: f64,
+    debug: bool,
+}
+
+struct Shared {
+    api: API,
+    track: Arc<TrackLocalStaticSample>,
+    sessions: Mutex<HashMap<u64, Arc<RTCPeerConnection>>>,
+    next_session: AtomicU64,
+    viewers: AtomicUsize,
+    debug: bool,
+}
+
+impl WebrtcServer {
+    /// Listens for viewers on `addr`; frames arrive at up to `fps`.
+    pub fn bind(addr: SocketAddr, fps: f64, debug: bool) -> Result<Self, CameraError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("webrtc")
+            .enable_all()
+            .build()
+            .map_err(|e| CameraError::driver("starting the WebRTC runtime", e))?;
+
+        let mut media = MediaEngine::default();
+        media
+            .register_default_codecs()
+            .map_err(|e| CameraError::driver("registering WebRTC codecs", e))?;
+        let interceptors = register_default_interceptors(Registry::new(), &mut media)
+            .map_err(|e| CameraError::driver("registering WebRTC interceptors", e))?;
+        let api = APIBuilder::new()
+            .with_media_engine(media)
+            .with_interceptor_registry(interceptors)
+            .build();
+        let track = Arc::new(TrackLocalStaticSample::new(
+            RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_VP8.to_owned(),
+                clock_rate: 90000,
+                ..Default::default()
+            },
+            "video".to_owned(),
+            "asimov-camera".to_owned(),
+        ));
+        let shared = Arc::new(Shared {
+            api,
+            track,
+            sessions: Mutex::new(HashMap::new()),
+            next_session: AtomicU64::new(0),
+            viewers: AtomicUsize::new(0),
+            debug,
+        });
+
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind(addr))
+            .map_err(|e| CameraError::invalid_config(format!("binding --webrtc {addr}: {e}")))?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(|e| CameraError::driver("binding --webrtc", e))?;
+        runtime.spawn(serve(listener, Arc::clone(&shared)));
+
+        Ok(Self {
+            runtime,
+            shared,
+            encoder: Mutex::new(None),
+            local_addr,
+            fps,
+            debug,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Streams `frame` to the connected viewers. The encoder only runs
+    /// while someone is watching, and restarts when the frame size changes.
+    pub fn send(&self, frame: &Frame) {
+        let mut encoder = self.encoder.lock().unwrap_or_else(|p| p.into_inner());
+        if self.shared.viewers.load(Ordering::SeqCst) == 0 {
+            encoder.take();
+            return;
+        }
+        let rgb = match frame.to_rgb8() {
+            Ok(rgb) => rgb,
+            Err(err) => {
+                if self.debug {
+                    eprintln!("WARN: WebRTC: {err}");
+                }
+                return;
+            },
+        };
+        if encoder
+            .as_ref()
+            .is_some_and(|e| e.size != (rgb.width, rgb.height))
+        {
+            encoder.take();
+        }
+        if encoder.is_none() {
+            match Encoder::spawn(
+                (rgb.width, rgb.height),
+                self.fps,
+                Arc::clone(&self.shared.track),
+                self.runtime.handle().clone(),
+                self.debug,
+            ) {
+                Ok(spawned) => *encoder = Some(spawned),
+                Err(err) => {
+                    eprintln!("WARN: WebRTC: {err}");
+                    return;
+                },
+            }
+        }
+        if let Some(running) = encoder.as_mut()
+            && let Err(err) = running.stdin.write_all(&rgb.data)
+        {
+            if self.debug {
+                eprintln!("WARN: WebRTC encoder: {err}");
+            }
+            encoder.take();
+        }
+    }
+}
+
+impl Drop for WebrtcServer {
+    fn drop(&mut self) {
+        self.encoder
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .take();
+        let sessions: Vec<_> = self
+            .shared
+            .sessions
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .drain()
+            .map(|(_, pc)| pc)
+            .collect();
+        self.runtime.block_on(async {
+            let _ = tokio::time::timeout(CLOSE_TIMEOUT, close_all(sessions)).await;
+        });
+    }
+}
+
+async fn close_all(sessions: Vec<Arc<RTCPeerConnection>>) {
+    for pc in sessions {
+        let _ = pc.close().await;
+    }
+}
+
+/// An ffmpeg process turning RGB8 frames on stdin into VP8 samples.
+struct Encoder {
+    child: Child,
+    stdin: ChildStdin,
+    size: (u32, u32),
+    output: Option<JoinHandle<()>>,
+}
+
+impl Encoder {
+    fn spawn(
+        size: (u32, u32),
+        fps: f64,
+        track: Arc<TrackLocalStaticSample>,
+        runtime: tokio::runtime::Handle,
+        debug: bool,
+    ) -> Result<Self, CameraError> {
+        let keyframe_interval = (fps * KEYFRAME_INTERVAL_SECS).ceil().max(1.0);
+        let ffargs: Vec<String> = vec![
+            "-hide_banner".into(),
+            "-nostats".into(),
+            "-loglevel".into(),
+            "error".into(),
+            "-f".into(),
+            "rawvideo".into(),
+            "-pix_fmt".into(),
+            "rgb24".into(),
+            "-s".into(),
+            format!("{}x{}", size.0, size.1),
+            "-framerate".into(),
+            fps.to_string(),
+            "-i".into(),
+            "pipe:0".into(),
+            // 4:2:0 needs even dimensions.
+            "-vf".into(),
+            "pad=ceil(iw/2)*2:ceil(ih/2)*2".into(),
+            "-pix_fmt".into(),
+            "yuv420p".into(),
+            "-c:v".into(),
+            "libvpx".into(),
+            "-deadline".into(),
+            "realtime".into(),
+            "-cpu-used".into(),
+            "8".into(),
+            "-b:v".into(),
+            format!("{BITRATE_KBPS}k"),
+            "-g".into(),
+            keyframe_interval.to_string(),
+            "-error-resilient".into(),
+            "1".into(),
+            "-auto-alt-ref".into(),
+            "0".into(),
+            "-f".into(),
+            "ivf".into(),
+            "pipe:1".into(),
+        ];
+        let stderr = if debug || env::var_os("ASIMOV_CAMERA_FFMPEG_STDERR").is_some() {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        };
+        let mut child = Command::new("ffmpeg")
+            .args(&ffargs)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(stderr)
+            .spawn()
+            .map_err(|e| CameraError::driver("spawning ffmpeg for WebRTC", e))?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(CameraError::other("ffmpeg for WebRTC has no pipes"));
+        };
+
+        let output = std::thread::Builder::new()
+            .name("webrtc-encoder".into())
+            .spawn(move || {
+                let Ok((mut ivf, _)) = IVFReader::new(BufReader::new(stdout)) else {
+                    return;
+                };
+                // Samples are timed by arrival, so debounced or throttled
+                // frames don't speed up playback.
+                let mut last = Instant::now();
+                while let Ok((data, _)) = ivf.parse_next_frame() {
+                    let now = Instant::now();
+                    let sample = Sample {
+                        data: data.freeze(),
+                        timestamp: SystemTime::now(),
+                        duration: now - last,
+                        ..Default::default()
+                    };
+                    last = now;
+                    if let Err(err) = runtime.block_on(track.write_sample(&sample))
+                        && debug
+                    {
+                        eprintln!("WARN: WebRTC: {err}");
+                    }
+                }
+            })
+            .map_err(|e| CameraError::driver("spawning WebRTC encoder thread", e))?;
+
+        Ok(Self {
+            child,
+            stdin,
+            size,
+            output: Some(output),
+        })
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if let Some(output) = self.output.take() {
+            let _ = output.join();
+        }
+    }
+}
+
+async fn serve(listener: tokio::net::TcpListener, shared: Arc<Shared>) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        let shared = Arc::clone(&shared);
+        tokio::spawn(async move {
+            if let Err(err) = handle(stream, &shared).await
+                && shared.debug
+            {
+                eprintln!("WARN: WebRTC signaling: {err}");
+            }
+        });
+    }
+}
+
+/// Answers one HTTP/1.1 request, then closes the connection.
+async fn handle(mut stream: tokio::net::TcpStream, shared: &Arc<Shared>) -> std::io::Result<()> {
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+    let (request, head_len) = loop {
+        if let Some(parsed) = HttpRequest::parse(&data)? {
+            break parsed;
+        }
+        match stream.read(&mut buf).await? {
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            n => data.extend_from_slice(&buf[..n]),
+        }
+    };
+
+    let content_length = request.content_length;
+    let (status, content_type, body) = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => (
+            "200 OK",
+            "text/html; charset=utf-8",
+            VIEWER_PAGE.to_string(),
+        ),
+        ("POST", "/offer") if content_length > MAX_OFFER_BYTES => (
+            "413 Payload Too Large",
+            "text/plain",
+            "offer too large\n".to_string(),
+        ),
+        ("POST", "/offer") => {
+            let mut offer = data.split_off(head_len);
+            let read = offer.len().min(content_length);
+            offer.resize(content_length, 0);
+            stream.read_exact(&mut offer[read..]).await?;
+            match answer(shared, String::from_utf8_lossy(&offer).into_owned()).await {
+                Ok(Some(sdp)) => ("201 Created", "application/sdp", sdp),
+                Ok(None) => (
+                    "503 Service Unavailable",
+                    "text/plain",
+                    format!("at most {MAX_VIEWERS} viewers\n"),
+                ),
+                Err(err) => ("400 Bad Request", "text/plain", format!("{err}\n")),
+            }
+        },
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Creates a peer connection for `offer` and returns the answer with all
+/// ICE candidates, or `None` if the viewer limit is reached.
+async fn answer(shared: &Arc<Shared>, offer: String) -> Result<Option<String>, webrtc::Error> {
+    if shared
+        .sessions
+        .lock()
+        .unwrap_or_else(|p| p.into_inner())
+        .len()
+        >= MAX_VIEWERS
+    {
+        return Ok(None);
+    }
+    let pc = Arc::new(
+        shared
+            .api
+            .new_peer_connection(RTCConfiguration::default())
+            .await?,
+    );
+    let sender = pc
+        .add_track(Arc::clone(&shared.track) as Arc<dyn TrackLocal + Send + Sync>)
+        .await?;
+    // Reading RTCP lets the interceptors handle NACKs and receiver reports.
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 1500];
+        while sender.read(&mut buf).await.is_ok() {}
+    });
+
+    let id = shared.next_session.fetch_add(1, Ordering::SeqCst);
+    let watching = Arc::new(AtomicBool::new(false));
+    let weak: Weak<Shared> = Arc::downgrade(shared);
+    pc.on_peer_connection_state_change(Box::new(move |state| {
+        let (weak, watching) = (weak.clone(), Arc::clone(&watching));
+        Box::pin(async move {
+            let Some(shared) = weak.upgrade() else {
+                return;
+            };
+            match state {
+                RTCPeerConnectionState::Connected if !watching.swap(true, Ordering::SeqCst) => {
+                    shared.viewers.fetch_add(1, Ordering::SeqCst);
+                },
+                RTCPeerConnectionState::Disconnected
+                | RTCPeerConnectionState::Failed
+                | RTCPeerConnectionState::Closed => {
+                    if watching.swap(false, Ordering::SeqCst) {
+                        shared.viewers.fetch_sub(1, Ordering::SeqCst);
+                    }
+                    let pc = shared
+                        .sessions
+                        .lock()
+                        .unwrap_or_else(|p| p.into_inner())
+                        .remove(&id);
+                    if let Some(pc) = pc {
+                        tokio::spawn(async move {
+                            let _ = pc.close().await;
+                        });
+                    }
+                },
+                _ => {},
+            }
+        })
+    }));
+
+    shared
+        .sessions
+        .lock()
+        .unwrap_or_else(|p| p.into_inner())
+        .insert(id, Arc::clone(&pc));
+    let negotiated = async {
+        pc.set_remote_description(RTCSessionDescription::offer(offer)?)
+            .await?;
+        let answer = pc.create_answer(None).await?;
+        let mut gathered = pc.gathering_complete_promise().await;
+        pc.set_local_description(answer).await?;
+        let _ = gathered.recv().await;
+        Ok(Some(
+            pc.local_description()
+                .await
+                .map(|d| d.sdp)
+                .unwrap_or_default(),
+        ))
+    }
+    .await;
+    if negotiated.is_err() {
+        shared
+            .sessions
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .remove(&id);
+        let _ = pc.close().await;
+    }
+    negotiated
+}
@@ -0,0 +1,151 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::{ThreadPriority, ThreadScheduling};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Bounded hand-off queue between the camera's sink callback and the
+/// workers. When full, the oldest entry is dropped so the capture side
+/// never waits on encoding or a slow stdout consumer.
+#[derive(Debug)]
+pub struct WorkQueue<T> {
+    state: Mutex<QueueState<T>>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct QueueState<T> {
+    items: VecDeque<T>,
+    closed: bool,
+}
+
+impl<T> WorkQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            state: Mutex::new(QueueState {
+                items: VecDeque::with_capacity(capacity),
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            capacity,
+        }
+    }
+
+    /// Enqueues `item`, returning how many older items were evicted.
+    pub fn push(&self, item: T) -> usize {
+        let mut state = self.state.lock().unwrap_or_else(|p| p.into_inner());
+        if state.closed {
+            return 1;
+        }
+        let mut evicted = 0;
+        while state.items.len() >= self.capacity {
+            state.items.pop_front();
+            evicted += 1;
+        }
+        state.items.push_back(item);
+        self.ready.notify_one();
+        evicted
+    }
+
+    /// Blocks until an item is available; `None` once the queue is closed
+    /// and drained.
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap_or_else(|p| p.into_inner());
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.ready.wait(state).unwrap_or_else(|p| p.into_inner());
+        }
+    }
+
+    pub fn close(&self) {
+        self.state.lock().unwrap_or_else(|p| p.into_inner()).closed = true;
+        self.ready.notify_all();
+    }
+}
+
+/// Fixed set of threads draining a [`WorkQueue`].
+#[derive(Debug)]
+pub struct WorkerPool<T> {
+    queue: Arc<WorkQueue<T>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /// Starts `workers` threads at `priority`, leaving them at the default
+    /// where the OS refuses (the dispatch thread's warning covers that).
+    pub fn spawn(
+        workers: usize,
+        capacity: usize,
+        priority: ThreadPriority,
+        handler: impl Fn(T) + Send + Sync + 'static,
+    ) -> std::io::Result<Self> {
+        let queue = Arc::new(WorkQueue::new(capacity));
+        let handler = Arc::new(handler);
+        let mut pool = Self {
+            queue,
+            threads: Vec::with_capacity(workers),
+        };
+        for i in 0..workers.max(1) {
+            let queue = Arc::clone(&pool.queue);
+            let handler = Arc::clone(&handler);
+            let thread = std::thread::Builder::new()
+                .name(format!("reader-worker-{i}"))
+                .spawn(move || {
+                    if priority != ThreadPriority::Normal {
+                        let scheduling = ThreadScheduling {
+                            priority,
+                            core: None,
+                        };
+                        let _ = scheduling.apply_to_current_thread();
+                    }
+                    while let Some(item) = queue.pop() {
+                        handler(item);
+                    }
+                });
+            match thread {
+                Ok(t) => pool.threads.push(t),
+                Err(err) => {
+                    pool.shutdown(Duration::ZERO);
+                    return Err(err);
+                },
+            }
+        }
+        Ok(pool)
+    }
+
+    pub fn queue(&self) -> &Arc<WorkQueue<T>> {
+        &self.queue
+    }
+
+    /// Closes the queue and waits up to `timeout` for the workers to finish
+    /// what's queued; workers stuck on a blocked stdout are left behind.
+    pub fn shutdown(&mut self, timeout: Duration) {
+        self.queue.close();
+        let deadline = Instant::now() + timeout;
+        for thread in self.threads.drain(..) {
+            while !thread.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            if thread.is_finished() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl<T> Drop for WorkerPool<T> {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
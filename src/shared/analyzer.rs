@@ -0,0 +1,121 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{Frame, Motion, MotionDetector, Rect, process::luma};
+
+/// One result reported by a [`FrameAnalyzer`], e.g. a detected object or a
+/// scalar measurement of the frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    pub label: String,
+    pub value: Option<f64>,
+    pub confidence: Option<f32>,
+    pub bbox: Option<Rect>,
+}
+
+impl Observation {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            value: None,
+            confidence: None,
+            bbox: None,
+        }
+    }
+
+    pub fn with_value(mut self, value: f64) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    pub fn with_confidence(mut self, confidence: f32) -> Self {
+        self.confidence = Some(confidence);
+        self
+    }
+
+    pub fn with_bbox(mut self, bbox: Rect) -> Self {
+        self.bbox = Some(bbox);
+        self
+    }
+}
+
+/// In-process extension point for frame analysis (detectors, classifiers,
+/// metrics). Analyzers registered with `Camera::add_analyzer` run on the
+/// dispatch thread and report through `CameraEvent::Observed`.
+pub trait FrameAnalyzer: Send {
+    fn name(&self) -> &str;
+    fn analyze(&mut self, frame: &Frame) -> Vec<Observation>;
+}
+
+impl From<Motion> for Observation {
+    fn from(motion: Motion) -> Self {
+        Observation::new("motion")
+            .with_value(motion.score as f64)
+            .with_bbox(motion.bbox)
+    }
+}
+
+impl FrameAnalyzer for MotionDetector {
+    fn name(&self) -> &str {
+        "motion"
+    }
+
+    fn analyze(&mut self, frame: &Frame) -> Vec<Observation> {
+        self.process(frame)
+            .map(Observation::from)
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Built-in analyzer reporting `brightness` (mean luma, 0-1) and
+/// `sharpness` (variance of the luma Laplacian) for every frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QualityAnalyzer;
+
+impl FrameAnalyzer for QualityAnalyzer {
+    fn name(&self) -> &str {
+        "quality"
+    }
+
+    fn analyze(&mut self, frame: &Frame) -> Vec<Observation> {
+        if !frame.validate() {
+            return Vec::new();
+        }
+        let (brightness, sharpness) = quality(frame);
+        vec![
+            Observation::new("brightness").with_value(brightness),
+            Observation::new("sharpness").with_value(sharpness),
+        ]
+    }
+}
+
+fn quality(frame: &Frame) -> (f64, f64) {
+    let bpp = frame.pixel_format.bytes_per_pixel() as usize;
+    let stride = frame.stride as usize;
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let at =
+        |x: usize, y: usize| luma(&frame.data[y * stride + x * bpp..], frame.pixel_format) as f64;
+
+    let mut sum = 0.0;
+    for y in 0..h {
+        for x in 0..w {
+            sum += at(x, y);
+        }
+    }
+    let brightness = sum / (w * h) as f64 / 255.0;
+
+    if w < 3 || h < 3 {
+        return (brightness, 0.0);
+    }
+    let (mut lap_sum, mut lap_sq, mut n) = (0.0, 0.0, 0.0);
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let lap = at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y);
+            lap_sum += lap;
+            lap_sq += lap * lap;
+            n += 1.0;
+        }
+    }
+    let mean = lap_sum / n;
+    (brightness, lap_sq / n - mean * mean)
+}
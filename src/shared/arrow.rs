@@ -0,0 +1,53 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{CameraError, Frame};
+use arrow_array::{Array, UInt8Array};
+use arrow_buffer::{Buffer, ScalarBuffer};
+use arrow_data::ffi::FFI_ArrowArray;
+use arrow_schema::{DataType, ffi::FFI_ArrowSchema};
+
+/// A frame viewed as a `height x width x channels` tensor of `u8` (or
+/// little-endian `u16` for 16-bit formats and P010 luma, `u32` for
+/// RGBA1010102), backed by the frame's own memory.
+#[derive(Clone, Debug)]
+pub struct FrameTensor {
+    pub buffer: Buffer,
+    /// `[height, width, channels]`.
+    pub shape: [usize; 3],
+    /// Byte strides for each dimension of `shape`.
+    pub strides: [usize; 3],
+}
+
+impl Frame {
+    /// Wraps the frame bytes as an Arrow buffer. The buffer holds a reference
+    /// to the frame memory, so no pixel data is copied.
+    pub fn to_arrow_buffer(&self) -> Buffer {
+        Buffer::from(self.data.clone())
+    }
+
+    pub fn to_arrow_tensor(&self) -> FrameTensor {
+        let channels = self.pixel_format.channels() as usize;
+        let sample = self.pixel_format.bytes_per_sample() as usize;
+        FrameTensor {
+            buffer: self.to_arrow_buffer(),
+            shape: [self.height as usize, self.width as usize, channels],
+            strides: [self.stride as usize, channels * sample, sample],
+        }
+    }
+
+    /// Returns the frame bytes (including any row padding) as a `UInt8Array`.
+    pub fn to_arrow_array(&self) -> UInt8Array {
+        let buffer = self.to_arrow_buffer();
+        let len = buffer.len();
+        UInt8Array::new(ScalarBuffer::new(buffer, 0, len), None)
+    }
+
+    /// Exports the frame through the Arrow C data interface, e.g. for
+    /// `pyarrow.Array._import_from_c(array_ptr, schema_ptr)`.
+    pub fn to_arrow_ffi(&self) -> Result<(FFI_ArrowArray, FFI_ArrowSchema), CameraError> {
+        let array = self.to_arrow_array().to_data();
+        let schema = FFI_ArrowSchema::try_from(&DataType::UInt8)
+            .map_err(|e| CameraError::driver("exporting arrow schema", e))?;
+        Ok((FFI_ArrowArray::new(&array), schema))
+    }
+}
@@ -0,0 +1,81 @@
+// This is free and unencumbered software released into the public domain.
+
+use bytes::Bytes;
+use core::time::Duration;
+
+/// Microphone capture requested alongside video with
+/// `CameraConfig::with_audio`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AudioConfig {
+    /// Backend-specific input: `pulse:NAME` or `alsa:hw:1` on Linux,
+    /// `avf:N` on macOS, `dshow:audio=NAME` on Windows. Empty picks the
+    /// system default.
+    pub device: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Duration of each [`AudioFrame`].
+    pub chunk: Duration,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            device: String::new(),
+            sample_rate: 48_000,
+            channels: 2,
+            chunk: Duration::from_millis(20),
+        }
+    }
+}
+
+impl AudioConfig {
+    pub fn new(device: impl Into<String>) -> Self {
+        Self {
+            device: device.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_sample_rate(mut self, rate: u32) -> Self {
+        self.sample_rate = rate.clamp(8_000, 192_000);
+        self
+    }
+
+    pub fn with_channels(mut self, channels: u16) -> Self {
+        self.channels = channels.clamp(1, 8);
+        self
+    }
+
+    pub fn with_chunk(mut self, chunk: Duration) -> Self {
+        self.chunk = chunk.max(Duration::from_millis(1));
+        self
+    }
+
+    /// Bytes in one chunk of interleaved 16-bit samples.
+    pub fn chunk_bytes(&self) -> usize {
+        let frames = (self.sample_rate as f64 * self.chunk.as_secs_f64()).round() as usize;
+        frames.max(1) * self.channels as usize * 2
+    }
+}
+
+/// A chunk of interleaved signed 16-bit little-endian PCM.
+#[derive(Clone, Debug)]
+pub struct AudioFrame {
+    pub data: Bytes,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Capture time of the first sample, on the same clock as
+    /// `Frame::timestamp_ns`, so the two streams can be muxed.
+    pub timestamp_ns: u64,
+}
+
+impl AudioFrame {
+    /// Samples per channel.
+    pub fn frames(&self) -> usize {
+        self.data.len() / (self.channels.max(1) as usize * 2)
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate.max(1) as f64)
+    }
+}
@@ -0,0 +1,66 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Locking the camera's automatic exposure and white balance once they've
+//! converged on the scene, so a timelapse or a photometric pipeline sees
+//! no flicker between frames as the camera keeps adjusting.
+
+use core::{fmt, time::Duration};
+
+/// An automatic control the camera adjusts on its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AutoControl {
+    Exposure,
+    WhiteBalance,
+}
+
+impl AutoControl {
+    pub const ALL: [Self; 2] = [Self::Exposure, Self::WhiteBalance];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Exposure => "exposure",
+            Self::WhiteBalance => "white balance",
+        }
+    }
+}
+
+impl fmt::Display for AutoControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// When `Camera::check_auto_lock` locks each automatic control: this long
+/// after capture (re)starts delivering frames. `None` leaves it automatic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AutoLock {
+    pub exposure: Option<Duration>,
+    pub white_balance: Option<Duration>,
+}
+
+impl AutoLock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_exposure(mut self, after: Duration) -> Self {
+        self.exposure = Some(after);
+        self
+    }
+
+    pub fn with_white_balance(mut self, after: Duration) -> Self {
+        self.white_balance = Some(after);
+        self
+    }
+
+    pub fn after(&self, control: AutoControl) -> Option<Duration> {
+        match control {
+            AutoControl::Exposure => self.exposure,
+            AutoControl::WhiteBalance => self.white_balance,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exposure.is_none() && self.white_balance.is_none()
+    }
+}
@@ -0,0 +1,139 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Pairing the frames of several cameras by capture time, for stereo
+//! vision and multi-angle capture: each [`FrameBundle`] holds one frame
+//! per camera, all captured within the synchronizer's tolerance.
+
+use crate::shared::{Frame, FrameSink};
+use core::time::Duration;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// Frames a source may get ahead of the others by before its oldest are
+/// given up on.
+pub const MAX_UNPAIRED_FRAMES: usize = 8;
+
+/// One frame from each source of a `FrameSynchronizer`, in the order the
+/// sources are numbered.
+#[derive(Clone, Debug)]
+pub struct FrameBundle {
+    pub frames: Vec<Frame>,
+}
+
+impl FrameBundle {
+    /// When the earliest frame of the bundle was captured, on the
+    /// monotonic clock.
+    pub fn monotonic_ns(&self) -> u64 {
+        self.frames.iter().map(capture_ns).min().unwrap_or(0)
+    }
+
+    /// How far apart the earliest and latest frames were captured.
+    pub fn spread(&self) -> Duration {
+        let latest = self.frames.iter().map(capture_ns).max().unwrap_or(0);
+        Duration::from_nanos(latest - self.monotonic_ns())
+    }
+}
+
+/// Called with each bundle a `FrameSynchronizer` completes.
+pub type BundleSink = Arc<dyn Fn(FrameBundle) + Send + Sync + 'static>;
+
+/// Matches frames from `sources` cameras whose capture times lie within
+/// `tolerance` of each other. Frames that can no longer be matched, because
+/// every other source has moved past them, are dropped and counted.
+#[derive(Debug)]
+pub struct FrameSynchronizer {
+    tolerance: Duration,
+    state: Mutex<SyncState>,
+}
+
+#[derive(Debug)]
+struct SyncState {
+    queues: Vec<VecDeque<Frame>>,
+    unpaired: u64,
+}
+
+impl FrameSynchronizer {
+    pub fn new(sources: usize, tolerance: Duration) -> Self {
+        Self {
+            tolerance,
+            state: Mutex::new(SyncState {
+                queues: (0..sources).map(|_| VecDeque::new()).collect(),
+                unpaired: 0,
+            }),
+        }
+    }
+
+    pub fn sources(&self) -> usize {
+        self.lock().queues.len()
+    }
+
+    pub fn tolerance(&self) -> Duration {
+        self.tolerance
+    }
+
+    /// How many frames were dropped without a match so far.
+    pub fn unpaired(&self) -> u64 {
+        self.lock().unpaired
+    }
+
+    /// Adds a frame from `source`, returning the bundle it completes, if
+    /// any. Frames from each source must arrive in capture order.
+    pub fn push(&self, source: usize, frame: Frame) -> Option<FrameBundle> {
+        let tolerance = self.tolerance.as_nanos() as u64;
+        let mut state = self.lock();
+        let state = &mut *state;
+        let queue = state.queues.get_mut(source)?;
+        if queue.len() == MAX_UNPAIRED_FRAMES {
+            queue.pop_front();
+            state.unpaired += 1;
+        }
+        queue.push_back(frame);
+
+        // Every source's oldest frame is the earliest it could still match.
+        // If those span more than the tolerance, the earliest can never be
+        // matched with the latest source, whose older frames are gone.
+        while state.queues.iter().all(|queue| !queue.is_empty()) {
+            let heads: Vec<u64> = state.queues.iter().map(|q| capture_ns(&q[0])).collect();
+            let (earliest, &min) = heads.iter().enumerate().min_by_key(|&(_, ns)| *ns)?;
+            let max = heads.iter().copied().max().unwrap_or(min);
+            if max - min <= tolerance {
+                let frames = state
+                    .queues
+                    .iter_mut()
+                    .filter_map(VecDeque::pop_front)
+                    .collect();
+                return Some(FrameBundle { frames });
+            }
+            state.queues[earliest].pop_front();
+            state.unpaired += 1;
+        }
+        None
+    }
+
+    /// A sink for the frames of `source`, handing completed bundles to
+    /// `on_bundle` on the thread that completed them.
+    pub fn sink(self: &Arc<Self>, source: usize, on_bundle: BundleSink) -> FrameSink {
+        let synchronizer = Arc::clone(self);
+        Arc::new(move |frame| {
+            if let Some(bundle) = synchronizer.push(source, frame) {
+                on_bundle(bundle);
+            }
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SyncState> {
+        self.state.lock().unwrap_or_else(|p| p.into_inner())
+    }
+}
+
+/// A frame's capture time, on the monotonic clock where the backend or the
+/// dispatcher set one.
+fn capture_ns(frame: &Frame) -> u64 {
+    if frame.monotonic_ns != 0 {
+        frame.monotonic_ns
+    } else {
+        frame.timestamp_ns
+    }
+}
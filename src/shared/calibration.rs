@@ -0,0 +1,676 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Lens calibration from checkerboard views: Zhang's method for a first
+//! estimate of the intrinsics, refined together with the distortion and
+//! every view's pose by Levenberg-Marquardt, in the Brown-Conrady model
+//! OpenCV uses, so calibrations carry over.
+
+use crate::shared::CameraError;
+use core::{fmt, str::FromStr};
+
+/// Views `Calibrator::calibrate` needs at the least.
+pub const MIN_CALIBRATION_VIEWS: usize = 3;
+
+/// A camera's intrinsics and lens distortion, for frames of `width` by
+/// `height` pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LensCalibration {
+    pub width: u32,
+    pub height: u32,
+    /// Focal lengths, in pixels.
+    pub fx: f64,
+    pub fy: f64,
+    /// The principal point, in pixels.
+    pub cx: f64,
+    pub cy: f64,
+    /// `k1, k2, p1, p2, k3`: radial and tangential coefficients.
+    pub distortion: [f64; 5],
+}
+
+impl LensCalibration {
+    /// The calibration for frames of another size, assuming they're the
+    /// same view scaled.
+    pub fn scaled_to(&self, width: u32, height: u32) -> Self {
+        let (sx, sy) = (
+            width as f64 / self.width as f64,
+            height as f64 / self.height as f64,
+        );
+        Self {
+            width,
+            height,
+            fx: self.fx * sx,
+            fy: self.fy * sy,
+            cx: self.cx * sx,
+            cy: self.cy * sy,
+            distortion: self.distortion,
+        }
+    }
+
+    /// Distorts a point on the normalized image plane (`x/z`, `y/z`) as the
+    /// lens does.
+    pub fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+        let [k1, k2, p1, p2, k3] = self.distortion;
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+        (
+            x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x),
+            y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y,
+        )
+    }
+
+    /// Where a point on the normalized image plane lands in the frame.
+    pub fn project(&self, x: f64, y: f64) -> (f64, f64) {
+        let (x, y) = self.distort(x, y);
+        (self.fx * x + self.cx, self.fy * y + self.cy)
+    }
+
+    fn to_params(self) -> [f64; 9] {
+        let [k1, k2, p1, p2, k3] = self.distortion;
+        [self.fx, self.fy, self.cx, self.cy, k1, k2, p1, p2, k3]
+    }
+
+    fn with_params(self, p: &[f64]) -> Self {
+        Self {
+            fx: p[0],
+            fy: p[1],
+            cx: p[2],
+            cy: p[3],
+            distortion: [p[4], p[5], p[6], p[7], p[8]],
+            ..self
+        }
+    }
+}
+
+/// Spelled as `WxH:FX,FY,CX,CY:K1,K2,P1,P2,K3`, the form the reader's
+/// `--undistort` takes and `--calibrate` writes.
+impl fmt::Display for LensCalibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [k1, k2, p1, p2, k3] = self.distortion;
+        write!(
+            f,
+            "{}x{}:{},{},{},{}:{},{},{},{},{}",
+            self.width, self.height, self.fx, self.fy, self.cx, self.cy, k1, k2, p1, p2, k3
+        )
+    }
+}
+
+impl FromStr for LensCalibration {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            CameraError::invalid_config(format!(
+                "invalid lens calibration '{s}' (expected WxH:FX,FY,CX,CY:K1,K2,P1,P2,K3)"
+            ))
+        };
+        let mut parts = s.trim().split(':');
+        let (size, camera, distortion) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(size), Some(camera), distortion) if parts.next().is_none() => {
+                (size, camera, distortion.unwrap_or(""))
+            },
+            _ => return Err(invalid()),
+        };
+        let (width, height) = size.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let (width, height): (u32, u32) = (
+            width.trim().parse().map_err(|_| invalid())?,
+            height.trim().parse().map_err(|_| invalid())?,
+        );
+        let numbers = |list: &str| -> Result<Vec<f64>, CameraError> {
+            list.split(',')
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(|n| n.parse::<f64>().map_err(|_| invalid()))
+                .collect()
+        };
+        let [fx, fy, cx, cy] = numbers(camera)?.try_into().map_err(|_| invalid())?;
+        // Trailing coefficients may be left out, as OpenCV does.
+        let mut coefficients = numbers(distortion)?;
+        if coefficients.len() > 5 {
+            return Err(invalid());
+        }
+        coefficients.resize(5, 0.0);
+        let calibration = Self {
+            width,
+            height,
+            fx,
+            fy,
+            cx,
+            cy,
+            distortion: coefficients.try_into().map_err(|_| invalid())?,
+        };
+        let finite = calibration.to_params().iter().all(|p| p.is_finite());
+        if width == 0 || height == 0 || !finite || !(fx > 0.0 && fy > 0.0) {
+            return Err(invalid());
+        }
+        Ok(calibration)
+    }
+}
+
+/// A calibration and how well it explains the views it came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CalibrationReport {
+    pub calibration: LensCalibration,
+    /// Root-mean-square distance between the detected corners and where
+    /// the calibration puts them, in pixels.
+    pub rms_error: f64,
+    pub views: usize,
+}
+
+/// Collects views of a planar target, each the image positions of its
+/// points in the same order, and calibrates the camera from them.
+#[derive(Clone, Debug)]
+pub struct Calibrator {
+    width: u32,
+    height: u32,
+    /// The target's points, on its plane.
+    object: Vec<[f64; 2]>,
+    views: Vec<Vec<[f64; 2]>>,
+}
+
+impl Calibrator {
+    /// For frames of `width` by `height` pixels showing a target with
+    /// points at `object`, in any unit.
+    pub fn new(width: u32, height: u32, object: Vec<[f64; 2]>) -> Self {
+        Self {
+            width,
+            height,
+            object,
+            views: Vec::new(),
+        }
+    }
+
+    /// For a checkerboard of `cols` by `rows` inner corners, as
+    /// `Checkerboard::find` orders them.
+    pub fn for_checkerboard(width: u32, height: u32, cols: u32, rows: u32) -> Self {
+        let object = (0..rows)
+            .flat_map(|j| (0..cols).map(move |i| [i as f64, j as f64]))
+            .collect();
+        Self::new(width, height, object)
+    }
+
+    /// The frame size the views are in.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn views(&self) -> usize {
+        self.views.len()
+    }
+
+    /// Adds a view; `points` has to match the target's points one to one.
+    pub fn add_view(&mut self, points: Vec<[f64; 2]>) -> Result<(), CameraError> {
+        if points.len() != self.object.len() {
+            return Err(CameraError::invalid_config(format!(
+                "a calibration view needs {} points, not {}",
+                self.object.len(),
+                points.len()
+            )));
+        }
+        self.views.push(points);
+        Ok(())
+    }
+
+    /// The last view added, to tell whether a new one shows anything new.
+    pub fn last_view(&self) -> Option<&[[f64; 2]]> {
+        self.views.last().map(Vec::as_slice)
+    }
+
+    pub fn calibrate(&self) -> Result<CalibrationReport, CameraError> {
+        if self.views.len() < MIN_CALIBRATION_VIEWS {
+            return Err(CameraError::invalid_config(format!(
+                "calibration needs at least {MIN_CALIBRATION_VIEWS} views, not {}",
+                self.views.len()
+            )));
+        }
+        if self.object.len() < 4 {
+            return Err(CameraError::invalid_config(
+                "a calibration target needs at least 4 points",
+            ));
+        }
+        let homographies: Vec<Mat3> = self
+            .views
+            .iter()
+            .map(|view| homography(&self.object, view))
+            .collect::<Option<_>>()
+            .ok_or_else(|| CameraError::other("calibration views are degenerate"))?;
+        let initial = self.initial_intrinsics(&homographies);
+        let poses: Vec<[f64; 6]> = homographies.iter().map(|h| pose(&initial, h)).collect();
+        let (calibration, rms_error) = self.refine(initial, poses);
+        if !calibration.to_params().iter().all(|p| p.is_finite()) || !rms_error.is_finite() {
+            return Err(CameraError::other("calibration did not converge"));
+        }
+        Ok(CalibrationReport {
+            calibration,
+            rms_error,
+            views: self.views.len(),
+        })
+    }
+
+    /// The focal length that makes the homographies' rotations orthonormal,
+    /// with the principal point at the centre, square pixels and no
+    /// distortion, which Levenberg-Marquardt then improves on.
+    fn initial_intrinsics(&self, homographies: &[Mat3]) -> LensCalibration {
+        let (cx, cy) = (self.width as f64 / 2.0, self.height as f64 / 2.0);
+        let (mut ab, mut aa) = (0.0, 0.0);
+        for h in homographies {
+            // Move the principal point to the origin.
+            let col = |c: usize| [h[0][c] - cx * h[2][c], h[1][c] - cy * h[2][c], h[2][c]];
+            let (h1, h2) = (col(0), col(1));
+            // h1ᵀωh2 = 0 and h1ᵀωh1 = h2ᵀωh2, linear in 1/f² for ω = diag(1/f², 1/f², 1).
+            for (a, b) in [
+                (h1[0] * h2[0] + h1[1] * h2[1], -h1[2] * h2[2]),
+                (
+                    h1[0] * h1[0] + h1[1] * h1[1] - h2[0] * h2[0] - h2[1] * h2[1],
+                    h2[2] * h2[2] - h1[2] * h1[2],
+                ),
+            ] {
+                ab += a * b;
+                aa += a * a;
+            }
+        }
+        let fallback = self.width.max(self.height) as f64;
+        let f = match ab / aa {
+            t if t > 0.0 && t.is_finite() => {
+                (1.0 / t.sqrt()).clamp(fallback / 10.0, fallback * 20.0)
+            },
+            _ => fallback,
+        };
+        LensCalibration {
+            width: self.width,
+            height: self.height,
+            fx: f,
+            fy: f,
+            cx,
+            cy,
+            distortion: [0.0; 5],
+        }
+    }
+
+    /// Levenberg-Marquardt over the intrinsics, distortion and poses,
+    /// returning the calibration and its RMS error.
+    fn refine(&self, initial: LensCalibration, poses: Vec<[f64; 6]>) -> (LensCalibration, f64) {
+        const INTRINSICS: usize = 9;
+        let n = INTRINSICS + 6 * poses.len();
+        let mut params: Vec<f64> = initial.to_params().to_vec();
+        params.extend(poses.iter().flatten());
+
+        let residuals = |params: &[f64], view: usize, out: &mut Vec<f64>| {
+            let calibration = initial.with_params(&params[..INTRINSICS]);
+            let pose = &params[INTRINSICS + 6 * view..][..6];
+            out.clear();
+            for (object, seen) in self.object.iter().zip(&self.views[view]) {
+                let (u, v) = project_point(&calibration, pose, *object);
+                out.push(u - seen[0]);
+                out.push(v - seen[1]);
+            }
+        };
+        let cost = |params: &[f64]| {
+            let mut out = Vec::new();
+            (0..self.views.len())
+                .map(|view| {
+                    residuals(params, view, &mut out);
+                    out.iter().map(|r| r * r).sum::<f64>()
+                })
+                .sum::<f64>()
+        };
+
+        let mut current = cost(&params);
+        let mut lambda = 1e-3;
+        let (mut r0, mut rp, mut rm) = (Vec::new(), Vec::new(), Vec::new());
+        for _ in 0..100 {
+            // Normal equations, from central differences; each view's
+            // residuals depend on the intrinsics and its own pose only.
+            let mut jtj = vec![0.0; n * n];
+            let mut jtr = vec![0.0; n];
+            for view in 0..self.views.len() {
+                residuals(&params, view, &mut r0);
+                let columns: Vec<usize> = (0..INTRINSICS)
+                    .chain(INTRINSICS + 6 * view..INTRINSICS + 6 * view + 6)
+                    .collect();
+                let mut jacobian = Vec::with_capacity(columns.len());
+                let mut probe = params.clone();
+                for &c in &columns {
+                    let step = 1e-6 * params[c].abs().max(1e-2);
+                    probe[c] = params[c] + step;
+                    residuals(&probe, view, &mut rp);
+                    probe[c] = params[c] - step;
+                    residuals(&probe, view, &mut rm);
+                    probe[c] = params[c];
+                    let column: Vec<f64> = rp
+                        .iter()
+                        .zip(&rm)
+                        .map(|(p, m)| (p - m) / (2.0 * step))
+                        .collect();
+                    jacobian.push(column);
+                }
+                for (a, &ca) in columns.iter().enumerate() {
+                    jtr[ca] += dot(&jacobian[a], &r0);
+                    for (b, &cb) in columns.iter().enumerate().skip(a) {
+                        let value = dot(&jacobian[a], &jacobian[b]);
+                        jtj[ca * n + cb] += value;
+                        if ca != cb {
+                            jtj[cb * n + ca] += value;
+                        }
+                    }
+                }
+            }
+
+            let mut improved = false;
+            while lambda < 1e12 {
+                let mut a = jtj.clone();
+                for i in 0..n {
+                    a[i * n + i] += lambda * jtj[i * n + i].max(1e-12);
+                }
+                let b: Vec<f64> = jtr.iter().map(|v| -v).collect();
+                let Some(delta) = solve(a, b, n) else {
+                    lambda *= 10.0;
+                    continue;
+                };
+                let candidate: Vec<f64> = params.iter().zip(&delta).map(|(p, d)| p + d).collect();
+                let next = cost(&candidate);
+                if next.is_finite() && next < current {
+                    let gain = (current - next) / current.max(f64::MIN_POSITIVE);
+                    params = candidate;
+                    current = next;
+                    lambda = (lambda / 10.0).max(1e-12);
+                    improved = gain > 1e-12;
+                    break;
+                }
+                lambda *= 10.0;
+            }
+            if !improved {
+                break;
+            }
+        }
+
+        let points = (self.object.len() * self.views.len()) as f64;
+        (
+            initial.with_params(&params[..INTRINSICS]),
+            (current / points).sqrt(),
+        )
+    }
+}
+
+type Mat3 = [[f64; 3]; 3];
+
+/// Where the target's point `object` lands in a view at `pose`
+/// (a Rodrigues rotation and a translation).
+fn project_point(calibration: &LensCalibration, pose: &[f64], object: [f64; 2]) -> (f64, f64) {
+    let r = rotation(&pose[..3]);
+    let p = [
+        r[0][0] * object[0] + r[0][1] * object[1] + pose[3],
+        r[1][0] * object[0] + r[1][1] * object[1] + pose[4],
+        r[2][0] * object[0] + r[2][1] * object[1] + pose[5],
+    ];
+    calibration.project(p[0] / p[2], p[1] / p[2])
+}
+
+/// The homography from the target's plane to a view, by the normalized
+/// direct linear transform.
+fn homography(object: &[[f64; 2]], image: &[[f64; 2]]) -> Option<Mat3> {
+    let (to, ti) = (normalization(object)?, normalization(image)?);
+    let apply = |t: &Mat3, p: &[f64; 2]| [t[0][0] * p[0] + t[0][2], t[1][1] * p[1] + t[1][2]];
+    let mut ata = vec![0.0; 81];
+    for (o, i) in object.iter().zip(image) {
+        let ([x, y], [u, v]) = (apply(&to, o), apply(&ti, i));
+        for row in [
+            [-x, -y, -1.0, 0.0, 0.0, 0.0, u * x, u * y, u],
+            [0.0, 0.0, 0.0, -x, -y, -1.0, v * x, v * y, v],
+        ] {
+            for a in 0..9 {
+                for b in 0..9 {
+                    ata[a * 9 + b] += row[a] * row[b];
+                }
+            }
+        }
+    }
+    let h = smallest_eigenvector(ata, 9);
+    let hn = [[h[0], h[1], h[2]], [h[3], h[4], h[5]], [h[6], h[7], h[8]]];
+    // H = Ti⁻¹ Hn To.
+    let h = mul3(&mul3(&inverse_normalization(&ti), &hn), &to);
+    let scale = h[2][2];
+    if scale.abs() < 1e-12 || !h.iter().flatten().all(|v| v.is_finite()) {
+        return None;
+    }
+    Some(h.map(|row| row.map(|v| v / scale)))
+}
+
+/// Hartley's normalization: moves the points' centroid to the origin and
+/// scales their mean distance from it to √2.
+fn normalization(points: &[[f64; 2]]) -> Option<Mat3> {
+    let n = points.len() as f64;
+    let (mx, my) = points
+        .iter()
+        .fold((0.0, 0.0), |(x, y), p| (x + p[0] / n, y + p[1] / n));
+    let mean = points
+        .iter()
+        .map(|p| ((p[0] - mx).powi(2) + (p[1] - my).powi(2)).sqrt())
+        .sum::<f64>()
+        / n;
+    if mean < 1e-12 {
+        return None;
+    }
+    let s = 2f64.sqrt() / mean;
+    Some([[s, 0.0, -s * mx], [0.0, s, -s * my], [0.0, 0.0, 1.0]])
+}
+
+fn inverse_normalization(t: &Mat3) -> Mat3 {
+    let s = t[0][0];
+    [
+        [1.0 / s, 0.0, -t[0][2] / s],
+        [0.0, 1.0 / s, -t[1][2] / s],
+        [0.0, 0.0, 1.0],
+    ]
+}
+
+/// A view's pose from its homography and the intrinsics, as a Rodrigues
+/// rotation and a translation.
+fn pose(calibration: &LensCalibration, h: &Mat3) -> [f64; 6] {
+    let LensCalibration { fx, fy, cx, cy, .. } = *calibration;
+    let unproject = |c: usize| {
+        let (x, y, z) = (h[0][c], h[1][c], h[2][c]);
+        [(x - cx * z) / fx, (y - cy * z) / fy, z]
+    };
+    let (h1, h2, h3) = (unproject(0), unproject(1), unproject(2));
+    let mut lambda = 1.0 / norm(&h1);
+    // The target lies in front of the camera.
+    if h3[2] * lambda < 0.0 {
+        lambda = -lambda;
+    }
+    let r1 = h1.map(|v| v * lambda);
+    let r2 = h2.map(|v| v * lambda);
+    let r3 = cross(&r1, &r2);
+    let r = orthonormalize([
+        [r1[0], r2[0], r3[0]],
+        [r1[1], r2[1], r3[1]],
+        [r1[2], r2[2], r3[2]],
+    ]);
+    let w = rodrigues(&r);
+    [
+        w[0],
+        w[1],
+        w[2],
+        h3[0] * lambda,
+        h3[1] * lambda,
+        h3[2] * lambda,
+    ]
+}
+
+/// The rotation nearest to `m`, `m (mᵀm)^-½`.
+fn orthonormalize(m: Mat3) -> Mat3 {
+    let mut mtm = vec![0.0; 9];
+    for a in 0..3 {
+        for b in 0..3 {
+            mtm[a * 3 + b] = (0..3).map(|k| m[k][a] * m[k][b]).sum();
+        }
+    }
+    let (values, vectors) = eigen(mtm, 3);
+    let mut inv_sqrt = [[0.0; 3]; 3];
+    for a in 0..3 {
+        for b in 0..3 {
+            inv_sqrt[a][b] = (0..3)
+                .map(|k| vectors[a * 3 + k] * vectors[b * 3 + k] / values[k].max(1e-12).sqrt())
+                .sum();
+        }
+    }
+    mul3(&m, &inv_sqrt)
+}
+
+/// The rotation of a Rodrigues vector.
+fn rotation(w: &[f64]) -> Mat3 {
+    let theta = (w[0] * w[0] + w[1] * w[1] + w[2] * w[2]).sqrt();
+    if theta < 1e-12 {
+        return [[1.0, -w[2], w[1]], [w[2], 1.0, -w[0]], [-w[1], w[0], 1.0]];
+    }
+    let k = [w[0] / theta, w[1] / theta, w[2] / theta];
+    let (s, c) = theta.sin_cos();
+    let t = 1.0 - c;
+    [
+        [
+            c + k[0] * k[0] * t,
+            k[0] * k[1] * t - k[2] * s,
+            k[0] * k[2] * t + k[1] * s,
+        ],
+        [
+            k[1] * k[0] * t + k[2] * s,
+            c + k[1] * k[1] * t,
+            k[1] * k[2] * t - k[0] * s,
+        ],
+        [
+            k[2] * k[0] * t - k[1] * s,
+            k[2] * k[1] * t + k[0] * s,
+            c + k[2] * k[2] * t,
+        ],
+    ]
+}
+
+/// The Rodrigues vector of a rotation.
+fn rodrigues(r: &Mat3) -> [f64; 3] {
+    let cos = ((r[0][0] + r[1][1] + r[2][2] - 1.0) / 2.0).clamp(-1.0, 1.0);
+    let theta = cos.acos();
+    let axis = [r[2][1] - r[1][2], r[0][2] - r[2][0], r[1][0] - r[0][1]];
+    if theta < 1e-9 {
+        return [axis[0] / 2.0, axis[1] / 2.0, axis[2] / 2.0];
+    }
+    let sin = theta.sin();
+    if sin.abs() < 1e-6 {
+        // A half turn: the axis is the column of R + I with the largest norm.
+        let columns = [0, 1, 2].map(|c| {
+            let mut v = [r[0][c], r[1][c], r[2][c]];
+            v[c] += 1.0;
+            v
+        });
+        let v = columns
+            .iter()
+            .max_by(|a, b| norm(a).total_cmp(&norm(b)))
+            .copied()
+            .unwrap_or([1.0, 0.0, 0.0]);
+        let n = norm(&v);
+        return v.map(|x| x / n * theta);
+    }
+    axis.map(|v| v * theta / (2.0 * sin))
+}
+
+fn mul3(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut out = [[0.0; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn cross(a: &[f64; 3], b: &[f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(v: &[f64; 3]) -> f64 {
+    dot(v, v).sqrt()
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// The eigenvalues and (column) eigenvectors of a symmetric `n` by `n`
+/// matrix, by cyclic Jacobi rotations.
+fn eigen(mut a: Vec<f64>, n: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut v = vec![0.0; n * n];
+    for i in 0..n {
+        v[i * n + i] = 1.0;
+    }
+    for _ in 0..100 {
+        let off: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i * n + j] * a[i * n + j])
+            .sum();
+        if off < 1e-30 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[p * n + q];
+                if apq.abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let t = if theta == 0.0 { 1.0 } else { t };
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let (akp, akq) = (a[k * n + p], a[k * n + q]);
+                    a[k * n + p] = c * akp - s * akq;
+                    a[k * n + q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * apk - s * aqk;
+                    a[q * n + k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[k * n + p], v[k * n + q]);
+                    v[k * n + p] = c * vkp - s * vkq;
+                    v[k * n + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[i * n + i]).collect(), v)
+}
+
+fn smallest_eigenvector(a: Vec<f64>, n: usize) -> Vec<f64> {
+    let (values, vectors) = eigen(a, n);
+    let smallest = (0..n)
+        .min_by(|&i, &j| values[i].total_cmp(&values[j]))
+        .unwrap_or(0);
+    (0..n).map(|k| vectors[k * n + smallest]).collect()
+}
+
+/// Solves `a x = b` for a symmetric positive definite `a`, by Cholesky.
+fn solve(mut a: Vec<f64>, mut b: Vec<f64>, n: usize) -> Option<Vec<f64>> {
+    for j in 0..n {
+        let d = a[j * n + j] - (0..j).map(|k| a[j * n + k] * a[j * n + k]).sum::<f64>();
+        if d <= 0.0 || !d.is_finite() {
+            return None;
+        }
+        let d = d.sqrt();
+        a[j * n + j] = d;
+        for i in j + 1..n {
+            let s = a[i * n + j] - (0..j).map(|k| a[i * n + k] * a[j * n + k]).sum::<f64>();
+            a[i * n + j] = s / d;
+        }
+    }
+    for i in 0..n {
+        b[i] = (b[i] - (0..i).map(|k| a[i * n + k] * b[k]).sum::<f64>()) / a[i * n + i];
+    }
+    for i in (0..n).rev() {
+        b[i] = (b[i] - (i + 1..n).map(|k| a[k * n + i] * b[k]).sum::<f64>()) / a[i * n + i];
+    }
+    Some(b)
+}
@@ -0,0 +1,128 @@
+// This is free and unencumbered software released into the public domain.
+
+use core::fmt;
+
+/// A capture mode a device advertises, from `Camera::modes`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraMode {
+    pub width: u32,
+    pub height: u32,
+    /// The device's own name for the format, e.g. `mjpeg`, `yuyv422` or
+    /// `SRGGB10_CSI2P`, where the backend reports formats per mode.
+    pub format: Option<String>,
+    /// The lowest frame rate the mode runs at, where reported.
+    pub min_fps: Option<f64>,
+    /// The highest frame rate the mode runs at, where reported.
+    pub max_fps: Option<f64>,
+}
+
+impl CameraMode {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            format: None,
+            min_fps: None,
+            max_fps: None,
+        }
+    }
+
+    pub fn with_format(mut self, format: impl Into<String>) -> Self {
+        self.format = Some(format.into());
+        self
+    }
+
+    pub fn with_fps(mut self, min: f64, max: f64) -> Self {
+        self.min_fps = Some(min.min(max));
+        self.max_fps = Some(max.max(min));
+        self
+    }
+
+    /// Returns whether the mode captures `width`×`height` at `fps`, with 1%
+    /// slack for devices listing 30 fps modes as 29.97.
+    pub fn accepts(&self, width: u32, height: u32, fps: f64) -> bool {
+        let slack = fps * 0.01;
+        (self.width, self.height) == (width, height)
+            && self.min_fps.is_none_or(|min| fps >= min - slack)
+            && self.max_fps.is_none_or(|max| fps <= max + slack)
+    }
+}
+
+impl fmt::Display for CameraMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        match (self.min_fps, self.max_fps) {
+            (Some(min), Some(max)) if min < max => write!(f, " @ {min}-{max} fps")?,
+            (_, Some(max)) => write!(f, " @ {max} fps")?,
+            _ => {},
+        }
+        if let Some(format) = &self.format {
+            write!(f, " ({format})")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a backend would run or request to capture the configured device,
+/// from `Camera::plan`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CapturePlan {
+    /// A helper program and its arguments, as for ffmpeg or rpicam-vid.
+    Command(Vec<String>),
+    /// The format a backend negotiates in-process.
+    Request(String),
+}
+
+impl fmt::Display for CapturePlan {
+    /// Commands are quoted so they can be pasted into a POSIX shell.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapturePlan::Command(args) => {
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    if !arg.is_empty()
+                        && arg
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c))
+                    {
+                        f.write_str(arg)?;
+                    } else {
+                        write!(f, "'{}'", arg.replace('\'', "'\\''"))?;
+                    }
+                }
+                Ok(())
+            },
+            CapturePlan::Request(request) => f.write_str(request),
+        }
+    }
+}
+
+/// Sorts modes by format, then largest first, merging the entries
+/// backends list per size and frame rate into one per size with the range
+/// of rates.
+pub(crate) fn normalize_modes(modes: &mut Vec<CameraMode>) {
+    modes.sort_by(|a, b| {
+        a.format
+            .cmp(&b.format)
+            .then((b.width * b.height).cmp(&(a.width * a.height)))
+            .then(b.width.cmp(&a.width))
+    });
+    modes.dedup_by(|next, kept| {
+        if (&next.format, next.width, next.height) != (&kept.format, kept.width, kept.height) {
+            return false;
+        }
+        kept.min_fps = combine(kept.min_fps, next.min_fps, f64::min);
+        kept.max_fps = combine(kept.max_fps, next.max_fps, f64::max);
+        true
+    });
+}
+
+/// Combines two optional rates, keeping whichever one is present.
+fn combine(a: Option<f64>, b: Option<f64>, pick: fn(f64, f64) -> f64) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(pick(a, b)),
+        (a, b) => a.or(b),
+    }
+}
@@ -0,0 +1,491 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Finding a checkerboard calibration target: saddle points of the
+//! smoothed luma are refined to subpixel accuracy, kept if the pixels
+//! around them alternate dark and light four times, and chained along the
+//! board's edges into a grid.
+
+use crate::shared::{CameraError, Frame, process::luma};
+use core::{fmt, str::FromStr};
+use std::collections::{HashMap, VecDeque};
+
+/// Smoothing of the luma before saddle points are looked for.
+const SIGMA: f32 = 1.5;
+
+/// Radius of the circle round a corner whose pixels have to alternate, and
+/// of the window that refines it; squares have to be twice as wide.
+const RING_RADIUS: f32 = 4.5;
+const RING_SAMPLES: usize = 32;
+
+/// Luma difference (0-255) between a corner's dark and light squares.
+const MIN_CONTRAST: f32 = 24.0;
+
+/// How far off a board edge a neighbour may lie, and how much longer or
+/// shorter than the last step to it, before it's not taken for the next
+/// corner.
+const MAX_ANGLE_COS: f64 = 0.9;
+const STEP_RATIO: (f64, f64) = (0.6, 1.6);
+
+/// A checkerboard by its inner corners (where four squares meet), e.g.
+/// `9x6` for a board of 10 by 7 squares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkerboard {
+    pub cols: u32,
+    pub rows: u32,
+}
+
+impl Checkerboard {
+    pub fn new(cols: u32, rows: u32) -> Self {
+        Self { cols, rows }
+    }
+
+    pub fn corners(&self) -> usize {
+        (self.cols * self.rows) as usize
+    }
+
+    /// The board's inner corners in `frame`, to subpixel accuracy: row by
+    /// row of `cols` corners, each row clockwise of the one before,
+    /// starting in the image's top-left-most corner of the board. `None`
+    /// unless every corner was found.
+    pub fn find(&self, frame: &Frame) -> Option<Vec<[f64; 2]>> {
+        if self.cols < 2 || self.rows < 2 || frame.check_single_plane().is_err() {
+            return None;
+        }
+        let image = Gray::of(frame);
+        let smooth = image.blurred(SIGMA);
+        let corners: Vec<Corner> = smooth
+            .saddles(8 * self.corners())
+            .into_iter()
+            .filter_map(|p| smooth.refine(p))
+            .filter_map(|p| smooth.corner(p))
+            .collect();
+        let corners = dedup(corners);
+        self.grid(&corners)
+    }
+
+    /// Chains the corners into a grid from the one nearest their centroid,
+    /// and returns the board's window of it.
+    fn grid(&self, corners: &[Corner]) -> Option<Vec<[f64; 2]>> {
+        if corners.len() < self.corners() {
+            return None;
+        }
+        let n = corners.len() as f64;
+        let centroid = corners
+            .iter()
+            .fold([0.0, 0.0], |c, k| [c[0] + k.p[0] / n, c[1] + k.p[1] / n]);
+        let seed = (0..corners.len()).min_by(|&a, &b| {
+            dist(corners[a].p, centroid).total_cmp(&dist(corners[b].p, centroid))
+        })?;
+
+        let reach = self.cols.max(self.rows) as i32;
+        let mut labels: HashMap<(i32, i32), usize> = HashMap::new();
+        let mut labelled: HashMap<usize, (i32, i32)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        labels.insert((0, 0), seed);
+        labelled.insert(seed, (0, 0));
+        let [a, b] = corners[seed].edges;
+        queue.push_back((seed, (0, 0), [a, b], [0.0, 0.0]));
+        while let Some((at, (i, j), axes, steps)) = queue.pop_front() {
+            for (axis, sign) in [(0, 1.0), (0, -1.0), (1, 1.0), (1, -1.0)] {
+                let direction = axes[axis].map(|v| v * sign);
+                let Some((next, step)) = neighbour(corners, at, direction, steps[axis]) else {
+                    continue;
+                };
+                let delta = if sign > 0.0 { 1 } else { -1 };
+                let label = if axis == 0 {
+                    (i + delta, j)
+                } else {
+                    (i, j + delta)
+                };
+                match (labelled.get(&next), labels.get(&label)) {
+                    (Some(&seen), _) if seen == label => continue,
+                    // The chain went round and came back inconsistent.
+                    (Some(_), _) | (None, Some(_)) => return None,
+                    (None, None) => {},
+                }
+                if label.0.abs() > reach || label.1.abs() > reach {
+                    continue;
+                }
+                labels.insert(label, next);
+                labelled.insert(next, label);
+                // The next corner's own edges, turned to point the same way.
+                let along = |v: [f64; 2]| {
+                    let candidates = corners[next].edges;
+                    let (best, cos) = candidates
+                        .iter()
+                        .map(|e| (e, dot2(*e, v)))
+                        .max_by(|x, y| x.1.abs().total_cmp(&y.1.abs()))
+                        .unwrap_or((&candidates[0], 1.0));
+                    if cos < 0.0 { best.map(|x| -x) } else { *best }
+                };
+                let next_axes = [along(axes[0]), along(axes[1])];
+                let mut next_steps = steps;
+                next_steps[axis] = step;
+                queue.push_back((next, label, next_axes, next_steps));
+            }
+        }
+
+        let (min_i, max_i) = min_max(labels.keys().map(|l| l.0));
+        let (min_j, max_j) = min_max(labels.keys().map(|l| l.1));
+        let (cols, rows) = (self.cols as i32, self.rows as i32);
+        let mut found = None;
+        for (wide, high, transposed) in [(cols, rows, false), (rows, cols, true)] {
+            for oi in min_i..=max_i - wide + 1 {
+                for oj in min_j..=max_j - high + 1 {
+                    let full = (0..high)
+                        .all(|j| (0..wide).all(|i| labels.contains_key(&(oi + i, oj + j))));
+                    if !full {
+                        continue;
+                    }
+                    // Two windows would be a guess.
+                    if found.is_some() {
+                        return None;
+                    }
+                    found = Some((oi, oj, transposed));
+                }
+            }
+            if cols == rows {
+                break;
+            }
+        }
+        let (oi, oj, transposed) = found?;
+        let mut points: Vec<[f64; 2]> = (0..rows)
+            .flat_map(|r| (0..cols).map(move |c| (c, r)))
+            .map(|(c, r)| {
+                let label = if transposed {
+                    (oi + r, oj + c)
+                } else {
+                    (oi + c, oj + r)
+                };
+                corners[labels[&label]].p
+            })
+            .collect();
+        // Keep the board's handedness: the second row is clockwise of the
+        // first, as it is when the board faces the camera upright.
+        let (o, a, b) = (points[0], points[1], points[self.cols as usize]);
+        if (a[0] - o[0]) * (b[1] - o[1]) < (a[1] - o[1]) * (b[0] - o[0]) {
+            points = points
+                .chunks_exact(self.cols as usize)
+                .rev()
+                .flatten()
+                .copied()
+                .collect();
+        }
+        let (first, last) = (points[0], points[points.len() - 1]);
+        if first[0] + first[1] > last[0] + last[1] {
+            points.reverse();
+        }
+        Some(points)
+    }
+}
+
+/// Spelled as `COLSxROWS`.
+impl fmt::Display for Checkerboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.cols, self.rows)
+    }
+}
+
+impl FromStr for Checkerboard {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            CameraError::invalid_config(format!(
+                "invalid checkerboard '{s}' (expected COLSxROWS inner corners, e.g. 9x6)"
+            ))
+        };
+        let (cols, rows) = s.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+        let (cols, rows): (u32, u32) = (
+            cols.trim().parse().map_err(|_| invalid())?,
+            rows.trim().parse().map_err(|_| invalid())?,
+        );
+        if cols < 2 || rows < 2 {
+            return Err(invalid());
+        }
+        Ok(Self { cols, rows })
+    }
+}
+
+/// A checkerboard corner and the directions of the two board edges
+/// through it.
+#[derive(Clone, Copy, Debug)]
+struct Corner {
+    p: [f64; 2],
+    edges: [[f64; 2]; 2],
+}
+
+/// The nearest corner from `at` in `direction`, along an edge of both,
+/// and its distance. `step`, if not 0, is the last distance in that
+/// direction, which the next has to be close to.
+fn neighbour(
+    corners: &[Corner],
+    at: usize,
+    direction: [f64; 2],
+    step: f64,
+) -> Option<(usize, f64)> {
+    let from = corners[at].p;
+    corners
+        .iter()
+        .enumerate()
+        .filter(|&(k, _)| k != at)
+        .filter_map(|(k, corner)| {
+            let v = [corner.p[0] - from[0], corner.p[1] - from[1]];
+            let d = (v[0] * v[0] + v[1] * v[1]).sqrt();
+            if d < 2.0 * RING_RADIUS as f64 {
+                return None;
+            }
+            let u = [v[0] / d, v[1] / d];
+            let on_edge = corner
+                .edges
+                .iter()
+                .any(|e| dot2(*e, u).abs() > MAX_ANGLE_COS);
+            (dot2(u, direction) > MAX_ANGLE_COS && on_edge).then_some((k, d))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .filter(|&(_, d)| step == 0.0 || (STEP_RATIO.0..=STEP_RATIO.1).contains(&(d / step)))
+}
+
+/// Drops corners found twice, keeping the first.
+fn dedup(corners: Vec<Corner>) -> Vec<Corner> {
+    let mut kept: Vec<Corner> = Vec::with_capacity(corners.len());
+    for corner in corners {
+        if kept.iter().all(|k| dist(k.p, corner.p) > 2.0) {
+            kept.push(corner);
+        }
+    }
+    kept
+}
+
+fn dist(a: [f64; 2], b: [f64; 2]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+fn dot2(a: [f64; 2], b: [f64; 2]) -> f64 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn min_max(values: impl Iterator<Item = i32>) -> (i32, i32) {
+    values.fold((i32::MAX, i32::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)))
+}
+
+/// A luma image, as floats.
+struct Gray {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Gray {
+    fn of(frame: &Frame) -> Self {
+        let bpp = frame.pixel_format.bytes_per_pixel() as usize;
+        let stride = frame.stride as usize;
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let row = &frame.data[y * stride..][..width * bpp];
+            data.extend(
+                row.chunks_exact(bpp)
+                    .map(|px| luma(px, frame.pixel_format) as f32),
+            );
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.data[y * self.width + x]
+    }
+
+    /// Bilinear sample, clamped to the image.
+    fn sample(&self, x: f64, y: f64) -> f32 {
+        let x = x.clamp(0.0, (self.width - 1) as f64);
+        let y = y.clamp(0.0, (self.height - 1) as f64);
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = ((x - x0 as f64) as f32, (y - y0 as f64) as f32);
+        let top = self.at(x0, y0) * (1.0 - fx) + self.at(x1, y0) * fx;
+        let bottom = self.at(x0, y1) * (1.0 - fx) + self.at(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    /// Separable Gaussian blur, clamping at the edges.
+    fn blurred(&self, sigma: f32) -> Self {
+        let radius = (3.0 * sigma).ceil() as isize;
+        let kernel: Vec<f32> = (-radius..=radius)
+            .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let total: f32 = kernel.iter().sum();
+        let kernel: Vec<f32> = kernel.iter().map(|k| k / total).collect();
+        let (w, h) = (self.width as isize, self.height as isize);
+        let pass = |src: &[f32], horizontal: bool| -> Vec<f32> {
+            let mut out = vec![0.0; src.len()];
+            for y in 0..h {
+                for x in 0..w {
+                    let mut sum = 0.0;
+                    for (k, weight) in kernel.iter().enumerate() {
+                        let o = k as isize - radius;
+                        let (sx, sy) = if horizontal {
+                            ((x + o).clamp(0, w - 1), y)
+                        } else {
+                            (x, (y + o).clamp(0, h - 1))
+                        };
+                        sum += weight * src[(sy * w + sx) as usize];
+                    }
+                    out[(y * w + x) as usize] = sum;
+                }
+            }
+            out
+        };
+        let data = pass(&pass(&self.data, true), false);
+        Self { data, ..*self }
+    }
+
+    /// Local maxima of the saddle response `Ixy² - Ixx Iyy`, strongest
+    /// first, at most `limit` of them.
+    fn saddles(&self, limit: usize) -> Vec<[f64; 2]> {
+        let (w, h) = (self.width, self.height);
+        let margin = RING_RADIUS.ceil() as usize + 2;
+        if w <= 2 * margin || h <= 2 * margin {
+            return Vec::new();
+        }
+        let mut response = vec![0.0f32; w * h];
+        for y in 1..h - 1 {
+            for x in 1..w - 1 {
+                let c = self.at(x, y);
+                let ixx = self.at(x + 1, y) - 2.0 * c + self.at(x - 1, y);
+                let iyy = self.at(x, y + 1) - 2.0 * c + self.at(x, y - 1);
+                let ixy = (self.at(x + 1, y + 1) - self.at(x + 1, y - 1) - self.at(x - 1, y + 1)
+                    + self.at(x - 1, y - 1))
+                    / 4.0;
+                response[y * w + x] = (ixy * ixy - ixx * iyy).max(0.0);
+            }
+        }
+        let strongest = response.iter().copied().fold(0.0, f32::max);
+        let threshold = strongest * 0.02;
+        let mut peaks = Vec::new();
+        for y in margin..h - margin {
+            for x in margin..w - margin {
+                let r = response[y * w + x];
+                if r <= threshold {
+                    continue;
+                }
+                let peak = (y - 3..=y + 3).all(|ny| {
+                    (x - 3..=x + 3).all(|nx| {
+                        let other = response[ny * w + nx];
+                        other < r || (other == r && (ny, nx) >= (y, x))
+                    })
+                });
+                if peak {
+                    peaks.push((r, [x as f64, y as f64]));
+                }
+            }
+        }
+        peaks.sort_by(|a, b| b.0.total_cmp(&a.0));
+        peaks.truncate(limit);
+        peaks.into_iter().map(|(_, p)| p).collect()
+    }
+
+    /// Moves `p` to where the gradients round it are orthogonal to the
+    /// lines to it, as OpenCV's `cornerSubPix` does.
+    fn refine(&self, p: [f64; 2]) -> Option<[f64; 2]> {
+        let radius = RING_RADIUS.floor() as isize;
+        let mut q = p;
+        for _ in 0..20 {
+            let (cx, cy) = (q[0].round() as isize, q[1].round() as isize);
+            let (mut a, mut b, mut c, mut bx, mut by) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let (x, y) = (cx + dx, cy + dy);
+                    if x < 1
+                        || y < 1
+                        || x >= self.width as isize - 1
+                        || y >= self.height as isize - 1
+                    {
+                        return None;
+                    }
+                    let (x, y) = (x as usize, y as usize);
+                    let gx = ((self.at(x + 1, y) - self.at(x - 1, y)) / 2.0) as f64;
+                    let gy = ((self.at(x, y + 1) - self.at(x, y - 1)) / 2.0) as f64;
+                    let weight = (-((dx * dx + dy * dy) as f64) / (radius * radius) as f64).exp();
+                    let (gxx, gxy, gyy) = (gx * gx * weight, gx * gy * weight, gy * gy * weight);
+                    a += gxx;
+                    b += gxy;
+                    c += gyy;
+                    bx += gxx * x as f64 + gxy * y as f64;
+                    by += gxy * x as f64 + gyy * y as f64;
+                }
+            }
+            let det = a * c - b * b;
+            if det.abs() < 1e-9 {
+                return None;
+            }
+            let next = [(c * bx - b * by) / det, (a * by - b * bx) / det];
+            let moved = dist(next, q);
+            q = next;
+            if dist(q, p) > RING_RADIUS as f64 {
+                return None;
+            }
+            if moved < 0.01 {
+                break;
+            }
+        }
+        Some(q)
+    }
+
+    /// A corner at `p` if the circle round it crosses four edges between
+    /// dark and light, with the two board edges through it.
+    fn corner(&self, p: [f64; 2]) -> Option<Corner> {
+        let angle = |k: f64| k * core::f64::consts::TAU / RING_SAMPLES as f64;
+        let ring: Vec<f32> = (0..RING_SAMPLES)
+            .map(|k| {
+                let (sin, cos) = angle(k as f64).sin_cos();
+                self.sample(
+                    p[0] + cos * RING_RADIUS as f64,
+                    p[1] + sin * RING_RADIUS as f64,
+                )
+            })
+            .collect();
+        let (lo, hi) = ring
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        if hi - lo < MIN_CONTRAST {
+            return None;
+        }
+        let mid = (lo + hi) / 2.0;
+        let dead = (hi - lo) * 0.15;
+        // Samples near the middle keep the side of the one before, so noise
+        // on an edge doesn't count as crossings.
+        let start = ring.iter().position(|v| (v - mid).abs() > dead)?;
+        let mut side = ring[start] > mid;
+        let mut crossings = Vec::new();
+        for step in 1..=RING_SAMPLES {
+            let k = (start + step) % RING_SAMPLES;
+            let v = ring[k];
+            if (v - mid).abs() > dead && (v > mid) != side {
+                side = v > mid;
+                crossings.push(angle((start + step) as f64 - 0.5));
+            }
+        }
+        if crossings.len() != 4 {
+            return None;
+        }
+        let unit = |a: f64| [a.cos(), a.sin()];
+        let edge = |a: f64, b: f64| {
+            let (u, v) = (unit(a), unit(b));
+            let e = [u[0] - v[0], u[1] - v[1]];
+            let n = (e[0] * e[0] + e[1] * e[1]).sqrt();
+            [e[0] / n, e[1] / n]
+        };
+        Some(Corner {
+            p,
+            edges: [
+                edge(crossings[0], crossings[2]),
+                edge(crossings[1], crossings[3]),
+            ],
+        })
+    }
+}
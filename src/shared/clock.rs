@@ -0,0 +1,261 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Capture timestamps on common host clocks.
+//!
+//! Every delivered frame carries its capture time twice: as UTC wall-clock
+//! nanoseconds (`Frame::timestamp_ns`), for records and cross-machine
+//! correlation, and on the host's monotonic clock (`Frame::monotonic_ns`),
+//! which never steps and so orders frames from different cameras in one
+//! process. Drivers whose devices stamp frames on their own clock map those
+//! stamps onto the host clocks with a [`ClockSync`].
+
+use crate::shared::Frame;
+use core::time::Duration;
+use std::collections::VecDeque;
+
+/// Device-to-host offsets remembered by [`ClockSync`]; at 30 fps, four
+/// seconds' worth, enough to track drift while rejecting delayed frames.
+const SYNC_WINDOW: usize = 120;
+
+/// An offset this far from the current estimate means the device clock was
+/// reset, e.g. because the device restarted, rather than drifted.
+const SYNC_RESET_NS: i64 = 1_000_000_000;
+
+/// The host's monotonic clock in nanoseconds: `CLOCK_MONOTONIC` on Unix,
+/// the same clock V4L2, GStreamer and most Android sensors stamp frames
+/// with; the page's `performance.now()` in browsers; and a process-wide
+/// epoch elsewhere. Zero where no monotonic clock is available.
+pub fn monotonic_ns() -> u64 {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+            // SAFETY: `ts` is a valid timespec to write into.
+            unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+            ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+        } else if #[cfg(all(feature = "web", target_arch = "wasm32"))] {
+            web_sys::window()
+                .and_then(|w| w.performance())
+                .map_or(0, |p| (p.now() * 1e6) as u64)
+        } else if #[cfg(target_arch = "wasm32")] {
+            0
+        } else {
+            static EPOCH: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+            // Starts at 1, so zero keeps meaning "unknown".
+            EPOCH.get_or_init(std::time::Instant::now).elapsed().as_nanos() as u64 + 1
+        }
+    }
+}
+
+/// The current UTC time in nanoseconds since the Unix epoch.
+pub fn wall_clock_ns() -> u64 {
+    jiff::Timestamp::now().as_nanosecond().max(0) as u64
+}
+
+/// One instant on both host clocks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameTime {
+    /// UTC nanoseconds since the Unix epoch.
+    pub wall_ns: u64,
+    /// Nanoseconds on [`monotonic_ns`]'s clock.
+    pub monotonic_ns: u64,
+}
+
+impl FrameTime {
+    pub fn now() -> Self {
+        Self {
+            wall_ns: wall_clock_ns(),
+            monotonic_ns: monotonic_ns(),
+        }
+    }
+
+    /// The wall-clock time of a past monotonic instant, as of the current
+    /// offset between the two clocks.
+    pub fn from_monotonic(monotonic_ns: u64) -> Self {
+        let now = Self::now();
+        Self {
+            wall_ns: now
+                .wall_ns
+                .saturating_sub(now.monotonic_ns.saturating_sub(monotonic_ns)),
+            monotonic_ns,
+        }
+    }
+
+    /// The monotonic time of a past wall-clock instant, e.g. a timestamp
+    /// received from another process.
+    pub fn from_wall(wall_ns: u64) -> Self {
+        let now = Self::now();
+        Self {
+            wall_ns,
+            monotonic_ns: now
+                .monotonic_ns
+                .saturating_sub(now.wall_ns.saturating_sub(wall_ns)),
+        }
+    }
+}
+
+/// When a frame reached each stage past its driver, in nanoseconds on
+/// [`monotonic_ns`]'s clock; the capture itself is `Frame::monotonic_ns`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StageTimes {
+    /// When the driver queued the frame for the dispatcher.
+    pub enqueued_ns: Option<u64>,
+    /// When the dispatcher, done processing the frame, began invoking sinks.
+    pub dispatched_ns: Option<u64>,
+}
+
+impl StageTimes {
+    /// The current time, or `None` where there's no monotonic clock.
+    pub(crate) fn now() -> Option<u64> {
+        Some(monotonic_ns()).filter(|&ns| ns != 0)
+    }
+}
+
+/// How long a frame spent in each stage before reaching a sink.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StageLatency {
+    /// From capture to the dispatcher's queue: the device and its driver.
+    pub driver: Duration,
+    /// From the queue to the sinks: waiting in the channel, then the
+    /// dispatcher's transforms and checks.
+    pub dispatch: Duration,
+}
+
+/// Shifts the capture times of a replayed source so they keep moving
+/// forward: each pass starts one frame interval after the last one ended,
+/// whatever clock the source restarts from.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ReplayClock {
+    offset_ns: u64,
+    last_ns: u64,
+    interval_ns: u64,
+}
+
+impl ReplayClock {
+    pub(crate) fn rebase(&mut self, frame: &mut Frame) {
+        let mut ns = frame.monotonic_ns.saturating_add(self.offset_ns);
+        if self.last_ns != 0 && ns <= self.last_ns {
+            let shift = self.last_ns + self.interval_ns.max(1) - ns;
+            self.offset_ns += shift;
+            ns += shift;
+        } else if self.last_ns != 0 {
+            self.interval_ns = ns - self.last_ns;
+        }
+        let shift = ns - frame.monotonic_ns;
+        frame.monotonic_ns = ns;
+        frame.timestamp_ns = frame.timestamp_ns.saturating_add(shift);
+        self.last_ns = ns;
+    }
+}
+
+/// Estimates the offset between a device's frame clock and the host's
+/// monotonic clock, so device timestamps (sensor exposure times, pipeline
+/// presentation times) become comparable with other cameras.
+///
+/// Each frame's arrival bounds its capture offset from above; delivery
+/// delays only ever add to it. The estimate is therefore the smallest
+/// offset seen over a sliding window, which follows slow drift between the
+/// clocks and restarts when the device clock jumps.
+#[derive(Clone, Debug, Default)]
+pub struct ClockSync {
+    /// Candidate minima, oldest first, with increasing offsets.
+    window: VecDeque<(u64, i64)>,
+    samples: u64,
+}
+
+impl ClockSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a frame stamped `device_ns` by the device that arrived just
+    /// now, and returns its capture time on the host clocks.
+    pub fn observe(&mut self, device_ns: u64) -> FrameTime {
+        self.observe_at(device_ns, monotonic_ns())
+    }
+
+    /// Like [`observe`](Self::observe), for a frame that arrived at
+    /// `arrival_ns` on the monotonic clock.
+    pub fn observe_at(&mut self, device_ns: u64, arrival_ns: u64) -> FrameTime {
+        let offset = arrival_ns as i64 - device_ns as i64;
+        if self
+            .offset_ns()
+            .is_some_and(|estimate| (offset - estimate).abs() > SYNC_RESET_NS)
+        {
+            self.window.clear();
+        }
+        while self.window.back().is_some_and(|&(_, o)| o >= offset) {
+            self.window.pop_back();
+        }
+        self.window.push_back((self.samples, offset));
+        while self
+            .window
+            .front()
+            .is_some_and(|&(n, _)| n + SYNC_WINDOW as u64 <= self.samples)
+        {
+            self.window.pop_front();
+        }
+        self.samples += 1;
+        self.to_host(device_ns).unwrap_or_default()
+    }
+
+    /// The current estimate of host monotonic minus device time, if any
+    /// frame has been observed.
+    pub fn offset_ns(&self) -> Option<i64> {
+        self.window.front().map(|&(_, offset)| offset)
+    }
+
+    /// Maps a device timestamp onto the host clocks.
+    pub fn to_host(&self, device_ns: u64) -> Option<FrameTime> {
+        let offset = self.offset_ns()?;
+        Some(FrameTime::from_monotonic(
+            (device_ns as i64).saturating_add(offset).max(0) as u64,
+        ))
+    }
+}
+
+impl Frame {
+    /// The frame's capture time on both host clocks.
+    #[inline]
+    pub fn time(&self) -> FrameTime {
+        FrameTime {
+            wall_ns: self.timestamp_ns,
+            monotonic_ns: self.monotonic_ns,
+        }
+    }
+
+    #[inline]
+    pub fn with_time(mut self, time: FrameTime) -> Self {
+        self.timestamp_ns = time.wall_ns;
+        self.monotonic_ns = time.monotonic_ns;
+        self
+    }
+
+    /// How long the frame took to reach the sinks, stage by stage; `None`
+    /// for frames that didn't pass through a dispatcher's queue.
+    pub fn stage_latency(&self) -> Option<StageLatency> {
+        let StageTimes {
+            enqueued_ns: Some(enqueued),
+            dispatched_ns: Some(dispatched),
+        } = self.metadata.stages
+        else {
+            return None;
+        };
+        Some(StageLatency {
+            driver: Duration::from_nanos(enqueued.saturating_sub(self.monotonic_ns)),
+            dispatch: Duration::from_nanos(dispatched.saturating_sub(enqueued)),
+        })
+    }
+
+    /// Fills in whichever capture times the driver left at zero, from the
+    /// other one or, failing both, from the current time.
+    pub(crate) fn fill_time(&mut self) {
+        let time = match (self.timestamp_ns, self.monotonic_ns) {
+            (0, 0) => FrameTime::now(),
+            (0, monotonic_ns) => FrameTime::from_monotonic(monotonic_ns),
+            (wall_ns, 0) => FrameTime::from_wall(wall_ns),
+            _ => return,
+        };
+        self.timestamp_ns = time.wall_ns;
+        self.monotonic_ns = time.monotonic_ns;
+    }
+}
@@ -0,0 +1,165 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Colorimetry: what a frame's values mean, so consumers converting or
+//! analyzing them don't have to guess at the matrix and range.
+
+/// The YCbCr matrix of the camera's native signal, i.e. the coefficients
+/// the backend (or the sensor) converted to RGB with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMatrix {
+    /// ITU-R BT.601, as SD video and JPEG use.
+    Bt601,
+    /// ITU-R BT.709, as most HD webcams use.
+    Bt709,
+    /// ITU-R BT.2020 (non-constant luminance), as HDR sensors use.
+    Bt2020,
+}
+
+impl ColorMatrix {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ColorMatrix::Bt601 => "bt601",
+            ColorMatrix::Bt709 => "bt709",
+            ColorMatrix::Bt2020 => "bt2020",
+        }
+    }
+}
+
+/// Whether a frame's values span their whole range or the 16–235 "studio"
+/// range of 8-bit video.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorRange {
+    Full,
+    Limited,
+}
+
+impl ColorRange {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ColorRange::Full => "full",
+            ColorRange::Limited => "limited",
+        }
+    }
+}
+
+/// How a frame's values map to colors, as far as its backend knows; `None`
+/// fields are unknown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Colorimetry {
+    pub matrix: Option<ColorMatrix>,
+    /// The range of the frame's own values, after any conversion to RGB.
+    pub range: Option<ColorRange>,
+    /// The values are sRGB-encoded: sRGB primaries and transfer curve.
+    pub srgb: bool,
+}
+
+impl Colorimetry {
+    /// Nothing known.
+    pub const UNKNOWN: Colorimetry = Colorimetry {
+        matrix: None,
+        range: None,
+        srgb: false,
+    };
+
+    /// Full-range sRGB with no YCbCr origin, e.g. a browser canvas.
+    pub const SRGB: Colorimetry = Colorimetry {
+        matrix: None,
+        range: Some(ColorRange::Full),
+        srgb: true,
+    };
+
+    /// A decoded JPEG (JFIF): BT.601 over the full range, in sRGB.
+    pub const JPEG: Colorimetry = Colorimetry {
+        matrix: Some(ColorMatrix::Bt601),
+        range: Some(ColorRange::Full),
+        srgb: true,
+    };
+
+    pub fn is_unknown(&self) -> bool {
+        *self == Colorimetry::UNKNOWN
+    }
+
+    /// From a V4L2 format's `colorspace`, `ycbcr_enc` and `quantization`,
+    /// resolving their `DEFAULT`s as `V4L2_MAP_*_DEFAULT` do; `rgb` says
+    /// whether the buffer holds RGB rather than YCbCr. A backend converting
+    /// YCbCr buffers itself should set `range` for what it produces.
+    pub fn from_v4l2(colorspace: u32, ycbcr_enc: u32, quantization: u32, rgb: bool) -> Self {
+        const COLORSPACE_SMPTE240M: u32 = 2;
+        const COLORSPACE_REC709: u32 = 3;
+        const COLORSPACE_JPEG: u32 = 7;
+        const COLORSPACE_SRGB: u32 = 8;
+        const COLORSPACE_BT2020: u32 = 10;
+        const COLORSPACE_DCI_P3: u32 = 12;
+        let matrix = match ycbcr_enc {
+            0 => match colorspace {
+                COLORSPACE_REC709 | COLORSPACE_DCI_P3 => Some(ColorMatrix::Bt709),
+                COLORSPACE_BT2020 => Some(ColorMatrix::Bt2020),
+                COLORSPACE_SMPTE240M => None,
+                _ => Some(ColorMatrix::Bt601),
+            },
+            1 | 3 | 5 => Some(ColorMatrix::Bt601),
+            2 | 4 => Some(ColorMatrix::Bt709),
+            6 => Some(ColorMatrix::Bt2020),
+            _ => None,
+        };
+        let range = match quantization {
+            1 => ColorRange::Full,
+            2 => ColorRange::Limited,
+            _ if rgb => ColorRange::Full,
+            _ if ycbcr_enc == 3 || ycbcr_enc == 4 => ColorRange::Limited,
+            _ if colorspace == COLORSPACE_JPEG => ColorRange::Full,
+            _ => ColorRange::Limited,
+        };
+        Self {
+            matrix,
+            range: Some(range),
+            srgb: matches!(colorspace, COLORSPACE_SRGB | COLORSPACE_JPEG),
+        }
+    }
+
+    /// From a Media Foundation type's `MF_MT_YUV_MATRIX`,
+    /// `MF_MT_VIDEO_NOMINAL_RANGE` and `MF_MT_TRANSFER_FUNCTION`.
+    pub fn from_media_foundation(matrix: u32, nominal_range: u32, transfer: u32) -> Self {
+        const TRANSFER_SRGB: u32 = 7;
+        Self {
+            matrix: match matrix {
+                1 => Some(ColorMatrix::Bt709),
+                2 => Some(ColorMatrix::Bt601),
+                4 | 5 => Some(ColorMatrix::Bt2020),
+                _ => None,
+            },
+            range: match nominal_range {
+                1 => Some(ColorRange::Full),
+                2 => Some(ColorRange::Limited),
+                _ => None,
+            },
+            srgb: transfer == TRANSFER_SRGB,
+        }
+    }
+
+    /// From a `CVImageBuffer`'s `kCVImageBufferYCbCrMatrixKey` and
+    /// `kCVImageBufferTransferFunctionKey` attachments, and whether its
+    /// pixel format is a full-range (`…f`) or video-range (`…v`) one.
+    pub fn from_core_video(
+        matrix: Option<&str>,
+        transfer: Option<&str>,
+        full_range: Option<bool>,
+    ) -> Self {
+        Self {
+            matrix: match matrix {
+                Some("ITU_R_601_4") => Some(ColorMatrix::Bt601),
+                Some("ITU_R_709_2") => Some(ColorMatrix::Bt709),
+                Some("ITU_R_2020") => Some(ColorMatrix::Bt2020),
+                _ => None,
+            },
+            range: full_range.map(|full| {
+                if full {
+                    ColorRange::Full
+                } else {
+                    ColorRange::Limited
+                }
+            }),
+            srgb: transfer == Some("IEC_sRGB"),
+        }
+    }
+}
@@ -1,28 +1,115 @@
 // This is free and unencumbered software released into the public domain.
 
-use crate::shared::PixelFormat;
+use crate::shared::{
+    AutoLock, CameraBackend, DEFAULT_STOP_TIMEOUT, ExposureCheck, Flip, FocusCheck, FrameTransform,
+    FrameValidation, LensCalibration, LoadGuard, PixelFormat, Rotation, SensorMode, Sidecar,
+    ThreadScheduling, TriggerConfig,
+};
+use core::time::Duration;
+use std::path::PathBuf;
+
+#[cfg(feature = "audio")]
+use crate::shared::AudioConfig;
+
+/// The longest a `buffer_frames` queue should span at the configured rate.
+pub const MAX_BUFFER_SPAN: Duration = Duration::from_secs(2);
+
+/// The shortest a `buffer_frames` queue should span at the configured rate.
+pub const MIN_BUFFER_SPAN: Duration = Duration::from_millis(20);
 
 #[derive(Clone, Debug)]
 pub struct CameraConfig {
+    /// Driver to capture with; `None` picks the first one built in.
+    pub backend: Option<CameraBackend>,
     pub device: Option<String>,
     pub width: u32,
     pub height: u32,
     pub fps: f64,
     pub pixel_format: Option<PixelFormat>,
-    pub buffer_frames: usize,
+    /// Frames the dispatcher queues for the sinks before it drops new ones;
+    /// `None` uses the backend's `default_buffer_frames`.
+    pub buffer_frames: Option<usize>,
+    /// Priority and core of the thread that runs the sinks; by default
+    /// whatever the OS picks.
+    pub dispatch_thread: ThreadScheduling,
     pub diagnostics: bool,
+    /// Ask the backend to attach a `FrameHandle` to each frame where it can.
+    pub gpu_handles: bool,
+    pub transform: FrameTransform,
+    /// Remove the lens distortion of this calibration from every frame,
+    /// before the transform.
+    pub undistort: Option<LensCalibration>,
+    pub exposure_check: Option<ExposureCheck>,
+    /// Attach `FocusStats` to every frame, for focusing a lens by hand.
+    pub focus_check: Option<FocusCheck>,
+    /// Upper bound on how long stopping waits for capture threads.
+    pub stop_timeout: Duration,
+    /// Restart the driver when no frame arrives for this long while
+    /// capturing; see `Camera::check_watchdog`.
+    pub watchdog: Option<Duration>,
+    /// Lower the frame rate or resolution when the host can't keep up;
+    /// see `Camera::check_load`.
+    pub load_guard: Option<LoadGuard>,
+    /// Replay finite sources from the start when they end, with capture
+    /// times kept moving forward; see `Camera::check_replay`.
+    pub looping: bool,
+    /// Telemetry to attach to the frames of a file source, by time since
+    /// its first frame (`FrameMetadata::telemetry`).
+    pub sidecar: Option<Sidecar>,
+    /// Raw sensor mode for libcamera (`csi:`) cameras.
+    pub sensor_mode: Option<SensorMode>,
+    /// Ask the sensor for its HDR mode, where the backend can (`csi:`
+    /// cameras); others capture as usual.
+    pub hdr: bool,
+    /// libcamera IPA tuning file, e.g. for NoIR or third-party sensor boards.
+    pub tuning_file: Option<PathBuf>,
+    /// What to do with frames whose buffer doesn't match their dimensions.
+    pub frame_validation: FrameValidation,
+    /// Checksum each frame as the backend hands it over and warn if it
+    /// changed by delivery, to catch backends tearing frames.
+    pub frame_checksums: bool,
+    /// Capture in external trigger mode, one frame per trigger; see
+    /// `Camera::fire_trigger`.
+    pub trigger: Option<TriggerConfig>,
+    /// Lock automatic exposure and white balance once they've converged;
+    /// see `Camera::check_auto_lock`.
+    pub auto_lock: AutoLock,
+    /// Microphone to capture alongside video; see `Camera::take_audio`.
+    #[cfg(feature = "audio")]
+    pub audio: Option<AudioConfig>,
 }
 
 impl Default for CameraConfig {
     fn default() -> Self {
         Self {
+            backend: None,
             device: None,
             width: 640,
             height: 480,
             fps: 30.0,
             pixel_format: None,
-            buffer_frames: 2,
+            buffer_frames: None,
+            dispatch_thread: ThreadScheduling::default(),
             diagnostics: false,
+            gpu_handles: false,
+            transform: FrameTransform::IDENTITY,
+            undistort: None,
+            exposure_check: None,
+            focus_check: None,
+            stop_timeout: DEFAULT_STOP_TIMEOUT,
+            watchdog: None,
+            load_guard: None,
+            looping: false,
+            sidecar: None,
+            sensor_mode: None,
+            hdr: false,
+            tuning_file: None,
+            frame_validation: FrameValidation::default(),
+            frame_checksums: false,
+            trigger: None,
+            auto_lock: AutoLock::default(),
+            #[cfg(feature = "audio")]
+            audio: None,
         }
     }
 }
@@ -37,6 +124,11 @@ impl CameraConfig {
         }
     }
 
+    pub fn with_backend(mut self, backend: CameraBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
     pub fn with_device(mut self, device: impl Into<String>) -> Self {
         self.device = Some(device.into());
         self
@@ -48,7 +140,46 @@ impl CameraConfig {
     }
 
     pub fn with_buffer_frames(mut self, n: usize) -> Self {
-        self.buffer_frames = n.max(1);
+        self.buffer_frames = Some(n.max(1));
+        self
+    }
+
+    /// The dispatcher queue's capacity when capturing with `backend`.
+    pub fn buffer_frames_for(&self, backend: CameraBackend) -> usize {
+        self.buffer_frames
+            .unwrap_or_else(|| backend.default_buffer_frames())
+    }
+
+    /// Why a `buffer_frames` set explicitly doesn't suit the frame rate: a
+    /// queue spanning more than `MAX_BUFFER_SPAN` delivers stale frames, and
+    /// one shorter than `MIN_BUFFER_SPAN` drops frames on any sink hiccup.
+    pub fn buffer_frames_warning(&self) -> Option<String> {
+        let frames = self.buffer_frames?;
+        if !(self.fps.is_finite() && self.fps > 0.0) {
+            return None;
+        }
+        let span = Duration::from_secs_f64(frames as f64 / self.fps);
+        let queued = match frames {
+            1 => "1 frame".to_string(),
+            n => format!("{n} frames"),
+        };
+        if span > MAX_BUFFER_SPAN {
+            Some(format!(
+                "queueing {queued} at {} fps holds {span:.1?} of video; sinks will see frames that late",
+                self.fps
+            ))
+        } else if span < MIN_BUFFER_SPAN {
+            Some(format!(
+                "queueing {queued} at {} fps holds {span:.1?} of video; any longer sink stall drops frames",
+                self.fps
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn with_dispatch_thread(mut self, scheduling: ThreadScheduling) -> Self {
+        self.dispatch_thread = scheduling;
         self
     }
 
@@ -56,4 +187,114 @@ impl CameraConfig {
         self.diagnostics = enabled;
         self
     }
+
+    pub fn with_gpu_handles(mut self, enabled: bool) -> Self {
+        self.gpu_handles = enabled;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.transform.rotation = rotation;
+        self
+    }
+
+    pub fn with_flip(mut self, flip: Flip) -> Self {
+        self.transform.flip = flip;
+        self
+    }
+
+    pub fn with_undistort(mut self, calibration: LensCalibration) -> Self {
+        self.undistort = Some(calibration);
+        self
+    }
+
+    pub fn with_exposure_check(mut self, check: ExposureCheck) -> Self {
+        self.exposure_check = Some(check);
+        self
+    }
+
+    pub fn with_focus_check(mut self, check: FocusCheck) -> Self {
+        self.focus_check = Some(check);
+        self
+    }
+
+    pub fn with_stop_timeout(mut self, timeout: Duration) -> Self {
+        self.stop_timeout = timeout;
+        self
+    }
+
+    pub fn with_watchdog(mut self, timeout: Duration) -> Self {
+        self.watchdog = Some(timeout);
+        self
+    }
+
+    pub fn with_load_guard(mut self, guard: LoadGuard) -> Self {
+        self.load_guard = Some(guard);
+        self
+    }
+
+    pub fn with_looping(mut self, enabled: bool) -> Self {
+        self.looping = enabled;
+        self
+    }
+
+    pub fn with_sidecar(mut self, sidecar: Sidecar) -> Self {
+        self.sidecar = Some(sidecar);
+        self
+    }
+
+    pub fn with_sensor_mode(mut self, mode: SensorMode) -> Self {
+        self.sensor_mode = Some(mode);
+        self
+    }
+
+    pub fn with_hdr(mut self, hdr: bool) -> Self {
+        self.hdr = hdr;
+        self
+    }
+
+    pub fn with_tuning_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.tuning_file = Some(path.into());
+        self
+    }
+
+    pub fn with_frame_validation(mut self, validation: FrameValidation) -> Self {
+        self.frame_validation = validation;
+        self
+    }
+
+    pub fn with_frame_checksums(mut self, enabled: bool) -> Self {
+        self.frame_checksums = enabled;
+        self
+    }
+
+    pub fn with_trigger(mut self, trigger: TriggerConfig) -> Self {
+        self.trigger = Some(trigger);
+        self
+    }
+
+    pub fn with_auto_lock(mut self, auto_lock: AutoLock) -> Self {
+        self.auto_lock = auto_lock;
+        self
+    }
+
+    #[cfg(feature = "audio")]
+    pub fn with_audio(mut self, audio: AudioConfig) -> Self {
+        self.audio = Some(audio);
+        self
+    }
+
+    /// This configuration with the capture format (size, frame rate, pixel
+    /// format, sensor mode and HDR) of `other`, for `Camera::reconfigure`.
+    pub fn with_format_of(&self, other: &CameraConfig) -> Self {
+        Self {
+            width: other.width,
+            height: other.height,
+            fps: other.fps,
+            pixel_format: other.pixel_format,
+            sensor_mode: other.sensor_mode,
+            hdr: other.hdr,
+            ..self.clone()
+        }
+    }
 }
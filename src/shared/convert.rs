@@ -0,0 +1,414 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Pixel format conversion. With the `simd` feature, 8-bit RGB(A) rows go
+//! through SSSE3 or NEON kernels, or vImage on Apple platforms; the scalar
+//! loops are the fallback everywhere else.
+
+use crate::shared::{CameraError, ColorMatrix, ColorRange, Frame, PixelFormat};
+use bytes::Bytes;
+
+impl Frame {
+    /// Converts the frame into a new, tightly packed RGB8 frame. 16-bit
+    /// formats keep their high byte as grey, and 10-bit ones their top 8
+    /// bits; P010 is decoded with its colorimetry's matrix and range
+    /// (BT.709 and limited if unknown), without tone mapping.
+    pub fn to_rgb8(&self) -> Result<Frame, CameraError> {
+        self.check_valid()?;
+        if self.pixel_format == PixelFormat::P010 {
+            return Ok(self.p010_to_rgb8());
+        }
+        let (width, height) = (self.width as usize, self.height as usize);
+        let row_len = width * self.pixel_format.bytes_per_pixel() as usize;
+        let mut data = vec![0u8; width * height * 3];
+        #[cfg(all(feature = "simd", target_vendor = "apple"))]
+        if vimage::to_rgb8(self, &mut data) {
+            return Ok(self.derive_packed(data, PixelFormat::Rgb8));
+        }
+        for (src, dst) in self
+            .data
+            .chunks(self.stride as usize)
+            .zip(data.chunks_exact_mut(width * 3))
+        {
+            row_to_rgb8(&src[..row_len], self.pixel_format, dst);
+        }
+        Ok(self.derive_packed(data, PixelFormat::Rgb8))
+    }
+
+    /// Converts an RGB(A) or BGRA frame into a new, tightly packed RGBA8
+    /// frame. RGB8 gets an opaque alpha channel.
+    pub fn to_rgba8(&self) -> Result<Frame, CameraError> {
+        self.to_four_channel(PixelFormat::Rgba8)
+    }
+
+    /// Converts an RGB(A) or BGRA frame into a new, tightly packed BGRA8
+    /// frame. RGB8 gets an opaque alpha channel.
+    pub fn to_bgra8(&self) -> Result<Frame, CameraError> {
+        self.to_four_channel(PixelFormat::Bgra8)
+    }
+
+    /// Converts the frame into `format` where it can be, tightly packed
+    /// unless it's in `format` already; 16-bit frames only convert to RGB8.
+    pub fn to_pixel_format(&self, format: PixelFormat) -> Result<Frame, CameraError> {
+        match format {
+            _ if format == self.pixel_format => Ok(self.clone()),
+            PixelFormat::Rgb8 => self.to_rgb8(),
+            PixelFormat::Rgba8 => self.to_rgba8(),
+            PixelFormat::Bgra8 => self.to_bgra8(),
+            PixelFormat::Gray16
+            | PixelFormat::Z16
+            | PixelFormat::P010
+            | PixelFormat::Rgba1010102 => Err(CameraError::unsupported(format!(
+                "{} frames can't be converted to {}",
+                self.pixel_format.as_str(),
+                format.as_str()
+            ))),
+        }
+    }
+
+    /// Whether the rows follow each other without padding, i.e. `stride`
+    /// is exactly `width` pixels.
+    #[inline]
+    pub fn is_packed(&self) -> bool {
+        self.stride as u64 == self.width as u64 * self.pixel_format.bytes_per_pixel() as u64
+    }
+
+    /// Copies the frame into a new one in the same pixel format without
+    /// row padding, for consumers that assume `stride == width * bpp`.
+    pub fn to_packed(&self) -> Result<Frame, CameraError> {
+        self.check_valid()?;
+        let row_len = self.width as usize * self.pixel_format.bytes_per_pixel() as usize;
+        let rows = self.pixel_format.buffer_rows(self.height) as usize;
+        let mut data = Vec::with_capacity(row_len * rows);
+        for row in self.data.chunks(self.stride as usize).take(rows) {
+            data.extend_from_slice(&row[..row_len]);
+        }
+        Ok(self.derive_packed(data, self.pixel_format))
+    }
+
+    /// Returns the frame as is if its rows are packed already, trimming any
+    /// bytes past the last row, and a `to_packed` copy otherwise.
+    pub fn ensure_packed(mut self) -> Result<Frame, CameraError> {
+        if !self.is_packed() {
+            return self.to_packed();
+        }
+        self.check_valid()?;
+        let rows = self.pixel_format.buffer_rows(self.height) as usize;
+        self.data.truncate(self.stride as usize * rows);
+        Ok(self)
+    }
+
+    fn to_four_channel(&self, format: PixelFormat) -> Result<Frame, CameraError> {
+        self.check_valid()?;
+        if self.pixel_format == PixelFormat::P010 {
+            return self.p010_to_rgb8().to_four_channel(format);
+        }
+        let row_len = self.width as usize * self.pixel_format.bytes_per_pixel() as usize;
+        let mut data = Vec::with_capacity(self.width as usize * self.height as usize * 4);
+        for row in self
+            .data
+            .chunks(self.stride as usize)
+            .take(self.height as usize)
+        {
+            let row = &row[..row_len];
+            match self.pixel_format {
+                PixelFormat::Rgba8 | PixelFormat::Bgra8 => data.extend_from_slice(row),
+                PixelFormat::Rgb8 => row
+                    .chunks_exact(3)
+                    .for_each(|px| data.extend_from_slice(&[px[0], px[1], px[2], 0xFF])),
+                PixelFormat::Rgba1010102 => row
+                    .chunks_exact(4)
+                    .for_each(|px| data.extend_from_slice(&unpack_1010102(px))),
+                PixelFormat::Gray16 | PixelFormat::Z16 | PixelFormat::P010 => {
+                    return Err(CameraError::unsupported(format!(
+                        "{} frames have no {} form",
+                        self.pixel_format.as_str(),
+                        format.as_str()
+                    )));
+                },
+            }
+        }
+        if (self.pixel_format == PixelFormat::Bgra8) != (format == PixelFormat::Bgra8) {
+            swap_red_blue(&mut data);
+        }
+        Ok(self.derive_packed(data, format))
+    }
+
+    /// Decodes a valid P010 frame, one 2x2 block (sharing a Cb/Cr pair) at
+    /// a time.
+    fn p010_to_rgb8(&self) -> Frame {
+        let (kr, kb) = match self.colorimetry.matrix.unwrap_or(ColorMatrix::Bt709) {
+            ColorMatrix::Bt601 => (0.299, 0.114),
+            ColorMatrix::Bt709 => (0.2126, 0.0722),
+            ColorMatrix::Bt2020 => (0.2627, 0.0593),
+        };
+        let kg = 1.0 - kr - kb;
+        // Offset and scale of the 10-bit luma and chroma codes.
+        let (y_min, y_span, c_span) = match self.colorimetry.range.unwrap_or(ColorRange::Limited) {
+            ColorRange::Limited => (64.0, 876.0, 896.0),
+            ColorRange::Full => (0.0, 1023.0, 1023.0),
+        };
+        let (width, height) = (self.width as usize, self.height as usize);
+        let stride = self.stride as usize;
+        let sample =
+            |at: usize| (u16::from_le_bytes([self.data[at], self.data[at + 1]]) >> 6) as f32;
+        let to_u8 = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+        let chroma = stride * height;
+        let mut data = vec![0u8; width * height * 3];
+        for y in 0..height {
+            let uv_row = chroma + (y / 2) * stride;
+            for x in 0..width {
+                let luma = (sample(y * stride + x * 2) - y_min) / y_span;
+                let uv = uv_row + (x / 2) * 4;
+                let cb = (sample(uv) - 512.0) / c_span;
+                let cr = (sample(uv + 2) - 512.0) / c_span;
+                let r = luma + 2.0 * (1.0 - kr) * cr;
+                let b = luma + 2.0 * (1.0 - kb) * cb;
+                let g = (luma - kr * r - kb * b) / kg;
+                data[(y * width + x) * 3..][..3].copy_from_slice(&[to_u8(r), to_u8(g), to_u8(b)]);
+            }
+        }
+        let mut frame = self.derive_packed(data, PixelFormat::Rgb8);
+        frame.colorimetry.range = Some(ColorRange::Full);
+        frame
+    }
+
+    fn derive_packed(&self, data: Vec<u8>, pixel_format: PixelFormat) -> Frame {
+        let mut frame = Frame::new(
+            Bytes::from(data),
+            self.width,
+            self.height,
+            self.width * pixel_format.bytes_per_pixel(),
+            pixel_format,
+        )
+        .with_stream(self.stream)
+        .with_colorimetry(self.colorimetry)
+        .with_timestamp_ns(self.timestamp_ns);
+        frame.monotonic_ns = self.monotonic_ns;
+        frame.metadata = self.metadata.clone();
+        frame
+    }
+}
+
+/// Converts one row of `format` pixels into packed RGB8; `dst` holds
+/// exactly `3 * pixels` bytes.
+fn row_to_rgb8(src: &[u8], format: PixelFormat, dst: &mut [u8]) {
+    match format {
+        PixelFormat::Rgb8 => dst.copy_from_slice(src),
+        PixelFormat::Rgba8 => drop_alpha(src, dst, false),
+        PixelFormat::Bgra8 => drop_alpha(src, dst, true),
+        PixelFormat::Gray16 | PixelFormat::Z16 | PixelFormat::P010 => {
+            for (px, s) in dst.chunks_exact_mut(3).zip(src.chunks_exact(2)) {
+                px.fill(s[1]);
+            }
+        },
+        PixelFormat::Rgba1010102 => {
+            for (px, s) in dst.chunks_exact_mut(3).zip(src.chunks_exact(4)) {
+                px.copy_from_slice(&unpack_1010102(s)[..3]);
+            }
+        },
+    }
+}
+
+/// The top 8 bits of each component of an RGBA1010102 pixel, as RGBA8.
+#[inline]
+pub(crate) fn unpack_1010102(px: &[u8]) -> [u8; 4] {
+    let v = u32::from_le_bytes([px[0], px[1], px[2], px[3]]);
+    [
+        (v >> 2) as u8,
+        (v >> 12) as u8,
+        (v >> 22) as u8,
+        (v >> 30) as u8 * 0x55,
+    ]
+}
+
+/// Swaps the red and blue channels of packed 4-byte pixels in place, turning
+/// BGRA into RGBA and back.
+pub(crate) fn swap_red_blue(data: &mut [u8]) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if std::is_x86_feature_detected!("ssse3") {
+        // SAFETY: SSSE3 support was just checked.
+        let done = unsafe { x86::swap_red_blue(data) };
+        return scalar_swap_red_blue(&mut data[done..]);
+    }
+    #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+    {
+        let done = neon::swap_red_blue(data);
+        return scalar_swap_red_blue(&mut data[done..]);
+    }
+    #[allow(unreachable_code)]
+    scalar_swap_red_blue(data)
+}
+
+fn drop_alpha(src: &[u8], dst: &mut [u8], swap: bool) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if std::is_x86_feature_detected!("ssse3") {
+        // SAFETY: SSSE3 support was just checked.
+        let px = unsafe { x86::drop_alpha(src, dst, swap) };
+        return scalar_drop_alpha(&src[px * 4..], &mut dst[px * 3..], swap);
+    }
+    #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+    {
+        let px = neon::drop_alpha(src, dst, swap);
+        return scalar_drop_alpha(&src[px * 4..], &mut dst[px * 3..], swap);
+    }
+    #[allow(unreachable_code)]
+    scalar_drop_alpha(src, dst, swap)
+}
+
+fn scalar_drop_alpha(src: &[u8], dst: &mut [u8], swap: bool) {
+    let (r, b) = if swap { (2, 0) } else { (0, 2) };
+    for (d, s) in dst.chunks_exact_mut(3).zip(src.chunks_exact(4)) {
+        d[0] = s[r];
+        d[1] = s[1];
+        d[2] = s[b];
+    }
+}
+
+fn scalar_swap_red_blue(data: &mut [u8]) {
+    data.chunks_exact_mut(4).for_each(|px| px.swap(0, 2));
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod x86 {
+    use core::arch::x86_64::*;
+
+    /// Converts 16 pixels at a time, returning how many were done.
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn drop_alpha(src: &[u8], dst: &mut [u8], swap: bool) -> usize {
+        let pixels = (src.len() / 4).min(dst.len() / 3) / 16 * 16;
+        // Packs four pixels into the low 12 bytes, zeroing the rest.
+        let mask = if swap {
+            _mm_setr_epi8(2, 1, 0, 6, 5, 4, 10, 9, 8, 14, 13, 12, -1, -1, -1, -1)
+        } else {
+            _mm_setr_epi8(0, 1, 2, 4, 5, 6, 8, 9, 10, 12, 13, 14, -1, -1, -1, -1)
+        };
+        for i in (0..pixels).step_by(16) {
+            // SAFETY: `pixels` keeps all 64 source and 48 destination bytes
+            // of this block in bounds; the loads and stores are unaligned.
+            unsafe {
+                let s = src.as_ptr().add(i * 4) as *const __m128i;
+                let a = _mm_shuffle_epi8(_mm_loadu_si128(s), mask);
+                let b = _mm_shuffle_epi8(_mm_loadu_si128(s.add(1)), mask);
+                let c = _mm_shuffle_epi8(_mm_loadu_si128(s.add(2)), mask);
+                let d = _mm_shuffle_epi8(_mm_loadu_si128(s.add(3)), mask);
+                let out = dst.as_mut_ptr().add(i * 3) as *mut __m128i;
+                _mm_storeu_si128(out, _mm_or_si128(a, _mm_slli_si128(b, 12)));
+                _mm_storeu_si128(
+                    out.add(1),
+                    _mm_or_si128(_mm_srli_si128(b, 4), _mm_slli_si128(c, 8)),
+                );
+                _mm_storeu_si128(
+                    out.add(2),
+                    _mm_or_si128(_mm_srli_si128(c, 8), _mm_slli_si128(d, 4)),
+                );
+            }
+        }
+        pixels
+    }
+
+    /// Swaps four pixels at a time, returning how many bytes were done.
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn swap_red_blue(data: &mut [u8]) -> usize {
+        let bytes = data.len() / 16 * 16;
+        let mask = _mm_setr_epi8(2, 1, 0, 3, 6, 5, 4, 7, 10, 9, 8, 11, 14, 13, 12, 15);
+        for i in (0..bytes).step_by(16) {
+            // SAFETY: `bytes` keeps this 16-byte block in bounds.
+            unsafe {
+                let p = data.as_mut_ptr().add(i) as *mut __m128i;
+                _mm_storeu_si128(p, _mm_shuffle_epi8(_mm_loadu_si128(p), mask));
+            }
+        }
+        bytes
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+mod neon {
+    use core::arch::aarch64::*;
+
+    /// Converts 16 pixels at a time, returning how many were done.
+    pub(super) fn drop_alpha(src: &[u8], dst: &mut [u8], swap: bool) -> usize {
+        let pixels = (src.len() / 4).min(dst.len() / 3) / 16 * 16;
+        for i in (0..pixels).step_by(16) {
+            // SAFETY: NEON is baseline on aarch64, and `pixels` keeps the 64
+            // source and 48 destination bytes of this block in bounds.
+            unsafe {
+                let px = vld4q_u8(src.as_ptr().add(i * 4));
+                let rgb = if swap {
+                    uint8x16x3_t(px.2, px.1, px.0)
+                } else {
+                    uint8x16x3_t(px.0, px.1, px.2)
+                };
+                vst3q_u8(dst.as_mut_ptr().add(i * 3), rgb);
+            }
+        }
+        pixels
+    }
+
+    /// Swaps 16 pixels at a time, returning how many bytes were done.
+    pub(super) fn swap_red_blue(data: &mut [u8]) -> usize {
+        let bytes = data.len() / 64 * 64;
+        for i in (0..bytes).step_by(64) {
+            // SAFETY: as above, for one 64-byte block.
+            unsafe {
+                let p = data.as_mut_ptr().add(i);
+                let px = vld4q_u8(p);
+                vst4q_u8(p, uint8x16x4_t(px.2, px.1, px.0, px.3));
+            }
+        }
+        bytes
+    }
+}
+
+#[cfg(all(feature = "simd", target_vendor = "apple"))]
+mod vimage {
+    use crate::shared::{Frame, PixelFormat};
+    use core::ffi::c_void;
+
+    #[repr(C)]
+    struct Buffer {
+        data: *mut c_void,
+        height: usize,
+        width: usize,
+        row_bytes: usize,
+    }
+
+    #[link(name = "Accelerate", kind = "framework")]
+    unsafe extern "C" {
+        fn vImageConvert_BGRA8888toRGB888(
+            src: *const Buffer,
+            dst: *const Buffer,
+            flags: u32,
+        ) -> isize;
+        fn vImageConvert_RGBA8888toRGB888(
+            src: *const Buffer,
+            dst: *const Buffer,
+            flags: u32,
+        ) -> isize;
+    }
+
+    /// Converts a valid BGRA or RGBA frame, honouring its stride. Returns
+    /// `false` for other formats, or if vImage fails.
+    pub(super) fn to_rgb8(frame: &Frame, dst: &mut [u8]) -> bool {
+        let convert = match frame.pixel_format {
+            PixelFormat::Bgra8 => vImageConvert_BGRA8888toRGB888,
+            PixelFormat::Rgba8 => vImageConvert_RGBA8888toRGB888,
+            _ => return false,
+        };
+        let src = Buffer {
+            data: frame.data.as_ptr() as *mut c_void,
+            height: frame.height as usize,
+            width: frame.width as usize,
+            row_bytes: frame.stride as usize,
+        };
+        let out = Buffer {
+            data: dst.as_mut_ptr() as *mut c_void,
+            height: frame.height as usize,
+            width: frame.width as usize,
+            row_bytes: frame.width as usize * 3,
+        };
+        // SAFETY: the frame was validated, so both buffers span the stated
+        // rows; vImage only reads the source.
+        unsafe { convert(&src, &out, 0) == 0 }
+    }
+}
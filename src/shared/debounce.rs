@@ -0,0 +1,282 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{CameraError, Frame, wall_clock_ns};
+use core::{str::FromStr, time::Duration};
+use image_hasher::{HashAlg, Hasher, HasherConfig, ImageHash};
+use std::time::Instant;
+
+/// Frames are box-scaled to at most this width before hashing by default;
+/// perceptual hashes only look at a few dozen pixels, so full-size input is
+/// wasted work.
+pub const DEFAULT_PRESCALE_WIDTH: u32 = 256;
+
+/// Perceptual hash algorithm used to compare frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebounceAlg {
+    Mean,
+    Median,
+    #[default]
+    Gradient,
+    VertGradient,
+    DoubleGradient,
+    Blockhash,
+}
+
+impl DebounceAlg {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DebounceAlg::Mean => "mean",
+            DebounceAlg::Median => "median",
+            DebounceAlg::Gradient => "gradient",
+            DebounceAlg::VertGradient => "vert-gradient",
+            DebounceAlg::DoubleGradient => "double-gradient",
+            DebounceAlg::Blockhash => "blockhash",
+        }
+    }
+
+    fn hash_alg(self) -> HashAlg {
+        match self {
+            DebounceAlg::Mean => HashAlg::Mean,
+            DebounceAlg::Median => HashAlg::Median,
+            DebounceAlg::Gradient => HashAlg::Gradient,
+            DebounceAlg::VertGradient => HashAlg::VertGradient,
+            DebounceAlg::DoubleGradient => HashAlg::DoubleGradient,
+            DebounceAlg::Blockhash => HashAlg::Blockhash,
+        }
+    }
+}
+
+impl FromStr for DebounceAlg {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "mean" => Ok(DebounceAlg::Mean),
+            "median" => Ok(DebounceAlg::Median),
+            "gradient" => Ok(DebounceAlg::Gradient),
+            "vert-gradient" | "vertical-gradient" => Ok(DebounceAlg::VertGradient),
+            "double-gradient" => Ok(DebounceAlg::DoubleGradient),
+            "blockhash" => Ok(DebounceAlg::Blockhash),
+            other => Err(CameraError::invalid_config(format!(
+                "unknown hash algorithm '{other}' (expected mean, median, gradient, \
+                 vert-gradient, double-gradient or blockhash)"
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DebounceConfig {
+    pub alg: DebounceAlg,
+    /// Hash width and height in bits (8 gives a 64-bit hash).
+    pub hash_size: u32,
+    /// Frames whose hash differs from the last emitted one by fewer bits
+    /// than this are suppressed; 0 disables the similarity check.
+    pub distance: u32,
+    /// After a frame is emitted, suppress all frames for this long.
+    pub cooldown: Duration,
+    /// Frames wider than this are box-scaled down to it before hashing;
+    /// `None` hashes them at full size.
+    pub prescale: Option<u32>,
+}
+
+impl Default for DebounceConfig {
+    fn default() -> Self {
+        Self {
+            alg: DebounceAlg::Gradient,
+            hash_size: 8,
+            distance: 0,
+            cooldown: Duration::ZERO,
+            prescale: Some(DEFAULT_PRESCALE_WIDTH),
+        }
+    }
+}
+
+impl DebounceConfig {
+    pub fn with_alg(mut self, alg: DebounceAlg) -> Self {
+        self.alg = alg;
+        self
+    }
+
+    pub fn with_hash_size(mut self, size: u32) -> Self {
+        self.hash_size = size.clamp(2, 64);
+        self
+    }
+
+    pub fn with_distance(mut self, distance: u32) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Box-scales frames to at most `width` pixels wide before hashing, or
+    /// with `None` or 0 hashes them as they are.
+    pub fn with_prescale(mut self, width: Option<u32>) -> Self {
+        self.prescale = width.filter(|&w| w > 0);
+        self
+    }
+
+    /// Returns whether any suppression is configured.
+    pub fn is_enabled(&self) -> bool {
+        self.distance > 0 || !self.cooldown.is_zero()
+    }
+}
+
+/// What a [`Debouncer`] remembers about the frames it emitted, so that
+/// suppression carries across restarts instead of the first frame after
+/// one always being emitted again.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebounceState {
+    pub alg: DebounceAlg,
+    pub hash_size: u32,
+    /// Base64 perceptual hash of the last emitted frame.
+    pub hash: Option<String>,
+    /// Frames emitted so far, including by earlier runs.
+    pub emitted: u64,
+    /// When the last frame was emitted, in UTC nanoseconds; 0 if never.
+    pub timestamp_ns: u64,
+}
+
+/// Suppresses frames that look like the last emitted one, or that arrive
+/// within the cooldown after it.
+pub struct Debouncer {
+    config: DebounceConfig,
+    hasher: Hasher,
+    last_hash: Option<ImageHash>,
+    last_emit: Option<Instant>,
+    last_emit_ns: u64,
+    emitted: u64,
+}
+
+impl core::fmt::Debug for Debouncer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Debouncer")
+            .field("config", &self.config)
+            .field(
+                "last_hash",
+                &self.last_hash.as_ref().map(ImageHash::to_base64),
+            )
+            .field("last_emit", &self.last_emit)
+            .finish()
+    }
+}
+
+impl Debouncer {
+    pub fn new(config: DebounceConfig) -> Self {
+        let hasher = HasherConfig::new()
+            .hash_alg(config.alg.hash_alg())
+            .hash_size(config.hash_size, config.hash_size)
+            .to_hasher();
+        Self {
+            config,
+            hasher,
+            last_hash: None,
+            last_emit: None,
+            last_emit_ns: 0,
+            emitted: 0,
+        }
+    }
+
+    pub fn config(&self) -> &DebounceConfig {
+        &self.config
+    }
+
+    /// Computes the perceptual hash of `frame`, or `None` if it's malformed.
+    pub fn hash(&self, frame: &Frame) -> Option<ImageHash> {
+        if !frame.validate() {
+            return None;
+        }
+        let image = match self.config.prescale {
+            Some(width) if frame.width > width => {
+                let height =
+                    (frame.height as u64 * width as u64 / frame.width as u64).max(1) as u32;
+                // 8-bit frames shrink before converting, so only the
+                // thumbnail is converted; the rest convert first.
+                let small = if frame.pixel_format.bytes_per_sample() == 1 {
+                    frame.box_scale(width, height)
+                } else {
+                    frame.to_rgb8().and_then(|rgb| rgb.box_scale(width, height))
+                };
+                to_rgb(&small.ok()?)?
+            },
+            _ => to_rgb(frame)?,
+        };
+        Some(self.hasher.hash_image(&image))
+    }
+
+    /// Decides whether a frame with `hash` should be emitted at `now`, and
+    /// records it as the last emitted frame if so. Without a hash only the
+    /// cooldown applies.
+    pub fn accept(&mut self, hash: Option<&ImageHash>, now: Instant) -> bool {
+        if let Some(last) = self.last_emit
+            && now.saturating_duration_since(last) < self.config.cooldown
+        {
+            return false;
+        }
+        if self.config.distance > 0
+            && let (Some(hash), Some(prev)) = (hash, &self.last_hash)
+            && hash.dist(prev) < self.config.distance
+        {
+            return false;
+        }
+        self.force(hash, now);
+        true
+    }
+
+    /// Records a frame with `hash` as emitted at `now` whatever the
+    /// distance and cooldown, e.g. for one a user asked for; later frames
+    /// are compared with it.
+    pub fn force(&mut self, hash: Option<&ImageHash>, now: Instant) {
+        if let Some(hash) = hash {
+            self.last_hash = Some(hash.clone());
+        }
+        self.last_emit = Some(now);
+        self.last_emit_ns = wall_clock_ns();
+        self.emitted += 1;
+    }
+
+    pub fn reset(&mut self) {
+        self.last_hash = None;
+        self.last_emit = None;
+        self.last_emit_ns = 0;
+    }
+
+    /// The last emitted frame's hash and the emit count, for persisting.
+    pub fn state(&self) -> DebounceState {
+        DebounceState {
+            alg: self.config.alg,
+            hash_size: self.config.hash_size,
+            hash: self.last_hash.as_ref().map(ImageHash::to_base64),
+            emitted: self.emitted,
+            timestamp_ns: self.last_emit_ns,
+        }
+    }
+
+    /// Picks up where `state` left off: its hash suppresses look-alike
+    /// frames, and its timestamp the rest of the cooldown. A hash made
+    /// with another algorithm or size can't be compared and is ignored.
+    pub fn restore(&mut self, state: &DebounceState) {
+        self.emitted = state.emitted;
+        if state.alg == self.config.alg && state.hash_size == self.config.hash_size {
+            self.last_hash = state
+                .hash
+                .as_deref()
+                .and_then(|h| ImageHash::from_base64(h).ok());
+        }
+        if state.timestamp_ns != 0 {
+            let ago = Duration::from_nanos(wall_clock_ns().saturating_sub(state.timestamp_ns));
+            self.last_emit = Instant::now().checked_sub(ago);
+            self.last_emit_ns = state.timestamp_ns;
+        }
+    }
+}
+
+/// Converts a valid frame of any pixel format into an RGB image.
+fn to_rgb(frame: &Frame) -> Option<image::RgbImage> {
+    let rgb = frame.to_rgb8().ok()?;
+    image::RgbImage::from_raw(rgb.width, rgb.height, rgb.data.into())
+}
@@ -0,0 +1,323 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The controls a V4L2 device offers (brightness, exposure, focus, …), as
+//! `VIDIOC_QUERYCTRL` enumerates them, with their ranges, defaults and
+//! current values, and locking its automatic exposure and white balance.
+
+#[cfg(target_os = "linux")]
+use crate::shared::{AutoControl, CameraError};
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlKind {
+    Integer,
+    Boolean,
+    Menu,
+    Button,
+    Integer64,
+    String,
+    Bitmask,
+    IntegerMenu,
+}
+
+impl ControlKind {
+    /// The kind of a `V4L2_CTRL_TYPE_*` code, if it's a control rather than
+    /// a class heading or a compound type.
+    pub fn from_v4l2(code: u32) -> Option<Self> {
+        Some(match code {
+            1 => ControlKind::Integer,
+            2 => ControlKind::Boolean,
+            3 => ControlKind::Menu,
+            4 => ControlKind::Button,
+            5 => ControlKind::Integer64,
+            7 => ControlKind::String,
+            8 => ControlKind::Bitmask,
+            9 => ControlKind::IntegerMenu,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ControlKind::Integer => "int",
+            ControlKind::Boolean => "bool",
+            ControlKind::Menu => "menu",
+            ControlKind::Button => "button",
+            ControlKind::Integer64 => "int64",
+            ControlKind::String => "str",
+            ControlKind::Bitmask => "bitmask",
+            ControlKind::IntegerMenu => "intmenu",
+        }
+    }
+
+    /// Whether the control's values are named by `VIDIOC_QUERYMENU`.
+    pub fn has_menu(&self) -> bool {
+        matches!(self, ControlKind::Menu | ControlKind::IntegerMenu)
+    }
+}
+
+impl fmt::Display for ControlKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CameraControl {
+    /// The `V4L2_CID_*` id.
+    pub id: u32,
+    /// The name as `v4l2-ctl` spells it, e.g. `exposure_auto`.
+    pub name: String,
+    /// The name the driver reports, e.g. `Exposure, Auto`.
+    pub label: String,
+    pub kind: ControlKind,
+    pub minimum: i64,
+    pub maximum: i64,
+    pub step: i64,
+    pub default: i64,
+    /// The current value, for controls that can be read.
+    pub value: Option<i64>,
+    /// The `(index, name)` of each menu entry the driver offers.
+    pub menu: Vec<(i64, String)>,
+    pub read_only: bool,
+    /// Whether the control has no effect right now, e.g. manual exposure
+    /// while auto exposure is on.
+    pub inactive: bool,
+}
+
+/// Spells a control's label the way `v4l2-ctl` does: lowercase, with every
+/// run of other characters as one `_`, e.g. `Focus, Absolute` as
+/// `focus_absolute`.
+pub fn control_name(label: &str) -> String {
+    let mut name = String::with_capacity(label.len());
+    for c in label.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    if name.ends_with('_') {
+        name.pop();
+    }
+    name
+}
+
+/// Lists the controls of the V4L2 device at `path`, e.g. `/dev/video0`.
+#[cfg(target_os = "linux")]
+pub fn v4l2_controls(path: &str) -> Result<Vec<CameraControl>, CameraError> {
+    use std::{fs::OpenOptions, os::fd::AsRawFd, os::unix::fs::OpenOptionsExt};
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .map_err(|e| CameraError::driver("opening the device to query its controls", e))?;
+    let fd = file.as_raw_fd();
+
+    let mut controls = Vec::new();
+    let mut query = sys::QueryCtrl {
+        id: sys::V4L2_CTRL_FLAG_NEXT_CTRL,
+        ..Default::default()
+    };
+    while unsafe { libc::ioctl(fd, sys::VIDIOC_QUERYCTRL as _, &mut query) } == 0 {
+        let id = query.id;
+        query.id |= sys::V4L2_CTRL_FLAG_NEXT_CTRL;
+
+        let Some(kind) = ControlKind::from_v4l2(query.r#type) else {
+            continue;
+        };
+        if query.flags & sys::V4L2_CTRL_FLAG_DISABLED != 0 {
+            continue;
+        }
+        let end = query.name.iter().position(|&b| b == 0).unwrap_or(32);
+        let label = String::from_utf8_lossy(&query.name[..end]).into_owned();
+
+        let write_only = query.flags & sys::V4L2_CTRL_FLAG_WRITE_ONLY != 0;
+        let value = match kind {
+            ControlKind::Button | ControlKind::Integer64 | ControlKind::String => None,
+            _ if write_only => None,
+            _ => {
+                let mut control = sys::Control { id, value: 0 };
+                let ok = unsafe { libc::ioctl(fd, sys::VIDIOC_G_CTRL as _, &mut control) } == 0;
+                ok.then_some(control.value as i64)
+            },
+        };
+
+        let mut menu = Vec::new();
+        if kind.has_menu() {
+            for index in query.minimum..=query.maximum {
+                let mut item = sys::QueryMenu {
+                    id,
+                    index: index as u32,
+                    ..Default::default()
+                };
+                // Drivers may leave gaps in a menu.
+                if unsafe { libc::ioctl(fd, sys::VIDIOC_QUERYMENU as _, &mut item) } != 0 {
+                    continue;
+                }
+                let name = if kind == ControlKind::IntegerMenu {
+                    i64::from_ne_bytes(item.name[..8].try_into().unwrap_or_default()).to_string()
+                } else {
+                    let end = item.name.iter().position(|&b| b == 0).unwrap_or(32);
+                    String::from_utf8_lossy(&item.name[..end]).into_owned()
+                };
+                menu.push((index as i64, name));
+            }
+        }
+
+        controls.push(CameraControl {
+            id,
+            name: control_name(&label),
+            label,
+            kind,
+            minimum: query.minimum as i64,
+            maximum: query.maximum as i64,
+            step: query.step as i64,
+            default: query.default_value as i64,
+            value,
+            menu,
+            read_only: query.flags & sys::V4L2_CTRL_FLAG_READ_ONLY != 0,
+            inactive: query.flags & sys::V4L2_CTRL_FLAG_INACTIVE != 0,
+        });
+    }
+    Ok(controls)
+}
+
+/// Holds the automatic `control` of the V4L2 device at `path` at its
+/// current values (`locked`), or lets it adjust again. Drivers with
+/// `V4L2_CID_3A_LOCK` hold the automatic control; elsewhere, as on UVC
+/// cameras, the automatic control is switched to manual with the value it
+/// last chose (the exposure time or white balance temperature) written
+/// back, and unlocking restores the automatic mode the driver defaults to.
+#[cfg(target_os = "linux")]
+pub fn v4l2_lock_auto(path: &str, control: AutoControl, locked: bool) -> Result<(), CameraError> {
+    use std::{fs::OpenOptions, os::fd::AsRawFd, os::unix::fs::OpenOptionsExt};
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .map_err(|e| CameraError::driver("opening the device to lock its controls", e))?;
+    let fd = file.as_raw_fd();
+    let get = |id: u32| {
+        let mut control = sys::Control { id, value: 0 };
+        let ok = unsafe { libc::ioctl(fd, sys::VIDIOC_G_CTRL as _, &mut control) } == 0;
+        ok.then_some(control.value)
+    };
+    let set = |id: u32, value: i32| {
+        let mut control = sys::Control { id, value };
+        if unsafe { libc::ioctl(fd, sys::VIDIOC_S_CTRL as _, &mut control) } != 0 {
+            return Err(CameraError::driver(
+                "setting a camera control",
+                std::io::Error::last_os_error(),
+            ));
+        }
+        Ok(())
+    };
+
+    let (bit, auto, auto_off, value) = match control {
+        AutoControl::Exposure => (
+            sys::V4L2_LOCK_EXPOSURE,
+            sys::V4L2_CID_EXPOSURE_AUTO,
+            sys::V4L2_EXPOSURE_MANUAL,
+            sys::V4L2_CID_EXPOSURE_ABSOLUTE,
+        ),
+        AutoControl::WhiteBalance => (
+            sys::V4L2_LOCK_WHITE_BALANCE,
+            sys::V4L2_CID_AUTO_WHITE_BALANCE,
+            0,
+            sys::V4L2_CID_WHITE_BALANCE_TEMPERATURE,
+        ),
+    };
+    if let Some(locks) = get(sys::V4L2_CID_3A_LOCK) {
+        let locks = if locked { locks | bit } else { locks & !bit };
+        return set(sys::V4L2_CID_3A_LOCK, locks);
+    }
+    if get(auto).is_none() {
+        return Err(CameraError::unsupported(format!(
+            "{path} has no auto {control} control to lock"
+        )));
+    }
+    if !locked {
+        let mut query = sys::QueryCtrl {
+            id: auto,
+            ..Default::default()
+        };
+        if unsafe { libc::ioctl(fd, sys::VIDIOC_QUERYCTRL as _, &mut query) } != 0 {
+            return Err(CameraError::driver(
+                "querying a camera control",
+                std::io::Error::last_os_error(),
+            ));
+        }
+        return set(auto, query.default_value);
+    }
+    // Read before switching, which some drivers reset the value on.
+    let current = get(value);
+    set(auto, auto_off)?;
+    match current {
+        Some(current) => set(value, current),
+        None => Ok(()),
+    }
+}
+
+/// The parts of `<linux/videodev2.h>` the query and the locks need.
+#[cfg(target_os = "linux")]
+mod sys {
+    pub const VIDIOC_G_CTRL: u32 = 0xc008_561b;
+    pub const VIDIOC_S_CTRL: u32 = 0xc008_561c;
+    pub const VIDIOC_QUERYCTRL: u32 = 0xc044_5624;
+    pub const VIDIOC_QUERYMENU: u32 = 0xc02c_5625;
+
+    pub const V4L2_CTRL_FLAG_DISABLED: u32 = 0x0001;
+    pub const V4L2_CTRL_FLAG_READ_ONLY: u32 = 0x0004;
+    pub const V4L2_CTRL_FLAG_INACTIVE: u32 = 0x0010;
+    pub const V4L2_CTRL_FLAG_WRITE_ONLY: u32 = 0x0040;
+    pub const V4L2_CTRL_FLAG_NEXT_CTRL: u32 = 0x8000_0000;
+
+    pub const V4L2_CID_AUTO_WHITE_BALANCE: u32 = 0x0098_090c;
+    pub const V4L2_CID_WHITE_BALANCE_TEMPERATURE: u32 = 0x0098_091a;
+    pub const V4L2_CID_EXPOSURE_AUTO: u32 = 0x009a_0901;
+    pub const V4L2_CID_EXPOSURE_ABSOLUTE: u32 = 0x009a_0902;
+    pub const V4L2_CID_3A_LOCK: u32 = 0x009a_091b;
+
+    pub const V4L2_EXPOSURE_MANUAL: i32 = 1;
+    pub const V4L2_LOCK_EXPOSURE: i32 = 1 << 0;
+    pub const V4L2_LOCK_WHITE_BALANCE: i32 = 1 << 1;
+
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct QueryCtrl {
+        pub id: u32,
+        pub r#type: u32,
+        pub name: [u8; 32],
+        pub minimum: i32,
+        pub maximum: i32,
+        pub step: i32,
+        pub default_value: i32,
+        pub flags: u32,
+        pub reserved: [u32; 2],
+    }
+
+    /// `name` doubles as the `__s64 value` of integer menus.
+    #[repr(C, packed)]
+    #[derive(Default)]
+    pub struct QueryMenu {
+        pub id: u32,
+        pub index: u32,
+        pub name: [u8; 32],
+        pub reserved: u32,
+    }
+
+    #[repr(C)]
+    pub struct Control {
+        pub id: u32,
+        pub value: i32,
+    }
+
+    const _: () = assert!(size_of::<QueryCtrl>() == 68);
+    const _: () = assert!(size_of::<QueryMenu>() == 44);
+    const _: () = assert!(size_of::<Control>() == 8);
+}
@@ -0,0 +1,503 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Device lists and capture modes from `ffmpeg -list_devices true` and its
+//! relatives, which ffmpeg only prints as log lines on stderr, and from
+//! `rpicam-hello --list-cameras`. ffmpeg's layout has changed between
+//! versions: each line carries a `[context @ 0x…]` prefix (and, with
+//! `-loglevel +level`, a `[info]` one), and since ffmpeg 4.4 DirectShow tags
+//! each device `(video)` or `(audio)` instead of printing section headers.
+//! The parsers accept all of these and report what they couldn't make sense
+//! of as warnings, so an unrecognized format doesn't pass for "no cameras".
+
+use crate::shared::{CameraMode, capabilities::normalize_modes};
+
+/// A device from a `-list_devices` listing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ListedDevice {
+    /// The AVFoundation device index.
+    pub index: Option<u32>,
+    pub name: String,
+    /// The DirectShow device path (`@device_pnp_…`), which tells devices
+    /// with the same name apart.
+    pub alternative_name: Option<String>,
+}
+
+/// The devices `ffmpeg -list_devices` printed, by kind.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceList {
+    pub video: Vec<ListedDevice>,
+    pub audio: Vec<ListedDevice>,
+    /// Lines that looked like part of the listing but didn't parse, or a
+    /// note that no listing was found at all.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    None,
+    Video,
+    Audio,
+}
+
+impl DeviceList {
+    fn push(&mut self, section: Section, device: ListedDevice) {
+        match section {
+            Section::Video => self.video.push(device),
+            Section::Audio => self.audio.push(device),
+            Section::None => {},
+        }
+    }
+
+    fn finish(mut self, found_listing: bool, stderr: &str, what: &str) -> Self {
+        if !found_listing {
+            self.warnings.push(no_listing(
+                stderr,
+                &format!("{what} device list in ffmpeg's output"),
+            ));
+        }
+        self
+    }
+}
+
+/// The capture modes a device listing printed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModeList {
+    /// Largest first within each format, one entry per size.
+    pub modes: Vec<CameraMode>,
+    /// Lines that looked like modes but didn't parse, or a note that no
+    /// modes were found at all.
+    pub warnings: Vec<String>,
+}
+
+impl ModeList {
+    /// `what` names the listing and `tool` the program that printed it.
+    fn finish(mut self, found_listing: bool, output: &str, what: &str, tool: &str) -> Self {
+        if !found_listing {
+            self.warnings
+                .push(no_listing(output, &format!("{what} in {tool}'s output")));
+        } else if self.modes.is_empty() && self.warnings.is_empty() {
+            self.warnings.push(format!("the device lists no {what}"));
+        }
+        normalize_modes(&mut self.modes);
+        self
+    }
+
+    fn unrecognized(&mut self, line: &str) {
+        self.warnings
+            .push(format!("unrecognized mode line: {line}"));
+    }
+}
+
+/// Warns about output that had no recognizable listing, quoting its last
+/// line, which is usually the tool's error.
+fn no_listing(output: &str, what: &str) -> String {
+    let last = output
+        .lines()
+        .map(|line| strip_context(line).trim())
+        .rfind(|line| !line.is_empty());
+    match last {
+        Some(last) => format!("found no {what}: {last}"),
+        None => format!("found no {what}"),
+    }
+}
+
+/// Parses the output of `ffmpeg -f avfoundation -list_devices true -i ""`:
+///
+/// ```text
+/// [AVFoundation indev @ 0x7f8e4c704a80] AVFoundation video devices:
+/// [AVFoundation indev @ 0x7f8e4c704a80] [0] FaceTime HD Camera
+/// [AVFoundation indev @ 0x7f8e4c704a80] AVFoundation audio devices:
+/// [AVFoundation indev @ 0x7f8e4c704a80] [0] MacBook Pro Microphone
+/// ```
+pub fn avfoundation(stderr: &str) -> DeviceList {
+    let mut list = DeviceList::default();
+    let mut section = Section::None;
+    let mut found_listing = false;
+
+    for line in stderr.lines() {
+        let line = strip_context(line).trim();
+        if let Some(device) = avfoundation_device(line) {
+            if section == Section::None {
+                list.warnings
+                    .push(format!("device listed outside a device section: {line}"));
+            }
+            list.push(section, device);
+        } else if let Some(next) = section_header(line) {
+            section = next;
+            found_listing = true;
+        } else if section != Section::None && line.starts_with('[') {
+            list.warnings
+                .push(format!("unrecognized device list line: {line}"));
+        }
+    }
+
+    list.finish(found_listing, stderr, "AVFoundation")
+}
+
+/// `[N] NAME`.
+fn avfoundation_device(line: &str) -> Option<ListedDevice> {
+    let (index, name) = line.strip_prefix('[')?.split_once(']')?;
+    let index = index.trim().parse().ok()?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| ListedDevice {
+        index: Some(index),
+        name: name.to_string(),
+        alternative_name: None,
+    })
+}
+
+/// Parses the output of `ffmpeg -f dshow -list_devices true -i dummy`, in
+/// the sectioned layout of ffmpeg 4.3 and older:
+///
+/// ```text
+/// [dshow @ 000001e0] DirectShow video devices (some may be both video and audio devices)
+/// [dshow @ 000001e0]  "Integrated Camera"
+/// [dshow @ 000001e0]     Alternative name "@device_pnp_\\?\usb#vid_04f2&pid_b6d9…"
+/// [dshow @ 000001e0] DirectShow audio devices
+/// ```
+///
+/// or, since 4.4, with the kind after each name: `"Integrated Camera" (video)`.
+pub fn dshow(stderr: &str) -> DeviceList {
+    let mut list = DeviceList::default();
+    let mut section = Section::None;
+    let mut found_listing = false;
+    // Where the device the next "Alternative name" belongs to went.
+    let mut last: Option<Section> = None;
+
+    for line in stderr.lines() {
+        let line = strip_context(line).trim();
+        if let Some(alternative) = line.strip_prefix("Alternative name") {
+            let device = match last {
+                Some(Section::Video) => list.video.last_mut(),
+                Some(Section::Audio) => list.audio.last_mut(),
+                // Belongs to a device that was skipped.
+                Some(Section::None) => continue,
+                None => None,
+            };
+            match (device, quoted(alternative.trim())) {
+                (Some(device), Some((alternative, _))) => {
+                    device.alternative_name = Some(alternative.to_string());
+                },
+                _ => list
+                    .warnings
+                    .push(format!("unrecognized device list line: {line}")),
+            }
+        } else if let Some((name, kind)) = quoted(line) {
+            found_listing = true;
+            let kind = match kind.trim() {
+                "" if section == Section::None => {
+                    list.warnings
+                        .push(format!("device listed outside a device section: {line}"));
+                    Section::None
+                },
+                "" => section,
+                kind if kind.contains("video") => Section::Video,
+                kind if kind.contains("audio") => Section::Audio,
+                // `(none)`: neither, so nothing to capture from.
+                _ => Section::None,
+            };
+            last = Some(kind);
+            if name.is_empty() {
+                list.warnings
+                    .push(format!("unrecognized device list line: {line}"));
+                continue;
+            }
+            list.push(
+                kind,
+                ListedDevice {
+                    index: None,
+                    name: name.to_string(),
+                    alternative_name: None,
+                },
+            );
+        } else if let Some(next) = section_header(line) {
+            section = next;
+            found_listing = true;
+        }
+    }
+
+    list.finish(found_listing, stderr, "DirectShow")
+}
+
+/// Parses the output of `ffmpeg -f v4l2 -list_formats all -i /dev/videoN`,
+/// which has no frame rates:
+///
+/// ```text
+/// [video4linux2,v4l2 @ 0x5581c7c0] Compressed:       mjpeg :          Motion-JPEG : 1280x720 640x480
+/// [video4linux2,v4l2 @ 0x5581c7c0] Raw       :     yuyv422 :           YUYV 4:2:2 : 640x480 320x240
+/// [video4linux2,v4l2 @ 0x5581c7c0] Raw       :     yuv420p :     Planar YUV 4:2:0 : {32-4096, 2}x{32-2304, 2}
+/// ```
+///
+/// Devices with continuous sizes get a mode at their largest size.
+pub fn v4l2_modes(stderr: &str) -> ModeList {
+    let mut list = ModeList::default();
+    let mut found_listing = false;
+
+    for line in stderr.lines() {
+        let line = strip_context(line).trim();
+        let Some(rest) = ["Raw", "Compressed"]
+            .iter()
+            .find_map(|kind| line.strip_prefix(kind))
+            .and_then(|rest| rest.trim_start().strip_prefix(':'))
+        else {
+            continue;
+        };
+        found_listing = true;
+        // `FORMAT : DESCRIPTION [: Emulated] : SIZES`; descriptions contain
+        // colons ("YUYV 4:2:2"), but no " : ".
+        let fields: Vec<&str> = rest.split(" : ").map(str::trim).collect();
+        let (format, sizes) = match fields.as_slice() {
+            [format, _, .., sizes] if !format.is_empty() => (*format, *sizes),
+            _ => {
+                list.unrecognized(line);
+                continue;
+            },
+        };
+        if sizes.starts_with('{') {
+            match stepwise_max(sizes) {
+                Some((width, height)) => {
+                    list.modes
+                        .push(CameraMode::new(width, height).with_format(format));
+                },
+                None => list.unrecognized(line),
+            }
+            continue;
+        }
+        for size in sizes.split_whitespace() {
+            match parse_size(size) {
+                Some((width, height)) => {
+                    list.modes
+                        .push(CameraMode::new(width, height).with_format(format));
+                },
+                None => list.unrecognized(line),
+            }
+        }
+    }
+
+    list.finish(found_listing, stderr, "V4L2 formats", "ffmpeg")
+}
+
+/// The largest size of `{MIN-MAX, STEP}x{MIN-MAX, STEP}`.
+fn stepwise_max(s: &str) -> Option<(u32, u32)> {
+    let (w, h) = s.split_once("}x{")?;
+    let max = |range: &str| {
+        let range = range.trim_matches(['{', '}']);
+        let (span, _step) = range.split_once(',').unwrap_or((range, ""));
+        span.split_once('-')?.1.trim().parse().ok()
+    };
+    Some((max(w)?, max(h)?))
+}
+
+/// Parses the modes ffmpeg's AVFoundation input prints when asked for a
+/// size or rate the device doesn't offer:
+///
+/// ```text
+/// [avfoundation @ 0x7fa1c6004c00] Selected video size (1x1) is not supported by the device.
+/// [avfoundation @ 0x7fa1c6004c00] Supported modes:
+/// [avfoundation @ 0x7fa1c6004c00]   1280x720@[1.000000 30.000000]fps
+/// ```
+pub fn avfoundation_modes(stderr: &str) -> ModeList {
+    let mut list = ModeList::default();
+    let mut in_modes = false;
+    let mut found_listing = false;
+
+    for line in stderr.lines() {
+        let line = strip_context(line).trim();
+        if line.eq_ignore_ascii_case("supported modes:") {
+            in_modes = true;
+            found_listing = true;
+            continue;
+        }
+        if !in_modes {
+            continue;
+        }
+        let Some((size, rates)) = line.split_once('@') else {
+            // The listing ends at the next message.
+            in_modes = false;
+            continue;
+        };
+        let rates = rates
+            .trim()
+            .strip_prefix('[')
+            .and_then(|r| r.strip_suffix("]fps"))
+            .map(|r| {
+                r.split_whitespace()
+                    .map(str::parse::<f64>)
+                    .collect::<Result<Vec<_>, _>>()
+            });
+        match (parse_size(size), rates) {
+            (Some((width, height)), Some(Ok(rates))) if !rates.is_empty() => {
+                let min = rates.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = rates.iter().copied().fold(0.0, f64::max);
+                list.modes
+                    .push(CameraMode::new(width, height).with_fps(min, max));
+            },
+            _ => list.unrecognized(line),
+        }
+    }
+
+    list.finish(found_listing, stderr, "AVFoundation modes", "ffmpeg")
+}
+
+/// Parses the output of `ffmpeg -f dshow -list_options true -i video=NAME`:
+///
+/// ```text
+/// [dshow @ 000001e0]  Pin "Capture" (alternative pin name "0")
+/// [dshow @ 000001e0]   pixel_format=yuyv422  min s=640x480 fps=5 max s=640x480 fps=30
+/// [dshow @ 000001e0]   vcodec=mjpeg  min s=1280x720 fps=5 max s=1280x720 fps=30.0003 (tv, bt470bg/bt709/unknown, topleft)
+/// ```
+pub fn dshow_modes(stderr: &str) -> ModeList {
+    let mut list = ModeList::default();
+    let mut found_listing = false;
+
+    for line in stderr.lines() {
+        let line = strip_context(line).trim();
+        let Some((_, format)) = ["pixel_format=", "vcodec="]
+            .iter()
+            .find_map(|key| Some((key, line.strip_prefix(key)?)))
+        else {
+            found_listing |= line.starts_with("Pin ") || line.contains("device options");
+            continue;
+        };
+        found_listing = true;
+        let mut tokens = format.split_whitespace();
+        let format = tokens.next().unwrap_or_default();
+        // `min s=WxH fps=F max s=WxH fps=F`, ignoring the colorimetry after.
+        let (mut max_size, mut fps) = (None, [None, None]);
+        let mut bound = 0;
+        for token in tokens {
+            match token {
+                "min" => bound = 0,
+                "max" => bound = 1,
+                token => {
+                    if let Some(size) = token.strip_prefix("s=") {
+                        if bound == 1 {
+                            max_size = parse_size(size);
+                        }
+                    } else if let Some(rate) = token.strip_prefix("fps=") {
+                        fps[bound] = rate.parse::<f64>().ok();
+                    }
+                },
+            }
+        }
+        match (max_size, fps) {
+            (Some((width, height)), [Some(min), Some(max)]) if !format.is_empty() => {
+                list.modes.push(
+                    CameraMode::new(width, height)
+                        .with_format(format)
+                        .with_fps(min, max),
+                );
+            },
+            _ => list.unrecognized(line),
+        }
+    }
+
+    list.finish(found_listing, stderr, "DirectShow modes", "ffmpeg")
+}
+
+/// Parses camera `index`'s modes from `rpicam-hello --list-cameras`:
+///
+/// ```text
+/// 0 : imx708_wide [4608x2592 10-bit RGGB] (/base/axi/pcie@120000/rp1/i2c@88000/imx708@1a)
+///     Modes: 'SRGGB10_CSI2P' : 1536x864 [120.13 fps - (768, 432)/3072x1728 crop]
+///                              4608x2592 [14.35 fps - (0, 0)/4608x2592 crop]
+/// ```
+pub fn rpicam_modes(stdout: &str, index: u32) -> ModeList {
+    let mut list = ModeList::default();
+    let mut in_camera = false;
+    let mut found_listing = false;
+    let mut format = None;
+
+    for line in stdout.lines() {
+        let line = line.trim();
+        if let Some((camera, _)) = line.split_once(" : ")
+            && let Ok(camera) = camera.trim().parse::<u32>()
+        {
+            in_camera = camera == index;
+            found_listing |= in_camera;
+            format = None;
+            continue;
+        }
+        if !in_camera {
+            continue;
+        }
+        let mut rest = line.strip_prefix("Modes:").unwrap_or(line).trim();
+        if let Some(quoted) = rest.strip_prefix('\'')
+            && let Some((name, tail)) = quoted.split_once('\'')
+        {
+            format = Some(name.to_string());
+            rest = tail.trim().trim_start_matches(':').trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+        let parsed = rest.split_once(" [").and_then(|(size, tail)| {
+            let (width, height) = parse_size(size.trim())?;
+            let mut mode = CameraMode::new(width, height);
+            // The highest rate; the sensor goes as slow as asked.
+            mode.max_fps = Some(tail.split_whitespace().next()?.parse().ok()?);
+            Some(mode)
+        });
+        match (parsed, &format) {
+            (Some(mode), Some(format)) => list.modes.push(mode.with_format(format.clone())),
+            (Some(mode), None) => list.modes.push(mode),
+            (None, _) => list.unrecognized(line),
+        }
+    }
+
+    list.finish(
+        found_listing,
+        stdout,
+        &format!("modes for camera {index}"),
+        "rpicam-hello",
+    )
+}
+
+/// `WxH`.
+fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let (w, h) = s.trim().split_once('x')?;
+    let (w, h) = (w.parse().ok()?, h.parse().ok()?);
+    (w > 0 && h > 0).then_some((w, h))
+}
+
+/// Splits `"NAME" REST` at the last quote, as ffmpeg doesn't escape quotes
+/// inside names.
+fn quoted(s: &str) -> Option<(&str, &str)> {
+    let rest = s.strip_prefix('"')?;
+    let end = rest.rfind('"')?;
+    Some((&rest[..end], &rest[end + 1..]))
+}
+
+/// `AVFoundation video devices:`, `DirectShow audio devices`, and the like.
+fn section_header(line: &str) -> Option<Section> {
+    let line = line.to_ascii_lowercase();
+    if line.contains("video devices") {
+        Some(Section::Video)
+    } else if line.contains("audio devices") {
+        Some(Section::Audio)
+    } else {
+        None
+    }
+}
+
+/// Strips ffmpeg's leading log context and level, such as `[dshow @
+/// 000001e0]` or `[AVFoundation indev @ 0x7f8e4c704a80] [info]`, but not
+/// the `[0]` device index that follows them.
+fn strip_context(mut line: &str) -> &str {
+    loop {
+        let trimmed = line.trim_start();
+        let Some((context, rest)) = trimmed
+            .strip_prefix('[')
+            .and_then(|inner| inner.split_once(']'))
+        else {
+            return line;
+        };
+        const LEVELS: [&str; 8] = [
+            "quiet", "panic", "fatal", "error", "warning", "info", "verbose", "debug",
+        ];
+        if !context.contains(" @ ") && !LEVELS.contains(&context) {
+            return line;
+        }
+        line = rest;
+    }
+}
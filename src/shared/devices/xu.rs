@@ -0,0 +1,312 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Raw requests to UVC extension units, the vendor-defined controls
+//! (trigger mode, strobe, …) that cameras expose outside the standard
+//! control set, as `UVCIOC_CTRL_QUERY` passes them through.
+
+use crate::shared::CameraError;
+use core::{fmt, str::FromStr};
+
+/// A UVC class-specific request, by the `bRequest` code it's sent as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XuQuery {
+    SetCur,
+    GetCur,
+    GetMin,
+    GetMax,
+    GetRes,
+    GetLen,
+    GetInfo,
+    GetDef,
+}
+
+impl XuQuery {
+    pub fn code(&self) -> u8 {
+        match self {
+            XuQuery::SetCur => 0x01,
+            XuQuery::GetCur => 0x81,
+            XuQuery::GetMin => 0x82,
+            XuQuery::GetMax => 0x83,
+            XuQuery::GetRes => 0x84,
+            XuQuery::GetLen => 0x85,
+            XuQuery::GetInfo => 0x86,
+            XuQuery::GetDef => 0x87,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            XuQuery::SetCur => "set",
+            XuQuery::GetCur => "cur",
+            XuQuery::GetMin => "min",
+            XuQuery::GetMax => "max",
+            XuQuery::GetRes => "res",
+            XuQuery::GetLen => "len",
+            XuQuery::GetInfo => "info",
+            XuQuery::GetDef => "def",
+        }
+    }
+
+    /// Whether the request writes `data` to the device rather than reading
+    /// into it.
+    pub fn is_set(&self) -> bool {
+        *self == XuQuery::SetCur
+    }
+}
+
+impl fmt::Display for XuQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for XuQuery {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let s = s.strip_prefix("uvc_").unwrap_or(&s).replace(['_', '-'], "");
+        Ok(match s.as_str() {
+            "set" | "setcur" => XuQuery::SetCur,
+            "get" | "cur" | "getcur" => XuQuery::GetCur,
+            "min" | "getmin" => XuQuery::GetMin,
+            "max" | "getmax" => XuQuery::GetMax,
+            "res" | "getres" => XuQuery::GetRes,
+            "len" | "getlen" => XuQuery::GetLen,
+            "info" | "getinfo" => XuQuery::GetInfo,
+            "def" | "getdef" => XuQuery::GetDef,
+            other => {
+                return Err(CameraError::invalid_config(format!(
+                    "unknown extension unit query '{other}' (expected set, cur, min, max, res, len, info or def)"
+                )));
+            },
+        })
+    }
+}
+
+/// One request to control `selector` of extension unit `unit`. Gets read
+/// `data.len()` bytes, or as many as the control's `GET_LEN` reports when
+/// `data` is empty; sets write `data`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VendorControl {
+    pub unit: u8,
+    pub selector: u8,
+    pub query: XuQuery,
+    pub data: Vec<u8>,
+}
+
+impl VendorControl {
+    /// Reads the current value of the control.
+    pub fn get(unit: u8, selector: u8) -> Self {
+        Self {
+            unit,
+            selector,
+            query: XuQuery::GetCur,
+            data: Vec::new(),
+        }
+    }
+
+    /// Writes `data` as the control's value.
+    pub fn set(unit: u8, selector: u8, data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            unit,
+            selector,
+            query: XuQuery::SetCur,
+            data: data.into(),
+        }
+    }
+
+    pub fn with_query(mut self, query: XuQuery) -> Self {
+        self.query = query;
+        self
+    }
+}
+
+/// Spelled as `UNIT:SELECTOR:QUERY`, with `=HEX` for the data of a set.
+impl fmt::Display for VendorControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.unit, self.selector, self.query)?;
+        if self.query.is_set() {
+            write!(f, "={}", to_hex(&self.data))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `UNIT:SELECTOR[:QUERY][=HEX]`, e.g. `4:2` (read the current
+/// value), `4:2:max`, or `4:2=0100` (set). Units and selectors are decimal
+/// or `0x` hex; the data's bytes may be separated by spaces.
+impl FromStr for VendorControl {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (request, data) = match s.split_once('=') {
+            Some((request, hex)) => (request, Some(parse_hex(hex)?)),
+            None => (s, None),
+        };
+        let mut parts = request.trim().split(':');
+        let unit = parse_id(parts.next().unwrap_or(""), "unit")?;
+        let selector = parse_id(parts.next().unwrap_or(""), "selector")?;
+        let query = match parts.next() {
+            Some(query) => query.parse()?,
+            None if data.is_some() => XuQuery::SetCur,
+            None => XuQuery::GetCur,
+        };
+        if parts.next().is_some() {
+            return Err(CameraError::invalid_config(format!(
+                "expected UNIT:SELECTOR[:QUERY][=HEX], got '{s}'"
+            )));
+        }
+        let data = match (query.is_set(), data) {
+            (true, Some(data)) if !data.is_empty() => data,
+            (true, _) => {
+                return Err(CameraError::invalid_config(format!(
+                    "'{s}' sets the control but gives no =HEX data"
+                )));
+            },
+            (false, Some(_)) => {
+                return Err(CameraError::invalid_config(format!(
+                    "'{s}' reads the control ({query}) and takes no =HEX data"
+                )));
+            },
+            (false, None) => Vec::new(),
+        };
+        Ok(Self {
+            unit,
+            selector,
+            query,
+            data,
+        })
+    }
+}
+
+fn parse_id(s: &str, what: &str) -> Result<u8, CameraError> {
+    let s = s.trim();
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| {
+        CameraError::invalid_config(format!(
+            "invalid extension unit {what} '{s}' (expected 0-255)"
+        ))
+    })
+}
+
+/// Parses hex bytes like `0a01`, `0x0a01` or `0a 01`.
+pub fn parse_hex(s: &str) -> Result<Vec<u8>, CameraError> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    let invalid = || CameraError::invalid_config(format!("invalid hex data '{s}'"));
+    if !digits.len().is_multiple_of(2) || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return Err(invalid());
+    }
+    let nibble = |b: u8| (b as char).to_digit(16).unwrap_or(0) as u8;
+    Ok(digits
+        .chunks(2)
+        .map(|pair| nibble(pair[0]) << 4 | nibble(pair[1]))
+        .collect())
+}
+
+/// The bytes as lowercase hex, without separators.
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The V4L2 node of a device id (`file:/dev/video2`, `/dev/video2` or
+/// `2`), which extension unit requests go through.
+pub fn uvc_device_path(device: &str) -> Result<String, CameraError> {
+    let id = ["file:", "uvc:", "v4l2:"]
+        .iter()
+        .find_map(|prefix| device.strip_prefix(prefix))
+        .unwrap_or(device)
+        .trim();
+    if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(format!("/dev/video{id}"));
+    }
+    if id.is_empty() || id.eq_ignore_ascii_case("default") {
+        return Ok("/dev/video0".to_string());
+    }
+    if id.starts_with("/dev/") {
+        return Ok(id.to_string());
+    }
+    Err(CameraError::unsupported(format!(
+        "extension unit controls need a V4L2 device, not '{device}'"
+    )))
+}
+
+/// Sends `control` to the UVC device at `path`, returning the bytes a get
+/// read (and nothing for a set).
+#[cfg(target_os = "linux")]
+pub fn uvc_xu_query(path: &str, control: &VendorControl) -> Result<Vec<u8>, CameraError> {
+    use std::{fs::OpenOptions, os::fd::AsRawFd, os::unix::fs::OpenOptionsExt};
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .map_err(|e| CameraError::driver("opening the device for an extension unit request", e))?;
+    let fd = file.as_raw_fd();
+
+    let query = |code: u8, data: &mut [u8]| {
+        let mut request = sys::XuControlQuery {
+            unit: control.unit,
+            selector: control.selector,
+            query: code,
+            size: data.len() as u16,
+            data: data.as_mut_ptr(),
+        };
+        if unsafe { libc::ioctl(fd, sys::UVCIOC_CTRL_QUERY as _, &mut request) } == 0 {
+            Ok(())
+        } else {
+            Err(CameraError::driver(
+                "sending the extension unit request",
+                std::io::Error::last_os_error(),
+            ))
+        }
+    };
+
+    if control.query.is_set() {
+        let mut data = control.data.clone();
+        query(control.query.code(), &mut data)?;
+        return Ok(Vec::new());
+    }
+    let len = match control.query {
+        XuQuery::GetLen => 2,
+        XuQuery::GetInfo => 1,
+        _ if !control.data.is_empty() => control.data.len(),
+        _ => {
+            let mut len = [0; 2];
+            query(XuQuery::GetLen.code(), &mut len)?;
+            u16::from_le_bytes(len) as usize
+        },
+    };
+    let mut data = vec![0; len];
+    query(control.query.code(), &mut data)?;
+    Ok(data)
+}
+
+/// The parts of `<linux/uvcvideo.h>` the request needs.
+#[cfg(target_os = "linux")]
+mod sys {
+    #[repr(C)]
+    pub struct XuControlQuery {
+        pub unit: u8,
+        pub selector: u8,
+        pub query: u8,
+        pub size: u16,
+        pub data: *mut u8,
+    }
+
+    /// `_IOWR('u', 0x21, struct uvc_xu_control_query)`.
+    pub const UVCIOC_CTRL_QUERY: u32 =
+        0xc000_0000 | ((size_of::<XuControlQuery>() as u32) << 16) | (b'u' as u32) << 8 | 0x21;
+
+    #[cfg(target_pointer_width = "64")]
+    const _: () = assert!(UVCIOC_CTRL_QUERY == 0xc010_7521);
+}
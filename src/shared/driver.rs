@@ -1,10 +1,24 @@
 // This is free and unencumbered software released into the public domain.
 
-use crate::shared::{CameraError, Frame};
+use crate::shared::{
+    AutoControl, AutoLock, CameraConfig, CameraError, CameraMode, CapturePlan, ExposureCheck,
+    FocusCheck, FocusStats, Frame, FrameAnalyzer, FrameChecks, FrameTransform, FrameValidation,
+    LensCalibration, LoadGuard, LuminanceStats, Observation, Overload, Photo, PhotoFormat,
+    Pipeline, PrivacySchedule, Sidecar, SinkRate, StageTimes, ThreadScheduling, TriggerConfig,
+    TriggerTrack, Undistorter, capabilities::normalize_modes, clock::ReplayClock,
+    devices::xu::VendorControl, exposure::ExposureMonitor, load::LoadMonitor, monotonic_ns,
+    pipeline::PipelineStage,
+};
+use core::time::Duration;
+
+#[cfg(feature = "audio")]
+use crate::shared::AudioFrame;
 use std::{
     any::Any,
+    path::PathBuf,
     sync::{
-        Arc, RwLock,
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         mpsc::{Receiver, SyncSender, TrySendError, sync_channel},
     },
     thread::JoinHandle,
@@ -12,6 +26,9 @@ use std::{
 
 pub type FrameSink = Arc<dyn Fn(Frame) + Send + Sync + 'static>;
 
+/// Called once a finite source has delivered its last frame.
+pub type EndCallback = Arc<dyn Fn() + Send + Sync + 'static>;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CameraBackend {
     Android,
@@ -19,6 +36,86 @@ pub enum CameraBackend {
     Dshow,
     V4l2,
     Ffmpeg,
+    Gstreamer,
+    /// NDI sources on the network, through the NDI runtime (`ndi:NAME`).
+    Ndi,
+    /// PipeWire camera nodes, negotiated through the camera portal.
+    Pipewire,
+    /// Raspberry Pi CSI camera modules through libcamera's `rpicam-vid`.
+    Rpi,
+    /// Frames from another process's shared-memory ring (`shm:NAME`).
+    Shm,
+    /// Pure-Rust capture through nokhwa (V4L2, AVFoundation, Media Foundation).
+    Uvc,
+    Web,
+    /// Scripted frames and events for tests (`mock:SCRIPT`).
+    #[cfg(feature = "test-util")]
+    Mock,
+}
+
+impl CameraBackend {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            CameraBackend::Android => "android",
+            CameraBackend::Avf => "avf",
+            CameraBackend::Dshow => "dshow",
+            CameraBackend::V4l2 => "v4l2",
+            CameraBackend::Ffmpeg => "ffmpeg",
+            CameraBackend::Gstreamer => "gstreamer",
+            CameraBackend::Ndi => "ndi",
+            CameraBackend::Pipewire => "pipewire",
+            CameraBackend::Rpi => "rpi",
+            CameraBackend::Shm => "shm",
+            CameraBackend::Uvc => "uvc",
+            CameraBackend::Web => "web",
+            #[cfg(feature = "test-util")]
+            CameraBackend::Mock => "mock",
+        }
+    }
+
+    /// Frames the dispatcher queues for the sinks when
+    /// `CameraConfig::buffer_frames` is unset. Cameras get two, so a slow
+    /// sink sees fresh frames; decoders and browsers, which hand frames over
+    /// in bursts, get more so the bursts don't turn into drops.
+    pub const fn default_buffer_frames(self) -> usize {
+        match self {
+            CameraBackend::Ffmpeg | CameraBackend::Gstreamer => 4,
+            CameraBackend::Web => 3,
+            _ => 2,
+        }
+    }
+}
+
+impl core::fmt::Display for CameraBackend {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl core::str::FromStr for CameraBackend {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "android" => Ok(CameraBackend::Android),
+            "avf" | "avfoundation" => Ok(CameraBackend::Avf),
+            "dshow" => Ok(CameraBackend::Dshow),
+            "v4l2" => Ok(CameraBackend::V4l2),
+            "ffmpeg" => Ok(CameraBackend::Ffmpeg),
+            "gstreamer" | "gst" => Ok(CameraBackend::Gstreamer),
+            "ndi" => Ok(CameraBackend::Ndi),
+            "pipewire" => Ok(CameraBackend::Pipewire),
+            "rpi" | "csi" => Ok(CameraBackend::Rpi),
+            "shm" => Ok(CameraBackend::Shm),
+            "uvc" | "nokhwa" => Ok(CameraBackend::Uvc),
+            "web" => Ok(CameraBackend::Web),
+            #[cfg(feature = "test-util")]
+            "mock" => Ok(CameraBackend::Mock),
+            other => Err(CameraError::invalid_config(format!(
+                "unknown backend '{other}' (expected ffmpeg, gstreamer, ndi, pipewire, rpi, shm, uvc, v4l2, avf, dshow, android or web)"
+            ))),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -26,8 +123,10 @@ pub enum CameraEvent {
     Started {
         backend: CameraBackend,
     },
+    /// The dispatcher shut down; `stats` covers the whole session.
     Stopped {
         backend: CameraBackend,
+        stats: CameraStats,
     },
     FrameDropped {
         backend: CameraBackend,
@@ -36,91 +135,986 @@ pub enum CameraEvent {
         backend: CameraBackend,
         message: String,
     },
+    PipelineChanged {
+        backend: CameraBackend,
+        label: String,
+        previous_sinks: usize,
+        sinks: usize,
+    },
+    /// Capture was disabled (`active`) or re-enabled by a privacy window.
+    PrivacyChanged {
+        backend: CameraBackend,
+        active: bool,
+    },
+    /// `Camera::pause` (`paused`) or `Camera::resume` took effect.
+    PauseChanged {
+        backend: CameraBackend,
+        paused: bool,
+    },
+    /// `Camera::switch_device` moved capture to `device`.
+    DeviceChanged {
+        backend: CameraBackend,
+        device: String,
+    },
+    /// The dispatcher queue held at least `depth` of its `capacity` frames
+    /// for `after`: the sinks can't keep up with the driver, which will drop
+    /// frames once the queue is full. Reported once per such stretch.
+    Backpressure {
+        backend: CameraBackend,
+        depth: usize,
+        capacity: usize,
+        after: Duration,
+    },
+    /// No frame arrived for `after` while capturing, so the watchdog
+    /// restarted the driver.
+    Stalled {
+        backend: CameraBackend,
+        after: Duration,
+    },
+    /// The file system holding `path`, where a sink writes, has only
+    /// `available` bytes free, below `min_free`; see `Retention::sweep`.
+    LowDiskSpace {
+        backend: CameraBackend,
+        path: PathBuf,
+        available: u64,
+        min_free: u64,
+    },
+    /// `Camera::reconfigure` renegotiated the capture format.
+    FormatChanged {
+        backend: CameraBackend,
+        width: u32,
+        height: u32,
+        fps: f64,
+    },
+    /// `Camera::check_load` lowered the capture format because of `reason`;
+    /// a `FormatChanged` precedes it.
+    Downgraded {
+        backend: CameraBackend,
+        reason: Overload,
+        previous_width: u32,
+        previous_height: u32,
+        previous_fps: f64,
+        width: u32,
+        height: u32,
+        fps: f64,
+    },
+    /// `Camera::lock_auto` locked (`locked`) an automatic control at its
+    /// converged values, or handed it back to the camera.
+    AutoLockChanged {
+        backend: CameraBackend,
+        control: AutoControl,
+        locked: bool,
+    },
+    /// A finite source ran out of frames, all of which reached the sinks
+    /// before the `Camera::on_end` callbacks ran. Capture has stopped for
+    /// good, unless started again.
+    EndOfStream {
+        backend: CameraBackend,
+    },
+    /// Results from an analyzer registered with `Camera::add_analyzer`.
+    Observed {
+        backend: CameraBackend,
+        analyzer: String,
+        timestamp_ns: u64,
+        observations: Vec<Observation>,
+    },
     Error {
         backend: CameraBackend,
         error: CameraError,
     },
 }
 
+// Boxing would allocate for every frame to shrink the one `Stop`.
+#[allow(clippy::large_enum_variant)]
 pub enum FrameMsg {
     Frame(Frame),
+    /// The source has no more frames.
+    Eos,
     Stop,
 }
 
+/// Frame counters for one camera session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CameraStats {
+    /// Frames the driver handed to the dispatcher.
+    pub frames_captured: u64,
+    /// Frames the driver discarded because the dispatcher queue was full.
+    pub frames_dropped: u64,
+    /// Frames delivered to the sinks.
+    pub frames_delivered: u64,
+    /// Frames `CameraConfig::frame_validation` found malformed, whether
+    /// or not they were dropped.
+    pub frames_malformed: u64,
+    /// Delivered frames a sink couldn't keep up with and discarded, as
+    /// reported through a `DropReporter`.
+    pub frames_shed: u64,
+    /// Frames waiting in the dispatcher queue for the sinks.
+    pub queue_depth: usize,
+    /// The most frames the queue held at once this session.
+    pub queue_peak: usize,
+    /// How many frames the queue holds before the driver drops them.
+    pub queue_capacity: usize,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StatsCounters {
+    captured: AtomicU64,
+    dropped: AtomicU64,
+    delivered: AtomicU64,
+    shed: AtomicU64,
+    queued: AtomicUsize,
+    queue_peak: AtomicUsize,
+    capacity: usize,
+    /// When the queue last filled to three quarters, or 0 while it isn't.
+    backed_up_ns: AtomicU64,
+    /// Whether `Backpressure` was reported since the queue backed up.
+    backpressure_reported: AtomicBool,
+    checks: FrameChecks,
+}
+
+impl StatsCounters {
+    /// The depth from which the queue counts as backed up.
+    fn backed_up_depth(&self) -> usize {
+        (self.capacity * 3).div_ceil(4)
+    }
+
+    fn snapshot(&self) -> CameraStats {
+        CameraStats {
+            frames_captured: self.captured.load(Ordering::Relaxed),
+            frames_dropped: self.dropped.load(Ordering::Relaxed),
+            frames_delivered: self.delivered.load(Ordering::Relaxed),
+            frames_malformed: self.checks.malformed(),
+            frames_shed: self.shed.load(Ordering::Relaxed),
+            queue_depth: self.queued.load(Ordering::Relaxed),
+            queue_peak: self.queue_peak.load(Ordering::Relaxed),
+            queue_capacity: self.capacity,
+        }
+    }
+}
+
+/// The drivers' end of the frame channel; counts captured and dropped frames.
+#[derive(Clone, Debug)]
+pub struct FrameSender {
+    tx: SyncSender<FrameMsg>,
+    stats: Arc<StatsCounters>,
+}
+
+impl FrameSender {
+    /// Queues `frame` without blocking; on error the frame is discarded.
+    pub fn try_send(&self, mut frame: Frame) -> Result<(), TrySendError<()>> {
+        // Stamped capture times can't be later than the queueing.
+        frame.fill_time();
+        frame.metadata.stages.enqueued_ns = StageTimes::now();
+        // Counted first, so no sink sees a frame the stats don't include.
+        self.stats.captured.fetch_add(1, Ordering::Relaxed);
+        let depth = self.stats.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        let result = self.tx.try_send(FrameMsg::Frame(frame));
+        if result.is_ok() {
+            self.stats.queue_peak.fetch_max(depth, Ordering::Relaxed);
+            if depth >= self.stats.backed_up_depth() {
+                let _ = self.stats.backed_up_ns.compare_exchange(
+                    0,
+                    monotonic_ns().max(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
+            }
+        } else {
+            self.stats.captured.fetch_sub(1, Ordering::Relaxed);
+            self.stats.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        match result {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                Err(TrySendError::Full(()))
+            },
+            Err(TrySendError::Disconnected(_)) => Err(TrySendError::Disconnected(())),
+        }
+    }
+    /// Tells the dispatcher the source has ended, once the frames queued
+    /// before have been delivered; waits for room rather than drop it.
+    pub fn end_of_stream(&self) {
+        let _ = self.tx.send(FrameMsg::Eos);
+    }
+
+    /// Counts a frame the driver discarded itself, like one the queue had
+    /// no room for.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn count_drop(&self) {
+        self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Lets a sink that queues frames for later count the ones it had to
+/// discard, so `CameraStats::frames_shed` and `Camera::check_load` see them.
+#[derive(Clone, Debug)]
+pub struct DropReporter {
+    stats: Arc<StatsCounters>,
+}
+
+impl DropReporter {
+    pub fn report(&self, frames: u64) {
+        self.stats.shed.fetch_add(frames, Ordering::Relaxed);
+    }
+}
+
+/// How long the dispatcher queue must stay three quarters full before
+/// `Backpressure` is reported.
+const BACKPRESSURE_AFTER: Duration = Duration::from_secs(1);
+
+/// How long `Camera::stop` waits for capture threads before abandoning them.
+pub const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a burst waits for each frame, beyond its interval, before
+/// giving up.
+#[cfg(not(all(feature = "web", target_arch = "wasm32")))]
+const BURST_FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Frames skipped after each exposure change in a bracket, while the sensor
+/// settles and frames queued at the old exposure drain.
+#[cfg(not(all(feature = "web", target_arch = "wasm32")))]
+const BRACKET_SETTLE_FRAMES: usize = 3;
+
+/// Delivers the frames drivers send to the sinks, on a dispatch thread
+/// that `stop` ends and `start` runs again, with the same queue and sinks.
 pub struct Dispatcher {
     tx: SyncSender<FrameMsg>,
-    sinks: Arc<RwLock<Vec<FrameSink>>>,
+    sinks: Arc<RwLock<SinkSet>>,
+    stages: Arc<FrameStages>,
+    scheduling: ThreadScheduling,
+    /// The queue's receiving end while no dispatch thread holds it; each
+    /// thread hands it back as it exits.
+    idle: Arc<Mutex<Option<Receiver<FrameMsg>>>>,
+    running: bool,
     join: Option<JoinHandle<()>>,
 }
 
+/// Per-frame processing the dispatcher applies before delivering to sinks.
+pub(crate) struct FrameStages {
+    backend: CameraBackend,
+    events_tx: SyncSender<CameraEvent>,
+    transform: RwLock<FrameTransform>,
+    undistort: Mutex<Option<Undistorter>>,
+    exposure: Mutex<Option<ExposureMonitor>>,
+    focus: RwLock<Option<FocusCheck>>,
+    stats: Arc<StatsCounters>,
+    paused: AtomicBool,
+    /// Receives a copy of every delivered frame during a burst.
+    tap: Mutex<Option<SyncSender<Frame>>>,
+    on_end: RwLock<Vec<EndCallback>>,
+    /// Set once the source ended, until the driver is started again.
+    ended: AtomicBool,
+    looping: AtomicBool,
+    replay: Mutex<ReplayClock>,
+    telemetry: Mutex<Option<TelemetryTrack>>,
+    trigger: Mutex<Option<TriggerTrack>>,
+}
+
+/// A sidecar and when the current pass of its source began.
+struct TelemetryTrack {
+    sidecar: Sidecar,
+    first_ns: Option<u64>,
+}
+
+impl FrameStages {
+    pub(crate) fn stopped_event(&self) -> CameraEvent {
+        CameraEvent::Stopped {
+            backend: self.backend,
+            stats: self.stats.snapshot(),
+        }
+    }
+
+    /// Counts a frame leaving the queue, reporting `Backpressure` once the
+    /// queue stayed backed up for `BACKPRESSURE_AFTER`.
+    fn dequeued(&self) {
+        let stats = &self.stats;
+        let depth = stats.queued.fetch_sub(1, Ordering::Relaxed);
+        if depth < stats.backed_up_depth() {
+            stats.backed_up_ns.store(0, Ordering::Relaxed);
+            stats.backpressure_reported.store(false, Ordering::Relaxed);
+            return;
+        }
+        let since_ns = stats.backed_up_ns.load(Ordering::Relaxed);
+        let after = Duration::from_nanos(monotonic_ns().saturating_sub(since_ns));
+        if since_ns != 0
+            && after >= BACKPRESSURE_AFTER
+            && !stats.backpressure_reported.swap(true, Ordering::Relaxed)
+        {
+            let _ = self.events_tx.try_send(CameraEvent::Backpressure {
+                backend: self.backend,
+                depth,
+                capacity: stats.capacity,
+                after,
+            });
+        }
+    }
+
+    fn process(&self, mut frame: Frame) -> Frame {
+        frame.fill_time();
+        if self.looping.load(Ordering::Relaxed) {
+            self.replay
+                .lock()
+                .unwrap_or_else(|p| p.into_inner())
+                .rebase(&mut frame);
+        }
+        if let Some(track) = self
+            .telemetry
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .as_mut()
+        {
+            let first_ns = *track.first_ns.get_or_insert(frame.monotonic_ns);
+            let offset = Duration::from_nanos(frame.monotonic_ns.saturating_sub(first_ns));
+            frame.metadata.telemetry = track.sidecar.at(offset).cloned();
+        }
+        if let Some(track) = self
+            .trigger
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .as_mut()
+        {
+            frame.metadata.trigger = Some(track.mark(frame.monotonic_ns));
+        }
+        if let Some(undistorter) = self
+            .undistort
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .as_mut()
+        {
+            // Like transforms, malformed frames pass through untouched.
+            if let Ok(undistorted) = undistorter.apply(&frame) {
+                frame = undistorted;
+            }
+        }
+        let transform = *self.transform.read().unwrap_or_else(|p| p.into_inner());
+        // Malformed frames can't be transformed; pass them through untouched.
+        let mut frame = if transform.is_identity() {
+            frame
+        } else {
+            transform.apply(frame.clone()).unwrap_or(frame)
+        };
+
+        let mut exposure = self.exposure.lock().unwrap_or_else(|p| p.into_inner());
+        if let Some(monitor) = exposure.as_mut()
+            && let Some(stats) = LuminanceStats::compute(&frame, monitor.sample_step())
+        {
+            if let Some(message) = monitor.observe(&stats) {
+                let _ = self.events_tx.try_send(CameraEvent::Warning {
+                    backend: self.backend,
+                    message,
+                });
+            }
+            frame.metadata.luminance = Some(stats);
+        }
+        if let Some(check) = *self.focus.read().unwrap_or_else(|p| p.into_inner()) {
+            frame.metadata.focus = FocusStats::compute(&frame, &check);
+        }
+        frame
+    }
+}
+
 impl Dispatcher {
     pub fn new(
         capacity: usize,
         backend: CameraBackend,
         events_tx: SyncSender<CameraEvent>,
     ) -> Self {
-        let (tx, rx) = sync_channel::<FrameMsg>(capacity.max(1));
-        let sinks: Arc<RwLock<Vec<FrameSink>>> = Arc::new(RwLock::new(Vec::new()));
-        let sinks_clone = Arc::clone(&sinks);
-
-        let join = std::thread::spawn(move || {
-            let _ = events_tx.try_send(CameraEvent::Started { backend });
-
-            while let Ok(msg) = rx.recv() {
-                match msg {
-                    FrameMsg::Frame(frame) => {
-                        if let Ok(list) = sinks_clone.read() {
-                            for s in list.iter() {
-                                (s)(frame.clone());
-                            }
-                        }
-                    },
-                    FrameMsg::Stop => break,
-                }
-            }
+        Self::with_scheduling(capacity, backend, events_tx, ThreadScheduling::default())
+    }
 
-            let _ = events_tx.try_send(CameraEvent::Stopped { backend });
+    /// Like `new`, running the dispatch thread at `scheduling`; if the OS
+    /// refuses, the thread runs as usual and a `Warning` says why.
+    pub fn with_scheduling(
+        capacity: usize,
+        backend: CameraBackend,
+        events_tx: SyncSender<CameraEvent>,
+        scheduling: ThreadScheduling,
+    ) -> Self {
+        let capacity = capacity.max(1);
+        let (tx, rx) = sync_channel::<FrameMsg>(capacity);
+        let sinks = Arc::new(RwLock::new(SinkSet::default()));
+        let stages = Arc::new(FrameStages {
+            backend,
+            events_tx,
+            transform: RwLock::default(),
+            undistort: Mutex::new(None),
+            exposure: Mutex::new(None),
+            focus: RwLock::new(None),
+            stats: Arc::new(StatsCounters {
+                capacity,
+                ..StatsCounters::default()
+            }),
+            paused: AtomicBool::new(false),
+            tap: Mutex::new(None),
+            on_end: RwLock::default(),
+            ended: AtomicBool::new(false),
+            looping: AtomicBool::new(false),
+            replay: Mutex::default(),
+            telemetry: Mutex::new(None),
+            trigger: Mutex::new(None),
         });
-
-        Self {
+        let mut dispatcher = Self {
             tx,
             sinks,
-            join: Some(join),
+            stages,
+            scheduling,
+            idle: Arc::new(Mutex::new(Some(rx))),
+            running: false,
+            join: None,
+        };
+        // Can't fail: the receiver is idle.
+        let _ = dispatcher.start();
+        dispatcher
+    }
+
+    /// Runs the dispatch thread again after `stop`, delivering the frames
+    /// sent from now on to the same sinks; frames queued while stopped are
+    /// discarded. Does nothing while running, and fails while the previous
+    /// thread is still stuck in a sink.
+    pub fn start(&mut self) -> Result<(), CameraError> {
+        if self.running {
+            return Ok(());
+        }
+        let rx = self
+            .idle
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .take()
+            .ok_or_else(|| {
+                CameraError::other("the previous dispatch thread is still stuck in a frame sink")
+            })?;
+        while let Ok(msg) = rx.try_recv() {
+            if let FrameMsg::Frame(_) = msg {
+                self.stages.stats.queued.fetch_sub(1, Ordering::Relaxed);
+            }
         }
+        self.stages.stats.backed_up_ns.store(0, Ordering::Relaxed);
+        let sinks = Arc::clone(&self.sinks);
+        let stages = Arc::clone(&self.stages);
+        let idle = Arc::clone(&self.idle);
+        let backend = stages.backend;
+        let events_tx = stages.events_tx.clone();
+        let scheduling = self.scheduling;
+
+        #[cfg(not(all(feature = "web", target_arch = "wasm32")))]
+        {
+            self.join = Some(std::thread::spawn(move || {
+                if !scheduling.is_default()
+                    && let Err(err) = scheduling.apply_to_current_thread()
+                {
+                    let _ = events_tx.try_send(CameraEvent::Warning {
+                        backend,
+                        message: format!("can't schedule the dispatch thread as asked: {err}"),
+                    });
+                }
+                let _ = events_tx.try_send(CameraEvent::Started { backend });
+
+                while let Ok(msg) = rx.recv() {
+                    match msg {
+                        FrameMsg::Frame(frame) => deliver_frame(&sinks, &stages, frame),
+                        FrameMsg::Eos => end_stream(&stages),
+                        FrameMsg::Stop => break,
+                    }
+                }
+
+                *idle.lock().unwrap_or_else(|p| p.into_inner()) = Some(rx);
+                let _ = events_tx.try_send(stages.stopped_event());
+            }));
+        }
+
+        #[cfg(all(feature = "web", target_arch = "wasm32"))]
+        {
+            // The browser's event loop runs at its own priority.
+            let _ = scheduling;
+            super::drivers::web::spawn_dispatch_loop(rx, idle, sinks, stages, events_tx, backend);
+        }
+
+        self.running = true;
+        Ok(())
+    }
+
+    /// Whether the dispatch thread runs, i.e. it was started and not
+    /// stopped since.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn sender(&self) -> FrameSender {
+        FrameSender {
+            tx: self.tx.clone(),
+            stats: Arc::clone(&self.stages.stats),
+        }
+    }
+
+    pub fn stats(&self) -> CameraStats {
+        self.stages.stats.snapshot()
     }
 
-    pub fn sender(&self) -> SyncSender<FrameMsg> {
-        self.tx.clone()
+    /// Frames waiting in the queue for the sinks.
+    pub fn queue_depth(&self) -> usize {
+        self.stages.stats.queued.load(Ordering::Relaxed)
+    }
+
+    /// The most frames the queue held at once so far.
+    pub fn queue_peak(&self) -> usize {
+        self.stages.stats.queue_peak.load(Ordering::Relaxed)
+    }
+
+    pub fn drop_reporter(&self) -> DropReporter {
+        DropReporter {
+            stats: Arc::clone(&self.stages.stats),
+        }
     }
 
     pub fn add_sink(&self, sink: FrameSink) {
         if let Ok(mut g) = self.sinks.write() {
-            g.push(sink);
+            g.sinks.push(sink);
         }
     }
 
-    pub fn stop(&mut self) {
-        let _ = self.tx.try_send(FrameMsg::Stop);
-        if let Some(j) = self.join.take() {
-            let _ = j.join();
+    /// Adds a sink that receives only the frames `rate` admits, counted
+    /// from the frames delivered after it was added.
+    pub fn add_sink_with_rate(&self, sink: FrameSink, rate: SinkRate) -> Result<(), CameraError> {
+        rate.validate()?;
+        self.add_sink(rate.limit(sink));
+        Ok(())
+    }
+
+    /// Calls `callback` whenever a finite source ends, after its last frame
+    /// reached the sinks.
+    pub fn on_end(&self, callback: EndCallback) {
+        self.stages
+            .on_end
+            .write()
+            .unwrap_or_else(|p| p.into_inner())
+            .push(callback);
+    }
+
+    /// Whether the source ended since the driver last started.
+    pub fn has_ended(&self) -> bool {
+        self.stages.ended.load(Ordering::SeqCst)
+    }
+
+    /// Treats the source as endless: the end of the stream is no longer
+    /// reported, and capture times of replayed frames never go backwards.
+    pub fn set_looping(&self, enabled: bool) {
+        self.stages.looping.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.stages.looping.load(Ordering::SeqCst)
+    }
+
+    /// Attaches `sidecar`'s telemetry to frames, by time since the first
+    /// frame after each start.
+    pub fn set_sidecar(&self, sidecar: Option<Sidecar>) {
+        *self
+            .stages
+            .telemetry
+            .lock()
+            .unwrap_or_else(|p| p.into_inner()) = sidecar.map(|sidecar| TelemetryTrack {
+            sidecar,
+            first_ns: None,
+        });
+    }
+
+    /// Marks every frame from now on as a `TriggeredExposure`, counting
+    /// them from 1 (or on from where the count was, if already marking),
+    /// or stops marking them.
+    pub fn set_trigger_mode(&self, enabled: bool) {
+        let mut track = self
+            .stages
+            .trigger
+            .lock()
+            .unwrap_or_else(|p| p.into_inner());
+        if !enabled {
+            *track = None;
+        } else if track.is_none() {
+            *track = Some(TriggerTrack::default());
+        }
+    }
+
+    pub fn is_trigger_mode(&self) -> bool {
+        self.stages
+            .trigger
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .is_some()
+    }
+
+    /// Records a software trigger fired at `monotonic_ns`, for the next
+    /// frame captured after it.
+    pub fn trigger_fired(&self, monotonic_ns: u64) {
+        if let Some(track) = self
+            .stages
+            .trigger
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .as_mut()
+        {
+            track.fired(monotonic_ns);
+        }
+    }
+
+    /// Forgets that the stream ended, and when it began, as the driver
+    /// starts again from the top of the source.
+    pub(crate) fn restart_stream(&self) {
+        self.stages.ended.store(false, Ordering::SeqCst);
+        if let Some(track) = self
+            .stages
+            .telemetry
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .as_mut()
+        {
+            track.first_ns = None;
+        }
+    }
+
+    /// Replaces the pipeline under a single write lock, so each frame is
+    /// delivered either to the previous one or to `pipeline`, never a mix.
+    /// Sinks added with `add_sink` stay. Returns how many sinks the
+    /// previous pipeline had.
+    pub fn replace_pipeline(&self, pipeline: Pipeline) -> usize {
+        let mut g = self.sinks.write().unwrap_or_else(|p| p.into_inner());
+        g.pipeline
+            .replace(pipeline.into_stage())
+            .map_or(0, |previous| previous.len())
+    }
+
+    /// Sets the orientation correction applied to every frame before it
+    /// reaches the sinks.
+    pub fn set_transform(&self, transform: FrameTransform) {
+        *self
+            .stages
+            .transform
+            .write()
+            .unwrap_or_else(|p| p.into_inner()) = transform;
+    }
+
+    /// Removes the lens distortion of `calibration` from every frame, before
+    /// the transform; `None` turns it off.
+    pub fn set_undistort(&self, calibration: Option<LensCalibration>) {
+        *self
+            .stages
+            .undistort
+            .lock()
+            .unwrap_or_else(|p| p.into_inner()) = calibration.map(Undistorter::new);
+    }
+
+    /// Enables per-frame luminance statistics (attached as
+    /// `FrameMetadata::luminance`) with low-light / overexposure warnings.
+    pub fn set_exposure_check(&self, check: Option<ExposureCheck>) {
+        *self
+            .stages
+            .exposure
+            .lock()
+            .unwrap_or_else(|p| p.into_inner()) = check.map(ExposureMonitor::new);
+    }
+
+    /// Enables per-frame focus statistics, attached as
+    /// `FrameMetadata::focus`.
+    pub fn set_focus_check(&self, check: Option<FocusCheck>) {
+        *self.stages.focus.write().unwrap_or_else(|p| p.into_inner()) = check;
+    }
+
+    /// Sets what `try_send_frame` does with malformed frames.
+    pub fn set_frame_validation(&self, validation: FrameValidation) {
+        self.stages.stats.checks.set_validation(validation);
+    }
+
+    /// Checksums frames as drivers send them, warning when one changed by
+    /// delivery.
+    pub fn set_frame_checksums(&self, enabled: bool) {
+        self.stages.stats.checks.set_checksums(enabled);
+    }
+
+    /// Discards (`true`) or resumes delivering frames from the driver.
+    pub fn set_paused(&self, paused: bool) {
+        self.stages.paused.store(paused, Ordering::SeqCst);
+    }
+
+    /// Sends a copy of every frame delivered from now on to `tap`, until
+    /// replaced or cleared with `None`. Frames are dropped while it is full.
+    pub fn set_tap(&self, tap: Option<SyncSender<Frame>>) {
+        *self.stages.tap.lock().unwrap_or_else(|p| p.into_inner()) = tap;
+    }
+
+    /// Stops the dispatch thread, waiting at most `timeout` for the sinks to
+    /// return; does nothing if stopped already. Returns `false` if the
+    /// thread had to be abandoned.
+    pub fn stop(&mut self, timeout: Duration) -> bool {
+        if !self.is_running() {
+            return true;
+        }
+        self.running = false;
+        let Some(join) = self.join.take() else {
+            let _ = self.tx.try_send(FrameMsg::Stop);
+            return true;
+        };
+        let deadline = std::time::Instant::now() + timeout;
+        // The queue may be full of frames; keep offering Stop until there's room.
+        loop {
+            match self.tx.try_send(FrameMsg::Stop) {
+                Err(TrySendError::Full(_)) if std::time::Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(5));
+                },
+                _ => break,
+            }
         }
+        join_until(join, deadline)
     }
 }
 
+/// Joins `handle` if it finishes before `deadline`; otherwise detaches it.
+pub(crate) fn join_until(handle: JoinHandle<()>, deadline: std::time::Instant) -> bool {
+    while !handle.is_finished() {
+        if std::time::Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let _ = handle.join();
+    true
+}
+
+/// The sinks frames are delivered to: those added one by one, which stay,
+/// and the pipeline `Camera::apply_pipeline` swaps.
+#[derive(Default)]
+pub(crate) struct SinkSet {
+    sinks: Vec<FrameSink>,
+    pipeline: Option<PipelineStage>,
+}
+
+pub(crate) fn deliver_frame(sinks: &RwLock<SinkSet>, stages: &FrameStages, frame: Frame) {
+    stages.dequeued();
+    // Frames a backend delivers while paused, or had buffered, are stale.
+    if stages.paused.load(Ordering::SeqCst) {
+        return;
+    }
+    stages
+        .stats
+        .checks
+        .verify(&frame, &stages.events_tx, stages.backend);
+    let mut frame = stages.process(frame);
+    frame.metadata.stages.dispatched_ns = StageTimes::now();
+    stages.stats.delivered.fetch_add(1, Ordering::Relaxed);
+    if let Some(tap) = stages
+        .tap
+        .lock()
+        .unwrap_or_else(|p| p.into_inner())
+        .as_ref()
+    {
+        let _ = tap.try_send(frame.clone());
+    }
+    if let Ok(set) = sinks.read() {
+        for s in set.sinks.iter() {
+            (s)(frame.clone());
+        }
+        if let Some(pipeline) = &set.pipeline {
+            pipeline.deliver(frame);
+        }
+    }
+}
+
+/// Runs the end-of-stream callbacks, then reports `EndOfStream`.
+pub(crate) fn end_stream(stages: &FrameStages) {
+    stages.ended.store(true, Ordering::SeqCst);
+    // A looping source is replayed by `Camera::check_replay` instead.
+    if stages.looping.load(Ordering::SeqCst) {
+        return;
+    }
+    if let Ok(list) = stages.on_end.read() {
+        for callback in list.iter() {
+            callback();
+        }
+    }
+    let _ = stages.events_tx.try_send(CameraEvent::EndOfStream {
+        backend: stages.backend,
+    });
+}
+
 pub trait CameraDriver: Send {
     fn backend(&self) -> CameraBackend;
     fn start(&mut self) -> Result<(), CameraError>;
     fn stop(&mut self) -> Result<(), CameraError> {
         Ok(())
     }
+    /// Lets the driver apply as much of `transform` as the hardware supports,
+    /// returning what remains to be done in software by the dispatcher.
+    fn offload_transform(&mut self, transform: FrameTransform) -> FrameTransform {
+        transform
+    }
+    /// Moves capture to `device`, restarting it if running. Sinks live in
+    /// the dispatcher, so they keep receiving frames from the new device.
+    fn switch_device(&mut self, device: &str) -> Result<(), CameraError> {
+        let _ = device;
+        Err(CameraError::unsupported(
+            "switching devices is not supported by this backend",
+        ))
+    }
+    /// Renegotiates the capture format from `config` (see
+    /// `Camera::reconfigure`), restarting capture if running. On failure
+    /// the driver keeps, or goes back to, the old format.
+    fn reconfigure(&mut self, config: &CameraConfig) -> Result<(), CameraError> {
+        let _ = config;
+        Err(CameraError::unsupported(
+            "reconfiguring is not supported by this backend",
+        ))
+    }
+    /// Suspends (`true`) or resumes streaming from the sensor while the
+    /// device stays open and configured. Backends that can't keep the
+    /// default, and `Camera::pause` then only stops delivering frames.
+    fn set_paused(&mut self, paused: bool) -> Result<(), CameraError> {
+        let _ = paused;
+        Err(CameraError::unsupported(
+            "pausing is not supported by this backend",
+        ))
+    }
+    /// Shifts auto exposure by `ev` stops (0 restores the metered
+    /// exposure), restarting capture if the backend must, for
+    /// `Camera::capture_bracket`.
+    fn set_exposure_bias(&mut self, ev: f32) -> Result<(), CameraError> {
+        let _ = ev;
+        Err(CameraError::unsupported(
+            "exposure bracketing is not supported by this backend",
+        ))
+    }
+    /// Takes a still through the platform's photo pipeline, at the best
+    /// quality it offers, pausing any stream it has to share the sensor with.
+    fn capture_photo(&mut self, format: PhotoFormat) -> Result<Photo, CameraError> {
+        let _ = format;
+        Err(CameraError::unsupported(
+            "still capture is not supported by this backend",
+        ))
+    }
+    /// Lists the capture modes the configured device advertises, without
+    /// streaming from it. Backends send what they couldn't parse as
+    /// `CameraEvent::Warning`s.
+    fn modes(&mut self) -> Result<Vec<CameraMode>, CameraError> {
+        Err(CameraError::unsupported(
+            "listing capture modes is not supported by this backend",
+        ))
+    }
+    /// Sends a raw request to a UVC extension unit of the device, returning
+    /// the bytes a get reads, for vendor controls with no standard
+    /// equivalent.
+    fn vendor_control(&mut self, control: &VendorControl) -> Result<Vec<u8>, CameraError> {
+        let _ = control;
+        Err(CameraError::unsupported(
+            "extension unit controls are not supported by this backend",
+        ))
+    }
+    /// Holds the device's automatic `control` at the values it has
+    /// converged on (`locked`), or lets it adjust again.
+    fn lock_auto(&mut self, control: AutoControl, locked: bool) -> Result<(), CameraError> {
+        let _ = locked;
+        Err(CameraError::unsupported(format!(
+            "locking auto {control} is not supported by this backend"
+        )))
+    }
+    /// Puts the device in (`enabled`) or takes it out of trigger mode,
+    /// before capture starts and after it stops. By default this sends
+    /// the trigger's extension unit requests.
+    fn set_trigger_mode(
+        &mut self,
+        trigger: &TriggerConfig,
+        enabled: bool,
+    ) -> Result<(), CameraError> {
+        let requests = if enabled {
+            &trigger.enable
+        } else {
+            &trigger.disable
+        };
+        for request in requests {
+            self.vendor_control(request)?;
+        }
+        Ok(())
+    }
+    /// Fires one software trigger in trigger mode. By default this sends
+    /// the trigger's `fire` request.
+    fn fire_trigger(&mut self, trigger: &TriggerConfig) -> Result<(), CameraError> {
+        match &trigger.fire {
+            Some(request) => self.vendor_control(request).map(drop),
+            None => Err(CameraError::unsupported(
+                "software triggers need a fire request with this backend",
+            )),
+        }
+    }
+    /// Describes what `start` would run or request for the current
+    /// configuration, failing as `start` would on settings the backend
+    /// can't capture with; `None` where the backend can't tell.
+    fn plan(&self) -> Result<Option<CapturePlan>, CameraError> {
+        Ok(None)
+    }
+    /// Hands the driver the channel for `CameraConfig::audio` chunks.
+    #[cfg(feature = "audio")]
+    fn set_audio_sender(&mut self, tx: SyncSender<AudioFrame>) -> Result<(), CameraError> {
+        let _ = tx;
+        Err(CameraError::unsupported(
+            "audio capture is not supported by this backend",
+        ))
+    }
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
+/// The last progress `Camera::check_watchdog` saw.
+#[derive(Clone, Copy, Debug)]
+struct Watchdog {
+    timeout: Duration,
+    captured: u64,
+    /// On the monotonic clock, which unlike `Instant` works in browsers.
+    since_ns: u64,
+}
+
+/// How far `Camera::check_auto_lock` has got since the driver started.
+#[derive(Clone, Debug, Default)]
+struct AutoLockState {
+    config: AutoLock,
+    /// Frames captured when the driver started, to tell the first new one.
+    captured: u64,
+    /// When that first frame arrived, on the monotonic clock.
+    since_ns: Option<u64>,
+    /// Controls already locked, or that failed to, since the start.
+    done: Vec<AutoControl>,
+    /// Controls locked now, which `stop` releases.
+    locked: Vec<AutoControl>,
+}
+
+/// Where a `Camera` is in its lifecycle. `start` moves a created or
+/// stopped camera to `Running`, and `stop` a created or running one to
+/// `Stopped`; calls that wouldn't change the state do nothing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraState {
+    /// Opened, and never started.
+    #[default]
+    Created,
+    Running,
+    Stopped,
+}
+
+/// An open camera. It is `Send` but not `Sync`: move it to the thread that
+/// drives it, or share it behind a `Mutex` as the Python and JNI bindings do.
 pub struct Camera {
     driver: Box<dyn CameraDriver>,
     dispatcher: Dispatcher,
+    events_tx: SyncSender<CameraEvent>,
     events_rx: Receiver<CameraEvent>,
+    events_taken: bool,
+    state: CameraState,
+    private: bool,
+    paused: bool,
+    stop_timeout: Duration,
+    watchdog: Option<Watchdog>,
+    load: Option<LoadMonitor>,
+    /// The capture format the driver was opened or last reconfigured with.
+    format: CameraConfig,
+    trigger: Option<TriggerConfig>,
+    auto_lock: AutoLockState,
+    #[cfg(feature = "audio")]
+    audio_rx: Option<Receiver<AudioFrame>>,
 }
 
 impl Camera {
@@ -134,43 +1128,679 @@ impl Camera {
             all(feature = "android", target_os = "android"),
             all(feature = "dshow", target_os = "windows"),
             all(feature = "v4l2", target_os = "linux"),
+            all(feature = "web", target_arch = "wasm32"),
         )),
         allow(dead_code)
     )]
     pub(crate) fn new(
         driver: Box<dyn CameraDriver>,
         dispatcher: Dispatcher,
+        events_tx: SyncSender<CameraEvent>,
         events_rx: Receiver<CameraEvent>,
     ) -> Self {
         Self {
             driver,
             dispatcher,
+            events_tx,
             events_rx,
+            events_taken: false,
+            state: CameraState::Created,
+            private: false,
+            paused: false,
+            stop_timeout: DEFAULT_STOP_TIMEOUT,
+            watchdog: None,
+            load: None,
+            format: CameraConfig::default(),
+            trigger: None,
+            auto_lock: AutoLockState::default(),
+            #[cfg(feature = "audio")]
+            audio_rx: None,
+        }
+    }
+
+    /// Sets how long `stop` waits for capture and dispatch threads.
+    pub fn set_stop_timeout(&mut self, timeout: Duration) {
+        self.stop_timeout = timeout;
+    }
+
+    /// Sets how long capture may go without a frame before
+    /// `check_watchdog` restarts the driver; `None` disables it.
+    pub fn set_watchdog(&mut self, timeout: Option<Duration>) {
+        self.watchdog = timeout.map(|timeout| Watchdog {
+            timeout,
+            captured: self.stats().frames_captured,
+            since_ns: monotonic_ns(),
+        });
+    }
+
+    /// Restarts the driver, emitting `Stalled`, if it was started but
+    /// delivered no frame within the watchdog timeout; backends can wedge
+    /// silently, e.g. ffmpeg when the device is unplugged. Call
+    /// periodically; returns whether it restarted.
+    pub fn check_watchdog(&mut self) -> Result<bool, CameraError> {
+        let captured = self.stats().frames_captured;
+        let active = self.is_running() && !self.private && !self.paused && !self.has_ended();
+        let Some(watchdog) = &mut self.watchdog else {
+            return Ok(false);
+        };
+        if !active || watchdog.captured != captured {
+            watchdog.captured = captured;
+            watchdog.since_ns = monotonic_ns();
+            return Ok(false);
+        }
+        let after = Duration::from_nanos(monotonic_ns().saturating_sub(watchdog.since_ns));
+        if after < watchdog.timeout {
+            return Ok(false);
+        }
+        // Give the restarted driver a full timeout, even if starting fails.
+        watchdog.since_ns = monotonic_ns();
+        let _ = self.events_tx.try_send(CameraEvent::Stalled {
+            backend: self.backend(),
+            after,
+        });
+        let _ = self.driver.stop();
+        self.start_driver()?;
+        Ok(true)
+    }
+
+    /// Starts an ended source over from the beginning if
+    /// `CameraConfig::looping` is set, so a video file or scripted source
+    /// plays forever. Call periodically, like `check_watchdog`; returns
+    /// whether it restarted.
+    pub fn check_replay(&mut self) -> Result<bool, CameraError> {
+        let active = self.is_running() && !self.private && !self.paused;
+        if !active || !self.dispatcher.is_looping() || !self.has_ended() {
+            return Ok(false);
         }
+        let _ = self.driver.stop();
+        self.start_driver()?;
+        Ok(true)
+    }
+
+    /// Sets when `check_auto_lock` locks exposure and white balance.
+    pub fn set_auto_lock(&mut self, auto_lock: AutoLock) {
+        self.auto_lock.config = auto_lock;
+    }
+
+    /// Locks each control of `CameraConfig::auto_lock` once its delay has
+    /// passed since the first frame after the driver started, letting the
+    /// camera converge until then. Call periodically, like
+    /// `check_watchdog`; returns whether it locked one. A control that
+    /// fails to lock isn't tried again until capture restarts.
+    pub fn check_auto_lock(&mut self) -> Result<bool, CameraError> {
+        let captured = self.stats().frames_captured;
+        let active = self.is_running() && !self.private && !self.paused;
+        let state = &mut self.auto_lock;
+        if !active || state.config.is_empty() {
+            return Ok(false);
+        }
+        let since_ns = match state.since_ns {
+            Some(since_ns) => since_ns,
+            None if captured != state.captured => *state.since_ns.insert(monotonic_ns()),
+            None => return Ok(false),
+        };
+        let elapsed = Duration::from_nanos(monotonic_ns().saturating_sub(since_ns));
+        let due = AutoControl::ALL.into_iter().find(|control| {
+            !state.done.contains(control)
+                && state
+                    .config
+                    .after(*control)
+                    .is_some_and(|after| elapsed >= after)
+        });
+        let Some(control) = due else {
+            return Ok(false);
+        };
+        state.done.push(control);
+        self.lock_auto(control, true).map(|()| true)
+    }
+
+    /// Holds `control` at the values the camera has converged on, or
+    /// (`!locked`) lets the camera adjust it again, emitting
+    /// `AutoLockChanged`. Locks are released when capture stops. Needs a
+    /// V4L2 device (through the ffmpeg, uvc or v4l2 backends) for now.
+    pub fn lock_auto(&mut self, control: AutoControl, locked: bool) -> Result<(), CameraError> {
+        self.driver.lock_auto(control, locked)?;
+        self.auto_lock.locked.retain(|c| *c != control);
+        if locked {
+            self.auto_lock.locked.push(control);
+        }
+        let _ = self.events_tx.try_send(CameraEvent::AutoLockChanged {
+            backend: self.backend(),
+            control,
+            locked,
+        });
+        Ok(())
+    }
+
+    /// Restarts the watchdog timeout, e.g. because capture (re)started.
+    fn reset_watchdog(&mut self) {
+        if let Some(watchdog) = &mut self.watchdog {
+            watchdog.since_ns = monotonic_ns();
+        }
+    }
+
+    /// Records the capture format `config` opened the driver with.
+    pub(crate) fn set_format(&mut self, config: &CameraConfig) {
+        self.format = self.format.with_format_of(config);
+    }
+
+    /// Captures in trigger mode from the next start, one frame per
+    /// trigger, marking each with `FrameMetadata::trigger`; `None`
+    /// captures free-running again.
+    pub fn set_trigger(&mut self, trigger: Option<TriggerConfig>) {
+        self.trigger = trigger;
+    }
+
+    pub fn trigger(&self) -> Option<&TriggerConfig> {
+        self.trigger.as_ref()
+    }
+
+    /// Fires one software trigger, for a camera in trigger mode; the
+    /// frame that answers it carries the time it was fired.
+    pub fn fire_trigger(&mut self) -> Result<(), CameraError> {
+        let Some(trigger) = &self.trigger else {
+            return Err(CameraError::invalid_config(
+                "the camera isn't in trigger mode",
+            ));
+        };
+        if !self.is_running() || self.private {
+            return Err(CameraError::invalid_config(
+                "can't fire a trigger while not capturing",
+            ));
+        }
+        let fired_ns = monotonic_ns();
+        self.driver.fire_trigger(trigger)?;
+        self.dispatcher.trigger_fired(fired_ns);
+        Ok(())
+    }
+
+    /// Sets when `check_load` downgrades capture; `None` disables it.
+    pub fn set_load_guard(&mut self, guard: Option<LoadGuard>) {
+        let stats = self.stats();
+        self.load = guard.map(|guard| LoadMonitor::new(guard, stats));
+    }
+
+    /// Halves the frame rate, or once it's at the guard's floor moves to a
+    /// smaller mode, emitting `Downgraded`, if frames were dropped or the
+    /// CPUs were saturated for the whole of the load guard's window; an
+    /// overloaded edge device then keeps up rather than dropping most of
+    /// what the camera sends. Downgrades aren't undone. Call periodically;
+    /// returns whether it downgraded.
+    pub fn check_load(&mut self) -> Result<bool, CameraError> {
+        let stats = self.stats();
+        let active = self.is_running() && !self.private && !self.paused && !self.has_ended();
+        let Some(load) = &mut self.load else {
+            return Ok(false);
+        };
+        if !active {
+            load.restart(stats);
+            return Ok(false);
+        }
+        let Some(reason) = load.observe(stats) else {
+            return Ok(false);
+        };
+        let guard = *load.guard();
+        let (width, height, fps) = (self.format.width, self.format.height, self.format.fps);
+        let modes = if fps <= guard.min_fps && guard.lower_resolution {
+            self.modes().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        let Some((new_width, new_height, new_fps)) = guard.step_down(width, height, fps, &modes)
+        else {
+            return Ok(false);
+        };
+        let config = CameraConfig {
+            width: new_width,
+            height: new_height,
+            fps: new_fps,
+            ..self.format.clone()
+        };
+        // The next window started when this one ended, before the restart.
+        self.reconfigure(config)?;
+        let _ = self.events_tx.try_send(CameraEvent::Downgraded {
+            backend: self.backend(),
+            reason,
+            previous_width: width,
+            previous_height: height,
+            previous_fps: fps,
+            width: new_width,
+            height: new_height,
+            fps: new_fps,
+        });
+        Ok(true)
     }
 
     pub fn backend(&self) -> CameraBackend {
         self.driver.backend()
     }
 
+    /// The capture format (size, frame rate, pixel format and sensor mode)
+    /// the driver was opened or last reconfigured with, e.g. as the base of
+    /// a `reconfigure` that changes only some of it.
+    pub fn format(&self) -> &CameraConfig {
+        &self.format
+    }
+
     pub fn add_sink(&self, sink: FrameSink) {
         self.dispatcher.add_sink(sink);
     }
 
+    /// Adds a sink at its own rate, e.g. a recording at the camera's full
+    /// rate beside another sink at one frame a second.
+    pub fn add_sink_with_rate(&self, sink: FrameSink, rate: SinkRate) -> Result<(), CameraError> {
+        self.dispatcher.add_sink_with_rate(sink, rate)
+    }
+
+    /// Calls `callback` when a finite source, like a video file, has
+    /// delivered its last frame; `CameraEvent::EndOfStream` follows.
+    pub fn on_end(&self, callback: EndCallback) {
+        self.dispatcher.on_end(callback);
+    }
+
+    /// Whether the source ended (see `on_end`) since capture last started;
+    /// the watchdog and load guard leave an ended stream alone.
+    pub fn has_ended(&self) -> bool {
+        self.dispatcher.has_ended()
+    }
+
+    /// Runs `analyzer` on every frame; non-empty results are reported as
+    /// `CameraEvent::Observed`.
+    pub fn add_analyzer(&self, analyzer: impl FrameAnalyzer + 'static) {
+        let backend = self.backend();
+        let events_tx = self.events_tx.clone();
+        let analyzer = Mutex::new(analyzer);
+        self.add_sink(Arc::new(move |frame: Frame| {
+            let mut analyzer = analyzer.lock().unwrap_or_else(|p| p.into_inner());
+            let observations = analyzer.analyze(&frame);
+            if !observations.is_empty() {
+                let _ = events_tx.try_send(CameraEvent::Observed {
+                    backend,
+                    analyzer: analyzer.name().to_string(),
+                    timestamp_ns: frame.timestamp_ns,
+                    observations,
+                });
+            }
+        }));
+    }
+
+    /// Atomically swaps the previous pipeline for `pipeline`, its sinks,
+    /// masking and debounce, without restarting the driver, and reports the
+    /// change as an event. Sinks and analyzers added on their own stay.
+    pub fn apply_pipeline(&self, pipeline: Pipeline) -> Result<(), CameraError> {
+        pipeline.validate()?;
+        let (label, count) = (pipeline.label().to_string(), pipeline.len());
+        let previous_sinks = self.dispatcher.replace_pipeline(pipeline);
+        let _ = self.events_tx.try_send(CameraEvent::PipelineChanged {
+            backend: self.backend(),
+            label,
+            previous_sinks,
+            sinks: count,
+        });
+        Ok(())
+    }
+
     pub fn events(&self) -> &Receiver<CameraEvent> {
         &self.events_rx
     }
 
+    /// A sender into the camera's event stream, for the embedder's own
+    /// sinks to report events (e.g. `LowDiskSpace`) beside the camera's.
+    pub fn event_sender(&self) -> SyncSender<CameraEvent> {
+        self.events_tx.clone()
+    }
+
+    /// Takes the event receiver, e.g. to forward events to a callback from
+    /// a thread of its own; `events` receives nothing afterwards. Returns
+    /// `None` if it was already taken.
+    pub fn take_events(&mut self) -> Option<Receiver<CameraEvent>> {
+        if core::mem::replace(&mut self.events_taken, true) {
+            return None;
+        }
+        let (_, empty) = sync_channel(1);
+        Some(core::mem::replace(&mut self.events_rx, empty))
+    }
+
+    #[cfg(feature = "audio")]
+    pub(crate) fn set_audio_receiver(&mut self, rx: Receiver<AudioFrame>) {
+        self.audio_rx = Some(rx);
+    }
+
+    /// Takes the receiver of audio chunks captured alongside video, if
+    /// `CameraConfig::audio` was set. Chunks are dropped while it is full.
+    #[cfg(feature = "audio")]
+    pub fn take_audio(&mut self) -> Option<Receiver<AudioFrame>> {
+        self.audio_rx.take()
+    }
+
+    /// Starts capture, or starts it again after `stop`, delivering to the
+    /// same sinks; does nothing while running. Inside a privacy window the
+    /// start is deferred until the window ends.
     pub fn start(&mut self) -> Result<(), CameraError> {
-        self.driver.start()
+        if self.is_running() {
+            return Ok(());
+        }
+        self.dispatcher.start()?;
+        self.state = CameraState::Running;
+        if self.private {
+            return Ok(());
+        }
+        self.start_driver()
+    }
+
+    /// Starts the driver, leaving the sensor suspended if paused.
+    fn start_driver(&mut self) -> Result<(), CameraError> {
+        self.reset_watchdog();
+        // Controls still locked stay locked; the rest converge afresh.
+        self.auto_lock.captured = self.stats().frames_captured;
+        self.auto_lock.since_ns = None;
+        self.auto_lock.done = self.auto_lock.locked.clone();
+        self.dispatcher.restart_stream();
+        if let Some(trigger) = &self.trigger {
+            self.driver.set_trigger_mode(trigger, true)?;
+        }
+        self.dispatcher.set_trigger_mode(self.trigger.is_some());
+        self.driver.start()?;
+        if self.paused {
+            self.pause_driver(true)?;
+        }
+        Ok(())
+    }
+
+    /// Stops the driver, taking the device out of trigger mode and
+    /// releasing the automatic controls again.
+    fn stop_driver(&mut self) -> Result<(), CameraError> {
+        let result = self.driver.stop();
+        for control in core::mem::take(&mut self.auto_lock.locked) {
+            if let Err(err) = self.lock_auto(control, false) {
+                let _ = self.events_tx.try_send(CameraEvent::Warning {
+                    backend: self.backend(),
+                    message: format!("can't release the auto {control} lock: {err}"),
+                });
+            }
+        }
+        if !self.dispatcher.is_trigger_mode() {
+            return result;
+        }
+        self.dispatcher.set_trigger_mode(false);
+        if let Some(trigger) = &self.trigger
+            && let Err(err) = self.driver.set_trigger_mode(trigger, false)
+        {
+            let _ = self.events_tx.try_send(CameraEvent::Warning {
+                backend: self.backend(),
+                message: format!("can't leave trigger mode: {err}"),
+            });
+        }
+        result
     }
 
+    fn pause_driver(&mut self, paused: bool) -> Result<(), CameraError> {
+        match self.driver.set_paused(paused) {
+            // Delivery is gated in the dispatcher either way.
+            Err(CameraError::Unsupported(_)) => Ok(()),
+            result => result,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stops delivering frames while keeping the device open and
+    /// configured, so `resume` takes effect without renegotiating it.
+    /// Backends that can also suspend the sensor (ffmpeg and Raspberry Pi
+    /// cameras on Unix, GStreamer) do so.
+    pub fn pause(&mut self) -> Result<(), CameraError> {
+        self.set_paused(true)
+    }
+
+    /// Resumes delivering frames after `pause`.
+    pub fn resume(&mut self) -> Result<(), CameraError> {
+        self.set_paused(false)
+    }
+
+    fn set_paused(&mut self, paused: bool) -> Result<(), CameraError> {
+        if self.paused == paused {
+            return Ok(());
+        }
+        if paused {
+            self.dispatcher.set_paused(true);
+        }
+        let result = if self.is_running() && !self.private {
+            self.pause_driver(paused)
+        } else {
+            Ok(())
+        };
+        if let Err(err) = result {
+            self.dispatcher.set_paused(self.paused);
+            return Err(err);
+        }
+        self.paused = paused;
+        self.dispatcher.set_paused(paused);
+        self.reset_watchdog();
+        let _ = self.events_tx.try_send(CameraEvent::PauseChanged {
+            backend: self.backend(),
+            paused,
+        });
+        Ok(())
+    }
+
+    /// Moves capture to another device (e.g. `avf:back`) without tearing
+    /// down sinks, analyzers or the pipeline, and reports the change as an
+    /// event. On failure the driver keeps, or goes back to, the old device.
+    pub fn switch_device(&mut self, device: impl AsRef<str>) -> Result<(), CameraError> {
+        let device = device.as_ref();
+        self.driver.switch_device(device)?;
+        if self.paused && self.is_running() && !self.private {
+            self.pause_driver(true)?;
+        }
+        let _ = self.events_tx.try_send(CameraEvent::DeviceChanged {
+            backend: self.backend(),
+            device: device.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Switches to the capture format of `config` (size, frame rate, pixel
+    /// format and sensor mode) without tearing down sinks, analyzers or the
+    /// pipeline, e.g. between a preview and a full-resolution still, and
+    /// reports the change as an event; the device and everything else stay
+    /// as opened. On failure the driver keeps, or goes back to, the old
+    /// format.
+    pub fn reconfigure(&mut self, config: CameraConfig) -> Result<(), CameraError> {
+        if let Some(backend) = config.backend
+            && backend != self.backend()
+        {
+            return Err(CameraError::invalid_config(format!(
+                "can't reconfigure a {:?} camera for {backend:?}",
+                self.backend()
+            )));
+        }
+        self.driver.reconfigure(&config)?;
+        self.format = self.format.with_format_of(&config);
+        self.reset_watchdog();
+        if self.paused && self.is_running() && !self.private {
+            self.pause_driver(true)?;
+        }
+        let _ = self.events_tx.try_send(CameraEvent::FormatChanged {
+            backend: self.backend(),
+            width: config.width,
+            height: config.height,
+            fps: config.fps,
+        });
+        Ok(())
+    }
+
+    /// Collects the next `count` frames of the running stream, at least
+    /// `interval` apart (zero takes consecutive frames), for HDR merging or
+    /// inspection; they reach the sinks as usual too. Fails if capture
+    /// isn't running or the stream stalls mid-burst.
+    #[cfg(not(all(feature = "web", target_arch = "wasm32")))]
+    pub fn capture_burst(
+        &mut self,
+        count: usize,
+        interval: Duration,
+    ) -> Result<Vec<Frame>, CameraError> {
+        self.check_capturing()?;
+        let (tx, rx) = sync_channel(count.clamp(1, 64));
+        self.dispatcher.set_tap(Some(tx));
+        let frames = collect_burst(&rx, count, interval);
+        self.dispatcher.set_tap(None);
+        frames
+    }
+
+    /// Captures one frame at each exposure compensation in `ev`, in stops
+    /// relative to the metered exposure (e.g. `[-2.0, 0.0, 2.0]`), then
+    /// returns to the metered exposure. Needs a backend that can bias
+    /// exposure (uvc, or Raspberry Pi cameras, which restart `rpicam-vid`
+    /// for each step); elsewhere it's unsupported.
+    #[cfg(not(all(feature = "web", target_arch = "wasm32")))]
+    pub fn capture_bracket(&mut self, ev: &[f32]) -> Result<Vec<Frame>, CameraError> {
+        self.check_capturing()?;
+        let mut frames = Vec::with_capacity(ev.len());
+        let result = self.bracket_into(ev, &mut frames);
+        let reset = self.driver.set_exposure_bias(0.0);
+        self.reset_watchdog();
+        result.and(reset).map(|()| frames)
+    }
+
+    #[cfg(not(all(feature = "web", target_arch = "wasm32")))]
+    fn bracket_into(&mut self, ev: &[f32], frames: &mut Vec<Frame>) -> Result<(), CameraError> {
+        for &bias in ev {
+            self.driver.set_exposure_bias(bias)?;
+            self.reset_watchdog();
+            let mut settled = self.capture_burst(BRACKET_SETTLE_FRAMES + 1, Duration::ZERO)?;
+            frames.extend(settled.pop());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(all(feature = "web", target_arch = "wasm32")))]
+    fn check_capturing(&self) -> Result<(), CameraError> {
+        let state = if !self.is_running() {
+            "not started"
+        } else if self.private {
+            "in a privacy window"
+        } else if self.paused {
+            "paused"
+        } else {
+            return Ok(());
+        };
+        Err(CameraError::invalid_config(format!(
+            "can't capture a burst: the camera is {state}"
+        )))
+    }
+
+    /// Takes a full-quality still through the backend's photo pipeline
+    /// (Android's still-capture template, `rpicam-still` for Raspberry Pi
+    /// cameras) instead of grabbing a streaming frame. A running stream is
+    /// interrupted while the photo is taken and then resumes.
+    pub fn capture_photo(&mut self, format: PhotoFormat) -> Result<Photo, CameraError> {
+        if self.private {
+            return Err(CameraError::invalid_config(
+                "can't take a photo during a privacy window",
+            ));
+        }
+        let photo = self.driver.capture_photo(format)?;
+        self.reset_watchdog();
+        if self.paused && self.is_running() {
+            self.pause_driver(true)?;
+        }
+        Ok(photo)
+    }
+
+    /// The sizes, formats and frame rates the device advertises, largest
+    /// first within each format.
+    pub fn modes(&mut self) -> Result<Vec<CameraMode>, CameraError> {
+        let mut modes = self.driver.modes()?;
+        normalize_modes(&mut modes);
+        Ok(modes)
+    }
+
+    /// Passes `control` through to a vendor-specific UVC extension unit
+    /// (e.g. a trigger mode or strobe), returning what a get reads. The
+    /// request isn't checked against the unit's descriptor, so it's
+    /// only as safe as the vendor's documentation of it.
+    pub fn vendor_control(&mut self, control: &VendorControl) -> Result<Vec<u8>, CameraError> {
+        self.driver.vendor_control(control)
+    }
+
+    /// The helper command line, or the format requested in-process, that
+    /// starting capture would use, without touching the device.
+    pub fn plan(&self) -> Result<Option<CapturePlan>, CameraError> {
+        self.driver.plan()
+    }
+
+    /// Stops capture and dispatch. Threads still blocked (e.g. in a read on
+    /// a wedged device, or in a sink) after the configured stop timeout are
+    /// abandoned with a warning, so this always returns promptly. Does
+    /// nothing once stopped.
     pub fn stop(&mut self) -> Result<(), CameraError> {
-        let r = self.driver.stop();
-        self.dispatcher.stop();
+        if self.state == CameraState::Stopped {
+            return Ok(());
+        }
+        self.state = CameraState::Stopped;
+        let r = self.stop_driver();
+        if !self.dispatcher.stop(self.stop_timeout) {
+            let _ = self.events_tx.try_send(CameraEvent::Warning {
+                backend: self.backend(),
+                message: format!(
+                    "a frame sink did not return within {:?}; abandoning the dispatch thread",
+                    self.stop_timeout
+                ),
+            });
+        }
         r
     }
 
+    pub fn stats(&self) -> CameraStats {
+        self.dispatcher.stats()
+    }
+
+    /// A handle for sinks to count the frames they discard.
+    pub fn drop_reporter(&self) -> DropReporter {
+        self.dispatcher.drop_reporter()
+    }
+
+    pub fn state(&self) -> CameraState {
+        self.state
+    }
+
+    /// Whether `start` was called without a `stop` since.
+    pub fn is_running(&self) -> bool {
+        self.state == CameraState::Running
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+
+    /// Enters or leaves privacy mode. Entering stops the driver so the device
+    /// is released; leaving restarts it if capture had been started.
+    pub fn set_private(&mut self, active: bool) -> Result<(), CameraError> {
+        if self.private == active {
+            return Ok(());
+        }
+        self.private = active;
+        let result = if active {
+            self.stop_driver()
+        } else if self.is_running() {
+            self.start_driver()
+        } else {
+            Ok(())
+        };
+        let _ = self.events_tx.try_send(CameraEvent::PrivacyChanged {
+            backend: self.backend(),
+            active,
+        });
+        result
+    }
+
+    /// Applies `schedule` to the current local time; call periodically.
+    pub fn apply_privacy(&mut self, schedule: &PrivacySchedule) -> Result<(), CameraError> {
+        self.set_private(schedule.is_active_now())
+    }
+
     pub fn driver_as<T: 'static>(&self) -> Option<&T> {
         self.driver.as_any().downcast_ref::<T>()
     }
@@ -186,17 +1816,51 @@ impl Drop for Camera {
     }
 }
 
+#[cfg(not(all(feature = "web", target_arch = "wasm32")))]
+fn collect_burst(
+    rx: &Receiver<Frame>,
+    count: usize,
+    interval: Duration,
+) -> Result<Vec<Frame>, CameraError> {
+    let mut frames: Vec<Frame> = Vec::with_capacity(count);
+    while frames.len() < count {
+        use std::sync::mpsc::RecvTimeoutError;
+        let frame = match rx.recv_timeout(interval + BURST_FRAME_TIMEOUT) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(CameraError::other(format!(
+                    "burst stalled after {} of {count} frames",
+                    frames.len()
+                )));
+            },
+            Err(RecvTimeoutError::Disconnected) => return Err(CameraError::Closed),
+        };
+        if frames.last().is_some_and(|last| {
+            frame.monotonic_ns.saturating_sub(last.monotonic_ns) < interval.as_nanos() as u64
+        }) {
+            continue;
+        }
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
 pub fn report_drop(events_tx: &SyncSender<CameraEvent>, backend: CameraBackend) {
     let _ = events_tx.try_send(CameraEvent::FrameDropped { backend });
 }
 
+/// Queues `frame` for the dispatcher, validating it as
+/// `CameraConfig::frame_validation` says, and reports drops as events.
 pub fn try_send_frame(
-    frame_tx: &SyncSender<FrameMsg>,
+    frame_tx: &FrameSender,
     events_tx: &SyncSender<CameraEvent>,
     backend: CameraBackend,
-    frame: Frame,
+    mut frame: Frame,
 ) {
-    match frame_tx.try_send(FrameMsg::Frame(frame)) {
+    if !frame_tx.stats.checks.admit(&mut frame, events_tx, backend) {
+        return;
+    }
+    match frame_tx.try_send(frame) {
         Ok(()) => {},
         Err(TrySendError::Full(_)) => report_drop(events_tx, backend),
         Err(TrySendError::Disconnected(_)) => {
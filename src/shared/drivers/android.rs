@@ -30,6 +30,10 @@ pub use image::*;
 mod image_reader;
 pub use image_reader::*;
 
+/// JNI entry points backing `sh.asimov.camera.AsimovCamera` in Kotlin.
+#[cfg(feature = "asimov_camera_jni")]
+pub mod jni;
+
 mod media_status;
 pub use media_status::*;
 
@@ -37,16 +41,17 @@ mod native_window;
 pub use native_window::*;
 
 use crate::shared::{
-    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, Frame, FrameMsg,
-    try_send_frame,
+    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, Frame, FrameSender, Photo,
+    PhotoFormat, try_send_frame, wall_clock_ns,
 };
 use alloc::{borrow::Cow, ffi::CString};
 use bytes::Bytes;
 use core::{ffi::CStr, ptr::null_mut};
 use ndk_sys::{
-    ACameraManager_create, ACameraManager_delete, ACameraManager_deleteCameraIdList,
-    ACameraManager_getCameraIdList, ACameraManager_openCamera, android_get_device_api_level,
-    camera_status_t,
+    ACameraDevice_request_template, ACameraManager_create, ACameraManager_delete,
+    ACameraManager_deleteCameraIdList, ACameraManager_getCameraIdList, ACameraManager_openCamera,
+    AIMAGE_FORMATS, acamera_metadata_tag, android_get_device_api_level, camera_status_t,
+    media_status_t,
 };
 use scopeguard::defer;
 use std::any::Any;
@@ -55,6 +60,10 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     mpsc::SyncSender,
 };
+use std::time::{Duration, Instant};
+
+/// How long `capture_photo` waits for the encoded still.
+const PHOTO_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[link(name = "camera2ndk")]
 unsafe extern "C" {}
@@ -65,20 +74,36 @@ unsafe extern "C" {}
 #[link(name = "binder_ndk")]
 unsafe extern "C" {}
 
+/// Keeps the NDK objects backing a preview session alive while it runs.
+#[derive(Debug)]
+struct PreviewOutput {
+    _outputs: CaptureSessionOutputContainer,
+    _output: CaptureSessionOutput,
+    _target: CameraOutputTarget,
+    _request: CaptureRequest,
+}
+
 #[derive(Debug)]
 pub struct AndroidCameraDriver {
     pub config: CameraConfig,
     pub api_level: u32,
+    camera_id: String,
     #[allow(unused)]
     pub(crate) device: CameraDevice,
     #[allow(unused)]
     pub(crate) session: Option<CameraCaptureSession>,
+    pub(crate) preview_window: Option<NativeWindow>,
+    preview: Option<PreviewOutput>,
 
-    frame_tx: SyncSender<FrameMsg>,
+    frame_tx: FrameSender,
     events_tx: SyncSender<CameraEvent>,
     running: Arc<AtomicBool>,
 }
 
+// The NDK camera objects are safe to use from any thread; the raw handles
+// are only touched through `&mut self`.
+unsafe impl Send for AndroidCameraDriver {}
+
 impl dogma::Named for AndroidCameraDriver {
     fn name(&self) -> Cow<'_, str> {
         "camera2".into()
@@ -95,7 +120,7 @@ impl AndroidCameraDriver {
     pub fn open(
         _input_url: impl AsRef<str>,
         config: CameraConfig,
-        frame_tx: SyncSender<FrameMsg>,
+        frame_tx: FrameSender,
         events_tx: SyncSender<CameraEvent>,
     ) -> Result<Self, CameraError> {
         unsafe {
@@ -137,7 +162,8 @@ impl AndroidCameraDriver {
             }
 
             let mut device = CameraDevice::default();
-            let device_id = CString::new(camera_id_strings[0].clone()).unwrap();
+            let camera_id = camera_id_strings[0].clone();
+            let device_id = CString::new(camera_id.clone()).unwrap();
 
             let status = ACameraManager_openCamera(
                 camera_manager,
@@ -160,8 +186,11 @@ impl AndroidCameraDriver {
             Ok(AndroidCameraDriver {
                 config,
                 api_level,
+                camera_id,
                 device,
                 session: None,
+                preview_window: None,
+                preview: None,
                 frame_tx,
                 events_tx,
                 running: Arc::new(AtomicBool::new(false)),
@@ -169,6 +198,107 @@ impl AndroidCameraDriver {
         }
     }
 
+    /// Renders the camera stream into `window` (e.g. an Android `Surface`)
+    /// from the next call to `start()` on.
+    pub fn set_preview_window(&mut self, window: NativeWindow) {
+        self.preview_window = Some(window);
+    }
+
+    fn start_preview(&mut self) -> CameraResult {
+        let Some(window) = self.preview_window.as_ref() else {
+            return Ok(());
+        };
+
+        let output = CaptureSessionOutput::new(window)?;
+        let mut outputs = CaptureSessionOutputContainer::new()?;
+        outputs.add(&output)?;
+
+        let target = CameraOutputTarget::new(window)?;
+        let mut request = CaptureRequest::new(&self.device)?;
+        request.add_target(&target)?;
+
+        let mut session = CameraCaptureSession::open(&self.device, &outputs)?;
+        session.set_repeating_request(&request)?;
+
+        self.session = Some(session);
+        self.preview = Some(PreviewOutput {
+            _outputs: outputs,
+            _output: output,
+            _target: target,
+            _request: request,
+        });
+        Ok(())
+    }
+
+    /// Opens a one-off session with a JPEG or HEIC image reader at the
+    /// largest size the camera offers, and fires a single still-capture
+    /// request into it.
+    fn take_still(&mut self, format: PhotoFormat) -> Result<Photo, CameraError> {
+        let (image_format, tag) = match format {
+            PhotoFormat::Jpeg => (
+                AIMAGE_FORMATS::AIMAGE_FORMAT_JPEG,
+                acamera_metadata_tag::ACAMERA_SCALER_AVAILABLE_STREAM_CONFIGURATIONS,
+            ),
+            PhotoFormat::Heif if self.api_level >= 29 => (
+                AIMAGE_FORMATS::AIMAGE_FORMAT_HEIC,
+                acamera_metadata_tag::ACAMERA_HEIC_AVAILABLE_HEIC_STREAM_CONFIGURATIONS,
+            ),
+            PhotoFormat::Heif => {
+                return Err(CameraError::unsupported("HEIF stills need Android 10"));
+            },
+        };
+        let size = CameraManager::new()
+            .largest_output_size(&self.camera_id, tag, image_format.0 as i32)
+            .map_err(|e| CameraError::driver("reading camera characteristics", e))?
+            .ok_or_else(|| {
+                CameraError::unsupported(format!("this camera can't capture {format} stills"))
+            })?;
+
+        let media = |e| CameraError::driver("reading the still", e);
+        let camera = |e| CameraError::driver("capturing a still", e);
+        let reader = ImageReader::polled(size, image_format.0 as i32, 1).map_err(media)?;
+        let window = reader.get_window().map_err(media)?;
+        let output = CaptureSessionOutput::new(&window).map_err(camera)?;
+        let mut outputs = CaptureSessionOutputContainer::new().map_err(camera)?;
+        outputs.add(&output).map_err(camera)?;
+        let target = CameraOutputTarget::new(&window).map_err(camera)?;
+        let mut request = CaptureRequest::with_template(
+            &self.device,
+            ACameraDevice_request_template::TEMPLATE_STILL_CAPTURE,
+        )
+        .map_err(camera)?;
+        request.add_target(&target).map_err(camera)?;
+        let mut session = CameraCaptureSession::open(&self.device, &outputs).map_err(camera)?;
+        session.capture(&request).map_err(camera)?;
+
+        let deadline = Instant::now() + PHOTO_TIMEOUT;
+        let image = loop {
+            match reader.acquire_latest_image() {
+                Ok(image) => break image,
+                Err(MediaStatus(media_status_t::AMEDIA_IMGREADER_NO_BUFFER_AVAILABLE))
+                    if Instant::now() < deadline =>
+                {
+                    std::thread::sleep(Duration::from_millis(10));
+                },
+                Err(MediaStatus(media_status_t::AMEDIA_IMGREADER_NO_BUFFER_AVAILABLE)) => {
+                    return Err(CameraError::other(format!(
+                        "no still arrived within {PHOTO_TIMEOUT:?}"
+                    )));
+                },
+                Err(e) => return Err(media(e)),
+            }
+        };
+        let (width, height) = image.get_dimensions().map_err(media)?;
+        let data = Bytes::copy_from_slice(image.get_plane_data(0).map_err(media)?);
+        Ok(Photo {
+            data,
+            format,
+            width,
+            height,
+            timestamp_ns: wall_clock_ns(),
+        })
+    }
+
     fn emit_frame(&self, frame: Frame) {
         try_send_frame(
             &self.frame_tx,
@@ -185,6 +315,14 @@ impl CameraDriver for AndroidCameraDriver {
     }
 
     fn start(&mut self) -> Result<(), CameraError> {
+        if self.preview_window.is_some() {
+            if self.session.is_none() {
+                self.start_preview()
+                    .map_err(|e| CameraError::driver("starting android preview", e))?;
+            }
+            return Ok(());
+        }
+
         let session_output_container = CaptureSessionOutputContainer::new().unwrap();
         self.session =
             Some(CameraCaptureSession::open(&self.device, &session_output_container).unwrap()); // FIXME
@@ -195,10 +333,34 @@ impl CameraDriver for AndroidCameraDriver {
     }
 
     fn stop(&mut self) -> Result<(), CameraError> {
+        if let Some(session) = self.session.as_mut() {
+            let _ = session.stop_repeating();
+        }
         self.session = None;
+        self.preview = None;
         Ok(())
     }
 
+    fn reconfigure(&mut self, config: &CameraConfig) -> Result<(), CameraError> {
+        // The capture session is opened for a format; recreate it.
+        let running = self.session.is_some();
+        self.stop()?;
+        self.config = self.config.with_format_of(config);
+        if running { self.start() } else { Ok(()) }
+    }
+
+    fn capture_photo(&mut self, format: PhotoFormat) -> Result<Photo, CameraError> {
+        // A device has one session at a time, so the preview's is closed for
+        // the still and reopened afterwards.
+        let running = self.session.is_some();
+        self.stop()?;
+        let photo = self.take_still(format);
+        if running {
+            self.start()?;
+        }
+        photo
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -2,12 +2,13 @@
 
 use super::{CameraDevice, CameraResult};
 use alloc::ffi::CString;
+use core::mem::zeroed;
 use core::{ffi::CStr, ptr::null_mut};
-//use core::{mem::zeroed, ptr::null_mut};
 use ndk_sys::{
     ACameraManager, ACameraManager_create, ACameraManager_delete,
-    ACameraManager_deleteCameraIdList, ACameraManager_getCameraIdList, ACameraManager_openCamera,
-    camera_status_t,
+    ACameraManager_deleteCameraIdList, ACameraManager_getCameraCharacteristics,
+    ACameraManager_getCameraIdList, ACameraManager_openCamera, ACameraMetadata_const_entry,
+    ACameraMetadata_free, ACameraMetadata_getConstEntry, acamera_metadata_tag, camera_status_t,
 };
 use scopeguard::defer;
 
@@ -58,6 +59,42 @@ impl CameraManager {
         Ok(result)
     }
 
+    /// The largest output size camera `id` lists for image `format` in the
+    /// stream configurations under `tag` (e.g.
+    /// `ACAMERA_SCALER_AVAILABLE_STREAM_CONFIGURATIONS`), if any.
+    pub fn largest_output_size(
+        &self,
+        id: impl AsRef<str>,
+        tag: acamera_metadata_tag,
+        format: i32,
+    ) -> CameraResult<Option<(u32, u32)>> {
+        let id = CString::new(String::from(id.as_ref())).unwrap();
+        let mut metadata = null_mut();
+        let status = unsafe {
+            ACameraManager_getCameraCharacteristics(self.handle, id.as_ptr(), &mut metadata)
+        };
+        if status != camera_status_t::ACAMERA_OK {
+            return Err(status.into());
+        }
+        defer! {
+            unsafe { ACameraMetadata_free(metadata); }
+        }
+
+        let mut entry: ACameraMetadata_const_entry = unsafe { zeroed() };
+        let status = unsafe { ACameraMetadata_getConstEntry(metadata, tag.0, &mut entry) };
+        if status != camera_status_t::ACAMERA_OK {
+            return Ok(None); // the camera doesn't list this tag
+        }
+
+        // Entries are (format, width, height, is_input) quadruples.
+        let values = unsafe { core::slice::from_raw_parts(entry.data.i32_, entry.count as usize) };
+        Ok(values
+            .chunks_exact(4)
+            .filter(|c| c[0] == format && c[3] == 0)
+            .map(|c| (c[1].max(0) as u32, c[2].max(0) as u32))
+            .max_by_key(|&(width, height)| width as u64 * height as u64))
+    }
+
     pub fn open_camera(&self, id: impl AsRef<str>) -> CameraResult<CameraDevice> {
         let id = CString::new(String::from(id.as_ref())).unwrap();
 
@@ -21,18 +21,26 @@ impl Drop for CaptureRequest {
 
 impl CaptureRequest {
     pub fn new(device: &CameraDevice) -> CameraResult<Self> {
+        Self::with_template(device, ACameraDevice_request_template::TEMPLATE_PREVIEW)
+    }
+
+    /// See: https://developer.android.com/ndk/reference/group/camera#acameradevice_createcapturerequest
+    pub fn with_template(
+        device: &CameraDevice,
+        template: ACameraDevice_request_template,
+    ) -> CameraResult<Self> {
         let mut result = Self::default();
-        result.init(device)?;
+        result.init(device, template)?;
         Ok(result)
     }
 
-    fn init(&mut self, device: &CameraDevice) -> CameraResult {
+    fn init(
+        &mut self,
+        device: &CameraDevice,
+        template: ACameraDevice_request_template,
+    ) -> CameraResult {
         let status = unsafe {
-            ACameraDevice_createCaptureRequest(
-                device.handle,
-                ACameraDevice_request_template::TEMPLATE_PREVIEW,
-                &mut self.handle,
-            )
+            ACameraDevice_createCaptureRequest(device.handle, template, &mut self.handle)
         };
         eprintln!("ACameraDevice_createCaptureRequest={:?}", status); // DEBUG
         if status != camera_status_t::ACAMERA_OK {
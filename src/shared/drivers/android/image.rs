@@ -2,7 +2,10 @@
 
 use super::MediaResult;
 use core::ptr::null_mut;
-use ndk_sys::{AImage, AImage_delete, AImage_getTimestamp, media_status_t};
+use ndk_sys::{
+    AImage, AImage_delete, AImage_getHeight, AImage_getPlaneData, AImage_getTimestamp,
+    AImage_getWidth, media_status_t,
+};
 
 #[derive(Debug, Default)]
 pub struct Image {
@@ -25,4 +28,31 @@ impl Image {
         }
         Ok(result as _)
     }
+
+    pub fn get_dimensions(&self) -> MediaResult<(u32, u32)> {
+        let (mut width, mut height) = (0, 0);
+        let status = unsafe { AImage_getWidth(self.handle, &mut width) };
+        if status != media_status_t::AMEDIA_OK {
+            return Err(status.into());
+        }
+        let status = unsafe { AImage_getHeight(self.handle, &mut height) };
+        if status != media_status_t::AMEDIA_OK {
+            return Err(status.into());
+        }
+        Ok((width as _, height as _))
+    }
+
+    /// The bytes of `plane`; JPEG and HEIC images have a single plane
+    /// holding the whole file.
+    ///
+    /// See: https://developer.android.com/ndk/reference/group/media#aimage_getplanedata
+    pub fn get_plane_data(&self, plane: i32) -> MediaResult<&[u8]> {
+        let (mut data, mut len) = (null_mut(), 0);
+        let status = unsafe { AImage_getPlaneData(self.handle, plane, &mut data, &mut len) };
+        if status != media_status_t::AMEDIA_OK {
+            return Err(status.into());
+        }
+        // The buffer stays valid until the image is deleted.
+        Ok(unsafe { core::slice::from_raw_parts(data, len.max(0) as usize) })
+    }
 }
@@ -69,6 +69,26 @@ impl ImageReader {
         Ok(this)
     }
 
+    /// A reader without an image listener, for polling with
+    /// `acquire_latest_image`, holding at most `max_images` at a time.
+    pub fn polled(dimensions: (u32, u32), format: i32, max_images: i32) -> MediaResult<Self> {
+        let (width, height) = dimensions;
+        let mut this = Self::default();
+        let status = unsafe {
+            AImageReader_new(
+                width as _,
+                height as _,
+                format,
+                max_images,
+                &mut this.handle,
+            )
+        };
+        if status != media_status_t::AMEDIA_OK {
+            return Err(status.into());
+        }
+        Ok(this)
+    }
+
     /// See: https://developer.android.com/ndk/reference/group/media#aimagereader_getformat
     pub fn get_format(&self) -> MediaResult<i32> {
         let mut result = 0;
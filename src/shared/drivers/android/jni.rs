@@ -0,0 +1,538 @@
+// This is free and unencumbered software released into the public domain.
+
+use super::{AndroidCameraDriver, NativeWindow};
+use crate::shared::{
+    Camera, CameraConfig, CameraError, CameraEvent, Frame, PhotoFormat, PixelFormat, open_camera,
+};
+use core::ops::{Deref, DerefMut};
+use jni::{
+    JNIEnv, JavaVM,
+    objects::{GlobalRef, JClass, JObject, JString, JValue},
+    sys::{jboolean, jbyteArray, jdouble, jint, jlong, jlongArray},
+};
+use ndk_sys::ANativeWindow_fromSurface;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+const ILLEGAL_STATE: &str = "java/lang/IllegalStateException";
+
+fn throw(env: &mut JNIEnv, err: impl core::fmt::Display) {
+    let _ = env.throw_new(ILLEGAL_STATE, err.to_string());
+}
+
+/// What `nativeOpen` returns a pointer to.
+///
+/// Every entry point but `nativeClose` may be called from any thread: each
+/// locks the camera for the duration of the call, so concurrent calls take
+/// turns. `nativeClose` frees the handle, so the caller must make sure no
+/// other call is in progress or follows it; `AsimovCamera` holds a
+/// read-write lock for that.
+struct Handle {
+    camera: Mutex<Option<Camera>>,
+    /// The format the frame callback receives frames in, where they can be
+    /// converted; `None` passes them on as captured.
+    format: Option<PixelFormat>,
+}
+
+/// The camera behind a handle, locked until dropped.
+struct CameraGuard<'a>(MutexGuard<'a, Option<Camera>>);
+
+impl Deref for CameraGuard<'_> {
+    type Target = Camera;
+
+    fn deref(&self) -> &Camera {
+        self.0.as_ref().expect("locked cameras are open")
+    }
+}
+
+impl DerefMut for CameraGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Camera {
+        self.0.as_mut().expect("locked cameras are open")
+    }
+}
+
+/// # Safety
+/// `handle` must be zero or a pointer returned by `nativeOpen` that has not
+/// yet been passed to `nativeClose`.
+unsafe fn handle_ref<'a>(handle: jlong) -> Option<&'a Handle> {
+    unsafe { (handle as *const Handle).as_ref() }
+}
+
+/// Locks the camera behind `handle`, unless it's closed.
+///
+/// # Safety
+/// As for [`handle_ref`].
+unsafe fn lock_camera<'a>(handle: jlong) -> Option<CameraGuard<'a>> {
+    let handle = unsafe { handle_ref(handle) }?;
+    let camera = handle.camera.lock().unwrap_or_else(|p| p.into_inner());
+    camera.is_some().then(|| CameraGuard(camera))
+}
+
+fn pixel_format_code(fmt: PixelFormat) -> jint {
+    match fmt {
+        PixelFormat::Rgb8 => 0,
+        PixelFormat::Bgra8 => 1,
+        PixelFormat::Rgba8 => 2,
+        PixelFormat::Gray16 => 3,
+        PixelFormat::Z16 => 4,
+        PixelFormat::P010 => 5,
+        PixelFormat::Rgba1010102 => 6,
+    }
+}
+
+/// The format a `pixel_format_code` names; `-1` leaves it to the camera.
+fn pixel_format_from_code(code: jint) -> Result<Option<PixelFormat>, CameraError> {
+    Ok(Some(match code {
+        -1 => return Ok(None),
+        0 => PixelFormat::Rgb8,
+        1 => PixelFormat::Bgra8,
+        2 => PixelFormat::Rgba8,
+        3 => PixelFormat::Gray16,
+        4 => PixelFormat::Z16,
+        5 => PixelFormat::P010,
+        6 => PixelFormat::Rgba1010102,
+        _ => {
+            return Err(CameraError::invalid_config(format!(
+                "invalid pixel format code {code}"
+            )));
+        },
+    }))
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativeOpen(
+    mut env: JNIEnv,
+    _class: JClass,
+    device: JString,
+    width: jint,
+    height: jint,
+    fps: jdouble,
+    format: jint,
+) -> jlong {
+    let format = match pixel_format_from_code(format) {
+        Ok(format) => format,
+        Err(e) => {
+            throw(&mut env, e);
+            return 0;
+        },
+    };
+    let mut config = CameraConfig::new(width.max(1) as u32, height.max(1) as u32, fps);
+    if let Some(format) = format {
+        config = config.with_pixel_format(format);
+    }
+    if !device.is_null() {
+        match env.get_string(&device) {
+            Ok(s) => {
+                let s: String = s.into();
+                if !s.is_empty() {
+                    config = config.with_device(s);
+                }
+            },
+            Err(e) => {
+                throw(&mut env, e);
+                return 0;
+            },
+        }
+    }
+
+    match open_camera("", config) {
+        Ok(camera) => Box::into_raw(Box::new(Handle {
+            camera: Mutex::new(Some(camera)),
+            format,
+        })) as jlong,
+        Err(e) => {
+            throw(&mut env, e);
+            0
+        },
+    }
+}
+
+/// Registers `callback.onFrame(ByteBuffer, width, height, stride, format, timestampNs)`.
+///
+/// The `ByteBuffer` is a read-only view of the frame memory and is only valid
+/// for the duration of the call; copy it if it must outlive the callback.
+/// Frames are converted into the format passed to `nativeOpen` where the
+/// camera delivers another; `format` is always the buffer's actual one.
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativeSetFrameCallback(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    callback: JObject,
+) {
+    let Some(format) = (unsafe { handle_ref(handle) }).map(|handle| handle.format) else {
+        throw(&mut env, CameraError::Closed);
+        return;
+    };
+    let Some(camera) = (unsafe { lock_camera(handle) }) else {
+        throw(&mut env, CameraError::Closed);
+        return;
+    };
+
+    let (vm, callback) = match (env.get_java_vm(), env.new_global_ref(callback)) {
+        (Ok(vm), Ok(callback)) => (Arc::new(vm), callback),
+        (Err(e), _) | (_, Err(e)) => {
+            throw(&mut env, e);
+            return;
+        },
+    };
+
+    camera.add_sink(Arc::new(move |frame: Frame| {
+        let frame = match format.map(|format| frame.to_pixel_format(format)) {
+            Some(Ok(converted)) => converted,
+            _ => frame,
+        };
+        deliver_frame(&vm, &callback, &frame);
+    }));
+}
+
+fn deliver_frame(vm: &JavaVM, callback: &GlobalRef, frame: &Frame) {
+    let Ok(mut env) = vm.attach_current_thread_as_daemon() else {
+        return;
+    };
+
+    let _ = env.with_local_frame(4, |env| -> jni::errors::Result<()> {
+        // The JVM only reads from the buffer while `onFrame` runs, and the
+        // frame outlives that call, so lending the pointer is sound.
+        let buffer = unsafe {
+            env.new_direct_byte_buffer(frame.data.as_ptr() as *mut u8, frame.data.len())?
+        };
+        let result = env.call_method(
+            callback.as_obj(),
+            "onFrame",
+            "(Ljava/nio/ByteBuffer;IIIIJ)V",
+            &[
+                JValue::Object(&buffer),
+                JValue::Int(frame.width as jint),
+                JValue::Int(frame.height as jint),
+                JValue::Int(frame.stride as jint),
+                JValue::Int(pixel_format_code(frame.pixel_format)),
+                JValue::Long(frame.timestamp_ns as jlong),
+            ],
+        );
+        if result.is_err() && env.exception_check()? {
+            env.exception_describe()?;
+            env.exception_clear()?;
+        }
+        Ok(())
+    });
+}
+
+/// Registers `callback.onEvent(kind, message)`, called on a thread of its
+/// own for every `CameraEvent`; `kind` is the variant's name, such as
+/// `Error` or `Stalled`. Can only be set once per camera.
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativeSetEventCallback(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    callback: JObject,
+) {
+    let Some(mut camera) = (unsafe { lock_camera(handle) }) else {
+        throw(&mut env, CameraError::Closed);
+        return;
+    };
+    let (vm, callback) = match (env.get_java_vm(), env.new_global_ref(callback)) {
+        (Ok(vm), Ok(callback)) => (vm, callback),
+        (Err(e), _) | (_, Err(e)) => {
+            throw(&mut env, e);
+            return;
+        },
+    };
+    let Some(events) = camera.take_events() else {
+        throw(
+            &mut env,
+            CameraError::invalid_config("the event callback is already set"),
+        );
+        return;
+    };
+    // Ends once the camera is closed and its senders are gone.
+    let spawned = std::thread::Builder::new()
+        .name("asimov-camera-events".into())
+        .spawn(move || {
+            for event in events {
+                deliver_event(&vm, &callback, &event);
+            }
+        });
+    if let Err(e) = spawned {
+        throw(&mut env, e);
+    }
+}
+
+fn deliver_event(vm: &JavaVM, callback: &GlobalRef, event: &CameraEvent) {
+    let Ok(mut env) = vm.attach_current_thread_as_daemon() else {
+        return;
+    };
+    let (kind, message) = describe_event(event);
+    let _ = env.with_local_frame(4, |env| -> jni::errors::Result<()> {
+        let kind = env.new_string(kind)?;
+        let message = env.new_string(message)?;
+        let result = env.call_method(
+            callback.as_obj(),
+            "onEvent",
+            "(Ljava/lang/String;Ljava/lang/String;)V",
+            &[JValue::Object(&kind), JValue::Object(&message)],
+        );
+        if result.is_err() && env.exception_check()? {
+            env.exception_describe()?;
+            env.exception_clear()?;
+        }
+        Ok(())
+    });
+}
+
+/// The event's name and a human-readable description of it.
+fn describe_event(event: &CameraEvent) -> (&'static str, String) {
+    match event {
+        CameraEvent::Started { .. } => ("Started", String::new()),
+        CameraEvent::Stopped { stats, .. } => (
+            "Stopped",
+            format!(
+                "{} frames captured, {} delivered, {} dropped",
+                stats.frames_captured, stats.frames_delivered, stats.frames_dropped
+            ),
+        ),
+        CameraEvent::FrameDropped { .. } => ("FrameDropped", String::new()),
+        CameraEvent::Warning { message, .. } => ("Warning", message.clone()),
+        CameraEvent::PipelineChanged { label, sinks, .. } => {
+            ("PipelineChanged", format!("{label} ({sinks} sinks)"))
+        },
+        CameraEvent::PrivacyChanged { active, .. } => ("PrivacyChanged", active.to_string()),
+        CameraEvent::PauseChanged { paused, .. } => ("PauseChanged", paused.to_string()),
+        CameraEvent::DeviceChanged { device, .. } => ("DeviceChanged", device.clone()),
+        CameraEvent::Backpressure {
+            depth,
+            capacity,
+            after,
+            ..
+        } => (
+            "Backpressure",
+            format!(
+                "{depth} of {capacity} frames queued for {} ms",
+                after.as_millis()
+            ),
+        ),
+        CameraEvent::LowDiskSpace {
+            path, available, ..
+        } => (
+            "LowDiskSpace",
+            format!("{available} bytes free on {}", path.display()),
+        ),
+        CameraEvent::Stalled { after, .. } => {
+            ("Stalled", format!("no frame for {} ms", after.as_millis()))
+        },
+        CameraEvent::FormatChanged {
+            width, height, fps, ..
+        } => ("FormatChanged", format!("{width}x{height} at {fps} fps")),
+        CameraEvent::Downgraded {
+            reason,
+            width,
+            height,
+            fps,
+            ..
+        } => (
+            "Downgraded",
+            format!("{reason}; now {width}x{height} at {fps} fps"),
+        ),
+        CameraEvent::AutoLockChanged {
+            control, locked, ..
+        } => (
+            "AutoLockChanged",
+            format!(
+                "auto {control} {}",
+                if *locked { "locked" } else { "released" }
+            ),
+        ),
+        CameraEvent::EndOfStream { .. } => ("EndOfStream", String::new()),
+        CameraEvent::Observed {
+            analyzer,
+            observations,
+            ..
+        } => (
+            "Observed",
+            format!("{} observations from {analyzer}", observations.len()),
+        ),
+        CameraEvent::Error { error, .. } => ("Error", error.to_string()),
+    }
+}
+
+/// Returns `[framesCaptured, framesDropped, framesDelivered]`.
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativeGetStats(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jlongArray {
+    let Some(camera) = (unsafe { lock_camera(handle) }) else {
+        throw(&mut env, CameraError::Closed);
+        return core::ptr::null_mut();
+    };
+    let stats = camera.stats();
+    let values = [
+        stats.frames_captured as jlong,
+        stats.frames_dropped as jlong,
+        stats.frames_delivered as jlong,
+    ];
+    let array = match env.new_long_array(values.len() as jint) {
+        Ok(array) => array,
+        Err(e) => {
+            throw(&mut env, e);
+            return core::ptr::null_mut();
+        },
+    };
+    if let Err(e) = env.set_long_array_region(&array, 0, &values) {
+        throw(&mut env, e);
+        return core::ptr::null_mut();
+    }
+    array.into_raw()
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativeSetPreviewSurface(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    surface: JObject,
+) {
+    let Some(mut camera) = (unsafe { lock_camera(handle) }) else {
+        throw(&mut env, CameraError::Closed);
+        return;
+    };
+
+    let window =
+        unsafe { ANativeWindow_fromSurface(env.get_raw() as *mut _, surface.as_raw() as _) };
+    if window.is_null() {
+        throw(
+            &mut env,
+            CameraError::invalid_config("surface has no native window"),
+        );
+        return;
+    }
+
+    let window = NativeWindow {
+        handle: window,
+        owned: true,
+    };
+    match camera.driver_as_mut::<AndroidCameraDriver>() {
+        Some(driver) => driver.set_preview_window(window),
+        None => throw(&mut env, CameraError::NotApplicable),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativeStart(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jboolean {
+    let Some(mut camera) = (unsafe { lock_camera(handle) }) else {
+        throw(&mut env, CameraError::Closed);
+        return 0;
+    };
+    match camera.start() {
+        Ok(()) => 1,
+        Err(e) => {
+            throw(&mut env, e);
+            0
+        },
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativePause(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jboolean {
+    set_paused(&mut env, handle, true)
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativeResume(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jboolean {
+    set_paused(&mut env, handle, false)
+}
+
+fn set_paused(env: &mut JNIEnv, handle: jlong, paused: bool) -> jboolean {
+    let Some(mut camera) = (unsafe { lock_camera(handle) }) else {
+        throw(env, CameraError::Closed);
+        return 0;
+    };
+    let result = if paused {
+        camera.pause()
+    } else {
+        camera.resume()
+    };
+    match result {
+        Ok(()) => 1,
+        Err(e) => {
+            throw(env, e);
+            0
+        },
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativeCapturePhoto(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    heif: jboolean,
+) -> jbyteArray {
+    let Some(mut camera) = (unsafe { lock_camera(handle) }) else {
+        throw(&mut env, CameraError::Closed);
+        return core::ptr::null_mut();
+    };
+    let format = if heif != 0 {
+        PhotoFormat::Heif
+    } else {
+        PhotoFormat::Jpeg
+    };
+    let photo = match camera.capture_photo(format) {
+        Ok(photo) => photo,
+        Err(e) => {
+            throw(&mut env, e);
+            return core::ptr::null_mut();
+        },
+    };
+    match env.byte_array_from_slice(&photo.data) {
+        Ok(array) => array.into_raw(),
+        Err(e) => {
+            throw(&mut env, e);
+            core::ptr::null_mut()
+        },
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativeStop(
+    _env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) {
+    if let Some(mut camera) = unsafe { lock_camera(handle) } {
+        let _ = camera.stop();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_sh_asimov_camera_AsimovCamera_nativeClose(
+    _env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) {
+    if handle == 0 {
+        return;
+    }
+    let handle = unsafe { Box::from_raw(handle as *mut Handle) };
+    // Stop outside the lock, so sinks calling back in get `Closed`
+    // rather than waiting on it.
+    let camera = handle
+        .camera
+        .lock()
+        .unwrap_or_else(|p| p.into_inner())
+        .take();
+    drop(camera);
+}
@@ -1,7 +1,8 @@
 // This is free and unencumbered software released into the public domain.
 
 use crate::shared::{
-    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, FrameMsg,
+    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, CameraPosition,
+    FrameSender,
 };
 use alloc::borrow::Cow;
 use std::{any::Any, sync::mpsc::SyncSender};
@@ -9,7 +10,10 @@ use std::{any::Any, sync::mpsc::SyncSender};
 #[derive(Debug)]
 pub struct AvfCameraDriver {
     _config: CameraConfig,
-    _frame_tx: SyncSender<FrameMsg>,
+    /// From `avf:front` or `avf:back`; device lookup should match it
+    /// against `AVCaptureDevice.position` instead of the index.
+    _position: Option<CameraPosition>,
+    _frame_tx: FrameSender,
     _events_tx: SyncSender<CameraEvent>,
 }
 
@@ -23,10 +27,15 @@ impl AvfCameraDriver {
     pub fn open(
         _input_url: impl AsRef<str>,
         config: CameraConfig,
-        frame_tx: SyncSender<FrameMsg>,
+        frame_tx: FrameSender,
         events_tx: SyncSender<CameraEvent>,
     ) -> Result<Self, CameraError> {
+        let position = config
+            .device
+            .as_deref()
+            .and_then(CameraPosition::from_device_id);
         Ok(Self {
+            _position: position,
             _config: config,
             _frame_tx: frame_tx,
             _events_tx: events_tx,
@@ -49,6 +58,19 @@ impl CameraDriver for AvfCameraDriver {
         Ok(())
     }
 
+    fn switch_device(&mut self, device: &str) -> Result<(), CameraError> {
+        // Nothing is ever running yet, so only the selection changes.
+        self._position = CameraPosition::from_device_id(device);
+        self._config.device = Some(device.to_string());
+        Ok(())
+    }
+
+    fn reconfigure(&mut self, config: &CameraConfig) -> Result<(), CameraError> {
+        // Nothing is running to renegotiate; the next session uses the format.
+        self._config = self._config.with_format_of(config);
+        Ok(())
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -1,14 +1,14 @@
 // This is free and unencumbered software released into the public domain.
 
 use crate::shared::{
-    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, FrameMsg,
+    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, FrameSender,
 };
 use std::{any::Any, sync::mpsc::SyncSender};
 
 #[derive(Debug)]
 pub struct DshowCameraDriver {
     _config: CameraConfig,
-    _frame_tx: SyncSender<FrameMsg>,
+    _frame_tx: FrameSender,
     _events_tx: SyncSender<CameraEvent>,
 }
 
@@ -16,7 +16,7 @@ impl DshowCameraDriver {
     pub fn open(
         _input_url: impl AsRef<str>,
         config: CameraConfig,
-        frame_tx: SyncSender<FrameMsg>,
+        frame_tx: FrameSender,
         events_tx: SyncSender<CameraEvent>,
     ) -> Result<Self, CameraError> {
         Ok(Self {
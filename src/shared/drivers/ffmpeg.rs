@@ -1,10 +1,19 @@
 // This is free and unencumbered software released into the public domain.
 
 use crate::shared::{
-    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, Frame, FrameMsg,
-    try_send_frame,
+    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, CameraMode,
+    CameraPosition, CapturePlan, ColorRange, Colorimetry, Frame, FrameSender, FrameTime,
+    PixelFormat,
+    devices::{
+        parse::{self, ModeList},
+        xu::VendorControl,
+    },
+    join_until, try_send_frame,
 };
 use bytes::Bytes;
+
+#[cfg(feature = "audio")]
+use crate::shared::{AudioConfig, AudioFrame, wall_clock_ns};
 use std::{
     any::Any,
     env,
@@ -16,17 +25,47 @@ use std::{
         mpsc::SyncSender,
     },
     thread::JoinHandle,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant},
 };
 
+mod child;
+pub(crate) use child::spawn_child;
+
+/// How long ffmpeg may take to exit once its output reached EOF.
+const EXIT_GRACE: Duration = Duration::from_secs(1);
+
+/// The URL schemes of network streams, opened by URL rather than through
+/// the platform's capture input.
+const STREAM_SCHEMES: &[&str] = &["onvif", "rtmp", "rtsp", "rtsps", "srt"];
+
+/// Whether `device` is a network stream, e.g. `rtsp://camera/stream` or an
+/// `onvif://` camera, rather than a capture device.
+pub fn is_network_stream(device: &str) -> bool {
+    device
+        .trim()
+        .split_once("://")
+        .is_some_and(|(scheme, _)| STREAM_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()))
+}
+
 pub struct FfmpegCameraDriver {
     config: CameraConfig,
     child: Option<Arc<Mutex<Child>>>,
     stop: Arc<AtomicBool>,
     reader_join: Option<JoinHandle<()>>,
     monitor_join: Option<JoinHandle<()>>,
-    frame_tx: SyncSender<FrameMsg>,
+    frame_tx: FrameSender,
     events_tx: SyncSender<CameraEvent>,
+    #[cfg(feature = "audio")]
+    audio: AudioCapture,
+}
+
+/// The second ffmpeg process capturing `CameraConfig::audio`.
+#[cfg(feature = "audio")]
+#[derive(Default)]
+struct AudioCapture {
+    tx: Option<SyncSender<AudioFrame>>,
+    child: Option<Child>,
+    join: Option<JoinHandle<()>>,
 }
 
 impl core::fmt::Debug for FfmpegCameraDriver {
@@ -42,7 +81,7 @@ impl FfmpegCameraDriver {
     pub fn open(
         _input_url: impl AsRef<str>,
         config: CameraConfig,
-        frame_tx: SyncSender<FrameMsg>,
+        frame_tx: FrameSender,
         events_tx: SyncSender<CameraEvent>,
     ) -> Result<Self, CameraError> {
         Ok(Self {
@@ -53,17 +92,11 @@ impl FfmpegCameraDriver {
             monitor_join: None,
             frame_tx,
             events_tx,
+            #[cfg(feature = "audio")]
+            audio: AudioCapture::default(),
         })
     }
 
-    #[inline]
-    fn now_ns_best_effort() -> u64 {
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_nanos() as u64)
-            .unwrap_or(0)
-    }
-
     fn spawn(&self) -> Result<Child, CameraError> {
         spawn_reader(&self.config)
     }
@@ -73,7 +106,7 @@ impl FfmpegCameraDriver {
             return;
         };
         if let Ok(mut g) = child_arc.lock() {
-            terminate_child(&mut *g);
+            terminate_child(&mut g);
         }
     }
 }
@@ -98,8 +131,11 @@ impl CameraDriver for FfmpegCameraDriver {
 
         let width = self.config.width;
         let height = self.config.height;
-        let stride = width.saturating_mul(3);
-        let frame_size = (stride as usize).saturating_mul(height as usize);
+        let pixel_format = self.config.pixel_format.unwrap_or(PixelFormat::Rgb8);
+        let stride = width.saturating_mul(pixel_format.bytes_per_pixel());
+        let rows = pixel_format.buffer_rows(height);
+        let frame_size = (stride as usize).saturating_mul(rows as usize);
+        let colorimetry = output_colorimetry(pixel_format);
 
         let child_arc = Arc::new(Mutex::new(child));
         self.child = Some(Arc::clone(&child_arc));
@@ -107,6 +143,7 @@ impl CameraDriver for FfmpegCameraDriver {
         let stop = Arc::clone(&self.stop);
         let frame_tx = self.frame_tx.clone();
         let events_tx = self.events_tx.clone();
+        let child_arc1 = Arc::clone(&child_arc);
 
         let reader_join = std::thread::spawn(move || {
             let mut reader = std::io::BufReader::new(stdout);
@@ -115,17 +152,28 @@ impl CameraDriver for FfmpegCameraDriver {
             while !stop.load(Ordering::Relaxed) {
                 match reader.read_exact(&mut buf) {
                     Ok(()) => {
-                        let ts = FfmpegCameraDriver::now_ns_best_effort();
-                        let frame =
-                            Frame::new_rgb8(Bytes::copy_from_slice(&buf), width, height, stride)
-                                .with_timestamp_ns(ts);
+                        let ts = FrameTime::now();
+                        if pixel_format == PixelFormat::Rgba1010102 {
+                            // x2bgr10le leaves the alpha bits zero; make it opaque.
+                            buf.iter_mut().skip(3).step_by(4).for_each(|b| *b |= 0xC0);
+                        }
+                        let data = Bytes::copy_from_slice(&buf);
+                        let frame = Frame::new(data, width, height, stride, pixel_format)
+                            .with_colorimetry(colorimetry)
+                            .with_time(ts);
                         try_send_frame(&frame_tx, &events_tx, CameraBackend::Ffmpeg, frame);
                     },
+                    // Killing ffmpeg on stop closes the pipe; that's not an error.
+                    Err(_) if stop.load(Ordering::Relaxed) => break,
                     Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                        let _ = events_tx.try_send(CameraEvent::Error {
-                            backend: CameraBackend::Ffmpeg,
-                            error: CameraError::other("ffmpeg stream ended (EOF)"),
-                        });
+                        if exited_cleanly(&child_arc1) {
+                            frame_tx.end_of_stream();
+                        } else {
+                            let _ = events_tx.try_send(CameraEvent::Error {
+                                backend: CameraBackend::Ffmpeg,
+                                error: CameraError::other("ffmpeg stream ended (EOF)"),
+                            });
+                        }
                         break;
                     },
                     Err(e) => {
@@ -155,8 +203,10 @@ impl CameraDriver for FfmpegCameraDriver {
 
                 match status {
                     Ok(Some(s)) => {
-                        // If we are stopping intentionally, don't spam as "error".
-                        if stop2.load(Ordering::Relaxed) {
+                        // If we are stopping intentionally, don't spam as "error";
+                        // a clean exit is the end of the input, which the reader
+                        // thread reports once it has read the last frame.
+                        if stop2.load(Ordering::Relaxed) || s.success() {
                             break;
                         }
                         let _ = events_tx2.try_send(CameraEvent::Error {
@@ -183,6 +233,12 @@ impl CameraDriver for FfmpegCameraDriver {
         self.reader_join = Some(reader_join);
         self.monitor_join = Some(monitor_join);
 
+        #[cfg(feature = "audio")]
+        if let Err(err) = self.start_audio() {
+            let _ = self.stop();
+            return Err(err);
+        }
+
         Ok(())
     }
 
@@ -190,13 +246,127 @@ impl CameraDriver for FfmpegCameraDriver {
         self.stop.store(true, Ordering::Relaxed);
         self.stop_child();
 
-        if let Some(j) = self.reader_join.take() {
-            let _ = j.join();
+        // Killing ffmpeg normally unblocks the reader with EOF, but a wedged
+        // pipe (e.g. held open by a grandchild) must not hang shutdown.
+        #[cfg(feature = "audio")]
+        let audio_join = self.stop_audio();
+        #[cfg(not(feature = "audio"))]
+        let audio_join = None;
+
+        let deadline = Instant::now() + self.config.stop_timeout;
+        let mut joined = true;
+        for j in [
+            self.reader_join.take(),
+            self.monitor_join.take(),
+            audio_join,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            joined &= join_until(j, deadline);
+        }
+        if !joined {
+            let _ = self.events_tx.try_send(CameraEvent::Warning {
+                backend: CameraBackend::Ffmpeg,
+                message: format!(
+                    "ffmpeg reader did not exit within {:?}; abandoning it",
+                    self.config.stop_timeout
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn switch_device(&mut self, device: &str) -> Result<(), CameraError> {
+        let running = self.child.is_some();
+        if running {
+            self.stop()?;
+        }
+        let previous = self.config.device.replace(device.to_string());
+        if !running {
+            return Ok(());
+        }
+        if let Err(err) = self.start() {
+            self.config.device = previous;
+            let _ = self.start();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn reconfigure(&mut self, config: &CameraConfig) -> Result<(), CameraError> {
+        let running = self.child.is_some();
+        if running {
+            self.stop()?;
+        }
+        let reconfigured = self.config.with_format_of(config);
+        let previous = core::mem::replace(&mut self.config, reconfigured);
+        if !running {
+            return Ok(());
+        }
+        if let Err(err) = self.start() {
+            self.config = previous;
+            let _ = self.start();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Stops (`SIGSTOP`) or continues the ffmpeg processes; the device
+    /// stays open, and frames it captures meanwhile are dropped by the
+    /// kernel once ffmpeg's buffers fill.
+    #[cfg(unix)]
+    fn set_paused(&mut self, paused: bool) -> Result<(), CameraError> {
+        if let Some(child) = &self.child {
+            pause_child(&child.lock().unwrap_or_else(|p| p.into_inner()), paused)?;
+        }
+        #[cfg(feature = "audio")]
+        if let Some(child) = &self.audio.child {
+            pause_child(child, paused)?;
         }
-        if let Some(j) = self.monitor_join.take() {
-            let _ = j.join();
+        Ok(())
+    }
+
+    fn modes(&mut self) -> Result<Vec<CameraMode>, CameraError> {
+        let device = self.config.device.as_deref().unwrap_or("").trim();
+        let list = list_modes(&get_input_device(device))?;
+        for message in list.warnings {
+            let _ = self.events_tx.try_send(CameraEvent::Warning {
+                backend: CameraBackend::Ffmpeg,
+                message,
+            });
         }
+        Ok(list.modes)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn vendor_control(&mut self, control: &VendorControl) -> Result<Vec<u8>, CameraError> {
+        use crate::shared::devices::xu::{uvc_device_path, uvc_xu_query};
+        let device = self.config.device.as_deref().unwrap_or("").trim();
+        uvc_xu_query(&uvc_device_path(device)?, control)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn lock_auto(
+        &mut self,
+        control: crate::shared::AutoControl,
+        locked: bool,
+    ) -> Result<(), CameraError> {
+        use crate::shared::devices::{controls::v4l2_lock_auto, xu::uvc_device_path};
+        let device = self.config.device.as_deref().unwrap_or("").trim();
+        v4l2_lock_auto(&uvc_device_path(device)?, control, locked)
+    }
 
+    fn plan(&self) -> Result<Option<CapturePlan>, CameraError> {
+        let mut command = vec!["ffmpeg".to_string()];
+        command.extend(reader_args(&self.config)?);
+        Ok(Some(CapturePlan::Command(command)))
+    }
+
+    #[cfg(feature = "audio")]
+    fn set_audio_sender(&mut self, tx: SyncSender<AudioFrame>) -> Result<(), CameraError> {
+        self.audio.tx = Some(tx);
         Ok(())
     }
 
@@ -209,6 +379,61 @@ impl CameraDriver for FfmpegCameraDriver {
     }
 }
 
+#[cfg(feature = "audio")]
+impl FfmpegCameraDriver {
+    fn start_audio(&mut self) -> Result<(), CameraError> {
+        let (Some(audio), Some(tx)) = (self.config.audio.clone(), self.audio.tx.clone()) else {
+            return Ok(());
+        };
+
+        let mut child = spawn_audio_reader(&audio, self.config.diagnostics)?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| CameraError::other("ffmpeg audio stdout not piped"))?;
+        self.audio.child = Some(child);
+
+        let stop = Arc::clone(&self.stop);
+        let events_tx = self.events_tx.clone();
+        let chunk_ns = audio.chunk.as_nanos() as u64;
+        self.audio.join = Some(std::thread::spawn(move || {
+            let mut reader = std::io::BufReader::new(stdout);
+            let mut buf = vec![0u8; audio.chunk_bytes()];
+            while !stop.load(Ordering::Relaxed) {
+                match reader.read_exact(&mut buf) {
+                    Ok(()) => {
+                        let now = wall_clock_ns();
+                        // A full queue means nobody is draining it; drop the chunk.
+                        let _ = tx.try_send(AudioFrame {
+                            data: Bytes::copy_from_slice(&buf),
+                            sample_rate: audio.sample_rate,
+                            channels: audio.channels,
+                            timestamp_ns: now.saturating_sub(chunk_ns),
+                        });
+                    },
+                    Err(_) if stop.load(Ordering::Relaxed) => break,
+                    Err(e) => {
+                        let _ = events_tx.try_send(CameraEvent::Error {
+                            backend: CameraBackend::Ffmpeg,
+                            error: CameraError::driver("ffmpeg audio read", e),
+                        });
+                        break;
+                    },
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    /// Terminates the audio process, returning its reader thread to join.
+    fn stop_audio(&mut self) -> Option<JoinHandle<()>> {
+        if let Some(mut child) = self.audio.child.take() {
+            terminate_child(&mut child);
+        }
+        self.audio.join.take()
+    }
+}
+
 impl Drop for FfmpegCameraDriver {
     fn drop(&mut self) {
         let _ = self.stop();
@@ -216,7 +441,58 @@ impl Drop for FfmpegCameraDriver {
 }
 
 fn spawn_reader(config: &CameraConfig) -> Result<Child, CameraError> {
+    let ffargs = reader_args(config)?;
+    let stderr = if config.diagnostics || env::var_os("ASIMOV_CAMERA_FFMPEG_STDERR").is_some() {
+        Stdio::inherit()
+    } else {
+        Stdio::null()
+    };
+
+    let mut command = Command::new("ffmpeg");
+    command.args(&ffargs).stdout(Stdio::piped()).stderr(stderr);
+    spawn_child(command).map_err(|e| CameraError::driver("spawning ffmpeg", e))
+}
+
+/// Waits briefly for ffmpeg to exit after it closed its output; a clean
+/// exit means the input ended rather than failed.
+fn exited_cleanly(child: &Mutex<Child>) -> bool {
+    let deadline = Instant::now() + EXIT_GRACE;
+    loop {
+        let status = child.lock().unwrap_or_else(|p| p.into_inner()).try_wait();
+        match status {
+            Ok(Some(status)) => return status.success(),
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            _ => return false,
+        }
+    }
+}
+
+/// The arguments ffmpeg captures `config` to raw frames on stdout with.
+fn reader_args(config: &CameraConfig) -> Result<Vec<String>, CameraError> {
     let device = config.device.as_deref().unwrap_or("").trim();
+    if let Some(position) = CameraPosition::from_device_id(device) {
+        return Err(CameraError::unsupported(format!(
+            "ffmpeg can't select the {position} camera by position; use its index or name"
+        )));
+    }
+    let pix_fmt = match config.pixel_format.unwrap_or(PixelFormat::Rgb8) {
+        PixelFormat::Rgb8 => "rgb24",
+        PixelFormat::Bgra8 => "bgra",
+        PixelFormat::Rgba8 => "rgba",
+        PixelFormat::Gray16 => "gray16le",
+        PixelFormat::P010 => "p010le",
+        // The same layout, with padding where the alpha goes.
+        PixelFormat::Rgba1010102 => "x2bgr10le",
+        // ffmpeg's v4l2 input has no mapping for the Z16 fourcc.
+        PixelFormat::Z16 => {
+            return Err(CameraError::unsupported(
+                "ffmpeg can't capture Z16 depth; use a native backend (v4l2, avf)",
+            ));
+        },
+    };
+    if is_network_stream(device) {
+        return stream_args(config, device, pix_fmt);
+    }
     let input_device = get_input_device(device);
 
     // On macOS/AVFoundation, many devices reject "odd" framerates even when listed.
@@ -258,24 +534,141 @@ fn spawn_reader(config: &CameraConfig) -> Result<Child, CameraError> {
         "-i".into(),
         input_device,
         "-pix_fmt".into(),
-        "rgb24".into(),
+        pix_fmt.into(),
         "-f".into(),
         "rawvideo".into(),
         "pipe:1".into(),
     ]);
+    Ok(ffargs)
+}
 
-    let stderr = if config.diagnostics || env::var_os("ASIMOV_CAMERA_FFMPEG_STDERR").is_some() {
+/// The arguments ffmpeg receives the network stream `device` with, scaled
+/// to the configured size, since a camera sends what it sends.
+fn stream_args(
+    config: &CameraConfig,
+    device: &str,
+    pix_fmt: &str,
+) -> Result<Vec<String>, CameraError> {
+    let url = stream_url(device)?;
+    let mut ffargs: Vec<String> = vec![
+        "-hide_banner".into(),
+        "-nostdin".into(),
+        "-nostats".into(),
+        "-loglevel".into(),
+        "error".into(),
+    ];
+    // RTSP over UDP loses frames to any lost packet; cameras all offer TCP.
+    if url.starts_with("rtsp://") || url.starts_with("rtsps://") {
+        ffargs.extend(["-rtsp_transport".into(), "tcp".into()]);
+    }
+    ffargs.extend([
+        "-i".into(),
+        url,
+        "-an".into(),
+        "-vf".into(),
+        format!("scale={}:{}", config.width, config.height),
+        "-pix_fmt".into(),
+        pix_fmt.into(),
+        "-f".into(),
+        "rawvideo".into(),
+        "pipe:1".into(),
+    ]);
+    Ok(ffargs)
+}
+
+/// The URL ffmpeg opens for the network stream `device`: an `onvif://`
+/// camera's RTSP URL, asked of the camera on each start, or `device`.
+fn stream_url(device: &str) -> Result<String, CameraError> {
+    #[cfg(feature = "onvif")]
+    if device.starts_with(crate::shared::ONVIF_PREFIX) {
+        return crate::shared::OnvifClient::from_device(device)?.rtsp_url();
+    }
+    #[cfg(not(feature = "onvif"))]
+    if device.starts_with("onvif://") {
+        return Err(CameraError::unsupported(
+            "onvif:// devices need a build with --features=onvif",
+        ));
+    }
+    Ok(device.to_string())
+}
+
+/// What `-pix_fmt` output looks like: swscale expands whatever range the
+/// camera has to full-range RGB, but doesn't say which matrix it decoded
+/// with (the input's tag, or BT.601 when it has none).
+fn output_colorimetry(pixel_format: PixelFormat) -> Colorimetry {
+    match pixel_format {
+        PixelFormat::Rgb8 | PixelFormat::Bgra8 | PixelFormat::Rgba8 | PixelFormat::Rgba1010102 => {
+            Colorimetry {
+                range: Some(ColorRange::Full),
+                ..Colorimetry::UNKNOWN
+            }
+        },
+        // P010 keeps the camera's YUV, whose tags ffmpeg doesn't pass on.
+        PixelFormat::Gray16 | PixelFormat::Z16 | PixelFormat::P010 => Colorimetry::UNKNOWN,
+    }
+}
+
+#[cfg(feature = "audio")]
+fn spawn_audio_reader(audio: &AudioConfig, diagnostics: bool) -> Result<Child, CameraError> {
+    let (format, input) = audio_input(audio.device.trim());
+    let ffargs: Vec<String> = vec![
+        "-hide_banner".into(),
+        "-nostdin".into(),
+        "-nostats".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-f".into(),
+        format.into(),
+        "-i".into(),
+        input,
+        "-vn".into(),
+        "-ac".into(),
+        audio.channels.to_string(),
+        "-ar".into(),
+        audio.sample_rate.to_string(),
+        "-f".into(),
+        "s16le".into(),
+        "pipe:1".into(),
+    ];
+
+    let stderr = if diagnostics || env::var_os("ASIMOV_CAMERA_FFMPEG_STDERR").is_some() {
         Stdio::inherit()
     } else {
         Stdio::null()
     };
 
-    Command::new("ffmpeg")
-        .args(&ffargs)
-        .stdout(Stdio::piped())
-        .stderr(stderr)
-        .spawn()
-        .map_err(|e| CameraError::driver("spawning ffmpeg", e))
+    let mut command = Command::new("ffmpeg");
+    command.args(&ffargs).stdout(Stdio::piped()).stderr(stderr);
+    spawn_child(command).map_err(|e| CameraError::driver("spawning ffmpeg for audio", e))
+}
+
+/// The ffmpeg input format and device for an `AudioConfig::device`.
+#[cfg(all(feature = "audio", target_os = "linux"))]
+fn audio_input(device: &str) -> (&'static str, String) {
+    if let Some(alsa) = device.strip_prefix("alsa:") {
+        return ("alsa", alsa.to_string());
+    }
+    let pulse = device.strip_prefix("pulse:").unwrap_or(device);
+    (
+        "pulse",
+        if pulse.is_empty() { "default" } else { pulse }.to_string(),
+    )
+}
+
+#[cfg(all(feature = "audio", target_os = "macos"))]
+fn audio_input(device: &str) -> (&'static str, String) {
+    let index = device.strip_prefix("avf:").unwrap_or(device);
+    (
+        "avfoundation",
+        format!(":{}", if index.is_empty() { "0" } else { index }),
+    )
+}
+
+#[cfg(all(feature = "audio", target_os = "windows"))]
+fn audio_input(device: &str) -> (&'static str, String) {
+    let device = device.strip_prefix("dshow:").unwrap_or(device);
+    let name = device.strip_prefix("audio=").unwrap_or(device);
+    ("dshow", format!("audio={name}"))
 }
 
 fn format_exit(status: ExitStatus) -> String {
@@ -286,11 +679,27 @@ fn format_exit(status: ExitStatus) -> String {
     }
 }
 
-fn terminate_child(child: &mut Child) {
+/// Suspends or continues `child` with `SIGSTOP`/`SIGCONT`.
+#[cfg(unix)]
+pub(crate) fn pause_child(child: &Child, paused: bool) -> Result<(), CameraError> {
+    let signal = if paused { libc::SIGSTOP } else { libc::SIGCONT };
+    // SAFETY: signals the process we spawned and haven't reaped.
+    if unsafe { libc::kill(child.id() as i32, signal) } != 0 {
+        return Err(CameraError::driver(
+            "signaling ffmpeg",
+            std::io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn terminate_child(child: &mut Child) {
     #[cfg(unix)]
     {
         unsafe {
             let _ = libc::kill(child.id() as i32, libc::SIGTERM);
+            // A paused child only acts on SIGTERM once continued.
+            let _ = libc::kill(child.id() as i32, libc::SIGCONT);
         }
         let start = std::time::Instant::now();
         while start.elapsed() < Duration::from_millis(900) {
@@ -314,6 +723,45 @@ fn terminate_child(child: &mut Child) {
     }
 }
 
+/// Runs ffmpeg for a listing it prints to stderr.
+fn ffmpeg_stderr(args: &[&str]) -> Result<String, CameraError> {
+    let out = Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| CameraError::driver("running ffmpeg", e))?;
+    Ok(String::from_utf8_lossy(&out.stderr).into_owned())
+}
+
+/// AVFoundation lists a device's modes when asked for one it doesn't have.
+#[cfg(target_os = "macos")]
+fn list_modes(input: &str) -> Result<ModeList, CameraError> {
+    let stderr = ffmpeg_stderr(&[
+        "-f",
+        "avfoundation",
+        "-video_size",
+        "1x1",
+        "-framerate",
+        "1",
+        "-i",
+        input,
+    ])?;
+    Ok(parse::avfoundation_modes(&stderr))
+}
+
+#[cfg(target_os = "linux")]
+fn list_modes(input: &str) -> Result<ModeList, CameraError> {
+    let stderr = ffmpeg_stderr(&["-f", "v4l2", "-list_formats", "all", "-i", input])?;
+    Ok(parse::v4l2_modes(&stderr))
+}
+
+#[cfg(target_os = "windows")]
+fn list_modes(input: &str) -> Result<ModeList, CameraError> {
+    let stderr = ffmpeg_stderr(&["-f", "dshow", "-list_options", "true", "-i", input])?;
+    Ok(parse::dshow_modes(&stderr))
+}
+
 #[cfg(target_os = "macos")]
 fn ffmpeg_format() -> &'static str {
     "avfoundation"
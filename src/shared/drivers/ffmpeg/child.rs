@@ -0,0 +1,195 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Keeps capture processes from outliving the process that started them,
+//! even when it is killed: a leftover ffmpeg holds on to the camera, and
+//! the next run fails with "device busy".
+//!
+//! - Linux: the child asks for `SIGKILL` when its parent dies
+//!   (`PR_SET_PDEATHSIG`).
+//! - macOS: the child leads its own process group, which a small shell
+//!   watcher in the same group kills once the parent is gone.
+//! - Windows: the child joins a job object that ends it once the parent's
+//!   handle to the job closes, as it does when the parent exits.
+
+use std::{
+    io,
+    process::{Child, Command},
+};
+
+/// Spawns `command` tied to this process's lifetime.
+pub(crate) fn spawn_child(command: Command) -> io::Result<Child> {
+    imp::spawn(command)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::{
+        io,
+        os::unix::process::CommandExt,
+        process::{Child, Command},
+        sync::{OnceLock, mpsc},
+    };
+
+    type SpawnRequest = (Command, mpsc::Sender<io::Result<Child>>);
+
+    pub fn spawn(mut command: Command) -> io::Result<Child> {
+        let parent = std::process::id() as libc::pid_t;
+        // SAFETY: prctl and getppid are async-signal-safe.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL as libc::c_ulong) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // The parent may have died before the prctl.
+                if libc::getppid() != parent {
+                    return Err(io::Error::from_raw_os_error(libc::ESRCH));
+                }
+                Ok(())
+            });
+        }
+        match spawner() {
+            Some(spawner) => {
+                let (reply, rx) = mpsc::channel();
+                spawner
+                    .send((command, reply))
+                    .map_err(|_| io::Error::other("the spawner thread is gone"))?;
+                rx.recv()
+                    .map_err(|_| io::Error::other("the spawner thread is gone"))?
+            },
+            None => command.spawn(),
+        }
+    }
+
+    /// The death signal follows the thread that spawned the child rather
+    /// than the process, so children are spawned from a thread that lives
+    /// as long as the process, not from whichever thread restarts capture.
+    fn spawner() -> Option<&'static mpsc::Sender<SpawnRequest>> {
+        static SPAWNER: OnceLock<Option<mpsc::Sender<SpawnRequest>>> = OnceLock::new();
+        SPAWNER
+            .get_or_init(|| {
+                let (tx, rx) = mpsc::channel::<SpawnRequest>();
+                std::thread::Builder::new()
+                    .name("ffmpeg-spawner".into())
+                    .spawn(move || {
+                        for (mut command, reply) in rx {
+                            let _ = reply.send(command.spawn());
+                        }
+                    })
+                    .ok()?;
+                Some(tx)
+            })
+            .as_ref()
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::{
+        io,
+        os::unix::process::CommandExt,
+        process::{Child, Command, Stdio},
+    };
+
+    /// Polls for the parent (`$0`) once a second while the child (`$1`)
+    /// lives, then kills the child's group, itself included. It runs in the
+    /// background, so its shell exits at once and launchd reaps it.
+    const WATCHER: &str = r#"(while kill -0 "$0" && kill -0 "$1"; do sleep 1; done; kill -s KILL -- -"$1") >/dev/null 2>&1 &"#;
+
+    pub fn spawn(mut command: Command) -> io::Result<Child> {
+        // Its own group also keeps a terminal's Ctrl-C from reaching the
+        // child before the parent stops it.
+        let child = command.process_group(0).spawn()?;
+        // Without a watcher the child still ends on a broken output pipe
+        // once the parent is gone, unless it's paused.
+        let _ = Command::new("/bin/sh")
+            .args(["-c", WATCHER])
+            .arg(std::process::id().to_string())
+            .arg(child.id().to_string())
+            .process_group(child.id() as i32)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        Ok(child)
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{
+        io,
+        os::windows::io::AsRawHandle,
+        process::{Child, Command},
+        sync::OnceLock,
+    };
+
+    mod win32 {
+        pub type Handle = *mut core::ffi::c_void;
+
+        pub const JOB_OBJECT_EXTENDED_LIMIT_INFORMATION: i32 = 9;
+        pub const JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE: u32 = 0x2000;
+
+        /// `JOBOBJECT_EXTENDED_LIMIT_INFORMATION`, of which only the limit
+        /// flags are set; only Windows reads them.
+        #[allow(dead_code)]
+        #[repr(C)]
+        pub struct ExtendedLimits {
+            pub _time_limits: [i64; 2],
+            pub limit_flags: u32,
+            pub _working_set: [usize; 2],
+            pub _active_process_limit: u32,
+            pub _affinity: usize,
+            pub _classes: [u32; 2],
+            pub _io_counters: [u64; 6],
+            pub _memory: [usize; 4],
+        }
+
+        #[link(name = "kernel32")]
+        unsafe extern "system" {
+            pub fn CreateJobObjectW(
+                attributes: *const core::ffi::c_void,
+                name: *const u16,
+            ) -> Handle;
+            pub fn SetInformationJobObject(
+                job: Handle,
+                class: i32,
+                info: *const core::ffi::c_void,
+                length: u32,
+            ) -> i32;
+            pub fn AssignProcessToJobObject(job: Handle, process: Handle) -> i32;
+        }
+    }
+
+    pub fn spawn(mut command: Command) -> io::Result<Child> {
+        let child = command.spawn()?;
+        if let Some(job) = job() {
+            // Older Windows can't nest jobs; the child then ends on a broken
+            // output pipe once the parent is gone.
+            let _ = unsafe { win32::AssignProcessToJobObject(job as _, child.as_raw_handle()) };
+        }
+        Ok(child)
+    }
+
+    /// The kill-on-close job, whose handle stays open for as long as the
+    /// process runs.
+    fn job() -> Option<usize> {
+        static JOB: OnceLock<Option<usize>> = OnceLock::new();
+        *JOB.get_or_init(|| {
+            let job = unsafe { win32::CreateJobObjectW(core::ptr::null(), core::ptr::null()) };
+            if job.is_null() {
+                return None;
+            }
+            let mut limits: win32::ExtendedLimits = unsafe { core::mem::zeroed() };
+            limits.limit_flags = win32::JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            let set = unsafe {
+                win32::SetInformationJobObject(
+                    job,
+                    win32::JOB_OBJECT_EXTENDED_LIMIT_INFORMATION,
+                    (&raw const limits).cast(),
+                    size_of::<win32::ExtendedLimits>() as u32,
+                )
+            };
+            (set != 0).then_some(job as usize)
+        })
+    }
+}
@@ -0,0 +1,487 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{
+    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, CameraPosition, ClockSync,
+    ColorMatrix, ColorRange, Colorimetry, Frame, FrameSender, FrameTime, PixelFormat, join_until,
+    try_send_frame,
+};
+#[cfg(target_os = "linux")]
+use crate::shared::{DmaBufHandle, FrameHandle};
+use bytes::Bytes;
+use gstreamer::{self as gst, prelude::*};
+use gstreamer_app as gst_app;
+use gstreamer_video as gst_video;
+use std::{
+    any::Any,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::SyncSender,
+    },
+    thread::JoinHandle,
+    time::Instant,
+};
+
+/// Builds the capture source element for a device id.
+pub(crate) type SourceFactory = Box<dyn Fn(&str) -> Result<gst::Element, CameraError> + Send>;
+
+/// Captures through `<source> ! videoconvert ! videoscale ! videorate !
+/// appsink`, letting GStreamer negotiate the camera's native format and
+/// convert it to `CameraConfig::pixel_format`.
+pub struct GstreamerCameraDriver {
+    backend: CameraBackend,
+    config: CameraConfig,
+    source: SourceFactory,
+    pipeline: Option<gst::Pipeline>,
+    stop: Arc<AtomicBool>,
+    reader_join: Option<JoinHandle<()>>,
+    frame_tx: FrameSender,
+    events_tx: SyncSender<CameraEvent>,
+}
+
+impl core::fmt::Debug for GstreamerCameraDriver {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("GstreamerCameraDriver")
+            .field("config", &self.config)
+            .field("pipeline", &self.pipeline.as_ref().map(|_| "<pipeline>"))
+            .finish()
+    }
+}
+
+impl GstreamerCameraDriver {
+    pub fn open(
+        _input_url: impl AsRef<str>,
+        config: CameraConfig,
+        frame_tx: FrameSender,
+        events_tx: SyncSender<CameraEvent>,
+    ) -> Result<Self, CameraError> {
+        Self::with_source(
+            CameraBackend::Gstreamer,
+            config,
+            Box::new(make_source),
+            frame_tx,
+            events_tx,
+        )
+    }
+
+    /// Opens a driver whose pipeline starts with `source`, reporting events
+    /// as `backend`.
+    pub(crate) fn with_source(
+        backend: CameraBackend,
+        config: CameraConfig,
+        source: SourceFactory,
+        frame_tx: FrameSender,
+        events_tx: SyncSender<CameraEvent>,
+    ) -> Result<Self, CameraError> {
+        gst::init().map_err(|e| CameraError::driver("initializing GStreamer", e))?;
+        Ok(Self {
+            backend,
+            config,
+            source,
+            pipeline: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            reader_join: None,
+            frame_tx,
+            events_tx,
+        })
+    }
+}
+
+impl CameraDriver for GstreamerCameraDriver {
+    fn backend(&self) -> CameraBackend {
+        self.backend
+    }
+
+    fn start(&mut self) -> Result<(), CameraError> {
+        if self.pipeline.is_some() {
+            return Ok(());
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+
+        let pixel_format = self.config.pixel_format.unwrap_or(PixelFormat::Rgb8);
+        let (pipeline, appsink) = build_pipeline(&self.config, &self.source, pixel_format)?;
+        if let Err(e) = pipeline.set_state(gst::State::Playing) {
+            let _ = pipeline.set_state(gst::State::Null);
+            return Err(CameraError::driver("starting GStreamer pipeline", e));
+        }
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| CameraError::other("GStreamer pipeline has no bus"))?;
+        self.pipeline = Some(pipeline);
+
+        let backend = self.backend;
+        #[cfg(target_os = "linux")]
+        let gpu_handles = self.config.gpu_handles;
+        let stop = Arc::clone(&self.stop);
+        let frame_tx = self.frame_tx.clone();
+        let events_tx = self.events_tx.clone();
+
+        self.reader_join = Some(std::thread::spawn(move || {
+            let poll = gst::ClockTime::from_mseconds(100);
+            let mut clock = ClockSync::new();
+            let mut ended = false;
+            while !stop.load(Ordering::Relaxed) {
+                if !ended
+                    && let Some(msg) =
+                        bus.pop_filtered(&[gst::MessageType::Error, gst::MessageType::Eos])
+                {
+                    if let gst::MessageView::Error(err) = msg.view() {
+                        let error = CameraError::other(format!(
+                            "GStreamer error from {}: {}",
+                            msg.src().map(|s| s.path_string()).unwrap_or_default(),
+                            err.error()
+                        ));
+                        let _ = events_tx.try_send(CameraEvent::Error { backend, error });
+                        break;
+                    }
+                    // A finite source, like `filesrc`, played to its end; the
+                    // samples the appsink still holds go out first.
+                    ended = true;
+                }
+
+                // `None` on timeout, and once the pipeline is flushing on stop.
+                let Some(sample) = appsink.try_pull_sample(poll) else {
+                    if ended {
+                        frame_tx.end_of_stream();
+                        break;
+                    }
+                    continue;
+                };
+                let (Some(buffer), Some(caps)) = (sample.buffer_owned(), sample.caps()) else {
+                    continue;
+                };
+                let Ok(info) = gst_video::VideoInfo::from_caps(caps) else {
+                    continue;
+                };
+                // GStreamer pads rows to 4 bytes, so RGB strides can exceed width * 3.
+                let stride = info.stride()[0].max(0) as u32;
+                // Frames keep P010's chroma plane right below the luma,
+                // with the same stride; every buffer is laid out alike.
+                if pixel_format.is_planar()
+                    && (info.stride()[1] != info.stride()[0]
+                        || info.offset()[1] != stride as usize * info.height() as usize)
+                {
+                    let error = CameraError::unsupported(
+                        "GStreamer delivers P010 with its planes apart; capture rgba1010102 instead",
+                    );
+                    let _ = events_tx.try_send(CameraEvent::Error { backend, error });
+                    break;
+                }
+                // Live sources stamp buffers with the pipeline clock's
+                // running time at capture; add the base time for the clock.
+                let time = match buffer.pts().zip(appsink.base_time()) {
+                    Some((pts, base)) => clock.observe((base + pts).nseconds()),
+                    None => FrameTime::now(),
+                };
+                #[cfg(target_os = "linux")]
+                let handle = gpu_handles
+                    .then(|| dmabuf_handle(&buffer, &info, pixel_format))
+                    .flatten();
+                let Ok(mapped) = buffer.into_mapped_buffer_readable() else {
+                    continue;
+                };
+                #[allow(unused_mut)]
+                let mut frame = Frame::new(
+                    Bytes::from_owner(mapped),
+                    info.width(),
+                    info.height(),
+                    stride,
+                    pixel_format,
+                )
+                .with_colorimetry(colorimetry(&info))
+                .with_time(time);
+                #[cfg(target_os = "linux")]
+                if let Some(handle) = handle {
+                    frame = frame.with_handle(handle);
+                }
+                try_send_frame(&frame_tx, &events_tx, backend, frame);
+            }
+        }));
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        self.stop.store(true, Ordering::Relaxed);
+        // Going to Null flushes the appsink, which unblocks a pending pull.
+        if let Some(pipeline) = self.pipeline.take() {
+            let _ = pipeline.set_state(gst::State::Null);
+        }
+
+        if let Some(j) = self.reader_join.take()
+            && !join_until(j, Instant::now() + self.config.stop_timeout)
+        {
+            let _ = self.events_tx.try_send(CameraEvent::Warning {
+                backend: self.backend,
+                message: format!(
+                    "GStreamer reader did not exit within {:?}; abandoning it",
+                    self.config.stop_timeout
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn reconfigure(&mut self, config: &CameraConfig) -> Result<(), CameraError> {
+        let running = self.pipeline.is_some();
+        if running {
+            self.stop()?;
+        }
+        let reconfigured = self.config.with_format_of(config);
+        let previous = core::mem::replace(&mut self.config, reconfigured);
+        if !running {
+            return Ok(());
+        }
+        if let Err(err) = self.start() {
+            self.config = previous;
+            let _ = self.start();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Moves the pipeline to `Paused`, where live sources keep the device
+    /// open but stop producing buffers, or back to `Playing`.
+    fn set_paused(&mut self, paused: bool) -> Result<(), CameraError> {
+        let Some(pipeline) = &self.pipeline else {
+            return Ok(());
+        };
+        let state = if paused {
+            gst::State::Paused
+        } else {
+            gst::State::Playing
+        };
+        pipeline
+            .set_state(state)
+            .map(|_| ())
+            .map_err(|e| CameraError::driver("pausing GStreamer pipeline", e))
+    }
+
+    fn switch_device(&mut self, device: &str) -> Result<(), CameraError> {
+        let running = self.pipeline.is_some();
+        if running {
+            self.stop()?;
+        }
+        let previous = self.config.device.replace(device.to_string());
+        if !running {
+            return Ok(());
+        }
+        if let Err(err) = self.start() {
+            self.config.device = previous;
+            let _ = self.start();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Drop for GstreamerCameraDriver {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+fn build_pipeline(
+    config: &CameraConfig,
+    source: &SourceFactory,
+    pixel_format: PixelFormat,
+) -> Result<(gst::Pipeline, gst_app::AppSink), CameraError> {
+    let format = match pixel_format {
+        PixelFormat::Rgb8 => gst_video::VideoFormat::Rgb,
+        PixelFormat::Bgra8 => gst_video::VideoFormat::Bgra,
+        PixelFormat::Rgba8 => gst_video::VideoFormat::Rgba,
+        PixelFormat::Gray16 => gst_video::VideoFormat::Gray16Le,
+        PixelFormat::P010 => gst_video::VideoFormat::P01010le,
+        PixelFormat::Rgba1010102 => gst_video::VideoFormat::Rgb10a2Le,
+        // videoconvert would treat depth as luma and rescale it.
+        PixelFormat::Z16 => {
+            return Err(CameraError::unsupported(
+                "GStreamer can't capture Z16 depth; use a native backend (v4l2, avf)",
+            ));
+        },
+    };
+
+    let source = source(config.device.as_deref().unwrap_or("").trim())?;
+    // Have v4l2src export DMA-BUFs; the transforms below pass them through
+    // untouched when the camera already delivers the requested caps.
+    #[cfg(target_os = "linux")]
+    if config.gpu_handles && source.has_property("io-mode") {
+        source.set_property_from_str("io-mode", "dmabuf");
+    }
+    let element = |name: &str| {
+        gst::ElementFactory::make(name)
+            .build()
+            .map_err(|e| CameraError::driver("creating GStreamer element", e))
+    };
+    let convert = element("videoconvert")?;
+    let scale = element("videoscale")?;
+    let rate = element("videorate")?;
+
+    let mut caps = gst_video::VideoCapsBuilder::new()
+        .format(format)
+        .width(config.width as i32)
+        .height(config.height as i32);
+    if config.fps.is_finite() && config.fps > 0.1 {
+        let fps = gst::Fraction::approximate_f64(config.fps.min(240.0))
+            .unwrap_or_else(|| gst::Fraction::new(30, 1));
+        caps = caps.framerate(fps);
+    }
+    // Keep only the newest frame; delivery is throttled by the dispatcher.
+    let appsink = gst_app::AppSink::builder()
+        .caps(&caps.build())
+        .max_buffers(1)
+        .drop(true)
+        .sync(false)
+        .build();
+
+    let pipeline = gst::Pipeline::new();
+    pipeline
+        .add_many([&source, &convert, &scale, &rate, appsink.upcast_ref()])
+        .and_then(|()| {
+            gst::Element::link_many([&source, &convert, &scale, &rate, appsink.upcast_ref()])
+        })
+        .map_err(|e| CameraError::driver("linking GStreamer pipeline", e))?;
+    Ok((pipeline, appsink))
+}
+
+/// Builds the capture source for a device id. `gst:DESCRIPTION` takes any
+/// source bin, e.g. `gst:videotestsrc pattern=ball` or
+/// `gst:rtspsrc location=rtsp://cam/stream ! decodebin`.
+/// The colorimetry the appsink's caps negotiated, i.e. of the converted
+/// frames rather than the camera's native format.
+fn colorimetry(info: &gst_video::VideoInfo) -> Colorimetry {
+    let colorimetry = info.colorimetry();
+    Colorimetry {
+        matrix: match colorimetry.matrix() {
+            gst_video::VideoColorMatrix::Bt601 => Some(ColorMatrix::Bt601),
+            gst_video::VideoColorMatrix::Bt709 => Some(ColorMatrix::Bt709),
+            gst_video::VideoColorMatrix::Bt2020 => Some(ColorMatrix::Bt2020),
+            _ => None,
+        },
+        range: match colorimetry.range() {
+            gst_video::VideoColorRange::Range0_255 => Some(ColorRange::Full),
+            gst_video::VideoColorRange::Range16_235 => Some(ColorRange::Limited),
+            _ => None,
+        },
+        srgb: colorimetry.transfer() == gst_video::VideoTransferFunction::Srgb,
+    }
+}
+
+fn make_source(device: &str) -> Result<gst::Element, CameraError> {
+    if let Some(description) = device.strip_prefix("gst:") {
+        return gst::parse::bin_from_description(description, true)
+            .map(|bin| bin.upcast())
+            .map_err(|e| {
+                CameraError::invalid_config(format!("GStreamer source '{description}': {e}"))
+            });
+    }
+    #[cfg(target_os = "linux")]
+    if device.starts_with("pipewire:") {
+        return pipewire_source(None, device);
+    }
+    if let Some(position) = CameraPosition::from_device_id(device) {
+        return Err(CameraError::unsupported(format!(
+            "GStreamer can't select the {position} camera by position; use its index or name"
+        )));
+    }
+    let (factory, property, value) = source_for(device);
+    let mut builder = gst::ElementFactory::make(factory);
+    if let Some(property) = property {
+        builder = match value.parse::<i32>() {
+            Ok(index) if property == "device-index" => builder.property(property, index),
+            _ => builder.property(property, value),
+        };
+    }
+    builder
+        .build()
+        .map_err(|e| CameraError::driver("creating GStreamer camera source", e))
+}
+
+/// Duplicates the descriptor of a single-plane DMA-BUF buffer, so the frame
+/// can outlive the buffer's return to the v4l2src pool.
+#[cfg(target_os = "linux")]
+fn dmabuf_handle(
+    buffer: &gst::Buffer,
+    info: &gst_video::VideoInfo,
+    pixel_format: PixelFormat,
+) -> Option<FrameHandle> {
+    use std::os::fd::BorrowedFd;
+    if buffer.n_memory() != 1 {
+        return None;
+    }
+    let memory = buffer.peek_memory(0);
+    let dmabuf = memory.downcast_memory_ref::<gstreamer_allocators::DmaBufMemory>()?;
+    // SAFETY: the memory keeps its descriptor open while borrowed.
+    let fd = unsafe { BorrowedFd::borrow_raw(dmabuf.fd()) }
+        .try_clone_to_owned()
+        .ok()?;
+    Some(FrameHandle::DmaBuf(DmaBufHandle::linear(
+        fd,
+        memory.offset() + info.offset()[0],
+        info.stride()[0].max(0) as u32,
+        pixel_format,
+    )))
+}
+
+/// Builds a `pipewiresrc` for `pipewire:NODE`, where NODE is a node name or
+/// serial; other ids use PipeWire's default camera. `fd` is a remote opened
+/// by the camera portal; without one the source connects to the session's
+/// PipeWire daemon.
+#[cfg(target_os = "linux")]
+pub(crate) fn pipewire_source(fd: Option<i32>, device: &str) -> Result<gst::Element, CameraError> {
+    let mut builder = gst::ElementFactory::make("pipewiresrc");
+    if let Some(fd) = fd {
+        builder = builder.property("fd", fd);
+    }
+    if let Some(node) = device.strip_prefix("pipewire:").filter(|n| !n.is_empty()) {
+        builder = builder.property("target-object", node);
+    }
+    builder
+        .build()
+        .map_err(|e| CameraError::driver("creating GStreamer PipeWire source", e))
+}
+
+#[cfg(target_os = "linux")]
+fn source_for(device: &str) -> (&'static str, Option<&'static str>, String) {
+    let d = device.strip_prefix("file:").unwrap_or(device);
+    let path = if d.is_empty() {
+        "/dev/video0".to_string()
+    } else if d.chars().all(|c| c.is_ascii_digit()) {
+        format!("/dev/video{d}")
+    } else {
+        d.to_string()
+    };
+    ("v4l2src", Some("device"), path)
+}
+
+#[cfg(target_os = "macos")]
+fn source_for(device: &str) -> (&'static str, Option<&'static str>, String) {
+    let index = device.strip_prefix("avf:").unwrap_or(device);
+    if index.is_empty() {
+        return ("avfvideosrc", None, String::new());
+    }
+    ("avfvideosrc", Some("device-index"), index.to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn source_for(device: &str) -> (&'static str, Option<&'static str>, String) {
+    let d = device.strip_prefix("dshow:").unwrap_or(device);
+    let d = d.strip_prefix("video=").unwrap_or(d);
+    if d.is_empty() {
+        ("ksvideosrc", None, String::new())
+    } else if d.chars().all(|c| c.is_ascii_digit()) {
+        ("ksvideosrc", Some("device-index"), d.to_string())
+    } else {
+        ("ksvideosrc", Some("device-name"), d.to_string())
+    }
+}
@@ -0,0 +1,551 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{
+    AutoControl, CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, CameraMode,
+    CapturePlan, ColorMatrix, ColorRange, Colorimetry, Frame, FrameSender, PixelFormat,
+    TriggerConfig, join_until, report_drop, try_send_frame,
+};
+use bytes::Bytes;
+use core::{fmt, str::FromStr, time::Duration};
+use std::{
+    any::Any,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, SyncSender, sync_channel},
+    },
+    thread::JoinHandle,
+    time::Instant,
+};
+
+/// Device ids starting with this select the mock driver, with the rest of
+/// the id as its [`MockScript`].
+pub const MOCK_PREFIX: &str = "mock:";
+
+/// What the scripted frames show.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MockPattern {
+    /// Black on the left to white on the right.
+    #[default]
+    Gradient,
+    /// White on the left to black on the right.
+    Inverted,
+    /// A uniform grey level.
+    Solid(u8),
+    /// Different pseudo-random pixels in every frame.
+    Noise,
+}
+
+/// One step of a [`MockScript`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockStep {
+    /// Switches the pattern of the following frames.
+    Pattern(MockPattern),
+    /// Switches the frame rate of the following frames (default: the
+    /// configured one).
+    Fps(f64),
+    /// Sends this many frames, paced at the frame rate.
+    Frames(usize),
+    /// Discards this many frames as if the dispatcher queue were full.
+    Drop(usize),
+    /// Reports a warning.
+    Warn(String),
+    /// Reports an error, which ends the script.
+    Error(String),
+    /// Sends nothing for this long.
+    Wait(Duration),
+    /// Ends the stream, as a video file does, which ends the script.
+    End,
+}
+
+/// What a mock camera does after each start: steps separated by commas,
+/// e.g. `noise,frames:10,drop:2,warn:too dark,wait:500ms,error:unplugged`.
+/// Steps are `gradient`, `inverted`, `solid:LEVEL`, `noise`, `fps:N`,
+/// `frames:N` (or `frame`), `drop:N`, `warn:MESSAGE`, `error:MESSAGE` and
+/// `wait:MILLIS` (or `wait:SECSs`) and `end`, and a final `repeat` replays
+/// the script until stopped. When the script runs out, the camera stalls.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MockScript {
+    pub steps: Vec<MockStep>,
+    pub repeat: bool,
+}
+
+impl FromStr for MockScript {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix(MOCK_PREFIX).unwrap_or(s);
+        let mut script = MockScript::default();
+        for step in s.split(',').map(str::trim).filter(|step| !step.is_empty()) {
+            if script.repeat {
+                return Err(CameraError::invalid_config(
+                    "'repeat' must be the last step of a mock script",
+                ));
+            }
+            let invalid = || {
+                CameraError::invalid_config(format!(
+                    "invalid mock step '{step}' (expected gradient, inverted, solid:LEVEL, noise, fps:N, frames:N, drop:N, warn:MESSAGE, error:MESSAGE, wait:MILLIS, end or repeat)"
+                ))
+            };
+            let (name, arg) = match step.split_once(':') {
+                Some((name, arg)) => (name.trim(), Some(arg.trim())),
+                None => (step, None),
+            };
+            let count = |arg: Option<&str>| arg.and_then(|n| n.parse::<usize>().ok());
+            script.steps.push(match (name, arg) {
+                ("gradient", None) => MockStep::Pattern(MockPattern::Gradient),
+                ("inverted", None) => MockStep::Pattern(MockPattern::Inverted),
+                ("noise", None) => MockStep::Pattern(MockPattern::Noise),
+                ("solid", Some(level)) => {
+                    MockStep::Pattern(MockPattern::Solid(level.parse().map_err(|_| invalid())?))
+                },
+                ("fps", Some(fps)) => match fps.parse::<f64>() {
+                    Ok(fps) if fps > 0.0 && fps.is_finite() => MockStep::Fps(fps),
+                    _ => return Err(invalid()),
+                },
+                ("frame", None) => MockStep::Frames(1),
+                ("frames", arg) => MockStep::Frames(count(arg).ok_or_else(invalid)?),
+                ("drop", None) => MockStep::Drop(1),
+                ("drop", arg) => MockStep::Drop(count(arg).ok_or_else(invalid)?),
+                ("warn", Some(message)) => MockStep::Warn(message.to_string()),
+                ("error", Some(message)) => MockStep::Error(message.to_string()),
+                ("error", None) => MockStep::Error("scripted error".into()),
+                ("wait", Some(wait)) => MockStep::Wait(parse_wait(wait).ok_or_else(invalid)?),
+                ("end", None) => MockStep::End,
+                ("repeat", None) => {
+                    script.repeat = true;
+                    continue;
+                },
+                _ => return Err(invalid()),
+            });
+        }
+        Ok(script)
+    }
+}
+
+impl fmt::Display for MockScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            match step {
+                MockStep::Pattern(MockPattern::Gradient) => f.write_str("gradient")?,
+                MockStep::Pattern(MockPattern::Inverted) => f.write_str("inverted")?,
+                MockStep::Pattern(MockPattern::Solid(level)) => write!(f, "solid:{level}")?,
+                MockStep::Pattern(MockPattern::Noise) => f.write_str("noise")?,
+                MockStep::Fps(fps) => write!(f, "fps:{fps}")?,
+                MockStep::Frames(n) => write!(f, "frames:{n}")?,
+                MockStep::Drop(n) => write!(f, "drop:{n}")?,
+                MockStep::Warn(message) => write!(f, "warn:{message}")?,
+                MockStep::Error(message) => write!(f, "error:{message}")?,
+                MockStep::Wait(wait) => write!(f, "wait:{}ms", wait.as_millis())?,
+                MockStep::End => f.write_str("end")?,
+            }
+        }
+        if self.repeat {
+            f.write_str(if self.steps.is_empty() {
+                "repeat"
+            } else {
+                ",repeat"
+            })?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_wait(s: &str) -> Option<Duration> {
+    if let Some(ms) = s.strip_suffix("ms") {
+        ms.trim().parse().ok().map(Duration::from_millis)
+    } else if let Some(secs) = s.strip_suffix('s') {
+        secs.trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+    } else {
+        s.parse().ok().map(Duration::from_millis)
+    }
+}
+
+/// Plays a [`MockScript`] from the device id (`mock:SCRIPT`) on every
+/// start, at the configured size and pixel format, so capture, dispatch
+/// and output can be tested without hardware. In trigger mode each frame
+/// of the script waits for a software trigger instead of the frame rate.
+#[derive(Debug)]
+pub struct MockCameraDriver {
+    config: CameraConfig,
+    script: MockScript,
+    stop: Arc<AtomicBool>,
+    player_join: Option<JoinHandle<()>>,
+    frame_tx: FrameSender,
+    events_tx: SyncSender<CameraEvent>,
+    starts: usize,
+    sent: Arc<AtomicUsize>,
+    triggered: bool,
+    trigger_tx: Option<SyncSender<()>>,
+    locked: Vec<AutoControl>,
+}
+
+impl MockCameraDriver {
+    pub fn open(
+        _input_url: impl AsRef<str>,
+        config: CameraConfig,
+        frame_tx: FrameSender,
+        events_tx: SyncSender<CameraEvent>,
+    ) -> Result<Self, CameraError> {
+        let script = config.device.as_deref().unwrap_or_default().parse()?;
+        Ok(Self {
+            config,
+            script,
+            stop: Arc::new(AtomicBool::new(false)),
+            player_join: None,
+            frame_tx,
+            events_tx,
+            starts: 0,
+            sent: Arc::default(),
+            triggered: false,
+            trigger_tx: None,
+            locked: Vec::new(),
+        })
+    }
+
+    /// How many times the driver was started, including restarts.
+    pub fn starts(&self) -> usize {
+        self.starts
+    }
+
+    /// How many frames the script offered the dispatcher so far, whether
+    /// queued or dropped.
+    pub fn frames_sent(&self) -> usize {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    /// The automatic controls locked now.
+    pub fn locked(&self) -> &[AutoControl] {
+        &self.locked
+    }
+}
+
+impl CameraDriver for MockCameraDriver {
+    fn backend(&self) -> CameraBackend {
+        CameraBackend::Mock
+    }
+
+    fn start(&mut self) -> Result<(), CameraError> {
+        if self.player_join.is_some() {
+            return Ok(());
+        }
+        self.starts += 1;
+        self.stop.store(false, Ordering::Relaxed);
+        let triggers = self.triggered.then(|| {
+            let (tx, rx) = sync_channel(64);
+            self.trigger_tx = Some(tx);
+            rx
+        });
+
+        let player = Player {
+            script: self.script.clone(),
+            width: self.config.width,
+            height: self.config.height,
+            format: self.config.pixel_format.unwrap_or(PixelFormat::Rgb8),
+            fps: self.config.fps,
+            stop: Arc::clone(&self.stop),
+            sent: Arc::clone(&self.sent),
+            frame_tx: self.frame_tx.clone(),
+            events_tx: self.events_tx.clone(),
+            triggers,
+        };
+        self.player_join = Some(std::thread::spawn(move || player.run()));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.trigger_tx = None;
+        if let Some(j) = self.player_join.take()
+            && !join_until(j, Instant::now() + self.config.stop_timeout)
+        {
+            let _ = self.events_tx.try_send(CameraEvent::Warning {
+                backend: CameraBackend::Mock,
+                message: "mock player did not exit; abandoning it".into(),
+            });
+        }
+        Ok(())
+    }
+
+    fn switch_device(&mut self, device: &str) -> Result<(), CameraError> {
+        let script = device.parse()?;
+        let running = self.player_join.is_some();
+        self.stop()?;
+        self.script = script;
+        self.config.device = Some(device.to_string());
+        if running { self.start() } else { Ok(()) }
+    }
+
+    fn reconfigure(&mut self, config: &CameraConfig) -> Result<(), CameraError> {
+        let running = self.player_join.is_some();
+        self.stop()?;
+        self.config.width = config.width;
+        self.config.height = config.height;
+        self.config.fps = config.fps;
+        self.config.pixel_format = config.pixel_format;
+        if running { self.start() } else { Ok(()) }
+    }
+
+    /// Needs no requests: the player just waits for triggers from the
+    /// next start.
+    fn set_trigger_mode(
+        &mut self,
+        _trigger: &TriggerConfig,
+        enabled: bool,
+    ) -> Result<(), CameraError> {
+        self.triggered = enabled;
+        Ok(())
+    }
+
+    fn lock_auto(&mut self, control: AutoControl, locked: bool) -> Result<(), CameraError> {
+        self.locked.retain(|c| *c != control);
+        if locked {
+            self.locked.push(control);
+        }
+        Ok(())
+    }
+
+    fn fire_trigger(&mut self, _trigger: &TriggerConfig) -> Result<(), CameraError> {
+        match &self.trigger_tx {
+            Some(tx) => tx
+                .try_send(())
+                .map_err(|_| CameraError::other("too many mock triggers pending")),
+            None => Err(CameraError::invalid_config(
+                "the mock camera isn't capturing in trigger mode",
+            )),
+        }
+    }
+
+    /// Any size works, so this is the configured one in every pixel
+    /// format, up to the fastest rate the script plays at.
+    fn modes(&mut self) -> Result<Vec<CameraMode>, CameraError> {
+        let max_fps = self
+            .script
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                MockStep::Fps(fps) => Some(*fps),
+                _ => None,
+            })
+            .fold(self.config.fps, f64::max);
+        Ok([
+            PixelFormat::Rgb8,
+            PixelFormat::Bgra8,
+            PixelFormat::Rgba8,
+            PixelFormat::Gray16,
+            PixelFormat::Z16,
+            PixelFormat::P010,
+            PixelFormat::Rgba1010102,
+        ]
+        .into_iter()
+        .map(|format| {
+            CameraMode::new(self.config.width, self.config.height)
+                .with_format(format.as_str())
+                .with_fps(1.0, max_fps)
+        })
+        .collect())
+    }
+
+    fn plan(&self) -> Result<Option<CapturePlan>, CameraError> {
+        Ok(Some(CapturePlan::Request(format!(
+            "play '{}' as {}x{} {} frames",
+            self.script,
+            self.config.width,
+            self.config.height,
+            self.config
+                .pixel_format
+                .unwrap_or(PixelFormat::Rgb8)
+                .as_str()
+        ))))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Drop for MockCameraDriver {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// The capture thread's copy of the script and where its frames go.
+struct Player {
+    script: MockScript,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    fps: f64,
+    stop: Arc<AtomicBool>,
+    sent: Arc<AtomicUsize>,
+    frame_tx: FrameSender,
+    events_tx: SyncSender<CameraEvent>,
+    /// In trigger mode, one message per frame to expose.
+    triggers: Option<Receiver<()>>,
+}
+
+impl Player {
+    fn run(mut self) {
+        let steps = core::mem::take(&mut self.script.steps);
+        let mut pattern = MockPattern::default();
+        let mut index = 0u64;
+        loop {
+            for step in steps.iter().cloned() {
+                match step {
+                    MockStep::Pattern(p) => pattern = p,
+                    MockStep::Fps(fps) => self.fps = fps,
+                    MockStep::Frames(n) => {
+                        for _ in 0..n {
+                            let go_on = match &self.triggers {
+                                Some(triggers) => self.wait_for_trigger(triggers),
+                                None => {
+                                    self.sleep(Duration::from_secs_f64(1.0 / self.fps.max(0.1)))
+                                },
+                            };
+                            if !go_on {
+                                return;
+                            }
+                            let frame = self.frame(pattern, index);
+                            index += 1;
+                            try_send_frame(
+                                &self.frame_tx,
+                                &self.events_tx,
+                                CameraBackend::Mock,
+                                frame,
+                            );
+                            self.sent.fetch_add(1, Ordering::Relaxed);
+                        }
+                    },
+                    MockStep::Drop(n) => {
+                        for _ in 0..n {
+                            self.frame_tx.count_drop();
+                            report_drop(&self.events_tx, CameraBackend::Mock);
+                        }
+                    },
+                    MockStep::Warn(message) => {
+                        let _ = self.events_tx.try_send(CameraEvent::Warning {
+                            backend: CameraBackend::Mock,
+                            message,
+                        });
+                    },
+                    MockStep::Error(message) => {
+                        let _ = self.events_tx.try_send(CameraEvent::Error {
+                            backend: CameraBackend::Mock,
+                            error: CameraError::other(message),
+                        });
+                        return;
+                    },
+                    MockStep::Wait(wait) => {
+                        if !self.sleep(wait) {
+                            return;
+                        }
+                    },
+                    MockStep::End => {
+                        self.frame_tx.end_of_stream();
+                        return;
+                    },
+                }
+            }
+            if !self.script.repeat || steps.is_empty() {
+                return;
+            }
+        }
+    }
+
+    /// Sleeps for `duration` unless stopped first; returns whether to go on.
+    fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while !self.stop.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            std::thread::sleep((deadline - now).min(Duration::from_millis(10)));
+        }
+        false
+    }
+
+    /// Waits for a trigger unless stopped first; returns whether to go on.
+    fn wait_for_trigger(&self, triggers: &Receiver<()>) -> bool {
+        while !self.stop.load(Ordering::Relaxed) {
+            match triggers.recv_timeout(Duration::from_millis(10)) {
+                Ok(()) => return true,
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => return false,
+            }
+        }
+        false
+    }
+
+    fn frame(&self, pattern: MockPattern, index: u64) -> Frame {
+        let (w, h) = (self.width.max(1), self.height.max(1));
+        let bpp = self.format.bytes_per_pixel() as usize;
+        let rows = self.format.buffer_rows(h) as usize;
+        let mut data = Vec::with_capacity(w as usize * rows * bpp);
+        let mut seed = index.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        for _ in 0..h {
+            for x in 0..w {
+                let level = match pattern {
+                    MockPattern::Gradient => (x * 255 / w) as u8,
+                    MockPattern::Inverted => 255 - (x * 255 / w) as u8,
+                    MockPattern::Solid(level) => level,
+                    MockPattern::Noise => {
+                        // xorshift64
+                        seed ^= seed << 13;
+                        seed ^= seed >> 7;
+                        seed ^= seed << 17;
+                        seed as u8
+                    },
+                };
+                match self.format {
+                    PixelFormat::Rgb8 => data.extend_from_slice(&[level; 3]),
+                    PixelFormat::Bgra8 | PixelFormat::Rgba8 => {
+                        data.extend_from_slice(&[level, level, level, 255])
+                    },
+                    PixelFormat::Gray16 | PixelFormat::Z16 => {
+                        data.extend_from_slice(&(level as u16 * 257).to_le_bytes())
+                    },
+                    // Limited-range luma, MSB-aligned.
+                    PixelFormat::P010 => {
+                        let luma = 64 + (level as u32 * 876 / 255) as u16;
+                        data.extend_from_slice(&(luma << 6).to_le_bytes())
+                    },
+                    PixelFormat::Rgba1010102 => {
+                        let v = (level as u32) << 2;
+                        data.extend_from_slice(&(v | v << 10 | v << 20 | 3 << 30).to_le_bytes())
+                    },
+                }
+            }
+        }
+        // Neutral chroma, so P010 frames are as grey as the others.
+        while data.len() < w as usize * rows * bpp {
+            data.extend_from_slice(&(512u16 << 6).to_le_bytes());
+        }
+        let stride = w * bpp as u32;
+        let colorimetry = match self.format {
+            PixelFormat::Rgb8
+            | PixelFormat::Bgra8
+            | PixelFormat::Rgba8
+            | PixelFormat::Rgba1010102 => Colorimetry::SRGB,
+            PixelFormat::P010 => Colorimetry {
+                matrix: Some(ColorMatrix::Bt709),
+                range: Some(ColorRange::Limited),
+                srgb: false,
+            },
+            PixelFormat::Gray16 | PixelFormat::Z16 => Colorimetry::UNKNOWN,
+        };
+        Frame::new(Bytes::from(data), w, h, stride, self.format).with_colorimetry(colorimetry)
+    }
+}
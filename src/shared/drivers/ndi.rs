@@ -0,0 +1,142 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{
+    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, FrameSender, NDI_PREFIX,
+    NdiReceiver, PixelFormat, join_until, try_send_frame,
+};
+use core::time::Duration;
+use std::{
+    any::Any,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::SyncSender,
+    },
+    thread::JoinHandle,
+    time::Instant,
+};
+
+/// Receives the NDI source named by device ids `ndi:MACHINE (SOURCE)`.
+/// Frames keep the source's size, in BGRA when that's the configured
+/// format and RGBA otherwise.
+#[derive(Debug)]
+pub struct NdiCameraDriver {
+    config: CameraConfig,
+    stop: Arc<AtomicBool>,
+    reader_join: Option<JoinHandle<()>>,
+    frame_tx: FrameSender,
+    events_tx: SyncSender<CameraEvent>,
+}
+
+impl NdiCameraDriver {
+    pub fn open(
+        _input_url: impl AsRef<str>,
+        config: CameraConfig,
+        frame_tx: FrameSender,
+        events_tx: SyncSender<CameraEvent>,
+    ) -> Result<Self, CameraError> {
+        Ok(Self {
+            config,
+            stop: Arc::new(AtomicBool::new(false)),
+            reader_join: None,
+            frame_tx,
+            events_tx,
+        })
+    }
+}
+
+impl CameraDriver for NdiCameraDriver {
+    fn backend(&self) -> CameraBackend {
+        CameraBackend::Ndi
+    }
+
+    fn start(&mut self) -> Result<(), CameraError> {
+        if self.reader_join.is_some() {
+            return Ok(());
+        }
+
+        let device = self.config.device.as_deref().unwrap_or("").trim();
+        let source = device.strip_prefix(NDI_PREFIX).unwrap_or(device).trim();
+        if source.is_empty() {
+            return Err(CameraError::invalid_config(
+                "expected an NDI source as ndi:MACHINE (SOURCE)",
+            ));
+        }
+        let format = self.config.pixel_format.unwrap_or(PixelFormat::Rgba8);
+        let mut receiver = NdiReceiver::connect(source, format)?;
+
+        self.stop.store(false, Ordering::Relaxed);
+        let stop = Arc::clone(&self.stop);
+        let frame_tx = self.frame_tx.clone();
+        let events_tx = self.events_tx.clone();
+
+        self.reader_join = Some(std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                match receiver.recv_timeout(Duration::from_millis(100)) {
+                    Ok(Some(frame)) => {
+                        try_send_frame(&frame_tx, &events_tx, CameraBackend::Ndi, frame)
+                    },
+                    Ok(None) => {},
+                    Err(error) => {
+                        let _ = events_tx.try_send(CameraEvent::Error {
+                            backend: CameraBackend::Ndi,
+                            error,
+                        });
+                        break;
+                    },
+                }
+            }
+        }));
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(j) = self.reader_join.take()
+            && !join_until(j, Instant::now() + self.config.stop_timeout)
+        {
+            let _ = self.events_tx.try_send(CameraEvent::Warning {
+                backend: CameraBackend::Ndi,
+                message: format!(
+                    "NDI receiver did not exit within {:?}; abandoning it",
+                    self.config.stop_timeout
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn switch_device(&mut self, device: &str) -> Result<(), CameraError> {
+        let running = self.reader_join.is_some();
+        if running {
+            self.stop()?;
+        }
+        let previous = self.config.device.replace(device.to_string());
+        if !running {
+            return Ok(());
+        }
+        if let Err(err) = self.start() {
+            self.config.device = previous;
+            let _ = self.start();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Drop for NdiCameraDriver {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
@@ -0,0 +1,111 @@
+// This is free and unencumbered software released into the public domain.
+
+use super::gstreamer::{GstreamerCameraDriver, pipewire_source};
+use crate::shared::{
+    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, FrameSender,
+};
+use ashpd::desktop::camera::Camera as CameraPortal;
+use std::{
+    any::Any,
+    os::fd::{AsRawFd, OwnedFd},
+    path::Path,
+    sync::mpsc::SyncSender,
+};
+
+/// Captures from PipeWire camera nodes, which is how libcamera cameras
+/// (Raspberry Pi, recent laptops) are exposed on modern Linux desktops.
+///
+/// Access is negotiated through the `org.freedesktop.portal.Camera` portal,
+/// which prompts the user and works inside Flatpak sandboxes; outside a
+/// sandbox, a missing portal falls back to the session's PipeWire daemon.
+/// Frames are pulled through GStreamer's `pipewiresrc`.
+#[derive(Debug)]
+pub struct PipewireCameraDriver {
+    inner: GstreamerCameraDriver,
+}
+
+impl PipewireCameraDriver {
+    pub fn open(
+        _input_url: impl AsRef<str>,
+        config: CameraConfig,
+        frame_tx: FrameSender,
+        events_tx: SyncSender<CameraEvent>,
+    ) -> Result<Self, CameraError> {
+        let remote = open_portal_remote()?;
+        let inner = GstreamerCameraDriver::with_source(
+            CameraBackend::Pipewire,
+            config,
+            Box::new(move |device| {
+                pipewire_source(remote.as_ref().map(OwnedFd::as_raw_fd), device)
+            }),
+            frame_tx,
+            events_tx,
+        )?;
+        Ok(Self { inner })
+    }
+}
+
+impl CameraDriver for PipewireCameraDriver {
+    fn backend(&self) -> CameraBackend {
+        CameraBackend::Pipewire
+    }
+
+    fn start(&mut self) -> Result<(), CameraError> {
+        self.inner.start()
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        self.inner.stop()
+    }
+
+    fn reconfigure(&mut self, config: &CameraConfig) -> Result<(), CameraError> {
+        self.inner.reconfigure(config)
+    }
+
+    fn set_paused(&mut self, paused: bool) -> Result<(), CameraError> {
+        self.inner.set_paused(paused)
+    }
+
+    fn switch_device(&mut self, device: &str) -> Result<(), CameraError> {
+        self.inner.switch_device(device)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Asks the camera portal for access and a PipeWire remote limited to
+/// camera nodes. Returns `None` when there's no portal to ask and the
+/// process isn't sandboxed, so the daemon can be used directly.
+fn open_portal_remote() -> Result<Option<OwnedFd>, CameraError> {
+    let sandboxed = Path::new("/.flatpak-info").exists();
+    futures_lite::future::block_on(async {
+        let portal = match CameraPortal::new().await {
+            Ok(portal) => portal,
+            Err(_) if !sandboxed => return Ok(None),
+            Err(e) => return Err(CameraError::driver("connecting to the camera portal", e)),
+        };
+        if !portal
+            .is_present()
+            .await
+            .map_err(|e| CameraError::driver("querying the camera portal", e))?
+        {
+            return Err(CameraError::other("the camera portal reports no cameras"));
+        }
+        portal
+            .request_access(Default::default())
+            .await
+            .and_then(|request| request.response())
+            .map_err(|e| CameraError::driver("requesting camera access", e))?;
+        portal
+            .open_pipe_wire_remote(Default::default())
+            .await
+            .map(Some)
+            .map_err(|e| CameraError::driver("opening the PipeWire remote", e))
+    })
+}
@@ -0,0 +1,594 @@
+// This is free and unencumbered software released into the public domain.
+
+use super::ffmpeg::{FfmpegCameraDriver, pause_child, spawn_child, terminate_child};
+use crate::shared::{
+    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, CameraMode, CapturePlan,
+    Colorimetry, Flip, Frame, FrameSender, FrameTime, FrameTransform, Photo, PhotoFormat,
+    PixelFormat, Rotation, convert::swap_red_blue, devices::parse, join_until, try_send_frame,
+    wall_clock_ns,
+};
+use bytes::Bytes;
+use std::{
+    any::Any,
+    env,
+    io::Read,
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::SyncSender,
+    },
+    thread::JoinHandle,
+    time::Instant,
+};
+
+/// Device id prefix for CSI camera modules, e.g. `csi:0`.
+pub const CSI_PREFIX: &str = "csi:";
+
+/// Buffered MJPEG beyond this without a complete image is discarded.
+const MAX_PENDING_BYTES: usize = 32 << 20;
+
+/// Captures Raspberry Pi camera modules with `rpicam-vid` (or the older
+/// `libcamera-vid`), which applies the sensor's libcamera tuning. Boards
+/// still on the legacy camera stack have no libcamera apps, and capture
+/// `csi:0` from the bcm2835-v4l2 node through ffmpeg instead.
+pub struct RpiCameraDriver {
+    config: CameraConfig,
+    /// `rpicam-vid` or `libcamera-vid`; `None` on the legacy stack.
+    program: Option<&'static str>,
+    legacy: Option<FfmpegCameraDriver>,
+    /// Mirroring done by the sensor, covering the flip and 180° turns.
+    flip: Flip,
+    /// Exposure compensation in stops, passed as `--ev`.
+    ev: f32,
+    child: Option<Child>,
+    stop: Arc<AtomicBool>,
+    reader_join: Option<JoinHandle<()>>,
+    frame_tx: FrameSender,
+    events_tx: SyncSender<CameraEvent>,
+}
+
+impl core::fmt::Debug for RpiCameraDriver {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RpiCameraDriver")
+            .field("config", &self.config)
+            .field("program", &self.program)
+            .field("legacy", &self.legacy.is_some())
+            .finish()
+    }
+}
+
+impl RpiCameraDriver {
+    pub fn open(
+        _input_url: impl AsRef<str>,
+        config: CameraConfig,
+        frame_tx: FrameSender,
+        events_tx: SyncSender<CameraEvent>,
+    ) -> Result<Self, CameraError> {
+        let index = camera_index(config.device.as_deref().unwrap_or(""))?;
+        let program = ["rpicam-vid", "libcamera-vid"]
+            .into_iter()
+            .find(|p| on_path(p));
+        let legacy = match program {
+            Some(_) => None,
+            None => {
+                let node = legacy_node().filter(|_| index == 0).ok_or_else(|| {
+                    CameraError::unsupported(
+                        "csi cameras need rpicam-vid (sudo apt install rpicam-apps) \
+                         or the bcm2835-v4l2 driver",
+                    )
+                })?;
+                let mut legacy = config.clone();
+                legacy.device = Some(format!("file:{node}"));
+                Some(FfmpegCameraDriver::open(
+                    "",
+                    legacy,
+                    frame_tx.clone(),
+                    events_tx.clone(),
+                )?)
+            },
+        };
+        Ok(Self {
+            config,
+            program,
+            legacy,
+            flip: Flip::None,
+            ev: 0.0,
+            child: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            reader_join: None,
+            frame_tx,
+            events_tx,
+        })
+    }
+
+    fn spawn(&self, program: &str) -> Result<Child, CameraError> {
+        let stderr = if self.config.diagnostics {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        };
+        let mut command = Command::new(program);
+        command
+            .args(self.args()?)
+            .stdout(Stdio::piped())
+            .stderr(stderr);
+        spawn_child(command).map_err(|e| CameraError::driver("spawning rpicam-vid", e))
+    }
+
+    /// The `rpicam-vid` arguments for MJPEG of the configured mode on stdout.
+    fn args(&self) -> Result<Vec<String>, CameraError> {
+        let config = &self.config;
+        let pixel_format = config.pixel_format.unwrap_or(PixelFormat::Rgb8);
+        if !matches!(
+            pixel_format,
+            PixelFormat::Rgb8 | PixelFormat::Rgba8 | PixelFormat::Bgra8
+        ) {
+            return Err(CameraError::unsupported(format!(
+                "csi cameras can't capture {}",
+                pixel_format.as_str()
+            )));
+        }
+        let fps = if config.fps.is_finite() && config.fps > 0.1 {
+            config.fps.min(240.0)
+        } else {
+            30.0
+        };
+        let mut args: Vec<String> = vec![
+            "--nopreview".into(),
+            "--timeout".into(),
+            "0".into(),
+            "--camera".into(),
+            camera_index(config.device.as_deref().unwrap_or(""))?.to_string(),
+            "--width".into(),
+            config.width.to_string(),
+            "--height".into(),
+            config.height.to_string(),
+            "--framerate".into(),
+            format!("{fps}"),
+            "--codec".into(),
+            "mjpeg".into(),
+            "--quality".into(),
+            "90".into(),
+            "--flush".into(),
+        ];
+        if let Some(mode) = config.sensor_mode {
+            args.extend(["--mode".into(), mode.to_string()]);
+        }
+        if let Some(tuning) = &config.tuning_file {
+            args.extend(["--tuning-file".into(), tuning.display().to_string()]);
+        }
+        if config.hdr {
+            args.push("--hdr=auto".into());
+        }
+        if self.ev != 0.0 {
+            args.extend(["--ev".into(), format!("{}", self.ev)]);
+        }
+        if matches!(self.flip, Flip::Horizontal | Flip::Both) {
+            args.push("--hflip".into());
+        }
+        if matches!(self.flip, Flip::Vertical | Flip::Both) {
+            args.push("--vflip".into());
+        }
+        args.extend(["--output".into(), "-".into()]);
+        Ok(args)
+    }
+}
+
+impl RpiCameraDriver {
+    /// Runs `rpicam-still` (or `libcamera-still`) once for a JPEG at the
+    /// sensor's full resolution.
+    fn take_still(&self, program: &str) -> Result<Photo, CameraError> {
+        let device = self.config.device.as_deref().unwrap_or("");
+        let mut args: Vec<String> = vec![
+            "--nopreview".into(),
+            // Long enough for auto exposure and white balance to settle.
+            "--timeout".into(),
+            "1000".into(),
+            "--camera".into(),
+            camera_index(device)?.to_string(),
+            "--encoding".into(),
+            "jpg".into(),
+            "--quality".into(),
+            "95".into(),
+        ];
+        if let Some(tuning) = &self.config.tuning_file {
+            args.extend(["--tuning-file".into(), tuning.display().to_string()]);
+        }
+        if self.ev != 0.0 {
+            args.extend(["--ev".into(), format!("{}", self.ev)]);
+        }
+        if matches!(self.flip, Flip::Horizontal | Flip::Both) {
+            args.push("--hflip".into());
+        }
+        if matches!(self.flip, Flip::Vertical | Flip::Both) {
+            args.push("--vflip".into());
+        }
+        args.extend(["--output".into(), "-".into()]);
+
+        let output = Command::new(program)
+            .args(&args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| CameraError::driver("spawning rpicam-still", e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(CameraError::other(format!(
+                "{program} failed ({}): {}",
+                output.status,
+                stderr.lines().last().unwrap_or("no output")
+            )));
+        }
+        let (width, height) = image::ImageReader::with_format(
+            std::io::Cursor::new(&output.stdout),
+            image::ImageFormat::Jpeg,
+        )
+        .into_dimensions()
+        .map_err(|e| CameraError::driver("reading the still's dimensions", e))?;
+        Ok(Photo {
+            data: Bytes::from(output.stdout),
+            format: PhotoFormat::Jpeg,
+            width,
+            height,
+            timestamp_ns: wall_clock_ns(),
+        })
+    }
+}
+
+impl CameraDriver for RpiCameraDriver {
+    fn backend(&self) -> CameraBackend {
+        CameraBackend::Rpi
+    }
+
+    fn start(&mut self) -> Result<(), CameraError> {
+        if let Some(legacy) = &mut self.legacy {
+            return legacy.start();
+        }
+        let Some(program) = self.program else {
+            return Ok(());
+        };
+        if self.child.is_some() {
+            return Ok(());
+        }
+
+        let pixel_format = self.config.pixel_format.unwrap_or(PixelFormat::Rgb8);
+
+        self.stop.store(false, Ordering::Relaxed);
+        let mut child = self.spawn(program)?;
+        let mut stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| CameraError::other("rpicam-vid stdout not piped"))?;
+        self.child = Some(child);
+
+        let stop = Arc::clone(&self.stop);
+        let frame_tx = self.frame_tx.clone();
+        let events_tx = self.events_tx.clone();
+
+        self.reader_join = Some(std::thread::spawn(move || {
+            let mut pending = Vec::with_capacity(1 << 20);
+            let mut chunk = vec![0u8; 64 << 10];
+            while !stop.load(Ordering::Relaxed) {
+                let n = match stdout.read(&mut chunk) {
+                    Ok(n) => n,
+                    Err(_) if stop.load(Ordering::Relaxed) => break,
+                    Err(e) => {
+                        let _ = events_tx.try_send(CameraEvent::Error {
+                            backend: CameraBackend::Rpi,
+                            error: CameraError::driver("rpicam-vid read", e),
+                        });
+                        break;
+                    },
+                };
+                if n == 0 {
+                    if !stop.load(Ordering::Relaxed) {
+                        let _ = events_tx.try_send(CameraEvent::Error {
+                            backend: CameraBackend::Rpi,
+                            error: CameraError::other(
+                                "rpicam-vid stream ended; check the camera cable and \
+                                 `rpicam-hello --list-cameras`",
+                            ),
+                        });
+                    }
+                    break;
+                }
+                pending.extend_from_slice(&chunk[..n]);
+
+                while let Some((start, end)) = next_jpeg(&pending) {
+                    let ts = FrameTime::now();
+                    match decode_jpeg(&pending[start..end], pixel_format) {
+                        Ok(frame) => try_send_frame(
+                            &frame_tx,
+                            &events_tx,
+                            CameraBackend::Rpi,
+                            frame.with_time(ts),
+                        ),
+                        Err(e) => {
+                            let _ = events_tx.try_send(CameraEvent::Warning {
+                                backend: CameraBackend::Rpi,
+                                message: format!("dropping undecodable frame: {e}"),
+                            });
+                        },
+                    }
+                    pending.drain(..end);
+                }
+                if pending.len() > MAX_PENDING_BYTES {
+                    pending.clear();
+                }
+            }
+        }));
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        if let Some(legacy) = &mut self.legacy {
+            return legacy.stop();
+        }
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(mut child) = self.child.take() {
+            terminate_child(&mut child);
+        }
+
+        if let Some(j) = self.reader_join.take()
+            && !join_until(j, Instant::now() + self.config.stop_timeout)
+        {
+            let _ = self.events_tx.try_send(CameraEvent::Warning {
+                backend: CameraBackend::Rpi,
+                message: format!(
+                    "rpicam-vid reader did not exit within {:?}; abandoning it",
+                    self.config.stop_timeout
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn offload_transform(&mut self, transform: FrameTransform) -> FrameTransform {
+        if self.legacy.is_some() {
+            return transform;
+        }
+        let (flip, rotation) = match transform.rotation {
+            Rotation::Cw180 => (transform.flip.compose(Flip::Both), Rotation::None),
+            rotation => (transform.flip, rotation),
+        };
+        self.flip = flip;
+        FrameTransform {
+            rotation,
+            flip: Flip::None,
+        }
+    }
+
+    fn reconfigure(&mut self, config: &CameraConfig) -> Result<(), CameraError> {
+        if let Some(legacy) = &mut self.legacy {
+            legacy.reconfigure(config)?;
+            self.config = self.config.with_format_of(config);
+            return Ok(());
+        }
+        let running = self.child.is_some();
+        if running {
+            self.stop()?;
+        }
+        let reconfigured = self.config.with_format_of(config);
+        let previous = core::mem::replace(&mut self.config, reconfigured);
+        if !running {
+            return Ok(());
+        }
+        if let Err(err) = self.start() {
+            self.config = previous;
+            let _ = self.start();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn set_paused(&mut self, paused: bool) -> Result<(), CameraError> {
+        if let Some(legacy) = &mut self.legacy {
+            return legacy.set_paused(paused);
+        }
+        match &self.child {
+            Some(child) => pause_child(child, paused),
+            None => Ok(()),
+        }
+    }
+
+    fn set_exposure_bias(&mut self, ev: f32) -> Result<(), CameraError> {
+        if self.legacy.is_some() {
+            return Err(CameraError::unsupported(
+                "the legacy camera stack can't bias exposure",
+            ));
+        }
+        // rpicam-vid accepts -10 to 10 stops.
+        let ev = ev.clamp(-10.0, 10.0);
+        if ev == self.ev {
+            return Ok(());
+        }
+        let running = self.child.is_some();
+        if running {
+            self.stop()?;
+        }
+        let previous = core::mem::replace(&mut self.ev, ev);
+        if !running {
+            return Ok(());
+        }
+        if let Err(err) = self.start() {
+            self.ev = previous;
+            let _ = self.start();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn capture_photo(&mut self, format: PhotoFormat) -> Result<Photo, CameraError> {
+        if format != PhotoFormat::Jpeg {
+            return Err(CameraError::unsupported(format!(
+                "csi cameras can't encode {format} stills"
+            )));
+        }
+        let program = match self.program {
+            Some("libcamera-vid") => "libcamera-still",
+            Some(_) => "rpicam-still",
+            None => {
+                return Err(CameraError::unsupported(
+                    "the legacy camera stack has no still pipeline",
+                ));
+            },
+        };
+        // The stream holds the camera, so it pauses for the still.
+        let running = self.child.is_some();
+        if running {
+            self.stop()?;
+        }
+        let photo = self.take_still(program);
+        if running {
+            self.start()?;
+        }
+        photo
+    }
+
+    fn modes(&mut self) -> Result<Vec<CameraMode>, CameraError> {
+        let program = match self.program {
+            Some("libcamera-vid") => "libcamera-hello",
+            Some(_) => "rpicam-hello",
+            None => {
+                return match &mut self.legacy {
+                    Some(legacy) => legacy.modes(),
+                    None => Err(CameraError::NotConfigured),
+                };
+            },
+        };
+        let index = camera_index(self.config.device.as_deref().unwrap_or(""))?;
+        let output = Command::new(program)
+            .arg("--list-cameras")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|e| CameraError::driver("running rpicam-hello", e))?;
+        let list = parse::rpicam_modes(&String::from_utf8_lossy(&output.stdout), index);
+        for message in list.warnings {
+            let _ = self.events_tx.try_send(CameraEvent::Warning {
+                backend: CameraBackend::Rpi,
+                message,
+            });
+        }
+        Ok(list.modes)
+    }
+
+    fn plan(&self) -> Result<Option<CapturePlan>, CameraError> {
+        match (self.program, &self.legacy) {
+            (_, Some(legacy)) => legacy.plan(),
+            (Some(program), None) => {
+                let mut command = vec![program.to_string()];
+                command.extend(self.args()?);
+                Ok(Some(CapturePlan::Command(command)))
+            },
+            (None, None) => Err(CameraError::NotConfigured),
+        }
+    }
+
+    fn switch_device(&mut self, device: &str) -> Result<(), CameraError> {
+        if self.legacy.is_some() {
+            return Err(CameraError::unsupported(
+                "the legacy camera stack only has csi:0",
+            ));
+        }
+        camera_index(device)?;
+        let running = self.child.is_some();
+        if running {
+            self.stop()?;
+        }
+        let previous = self.config.device.replace(device.to_string());
+        if !running {
+            return Ok(());
+        }
+        if let Err(err) = self.start() {
+            self.config.device = previous;
+            let _ = self.start();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Drop for RpiCameraDriver {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// The camera number in `csi:N`; empty ids mean camera 0.
+fn camera_index(device: &str) -> Result<u32, CameraError> {
+    let device = device.trim();
+    let index = device.strip_prefix(CSI_PREFIX).unwrap_or(device);
+    if index.is_empty() {
+        return Ok(0);
+    }
+    index.parse().map_err(|_| {
+        CameraError::invalid_config(format!(
+            "invalid csi camera '{device}' (expected csi:N, e.g. csi:0)"
+        ))
+    })
+}
+
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// The V4L2 node of the bcm2835-v4l2 driver, named "mmal service" after
+/// the firmware interface it wraps.
+fn legacy_node() -> Option<String> {
+    let entries = std::fs::read_dir("/sys/class/video4linux").ok()?;
+    let mut nodes = entries
+        .flatten()
+        .filter(|e| {
+            std::fs::read_to_string(e.path().join("name"))
+                .is_ok_and(|name| name.trim().starts_with("mmal service"))
+        })
+        .filter_map(|e| e.file_name().into_string().ok())
+        .map(|name| format!("/dev/{name}"))
+        .filter(|node| Path::new(node).exists())
+        .collect::<Vec<_>>();
+    nodes.sort();
+    nodes.into_iter().next()
+}
+
+/// Finds the first complete JPEG image (SOI to EOI) in an MJPEG stream.
+fn next_jpeg(buf: &[u8]) -> Option<(usize, usize)> {
+    let start = buf.windows(2).position(|w| w == [0xFF, 0xD8])?;
+    let end = buf[start + 2..]
+        .windows(2)
+        .position(|w| w == [0xFF, 0xD9])?;
+    Some((start, start + 2 + end + 2))
+}
+
+fn decode_jpeg(jpeg: &[u8], pixel_format: PixelFormat) -> Result<Frame, CameraError> {
+    let image = image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg)
+        .map_err(|e| CameraError::driver("decoding MJPEG frame", e))?;
+    let (width, height) = (image.width(), image.height());
+    let data = match pixel_format {
+        PixelFormat::Rgb8 => image.into_rgb8().into_raw(),
+        PixelFormat::Bgra8 => {
+            let mut data = image.into_rgba8().into_raw();
+            swap_red_blue(&mut data);
+            data
+        },
+        _ => image.into_rgba8().into_raw(),
+    };
+    let stride = width * pixel_format.bytes_per_pixel();
+    Ok(
+        Frame::new(Bytes::from(data), width, height, stride, pixel_format)
+            .with_colorimetry(Colorimetry::JPEG),
+    )
+}
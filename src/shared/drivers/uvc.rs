@@ -0,0 +1,525 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{
+    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, CameraMode,
+    CameraPosition, CapturePlan, ColorMatrix, ColorRange, Colorimetry, Frame, FrameSender,
+    FrameTime, PixelFormat, convert::swap_red_blue, devices::xu::VendorControl, join_until,
+    try_send_frame,
+};
+use bytes::Bytes;
+use nokhwa::{
+    Camera,
+    pixel_format::{RgbAFormat, RgbFormat},
+    utils::{
+        ApiBackend, CameraFormat, CameraIndex, ControlValueDescription, ControlValueSetter,
+        FrameFormat, KnownCameraControl, RequestedFormat, RequestedFormatType, Resolution,
+    },
+};
+use std::{
+    any::Any,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, SyncSender, sync_channel},
+    },
+    thread::JoinHandle,
+    time::Instant,
+};
+
+/// An exposure bias for the capture thread, with where to report the result.
+type ExposureRequest = (f32, SyncSender<Result<(), CameraError>>);
+
+/// V4L2's manual exposure time, in 100 µs units; nokhwa's `Exposure` maps
+/// to the rarely implemented `V4L2_CID_EXPOSURE` instead.
+const V4L2_CID_EXPOSURE_ABSOLUTE: u128 = 0x009a_0902;
+/// V4L2's auto exposure mode, where 1 is manual.
+#[cfg(target_os = "linux")]
+const V4L2_CID_EXPOSURE_AUTO: u128 = 0x009a_0901;
+
+/// Captures with nokhwa, which talks to V4L2, AVFoundation and Media
+/// Foundation directly, so no ffmpeg or other system tools are needed.
+///
+/// nokhwa cameras aren't `Send`, so each session opens its camera on the
+/// capture thread and reports the outcome back to `start`.
+pub struct UvcCameraDriver {
+    config: CameraConfig,
+    stop: Arc<AtomicBool>,
+    reader_join: Option<JoinHandle<()>>,
+    /// Exposure changes for the capture thread, which owns the camera.
+    exposure_tx: Option<SyncSender<ExposureRequest>>,
+    frame_tx: FrameSender,
+    events_tx: SyncSender<CameraEvent>,
+}
+
+impl core::fmt::Debug for UvcCameraDriver {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("UvcCameraDriver")
+            .field("config", &self.config)
+            .field("running", &self.reader_join.is_some())
+            .finish()
+    }
+}
+
+impl UvcCameraDriver {
+    pub fn open(
+        _input_url: impl AsRef<str>,
+        config: CameraConfig,
+        frame_tx: FrameSender,
+        events_tx: SyncSender<CameraEvent>,
+    ) -> Result<Self, CameraError> {
+        // Prompts for camera access on macOS; a no-op elsewhere.
+        if !nokhwa::nokhwa_check() {
+            nokhwa::nokhwa_initialize(|_| {});
+        }
+        Ok(Self {
+            config,
+            stop: Arc::new(AtomicBool::new(false)),
+            reader_join: None,
+            exposure_tx: None,
+            frame_tx,
+            events_tx,
+        })
+    }
+
+    /// The camera index, the MJPEG mode to ask for the closest match to,
+    /// and the format frames are decoded to.
+    fn requested(&self) -> Result<(CameraIndex, CameraFormat, PixelFormat), CameraError> {
+        let pixel_format = self.config.pixel_format.unwrap_or(PixelFormat::Rgb8);
+        if !matches!(
+            pixel_format,
+            PixelFormat::Rgb8 | PixelFormat::Rgba8 | PixelFormat::Bgra8
+        ) {
+            return Err(CameraError::unsupported(format!(
+                "the uvc backend can't capture {}",
+                pixel_format.as_str()
+            )));
+        }
+        let index = camera_index(self.config.device.as_deref().unwrap_or("").trim())?;
+        let requested = CameraFormat::new(
+            Resolution::new(self.config.width, self.config.height),
+            FrameFormat::MJPEG,
+            if self.config.fps.is_finite() && self.config.fps >= 1.0 {
+                self.config.fps.min(240.0).round() as u32
+            } else {
+                30
+            },
+        );
+        Ok((index, requested, pixel_format))
+    }
+}
+
+impl CameraDriver for UvcCameraDriver {
+    fn backend(&self) -> CameraBackend {
+        CameraBackend::Uvc
+    }
+
+    fn start(&mut self) -> Result<(), CameraError> {
+        if self.reader_join.is_some() {
+            return Ok(());
+        }
+
+        let (index, requested, pixel_format) = self.requested()?;
+
+        self.stop.store(false, Ordering::Relaxed);
+        let stop = Arc::clone(&self.stop);
+        let frame_tx = self.frame_tx.clone();
+        let events_tx = self.events_tx.clone();
+        let (opened_tx, opened_rx) = sync_channel::<Result<(), CameraError>>(1);
+        let (exposure_tx, exposure_rx) = sync_channel::<ExposureRequest>(1);
+
+        let reader_join = std::thread::Builder::new()
+            .name("uvc-capture".into())
+            .spawn(move || {
+                let format =
+                    RequestedFormat::new::<RgbFormat>(RequestedFormatType::Closest(requested));
+                let mut camera = match Camera::new(index, format)
+                    .and_then(|mut c| c.open_stream().map(|()| c))
+                {
+                    Ok(camera) => camera,
+                    Err(e) => {
+                        let _ = opened_tx.send(Err(CameraError::driver("opening uvc camera", e)));
+                        return;
+                    },
+                };
+                let _ = opened_tx.send(Ok(()));
+
+                let mut metered = None;
+                while !stop.load(Ordering::Relaxed) {
+                    apply_exposure_requests(&mut camera, &exposure_rx, &mut metered);
+                    let buffer = match camera.frame() {
+                        Ok(buffer) => buffer,
+                        Err(_) if stop.load(Ordering::Relaxed) => break,
+                        Err(e) => {
+                            let _ = events_tx.try_send(CameraEvent::Error {
+                                backend: CameraBackend::Uvc,
+                                error: CameraError::driver("uvc read", e),
+                            });
+                            break;
+                        },
+                    };
+                    let ts = FrameTime::now();
+                    let resolution = buffer.resolution();
+                    let (width, height) = (resolution.width(), resolution.height());
+                    let stride = width * pixel_format.bytes_per_pixel();
+                    let mut data = vec![0u8; stride as usize * height as usize];
+                    let decoded = match pixel_format {
+                        PixelFormat::Rgb8 => buffer.decode_image_to_buffer::<RgbFormat>(&mut data),
+                        _ => buffer.decode_image_to_buffer::<RgbAFormat>(&mut data),
+                    };
+                    if let Err(e) = decoded {
+                        let _ = events_tx.try_send(CameraEvent::Warning {
+                            backend: CameraBackend::Uvc,
+                            message: format!("dropping undecodable frame: {e}"),
+                        });
+                        continue;
+                    }
+                    if pixel_format == PixelFormat::Bgra8 {
+                        swap_red_blue(&mut data);
+                    }
+                    let frame = Frame::new(Bytes::from(data), width, height, stride, pixel_format)
+                        .with_colorimetry(decoded_colorimetry(buffer.source_frame_format()))
+                        .with_time(ts);
+                    try_send_frame(&frame_tx, &events_tx, CameraBackend::Uvc, frame);
+                }
+                if let Some(metered) = metered {
+                    let _ = metered.restore(&mut camera);
+                }
+                let _ = camera.stop_stream();
+            })
+            .map_err(|e| CameraError::driver("spawning uvc capture thread", e))?;
+
+        match opened_rx.recv() {
+            Ok(Ok(())) => {
+                self.reader_join = Some(reader_join);
+                self.exposure_tx = Some(exposure_tx);
+                Ok(())
+            },
+            Ok(Err(err)) => {
+                let _ = reader_join.join();
+                Err(err)
+            },
+            Err(_) => Err(CameraError::other("uvc capture thread exited during open")),
+        }
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.exposure_tx = None;
+
+        // The thread notices the flag after the frame it's waiting for.
+        if let Some(j) = self.reader_join.take()
+            && !join_until(j, Instant::now() + self.config.stop_timeout)
+        {
+            let _ = self.events_tx.try_send(CameraEvent::Warning {
+                backend: CameraBackend::Uvc,
+                message: format!(
+                    "uvc reader did not exit within {:?}; abandoning it",
+                    self.config.stop_timeout
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn switch_device(&mut self, device: &str) -> Result<(), CameraError> {
+        let running = self.reader_join.is_some();
+        if running {
+            self.stop()?;
+        }
+        let previous = self.config.device.replace(device.to_string());
+        if !running {
+            return Ok(());
+        }
+        if let Err(err) = self.start() {
+            self.config.device = previous;
+            let _ = self.start();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn reconfigure(&mut self, config: &CameraConfig) -> Result<(), CameraError> {
+        let running = self.reader_join.is_some();
+        if running {
+            self.stop()?;
+        }
+        let reconfigured = self.config.with_format_of(config);
+        let previous = core::mem::replace(&mut self.config, reconfigured);
+        if !running {
+            return Ok(());
+        }
+        if let Err(err) = self.start() {
+            self.config = previous;
+            let _ = self.start();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn set_exposure_bias(&mut self, ev: f32) -> Result<(), CameraError> {
+        let Some(exposure_tx) = &self.exposure_tx else {
+            return Err(CameraError::invalid_config(
+                "the uvc camera must be capturing to bias exposure",
+            ));
+        };
+        let (reply_tx, reply_rx) = sync_channel(1);
+        exposure_tx
+            .send((ev, reply_tx))
+            .map_err(|_| CameraError::Closed)?;
+        // Handled between frames.
+        reply_rx
+            .recv_timeout(self.config.stop_timeout)
+            .unwrap_or_else(|_| Err(CameraError::other("uvc capture thread didn't respond")))
+    }
+
+    /// Opens the camera without streaming, which works alongside a
+    /// capture session on the same device.
+    fn modes(&mut self) -> Result<Vec<CameraMode>, CameraError> {
+        let index = camera_index(self.config.device.as_deref().unwrap_or("").trim())?;
+        let formats = Camera::new(
+            index,
+            RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
+        )
+        .and_then(|mut camera| camera.compatible_camera_formats())
+        .map_err(|e| CameraError::driver("querying uvc camera formats", e))?;
+        Ok(formats
+            .into_iter()
+            .map(|format| {
+                let fps = format.frame_rate() as f64;
+                CameraMode::new(format.width(), format.height())
+                    .with_format(format.format().to_string().to_lowercase())
+                    .with_fps(fps, fps)
+            })
+            .collect())
+    }
+
+    /// On Linux nokhwa numbers cameras by their `/dev/video` node, which
+    /// takes the request alongside a capture session.
+    #[cfg(target_os = "linux")]
+    fn vendor_control(&mut self, control: &VendorControl) -> Result<Vec<u8>, CameraError> {
+        use crate::shared::devices::xu::{uvc_device_path, uvc_xu_query};
+        let path = match camera_index(self.config.device.as_deref().unwrap_or("").trim())? {
+            CameraIndex::Index(n) => format!("/dev/video{n}"),
+            CameraIndex::String(path) => uvc_device_path(&path)?,
+        };
+        uvc_xu_query(&path, control)
+    }
+
+    /// Through the `/dev/video` node, as `vendor_control` does, leaving
+    /// the capture thread alone.
+    #[cfg(target_os = "linux")]
+    fn lock_auto(
+        &mut self,
+        control: crate::shared::AutoControl,
+        locked: bool,
+    ) -> Result<(), CameraError> {
+        use crate::shared::devices::{controls::v4l2_lock_auto, xu::uvc_device_path};
+        let path = match camera_index(self.config.device.as_deref().unwrap_or("").trim())? {
+            CameraIndex::Index(n) => format!("/dev/video{n}"),
+            CameraIndex::String(path) => uvc_device_path(&path)?,
+        };
+        v4l2_lock_auto(&path, control, locked)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn vendor_control(&mut self, control: &VendorControl) -> Result<Vec<u8>, CameraError> {
+        let _ = control;
+        Err(CameraError::unsupported(
+            "extension unit controls need Media Foundation's IKsControl, which nokhwa doesn't expose",
+        ))
+    }
+
+    fn plan(&self) -> Result<Option<CapturePlan>, CameraError> {
+        let (index, requested, pixel_format) = self.requested()?;
+        Ok(Some(CapturePlan::Request(format!(
+            "camera {index}: the mode closest to {}x{} @ {} fps MJPEG, decoded to {}",
+            requested.width(),
+            requested.height(),
+            requested.frame_rate(),
+            pixel_format.as_str()
+        ))))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Drop for UvcCameraDriver {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// The exposure the camera metered before the first bias, so an `ev` of 0
+/// can restore it.
+struct MeteredExposure {
+    value: i64,
+    range: (i64, i64),
+    /// The auto exposure mode that manual exposure replaced.
+    #[cfg(target_os = "linux")]
+    auto_mode: Option<i64>,
+}
+
+impl MeteredExposure {
+    fn read(camera: &Camera) -> Result<Self, CameraError> {
+        let control = camera
+            .camera_control(exposure_control())
+            .map_err(|e| CameraError::driver("reading uvc exposure", e))?;
+        let (value, range) = match *control.description() {
+            ControlValueDescription::IntegerRange {
+                value, min, max, ..
+            } => (value, (min, max)),
+            ControlValueDescription::Integer { value, .. } => (value, (i64::MIN, i64::MAX)),
+            _ => {
+                return Err(CameraError::unsupported(
+                    "this camera's exposure isn't adjustable",
+                ));
+            },
+        };
+        Ok(Self {
+            value,
+            range,
+            #[cfg(target_os = "linux")]
+            auto_mode: camera
+                .camera_control(KnownCameraControl::Other(V4L2_CID_EXPOSURE_AUTO))
+                .ok()
+                .and_then(|c| match c.value() {
+                    ControlValueSetter::Integer(mode) | ControlValueSetter::EnumValue(mode) => {
+                        Some(mode)
+                    },
+                    _ => None,
+                }),
+        })
+    }
+
+    /// Sets the exposure `ev` stops away from the metered one.
+    fn bias(&self, camera: &mut Camera, ev: f32) -> Result<(), CameraError> {
+        // Media Foundation counts exposure in log2 seconds, the others in
+        // units of time.
+        let value = if cfg!(target_os = "windows") {
+            self.value + ev.round() as i64
+        } else {
+            (self.value.max(1) as f64 * 2f64.powf(ev as f64)).round() as i64
+        };
+        #[cfg(target_os = "linux")]
+        if self.auto_mode.is_some_and(|mode| mode != 1) {
+            // Verification of menu controls is unreliable; the exposure
+            // write below fails anyway if this didn't take.
+            let _ = camera.set_camera_control(
+                KnownCameraControl::Other(V4L2_CID_EXPOSURE_AUTO),
+                ControlValueSetter::Integer(1),
+            );
+        }
+        camera
+            .set_camera_control(
+                exposure_control(),
+                ControlValueSetter::Integer(value.clamp(self.range.0, self.range.1)),
+            )
+            .map_err(|e| CameraError::driver("setting uvc exposure", e))
+    }
+
+    fn restore(&self, camera: &mut Camera) -> Result<(), CameraError> {
+        let result = self.bias(camera, 0.0);
+        #[cfg(target_os = "linux")]
+        if let Some(mode) = self.auto_mode.filter(|&mode| mode != 1) {
+            let _ = camera.set_camera_control(
+                KnownCameraControl::Other(V4L2_CID_EXPOSURE_AUTO),
+                ControlValueSetter::Integer(mode),
+            );
+        }
+        result
+    }
+}
+
+fn exposure_control() -> KnownCameraControl {
+    if cfg!(target_os = "linux") {
+        KnownCameraControl::Other(V4L2_CID_EXPOSURE_ABSOLUTE)
+    } else {
+        KnownCameraControl::Exposure
+    }
+}
+
+/// Applies pending `UvcCameraDriver::set_exposure_bias` calls on the
+/// capture thread.
+fn apply_exposure_requests(
+    camera: &mut Camera,
+    requests: &Receiver<ExposureRequest>,
+    metered: &mut Option<MeteredExposure>,
+) {
+    while let Ok((ev, reply)) = requests.try_recv() {
+        let result = match (ev == 0.0, metered.take()) {
+            (true, None) => Ok(()),
+            (true, Some(previous)) => previous.restore(camera),
+            (false, Some(previous)) => {
+                let result = previous.bias(camera, ev);
+                *metered = Some(previous);
+                result
+            },
+            (false, None) => MeteredExposure::read(camera).and_then(|previous| {
+                let result = previous.bias(camera, ev);
+                *metered = Some(previous);
+                result
+            }),
+        };
+        let _ = reply.send(result);
+    }
+}
+
+/// Maps the ids the other backends use (`file:/dev/video2`, `avf:1`,
+/// `dshow:video=NAME`) onto a nokhwa camera index.
+fn camera_index(device: &str) -> Result<CameraIndex, CameraError> {
+    if let Some(position) = CameraPosition::from_device_id(device) {
+        return Err(CameraError::unsupported(format!(
+            "the uvc backend can't select the {position} camera by position; use its index or name"
+        )));
+    }
+    let id = ["file:", "avf:", "dshow:", "uvc:"]
+        .iter()
+        .find_map(|prefix| device.strip_prefix(prefix))
+        .unwrap_or(device);
+    let id = id.strip_prefix("video=").unwrap_or(id);
+    let id = id.strip_prefix("/dev/video").unwrap_or(id);
+    if id.is_empty() || id.eq_ignore_ascii_case("default") {
+        return Ok(CameraIndex::Index(0));
+    }
+    if let Ok(n) = id.parse::<u32>() {
+        return Ok(CameraIndex::Index(n));
+    }
+
+    let cameras = nokhwa::query(ApiBackend::Auto)
+        .map_err(|e| CameraError::driver("listing uvc cameras", e))?;
+    cameras
+        .iter()
+        .find(|info| info.human_name().eq_ignore_ascii_case(id))
+        .map(|info| info.index().clone())
+        .ok_or_else(|| {
+            CameraError::invalid_config(format!(
+                "no camera named '{id}' (found: {})",
+                cameras
+                    .iter()
+                    .map(|info| info.human_name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })
+}
+
+/// What nokhwa's decoding of a `source` frame produces: JFIF colors for
+/// MJPEG, and its BT.601 conversion, expanded to full range, for YUV.
+fn decoded_colorimetry(source: FrameFormat) -> Colorimetry {
+    match source {
+        FrameFormat::MJPEG => Colorimetry::JPEG,
+        FrameFormat::YUYV | FrameFormat::NV12 => Colorimetry {
+            matrix: Some(ColorMatrix::Bt601),
+            range: Some(ColorRange::Full),
+            srgb: false,
+        },
+        _ => Colorimetry::UNKNOWN,
+    }
+}
@@ -1,14 +1,18 @@
 // This is free and unencumbered software released into the public domain.
 
 use crate::shared::{
-    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, FrameMsg,
+    AutoControl, CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, FrameSender,
+    devices::{
+        controls::v4l2_lock_auto,
+        xu::{VendorControl, uvc_device_path, uvc_xu_query},
+    },
 };
 use std::{any::Any, sync::mpsc::SyncSender};
 
 #[derive(Debug)]
 pub struct V4l2CameraDriver {
-    _config: CameraConfig,
-    _frame_tx: SyncSender<FrameMsg>,
+    config: CameraConfig,
+    _frame_tx: FrameSender,
     _events_tx: SyncSender<CameraEvent>,
 }
 
@@ -16,11 +20,11 @@ impl V4l2CameraDriver {
     pub fn open(
         _input_url: impl AsRef<str>,
         config: CameraConfig,
-        frame_tx: SyncSender<FrameMsg>,
+        frame_tx: FrameSender,
         events_tx: SyncSender<CameraEvent>,
     ) -> Result<Self, CameraError> {
         Ok(Self {
-            _config: config,
+            config,
             _frame_tx: frame_tx,
             _events_tx: events_tx,
         })
@@ -40,6 +44,16 @@ impl CameraDriver for V4l2CameraDriver {
         Ok(())
     }
 
+    fn vendor_control(&mut self, control: &VendorControl) -> Result<Vec<u8>, CameraError> {
+        let device = self.config.device.as_deref().unwrap_or("").trim();
+        uvc_xu_query(&uvc_device_path(device)?, control)
+    }
+
+    fn lock_auto(&mut self, control: AutoControl, locked: bool) -> Result<(), CameraError> {
+        let device = self.config.device.as_deref().unwrap_or("").trim();
+        v4l2_lock_auto(&uvc_device_path(device)?, control, locked)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -0,0 +1,323 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{
+    CameraBackend, CameraConfig, CameraDriver, CameraError, CameraEvent, CameraPosition,
+    Colorimetry, Frame, FrameMsg, FrameSender, FrameStages, FrameTime, SinkSet, deliver_frame,
+    end_stream, try_send_frame,
+};
+use alloc::{borrow::Cow, rc::Rc};
+use bytes::Bytes;
+use core::cell::{Cell, RefCell};
+use js_sys::{Object, Promise, Reflect};
+use std::{
+    any::Any,
+    sync::{
+        Arc, Mutex, RwLock,
+        mpsc::{Receiver, SyncSender, TryRecvError},
+    },
+};
+use wasm_bindgen::{JsCast, JsValue, closure::Closure};
+use wasm_bindgen_futures::{JsFuture, spawn_local};
+use web_sys::{
+    CanvasRenderingContext2d, HtmlCanvasElement, HtmlVideoElement, MediaStream,
+    MediaStreamConstraints, MediaStreamTrack,
+};
+
+type TickClosure = Rc<RefCell<Option<Closure<dyn FnMut(f64)>>>>;
+
+#[derive(Debug, Default)]
+struct WebState {
+    running: Cell<bool>,
+    stream: RefCell<Option<MediaStream>>,
+}
+
+#[derive(Debug)]
+pub struct WebCameraDriver {
+    config: CameraConfig,
+    state: Rc<WebState>,
+    frame_tx: FrameSender,
+    events_tx: SyncSender<CameraEvent>,
+}
+
+// wasm32-unknown-unknown runs the driver on the browser's single JS thread,
+// so the non-Send JS handles are never shared across threads.
+unsafe impl Send for WebCameraDriver {}
+
+impl dogma::Named for WebCameraDriver {
+    fn name(&self) -> Cow<'_, str> {
+        "getusermedia".into()
+    }
+}
+
+impl WebCameraDriver {
+    pub fn open(
+        _input_url: impl AsRef<str>,
+        config: CameraConfig,
+        frame_tx: FrameSender,
+        events_tx: SyncSender<CameraEvent>,
+    ) -> Result<Self, CameraError> {
+        if web_sys::window().is_none() {
+            return Err(CameraError::NotApplicable);
+        }
+        Ok(Self {
+            config,
+            state: Rc::new(WebState::default()),
+            frame_tx,
+            events_tx,
+        })
+    }
+}
+
+impl CameraDriver for WebCameraDriver {
+    fn backend(&self) -> CameraBackend {
+        CameraBackend::Web
+    }
+
+    fn start(&mut self) -> Result<(), CameraError> {
+        if self.state.running.get() {
+            return Ok(());
+        }
+
+        let window = web_sys::window().ok_or(CameraError::NotApplicable)?;
+        let devices = window
+            .navigator()
+            .media_devices()
+            .map_err(|e| js_error("accessing navigator.mediaDevices", e))?;
+
+        let constraints = MediaStreamConstraints::new();
+        constraints.set_audio(&JsValue::FALSE);
+        constraints.set_video(&video_constraints(&self.config));
+        let promise = devices
+            .get_user_media_with_constraints(&constraints)
+            .map_err(|e| js_error("calling getUserMedia", e))?;
+
+        self.state.running.set(true);
+
+        let state = Rc::clone(&self.state);
+        let config = self.config.clone();
+        let frame_tx = self.frame_tx.clone();
+        let events_tx = self.events_tx.clone();
+
+        spawn_local(async move {
+            let result = match JsFuture::from(promise).await {
+                Ok(stream) => start_capture(
+                    &state,
+                    stream.unchecked_into(),
+                    &config,
+                    frame_tx,
+                    events_tx.clone(),
+                ),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                state.running.set(false);
+                let _ = events_tx.try_send(CameraEvent::Error {
+                    backend: CameraBackend::Web,
+                    error: js_error("starting getUserMedia capture", e),
+                });
+            }
+        });
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), CameraError> {
+        self.state.running.set(false);
+        if let Some(stream) = self.state.stream.borrow_mut().take() {
+            for track in stream.get_tracks().iter() {
+                track.unchecked_into::<MediaStreamTrack>().stop();
+            }
+        }
+        Ok(())
+    }
+
+    fn switch_device(&mut self, device: &str) -> Result<(), CameraError> {
+        // getUserMedia fails asynchronously, so errors from the new device
+        // arrive as events rather than here.
+        let running = self.state.running.get();
+        self.stop()?;
+        self.config.device = Some(device.to_string());
+        if running { self.start() } else { Ok(()) }
+    }
+
+    fn reconfigure(&mut self, config: &CameraConfig) -> Result<(), CameraError> {
+        // Like switching devices, the new constraints apply asynchronously.
+        let running = self.state.running.get();
+        self.stop()?;
+        self.config = self.config.with_format_of(config);
+        if running { self.start() } else { Ok(()) }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Drop for WebCameraDriver {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Runs the dispatcher on the JS event loop, since the browser has no
+/// threads to block on the frame channel.
+pub(crate) fn spawn_dispatch_loop(
+    rx: Receiver<FrameMsg>,
+    idle: Arc<Mutex<Option<Receiver<FrameMsg>>>>,
+    sinks: Arc<RwLock<SinkSet>>,
+    stages: Arc<FrameStages>,
+    events_tx: SyncSender<CameraEvent>,
+    backend: CameraBackend,
+) {
+    spawn_local(async move {
+        let _ = events_tx.try_send(CameraEvent::Started { backend });
+        loop {
+            match rx.try_recv() {
+                Ok(FrameMsg::Frame(frame)) => deliver_frame(&sinks, &stages, frame),
+                Ok(FrameMsg::Eos) => end_stream(&stages),
+                Ok(FrameMsg::Stop) | Err(TryRecvError::Disconnected) => break,
+                Err(TryRecvError::Empty) => next_animation_frame().await,
+            }
+        }
+        *idle.lock().unwrap_or_else(|p| p.into_inner()) = Some(rx);
+        let _ = events_tx.try_send(stages.stopped_event());
+    });
+}
+
+fn start_capture(
+    state: &Rc<WebState>,
+    stream: MediaStream,
+    config: &CameraConfig,
+    frame_tx: FrameSender,
+    events_tx: SyncSender<CameraEvent>,
+) -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+    let document = window
+        .document()
+        .ok_or_else(|| JsValue::from_str("no document"))?;
+    let performance = window
+        .performance()
+        .ok_or_else(|| JsValue::from_str("no performance timer"))?;
+
+    let video: HtmlVideoElement = document.create_element("video")?.dyn_into()?;
+    video.set_muted(true);
+    video.set_attribute("playsinline", "")?;
+    video.set_src_object(Some(&stream));
+    let _ = video.play()?;
+
+    let (width, height) = (config.width, config.height);
+    let canvas: HtmlCanvasElement = document.create_element("canvas")?.dyn_into()?;
+    canvas.set_width(width);
+    canvas.set_height(height);
+    let context: CanvasRenderingContext2d = canvas
+        .get_context("2d")?
+        .ok_or_else(|| JsValue::from_str("no 2d canvas context"))?
+        .dyn_into()?;
+
+    *state.stream.borrow_mut() = Some(stream);
+
+    let min_interval_ms = 1000.0 / config.fps.max(0.1);
+    let mut last_ms = f64::NEG_INFINITY;
+
+    let tick: TickClosure = Rc::new(RefCell::new(None));
+    let next_tick = Rc::clone(&tick);
+    let state = Rc::clone(state);
+
+    *tick.borrow_mut() = Some(Closure::new(move |now_ms: f64| {
+        if !state.running.get() {
+            let _ = next_tick.borrow_mut().take();
+            return;
+        }
+
+        // HAVE_CURRENT_DATA: the element has a decoded frame to draw.
+        if now_ms - last_ms >= min_interval_ms && video.ready_state() >= 2 {
+            last_ms = now_ms;
+            let (w, h) = (width as f64, height as f64);
+            if context
+                .draw_image_with_html_video_element_and_dw_and_dh(&video, 0.0, 0.0, w, h)
+                .is_ok()
+                && let Ok(image) = context.get_image_data(0.0, 0.0, w, h)
+            {
+                let time = FrameTime {
+                    wall_ns: ((performance.time_origin() + now_ms) * 1e6) as u64,
+                    monotonic_ns: (now_ms * 1e6) as u64,
+                };
+                let frame = Frame::new_rgba8(Bytes::from(image.data().0), width, height, width * 4)
+                    .with_colorimetry(Colorimetry::SRGB)
+                    .with_time(time);
+                try_send_frame(&frame_tx, &events_tx, CameraBackend::Web, frame);
+            }
+        }
+
+        if let Some(closure) = next_tick.borrow().as_ref() {
+            request_animation_frame(closure);
+        }
+    }));
+
+    if let Some(closure) = tick.borrow().as_ref() {
+        request_animation_frame(closure);
+    }
+    Ok(())
+}
+
+fn request_animation_frame(closure: &Closure<dyn FnMut(f64)>) {
+    if let Some(window) = web_sys::window() {
+        let _ = window.request_animation_frame(closure.as_ref().unchecked_ref());
+    }
+}
+
+async fn next_animation_frame() {
+    let promise = Promise::new(&mut |resolve, _reject| {
+        if let Some(window) = web_sys::window() {
+            let _ = window.request_animation_frame(&resolve);
+        }
+    });
+    let _ = JsFuture::from(promise).await;
+}
+
+fn video_constraints(config: &CameraConfig) -> JsValue {
+    let video = Object::new();
+    set_property(&video, "width", &ideal(config.width.into()));
+    set_property(&video, "height", &ideal(config.height.into()));
+    set_property(&video, "frameRate", &ideal(config.fps.into()));
+
+    let device = config.device.as_deref().unwrap_or("").trim();
+    if let Some(position) = CameraPosition::from_device_id(device) {
+        let facing = match position {
+            CameraPosition::Front => "user",
+            CameraPosition::Back => "environment",
+        };
+        let exact = Object::new();
+        set_property(&exact, "exact", &facing.into());
+        set_property(&video, "facingMode", &exact);
+        return video.into();
+    }
+
+    let device = device.strip_prefix("web:").unwrap_or(device);
+    if !device.is_empty() {
+        let exact = Object::new();
+        set_property(&exact, "exact", &device.into());
+        set_property(&video, "deviceId", &exact);
+    }
+
+    video.into()
+}
+
+fn ideal(value: JsValue) -> Object {
+    let object = Object::new();
+    set_property(&object, "ideal", &value);
+    object
+}
+
+fn set_property(target: &Object, key: &str, value: &JsValue) {
+    let _ = Reflect::set(target, &JsValue::from_str(key), value);
+}
+
+fn js_error(context: &str, value: JsValue) -> CameraError {
+    let detail = value.as_string().unwrap_or_else(|| format!("{value:?}"));
+    CameraError::other(format!("{context}: {detail}"))
+}
@@ -0,0 +1,302 @@
+// This is free and unencumbered software released into the public domain.
+
+//! At-rest encryption for the frames and recordings sinks write, so footage
+//! on a shared device can't be read without the key.
+//!
+//! Files are AES-256-GCM in 64 KiB chunks, as in the STREAM construction:
+//! an 8-byte magic and a random 7-byte nonce prefix, then each chunk sealed
+//! under the prefix, its big-endian index and a final-chunk flag. Chunks
+//! can't be reordered, dropped or cut off without decryption failing, and
+//! recordings are encrypted as they are written, without the plaintext
+//! touching the disk.
+
+use crate::shared::CameraError;
+use aes_gcm::{
+    Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, OsRng, Payload, rand_core::RngCore},
+};
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// The extension encrypted files get on top of their own (`1.png.enc`).
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+const MAGIC: &[u8; 8] = b"ACAMGCM1";
+const PREFIX_LEN: usize = 7;
+const HEADER_LEN: usize = MAGIC.len() + PREFIX_LEN;
+const CHUNK_LEN: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+
+/// `path` with `ENCRYPTED_EXTENSION` appended.
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(ENCRYPTED_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// A 256-bit AES-GCM key.
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl core::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl EncryptionKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// A random key from the operating system.
+    pub fn generate() -> Self {
+        let mut key = [0; 32];
+        OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// Reads a key file: 32 raw bytes, or 64 hex digits as
+    /// `openssl rand -hex 32` writes them.
+    pub fn from_file(path: &Path) -> Result<Self, CameraError> {
+        let invalid = |reason: &dyn core::fmt::Display| {
+            CameraError::invalid_config(format!("key file {}: {reason}", path.display()))
+        };
+        let data = std::fs::read(path).map_err(|e| invalid(&e))?;
+        if let Ok(key) = <[u8; 32]>::try_from(data.as_slice()) {
+            return Ok(Self(key));
+        }
+        let hex = data.trim_ascii();
+        if hex.len() != 64 {
+            return Err(invalid(&"expected 32 bytes or 64 hex digits"));
+        }
+        let mut key = [0; 32];
+        for (byte, pair) in key.iter_mut().zip(hex.chunks(2)) {
+            *byte = core::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| invalid(&"expected 32 bytes or 64 hex digits"))?;
+        }
+        Ok(Self(key))
+    }
+
+    /// Encrypts all of `data` at once.
+    pub fn encrypt(&self, data: &[u8]) -> Vec<u8> {
+        let mut writer = self.encryptor(Vec::with_capacity(encrypted_len(data.len())));
+        // Writing to a `Vec` can't fail.
+        let _ = writer.write_all(data);
+        writer.finish().unwrap_or_default()
+    }
+
+    /// Decrypts what `encrypt` or an `Encryptor` wrote.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, CameraError> {
+        let mut plain = Vec::with_capacity(data.len());
+        self.decryptor(data)
+            .read_to_end(&mut plain)
+            .map_err(|e| CameraError::driver("decrypting", e))?;
+        Ok(plain)
+    }
+
+    /// Encrypts what is written to it into `inner`, until `finish`.
+    pub fn encryptor<W: Write>(&self, inner: W) -> Encryptor<W> {
+        let mut prefix = [0; PREFIX_LEN];
+        OsRng.fill_bytes(&mut prefix);
+        Encryptor {
+            inner,
+            cipher: Aes256Gcm::new(&self.0.into()),
+            prefix,
+            index: 0,
+            header: false,
+            chunk: Vec::with_capacity(CHUNK_LEN),
+        }
+    }
+
+    /// Reads the plaintext of what `inner` holds, failing with
+    /// `InvalidData` where it was tampered with or cut off.
+    pub fn decryptor<R: Read>(&self, inner: R) -> Decryptor<R> {
+        Decryptor {
+            inner,
+            cipher: Aes256Gcm::new(&self.0.into()),
+            prefix: None,
+            index: 0,
+            lookahead: None,
+            plain: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+fn encrypted_len(len: usize) -> usize {
+    HEADER_LEN + len + len.div_ceil(CHUNK_LEN).max(1) * TAG_LEN
+}
+
+fn nonce(prefix: &[u8; PREFIX_LEN], index: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[..PREFIX_LEN].copy_from_slice(prefix);
+    nonce[PREFIX_LEN..11].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Encrypts into a writer; without `finish` the file can't be decrypted
+/// to its end, as with a recording cut off by a crash.
+pub struct Encryptor<W: Write> {
+    inner: W,
+    cipher: Aes256Gcm,
+    prefix: [u8; PREFIX_LEN],
+    index: u32,
+    header: bool,
+    chunk: Vec<u8>,
+}
+
+impl<W: Write> Encryptor<W> {
+    /// Seals the last chunk and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.seal(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn seal(&mut self, last: bool) -> io::Result<()> {
+        if !self.header {
+            self.inner.write_all(MAGIC)?;
+            self.inner.write_all(&self.prefix)?;
+            self.header = true;
+        }
+        let nonce = nonce(&self.prefix, self.index, last);
+        let sealed = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &self.chunk,
+                    aad: MAGIC,
+                },
+            )
+            .map_err(|_| io::Error::other("encrypting a chunk failed"))?;
+        self.inner.write_all(&sealed)?;
+        self.chunk.clear();
+        self.index = self
+            .index
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("too many chunks to encrypt"))?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Encryptor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A full chunk is only sealed once more follows, so the last one
+        // is never empty unless everything is.
+        if self.chunk.len() == CHUNK_LEN && !buf.is_empty() {
+            self.seal(false)?;
+        }
+        let n = buf.len().min(CHUNK_LEN - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    /// Flushes the sealed chunks; the one being filled stays buffered.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts from a reader.
+pub struct Decryptor<R: Read> {
+    inner: R,
+    cipher: Aes256Gcm,
+    prefix: Option<[u8; PREFIX_LEN]>,
+    index: u32,
+    /// A byte read past a full chunk to tell whether another follows.
+    lookahead: Option<u8>,
+    plain: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Decryptor<R> {
+    /// Reads into `buf` until it is full or the input ends.
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut len = 0;
+        while len < buf.len() {
+            match self.inner.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(len)
+    }
+
+    fn open_next(&mut self) -> io::Result<()> {
+        let prefix = match self.prefix {
+            Some(prefix) => prefix,
+            None => {
+                let mut header = [0; HEADER_LEN];
+                if self.fill(&mut header)? < HEADER_LEN || &header[..MAGIC.len()] != MAGIC {
+                    return Err(invalid_data("not an encrypted camera file"));
+                }
+                let prefix = header[MAGIC.len()..].try_into().unwrap_or_default();
+                *self.prefix.insert(prefix)
+            },
+        };
+        let mut sealed = vec![0; CHUNK_LEN + TAG_LEN];
+        let mut len = 0;
+        if let Some(byte) = self.lookahead.take() {
+            sealed[0] = byte;
+            len = 1;
+        }
+        len += self.fill(&mut sealed[len..])?;
+        let last = len < sealed.len() || {
+            let mut byte = [0];
+            let more = self.fill(&mut byte)? == 1;
+            self.lookahead = more.then_some(byte[0]);
+            !more
+        };
+        let nonce = nonce(&prefix, self.index, last);
+        self.plain = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &sealed[..len],
+                    aad: MAGIC,
+                },
+            )
+            .map_err(|_| {
+                invalid_data("the key doesn't match, or the file was changed or cut off")
+            })?;
+        self.pos = 0;
+        self.done = last;
+        self.index = self
+            .index
+            .checked_add(1)
+            .ok_or_else(|| invalid_data("too many chunks"))?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Decryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.open_next()?;
+        }
+        let n = buf.len().min(self.plain.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
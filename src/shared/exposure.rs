@@ -0,0 +1,137 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{Frame, process::luma};
+
+pub const LUMA_BINS: usize = 16;
+
+/// Luma values at or below this count as crushed blacks.
+const DARK_LEVEL: u8 = 16;
+/// Luma values at or above this count as blown highlights.
+const BRIGHT_LEVEL: u8 = 240;
+
+/// Per-frame luminance summary.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LuminanceStats {
+    /// Mean luma, `0.0..=1.0`.
+    pub mean: f32,
+    /// Fraction of sampled pixels that are nearly black.
+    pub dark_fraction: f32,
+    /// Fraction of sampled pixels that are nearly white.
+    pub bright_fraction: f32,
+    /// Luma histogram with 16 equal-width bins, as fractions of the sample.
+    pub histogram: [f32; LUMA_BINS],
+}
+
+impl LuminanceStats {
+    /// Samples every `step`-th pixel on every `step`-th row.
+    pub fn compute(frame: &Frame, step: u32) -> Option<Self> {
+        if !frame.validate() {
+            return None;
+        }
+        let step = step.max(1) as usize;
+        let bpp = frame.pixel_format.bytes_per_pixel() as usize;
+        let stride = frame.stride as usize;
+        let row_len = frame.width as usize * bpp;
+
+        let mut bins = [0u32; LUMA_BINS];
+        let (mut sum, mut dark, mut bright, mut n) = (0u64, 0u32, 0u32, 0u32);
+        for y in (0..frame.height as usize).step_by(step) {
+            let row = &frame.data[y * stride..][..row_len];
+            for px in row.chunks_exact(bpp).step_by(step) {
+                let l = luma(px, frame.pixel_format);
+                bins[l as usize * LUMA_BINS / 256] += 1;
+                sum += l as u64;
+                dark += (l <= DARK_LEVEL) as u32;
+                bright += (l >= BRIGHT_LEVEL) as u32;
+                n += 1;
+            }
+        }
+
+        let total = n.max(1) as f32;
+        Some(Self {
+            mean: sum as f32 / total / 255.0,
+            dark_fraction: dark as f32 / total,
+            bright_fraction: bright as f32 / total,
+            histogram: bins.map(|b| b as f32 / total),
+        })
+    }
+}
+
+/// Thresholds for the low-light / overexposure warnings.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExposureCheck {
+    /// Warn when the mean luma drops below this (`0.0..=1.0`).
+    pub min_mean: f32,
+    /// Warn when the mean luma rises above this (`0.0..=1.0`).
+    pub max_mean: f32,
+    /// Pixel sampling step for the histogram.
+    pub sample_step: u32,
+}
+
+impl Default for ExposureCheck {
+    fn default() -> Self {
+        Self {
+            min_mean: 0.08,
+            max_mean: 0.92,
+            sample_step: 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Exposure {
+    #[default]
+    Normal,
+    Dark,
+    Bright,
+}
+
+/// Tracks the exposure state across frames so warnings fire once per
+/// transition rather than on every frame.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ExposureMonitor {
+    check: ExposureCheck,
+    state: Exposure,
+}
+
+impl ExposureMonitor {
+    pub(crate) fn new(check: ExposureCheck) -> Self {
+        Self {
+            check,
+            state: Exposure::Normal,
+        }
+    }
+
+    pub(crate) fn sample_step(&self) -> u32 {
+        self.check.sample_step
+    }
+
+    /// Returns a warning message when the scene becomes too dark or too bright.
+    pub(crate) fn observe(&mut self, stats: &LuminanceStats) -> Option<String> {
+        let state = if stats.mean < self.check.min_mean {
+            Exposure::Dark
+        } else if stats.mean > self.check.max_mean {
+            Exposure::Bright
+        } else {
+            Exposure::Normal
+        };
+        let previous = core::mem::replace(&mut self.state, state);
+        match (previous, state) {
+            (a, b) if a == b => None,
+            (_, Exposure::Dark) => Some(format!(
+                "scene too dark (mean luma {:.0}%, {:.0}% near black); check lighting",
+                stats.mean * 100.0,
+                stats.dark_fraction * 100.0
+            )),
+            (_, Exposure::Bright) => Some(format!(
+                "scene overexposed (mean luma {:.0}%, {:.0}% near white)",
+                stats.mean * 100.0,
+                stats.bright_fraction * 100.0
+            )),
+            (_, Exposure::Normal) => Some(format!(
+                "exposure back to normal (mean luma {:.0}%)",
+                stats.mean * 100.0
+            )),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Focus measures for cameras without autofocus, so an industrial lens can
+//! be focused by hand against a number instead of by eye.
+
+use crate::shared::{Frame, LUMA_BINS, Rect, process::luma};
+
+/// Where `FocusStats::compute` measures.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FocusCheck {
+    /// The region to measure, in pixels of the transformed frame; the
+    /// whole frame when `None` or when it doesn't fit.
+    pub region: Option<Rect>,
+}
+
+impl FocusCheck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_region(mut self, region: Rect) -> Self {
+        self.region = Some(region);
+        self
+    }
+}
+
+/// Per-frame focus summary.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FocusStats {
+    /// Variance of the Laplacian of the luma, in squared 8-bit levels: the
+    /// sharper the focus, the higher. Only comparable between frames of
+    /// the same scene, region and size.
+    pub sharpness: f32,
+    /// RMS contrast, the standard deviation of the luma (`0.0..=1.0`).
+    /// Sharpness rises with it too, so this tells a change of lighting
+    /// from one of focus.
+    pub contrast: f32,
+    /// Luma histogram of the region with 16 equal-width bins, as fractions
+    /// of its pixels.
+    pub histogram: [f32; LUMA_BINS],
+}
+
+impl FocusStats {
+    /// Measures every pixel of the region; `None` for malformed frames and
+    /// regions under 3x3 pixels.
+    pub fn compute(frame: &Frame, check: &FocusCheck) -> Option<Self> {
+        if !frame.validate() {
+            return None;
+        }
+        let region = check
+            .region
+            .filter(|r| r.fits(frame.width, frame.height))
+            .unwrap_or(Rect::new(0, 0, frame.width, frame.height));
+        let (w, h) = (region.width as usize, region.height as usize);
+        if w < 3 || h < 3 {
+            return None;
+        }
+        let bpp = frame.pixel_format.bytes_per_pixel() as usize;
+        let stride = frame.stride as usize;
+
+        let mut lumas = Vec::with_capacity(w * h);
+        let mut bins = [0u32; LUMA_BINS];
+        let (mut sum, mut sum_sq) = (0u64, 0u64);
+        for y in region.y as usize..region.y as usize + h {
+            let row = &frame.data[y * stride + region.x as usize * bpp..][..w * bpp];
+            for px in row.chunks_exact(bpp) {
+                let l = luma(px, frame.pixel_format);
+                bins[l as usize * LUMA_BINS / 256] += 1;
+                sum += l as u64;
+                sum_sq += l as u64 * l as u64;
+                lumas.push(l as i32);
+            }
+        }
+
+        // The 4-neighbour Laplacian of every interior pixel.
+        let (mut lap_sum, mut lap_sq) = (0i64, 0i64);
+        for y in 1..h - 1 {
+            for x in 1..w - 1 {
+                let i = y * w + x;
+                let l = 4 * lumas[i] - lumas[i - 1] - lumas[i + 1] - lumas[i - w] - lumas[i + w];
+                lap_sum += l as i64;
+                lap_sq += l as i64 * l as i64;
+            }
+        }
+
+        let n = (w * h) as f64;
+        let interior = ((w - 2) * (h - 2)) as f64;
+        let mean = sum as f64 / n;
+        let lap_mean = lap_sum as f64 / interior;
+        Some(Self {
+            sharpness: (lap_sq as f64 / interior - lap_mean * lap_mean).max(0.0) as f32,
+            contrast: ((sum_sq as f64 / n - mean * mean).max(0.0).sqrt() / 255.0) as f32,
+            histogram: bins.map(|b| (b as f64 / n) as f32),
+        })
+    }
+}
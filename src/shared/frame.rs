@@ -1,23 +1,165 @@
 // This is free and unencumbered software released into the public domain.
 
+use crate::shared::{
+    CameraError, Colorimetry, FocusStats, FrameDefect, FrameHandle, LuminanceStats, StageTimes,
+    TelemetryEntry, TriggeredExposure,
+};
 use bytes::Bytes;
+use core::str::FromStr;
+use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PixelFormat {
     Rgb8,
     Bgra8,
+    Rgba8,
+    /// 16-bit little-endian grey, e.g. an infrared/NIR sensor (V4L2 `Y16`).
+    Gray16,
+    /// 16-bit little-endian depth in device units, usually millimetres
+    /// (RealSense `Z16`, TrueDepth `kCVPixelFormatType_DepthFloat16` converted).
+    Z16,
+    /// 10-bit YUV 4:2:0: a plane of 16-bit little-endian luma samples with
+    /// the value in their top 10 bits, then a half-height plane of
+    /// interleaved Cb/Cr pairs, both `stride` bytes per row.
+    P010,
+    /// 10-bit RGB in one little-endian 32-bit word per pixel: red in bits
+    /// 0–9, green 10–19, blue 20–29 and a 2-bit alpha on top (DRM `AB30`).
+    Rgba1010102,
 }
 
 impl PixelFormat {
     #[inline]
     pub const fn bytes_per_pixel(self) -> u32 {
+        self.channels() * self.bytes_per_sample()
+    }
+
+    /// Samples per pixel in the first plane; RGBA1010102 counts as one
+    /// packed 32-bit sample.
+    pub const fn channels(self) -> u32 {
         match self {
             PixelFormat::Rgb8 => 3,
-            PixelFormat::Bgra8 => 4,
+            PixelFormat::Bgra8 | PixelFormat::Rgba8 => 4,
+            PixelFormat::Gray16 | PixelFormat::Z16 | PixelFormat::P010 => 1,
+            PixelFormat::Rgba1010102 => 1,
+        }
+    }
+
+    pub const fn bytes_per_sample(self) -> u32 {
+        match self {
+            PixelFormat::Rgb8 | PixelFormat::Bgra8 | PixelFormat::Rgba8 => 1,
+            PixelFormat::Gray16 | PixelFormat::Z16 | PixelFormat::P010 => 2,
+            PixelFormat::Rgba1010102 => 4,
+        }
+    }
+
+    /// Significant bits per color component.
+    pub const fn bit_depth(self) -> u32 {
+        match self {
+            PixelFormat::Rgb8 | PixelFormat::Bgra8 | PixelFormat::Rgba8 => 8,
+            PixelFormat::P010 | PixelFormat::Rgba1010102 => 10,
+            PixelFormat::Gray16 | PixelFormat::Z16 => 16,
+        }
+    }
+
+    /// Whether frames hold more than one plane, which only conversions
+    /// (`Frame::to_rgb8` and friends) understand.
+    pub const fn is_planar(self) -> bool {
+        matches!(self, PixelFormat::P010)
+    }
+
+    /// How many `stride`-byte rows a frame `height` pixels tall occupies,
+    /// counting the half-height chroma plane of P010.
+    pub const fn buffer_rows(self, height: u32) -> u32 {
+        match self {
+            PixelFormat::P010 => height + height / 2,
+            _ => height,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            PixelFormat::Rgb8 => "rgb8",
+            PixelFormat::Bgra8 => "bgra8",
+            PixelFormat::Rgba8 => "rgba8",
+            PixelFormat::Gray16 => "gray16",
+            PixelFormat::Z16 => "z16",
+            PixelFormat::P010 => "p010",
+            PixelFormat::Rgba1010102 => "rgba1010102",
+        }
+    }
+
+    /// The stream a frame in this format most likely comes from.
+    pub const fn default_stream(self) -> FrameStream {
+        match self {
+            PixelFormat::Rgb8
+            | PixelFormat::Bgra8
+            | PixelFormat::Rgba8
+            | PixelFormat::P010
+            | PixelFormat::Rgba1010102 => FrameStream::Color,
+            PixelFormat::Gray16 => FrameStream::Infrared,
+            PixelFormat::Z16 => FrameStream::Depth,
+        }
+    }
+}
+
+/// Which sensor of a multi-stream (RGB-D) camera a frame comes from, so
+/// sinks shared by all streams can tell them apart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FrameStream {
+    #[default]
+    Color,
+    Depth,
+    Infrared,
+}
+
+impl FrameStream {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            FrameStream::Color => "color",
+            FrameStream::Depth => "depth",
+            FrameStream::Infrared => "infrared",
+        }
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rgb8" | "rgb24" | "rgb" => Ok(PixelFormat::Rgb8),
+            "bgra8" | "bgra" => Ok(PixelFormat::Bgra8),
+            "rgba8" | "rgba" => Ok(PixelFormat::Rgba8),
+            "gray16" | "gray16le" | "y16" => Ok(PixelFormat::Gray16),
+            "z16" => Ok(PixelFormat::Z16),
+            "p010" | "p010le" => Ok(PixelFormat::P010),
+            "rgba1010102" | "ab30" | "x2bgr10le" => Ok(PixelFormat::Rgba1010102),
+            other => Err(CameraError::invalid_config(format!(
+                "unknown pixel format '{other}' (expected rgb8, bgra8, rgba8, gray16, z16, p010 or rgba1010102)"
+            ))),
         }
     }
 }
 
+/// Measurements attached to a frame by the dispatcher.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameMetadata {
+    /// Present when `CameraConfig::exposure_check` is set.
+    pub luminance: Option<LuminanceStats>,
+    /// Present when `CameraConfig::focus_check` is set.
+    pub focus: Option<FocusStats>,
+    /// Present when `CameraConfig::frame_checksums` is set: the
+    /// `frame_checksum` of the data as the backend handed it over.
+    pub checksum: Option<u64>,
+    /// When the frame passed through the dispatcher (see
+    /// `Frame::stage_latency`).
+    pub stages: StageTimes,
+    /// The `CameraConfig::sidecar` reading at the frame's time.
+    pub telemetry: Option<Arc<TelemetryEntry>>,
+    /// Present when the frame was exposed in `CameraConfig::trigger` mode.
+    pub trigger: Option<TriggeredExposure>,
+}
+
 #[derive(Clone, Debug)]
 pub struct Frame {
     pub data: Bytes,
@@ -25,7 +167,16 @@ pub struct Frame {
     pub height: u32,
     pub stride: u32,
     pub pixel_format: PixelFormat,
+    pub stream: FrameStream,
+    pub colorimetry: Colorimetry,
+    /// UTC capture time in nanoseconds since the Unix epoch.
     pub timestamp_ns: u64,
+    /// Capture time on the host's monotonic clock (see `monotonic_ns`),
+    /// comparable between cameras in one process.
+    pub monotonic_ns: u64,
+    pub metadata: FrameMetadata,
+    /// GPU-side view of the same pixels, when the backend exported one.
+    pub handle: Option<FrameHandle>,
 }
 
 impl Frame {
@@ -43,7 +194,12 @@ impl Frame {
             height,
             stride,
             pixel_format,
+            stream: pixel_format.default_stream(),
+            colorimetry: Colorimetry::UNKNOWN,
             timestamp_ns: 0,
+            monotonic_ns: 0,
+            metadata: FrameMetadata::default(),
+            handle: None,
         }
     }
 
@@ -57,22 +213,81 @@ impl Frame {
         Self::new(data, width, height, stride, PixelFormat::Bgra8)
     }
 
+    #[inline]
+    pub fn new_rgba8(data: Bytes, width: u32, height: u32, stride: u32) -> Self {
+        Self::new(data, width, height, stride, PixelFormat::Rgba8)
+    }
+
+    #[inline]
+    pub fn with_stream(mut self, stream: FrameStream) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    #[inline]
+    pub fn with_colorimetry(mut self, colorimetry: Colorimetry) -> Self {
+        self.colorimetry = colorimetry;
+        self
+    }
+
     #[inline]
     pub fn with_timestamp_ns(mut self, timestamp_ns: u64) -> Self {
         self.timestamp_ns = timestamp_ns;
         self
     }
 
+    #[inline]
+    pub fn with_handle(mut self, handle: FrameHandle) -> Self {
+        self.handle = Some(handle);
+        self
+    }
+
     #[inline]
     pub fn validate(&self) -> bool {
-        let bpp = self.pixel_format.bytes_per_pixel();
+        self.check().is_ok()
+    }
+
+    /// Significant bits per color component, for encoders and analyzers
+    /// that need to scale samples; see `PixelFormat::bit_depth`.
+    #[inline]
+    pub fn bit_depth(&self) -> u32 {
+        self.pixel_format.bit_depth()
+    }
+
+    /// Checks that the buffer holds `height` rows (plus any chroma plane)
+    /// of `stride` bytes, each long enough for `width` pixels, saying
+    /// what's wrong if not.
+    pub fn check(&self) -> Result<(), FrameDefect> {
         if self.width == 0 || self.height == 0 || self.stride == 0 {
-            return false;
+            return Err(FrameDefect::Empty {
+                width: self.width,
+                height: self.height,
+                stride: self.stride,
+            });
+        }
+        let row_bytes = self.width as u64 * self.pixel_format.bytes_per_pixel() as u64;
+        if (self.stride as u64) < row_bytes {
+            return Err(FrameDefect::StrideTooSmall {
+                stride: self.stride,
+                row_bytes,
+            });
+        }
+        if self.pixel_format.is_planar()
+            && (!self.width.is_multiple_of(2) || !self.height.is_multiple_of(2))
+        {
+            return Err(FrameDefect::OddSubsampled {
+                width: self.width,
+                height: self.height,
+            });
         }
-        if self.stride < self.width.saturating_mul(bpp) {
-            return false;
+        let rows = self.pixel_format.buffer_rows(self.height);
+        let expected = (self.stride as usize).saturating_mul(rows as usize);
+        if self.data.len() < expected {
+            return Err(FrameDefect::Truncated {
+                len: self.data.len(),
+                expected,
+            });
         }
-        let min_len = (self.stride as usize).saturating_mul(self.height as usize);
-        self.data.len() >= min_len
+        Ok(())
     }
 }
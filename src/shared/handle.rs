@@ -0,0 +1,220 @@
+// This is free and unencumbered software released into the public domain.
+
+//! GPU-side handles to frame memory, for embedders that render previews or
+//! run inference without copying pixels back through the CPU.
+
+use crate::shared::PixelFormat;
+
+/// A platform handle to the memory a frame was captured into, attached
+/// alongside the CPU `Frame::data` when `CameraConfig::gpu_handles` is set
+/// and the backend can export one.
+///
+/// Handles describe the frame as captured: `Frame::scale`, `crop` and the
+/// other transforms produce CPU-only frames.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum FrameHandle {
+    /// A Linux DMA-BUF, importable with `EGL_EXT_image_dma_buf_import` or
+    /// `VK_EXT_external_memory_dma_buf`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    DmaBuf(DmaBufHandle),
+    /// A retained `CVPixelBufferRef`, backed by an IOSurface that Metal
+    /// textures can wrap without copying.
+    #[cfg(target_vendor = "apple")]
+    IoSurface(IoSurfaceHandle),
+    /// A retained `ID3D11Texture2D` and its array slice.
+    #[cfg(target_os = "windows")]
+    D3d11(D3d11TextureHandle),
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use dmabuf::*;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod dmabuf {
+    use crate::shared::PixelFormat;
+    use std::{
+        os::fd::{AsFd, BorrowedFd, OwnedFd},
+        sync::Arc,
+    };
+
+    /// One DMA-BUF plane. The descriptor is shared between clones and closed
+    /// when the last one drops.
+    #[derive(Clone, Debug)]
+    pub struct DmaBufHandle {
+        pub fd: Arc<OwnedFd>,
+        /// Byte offset of the first row within the buffer.
+        pub offset: usize,
+        pub stride: u32,
+        /// DRM fourcc, as in `drm_fourcc.h`.
+        pub fourcc: u32,
+        /// DRM format modifier; `0` is linear.
+        pub modifier: u64,
+    }
+
+    impl DmaBufHandle {
+        pub const MODIFIER_LINEAR: u64 = 0;
+
+        /// Wraps a linear buffer holding `pixel_format` pixels.
+        pub fn linear(fd: OwnedFd, offset: usize, stride: u32, pixel_format: PixelFormat) -> Self {
+            Self {
+                fd: Arc::new(fd),
+                offset,
+                stride,
+                fourcc: pixel_format.drm_fourcc(),
+                modifier: Self::MODIFIER_LINEAR,
+            }
+        }
+    }
+
+    impl AsFd for DmaBufHandle {
+        fn as_fd(&self) -> BorrowedFd<'_> {
+            self.fd.as_fd()
+        }
+    }
+}
+
+#[cfg(target_vendor = "apple")]
+pub use iosurface::*;
+
+#[cfg(target_vendor = "apple")]
+mod iosurface {
+    use core::ffi::c_void;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        fn CFRetain(cf: *const c_void) -> *const c_void;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    #[link(name = "CoreVideo", kind = "framework")]
+    unsafe extern "C" {
+        fn CVPixelBufferGetIOSurface(buffer: *const c_void) -> *const c_void;
+    }
+
+    /// A `CVPixelBufferRef`, retained for as long as any clone is alive.
+    #[derive(Debug)]
+    pub struct IoSurfaceHandle(*const c_void);
+
+    // SAFETY: CoreVideo pixel buffers are reference counted atomically and
+    // may be retained, released and read from any thread.
+    unsafe impl Send for IoSurfaceHandle {}
+    unsafe impl Sync for IoSurfaceHandle {}
+
+    impl IoSurfaceHandle {
+        /// Retains `pixel_buffer`, a valid `CVPixelBufferRef`.
+        ///
+        /// # Safety
+        /// `pixel_buffer` must point to a live CVPixelBuffer.
+        pub unsafe fn retain(pixel_buffer: *const c_void) -> Self {
+            // SAFETY: guaranteed by the caller.
+            Self(unsafe { CFRetain(pixel_buffer) })
+        }
+
+        /// The `CVPixelBufferRef`, valid while `self` is.
+        pub fn as_ptr(&self) -> *const c_void {
+            self.0
+        }
+
+        /// The backing `IOSurfaceRef`, or null if the buffer isn't
+        /// IOSurface-backed. Not retained; valid while `self` is.
+        pub fn io_surface(&self) -> *const c_void {
+            // SAFETY: `self.0` is a retained pixel buffer.
+            unsafe { CVPixelBufferGetIOSurface(self.0) }
+        }
+    }
+
+    impl Clone for IoSurfaceHandle {
+        fn clone(&self) -> Self {
+            // SAFETY: `self.0` is a retained pixel buffer.
+            unsafe { Self::retain(self.0) }
+        }
+    }
+
+    impl Drop for IoSurfaceHandle {
+        fn drop(&mut self) {
+            // SAFETY: balances the retain taken on construction.
+            unsafe { CFRelease(self.0) }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub use d3d11::*;
+
+#[cfg(target_os = "windows")]
+mod d3d11 {
+    use core::ffi::c_void;
+
+    #[repr(C)]
+    struct IUnknownVtbl {
+        query_interface: usize,
+        add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+        release: unsafe extern "system" fn(*mut c_void) -> u32,
+    }
+
+    /// An `ID3D11Texture2D`, with a COM reference held for as long as any
+    /// clone is alive. Open it on another device through `IDXGIResource1`
+    /// if the texture was created shareable.
+    #[derive(Debug)]
+    pub struct D3d11TextureHandle {
+        texture: *mut c_void,
+        /// Array slice of the texture holding this frame.
+        pub subresource: u32,
+    }
+
+    // SAFETY: COM reference counting is thread-safe, and D3D11 resources
+    // may be used from any thread through a multithread-protected device.
+    unsafe impl Send for D3d11TextureHandle {}
+    unsafe impl Sync for D3d11TextureHandle {}
+
+    impl D3d11TextureHandle {
+        /// Takes a new reference to `texture`.
+        ///
+        /// # Safety
+        /// `texture` must point to a live `ID3D11Texture2D`.
+        pub unsafe fn add_ref(texture: *mut c_void, subresource: u32) -> Self {
+            // SAFETY: guaranteed by the caller; every COM object starts with
+            // an IUnknown vtable pointer.
+            unsafe { ((**(texture as *mut *const IUnknownVtbl)).add_ref)(texture) };
+            Self {
+                texture,
+                subresource,
+            }
+        }
+
+        /// The `ID3D11Texture2D`, valid while `self` is.
+        pub fn as_ptr(&self) -> *mut c_void {
+            self.texture
+        }
+    }
+
+    impl Clone for D3d11TextureHandle {
+        fn clone(&self) -> Self {
+            // SAFETY: `self.texture` holds a reference.
+            unsafe { Self::add_ref(self.texture, self.subresource) }
+        }
+    }
+
+    impl Drop for D3d11TextureHandle {
+        fn drop(&mut self) {
+            // SAFETY: balances the reference taken on construction.
+            unsafe { ((**(self.texture as *mut *const IUnknownVtbl)).release)(self.texture) };
+        }
+    }
+}
+
+impl PixelFormat {
+    /// The DRM fourcc describing this format's memory layout. DRM names
+    /// are little-endian words, so `Rgba8` bytes are `ABGR8888`.
+    pub const fn drm_fourcc(self) -> u32 {
+        u32::from_le_bytes(*match self {
+            PixelFormat::Rgb8 => b"BG24",
+            PixelFormat::Bgra8 => b"AR24",
+            PixelFormat::Rgba8 => b"AB24",
+            PixelFormat::Gray16 | PixelFormat::Z16 => b"R16 ",
+            PixelFormat::P010 => b"P010",
+            PixelFormat::Rgba1010102 => b"AB30",
+        })
+    }
+}
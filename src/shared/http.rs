@@ -0,0 +1,314 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The little HTTP/1.1 that uploads, webhooks and the reader's local
+//! servers need: one request per connection, over TLS (rustls) for
+//! `https://` URLs, with the response's status, headers and a bounded body
+//! read back, and on the server side a request's head read with a bound.
+
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+use crate::shared::CameraError;
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+use core::time::Duration;
+use std::io::{self, Read};
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+use std::{
+    io::Write,
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::Arc,
+};
+
+/// The most of a request's head, its request line and headers, that a
+/// server reads.
+const MAX_REQUEST_HEAD: usize = 16 * 1024;
+
+/// How long to wait for a server to accept a connection.
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a read or write on a connection may stall.
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The most of a response's headers, or of its body, that is read.
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+const MAX_RESPONSE_BYTES: usize = 64 * 1024;
+
+/// The longest wait between two attempts at a request.
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+#[cfg_attr(not(any(feature = "s3", feature = "webhook")), allow(dead_code))]
+pub(crate) const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// What a client asked a server for.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    /// The path, without the query.
+    pub path: String,
+    /// The announced length of the body, 0 without one.
+    pub content_length: usize,
+}
+
+impl HttpRequest {
+    /// Parses the head of a request once `data` holds all of it, and returns
+    /// the request with the length of the head; `None` while it needs more.
+    /// Fails once the head is longer than 16 KiB.
+    pub fn parse(data: &[u8]) -> io::Result<Option<(Self, usize)>> {
+        let crlf = data
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|i| i + 4);
+        let lf = data.windows(2).position(|w| w == b"\n\n").map(|i| i + 2);
+        let Some(end) = crlf.into_iter().chain(lf).min() else {
+            if data.len() > MAX_REQUEST_HEAD {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "request headers are too long",
+                ));
+            }
+            return Ok(None);
+        };
+        if end > MAX_REQUEST_HEAD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request headers are too long",
+            ));
+        }
+        let head = String::from_utf8_lossy(&data[..end]);
+        let mut lines = head.lines();
+        let mut parts = lines.next().unwrap_or("").split_whitespace();
+        let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let content_length = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse().ok())
+            .unwrap_or(0);
+        let request = Self {
+            method: method.to_string(),
+            path: target.split('?').next().unwrap_or(target).to_string(),
+            content_length,
+        };
+        Ok(Some((request, end)))
+    }
+
+    /// Reads a request from `stream`, with its body when that is at most
+    /// `max_body` bytes; a longer one is left unread, for the caller to
+    /// refuse by its `content_length`.
+    pub fn read(stream: &mut impl Read, max_body: usize) -> io::Result<(Self, Vec<u8>)> {
+        let mut data = Vec::new();
+        let mut buf = [0; 4096];
+        let (request, head_len) = loop {
+            if let Some(parsed) = Self::parse(&data)? {
+                break parsed;
+            }
+            match stream.read(&mut buf)? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => data.extend_from_slice(&buf[..n]),
+            }
+        };
+        let mut body = data.split_off(head_len);
+        if request.content_length > max_body {
+            return Ok((request, Vec::new()));
+        }
+        if body.len() < request.content_length {
+            let more = request.content_length - body.len();
+            stream.take(more as u64).read_to_end(&mut body)?;
+        }
+        body.truncate(request.content_length);
+        Ok((request, body))
+    }
+}
+
+/// An `http://` or `https://` URL, split as requests need it.
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+#[derive(Clone, Debug)]
+pub(crate) struct Url {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    /// The path and query, without a trailing `/` unless that's all.
+    pub path: String,
+}
+
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+impl Url {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let (tls, rest) = match url.trim().split_once("://") {
+            Some(("https", rest)) => (true, rest),
+            Some(("http", rest)) => (false, rest),
+            _ => return Err("expected an http:// or https:// URL".into()),
+        };
+        let (authority, path) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+        // Credentials in the URL aren't sent; headers carry them.
+        let authority = authority.rsplit_once('@').map_or(authority, |(_, a)| a);
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (
+                host,
+                port.parse().map_err(|_| format!("invalid port '{port}'"))?,
+            ),
+            _ => (authority, if tls { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return Err("missing host".into());
+        }
+        let path = match path {
+            "" => "/".to_string(),
+            path if path.starts_with('?') => format!("/{path}"),
+            path => path.to_string(),
+        };
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+
+    /// The `Host` header, with the port only when it isn't the default.
+    pub fn host_header(&self) -> String {
+        match (self.tls, self.port) {
+            (true, 443) | (false, 80) => self.host.clone(),
+            (_, port) => format!("{}:{port}", self.host),
+        }
+    }
+}
+
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+pub(crate) trait Stream: Read + Write {}
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+impl<T: Read + Write> Stream for T {}
+
+/// A TLS configuration trusting the certificates in the PEM file `ca`, or
+/// without one the system's.
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+pub(crate) fn tls_config(ca: Option<&Path>) -> Result<Arc<rustls::ClientConfig>, CameraError> {
+    use rustls::pki_types::{CertificateDer, pem::PemObject};
+    let mut roots = rustls::RootCertStore::empty();
+    match ca {
+        Some(path) => {
+            let invalid = |e: &dyn core::fmt::Display| {
+                CameraError::invalid_config(format!("reading {}: {e}", path.display()))
+            };
+            for cert in CertificateDer::pem_file_iter(path).map_err(|e| invalid(&e))? {
+                roots
+                    .add(cert.map_err(|e| invalid(&e))?)
+                    .map_err(|e| invalid(&e))?;
+            }
+        },
+        None => {
+            for cert in rustls_native_certs::load_native_certs().certs {
+                let _ = roots.add(cert);
+            }
+        },
+    }
+    rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| CameraError::driver("setting up TLS", e))
+        .map(|builder| Arc::new(builder.with_root_certificates(roots).with_no_client_auth()))
+}
+
+/// Connects to the server of `url`, through TLS with `tls` for `https://`.
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+pub(crate) fn connect(
+    url: &Url,
+    tls: Option<&Arc<rustls::ClientConfig>>,
+) -> io::Result<Box<dyn Stream>> {
+    let host = url.host.trim_matches(['[', ']']);
+    let address = (host, url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::other(format!("{} has no address", url.host)))?;
+    let tcp = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+    tcp.set_read_timeout(Some(IO_TIMEOUT))?;
+    tcp.set_write_timeout(Some(IO_TIMEOUT))?;
+    tcp.set_nodelay(true)?;
+    let (true, Some(tls)) = (url.tls, tls) else {
+        return Ok(Box::new(tcp));
+    };
+    let name =
+        rustls::pki_types::ServerName::try_from(host.to_string()).map_err(io::Error::other)?;
+    let connection =
+        rustls::ClientConnection::new(Arc::clone(tls), name).map_err(io::Error::other)?;
+    Ok(Box::new(rustls::StreamOwned::new(connection, tcp)))
+}
+
+/// What a server answered.
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+#[derive(Clone, Debug)]
+pub(crate) struct Response {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+    /// At most `MAX_RESPONSE_BYTES` of the body.
+    pub body: Vec<u8>,
+}
+
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+impl Response {
+    /// The value of the header `name`, in any case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Whether another attempt at a request that got `status` might succeed:
+/// a timeout, throttling or a server error.
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+#[cfg_attr(not(any(feature = "s3", feature = "webhook")), allow(dead_code))]
+pub(crate) fn is_transient(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500..=599)
+}
+
+/// Reads the response to a request that asked for `connection: close`.
+#[cfg(any(feature = "onvif", feature = "s3", feature = "webhook"))]
+pub(crate) fn read_response(stream: &mut dyn Stream) -> io::Result<Response> {
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+    let header_end = loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        if data.len() > MAX_RESPONSE_BYTES {
+            return Err(io::Error::other("response headers are too long"));
+        }
+        match stream.read(&mut buf)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => data.extend_from_slice(&buf[..n]),
+        }
+    };
+    let head = String::from_utf8_lossy(&data[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| io::Error::other("malformed response"))?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok());
+    // The body is only for messages, so however the server ends it is fine.
+    let mut body = data.split_off(header_end + 4);
+    while length.is_none_or(|len| body.len() < len) && body.len() < MAX_RESPONSE_BYTES {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => body.extend_from_slice(&buf[..n]),
+        }
+    }
+    body.truncate(length.unwrap_or(body.len()).min(MAX_RESPONSE_BYTES));
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
@@ -0,0 +1,68 @@
+// This is free and unencumbered software released into the public domain.
+
+//! JPEG encoding for snapshots, saved frames and MJPEG streams. With the
+//! `mozjpeg` feature frames go through libjpeg-turbo's SIMD encoder, which
+//! keeps 1080p at 30 fps well within one core; otherwise through the
+//! pure-Rust encoder of the `image` crate.
+
+use crate::shared::{CameraError, Frame};
+
+/// The encoder `Frame::to_jpeg` uses in this build.
+pub const JPEG_ENCODER: &str = if cfg!(all(feature = "mozjpeg", not(target_arch = "wasm32"))) {
+    "libjpeg-turbo"
+} else {
+    "image"
+};
+
+impl Frame {
+    /// Encodes the frame as a baseline JPEG at `quality` (1-100).
+    pub fn to_jpeg(&self, quality: u8) -> Result<Vec<u8>, CameraError> {
+        let quality = quality.clamp(1, 100);
+        #[cfg(all(feature = "mozjpeg", not(target_arch = "wasm32")))]
+        return turbo::encode(self, quality);
+        #[cfg(not(all(feature = "mozjpeg", not(target_arch = "wasm32"))))]
+        {
+            let rgb = self.to_rgb8()?;
+            let mut jpeg = Vec::new();
+            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality)
+                .encode(
+                    &rgb.data,
+                    rgb.width,
+                    rgb.height,
+                    image::ExtendedColorType::Rgb8,
+                )
+                .map_err(|e| CameraError::other(format!("encoding JPEG: {e}")))?;
+            Ok(jpeg)
+        }
+    }
+}
+
+#[cfg(all(feature = "mozjpeg", not(target_arch = "wasm32")))]
+mod turbo {
+    use crate::shared::{CameraError, Frame, PixelFormat};
+    use mozjpeg::{ColorSpace, Compress};
+
+    pub fn encode(frame: &Frame, quality: u8) -> Result<Vec<u8>, CameraError> {
+        // libjpeg reads RGB, BGRA and RGBA rows as they are, so only
+        // padding and other formats need a copy first.
+        let (frame, color_space) = match frame.pixel_format {
+            PixelFormat::Rgb8 => (frame.to_packed()?, ColorSpace::JCS_EXT_RGB),
+            PixelFormat::Bgra8 => (frame.to_packed()?, ColorSpace::JCS_EXT_BGRA),
+            PixelFormat::Rgba8 => (frame.to_packed()?, ColorSpace::JCS_EXT_RGBA),
+            _ => (frame.to_rgb8()?, ColorSpace::JCS_EXT_RGB),
+        };
+        // libjpeg reports errors by unwinding out of the encoder.
+        std::panic::catch_unwind(|| {
+            let mut compress = Compress::new(color_space);
+            compress.set_size(frame.width as usize, frame.height as usize);
+            // Resets the quality, so it comes first.
+            compress.set_fastest_defaults();
+            compress.set_quality(quality as f32);
+            let mut started = compress.start_compress(Vec::new())?;
+            started.write_scanlines(&frame.data)?;
+            started.finish()
+        })
+        .map_err(|_| CameraError::other("encoding JPEG: libjpeg failed"))?
+        .map_err(|e| CameraError::other(format!("encoding JPEG: {e}")))
+    }
+}
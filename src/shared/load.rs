@@ -0,0 +1,199 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Guardrails for overloaded hosts: when frames are dropped, or the CPU is
+//! saturated, for a whole window, capture steps down to a cheaper format
+//! instead of silently discarding most of what the camera sends.
+
+use crate::shared::{CameraMode, CameraStats, monotonic_ns};
+use core::{fmt, time::Duration};
+
+/// Frames a window must see before its drop ratio means anything.
+const MIN_WINDOW_FRAMES: u64 = 10;
+
+/// When `Camera::check_load` downgrades capture, and how far it may go.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadGuard {
+    /// Downgrade when more than this fraction of the frames captured in a
+    /// window were dropped, by the driver or by sinks reporting to a
+    /// `DropReporter` (`0.0..=1.0`).
+    pub max_drop_ratio: f64,
+    /// Downgrade when the system's CPUs were busier than this fraction of
+    /// a window (`0.0..=1.0`); `None` ignores CPU load. Only measured on
+    /// Linux.
+    pub max_cpu: Option<f64>,
+    /// How long load must stay high before each downgrade.
+    pub window: Duration,
+    /// Lowest frame rate a downgrade halves the rate to.
+    pub min_fps: f64,
+    /// Also move to a smaller mode the device lists once the frame rate
+    /// is at `min_fps`.
+    pub lower_resolution: bool,
+    /// Smallest size a downgrade moves to.
+    pub min_size: (u32, u32),
+}
+
+impl LoadGuard {
+    /// The next format down from `width`x`height` at `fps`: half the frame
+    /// rate until `min_fps`, then the largest smaller mode in `modes` that
+    /// runs at that rate, if lowering the resolution is allowed.
+    pub(crate) fn step_down(
+        &self,
+        width: u32,
+        height: u32,
+        fps: f64,
+        modes: &[CameraMode],
+    ) -> Option<(u32, u32, f64)> {
+        let min_fps = self.min_fps;
+        if fps > min_fps {
+            return Some((width, height, (fps / 2.0).max(min_fps)));
+        }
+        if !self.lower_resolution {
+            return None;
+        }
+        let (min_width, min_height) = self.min_size;
+        modes
+            .iter()
+            .filter(|m| m.width * m.height < width * height)
+            .filter(|m| m.width >= min_width && m.height >= min_height)
+            .filter(|m| m.max_fps.is_none_or(|max| max >= fps))
+            .max_by_key(|m| (m.width * m.height, m.width))
+            .map(|m| (m.width, m.height, fps))
+    }
+}
+
+impl Default for LoadGuard {
+    fn default() -> Self {
+        Self {
+            max_drop_ratio: 0.25,
+            max_cpu: Some(0.95),
+            window: Duration::from_secs(5),
+            min_fps: 5.0,
+            lower_resolution: true,
+            min_size: (320, 240),
+        }
+    }
+}
+
+/// What made `Camera::check_load` downgrade capture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overload {
+    /// This fraction of the window's frames was dropped.
+    Drops { ratio: f64 },
+    /// The CPUs were busy for this fraction of the window.
+    Cpu { busy: f64 },
+}
+
+impl fmt::Display for Overload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overload::Drops { ratio } => write!(f, "{:.0}% of frames dropped", ratio * 100.0),
+            Overload::Cpu { busy } => write!(f, "CPU {:.0}% busy", busy * 100.0),
+        }
+    }
+}
+
+/// The load seen since the current window started.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LoadMonitor {
+    guard: LoadGuard,
+    captured: u64,
+    dropped: u64,
+    shed: u64,
+    /// On the monotonic clock, like the watchdog's.
+    since_ns: u64,
+    cpu: Option<CpuTimes>,
+}
+
+impl LoadMonitor {
+    pub(crate) fn new(guard: LoadGuard, stats: CameraStats) -> Self {
+        let mut monitor = Self {
+            guard,
+            captured: 0,
+            dropped: 0,
+            shed: 0,
+            since_ns: 0,
+            cpu: None,
+        };
+        monitor.restart(stats);
+        monitor
+    }
+
+    pub(crate) fn guard(&self) -> &LoadGuard {
+        &self.guard
+    }
+
+    /// Starts a new window from `stats`.
+    pub(crate) fn restart(&mut self, stats: CameraStats) {
+        self.captured = stats.frames_captured;
+        self.dropped = stats.frames_dropped;
+        self.shed = stats.frames_shed;
+        self.since_ns = monotonic_ns();
+        self.cpu = self.guard.max_cpu.and_then(|_| CpuTimes::read());
+    }
+
+    /// Ends the window if it's over, returning what overloaded it, if
+    /// anything did.
+    pub(crate) fn observe(&mut self, stats: CameraStats) -> Option<Overload> {
+        let elapsed = Duration::from_nanos(monotonic_ns().saturating_sub(self.since_ns));
+        let captured = stats.frames_captured.saturating_sub(self.captured);
+        let dropped = stats.frames_dropped.saturating_sub(self.dropped);
+        let shed = stats.frames_shed.saturating_sub(self.shed);
+        // Shed frames were captured first, so they only add to the losses.
+        let offered = captured + dropped;
+        if elapsed < self.guard.window || offered < MIN_WINDOW_FRAMES {
+            return None;
+        }
+        let busy = match (self.cpu, CpuTimes::read()) {
+            (Some(then), Some(now)) => now.busy_since(&then),
+            _ => None,
+        };
+        self.restart(stats);
+
+        let ratio = ((dropped + shed) as f64 / offered as f64).min(1.0);
+        if ratio > self.guard.max_drop_ratio {
+            return Some(Overload::Drops { ratio });
+        }
+        match (busy, self.guard.max_cpu) {
+            (Some(busy), Some(max)) if busy > max => Some(Overload::Cpu { busy }),
+            _ => None,
+        }
+    }
+}
+
+/// Aggregate CPU time from `/proc/stat`, in clock ticks.
+#[derive(Clone, Copy, Debug)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+impl CpuTimes {
+    #[cfg(target_os = "linux")]
+    fn read() -> Option<Self> {
+        let stat = std::fs::read_to_string("/proc/stat").ok()?;
+        let line = stat.lines().next()?.strip_prefix("cpu ")?;
+        let ticks = line
+            .split_whitespace()
+            .map(|v| v.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        // user nice system idle iowait irq softirq steal [guest guest_nice],
+        // where the guest times are already counted in user and nice.
+        let total: u64 = ticks.iter().take(8).sum();
+        let idle = ticks.get(3)? + ticks.get(4).unwrap_or(&0);
+        Some(Self {
+            busy: total - idle,
+            total,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read() -> Option<Self> {
+        None
+    }
+
+    /// The fraction of the time since `then` the CPUs were busy.
+    fn busy_since(&self, then: &CpuTimes) -> Option<f64> {
+        let total = self.total.checked_sub(then.total).filter(|&t| t > 0)?;
+        Some(self.busy.saturating_sub(then.busy) as f64 / total as f64)
+    }
+}
@@ -0,0 +1,241 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{CameraError, Frame, PixelFormat, Rect};
+use core::{fmt, str::FromStr};
+
+/// Default pixelation block size in pixels.
+const DEFAULT_BLOCK: u32 = 16;
+
+/// A privacy zone in frame coordinates: a rectangle (`X,Y,WxH`) or a
+/// polygon of three or more `X,Y` points separated by spaces or `;`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MaskShape {
+    Rect(Rect),
+    Polygon(Vec<(u32, u32)>),
+}
+
+impl MaskShape {
+    /// Returns whether the shape lies inside a `width`×`height` image.
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        match self {
+            MaskShape::Rect(rect) => rect.fits(width, height),
+            MaskShape::Polygon(points) => points.iter().all(|&(x, y)| x <= width && y <= height),
+        }
+    }
+
+    /// Appends the `[start, end)` column spans the shape covers in row `y`
+    /// of an image `width` pixels wide. Pixels count as covered when their
+    /// centre is inside.
+    fn row_spans(&self, y: u32, width: u32, spans: &mut Vec<(u32, u32)>) {
+        match self {
+            MaskShape::Rect(rect) => {
+                if y >= rect.y && y - rect.y < rect.height && rect.x < width {
+                    spans.push((rect.x, rect.x.saturating_add(rect.width).min(width)));
+                }
+            },
+            MaskShape::Polygon(points) => {
+                // Even-odd scanline fill through the row's pixel centres.
+                let cy = y as f64 + 0.5;
+                let mut crossings: Vec<f64> = points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .filter_map(|(&(x0, y0), &(x1, y1))| {
+                        let (x0, y0, x1, y1) = (x0 as f64, y0 as f64, x1 as f64, y1 as f64);
+                        ((y0 <= cy) != (y1 <= cy)).then(|| x0 + (cy - y0) * (x1 - x0) / (y1 - y0))
+                    })
+                    .collect();
+                crossings.sort_by(f64::total_cmp);
+                for pair in crossings.chunks_exact(2) {
+                    let start = (pair[0] - 0.5).ceil().max(0.0) as u32;
+                    let end = ((pair[1] - 0.5).ceil().max(0.0) as u32).min(width);
+                    if start < end {
+                        spans.push((start, end));
+                    }
+                }
+            },
+        }
+    }
+}
+
+impl FromStr for MaskShape {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.contains(['x', '×']) {
+            return s.parse().map(MaskShape::Rect);
+        }
+        let invalid = || {
+            CameraError::invalid_config(format!(
+                "invalid mask '{s}', use X,Y,WxH or a polygon like '0,0 200,0 100,150'"
+            ))
+        };
+        let points = s
+            .split(|c: char| c.is_whitespace() || c == ';')
+            .filter(|p| !p.is_empty())
+            .map(|point| {
+                let (x, y) = point.split_once(',').ok_or_else(invalid)?;
+                let num = |v: &str| v.trim().parse::<u32>().map_err(|_| invalid());
+                Ok((num(x)?, num(y)?))
+            })
+            .collect::<Result<Vec<_>, CameraError>>()?;
+        if points.len() < 3 {
+            return Err(invalid());
+        }
+        Ok(MaskShape::Polygon(points))
+    }
+}
+
+impl fmt::Display for MaskShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaskShape::Rect(rect) => rect.fmt(f),
+            MaskShape::Polygon(points) => {
+                for (i, (x, y)) in points.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{x},{y}")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// How masked pixels are hidden.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MaskStyle {
+    /// Black, or zero (no data) for depth.
+    #[default]
+    Black,
+    /// Averaged over square blocks this many pixels wide.
+    Pixelate(u32),
+}
+
+impl FromStr for MaskStyle {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let (name, block) = match s.split_once(':') {
+            Some((name, block)) => (name, Some(block)),
+            None => (s.as_str(), None),
+        };
+        let invalid = || {
+            CameraError::invalid_config(format!(
+                "invalid mask style '{s}' (expected black or pixelate[:PIXELS])"
+            ))
+        };
+        match (name, block) {
+            ("black", None) => Ok(MaskStyle::Black),
+            ("pixelate", None) => Ok(MaskStyle::Pixelate(DEFAULT_BLOCK)),
+            ("pixelate", Some(block)) => match block.parse::<u32>() {
+                Ok(block) if block >= 2 => Ok(MaskStyle::Pixelate(block)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Frame {
+    /// Hides the pixels inside `shapes` into a new, tightly packed frame;
+    /// parts of shapes outside the frame are ignored.
+    pub fn mask(&self, shapes: &[MaskShape], style: MaskStyle) -> Result<Frame, CameraError> {
+        self.check_single_plane()?;
+        let (w, h) = (self.width as usize, self.height as usize);
+        let bpp = self.pixel_format.bytes_per_pixel() as usize;
+        let row_len = w * bpp;
+
+        // Which pixels to hide, with the packed copy of the frame.
+        let mut covered = vec![false; w * h];
+        let mut spans = Vec::new();
+        let mut out = Vec::with_capacity(row_len * h);
+        for (y, row) in self.data.chunks(self.stride as usize).take(h).enumerate() {
+            out.extend_from_slice(&row[..row_len]);
+            spans.clear();
+            for shape in shapes {
+                shape.row_spans(y as u32, self.width, &mut spans);
+            }
+            for &(start, end) in &spans {
+                covered[y * w + start as usize..y * w + end as usize].fill(true);
+            }
+        }
+
+        match style {
+            MaskStyle::Black => {
+                let black = black_pixel(self.pixel_format);
+                for (px, _) in out
+                    .chunks_exact_mut(bpp)
+                    .zip(&covered)
+                    .filter(|(_, covered)| **covered)
+                {
+                    px.copy_from_slice(&black[..bpp]);
+                }
+            },
+            MaskStyle::Pixelate(block) => {
+                let block = block.max(1) as usize;
+                let wide = matches!(self.pixel_format, PixelFormat::Gray16 | PixelFormat::Z16);
+                let packed = self.pixel_format == PixelFormat::Rgba1010102;
+                let mut sum = vec![0u64; bpp];
+                for by in (0..h).step_by(block) {
+                    for bx in (0..w).step_by(block) {
+                        let (ys, xs) = (by..(by + block).min(h), bx..(bx + block).min(w));
+                        if !ys
+                            .clone()
+                            .any(|y| covered[y * w..][xs.clone()].contains(&true))
+                        {
+                            continue;
+                        }
+                        // Average the whole block, so its edge doesn't reveal the mask.
+                        sum.fill(0);
+                        let n = (ys.len() * xs.len()) as u64;
+                        for y in ys.clone() {
+                            for px in out[y * row_len..][bx * bpp..xs.end * bpp].chunks_exact(bpp) {
+                                if wide {
+                                    sum[0] += u16::from_le_bytes([px[0], px[1]]) as u64;
+                                } else if packed {
+                                    // Average each 10-bit (and the 2-bit alpha) field apart.
+                                    let v = u32::from_le_bytes([px[0], px[1], px[2], px[3]]);
+                                    for (i, s) in sum.iter_mut().enumerate() {
+                                        *s += ((v >> (10 * i)) & 0x3ff) as u64;
+                                    }
+                                } else {
+                                    sum.iter_mut().zip(px).for_each(|(s, &v)| *s += v as u64);
+                                }
+                            }
+                        }
+                        let mean: Vec<u8> = if wide {
+                            ((sum[0] / n) as u16).to_le_bytes().to_vec()
+                        } else if packed {
+                            let v = sum
+                                .iter()
+                                .enumerate()
+                                .fold(0u32, |v, (i, &s)| v | ((s / n) as u32) << (10 * i));
+                            v.to_le_bytes().to_vec()
+                        } else {
+                            sum.iter().map(|&s| (s / n) as u8).collect()
+                        };
+                        for y in ys {
+                            for x in xs.clone().filter(|&x| covered[y * w + x]) {
+                                out[y * row_len + x * bpp..][..bpp].copy_from_slice(&mean);
+                            }
+                        }
+                    }
+                }
+            },
+        }
+
+        Ok(self.derive(out, self.width, self.height))
+    }
+}
+
+/// An opaque black pixel in `format`, padded to four bytes.
+pub(crate) fn black_pixel(format: PixelFormat) -> [u8; 4] {
+    match format {
+        PixelFormat::Rgba8 | PixelFormat::Bgra8 => [0, 0, 0, 255],
+        PixelFormat::Rgba1010102 => [0, 0, 0, 0xC0],
+        PixelFormat::Rgb8 | PixelFormat::Gray16 | PixelFormat::Z16 | PixelFormat::P010 => [0; 4],
+    }
+}
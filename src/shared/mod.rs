@@ -1,11 +1,68 @@
 // This is free and unencumbered software released into the public domain.
 
+/// Zero-copy Arrow views of frame memory.
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
+mod analyzer;
+pub use analyzer::*;
+
+#[cfg(feature = "audio")]
+mod audio;
+#[cfg(feature = "audio")]
+pub use audio::*;
+
+mod auto_lock;
+pub use auto_lock::*;
+
+mod bundle;
+pub use bundle::*;
+
+mod calibration;
+pub use calibration::*;
+
+mod capabilities;
+pub use capabilities::*;
+
+mod checkerboard;
+pub use checkerboard::*;
+
+mod clock;
+pub use clock::*;
+
+mod color;
+pub use color::*;
+
 mod config;
 pub use config::*;
 
+mod convert;
+
+mod debounce;
+pub use debounce::*;
+
 mod driver;
 pub use driver::*;
 
+pub mod devices {
+    /// The controls V4L2 devices offer, with their ranges and values.
+    pub mod controls;
+
+    /// Parsers for the device lists and modes ffmpeg and rpicam print.
+    pub mod parse;
+
+    /// Raw requests to the vendor controls of UVC extension units.
+    pub mod xu;
+}
+
+mod handle;
+pub use handle::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod http;
+#[cfg(not(target_arch = "wasm32"))]
+pub use http::HttpRequest;
+
 pub mod drivers {
     /// Camera driver using FFmpeg.
     #[cfg(all(
@@ -14,6 +71,13 @@ pub mod drivers {
     ))]
     pub mod ffmpeg;
 
+    /// Camera driver using a GStreamer pipeline.
+    #[cfg(all(
+        feature = "gstreamer",
+        any(target_os = "macos", target_os = "linux", target_os = "windows")
+    ))]
+    pub mod gstreamer;
+
     /// Camera driver using the NDK on Android.
     #[cfg(all(feature = "android", target_os = "android"))]
     pub mod android;
@@ -29,13 +93,164 @@ pub mod drivers {
     /// Camera driver using V4L2 on Linux.
     #[cfg(all(feature = "v4l2", target_os = "linux"))]
     pub mod v4l2;
+
+    /// Camera driver using PipeWire through the camera portal.
+    #[cfg(all(feature = "pipewire", target_os = "linux"))]
+    pub mod pipewire;
+
+    /// Camera driver for Raspberry Pi CSI camera modules.
+    #[cfg(all(feature = "rpi", target_os = "linux"))]
+    pub mod rpi;
+
+    /// Camera driver receiving an NDI source through the NDI runtime.
+    #[cfg(all(
+        feature = "ndi",
+        any(target_os = "macos", target_os = "linux", target_os = "windows")
+    ))]
+    pub mod ndi;
+
+    /// Camera driver reading another process's shared-memory frame ring.
+    #[cfg(all(feature = "shm", unix))]
+    pub mod shm;
+
+    /// Camera driver using nokhwa's pure-Rust capture.
+    #[cfg(all(
+        feature = "uvc",
+        any(target_os = "macos", target_os = "linux", target_os = "windows")
+    ))]
+    pub mod uvc;
+
+    /// Scripted camera driver for tests.
+    #[cfg(feature = "test-util")]
+    pub mod mock;
+
+    /// Camera driver using getUserMedia in web browsers.
+    #[cfg(all(feature = "web", target_arch = "wasm32"))]
+    pub mod web;
 }
 
+#[cfg(feature = "encryption")]
+mod encrypt;
+#[cfg(feature = "encryption")]
+pub use encrypt::*;
+
 mod error;
 pub use error::*;
 
+mod jpeg;
+pub use jpeg::*;
+
+mod load;
+pub use load::*;
+
+mod mask;
+pub use mask::*;
+
+mod motion;
+pub use motion::*;
+
+mod overlay;
+pub use overlay::*;
+
+#[cfg(all(
+    feature = "ndi",
+    any(target_os = "macos", target_os = "linux", target_os = "windows")
+))]
+mod ndi;
+#[cfg(all(
+    feature = "ndi",
+    any(target_os = "macos", target_os = "linux", target_os = "windows")
+))]
+pub use ndi::*;
+
+mod notify;
+pub use notify::*;
+
+#[cfg(all(feature = "onvif", not(target_arch = "wasm32")))]
+mod onvif;
+#[cfg(all(feature = "onvif", not(target_arch = "wasm32")))]
+pub use onvif::*;
+
 mod open;
 pub use open::*;
 
+mod exposure;
+pub use exposure::*;
+
+mod focus;
+pub use focus::*;
+
 mod frame;
 pub use frame::*;
+
+mod photo;
+pub use photo::*;
+
+mod pipeline;
+pub use pipeline::*;
+
+mod position;
+pub use position::*;
+
+mod process;
+pub use process::*;
+
+#[cfg(all(feature = "provenance", not(target_arch = "wasm32")))]
+mod provenance;
+#[cfg(all(feature = "provenance", not(target_arch = "wasm32")))]
+pub use provenance::*;
+
+mod rate;
+pub use rate::*;
+
+mod rdf;
+pub use rdf::*;
+
+mod retention;
+pub use retention::*;
+
+#[cfg(all(feature = "s3", not(target_arch = "wasm32")))]
+mod s3;
+#[cfg(all(feature = "s3", not(target_arch = "wasm32")))]
+pub use s3::*;
+
+mod schedule;
+pub use schedule::*;
+
+mod scheduling;
+pub use scheduling::*;
+
+mod sensor;
+pub use sensor::*;
+
+mod sidecar;
+pub use sidecar::*;
+
+#[cfg(all(feature = "shm", unix))]
+mod shm;
+#[cfg(all(feature = "shm", unix))]
+pub use shm::*;
+
+#[cfg(feature = "stereo")]
+mod stereo;
+#[cfg(feature = "stereo")]
+pub use stereo::*;
+
+mod trigger;
+pub use trigger::*;
+
+mod undistort;
+pub use undistort::*;
+
+mod validation;
+pub use validation::*;
+
+#[cfg(all(feature = "webhook", not(target_arch = "wasm32")))]
+mod webhook;
+#[cfg(all(feature = "webhook", not(target_arch = "wasm32")))]
+pub use webhook::*;
+
+#[cfg(all(feature = "zmq", not(target_arch = "wasm32")))]
+mod zeromq;
+#[cfg(all(feature = "zmq", not(target_arch = "wasm32")))]
+pub use zeromq::*;
@@ -0,0 +1,119 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{Frame, Rect, process::luma};
+
+/// Side of the square pixel blocks frames are reduced to before comparison.
+const CELL: u32 = 8;
+
+/// How quickly the background model adapts to the scene (per frame).
+const BACKGROUND_RATE: f32 = 0.05;
+
+/// Motion found in one frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Motion {
+    /// Fraction of the frame that changed, in `0.0..=1.0`.
+    pub score: f32,
+    /// Bounding box of the changed area, in frame pixels.
+    pub bbox: Rect,
+}
+
+/// Detects motion by background subtraction over a block-averaged luma
+/// image, so sensor noise and small flicker don't register.
+#[derive(Clone, Debug)]
+pub struct MotionDetector {
+    threshold: u8,
+    min_area: f32,
+    grid: (u32, u32),
+    background: Vec<f32>,
+    active: bool,
+}
+
+impl MotionDetector {
+    /// `threshold` is the luma difference (0-255) at which a block counts as
+    /// changed; `min_area` is the changed fraction that counts as motion.
+    pub fn new(threshold: u8, min_area: f32) -> Self {
+        Self {
+            threshold,
+            min_area: min_area.clamp(0.0, 1.0),
+            grid: (0, 0),
+            background: Vec::new(),
+            active: false,
+        }
+    }
+
+    /// Returns whether the last processed frame had motion.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn reset(&mut self) {
+        self.background.clear();
+        self.active = false;
+    }
+
+    /// Feeds a frame into the model and returns the motion found in it, if any.
+    pub fn process(&mut self, frame: &Frame) -> Option<Motion> {
+        if !frame.validate() {
+            return None;
+        }
+        let grid = (frame.width.div_ceil(CELL), frame.height.div_ceil(CELL));
+        let cells = block_luma(frame, grid);
+
+        if self.grid != grid || self.background.len() != cells.len() {
+            self.grid = grid;
+            self.background = cells;
+            self.active = false;
+            return None;
+        }
+
+        let threshold = self.threshold as f32;
+        let (mut changed, mut min, mut max) = (0usize, (u32::MAX, u32::MAX), (0u32, 0u32));
+        for (i, (bg, &value)) in self.background.iter_mut().zip(&cells).enumerate() {
+            if (value - *bg).abs() > threshold {
+                changed += 1;
+                let (cx, cy) = (i as u32 % grid.0, i as u32 / grid.0);
+                min = (min.0.min(cx), min.1.min(cy));
+                max = (max.0.max(cx), max.1.max(cy));
+            }
+            *bg += BACKGROUND_RATE * (value - *bg);
+        }
+
+        let score = changed as f32 / cells.len() as f32;
+        self.active = changed > 0 && score >= self.min_area;
+        if !self.active {
+            return None;
+        }
+
+        let x = min.0 * CELL;
+        let y = min.1 * CELL;
+        let bbox = Rect::new(
+            x,
+            y,
+            ((max.0 + 1) * CELL).min(frame.width) - x,
+            ((max.1 + 1) * CELL).min(frame.height) - y,
+        );
+        Some(Motion { score, bbox })
+    }
+}
+
+fn block_luma(frame: &Frame, (gw, gh): (u32, u32)) -> Vec<f32> {
+    let bpp = frame.pixel_format.bytes_per_pixel() as usize;
+    let stride = frame.stride as usize;
+    let mut sums = vec![0u32; (gw * gh) as usize];
+    let mut counts = vec![0u32; sums.len()];
+
+    for y in 0..frame.height as usize {
+        let row = &frame.data[y * stride..][..frame.width as usize * bpp];
+        let base = (y as u32 / CELL * gw) as usize;
+        for (x, px) in row.chunks_exact(bpp).enumerate() {
+            let i = base + x / CELL as usize;
+            sums[i] += luma(px, frame.pixel_format) as u32;
+            counts[i] += 1;
+        }
+    }
+
+    sums.iter()
+        .zip(&counts)
+        .map(|(&s, &n)| s as f32 / n.max(1) as f32)
+        .collect()
+}
@@ -0,0 +1,366 @@
+// This is free and unencumbered software released into the public domain.
+
+//! NDI sources through the NDI runtime: finding them on the network, and
+//! receiving their video as frames.
+//!
+//! The runtime is loaded when first needed rather than linked, since its
+//! license doesn't let it be redistributed: from the directory its
+//! installers name in `$NDI_RUNTIME_DIR_V6` (or `_V5`), or else from the
+//! library path. Without it, NDI devices fail with an `Unsupported` error
+//! saying where to get it.
+
+use crate::shared::{CameraError, Frame, FrameTime, PixelFormat};
+use bytes::Bytes;
+use core::{
+    ffi::{CStr, c_char, c_void},
+    ptr::{self, NonNull},
+    time::Duration,
+};
+use std::{ffi::CString, sync::OnceLock};
+
+/// Device ids naming an NDI source: `ndi:MACHINE (SOURCE)`.
+pub const NDI_PREFIX: &str = "ndi:";
+
+/// Where to get the runtime, for the error when it isn't installed.
+const RUNTIME_URL: &str = "https://ndi.video/tools/";
+
+#[cfg(target_os = "linux")]
+const LIBRARY_NAMES: &[&str] = &["libndi.so.6", "libndi.so.5", "libndi.so"];
+#[cfg(target_os = "macos")]
+const LIBRARY_NAMES: &[&str] = &["libndi.dylib", "/usr/local/lib/libndi.dylib"];
+#[cfg(target_os = "windows")]
+const LIBRARY_NAMES: &[&str] = &["Processing.NDI.Lib.x64.dll"];
+
+// `NDIlib_recv_color_format_e`, `NDIlib_recv_bandwidth_e` and
+// `NDIlib_frame_type_e`.
+const COLOR_FORMAT_BGRX_BGRA: i32 = 0;
+const COLOR_FORMAT_RGBX_RGBA: i32 = 2;
+const BANDWIDTH_HIGHEST: i32 = 100;
+const FRAME_TYPE_VIDEO: i32 = 1;
+const FRAME_TYPE_ERROR: i32 = 4;
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+/// `NDIlib_source_t`.
+#[repr(C)]
+struct RawSource {
+    name: *const c_char,
+    url_address: *const c_char,
+}
+
+/// `NDIlib_find_create_t`.
+#[repr(C)]
+struct RawFindCreate {
+    show_local_sources: bool,
+    groups: *const c_char,
+    extra_ips: *const c_char,
+}
+
+/// `NDIlib_recv_create_v3_t`.
+#[repr(C)]
+struct RawRecvCreate {
+    source: RawSource,
+    color_format: i32,
+    bandwidth: i32,
+    allow_video_fields: bool,
+    name: *const c_char,
+}
+
+/// `NDIlib_video_frame_v2_t`.
+#[repr(C)]
+struct RawVideoFrame {
+    xres: i32,
+    yres: i32,
+    fourcc: u32,
+    frame_rate_n: i32,
+    frame_rate_d: i32,
+    picture_aspect_ratio: f32,
+    frame_format_type: i32,
+    timecode: i64,
+    data: *mut u8,
+    line_stride: i32,
+    metadata: *const c_char,
+    timestamp: i64,
+}
+
+/// The runtime's functions this module calls.
+struct Runtime {
+    find_create: unsafe extern "C" fn(*const RawFindCreate) -> *mut c_void,
+    find_wait: unsafe extern "C" fn(*mut c_void, u32) -> bool,
+    find_sources: unsafe extern "C" fn(*mut c_void, *mut u32) -> *const RawSource,
+    find_destroy: unsafe extern "C" fn(*mut c_void),
+    recv_create: unsafe extern "C" fn(*const RawRecvCreate) -> *mut c_void,
+    recv_capture:
+        unsafe extern "C" fn(*mut c_void, *mut RawVideoFrame, *mut c_void, *mut c_void, u32) -> i32,
+    recv_free_video: unsafe extern "C" fn(*mut c_void, *const RawVideoFrame),
+    recv_destroy: unsafe extern "C" fn(*mut c_void),
+    /// Kept loaded for the life of the process, as the functions are.
+    _library: libloading::Library,
+}
+
+/// The runtime, loaded and initialized on first use.
+fn runtime() -> Result<&'static Runtime, CameraError> {
+    static RUNTIME: OnceLock<Result<Runtime, String>> = OnceLock::new();
+    RUNTIME
+        .get_or_init(load_runtime)
+        .as_ref()
+        .map_err(|reason| CameraError::unsupported(reason.clone()))
+}
+
+fn load_runtime() -> Result<Runtime, String> {
+    let dirs: Vec<_> = ["NDI_RUNTIME_DIR_V6", "NDI_RUNTIME_DIR_V5"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(std::path::PathBuf::from)
+        .collect();
+    let candidates = dirs
+        .iter()
+        .flat_map(|dir| LIBRARY_NAMES.iter().map(move |name| dir.join(name)))
+        .chain(LIBRARY_NAMES.iter().map(std::path::PathBuf::from));
+    // SAFETY: the NDI runtime's initializers only set up its own state.
+    let library = candidates
+        .filter_map(|path| unsafe { libloading::Library::new(path) }.ok())
+        .next()
+        .ok_or_else(|| format!("the NDI runtime isn't installed (get it from {RUNTIME_URL})"))?;
+
+    macro_rules! symbol {
+        ($name:literal) => {
+            // SAFETY: the types are those of the NDI SDK's declarations.
+            *unsafe { library.get(concat!($name, "\0").as_bytes()) }
+                .map_err(|e| format!("the NDI runtime has no {}: {e}", $name))?
+        };
+    }
+    let initialize: unsafe extern "C" fn() -> bool = symbol!("NDIlib_initialize");
+    let runtime = Runtime {
+        find_create: symbol!("NDIlib_find_create_v2"),
+        find_wait: symbol!("NDIlib_find_wait_for_sources"),
+        find_sources: symbol!("NDIlib_find_get_current_sources"),
+        find_destroy: symbol!("NDIlib_find_destroy"),
+        recv_create: symbol!("NDIlib_recv_create_v3"),
+        recv_capture: symbol!("NDIlib_recv_capture_v2"),
+        recv_free_video: symbol!("NDIlib_recv_free_video_v2"),
+        recv_destroy: symbol!("NDIlib_recv_destroy"),
+        _library: library,
+    };
+    // SAFETY: initializing takes no arguments and may be called once.
+    if !unsafe { initialize() } {
+        return Err("the NDI runtime doesn't support this CPU".into());
+    }
+    Ok(runtime)
+}
+
+/// An NDI source on the network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NdiSource {
+    /// Its name, `MACHINE (SOURCE)`.
+    pub name: String,
+    /// Where it can be reached, e.g. `192.168.1.20:5961`.
+    pub address: Option<String>,
+}
+
+impl NdiSource {
+    /// The device id that receives it.
+    pub fn device_id(&self) -> String {
+        format!("{NDI_PREFIX}{}", self.name)
+    }
+}
+
+/// The NDI sources announced on the network, and any at `extra_ips` (a
+/// comma-separated list, for sources discovery doesn't reach), that turn
+/// up within `timeout`.
+pub fn find_ndi_sources(
+    timeout: Duration,
+    extra_ips: Option<&str>,
+) -> Result<Vec<NdiSource>, CameraError> {
+    let runtime = runtime()?;
+    let extra_ips = extra_ips
+        .map(CString::new)
+        .transpose()
+        .map_err(|_| CameraError::invalid_config("NDI addresses can't contain NUL"))?;
+    let settings = RawFindCreate {
+        show_local_sources: true,
+        groups: ptr::null(),
+        extra_ips: extra_ips.as_ref().map_or(ptr::null(), |ips| ips.as_ptr()),
+    };
+    // SAFETY: `settings` and the strings it points to outlive the call.
+    let finder = unsafe { (runtime.find_create)(&settings) };
+    let Some(finder) = NonNull::new(finder) else {
+        return Err(CameraError::other(
+            "the NDI runtime couldn't start looking for sources",
+        ));
+    };
+    let _finder = scopeguard::guard(finder, |finder| {
+        // SAFETY: `finder` was created above and is destroyed once.
+        unsafe { (runtime.find_destroy)(finder.as_ptr()) }
+    });
+
+    // Sources announce themselves one by one, so collect them until the
+    // time is up.
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        let wait = left.as_millis().clamp(1, u32::MAX as u128) as u32;
+        // SAFETY: `finder` is live.
+        unsafe { (runtime.find_wait)(finder.as_ptr(), wait) };
+    }
+
+    let mut count = 0;
+    // SAFETY: `finder` is live, and the array it returns stays valid until
+    // it's next asked or destroyed.
+    let sources = unsafe { (runtime.find_sources)(finder.as_ptr(), &mut count) };
+    if sources.is_null() {
+        return Ok(Vec::new());
+    }
+    // SAFETY: as above, `count` sources start at `sources`.
+    let sources = unsafe { core::slice::from_raw_parts(sources, count as usize) };
+    Ok(sources
+        .iter()
+        .filter_map(|source| {
+            Some(NdiSource {
+                // SAFETY: the runtime's strings are NUL-terminated.
+                name: unsafe { c_string(source.name) }?,
+                address: unsafe { c_string(source.url_address) },
+            })
+        })
+        .collect())
+}
+
+/// # Safety
+///
+/// `s` is null or points to a NUL-terminated string.
+unsafe fn c_string(s: *const c_char) -> Option<String> {
+    (!s.is_null()).then(|| unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned())
+}
+
+/// A connection receiving an NDI source's video.
+pub struct NdiReceiver {
+    runtime: &'static Runtime,
+    instance: NonNull<c_void>,
+    source: String,
+}
+
+// SAFETY: NDI receivers may be used from any thread, one call at a time,
+// which `&mut self` ensures.
+unsafe impl Send for NdiReceiver {}
+
+impl core::fmt::Debug for NdiReceiver {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("NdiReceiver")
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+impl NdiReceiver {
+    /// Connects to the source named `source`, `MACHINE (SOURCE)`, for
+    /// frames in `format`: BGRA for `Bgra8`, else RGBA. The connection is
+    /// made in the background, and remade when the source comes back.
+    pub fn connect(source: &str, format: PixelFormat) -> Result<Self, CameraError> {
+        let runtime = runtime()?;
+        let name = CString::new(source)
+            .map_err(|_| CameraError::invalid_config("NDI source names can't contain NUL"))?;
+        let receiver_name = CString::new("asimov-camera").expect("no NUL");
+        let settings = RawRecvCreate {
+            source: RawSource {
+                name: name.as_ptr(),
+                url_address: ptr::null(),
+            },
+            color_format: match format {
+                PixelFormat::Bgra8 => COLOR_FORMAT_BGRX_BGRA,
+                _ => COLOR_FORMAT_RGBX_RGBA,
+            },
+            bandwidth: BANDWIDTH_HIGHEST,
+            allow_video_fields: false,
+            name: receiver_name.as_ptr(),
+        };
+        // SAFETY: `settings` and its strings outlive the call, which copies
+        // them.
+        let instance = unsafe { (runtime.recv_create)(&settings) };
+        let instance = NonNull::new(instance).ok_or_else(|| {
+            CameraError::other(format!("the NDI runtime couldn't receive '{source}'"))
+        })?;
+        Ok(Self {
+            runtime,
+            instance,
+            source: source.to_string(),
+        })
+    }
+
+    /// The next video frame, if one arrives within `timeout`.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Frame>, CameraError> {
+        // SAFETY: all zeros is a valid, empty video frame.
+        let mut video: RawVideoFrame = unsafe { core::mem::zeroed() };
+        let timeout = timeout.as_millis().min(u32::MAX as u128) as u32;
+        // SAFETY: the receiver is live, and null audio and metadata frames
+        // mean those aren't wanted.
+        let kind = unsafe {
+            (self.runtime.recv_capture)(
+                self.instance.as_ptr(),
+                &mut video,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                timeout,
+            )
+        };
+        match kind {
+            FRAME_TYPE_VIDEO => {},
+            FRAME_TYPE_ERROR => {
+                return Err(CameraError::other(format!(
+                    "lost the connection to NDI source '{}'",
+                    self.source
+                )));
+            },
+            _ => return Ok(None),
+        }
+        let time = FrameTime::now();
+        let frame = self.copy_frame(&video).map(|frame| frame.with_time(time));
+        // SAFETY: `video` came from this receiver and is freed once.
+        unsafe { (self.runtime.recv_free_video)(self.instance.as_ptr(), &video) };
+        frame.map(Some)
+    }
+
+    fn copy_frame(&self, video: &RawVideoFrame) -> Result<Frame, CameraError> {
+        let pixel_format = match video.fourcc {
+            code if code == fourcc(b"RGBA") || code == fourcc(b"RGBX") => PixelFormat::Rgba8,
+            code if code == fourcc(b"BGRA") || code == fourcc(b"BGRX") => PixelFormat::Bgra8,
+            code => {
+                return Err(CameraError::unsupported(format!(
+                    "NDI source '{}' sent {:?} video",
+                    self.source,
+                    String::from_utf8_lossy(&code.to_le_bytes())
+                )));
+            },
+        };
+        let (width, height) = (video.xres.max(0) as u32, video.yres.max(0) as u32);
+        let stride = video.line_stride.max(0) as u32;
+        if video.data.is_null() || stride < width * 4 {
+            return Err(CameraError::other(format!(
+                "NDI source '{}' sent a malformed frame",
+                self.source
+            )));
+        }
+        // SAFETY: the runtime's frame holds `yres` rows of `line_stride`
+        // bytes until it's freed.
+        let pixels =
+            unsafe { core::slice::from_raw_parts(video.data, stride as usize * height as usize) };
+        Ok(Frame::new(
+            Bytes::copy_from_slice(pixels),
+            width,
+            height,
+            stride,
+            pixel_format,
+        ))
+    }
+}
+
+impl Drop for NdiReceiver {
+    fn drop(&mut self) {
+        // SAFETY: the receiver is live and destroyed once.
+        unsafe { (self.runtime.recv_destroy)(self.instance.as_ptr()) }
+    }
+}
@@ -0,0 +1,255 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{CameraError, CameraEvent, Frame, FrameSink};
+use std::{
+    collections::HashMap,
+    io::Write,
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Capture events an operator can be notified about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NotifyEvent {
+    FrameEmitted,
+    MotionDetected,
+    DeviceLost,
+}
+
+impl NotifyEvent {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            NotifyEvent::FrameEmitted => "frame",
+            NotifyEvent::MotionDetected => "motion",
+            NotifyEvent::DeviceLost => "lost",
+        }
+    }
+}
+
+impl FromStr for NotifyEvent {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "frame" | "frame-emitted" => Ok(NotifyEvent::FrameEmitted),
+            "motion" | "motion-detected" => Ok(NotifyEvent::MotionDetected),
+            "lost" | "device-lost" => Ok(NotifyEvent::DeviceLost),
+            other => Err(CameraError::invalid_config(format!(
+                "unknown notification event '{other}' (expected frame, motion or lost)"
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotifyAction {
+    /// Rings the terminal bell on stderr.
+    Bell,
+    /// Shows a desktop notification (notify-send, osascript or PowerShell).
+    System,
+    /// Runs a shell command with `ASIMOV_CAMERA_EVENT` and
+    /// `ASIMOV_CAMERA_MESSAGE` set in its environment.
+    Command(String),
+}
+
+impl FromStr for NotifyAction {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(cmd) = s.strip_prefix("cmd:") {
+            if cmd.trim().is_empty() {
+                return Err(CameraError::invalid_config("empty notification command"));
+            }
+            return Ok(NotifyAction::Command(cmd.trim().to_string()));
+        }
+        match s.to_ascii_lowercase().as_str() {
+            "bell" | "beep" => Ok(NotifyAction::Bell),
+            "system" | "desktop" => Ok(NotifyAction::System),
+            other => Err(CameraError::invalid_config(format!(
+                "unknown notification action '{other}' (expected bell, system or cmd:COMMAND)"
+            ))),
+        }
+    }
+}
+
+/// Parses an `EVENT=ACTION` rule such as `lost=system` or `frame=bell`.
+pub fn parse_notify_rule(s: &str) -> Result<(NotifyEvent, NotifyAction), CameraError> {
+    let (event, action) = s.split_once('=').ok_or_else(|| {
+        CameraError::invalid_config(format!("invalid notification rule '{s}', use EVENT=ACTION"))
+    })?;
+    Ok((event.parse()?, action.parse()?))
+}
+
+/// Dispatches feedback (bell, desktop notification, command) for capture
+/// events, rate-limited per event type.
+#[derive(Debug)]
+pub struct Notifier {
+    rules: Vec<(NotifyEvent, NotifyAction)>,
+    min_interval: Duration,
+    last: Mutex<HashMap<NotifyEvent, Instant>>,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            min_interval: Duration::from_secs(1),
+            last: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on(mut self, event: NotifyEvent, action: NotifyAction) -> Self {
+        self.rules.push((event, action));
+        self
+    }
+
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn handles(&self, event: NotifyEvent) -> bool {
+        self.rules.iter().any(|(e, _)| *e == event)
+    }
+
+    pub fn notify(&self, event: NotifyEvent, message: &str) {
+        if !self.handles(event) {
+            return;
+        }
+
+        {
+            let mut last = self.last.lock().unwrap_or_else(|p| p.into_inner());
+            let now = Instant::now();
+            if let Some(prev) = last.get(&event)
+                && now.duration_since(*prev) < self.min_interval
+            {
+                return;
+            }
+            last.insert(event, now);
+        }
+
+        for (_, action) in self.rules.iter().filter(|(e, _)| *e == event) {
+            run_action(action, event, message);
+        }
+    }
+
+    /// Maps camera events onto notifications; driver errors count as a lost device.
+    pub fn observe(&self, event: &CameraEvent) {
+        match event {
+            CameraEvent::Error { backend, error } => {
+                self.notify(NotifyEvent::DeviceLost, &format!("{backend:?}: {error}"));
+            },
+            CameraEvent::Stalled { backend, after } => {
+                self.notify(
+                    NotifyEvent::DeviceLost,
+                    &format!("{backend:?}: no frames for {after:.1?}"),
+                );
+            },
+            _ => {},
+        }
+    }
+
+    /// Returns a sink that reports every frame it receives as emitted.
+    pub fn frame_sink(self: &Arc<Self>) -> FrameSink {
+        let this = Arc::clone(self);
+        Arc::new(move |frame: Frame| {
+            this.notify(
+                NotifyEvent::FrameEmitted,
+                &format!("frame {}x{}", frame.width, frame.height),
+            );
+        })
+    }
+}
+
+fn run_action(action: &NotifyAction, event: NotifyEvent, message: &str) {
+    match action {
+        NotifyAction::Bell => {
+            let mut stderr = std::io::stderr();
+            let _ = stderr.write_all(b"\x07");
+            let _ = stderr.flush();
+        },
+        NotifyAction::System => spawn_detached(system_notification(message)),
+        NotifyAction::Command(cmd) => {
+            let mut command = shell_command(cmd);
+            command
+                .env("ASIMOV_CAMERA_EVENT", event.as_str())
+                .env("ASIMOV_CAMERA_MESSAGE", message);
+            spawn_detached(Some(command));
+        },
+    }
+}
+
+fn spawn_detached(command: Option<Command>) {
+    let Some(mut command) = command else {
+        return;
+    };
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Wait on a helper thread so finished children are reaped.
+    std::thread::spawn(move || {
+        let _ = command.status();
+    });
+}
+
+fn shell_command(cmd: &str) -> Command {
+    #[cfg(windows)]
+    {
+        let mut c = Command::new("cmd");
+        c.args(["/C", cmd]);
+        c
+    }
+    #[cfg(not(windows))]
+    {
+        let mut c = Command::new("sh");
+        c.args(["-c", cmd]);
+        c
+    }
+}
+
+fn system_notification(message: &str) -> Option<Command> {
+    const TITLE: &str = "ASIMOV Camera";
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "macos")] {
+            let script = format!(
+                "display notification {:?} with title {:?}",
+                message, TITLE
+            );
+            let mut c = Command::new("osascript");
+            c.args(["-e", &script]);
+            Some(c)
+        } else if #[cfg(target_os = "windows")] {
+            let script = format!(
+                "Add-Type -AssemblyName System.Windows.Forms; \
+                 $n = New-Object System.Windows.Forms.NotifyIcon; \
+                 $n.Icon = [System.Drawing.SystemIcons]::Information; $n.Visible = $true; \
+                 $n.ShowBalloonTip(3000, '{TITLE}', '{}', 'Info'); Start-Sleep 4; $n.Dispose()",
+                message.replace('\'', "''")
+            );
+            let mut c = Command::new("powershell");
+            c.args(["-NoProfile", "-Command", &script]);
+            Some(c)
+        } else if #[cfg(unix)] {
+            let mut c = Command::new("notify-send");
+            c.args([TITLE, message]);
+            Some(c)
+        } else {
+            let _ = (message, TITLE);
+            None
+        }
+    }
+}
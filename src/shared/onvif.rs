@@ -0,0 +1,701 @@
+// This is free and unencumbered software released into the public domain.
+
+//! ONVIF network cameras: finding them on the local network with
+//! WS-Discovery, and asking one for the RTSP URI of a stream (the media
+//! service's `GetProfiles` and `GetStreamUri`).
+//!
+//! Requests are authenticated with the WS-Security `UsernameToken` digest,
+//! timestamped by the camera's own clock, since cameras reject tokens from
+//! a clock that disagrees with theirs. Only what discovery and streaming
+//! need of SOAP is here, and the XML is read by element name.
+
+use crate::shared::{
+    CameraError,
+    http::{self, Url},
+};
+use base64::Engine;
+use core::time::Duration;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    net::UdpSocket,
+    sync::{Arc, OnceLock},
+};
+
+/// The device ids of ONVIF cameras: `onvif://[USER:PASSWORD@]HOST[:PORT][/PATH][?profile=TOKEN]`.
+pub const ONVIF_PREFIX: &str = "onvif://";
+
+/// Where WS-Discovery probes are sent.
+const DISCOVERY_ADDRESS: &str = "239.255.255.250:3702";
+
+/// Where cameras serve the device service, unless the device id says.
+const DEVICE_SERVICE_PATH: &str = "/onvif/device_service";
+
+const DEVICE_WSDL: &str = "http://www.onvif.org/ver10/device/wsdl";
+const MEDIA_WSDL: &str = "http://www.onvif.org/ver10/media/wsdl";
+const SCHEMA: &str = "http://www.onvif.org/ver10/schema";
+
+/// A camera that answered a WS-Discovery probe.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OnvifCamera {
+    /// Its endpoint reference, e.g. `urn:uuid:…`, which stays the same
+    /// whichever address it answers on.
+    pub endpoint: String,
+    /// The URLs of its device service.
+    pub xaddrs: Vec<String>,
+    /// Its scopes, e.g. `onvif://www.onvif.org/name/Garage`.
+    pub scopes: Vec<String>,
+}
+
+impl OnvifCamera {
+    /// The name it gives itself in its `name` scope.
+    pub fn name(&self) -> Option<String> {
+        self.scope("name")
+    }
+
+    /// Its model, from its `hardware` scope.
+    pub fn hardware(&self) -> Option<String> {
+        self.scope("hardware")
+    }
+
+    /// Where it says it is, from its `location` scope.
+    pub fn location(&self) -> Option<String> {
+        self.scope("location")
+    }
+
+    fn scope(&self, kind: &str) -> Option<String> {
+        let prefix = format!("onvif://www.onvif.org/{kind}/");
+        self.scopes
+            .iter()
+            .find_map(|scope| scope.strip_prefix(&prefix))
+            .map(percent_decode)
+    }
+
+    /// The device id that opens it, `onvif://HOST[:PORT][/PATH]`, from its
+    /// first `http://` device service URL.
+    pub fn device_id(&self) -> Option<String> {
+        let url = self
+            .xaddrs
+            .iter()
+            .filter_map(|xaddr| Url::parse(xaddr).ok())
+            .find(|url| !url.tls)?;
+        let mut id = format!("{ONVIF_PREFIX}{}", url.host_header());
+        if url.path != DEVICE_SERVICE_PATH {
+            id.push_str(&url.path);
+        }
+        Some(id)
+    }
+}
+
+/// The WS-Discovery probe for ONVIF video transmitters.
+pub fn probe_message(message_id: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <s:Envelope xmlns:s=\"http://www.w3.org/2003/05/soap-envelope\" \
+         xmlns:a=\"http://schemas.xmlsoap.org/ws/2004/08/addressing\" \
+         xmlns:d=\"http://schemas.xmlsoap.org/ws/2005/04/discovery\" \
+         xmlns:dn=\"http://www.onvif.org/ver10/network/wsdl\">\
+         <s:Header>\
+         <a:MessageID>{message_id}</a:MessageID>\
+         <a:To s:mustUnderstand=\"1\">urn:schemas-xmlsoap-org:ws:2005:04:discovery</a:To>\
+         <a:Action s:mustUnderstand=\"1\">http://schemas.xmlsoap.org/ws/2005/04/discovery/Probe</a:Action>\
+         </s:Header>\
+         <s:Body><d:Probe><d:Types>dn:NetworkVideoTransmitter</d:Types></d:Probe></s:Body>\
+         </s:Envelope>"
+    )
+}
+
+/// The cameras in a WS-Discovery `ProbeMatches` message.
+pub fn parse_probe_matches(xml: &str) -> Vec<OnvifCamera> {
+    elements(xml, "ProbeMatch")
+        .into_iter()
+        .filter_map(|m| {
+            let list = |name: &str| -> Vec<String> {
+                element(m.inner, name)
+                    .map(|e| e.text().split_whitespace().map(String::from).collect())
+                    .unwrap_or_default()
+            };
+            let camera = OnvifCamera {
+                endpoint: element(m.inner, "Address")
+                    .map(|e| e.text())
+                    .unwrap_or_default(),
+                xaddrs: list("XAddrs")
+                    .into_iter()
+                    .filter(|x| Url::parse(x).is_ok())
+                    .collect(),
+                scopes: list("Scopes"),
+            };
+            (!camera.xaddrs.is_empty()).then_some(camera)
+        })
+        .collect()
+}
+
+/// Probes the local network for ONVIF cameras, collecting the answers
+/// that arrive within `timeout`, one per camera.
+pub fn discover(timeout: Duration) -> Result<Vec<OnvifCamera>, CameraError> {
+    let socket =
+        UdpSocket::bind("0.0.0.0:0").map_err(|e| CameraError::driver("binding a UDP socket", e))?;
+    let _ = socket.set_multicast_ttl_v4(2);
+    let probe = probe_message(&message_id());
+    // SOAP-over-UDP repeats messages, since any one may be lost.
+    for _ in 0..2 {
+        socket
+            .send_to(probe.as_bytes(), DISCOVERY_ADDRESS)
+            .map_err(|e| CameraError::driver("sending the WS-Discovery probe", e))?;
+    }
+
+    let mut cameras: BTreeMap<String, OnvifCamera> = BTreeMap::new();
+    let deadline = std::time::Instant::now() + timeout;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        socket
+            .set_read_timeout(Some(left))
+            .map_err(|e| CameraError::driver("waiting for WS-Discovery answers", e))?;
+        let Ok((n, from)) = socket.recv_from(&mut buf) else {
+            break;
+        };
+        for camera in parse_probe_matches(&String::from_utf8_lossy(&buf[..n])) {
+            let key = match camera.endpoint.is_empty() {
+                true => from.ip().to_string(),
+                false => camera.endpoint.clone(),
+            };
+            cameras.entry(key).or_insert(camera);
+        }
+    }
+    Ok(cameras.into_values().collect())
+}
+
+/// A media profile of a camera: one of the streams it can send.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OnvifProfile {
+    pub token: String,
+    pub name: String,
+    /// The video encoding, e.g. `H264`.
+    pub encoding: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// The profiles in a `GetProfilesResponse`.
+pub fn parse_profiles(xml: &str) -> Vec<OnvifProfile> {
+    elements(xml, "Profiles")
+        .into_iter()
+        .filter_map(|p| {
+            let token = p.attribute("token")?;
+            let video = element(p.inner, "VideoEncoderConfiguration");
+            let field = |name: &str| video.and_then(|v| element(v.inner, name)).map(|e| e.text());
+            Some(OnvifProfile {
+                token,
+                name: element(p.inner, "Name")
+                    .map(|e| e.text())
+                    .unwrap_or_default(),
+                encoding: field("Encoding"),
+                width: field("Width").and_then(|w| w.parse().ok()),
+                height: field("Height").and_then(|h| h.parse().ok()),
+            })
+        })
+        .collect()
+}
+
+/// The URI in a `GetStreamUriResponse`.
+pub fn parse_stream_uri(xml: &str) -> Option<String> {
+    element(xml, "MediaUri")
+        .and_then(|uri| element(uri.inner, "Uri"))
+        .map(|e| e.text())
+        .filter(|uri| !uri.is_empty())
+}
+
+/// WS-Security's `PasswordDigest`: the Base64 of the SHA-1 of `nonce`,
+/// `created` and `password`.
+pub fn password_digest(nonce: &[u8], created: &str, password: &str) -> String {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY);
+    context.update(nonce);
+    context.update(created.as_bytes());
+    context.update(password.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(context.finish())
+}
+
+/// A camera's device and media services.
+pub struct OnvifClient {
+    device: Url,
+    username: Option<String>,
+    password: String,
+    profile: Option<String>,
+    tls: OnceLock<Arc<rustls::ClientConfig>>,
+    media: OnceLock<Url>,
+    /// How far the camera's clock is ahead of ours, in seconds.
+    clock_offset: OnceLock<i64>,
+}
+
+impl core::fmt::Debug for OnvifClient {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("OnvifClient")
+            .field("device", &self.device)
+            .field("username", &self.username)
+            .field("profile", &self.profile)
+            .finish()
+    }
+}
+
+impl OnvifClient {
+    /// A client of the device service at `url`, `http://` or `https://`.
+    pub fn new(url: &str) -> Result<Self, CameraError> {
+        let device = Url::parse(url)
+            .map_err(|e| CameraError::invalid_config(format!("ONVIF service URL: {e}")))?;
+        Ok(Self {
+            device,
+            username: None,
+            password: String::new(),
+            profile: None,
+            tls: OnceLock::new(),
+            media: OnceLock::new(),
+            clock_offset: OnceLock::new(),
+        })
+    }
+
+    /// A client of the camera `device` names,
+    /// `onvif://[USER:PASSWORD@]HOST[:PORT][/PATH][?profile=TOKEN]`; without
+    /// a password in it, `$ONVIF_PASSWORD` is used.
+    pub fn from_device(device: &str) -> Result<Self, CameraError> {
+        let invalid = |reason: &str| {
+            CameraError::invalid_config(format!("invalid ONVIF device '{device}': {reason}"))
+        };
+        let rest = device
+            .trim()
+            .strip_prefix(ONVIF_PREFIX)
+            .ok_or_else(|| invalid("expected onvif://HOST"))?;
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (userinfo, host) = match authority.rsplit_once('@') {
+            Some((userinfo, host)) => (Some(userinfo), host),
+            None => (None, authority),
+        };
+        if host.is_empty() {
+            return Err(invalid("missing host"));
+        }
+        let path = match path {
+            "" | "/" => DEVICE_SERVICE_PATH,
+            path => path,
+        };
+        let mut client =
+            Self::new(&format!("http://{host}{path}")).map_err(|_| invalid("bad host"))?;
+        if let Some(userinfo) = userinfo {
+            let (username, password) = match userinfo.split_once(':') {
+                Some((username, password)) => (username, Some(percent_decode(password))),
+                None => (userinfo, None),
+            };
+            let password = password
+                .or_else(|| std::env::var("ONVIF_PASSWORD").ok())
+                .unwrap_or_default();
+            client = client.with_credentials(percent_decode(username), password);
+        }
+        for (key, value) in query.split('&').filter_map(|kv| kv.split_once('=')) {
+            match key {
+                "profile" => client.profile = Some(percent_decode(value)),
+                _ => return Err(invalid(&format!("unknown parameter '{key}'"))),
+            }
+        }
+        Ok(client)
+    }
+
+    /// Authenticates requests as `username`.
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.username = Some(username.into());
+        self.password = password.into();
+        self
+    }
+
+    /// The camera's media profiles, the main stream usually first.
+    pub fn profiles(&self) -> Result<Vec<OnvifProfile>, CameraError> {
+        let media = self.media_service();
+        let response = self.call(
+            &media,
+            &format!("{MEDIA_WSDL}/GetProfiles"),
+            &format!("<trt:GetProfiles xmlns:trt=\"{MEDIA_WSDL}\"/>"),
+        )?;
+        Ok(parse_profiles(&response))
+    }
+
+    /// The RTSP URI of the profile `token`, as the camera gives it, without
+    /// credentials.
+    pub fn stream_uri(&self, token: &str) -> Result<String, CameraError> {
+        let media = self.media_service();
+        let response = self.call(
+            &media,
+            &format!("{MEDIA_WSDL}/GetStreamUri"),
+            &format!(
+                "<trt:GetStreamUri xmlns:trt=\"{MEDIA_WSDL}\" xmlns:tt=\"{SCHEMA}\">\
+                 <trt:StreamSetup><tt:Stream>RTP-Unicast</tt:Stream>\
+                 <tt:Transport><tt:Protocol>RTSP</tt:Protocol></tt:Transport></trt:StreamSetup>\
+                 <trt:ProfileToken>{}</trt:ProfileToken></trt:GetStreamUri>",
+                escape(token)
+            ),
+        )?;
+        parse_stream_uri(&response).ok_or_else(|| {
+            CameraError::other(format!(
+                "{} gave no stream URI for the profile '{token}'",
+                self.device.host
+            ))
+        })
+    }
+
+    /// The RTSP URL to capture: that of the profile the device id asked
+    /// for, or else the first, with the client's credentials in it.
+    pub fn rtsp_url(&self) -> Result<String, CameraError> {
+        let token = match &self.profile {
+            Some(token) => token.clone(),
+            None => self
+                .profiles()?
+                .into_iter()
+                .next()
+                .map(|p| p.token)
+                .ok_or_else(|| {
+                    CameraError::other(format!("{} has no media profiles", self.device.host))
+                })?,
+        };
+        let uri = self.stream_uri(&token)?;
+        Ok(match &self.username {
+            Some(username) => with_userinfo(&uri, username, &self.password),
+            None => uri,
+        })
+    }
+
+    /// Where the media service is: what `GetCapabilities` says, or else the
+    /// device service, which many cameras serve it on too. Asked once.
+    fn media_service(&self) -> Url {
+        let media = self.media.get_or_init(|| {
+            let response = self.call(
+                &self.device,
+                &format!("{DEVICE_WSDL}/GetCapabilities"),
+                &format!(
+                    "<tds:GetCapabilities xmlns:tds=\"{DEVICE_WSDL}\">\
+                     <tds:Category>Media</tds:Category></tds:GetCapabilities>"
+                ),
+            );
+            response
+                .ok()
+                .and_then(|xml| {
+                    let media = element(&xml, "Media")?;
+                    Url::parse(&element(media.inner, "XAddr")?.text()).ok()
+                })
+                .unwrap_or_else(|| self.device.clone())
+        });
+        media.clone()
+    }
+
+    /// How far the camera's clock is ahead of ours, asked once.
+    fn clock_offset(&self) -> i64 {
+        *self.clock_offset.get_or_init(|| {
+            let now = jiff::Timestamp::now().as_second();
+            self.request(
+                &self.device,
+                &format!("{DEVICE_WSDL}/GetSystemDateAndTime"),
+                &format!("<tds:GetSystemDateAndTime xmlns:tds=\"{DEVICE_WSDL}\"/>"),
+                false,
+            )
+            .ok()
+            .and_then(|xml| parse_utc_time(&xml))
+            .map_or(0, |camera| camera - now)
+        })
+    }
+
+    fn call(&self, url: &Url, action: &str, body: &str) -> Result<String, CameraError> {
+        self.request(url, action, body, self.username.is_some())
+    }
+
+    fn request(
+        &self,
+        url: &Url,
+        action: &str,
+        body: &str,
+        authenticate: bool,
+    ) -> Result<String, CameraError> {
+        let operation = action.rsplit('/').next().unwrap_or(action);
+        let failed = |reason: String| {
+            CameraError::other(format!("ONVIF {operation} at {}: {reason}", url.host))
+        };
+        let header = match (authenticate, &self.username) {
+            (true, Some(username)) => self.security_header(username)?,
+            _ => String::new(),
+        };
+        let envelope = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <s:Envelope xmlns:s=\"http://www.w3.org/2003/05/soap-envelope\">\
+             <s:Header>{header}</s:Header><s:Body>{body}</s:Body></s:Envelope>"
+        );
+        let request = format!(
+            "POST {} HTTP/1.1\r\nhost: {}\r\ncontent-type: application/soap+xml; charset=utf-8; action=\"{action}\"\r\n\
+             content-length: {}\r\nuser-agent: asimov-camera-module\r\nconnection: close\r\n\r\n{envelope}",
+            url.path,
+            url.host_header(),
+            envelope.len()
+        );
+
+        let tls = match url.tls {
+            true => Some(match self.tls.get() {
+                Some(tls) => Arc::clone(tls),
+                None => {
+                    let tls = http::tls_config(None)?;
+                    Arc::clone(self.tls.get_or_init(|| tls))
+                },
+            }),
+            false => None,
+        };
+        let mut stream = http::connect(url, tls.as_ref()).map_err(|e| failed(e.to_string()))?;
+        stream
+            .write_all(request.as_bytes())
+            .and_then(|()| stream.flush())
+            .map_err(|e| failed(e.to_string()))?;
+        let response = http::read_response(&mut *stream).map_err(|e| failed(e.to_string()))?;
+        let text = String::from_utf8_lossy(&response.body).into_owned();
+        if response.is_success() {
+            return Ok(text);
+        }
+        let digest_only = response.status == 401
+            && response
+                .header("www-authenticate")
+                .is_some_and(|scheme| scheme.to_ascii_lowercase().starts_with("digest"));
+        Err(failed(match fault_message(&text) {
+            Some(message) => format!("HTTP {}: {message}", response.status),
+            // Some cameras only take HTTP Digest, not WS-Security.
+            None if digest_only => "HTTP 401: the camera wants HTTP Digest authentication, \
+                 which isn't supported; enable WS-Security (UsernameToken) on it"
+                .to_string(),
+            None => format!("HTTP {}", response.status),
+        }))
+    }
+
+    /// The WS-Security header authenticating a request as `username`.
+    fn security_header(&self, username: &str) -> Result<String, CameraError> {
+        let mut nonce = [0; 16];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| CameraError::other("no randomness for the WS-Security nonce"))?;
+        let created =
+            jiff::Timestamp::from_second(jiff::Timestamp::now().as_second() + self.clock_offset())
+                .unwrap_or_else(|_| jiff::Timestamp::now())
+                .strftime("%Y-%m-%dT%H:%M:%SZ")
+                .to_string();
+        Ok(format!(
+            "<wsse:Security s:mustUnderstand=\"1\" \
+             xmlns:wsse=\"http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-secext-1.0.xsd\" \
+             xmlns:wsu=\"http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-utility-1.0.xsd\">\
+             <wsse:UsernameToken><wsse:Username>{}</wsse:Username>\
+             <wsse:Password Type=\"http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-username-token-profile-1.0#PasswordDigest\">{}</wsse:Password>\
+             <wsse:Nonce EncodingType=\"http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-soap-message-security-1.0#Base64Binary\">{}</wsse:Nonce>\
+             <wsu:Created>{created}</wsu:Created></wsse:UsernameToken></wsse:Security>",
+            escape(username),
+            password_digest(&nonce, &created, &self.password),
+            base64::engine::general_purpose::STANDARD.encode(nonce),
+        ))
+    }
+}
+
+/// The Unix time in a `GetSystemDateAndTimeResponse`'s `UTCDateTime`.
+fn parse_utc_time(xml: &str) -> Option<i64> {
+    let utc = element(xml, "UTCDateTime")?;
+    let field = |parent: &str, name: &str| -> Option<i64> {
+        let parent = element(utc.inner, parent)?;
+        element(parent.inner, name)?.text().parse().ok()
+    };
+    let date = jiff::civil::Date::new(
+        field("Date", "Year")? as i16,
+        field("Date", "Month")? as i8,
+        field("Date", "Day")? as i8,
+    )
+    .ok()?;
+    let time = jiff::civil::Time::new(
+        field("Time", "Hour")? as i8,
+        field("Time", "Minute")? as i8,
+        field("Time", "Second")? as i8,
+        0,
+    )
+    .ok()?;
+    let timestamp = date
+        .to_datetime(time)
+        .to_zoned(jiff::tz::TimeZone::UTC)
+        .ok()?;
+    Some(timestamp.timestamp().as_second())
+}
+
+/// The reason of a SOAP fault, e.g. `ter:NotAuthorized: Sender not authorized`.
+fn fault_message(xml: &str) -> Option<String> {
+    let fault = element(xml, "Fault")?;
+    let code = elements(fault.inner, "Value")
+        .last()
+        .map(|e| e.text())
+        .filter(|code| !code.is_empty());
+    let reason = element(fault.inner, "Text").map(|e| e.text());
+    match (code, reason) {
+        (Some(code), Some(reason)) => Some(format!("{code}: {reason}")),
+        (code, reason) => code.or(reason),
+    }
+}
+
+/// `uri` with `username` and `password` in it, unless it has credentials
+/// of its own.
+fn with_userinfo(uri: &str, username: &str, password: &str) -> String {
+    let Some((scheme, rest)) = uri.split_once("://") else {
+        return uri.to_string();
+    };
+    let authority = &rest[..rest.find('/').unwrap_or(rest.len())];
+    if authority.contains('@') {
+        return uri.to_string();
+    }
+    format!(
+        "{scheme}://{}:{}@{rest}",
+        percent_encode(username),
+        percent_encode(password)
+    )
+}
+
+/// A random `urn:uuid:` for a message, as version 4 UUIDs are.
+fn message_id() -> String {
+    let mut bytes = [0u8; 16];
+    let _ = SystemRandom::new().fill(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "urn:uuid:{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// An element of an XML document, found by its local name.
+#[derive(Clone, Copy, Debug)]
+struct Element<'a> {
+    /// Its start tag's attributes.
+    attributes: &'a str,
+    inner: &'a str,
+}
+
+impl Element<'_> {
+    /// Its text, unescaped and trimmed.
+    fn text(&self) -> String {
+        unescape(self.inner.trim())
+    }
+
+    fn attribute(&self, name: &str) -> Option<String> {
+        let mut rest = self.attributes;
+        loop {
+            let at = rest.find(name)?;
+            let (before, after) = (&rest[..at], &rest[at + name.len()..]);
+            rest = after;
+            if before.ends_with(char::is_whitespace)
+                && let Some(value) = after.trim_start().strip_prefix('=')
+            {
+                let value = value.trim_start();
+                let quote = value.chars().next()?;
+                let value = &value[1..];
+                return Some(unescape(&value[..value.find(quote)?]));
+            }
+        }
+    }
+}
+
+/// The elements named `name`, with any namespace prefix, in `xml`, outside
+/// in.
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<Element<'a>> {
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(open) = rest.find('<') {
+        rest = &rest[open + 1..];
+        let tag_end = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .unwrap_or(rest.len());
+        let qualified = &rest[..tag_end];
+        let local = qualified.rsplit(':').next().unwrap_or(qualified);
+        if local != name {
+            continue;
+        }
+        let Some(start_end) = rest.find('>') else {
+            break;
+        };
+        let attributes = &rest[tag_end..start_end];
+        if let Some(attributes) = attributes.strip_suffix('/') {
+            found.push(Element {
+                attributes,
+                inner: "",
+            });
+            rest = &rest[start_end + 1..];
+            continue;
+        }
+        let body = &rest[start_end + 1..];
+        let Some(close) = body.find(&format!("</{qualified}>")) else {
+            break;
+        };
+        found.push(Element {
+            attributes,
+            inner: &body[..close],
+        });
+        rest = &body[close..];
+    }
+    found
+}
+
+fn element<'a>(xml: &'a str, name: &str) -> Option<Element<'a>> {
+    elements(xml, name).into_iter().next()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| core::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            },
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
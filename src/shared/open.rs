@@ -3,7 +3,7 @@
 use super::{Camera, CameraConfig, CameraError};
 
 #[allow(unused_imports)]
-use super::{CameraBackend, CameraEvent, Dispatcher};
+use super::{CameraBackend, CameraDriver, CameraEvent, Dispatcher};
 #[allow(unused_imports)]
 use std::sync::mpsc::sync_channel;
 
@@ -17,19 +17,251 @@ pub fn open_camera(
     macro_rules! init_camera {
         ($driver_type:ty, $backend:expr, $url:expr, $config:expr) => {{
             let (events_tx, events_rx) = sync_channel::<CameraEvent>(128);
-            let dispatcher = Dispatcher::new($config.buffer_frames, $backend, events_tx.clone());
+            if let Some(message) = $config.buffer_frames_warning() {
+                let _ = events_tx.try_send(CameraEvent::Warning {
+                    backend: $backend,
+                    message,
+                });
+            }
+            let dispatcher = Dispatcher::with_scheduling(
+                $config.buffer_frames_for($backend),
+                $backend,
+                events_tx.clone(),
+                $config.dispatch_thread,
+            );
             let frame_tx = dispatcher.sender();
+            let transform = $config.transform;
+            let stop_timeout = $config.stop_timeout;
+            let watchdog = $config.watchdog;
+            let load_guard = $config.load_guard;
+            let trigger = $config.trigger.clone();
+            let auto_lock = $config.auto_lock;
+            let format = CameraConfig::default().with_format_of(&$config);
+            #[cfg(feature = "audio")]
+            let wants_audio = $config.audio.is_some();
+            dispatcher.set_undistort($config.undistort);
+            dispatcher.set_exposure_check($config.exposure_check);
+            dispatcher.set_focus_check($config.focus_check);
+            dispatcher.set_frame_validation($config.frame_validation);
+            dispatcher.set_frame_checksums($config.frame_checksums);
+            dispatcher.set_looping($config.looping);
+            dispatcher.set_sidecar($config.sidecar.clone());
 
-            let driver =
-                <$driver_type>::open($url.as_ref().to_string(), $config, frame_tx, events_tx)?;
+            let mut driver = <$driver_type>::open(
+                $url.as_ref().to_string(),
+                $config,
+                frame_tx,
+                events_tx.clone(),
+            )?;
+            dispatcher.set_transform(CameraDriver::offload_transform(&mut driver, transform));
+            #[cfg(feature = "audio")]
+            let audio_rx = if wants_audio {
+                let (audio_tx, audio_rx) = sync_channel::<super::AudioFrame>(64);
+                CameraDriver::set_audio_sender(&mut driver, audio_tx)?;
+                Some(audio_rx)
+            } else {
+                None
+            };
 
-            Ok(Camera::new(Box::new(driver), dispatcher, events_rx))
+            let mut camera = Camera::new(Box::new(driver), dispatcher, events_tx, events_rx);
+            camera.set_stop_timeout(stop_timeout);
+            camera.set_watchdog(watchdog);
+            camera.set_load_guard(load_guard);
+            camera.set_trigger(trigger);
+            camera.set_auto_lock(auto_lock);
+            camera.set_format(&format);
+            #[cfg(feature = "audio")]
+            if let Some(rx) = audio_rx {
+                camera.set_audio_receiver(rx);
+            }
+            Ok(camera)
         }};
     }
 
+    // `csi:` ids only make sense to the Raspberry Pi driver.
+    #[cfg(all(feature = "rpi", target_os = "linux"))]
+    let config = match &config.device {
+        Some(device)
+            if config.backend.is_none()
+                && device.trim().starts_with(super::drivers::rpi::CSI_PREFIX) =>
+        {
+            config.with_backend(CameraBackend::Rpi)
+        },
+        _ => config,
+    };
+
+    // `shm:` ids name a ring published by another process, not a camera.
+    #[cfg(all(feature = "shm", unix))]
+    let config = match &config.device {
+        Some(device)
+            if config.backend.is_none() && device.trim().starts_with(super::SHM_PREFIX) =>
+        {
+            config.with_backend(CameraBackend::Shm)
+        },
+        _ => config,
+    };
+
+    // `ndi:` ids name a source on the network, for the NDI runtime.
+    #[cfg(all(
+        feature = "ndi",
+        any(target_os = "macos", target_os = "linux", target_os = "windows")
+    ))]
+    let config = match &config.device {
+        Some(device)
+            if config.backend.is_none() && device.trim().starts_with(super::NDI_PREFIX) =>
+        {
+            config.with_backend(CameraBackend::Ndi)
+        },
+        _ => config,
+    };
+
+    // Network streams and ONVIF cameras are opened by ffmpeg.
+    #[cfg(all(
+        feature = "ffmpeg",
+        any(target_os = "macos", target_os = "linux", target_os = "windows")
+    ))]
+    let config = match &config.device {
+        Some(device)
+            if config.backend.is_none() && super::drivers::ffmpeg::is_network_stream(device) =>
+        {
+            config.with_backend(CameraBackend::Ffmpeg)
+        },
+        _ => config,
+    };
+
+    // `mock:` ids are scripts for the test driver.
+    #[cfg(feature = "test-util")]
+    let config = match &config.device {
+        Some(device)
+            if config.backend.is_none()
+                && device.trim().starts_with(super::drivers::mock::MOCK_PREFIX) =>
+        {
+            config.with_backend(CameraBackend::Mock)
+        },
+        _ => config,
+    };
+
+    if let Some(backend) = config.backend {
+        return match backend {
+            #[cfg(all(feature = "web", target_arch = "wasm32"))]
+            CameraBackend::Web => init_camera!(
+                super::drivers::web::WebCameraDriver,
+                CameraBackend::Web,
+                input_url,
+                config
+            ),
+            #[cfg(all(feature = "android", target_os = "android"))]
+            CameraBackend::Android => init_camera!(
+                super::drivers::android::AndroidCameraDriver,
+                CameraBackend::Android,
+                input_url,
+                config
+            ),
+            #[cfg(all(feature = "rpi", target_os = "linux"))]
+            CameraBackend::Rpi => init_camera!(
+                super::drivers::rpi::RpiCameraDriver,
+                CameraBackend::Rpi,
+                input_url,
+                config
+            ),
+            #[cfg(all(feature = "pipewire", target_os = "linux"))]
+            CameraBackend::Pipewire => init_camera!(
+                super::drivers::pipewire::PipewireCameraDriver,
+                CameraBackend::Pipewire,
+                input_url,
+                config
+            ),
+            #[cfg(all(
+                feature = "gstreamer",
+                any(target_os = "macos", target_os = "linux", target_os = "windows")
+            ))]
+            CameraBackend::Gstreamer => init_camera!(
+                super::drivers::gstreamer::GstreamerCameraDriver,
+                CameraBackend::Gstreamer,
+                input_url,
+                config
+            ),
+            #[cfg(all(
+                feature = "ffmpeg",
+                any(target_os = "macos", target_os = "linux", target_os = "windows")
+            ))]
+            CameraBackend::Ffmpeg => init_camera!(
+                super::drivers::ffmpeg::FfmpegCameraDriver,
+                CameraBackend::Ffmpeg,
+                input_url,
+                config
+            ),
+            #[cfg(all(feature = "avf", any(target_os = "ios", target_os = "macos")))]
+            CameraBackend::Avf => init_camera!(
+                super::drivers::avf::AvfCameraDriver,
+                CameraBackend::Avf,
+                input_url,
+                config
+            ),
+            #[cfg(all(feature = "dshow", target_os = "windows"))]
+            CameraBackend::Dshow => init_camera!(
+                super::drivers::dshow::DshowCameraDriver,
+                CameraBackend::Dshow,
+                input_url,
+                config
+            ),
+            #[cfg(all(feature = "v4l2", target_os = "linux"))]
+            CameraBackend::V4l2 => init_camera!(
+                super::drivers::v4l2::V4l2CameraDriver,
+                CameraBackend::V4l2,
+                input_url,
+                config
+            ),
+            #[cfg(all(
+                feature = "ndi",
+                any(target_os = "macos", target_os = "linux", target_os = "windows")
+            ))]
+            CameraBackend::Ndi => init_camera!(
+                super::drivers::ndi::NdiCameraDriver,
+                CameraBackend::Ndi,
+                input_url,
+                config
+            ),
+            #[cfg(all(feature = "shm", unix))]
+            CameraBackend::Shm => init_camera!(
+                super::drivers::shm::ShmCameraDriver,
+                CameraBackend::Shm,
+                input_url,
+                config
+            ),
+            #[cfg(all(
+                feature = "uvc",
+                any(target_os = "macos", target_os = "linux", target_os = "windows")
+            ))]
+            CameraBackend::Uvc => init_camera!(
+                super::drivers::uvc::UvcCameraDriver,
+                CameraBackend::Uvc,
+                input_url,
+                config
+            ),
+            #[cfg(feature = "test-util")]
+            CameraBackend::Mock => init_camera!(
+                super::drivers::mock::MockCameraDriver,
+                CameraBackend::Mock,
+                input_url,
+                config
+            ),
+            #[allow(unreachable_patterns)]
+            other => Err(CameraError::unsupported(format!(
+                "the {other} backend isn't built into this binary"
+            ))),
+        };
+    }
+
     cfg_if::cfg_if! {
-        if #[cfg(all(feature = "android", target_os = "android"))] {
+        if #[cfg(all(feature = "web", target_arch = "wasm32"))] {
+            init_camera!(super::drivers::web::WebCameraDriver, CameraBackend::Web, input_url, config)
+        } else if #[cfg(all(feature = "android", target_os = "android"))] {
             init_camera!(super::drivers::android::AndroidCameraDriver, CameraBackend::Android, input_url, config)
+        } else if #[cfg(all(feature = "pipewire", target_os = "linux"))] {
+            init_camera!(super::drivers::pipewire::PipewireCameraDriver, CameraBackend::Pipewire, input_url, config)
+        } else if #[cfg(all(feature = "gstreamer", any(target_os = "macos", target_os = "linux", target_os = "windows")))] {
+            init_camera!(super::drivers::gstreamer::GstreamerCameraDriver, CameraBackend::Gstreamer, input_url, config)
         } else if #[cfg(all(feature = "ffmpeg", any(target_os = "macos", target_os = "linux", target_os = "windows")))] {
             init_camera!(super::drivers::ffmpeg::FfmpegCameraDriver, CameraBackend::Ffmpeg, input_url, config)
         } else if #[cfg(all(feature = "avf", any(target_os = "ios", target_os = "macos")))] {
@@ -38,6 +270,8 @@ pub fn open_camera(
             init_camera!(super::drivers::dshow::DshowCameraDriver, CameraBackend::Dshow, input_url, config)
         } else if #[cfg(all(feature = "v4l2", target_os = "linux"))] {
             init_camera!(super::drivers::v4l2::V4l2CameraDriver, CameraBackend::V4l2, input_url, config)
+        } else if #[cfg(all(feature = "uvc", any(target_os = "macos", target_os = "linux", target_os = "windows")))] {
+            init_camera!(super::drivers::uvc::UvcCameraDriver, CameraBackend::Uvc, input_url, config)
         } else {
             let _ = (input_url, config);
             Err(CameraError::NoDriver)
@@ -0,0 +1,242 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{CameraError, Frame, black_pixel};
+use core::{fmt, str::FromStr};
+
+/// Glyphs are 5×7 pixels in a 6×8 cell, before scaling.
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: usize = GLYPH_HEIGHT + 1;
+
+/// Frames this many rows tall get the font at 1×; taller ones scale it up
+/// in whole steps, so the text stays legible at any resolution.
+const ROWS_PER_SCALE: u32 = 240;
+
+/// A 5×7 bitmap font for ASCII `' '` to `'_'`, one byte per row with the
+/// leftmost pixel in bit 4. Lowercase letters render as uppercase, and
+/// anything else as `'?'`.
+const FONT: [[u8; GLYPH_HEIGHT]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // '!'
+    [0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a], // '#'
+    [0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04], // '$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // '%'
+    [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d], // '&'
+    [0x04, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // '('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // ')'
+    [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00], // '*'
+    [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08], // ','
+    [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c], // '.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // '/'
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e], // '0'
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e], // '1'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f], // '2'
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e], // '3'
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02], // '4'
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e], // '5'
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e], // '6'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // '7'
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e], // '8'
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c], // '9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00], // ':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08], // ';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // '<'
+    [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00], // '='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // '>'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+    [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e], // '@'
+    [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // 'A'
+    [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e], // 'B'
+    [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e], // 'C'
+    [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c], // 'D'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f], // 'E'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10], // 'F'
+    [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f], // 'G'
+    [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // 'H'
+    [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // 'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c], // 'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // 'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f], // 'L'
+    [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11], // 'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // 'N'
+    [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'O'
+    [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10], // 'P'
+    [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d], // 'Q'
+    [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11], // 'R'
+    [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e], // 'S'
+    [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // 'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04], // 'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a], // 'W'
+    [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11], // 'X'
+    [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04], // 'Y'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f], // 'Z'
+    [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e], // '['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // '\\'
+    [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e], // ']'
+    [0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f], // '_'
+];
+
+/// Something the overlay burns into each frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverlayField {
+    /// The capture time, in local time to the second.
+    Timestamp,
+    /// The overlay's label, usually the device id.
+    Label,
+}
+
+impl FromStr for OverlayField {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "timestamp" | "time" => Ok(OverlayField::Timestamp),
+            "label" | "device" => Ok(OverlayField::Label),
+            other => Err(CameraError::invalid_config(format!(
+                "invalid overlay field '{other}' (expected timestamp or label)"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for OverlayField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OverlayField::Timestamp => "timestamp",
+            OverlayField::Label => "label",
+        })
+    }
+}
+
+/// Text burned into the top-left corner of frames, one line per field
+/// followed by any custom text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Overlay {
+    pub fields: Vec<OverlayField>,
+    pub label: String,
+    pub text: Option<String>,
+}
+
+impl Overlay {
+    pub fn new(fields: impl IntoIterator<Item = OverlayField>) -> Self {
+        Self {
+            fields: fields.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// Whether the overlay would draw anything.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.text.as_deref().is_none_or(str::is_empty)
+    }
+
+    /// The lines to draw on `frame`.
+    pub fn lines(&self, frame: &Frame) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .fields
+            .iter()
+            .map(|field| match field {
+                OverlayField::Timestamp => format_timestamp(frame.timestamp_ns),
+                OverlayField::Label => self.label.clone(),
+            })
+            .collect();
+        lines.extend(self.text.iter().flat_map(|t| t.lines()).map(str::to_string));
+        lines
+    }
+
+    /// Draws the overlay into a copy of `frame`.
+    pub fn render(&self, frame: &Frame) -> Result<Frame, CameraError> {
+        frame.draw_text(&self.lines(frame).join("\n"))
+    }
+}
+
+impl Frame {
+    /// Draws `text`, one line per `\n`, white on a black box in the
+    /// top-left corner of a new, tightly packed frame. Text that doesn't fit
+    /// is cut off at the frame's edges.
+    pub fn draw_text(&self, text: &str) -> Result<Frame, CameraError> {
+        self.check_single_plane()?;
+        let (w, h) = (self.width as usize, self.height as usize);
+        let bpp = self.pixel_format.bytes_per_pixel() as usize;
+        let row_len = w * bpp;
+        let mut out = Vec::with_capacity(row_len * h);
+        for row in self.data.chunks(self.stride as usize).take(h) {
+            out.extend_from_slice(&row[..row_len]);
+        }
+
+        let lines: Vec<&str> = text.lines().collect();
+        let columns = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        if columns == 0 {
+            return Ok(self.derive(out, self.width, self.height));
+        }
+        let scale = (self.height / ROWS_PER_SCALE).max(1) as usize;
+        let (white, black) = ([0xff; 4], black_pixel(self.pixel_format));
+        let mut fill = |x0: usize, y0: usize, x1: usize, y1: usize, color: &[u8; 4]| {
+            for y in y0..y1.min(h) {
+                for x in x0..x1.min(w) {
+                    out[y * row_len + x * bpp..][..bpp].copy_from_slice(&color[..bpp]);
+                }
+            }
+        };
+
+        // The box leaves one scaled pixel of padding around the glyphs.
+        let (left, top) = (2 * scale, 2 * scale);
+        fill(
+            left,
+            top,
+            left + (columns * CELL_WIDTH + 1) * scale,
+            top + (lines.len() * CELL_HEIGHT + 1) * scale,
+            &black,
+        );
+        for (line_no, line) in lines.iter().enumerate() {
+            let y = top + (line_no * CELL_HEIGHT + 1) * scale;
+            for (col, c) in line.chars().enumerate() {
+                let x = left + (col * CELL_WIDTH + 1) * scale;
+                for (gy, bits) in glyph(c).iter().enumerate() {
+                    for gx in (0..GLYPH_WIDTH).filter(|gx| bits & (0x10 >> gx) != 0) {
+                        let (px, py) = (x + gx * scale, y + gy * scale);
+                        fill(px, py, px + scale, py + scale, &white);
+                    }
+                }
+            }
+        }
+
+        Ok(self.derive(out, self.width, self.height))
+    }
+}
+
+fn glyph(c: char) -> &'static [u8; GLYPH_HEIGHT] {
+    let c = c.to_ascii_uppercase();
+    match c {
+        ' '..='_' => &FONT[c as usize - ' ' as usize],
+        _ => &FONT['?' as usize - ' ' as usize],
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in the system time zone.
+fn format_timestamp(timestamp_ns: u64) -> String {
+    jiff::Timestamp::from_nanosecond(timestamp_ns as i128)
+        .map(|ts| {
+            ts.to_zoned(jiff::tz::TimeZone::system())
+                .strftime("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
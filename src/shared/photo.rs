@@ -0,0 +1,68 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::CameraError;
+use bytes::Bytes;
+use core::{fmt, str::FromStr};
+use std::path::Path;
+
+/// How `Camera::capture_photo` encodes a still.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PhotoFormat {
+    #[default]
+    Jpeg,
+    /// HEIF/HEIC, where the camera encodes it (Android 10 and later).
+    Heif,
+}
+
+impl PhotoFormat {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            PhotoFormat::Jpeg => "jpeg",
+            PhotoFormat::Heif => "heif",
+        }
+    }
+
+    pub const fn mime_type(self) -> &'static str {
+        match self {
+            PhotoFormat::Jpeg => "image/jpeg",
+            PhotoFormat::Heif => "image/heic",
+        }
+    }
+
+    /// The format a file name asks for: `.jpg`/`.jpeg` or `.heic`/`.heif`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for PhotoFormat {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Ok(PhotoFormat::Jpeg),
+            "heif" | "heic" => Ok(PhotoFormat::Heif),
+            other => Err(CameraError::invalid_config(format!(
+                "invalid photo format '{other}' (expected jpeg or heif)"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for PhotoFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An encoded still from the camera's photo pipeline, typically at the
+/// sensor's full resolution rather than the streaming format.
+#[derive(Clone, Debug)]
+pub struct Photo {
+    pub data: Bytes,
+    pub format: PhotoFormat,
+    pub width: u32,
+    pub height: u32,
+    /// UTC capture time in nanoseconds since the Unix epoch.
+    pub timestamp_ns: u64,
+}
@@ -0,0 +1,168 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{
+    CameraError, DebounceConfig, Debouncer, Frame, FrameSink, MaskShape, MaskStyle,
+};
+use std::{sync::Mutex, time::Instant};
+
+/// A complete set of frame sinks, with the masking and debounce in front of
+/// them, that can be swapped onto a running camera.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    label: String,
+    sinks: Vec<FrameSink>,
+    masks: Vec<MaskShape>,
+    mask_style: MaskStyle,
+    debounce: Option<DebounceConfig>,
+    /// Whether the sinks apply `debounce` themselves.
+    sink_debounce: bool,
+}
+
+impl core::fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("label", &self.label)
+            .field("sinks", &self.sinks.len())
+            .field("masks", &self.masks)
+            .field("mask_style", &self.mask_style)
+            .field("debounce", &self.debounce)
+            .field("sink_debounce", &self.sink_debounce)
+            .finish()
+    }
+}
+
+impl Pipeline {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_sink(mut self, sink: FrameSink) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn add_sink(&mut self, sink: FrameSink) {
+        self.sinks.push(sink);
+    }
+
+    /// Hides `masks` in every frame before any of the pipeline's sinks
+    /// sees it.
+    pub fn with_masks(mut self, masks: Vec<MaskShape>, style: MaskStyle) -> Self {
+        self.masks = masks;
+        self.mask_style = style;
+        self
+    }
+
+    /// Suppresses frames `config` finds too like the last one delivered (or
+    /// too soon after it), for all of the pipeline's sinks at once.
+    pub fn with_debounce(mut self, config: DebounceConfig) -> Self {
+        self.debounce = Some(config);
+        self.sink_debounce = false;
+        self
+    }
+
+    /// Names `config` as the pipeline's debounce, but leaves applying it to
+    /// the sinks, which see every frame: for sinks that hash on worker
+    /// threads of their own, after cropping or scaling, rather than on the
+    /// camera's dispatch thread.
+    pub fn with_sink_debounce(mut self, config: DebounceConfig) -> Self {
+        self.debounce = Some(config);
+        self.sink_debounce = true;
+        self
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn masks(&self) -> &[MaskShape] {
+        &self.masks
+    }
+
+    pub fn debounce(&self) -> Option<&DebounceConfig> {
+        self.debounce.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), CameraError> {
+        if self.sinks.is_empty() {
+            return Err(CameraError::invalid_config(format!(
+                "pipeline '{}' has no sinks",
+                self.label
+            )));
+        }
+        if let Some(MaskShape::Polygon(points)) = self
+            .masks
+            .iter()
+            .find(|m| matches!(m, MaskShape::Polygon(p) if p.len() < 3))
+        {
+            return Err(CameraError::invalid_config(format!(
+                "pipeline '{}' has a mask polygon of {} points",
+                self.label,
+                points.len()
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn into_stage(self) -> PipelineStage {
+        PipelineStage {
+            sinks: self.sinks,
+            masks: self.masks,
+            mask_style: self.mask_style,
+            debouncer: self
+                .debounce
+                .filter(|_| !self.sink_debounce)
+                .map(|config| Mutex::new(Debouncer::new(config))),
+        }
+    }
+}
+
+/// An applied `Pipeline`, as the dispatcher delivers to it.
+pub(crate) struct PipelineStage {
+    sinks: Vec<FrameSink>,
+    masks: Vec<MaskShape>,
+    mask_style: MaskStyle,
+    debouncer: Option<Mutex<Debouncer>>,
+}
+
+impl PipelineStage {
+    pub(crate) fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    /// Masks `frame`, then hands it to every sink unless the debounce
+    /// suppresses it. A frame that can't be masked reaches none of them.
+    pub(crate) fn deliver(&self, frame: Frame) {
+        let frame = if self.masks.is_empty() {
+            frame
+        } else {
+            match frame.mask(&self.masks, self.mask_style) {
+                Ok(masked) => masked,
+                Err(_) => return,
+            }
+        };
+        if let Some(debouncer) = &self.debouncer {
+            let mut debouncer = debouncer.lock().unwrap_or_else(|p| p.into_inner());
+            let hash = (debouncer.config().distance > 0)
+                .then(|| debouncer.hash(&frame))
+                .flatten();
+            if !debouncer.accept(hash.as_ref(), Instant::now()) {
+                return;
+            }
+        }
+        for sink in &self.sinks {
+            sink(frame.clone());
+        }
+    }
+}
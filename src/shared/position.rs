@@ -0,0 +1,50 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::CameraError;
+use core::{fmt, str::FromStr};
+
+/// Which way a camera faces, for selecting cameras on phones and tablets
+/// by position (`avf:front`, `web:back`) instead of by index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraPosition {
+    /// Facing the user (`AVCaptureDevicePositionFront`, `facingMode: user`).
+    Front,
+    /// Facing away from the user (`AVCaptureDevicePositionBack`,
+    /// `facingMode: environment`).
+    Back,
+}
+
+impl CameraPosition {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            CameraPosition::Front => "front",
+            CameraPosition::Back => "back",
+        }
+    }
+
+    /// Parses the position out of a device id like `avf:front`.
+    pub fn from_device_id(id: &str) -> Option<Self> {
+        let (_, rest) = id.trim().split_once(':')?;
+        rest.parse().ok()
+    }
+}
+
+impl fmt::Display for CameraPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CameraPosition {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "front" | "user" => Ok(CameraPosition::Front),
+            "back" | "rear" | "environment" => Ok(CameraPosition::Back),
+            other => Err(CameraError::invalid_config(format!(
+                "unknown camera position '{other}' (expected front or back)"
+            ))),
+        }
+    }
+}
@@ -0,0 +1,458 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{CameraError, Frame, PixelFormat, convert::unpack_1010102};
+use bytes::Bytes;
+use core::{fmt, str::FromStr};
+
+/// A region of interest in pixels, written `X,Y,WxH`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns whether the rectangle fits inside a `width`×`height` image.
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self.x.checked_add(self.width).is_some_and(|r| r <= width)
+            && self.y.checked_add(self.height).is_some_and(|b| b <= height)
+    }
+}
+
+impl FromStr for Rect {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            CameraError::invalid_config(format!(
+                "invalid region '{s}', use X,Y,WxH (e.g. 0,0,640x480)"
+            ))
+        };
+        let mut parts = s.trim().splitn(3, ',');
+        let (Some(x), Some(y), Some(size)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let size = size.replace('×', "x");
+        let (w, h) = size.split_once('x').ok_or_else(invalid)?;
+        let num = |v: &str| v.trim().parse::<u32>().map_err(|_| invalid());
+        let rect = Rect::new(num(x)?, num(y)?, num(w)?, num(h)?);
+        if rect.width == 0 || rect.height == 0 {
+            return Err(invalid());
+        }
+        Ok(rect)
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}x{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl Frame {
+    /// Copies the `rect` region into a new, tightly packed frame.
+    pub fn crop(&self, rect: Rect) -> Result<Frame, CameraError> {
+        self.check_single_plane()?;
+        if !rect.fits(self.width, self.height) {
+            return Err(CameraError::invalid_config(format!(
+                "crop region {rect} is outside the {}x{} frame",
+                self.width, self.height
+            )));
+        }
+
+        let bpp = self.pixel_format.bytes_per_pixel() as usize;
+        let stride = self.stride as usize;
+        let row_len = rect.width as usize * bpp;
+        let x_off = rect.x as usize * bpp;
+
+        let mut out = Vec::with_capacity(row_len * rect.height as usize);
+        for row in self
+            .data
+            .chunks(stride)
+            .skip(rect.y as usize)
+            .take(rect.height as usize)
+        {
+            // Whole-row slice copies compile down to memcpy.
+            out.extend_from_slice(&row[x_off..x_off + row_len]);
+        }
+
+        Ok(self.derive(out, rect.width, rect.height))
+    }
+
+    /// Resamples the frame to `width`×`height` (nearest neighbour) into a new,
+    /// tightly packed frame.
+    pub fn scale(&self, width: u32, height: u32) -> Result<Frame, CameraError> {
+        self.check_single_plane()?;
+        if width == 0 || height == 0 {
+            return Err(CameraError::invalid_config(
+                "scale target must be non-empty",
+            ));
+        }
+        if width == self.width && height == self.height {
+            return self.crop(Rect::new(0, 0, width, height));
+        }
+
+        let bpp = self.pixel_format.bytes_per_pixel() as usize;
+        let stride = self.stride as usize;
+        let row_len = width as usize * bpp;
+
+        // Precompute source byte offsets per destination column so the inner
+        // loop is a straight gather without divisions.
+        let columns: Vec<usize> = (0..width as u64)
+            .map(|x| ((x * self.width as u64) / width as u64) as usize * bpp)
+            .collect();
+
+        let mut out = vec![0u8; row_len * height as usize];
+        let mut prev_src_y = None;
+        for y in 0..height as usize {
+            let src_y = ((y as u64 * self.height as u64) / height as u64) as usize;
+            let dst_start = y * row_len;
+            if prev_src_y == Some(src_y) {
+                // Upscaled rows repeat the previous destination row verbatim.
+                out.copy_within(dst_start - row_len..dst_start, dst_start);
+                continue;
+            }
+            prev_src_y = Some(src_y);
+
+            let src = &self.data[src_y * stride..][..self.width as usize * bpp];
+            let dst = &mut out[dst_start..dst_start + row_len];
+            match bpp {
+                2 => gather::<2>(src, dst, &columns),
+                3 => gather::<3>(src, dst, &columns),
+                4 => gather::<4>(src, dst, &columns),
+                _ => {
+                    for (px, &sx) in dst.chunks_exact_mut(bpp).zip(&columns) {
+                        px.copy_from_slice(&src[sx..sx + bpp]);
+                    }
+                },
+            }
+        }
+
+        Ok(self.derive(out, width, height))
+    }
+
+    /// Shrinks the frame to `width`×`height` by averaging each box of source
+    /// pixels, into a new, tightly packed frame. It reads every pixel, so it
+    /// costs more than `scale`, but it averages sensor noise away instead
+    /// of sampling it. Only for 8-bit formats.
+    pub fn box_scale(&self, width: u32, height: u32) -> Result<Frame, CameraError> {
+        self.check_single_plane()?;
+        if self.pixel_format.bytes_per_sample() != 1 {
+            return Err(CameraError::unsupported(format!(
+                "{} frames have to be converted (e.g. with to_rgb8) before they're box scaled",
+                self.pixel_format.as_str()
+            )));
+        }
+        if width == 0 || height == 0 || width > self.width || height > self.height {
+            return Err(CameraError::invalid_config(format!(
+                "box scale target {width}x{height} must be non-empty and within {}x{}",
+                self.width, self.height
+            )));
+        }
+
+        let bpp = self.pixel_format.bytes_per_pixel() as usize;
+        let stride = self.stride as usize;
+        let row_len = width as usize * bpp;
+        let span = |i: usize, n: u32, of: u32| (i as u64 * of as u64 / n as u64) as usize;
+        // Byte offsets where each destination column's box starts, and
+        // where the last one ends.
+        let edges: Vec<usize> = (0..=width as usize)
+            .map(|x| span(x, width, self.width) * bpp)
+            .collect();
+
+        let mut sums = vec![0u32; row_len];
+        let mut out = vec![0u8; row_len * height as usize];
+        for (y, dst) in out.chunks_exact_mut(row_len).enumerate() {
+            let (top, bottom) = (
+                span(y, height, self.height),
+                span(y + 1, height, self.height),
+            );
+            sums.fill(0);
+            for src_y in top..bottom {
+                let src = &self.data[src_y * stride..][..self.width as usize * bpp];
+                match bpp {
+                    3 => add_boxes::<3>(src, &mut sums, &edges),
+                    _ => add_boxes::<4>(src, &mut sums, &edges),
+                }
+            }
+            for (x, (px, sum)) in dst
+                .chunks_exact_mut(bpp)
+                .zip(sums.chunks_exact(bpp))
+                .enumerate()
+            {
+                let area = ((edges[x + 1] - edges[x]) / bpp * (bottom - top)) as u32;
+                for (d, &s) in px.iter_mut().zip(sum) {
+                    *d = ((s + area / 2) / area) as u8;
+                }
+            }
+        }
+
+        Ok(self.derive(out, width, height))
+    }
+
+    pub(crate) fn derive(&self, data: Vec<u8>, width: u32, height: u32) -> Frame {
+        let stride = width * self.pixel_format.bytes_per_pixel();
+        let mut frame = Frame::new(Bytes::from(data), width, height, stride, self.pixel_format)
+            .with_stream(self.stream)
+            .with_colorimetry(self.colorimetry)
+            .with_timestamp_ns(self.timestamp_ns);
+        frame.monotonic_ns = self.monotonic_ns;
+        frame.metadata = self.metadata.clone();
+        frame
+    }
+
+    /// Like `check_valid`, for operations that treat the frame as one
+    /// plane of whole pixels.
+    pub(crate) fn check_single_plane(&self) -> Result<(), CameraError> {
+        self.check_valid()?;
+        if self.pixel_format.is_planar() {
+            return Err(CameraError::unsupported(format!(
+                "{} frames have to be converted (e.g. with to_rgb8) before they're processed",
+                self.pixel_format.as_str()
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_valid(&self) -> Result<(), CameraError> {
+        if self.validate() {
+            Ok(())
+        } else {
+            Err(CameraError::other(format!(
+                "malformed {}x{} frame (stride {}, {} bytes)",
+                self.width,
+                self.height,
+                self.stride,
+                self.data.len()
+            )))
+        }
+    }
+}
+
+/// Clockwise rotation applied to captured frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Rotation {
+    pub const fn degrees(self) -> u32 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Cw90 => 90,
+            Rotation::Cw180 => 180,
+            Rotation::Cw270 => 270,
+        }
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().trim_end_matches('°') {
+            "0" | "360" => Ok(Rotation::None),
+            "90" | "-270" => Ok(Rotation::Cw90),
+            "180" | "-180" => Ok(Rotation::Cw180),
+            "270" | "-90" => Ok(Rotation::Cw270),
+            other => Err(CameraError::invalid_config(format!(
+                "invalid rotation '{other}' (expected 0, 90, 180 or 270)"
+            ))),
+        }
+    }
+}
+
+/// Mirroring applied to captured frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Flip {
+    #[default]
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+impl FromStr for Flip {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Flip::None),
+            "h" | "horizontal" => Ok(Flip::Horizontal),
+            "v" | "vertical" => Ok(Flip::Vertical),
+            "hv" | "vh" | "both" => Ok(Flip::Both),
+            other => Err(CameraError::invalid_config(format!(
+                "invalid flip '{other}' (expected h, v or hv)"
+            ))),
+        }
+    }
+}
+
+/// Orientation correction for mounted cameras: the flip is applied first,
+/// then the rotation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameTransform {
+    pub rotation: Rotation,
+    pub flip: Flip,
+}
+
+impl FrameTransform {
+    pub const IDENTITY: Self = Self {
+        rotation: Rotation::None,
+        flip: Flip::None,
+    };
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    pub fn apply(&self, frame: Frame) -> Result<Frame, CameraError> {
+        if self.is_identity() {
+            return Ok(frame);
+        }
+        // A 180° turn is the same as mirroring both ways, so fold it into the flip.
+        let (flip, rotation) = match (self.flip, self.rotation) {
+            (flip, Rotation::Cw180) => (flip.compose(Flip::Both), Rotation::None),
+            other => other,
+        };
+        let frame = if flip == Flip::None {
+            frame
+        } else {
+            frame.flip(flip)?
+        };
+        frame.rotate(rotation)
+    }
+}
+
+impl Flip {
+    pub(crate) const fn compose(self, other: Flip) -> Flip {
+        let h = matches!(self, Flip::Horizontal | Flip::Both)
+            != matches!(other, Flip::Horizontal | Flip::Both);
+        let v = matches!(self, Flip::Vertical | Flip::Both)
+            != matches!(other, Flip::Vertical | Flip::Both);
+        match (h, v) {
+            (false, false) => Flip::None,
+            (true, false) => Flip::Horizontal,
+            (false, true) => Flip::Vertical,
+            (true, true) => Flip::Both,
+        }
+    }
+}
+
+impl Frame {
+    /// Mirrors the frame into a new, tightly packed frame.
+    pub fn flip(&self, flip: Flip) -> Result<Frame, CameraError> {
+        self.check_single_plane()?;
+        let bpp = self.pixel_format.bytes_per_pixel() as usize;
+        let stride = self.stride as usize;
+        let row_len = self.width as usize * bpp;
+        let height = self.height as usize;
+
+        let mut out = vec![0u8; row_len * height];
+        for (y, dst) in out.chunks_exact_mut(row_len).enumerate() {
+            let src_y = match flip {
+                Flip::Vertical | Flip::Both => height - 1 - y,
+                _ => y,
+            };
+            let src = &self.data[src_y * stride..][..row_len];
+            match flip {
+                Flip::Horizontal | Flip::Both => {
+                    for (px, spx) in dst.chunks_exact_mut(bpp).zip(src.chunks_exact(bpp).rev()) {
+                        px.copy_from_slice(spx);
+                    }
+                },
+                _ => dst.copy_from_slice(src),
+            }
+        }
+
+        Ok(self.derive(out, self.width, self.height))
+    }
+
+    /// Rotates the frame clockwise into a new, tightly packed frame.
+    pub fn rotate(&self, rotation: Rotation) -> Result<Frame, CameraError> {
+        match rotation {
+            Rotation::None => return Ok(self.clone()),
+            Rotation::Cw180 => return self.flip(Flip::Both),
+            Rotation::Cw90 | Rotation::Cw270 => {},
+        }
+        self.check_single_plane()?;
+
+        let bpp = self.pixel_format.bytes_per_pixel() as usize;
+        let stride = self.stride as usize;
+        let (w, h) = (self.width as usize, self.height as usize);
+        // Rotated dimensions: the new row length spans the old height.
+        let dst_row = h * bpp;
+
+        let mut out = vec![0u8; w * h * bpp];
+        for y in 0..h {
+            let src = &self.data[y * stride..][..w * bpp];
+            for (x, spx) in src.chunks_exact(bpp).enumerate() {
+                let (dx, dy) = match rotation {
+                    Rotation::Cw90 => (h - 1 - y, x),
+                    _ => (y, w - 1 - x),
+                };
+                let at = dy * dst_row + dx * bpp;
+                out[at..at + bpp].copy_from_slice(spx);
+            }
+        }
+
+        Ok(self.derive(out, self.height, self.width))
+    }
+}
+
+/// BT.601 luma of one pixel in `format`, in 8-bit fixed point. For 16-bit
+/// formats (and P010's luma plane) this is the high byte, which is what
+/// motion and exposure need.
+#[inline]
+pub(crate) fn luma(px: &[u8], format: PixelFormat) -> u8 {
+    let (r, g, b) = match format {
+        PixelFormat::Rgb8 | PixelFormat::Rgba8 => (px[0], px[1], px[2]),
+        PixelFormat::Bgra8 => (px[2], px[1], px[0]),
+        PixelFormat::Gray16 | PixelFormat::Z16 | PixelFormat::P010 => return px[1],
+        PixelFormat::Rgba1010102 => {
+            let [r, g, b, _] = unpack_1010102(px);
+            (r, g, b)
+        },
+    };
+    ((77 * r as u32 + 150 * g as u32 + 29 * b as u32) >> 8) as u8
+}
+
+/// Copies `columns` pixels of `N` bytes from `src` into `dst`; a constant
+/// pixel size turns each copy into a single move instead of a `memcpy` call.
+#[inline]
+/// Adds one source row into the per-box channel sums; `edges` holds the
+/// byte offset of each box, as `box_scale` computes them.
+fn add_boxes<const N: usize>(src: &[u8], sums: &mut [u32], edges: &[usize]) {
+    for (sum, edge) in sums.chunks_exact_mut(N).zip(edges.windows(2)) {
+        let sum: &mut [u32; N] = sum.try_into().unwrap();
+        for px in src[edge[0]..edge[1]].chunks_exact(N) {
+            for (s, &v) in sum.iter_mut().zip(px) {
+                *s += v as u32;
+            }
+        }
+    }
+}
+
+fn gather<const N: usize>(src: &[u8], dst: &mut [u8], columns: &[usize]) {
+    for (px, &sx) in dst.chunks_exact_mut(N).zip(columns) {
+        let px: &mut [u8; N] = px.try_into().unwrap();
+        *px = src[sx..sx + N].try_into().unwrap();
+    }
+}
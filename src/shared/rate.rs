@@ -0,0 +1,119 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{CameraError, Frame, FrameSink};
+use std::sync::{Arc, Mutex};
+
+/// How many of the camera's frames a sink receives: every `stride`th frame,
+/// and of those at most `max_fps` a second by capture time. Sinks sharing
+/// a camera can each have their own, e.g. a recording at 30 fps beside
+/// records at 1 fps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SinkRate {
+    pub max_fps: Option<f64>,
+    pub stride: u32,
+}
+
+impl Default for SinkRate {
+    fn default() -> Self {
+        Self {
+            max_fps: None,
+            stride: 1,
+        }
+    }
+}
+
+impl SinkRate {
+    /// At most `fps` frames a second.
+    pub fn fps(fps: f64) -> Self {
+        Self::default().with_max_fps(fps)
+    }
+
+    /// Every `stride`th frame.
+    pub fn every(stride: u32) -> Self {
+        Self::default().with_stride(stride)
+    }
+
+    pub fn with_max_fps(mut self, fps: f64) -> Self {
+        self.max_fps = Some(fps);
+        self
+    }
+
+    pub fn with_stride(mut self, stride: u32) -> Self {
+        self.stride = stride;
+        self
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_fps.is_none() && self.stride <= 1
+    }
+
+    pub fn validate(&self) -> Result<(), CameraError> {
+        if let Some(fps) = self.max_fps
+            && !(fps.is_finite() && fps > 0.0)
+        {
+            return Err(CameraError::invalid_config(format!(
+                "invalid sink rate {fps} fps (expected a positive number)"
+            )));
+        }
+        if self.stride == 0 {
+            return Err(CameraError::invalid_config(
+                "invalid sink stride 0 (expected 1 or more)",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Wraps `sink` so it only receives the frames this rate admits.
+    pub fn limit(self, sink: FrameSink) -> FrameSink {
+        if self.is_unlimited() {
+            return sink;
+        }
+        let interval_ns = self.max_fps.map_or(0, |fps| (1e9 / fps) as u64);
+        let state = Mutex::new(RateState::default());
+        let stride = self.stride.max(1) as u64;
+        Arc::new(move |frame: Frame| {
+            let admitted = state.lock().unwrap_or_else(|p| p.into_inner()).admit(
+                frame.monotonic_ns,
+                stride,
+                interval_ns,
+            );
+            if admitted {
+                sink(frame);
+            }
+        })
+    }
+}
+
+#[derive(Default)]
+struct RateState {
+    seen: u64,
+    /// When the next frame is due, on the monotonic clock.
+    due_ns: Option<u64>,
+}
+
+impl RateState {
+    fn admit(&mut self, now_ns: u64, stride: u64, interval_ns: u64) -> bool {
+        let index = self.seen;
+        self.seen += 1;
+        if !index.is_multiple_of(stride) {
+            return false;
+        }
+        if interval_ns == 0 {
+            return true;
+        }
+        // Frames arrive with some jitter around the interval; allow an
+        // eighth of it so a 30 fps sink on a 30 fps camera keeps every frame.
+        if let Some(due_ns) = self.due_ns
+            && now_ns + interval_ns / 8 < due_ns
+        {
+            return false;
+        }
+        // Keep to the schedule, so the rate averages out to `max_fps`, unless
+        // capture fell behind it by more than a frame.
+        self.due_ns = Some(match self.due_ns {
+            Some(due_ns) if now_ns < due_ns + interval_ns => due_ns + interval_ns,
+            _ => now_ns + interval_ns,
+        });
+        true
+    }
+}
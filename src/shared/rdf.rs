@@ -0,0 +1,321 @@
+// This is free and unencumbered software released into the public domain.
+
+//! RDF serializations of compact JSON-LD records, for consumers loading
+//! straight into a triple store.
+//!
+//! Records carry no term definitions: a `@context` IRI (e.g. schema.org)
+//! names the vocabulary their types and properties expand against, and
+//! records without one use KNOW's. Strings become plain literals, numbers
+//! and booleans XSD-typed ones, arrays one statement per element, and
+//! nested objects blank nodes. Record ids that aren't absolute IRIs, such as
+//! those of devices named by a bare index, become blank nodes.
+
+use crate::shared::CameraError;
+use core::{fmt, fmt::Write as _};
+use serde_json::{Map, Value};
+
+/// The vocabulary of records without a `@context`.
+pub const KNOW_VOCAB: &str = "https://know.dev/";
+
+const SCHEMA_VOCAB: &str = "https://schema.org/";
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RdfFormat {
+    /// One statement per line, in the default graph.
+    #[default]
+    NQuads,
+    /// One self-contained Turtle document per record, so records concatenate.
+    Turtle,
+}
+
+/// Serializes the JSON-LD node object `record` in `format`.
+pub fn to_rdf(record: &Value, format: RdfFormat) -> Result<String, CameraError> {
+    let node = record
+        .as_object()
+        .ok_or_else(|| CameraError::other("RDF records must be JSON objects"))?;
+    let vocab = match node.get("@context").and_then(Value::as_str) {
+        Some(context) if context.ends_with(['/', '#']) => context.to_string(),
+        Some(context) => format!("{context}/"),
+        None => KNOW_VOCAB.to_string(),
+    };
+    let subject = match node.get("@id").and_then(Value::as_str) {
+        Some(id) if is_absolute_iri(id) => Term::Iri(id.to_string()),
+        Some(id) => Term::Blank(blank_label(id)),
+        None => Term::Blank("record".into()),
+    };
+    let mut out = String::new();
+    match format {
+        RdfFormat::NQuads => {
+            let mut writer = NQuads {
+                vocab: &vocab,
+                out: &mut out,
+                blanks: 0,
+            };
+            writer.node(&subject, node);
+        },
+        RdfFormat::Turtle => {
+            let writer = Turtle {
+                vocab: &vocab,
+                prefix: match vocab.as_str() {
+                    KNOW_VOCAB => Some("know"),
+                    SCHEMA_VOCAB => Some("schema"),
+                    _ => None,
+                },
+            };
+            writer.document(&subject, node, &mut out);
+        },
+    }
+    Ok(out)
+}
+
+enum Term {
+    Iri(String),
+    Blank(String),
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Iri(iri) => write_iri(f, iri),
+            Term::Blank(label) => write!(f, "_:{label}"),
+        }
+    }
+}
+
+/// The node's `@type`s and its properties, with values other than `null`.
+fn statements(node: &Map<String, Value>) -> (Vec<&str>, Vec<(&str, Vec<&Value>)>) {
+    let types = match node.get("@type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let properties = node
+        .iter()
+        .filter(|(key, _)| !key.starts_with('@'))
+        .map(|(key, value)| {
+            let values = match value {
+                Value::Array(values) => values.iter().filter(|v| !v.is_null()).collect(),
+                Value::Null => Vec::new(),
+                value => vec![value],
+            };
+            (key.as_str(), values)
+        })
+        .filter(|(_, values)| !values.is_empty())
+        .collect();
+    (types, properties)
+}
+
+struct NQuads<'a> {
+    vocab: &'a str,
+    out: &'a mut String,
+    blanks: usize,
+}
+
+impl NQuads<'_> {
+    fn node(&mut self, subject: &Term, node: &Map<String, Value>) {
+        let (types, properties) = statements(node);
+        for t in types {
+            let _ = writeln!(
+                self.out,
+                "{subject} <{RDF_TYPE}> {} .",
+                Term::Iri(expand(self.vocab, t))
+            );
+        }
+        for (key, values) in properties {
+            let predicate = Term::Iri(expand(self.vocab, key));
+            for value in values {
+                match value {
+                    Value::Object(object) => {
+                        let object_term = self.nested(subject);
+                        let _ = writeln!(self.out, "{subject} {predicate} {object_term} .");
+                        self.node(&object_term, object);
+                    },
+                    value => {
+                        let _ = write!(self.out, "{subject} {predicate} ");
+                        let _ = write_literal(self.out, value, true);
+                        self.out.push_str(" .\n");
+                    },
+                }
+            }
+        }
+    }
+
+    /// A blank node for an object nested in `parent`, labelled after the
+    /// parent so labels stay unique when records are concatenated.
+    fn nested(&mut self, parent: &Term) -> Term {
+        self.blanks += 1;
+        let parent = match parent {
+            Term::Iri(iri) => blank_label(iri),
+            Term::Blank(label) => label.clone(),
+        };
+        Term::Blank(format!("{parent}-b{}", self.blanks))
+    }
+}
+
+struct Turtle<'a> {
+    vocab: &'a str,
+    /// The prefix the vocabulary is abbreviated with, if it's a known one.
+    prefix: Option<&'a str>,
+}
+
+impl Turtle<'_> {
+    fn document(&self, subject: &Term, node: &Map<String, Value>, out: &mut String) {
+        if let Some(prefix) = self.prefix {
+            let _ = writeln!(out, "@prefix {prefix}: <{}> .\n", self.vocab);
+        }
+        let _ = write!(out, "{subject}");
+        self.properties(node, out, 1);
+        out.push_str(" .\n\n");
+    }
+
+    /// Writes a predicate-object list, one predicate per line at `depth`;
+    /// nested lists start on a line of their own.
+    fn properties(&self, node: &Map<String, Value>, out: &mut String, depth: usize) {
+        let (types, properties) = statements(node);
+        let indent = "    ".repeat(depth);
+        let mut first = true;
+        let mut separator = |out: &mut String| {
+            match (first, depth) {
+                (true, 1) => out.push(' '),
+                (true, _) => out.push('\n'),
+                (false, _) => out.push_str(" ;\n"),
+            }
+            if !first || depth > 1 {
+                out.push_str(&indent);
+            }
+            first = false;
+        };
+        if !types.is_empty() {
+            separator(out);
+            out.push('a');
+            for (i, t) in types.into_iter().enumerate() {
+                out.push_str(if i == 0 { " " } else { ", " });
+                self.name(t, out);
+            }
+        }
+        for (key, values) in properties {
+            separator(out);
+            self.name(key, out);
+            for (i, value) in values.into_iter().enumerate() {
+                out.push_str(if i == 0 { " " } else { ", " });
+                match value {
+                    Value::Object(object) => {
+                        out.push('[');
+                        self.properties(object, out, depth + 1);
+                        let _ = write!(out, "\n{indent}]");
+                    },
+                    value => {
+                        let _ = write_literal(out, value, false);
+                    },
+                }
+            }
+        }
+    }
+
+    /// Writes a term of the vocabulary, as a prefixed name where possible.
+    fn name(&self, term: &str, out: &mut String) {
+        match self.prefix {
+            Some(prefix) if is_local_name(term) => {
+                let _ = write!(out, "{prefix}:{term}");
+            },
+            _ => {
+                let _ = write!(out, "{}", Term::Iri(expand(self.vocab, term)));
+            },
+        }
+    }
+}
+
+/// Writes a literal; N-Quads spells out every datatype, while Turtle writes
+/// numbers and booleans bare.
+fn write_literal(out: &mut String, value: &Value, typed: bool) -> fmt::Result {
+    let (lexical, datatype) = match value {
+        Value::Bool(b) => (b.to_string(), "boolean"),
+        Value::Number(n) if n.is_i64() || n.is_u64() => (n.to_string(), "integer"),
+        Value::Number(n) => (format!("{:E}", n.as_f64().unwrap_or_default()), "double"),
+        Value::String(s) => {
+            out.push('"');
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if c.is_control() => write!(out, "\\u{:04X}", c as u32)?,
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            return Ok(());
+        },
+        _ => return Ok(()),
+    };
+    if typed {
+        write!(out, "\"{lexical}\"^^<{XSD}{datatype}>")
+    } else {
+        out.push_str(&lexical);
+        Ok(())
+    }
+}
+
+/// Expands a type or property name against `vocab`, unless it's already
+/// an absolute IRI.
+fn expand(vocab: &str, term: &str) -> String {
+    if is_absolute_iri(term) {
+        term.to_string()
+    } else {
+        format!("{vocab}{term}")
+    }
+}
+
+/// Writes `<iri>`, percent-encoding the characters IRIs can't contain.
+fn write_iri(f: &mut impl fmt::Write, iri: &str) -> fmt::Result {
+    f.write_char('<')?;
+    for c in iri.chars() {
+        match c {
+            '\0'..=' ' | '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\' => {
+                write!(f, "%{:02X}", c as u32)?
+            },
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('>')
+}
+
+/// Returns whether `s` starts with a URI scheme, as `file:` or `https:`.
+fn is_absolute_iri(s: &str) -> bool {
+    s.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    })
+}
+
+/// Returns whether `s` can follow a prefix in a Turtle prefixed name.
+fn is_local_name(s: &str) -> bool {
+    !s.is_empty()
+        && !s.ends_with(['.', '-'])
+        && !s.starts_with(['.', '-'])
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c))
+}
+
+/// A blank node label for `id`: its letters and digits, with every run of
+/// anything else as `_`.
+fn blank_label(id: &str) -> String {
+    let mut label = String::with_capacity(id.len());
+    for c in id.chars() {
+        if c.is_ascii_alphanumeric() {
+            label.push(c);
+        } else if !label.ends_with('_') {
+            label.push('_');
+        }
+    }
+    if label.is_empty() {
+        label.push_str("record");
+    }
+    label
+}
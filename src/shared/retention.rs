@@ -0,0 +1,244 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Keeping the directories sinks fill, with saved frames or finished
+//! recordings, within an age and size budget, oldest files first, so an
+//! unattended recorder doesn't fill the disk.
+
+use crate::shared::CameraError;
+use core::time::Duration;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
+};
+
+/// Free space below which `Retention::sweep` reports the file system as
+/// low on space, by default.
+pub const DEFAULT_MIN_FREE: u64 = 1_000_000_000;
+
+/// How much a `Retention` keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Delete the oldest files while all of them together take more.
+    pub max_bytes: Option<u64>,
+    /// Delete files last modified longer ago than this.
+    pub max_age: Option<Duration>,
+    /// Report a directory whose file system has less free space than this.
+    pub min_free: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_bytes: None,
+            max_age: None,
+            min_free: DEFAULT_MIN_FREE,
+        }
+    }
+}
+
+/// Files in one directory that a sink wrote, picked by name.
+struct FileSet {
+    dir: PathBuf,
+    matches: Box<dyn Fn(&str) -> bool + Send + Sync>,
+    /// Whether low space was reported and hasn't recovered since.
+    low: AtomicBool,
+}
+
+/// Applies a `RetentionPolicy` to the files sinks wrote.
+pub struct Retention {
+    policy: RetentionPolicy,
+    sets: Vec<FileSet>,
+}
+
+impl core::fmt::Debug for Retention {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Retention")
+            .field("policy", &self.policy)
+            .field(
+                "dirs",
+                &self.sets.iter().map(|s| &s.dir).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// What one `Retention::sweep` did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RetentionSweep {
+    pub deleted: usize,
+    pub freed_bytes: u64,
+    /// Bytes the remaining files take.
+    pub retained_bytes: u64,
+    /// Files that were due for deletion but couldn't be deleted.
+    pub failed: usize,
+    /// Directories whose file system fell below `min_free` since the last
+    /// sweep, with the bytes still free; each is reported once until it
+    /// has enough space again.
+    pub low_space: Vec<(PathBuf, u64)>,
+}
+
+impl Retention {
+    pub fn new(policy: RetentionPolicy) -> Self {
+        Self {
+            policy,
+            sets: Vec::new(),
+        }
+    }
+
+    /// Also manages the files in `dir` whose names `matches` accepts; the
+    /// rest of the directory is left alone.
+    pub fn with_files(
+        mut self,
+        dir: impl Into<PathBuf>,
+        matches: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.sets.push(FileSet {
+            dir: dir.into(),
+            matches: Box::new(matches),
+            low: AtomicBool::new(false),
+        });
+        self
+    }
+
+    pub fn policy(&self) -> &RetentionPolicy {
+        &self.policy
+    }
+
+    /// Deletes expired files, then the oldest until the rest fit
+    /// `max_bytes`, and checks the free space. Directories that don't
+    /// exist yet have nothing to delete.
+    pub fn sweep(&self) -> Result<RetentionSweep, CameraError> {
+        let now = SystemTime::now();
+        let mut files = Vec::new();
+        for set in &self.sets {
+            let entries = match std::fs::read_dir(&set.dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(CameraError::driver("listing files to retain", e)),
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                if !name.to_str().is_some_and(|name| (set.matches)(name)) {
+                    continue;
+                }
+                let Ok(meta) = entry.metadata() else {
+                    continue;
+                };
+                if meta.is_file() {
+                    files.push((meta.modified().unwrap_or(now), meta.len(), entry.path()));
+                }
+            }
+        }
+        files.sort();
+
+        let mut sweep = RetentionSweep {
+            retained_bytes: files.iter().map(|(_, len, _)| len).sum(),
+            ..Default::default()
+        };
+        for (modified, len, path) in files {
+            let expired = self
+                .policy
+                .max_age
+                .is_some_and(|age| now.duration_since(modified).unwrap_or_default() > age);
+            let over = self
+                .policy
+                .max_bytes
+                .is_some_and(|max| sweep.retained_bytes > max);
+            // Later files are newer, and the total only shrinks.
+            if !expired && !over {
+                break;
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => {
+                    sweep.deleted += 1;
+                    sweep.freed_bytes += len;
+                    sweep.retained_bytes -= len;
+                },
+                Err(e) if e.kind() == io::ErrorKind::NotFound => sweep.retained_bytes -= len,
+                Err(_) => sweep.failed += 1,
+            }
+        }
+
+        for set in &self.sets {
+            let Ok(available) = available_space(&set.dir) else {
+                continue;
+            };
+            let low = available < self.policy.min_free;
+            if set.low.swap(low, Ordering::Relaxed) != low && low {
+                sweep.low_space.push((set.dir.clone(), available));
+            }
+        }
+        Ok(sweep)
+    }
+}
+
+/// Bytes an unprivileged user can still write to the file system holding
+/// `path`, or its closest existing parent.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let path = path
+        .ancestors()
+        .find(|p| p.exists())
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    imp::available_space(path)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::{ffi::CString, io, os::unix::ffi::OsStrExt, path::Path};
+
+    pub fn available_space(path: &Path) -> io::Result<u64> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let mut stats: libc::statvfs = unsafe { core::mem::zeroed() };
+        // SAFETY: `path` is NUL-terminated and `stats` is writable.
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        #[allow(clippy::unnecessary_cast)]
+        Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{io, os::windows::ffi::OsStrExt, path::Path};
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            available: *mut u64,
+            total: *mut u64,
+            free: *mut u64,
+        ) -> i32;
+    }
+
+    pub fn available_space(path: &Path) -> io::Result<u64> {
+        let path: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+        let mut available = 0u64;
+        // SAFETY: `path` is NUL-terminated; the other counts are optional.
+        let ok = unsafe {
+            GetDiskFreeSpaceExW(
+                path.as_ptr(),
+                &mut available,
+                core::ptr::null_mut(),
+                core::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(available)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use std::{io, path::Path};
+
+    pub fn available_space(_path: &Path) -> io::Result<u64> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
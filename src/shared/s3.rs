@@ -0,0 +1,454 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Uploading files to S3 and S3-compatible object storage (MinIO, R2,
+//! Ceph and the like), so capture nodes can ship what they save without a
+//! second agent.
+//!
+//! Objects are written with a single signed (SigV4) `PUT`, streamed from
+//! the file, which S3 accepts up to 5 GiB. Uploads that fail on the way,
+//! or that the service is too busy for (5xx, 408, 429), are retried with
+//! exponential backoff; others, such as a denied request, fail at once.
+
+use crate::shared::{
+    CameraError,
+    http::{self, MAX_BACKOFF, Url},
+};
+use core::time::Duration;
+use ring::{digest, hmac};
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Where and how `S3Client` uploads.
+#[derive(Clone, Debug)]
+pub struct S3Config {
+    pub bucket: String,
+    /// Prepended to each object name, e.g. `camera/`.
+    pub prefix: String,
+    /// The service's URL, e.g. `http://minio:9000`, or AWS itself.
+    pub endpoint: Option<String>,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+    /// Attempts after the first that a failed upload gets.
+    pub retries: u32,
+    /// The wait before the first retry, doubled for each one after it.
+    pub backoff: Duration,
+    /// A PEM file of certificates to trust instead of the system's.
+    pub ca: Option<PathBuf>,
+}
+
+impl S3Config {
+    pub fn new(bucket: impl Into<String>, prefix: impl Into<String>) -> Self {
+        Self {
+            bucket: bucket.into(),
+            prefix: prefix.into(),
+            endpoint: None,
+            region: "us-east-1".into(),
+            access_key: String::new(),
+            secret_key: String::new(),
+            session_token: None,
+            retries: 5,
+            backoff: Duration::from_secs(1),
+            ca: None,
+        }
+    }
+
+    /// Takes the credentials, region and endpoint from the variables the
+    /// AWS tools read: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`,
+    /// `AWS_SESSION_TOKEN`, `AWS_REGION` (or `AWS_DEFAULT_REGION`) and
+    /// `AWS_ENDPOINT_URL_S3` (or `AWS_ENDPOINT_URL`).
+    pub fn from_env(mut self) -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        if let Some(key) = var("AWS_ACCESS_KEY_ID") {
+            self.access_key = key;
+        }
+        if let Some(secret) = var("AWS_SECRET_ACCESS_KEY") {
+            self.secret_key = secret;
+        }
+        if let Some(token) = var("AWS_SESSION_TOKEN") {
+            self.session_token = Some(token);
+        }
+        if let Some(region) = var("AWS_REGION").or_else(|| var("AWS_DEFAULT_REGION")) {
+            self.region = region;
+        }
+        if let Some(endpoint) = var("AWS_ENDPOINT_URL_S3").or_else(|| var("AWS_ENDPOINT_URL")) {
+            self.endpoint = Some(endpoint);
+        }
+        self
+    }
+
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = region.into();
+        self
+    }
+
+    pub fn with_credentials(
+        mut self,
+        access_key: impl Into<String>,
+        secret_key: impl Into<String>,
+    ) -> Self {
+        self.access_key = access_key.into();
+        self.secret_key = secret_key.into();
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_ca(mut self, ca: impl Into<PathBuf>) -> Self {
+        self.ca = Some(ca.into());
+        self
+    }
+}
+
+/// An object `S3Client` uploaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct S3Object {
+    pub bucket: String,
+    pub key: String,
+    pub size: u64,
+    pub etag: Option<String>,
+    /// How many attempts the upload took.
+    pub attempts: u32,
+}
+
+/// The SigV4 signature of requests, as AWS specifies it.
+#[derive(Clone, Debug)]
+pub struct SigV4<'a> {
+    pub access_key: &'a str,
+    pub secret_key: &'a str,
+    pub region: &'a str,
+    pub service: &'a str,
+}
+
+impl SigV4<'_> {
+    /// The `Authorization` header of a request sent at `amz_date`
+    /// (`20240501T100000Z`) with the given headers, all of which are
+    /// signed, and the hex SHA-256 of its payload.
+    pub fn authorization(
+        &self,
+        method: &str,
+        uri: &str,
+        query: &str,
+        headers: &[(&str, &str)],
+        payload_sha256: &str,
+        amz_date: &str,
+    ) -> String {
+        let mut headers: Vec<(String, &str)> = headers
+            .iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.trim()))
+            .collect();
+        headers.sort();
+        let signed: Vec<&str> = headers.iter().map(|(name, _)| name.as_str()).collect();
+        let signed = signed.join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let request =
+            format!("{method}\n{uri}\n{query}\n{canonical_headers}\n{signed}\n{payload_sha256}");
+
+        let date = &amz_date[..amz_date.len().min(8)];
+        let scope = format!("{date}/{}/{}/aws4_request", self.region, self.service);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(digest::digest(&digest::SHA256, request.as_bytes()).as_ref())
+        );
+        let mut key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date, self.region, self.service, "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&key, to_sign.as_bytes()));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed}, Signature={signature}",
+            self.access_key
+        )
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data)
+        .as_ref()
+        .to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// `s` with everything but unreserved characters (and `/`, unless
+/// `slash`) percent-encoded, as SigV4 canonical URIs need.
+fn uri_encode(s: &str, slash: bool) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            },
+            b'/' if !slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Where the objects of `config`'s bucket go: `/bucket` under the path of
+/// a custom endpoint, or its own host on AWS unless its name has dots,
+/// which don't match the wildcard certificate.
+fn endpoint(config: &S3Config) -> Result<Url, CameraError> {
+    let bucket = uri_encode(&config.bucket, true);
+    let Some(url) = &config.endpoint else {
+        return Ok(match config.bucket.contains('.') {
+            true => Url {
+                tls: true,
+                host: format!("s3.{}.amazonaws.com", config.region),
+                port: 443,
+                path: format!("/{bucket}"),
+            },
+            false => Url {
+                tls: true,
+                host: format!("{}.s3.{}.amazonaws.com", config.bucket, config.region),
+                port: 443,
+                path: String::new(),
+            },
+        });
+    };
+    let mut url = Url::parse(url)
+        .map_err(|e| CameraError::invalid_config(format!("S3 endpoint {url}: {e}")))?;
+    url.path = format!("{}/{bucket}", url.path.trim_end_matches('/'));
+    Ok(url)
+}
+
+/// The body of a `PUT`.
+enum Body<'a> {
+    Bytes(&'a [u8]),
+    File(&'a Path),
+}
+
+/// Why an attempt failed, and whether another might succeed.
+struct Failure {
+    error: CameraError,
+    retry: bool,
+}
+
+/// Uploads objects to one bucket.
+pub struct S3Client {
+    config: S3Config,
+    endpoint: Url,
+    tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl core::fmt::Debug for S3Client {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("S3Client")
+            .field("bucket", &self.config.bucket)
+            .field("prefix", &self.config.prefix)
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+impl S3Client {
+    pub fn new(config: S3Config) -> Result<Self, CameraError> {
+        if config.bucket.is_empty() {
+            return Err(CameraError::invalid_config("S3 needs a bucket"));
+        }
+        if config.access_key.is_empty() || config.secret_key.is_empty() {
+            return Err(CameraError::invalid_config(
+                "S3 needs credentials: set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+            ));
+        }
+        let endpoint = endpoint(&config)?;
+        let tls = match endpoint.tls {
+            true => Some(http::tls_config(config.ca.as_deref())?),
+            false => None,
+        };
+        Ok(Self {
+            config,
+            endpoint,
+            tls,
+        })
+    }
+
+    pub fn config(&self) -> &S3Config {
+        &self.config
+    }
+
+    /// The key an object named `name` gets, under the prefix.
+    pub fn key(&self, name: &str) -> String {
+        format!("{}{name}", self.config.prefix)
+    }
+
+    /// Uploads `data` as `key`.
+    pub fn put(&self, key: &str, data: &[u8], content_type: &str) -> Result<S3Object, CameraError> {
+        let sha256 = hex(digest::digest(&digest::SHA256, data).as_ref());
+        self.put_retrying(
+            key,
+            Body::Bytes(data),
+            data.len() as u64,
+            &sha256,
+            content_type,
+        )
+    }
+
+    /// Uploads the file at `path` as `key`, streaming it.
+    pub fn put_file(
+        &self,
+        key: &str,
+        path: &Path,
+        content_type: &str,
+    ) -> Result<S3Object, CameraError> {
+        let failed =
+            |e: io::Error| CameraError::other(format!("reading {} to upload: {e}", path.display()));
+        let size = std::fs::metadata(path).map_err(failed)?.len();
+        let sha256 = file_sha256(path).map_err(failed)?;
+        self.put_retrying(key, Body::File(path), size, &sha256, content_type)
+    }
+
+    fn put_retrying(
+        &self,
+        key: &str,
+        body: Body,
+        size: u64,
+        sha256: &str,
+        content_type: &str,
+    ) -> Result<S3Object, CameraError> {
+        let mut backoff = self.config.backoff;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match self.put_once(key, &body, size, sha256, content_type) {
+                Ok(etag) => {
+                    return Ok(S3Object {
+                        bucket: self.config.bucket.clone(),
+                        key: key.to_string(),
+                        size,
+                        etag,
+                        attempts,
+                    });
+                },
+                Err(Failure { retry, error }) if !retry || attempts > self.config.retries => {
+                    return Err(error);
+                },
+                Err(_) => {
+                    std::thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                },
+            }
+        }
+    }
+
+    fn put_once(
+        &self,
+        key: &str,
+        body: &Body,
+        size: u64,
+        sha256: &str,
+        content_type: &str,
+    ) -> Result<Option<String>, Failure> {
+        let transient = |e: io::Error| Failure {
+            error: CameraError::other(format!(
+                "uploading to s3://{}/{key}: {e}",
+                self.config.bucket
+            )),
+            retry: true,
+        };
+        let uri = format!("{}/{}", self.endpoint.path, uri_encode(key, false));
+        let amz_date = jiff::Timestamp::now()
+            .strftime("%Y%m%dT%H%M%SZ")
+            .to_string();
+        let host = self.endpoint.host_header();
+        let size = size.to_string();
+        let mut headers = vec![
+            ("host", host.as_str()),
+            ("content-length", size.as_str()),
+            ("content-type", content_type),
+            ("x-amz-content-sha256", sha256),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        if let Some(token) = &self.config.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+        let authorization = SigV4 {
+            access_key: &self.config.access_key,
+            secret_key: &self.config.secret_key,
+            region: &self.config.region,
+            service: "s3",
+        }
+        .authorization("PUT", &uri, "", &headers, sha256, &amz_date);
+
+        let mut request = format!("PUT {uri} HTTP/1.1\r\n");
+        for (name, value) in &headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str(&format!(
+            "authorization: {authorization}\r\nconnection: close\r\n\r\n"
+        ));
+
+        let mut stream = http::connect(&self.endpoint, self.tls.as_ref()).map_err(transient)?;
+        stream.write_all(request.as_bytes()).map_err(transient)?;
+        match body {
+            Body::Bytes(data) => stream.write_all(data).map_err(transient)?,
+            Body::File(path) => {
+                let mut file = std::fs::File::open(path).map_err(transient)?;
+                io::copy(&mut file, &mut stream).map_err(transient)?;
+            },
+        }
+        stream.flush().map_err(transient)?;
+        let response = http::read_response(&mut *stream).map_err(transient)?;
+        if response.is_success() {
+            let etag = response.header("etag").map(|etag| etag.trim_matches('"'));
+            return Ok(etag.map(str::to_string));
+        }
+        Err(Failure {
+            error: CameraError::other(format!(
+                "uploading to s3://{}/{key}: HTTP {}{}",
+                self.config.bucket,
+                response.status,
+                error_message(&response.body)
+                    .map(|m| format!(": {m}"))
+                    .unwrap_or_default()
+            )),
+            retry: http::is_transient(response.status),
+        })
+    }
+}
+
+fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut buf = vec![0; 256 * 1024];
+    loop {
+        match file.read(&mut buf)? {
+            0 => break,
+            n => context.update(&buf[..n]),
+        }
+    }
+    Ok(hex(context.finish().as_ref()))
+}
+
+/// The message of an S3 error's XML body: its code and message.
+fn error_message(body: &[u8]) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let element = |name: &str| {
+        let start = body.find(&format!("<{name}>"))? + name.len() + 2;
+        let end = body[start..].find(&format!("</{name}>"))? + start;
+        Some(body[start..end].to_string())
+    };
+    match (element("Code"), element("Message")) {
+        (Some(code), Some(message)) => Some(format!("{code}: {message}")),
+        (code, message) => code.or(message),
+    }
+}
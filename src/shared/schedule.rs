@@ -0,0 +1,175 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::CameraError;
+use core::{fmt, str::FromStr};
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const ALL_DAYS: u8 = 0b111_1111;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A recurring weekly window, written `[DAYS] HH:MM-HH:MM` in local time,
+/// e.g. `mon-fri 18:00-08:00`, `sat,sun 00:00-24:00` or `* 12:00-13:00`.
+///
+/// A window whose end is before its start runs past midnight; the days
+/// name the day it starts on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrivacyWindow {
+    /// Bit 0 is Monday, bit 6 is Sunday.
+    days: u8,
+    start: u16,
+    end: u16,
+}
+
+impl PrivacyWindow {
+    /// Returns whether the window covers `minute` (0..1440) of `weekday`
+    /// (0 = Monday).
+    pub fn contains(&self, weekday: u8, minute: u16) -> bool {
+        let weekday = weekday % 7;
+        let yesterday = (weekday + 6) % 7;
+        let on = |day: u8| self.days & (1 << day) != 0;
+        if self.start <= self.end {
+            on(weekday) && (self.start..self.end).contains(&minute)
+        } else {
+            (on(weekday) && minute >= self.start) || (on(yesterday) && minute < self.end)
+        }
+    }
+}
+
+impl FromStr for PrivacyWindow {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (days, times) = match s.rsplit_once(char::is_whitespace) {
+            Some((days, times)) => (parse_days(days.trim())?, times),
+            None => (ALL_DAYS, s),
+        };
+        let (start, end) = times.split_once('-').ok_or_else(|| {
+            CameraError::invalid_config(format!(
+                "invalid privacy window '{s}', use [DAYS] HH:MM-HH:MM"
+            ))
+        })?;
+        let (start, end) = (parse_time(start)?, parse_time(end)?);
+        if start == end {
+            return Err(CameraError::invalid_config(format!(
+                "privacy window '{s}' is empty"
+            )));
+        }
+        Ok(Self { days, start, end })
+    }
+}
+
+impl fmt::Display for PrivacyWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.days == ALL_DAYS {
+            f.write_str("*")?;
+        } else {
+            let days: Vec<&str> = (0..7)
+                .filter(|d| self.days & (1 << d) != 0)
+                .map(|d| DAY_NAMES[d as usize])
+                .collect();
+            f.write_str(&days.join(","))?;
+        }
+        write!(
+            f,
+            " {:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+fn parse_days(s: &str) -> Result<u8, CameraError> {
+    if s == "*" {
+        return Ok(ALL_DAYS);
+    }
+    let day = |name: &str| {
+        let name = name.trim().to_ascii_lowercase();
+        DAY_NAMES
+            .iter()
+            .position(|d| name.starts_with(d))
+            .map(|d| d as u8)
+            .ok_or_else(|| CameraError::invalid_config(format!("unknown weekday '{name}'")))
+    };
+    let mut days = 0u8;
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (day(from)?, day(to)?);
+                let mut d = from;
+                loop {
+                    days |= 1 << d;
+                    if d == to {
+                        break;
+                    }
+                    d = (d + 1) % 7;
+                }
+            },
+            None => days |= 1 << day(part)?,
+        }
+    }
+    Ok(days)
+}
+
+fn parse_time(s: &str) -> Result<u16, CameraError> {
+    let invalid = || CameraError::invalid_config(format!("invalid time '{s}', use HH:MM"));
+    let (h, m) = s.trim().split_once(':').ok_or_else(invalid)?;
+    let h: u16 = h.parse().map_err(|_| invalid())?;
+    let m: u16 = m.parse().map_err(|_| invalid())?;
+    let minute = h * 60 + m;
+    if m >= 60 || minute > MINUTES_PER_DAY {
+        return Err(invalid());
+    }
+    Ok(minute)
+}
+
+/// A set of windows during which capture is forcibly disabled.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrivacySchedule {
+    windows: Vec<PrivacyWindow>,
+}
+
+impl PrivacySchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_window(mut self, window: PrivacyWindow) -> Self {
+        self.windows.push(window);
+        self
+    }
+
+    pub fn windows(&self) -> &[PrivacyWindow] {
+        &self.windows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// Returns whether any window covers `minute` of `weekday` (0 = Monday).
+    pub fn is_active_at(&self, weekday: u8, minute: u16) -> bool {
+        self.windows.iter().any(|w| w.contains(weekday, minute))
+    }
+
+    /// Returns whether any window covers the current local time.
+    pub fn is_active_now(&self) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let now = jiff::Zoned::now();
+        let weekday = now.weekday().to_monday_zero_offset() as u8;
+        let minute = now.hour() as u16 * 60 + now.minute() as u16;
+        self.is_active_at(weekday, minute)
+    }
+}
+
+impl FromIterator<PrivacyWindow> for PrivacySchedule {
+    fn from_iter<I: IntoIterator<Item = PrivacyWindow>>(iter: I) -> Self {
+        Self {
+            windows: iter.into_iter().collect(),
+        }
+    }
+}
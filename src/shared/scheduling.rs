@@ -0,0 +1,237 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Priority and CPU affinity for the threads frames pass through, for
+//! robotics setups that need consistent frame latency.
+
+use crate::shared::CameraError;
+use core::{fmt, str::FromStr};
+
+/// Default `SCHED_FIFO` priority for `realtime` without a number.
+const DEFAULT_REALTIME_PRIORITY: u8 = 10;
+
+/// How urgently the OS should run a thread.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThreadPriority {
+    /// The OS default.
+    #[default]
+    Normal,
+    /// Ahead of ordinary threads: nice -10 on Linux, the user-initiated QoS
+    /// class on Apple platforms, `THREAD_PRIORITY_HIGHEST` on Windows.
+    High,
+    /// `SCHED_FIFO` at this priority (1–99) on Linux, which needs
+    /// `CAP_SYS_NICE` or an `RLIMIT_RTPRIO`; the user-interactive QoS class
+    /// on Apple platforms, `THREAD_PRIORITY_TIME_CRITICAL` on Windows.
+    Realtime(u8),
+}
+
+impl fmt::Display for ThreadPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThreadPriority::Normal => f.write_str("normal"),
+            ThreadPriority::High => f.write_str("high"),
+            ThreadPriority::Realtime(priority) => write!(f, "realtime:{priority}"),
+        }
+    }
+}
+
+impl FromStr for ThreadPriority {
+    type Err = CameraError;
+
+    /// Parses `normal`, `high`, `realtime` or `realtime:N`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s.as_str(), None),
+        };
+        match (name, arg) {
+            ("normal", None) => Ok(ThreadPriority::Normal),
+            ("high", None) => Ok(ThreadPriority::High),
+            ("realtime" | "rt", None) => Ok(ThreadPriority::Realtime(DEFAULT_REALTIME_PRIORITY)),
+            ("realtime" | "rt", Some(arg)) => match arg.parse::<u8>() {
+                Ok(priority @ 1..=99) => Ok(ThreadPriority::Realtime(priority)),
+                _ => Err(CameraError::invalid_config(format!(
+                    "invalid realtime priority '{arg}' (expected 1-99)"
+                ))),
+            },
+            _ => Err(CameraError::invalid_config(format!(
+                "unknown thread priority '{s}' (expected normal, high or realtime[:N])"
+            ))),
+        }
+    }
+}
+
+/// The priority and core a thread runs at; the default leaves both to the OS.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ThreadScheduling {
+    pub priority: ThreadPriority,
+    /// Pin the thread to this CPU core (Linux, Android and Windows).
+    pub core: Option<usize>,
+}
+
+impl ThreadScheduling {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Applies the priority and affinity to the calling thread. Both are
+    /// attempted; the error describes the first that failed.
+    pub fn apply_to_current_thread(&self) -> Result<(), CameraError> {
+        let priority = match self.priority {
+            ThreadPriority::Normal => Ok(()),
+            priority => set_priority(priority),
+        };
+        let affinity = match self.core {
+            Some(core) => set_affinity(core),
+            None => Ok(()),
+        };
+        priority.and(affinity)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_priority(priority: ThreadPriority) -> Result<(), CameraError> {
+    match priority {
+        ThreadPriority::Normal => Ok(()),
+        ThreadPriority::High => {
+            let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+            // PRIO_PROCESS with a thread id renices just that thread.
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, -10) } != 0 {
+                return Err(CameraError::driver(
+                    "raising thread priority",
+                    std::io::Error::last_os_error(),
+                ));
+            }
+            Ok(())
+        },
+        ThreadPriority::Realtime(priority) => {
+            let param = libc::sched_param {
+                sched_priority: priority as i32,
+            };
+            let rc = unsafe {
+                libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+            };
+            if rc != 0 {
+                return Err(CameraError::driver(
+                    "switching thread to SCHED_FIFO",
+                    std::io::Error::from_raw_os_error(rc),
+                ));
+            }
+            Ok(())
+        },
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_affinity(core: usize) -> Result<(), CameraError> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(CameraError::invalid_config(format!(
+            "CPU core {core} is out of range"
+        )));
+    }
+    let mut set: libc::cpu_set_t = unsafe { core::mem::zeroed() };
+    unsafe { libc::CPU_SET(core, &mut set) };
+    // Pid 0 is the calling thread.
+    if unsafe { libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        return Err(CameraError::driver(
+            "pinning thread to a core",
+            std::io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn set_priority(priority: ThreadPriority) -> Result<(), CameraError> {
+    let class = match priority {
+        ThreadPriority::Normal => return Ok(()),
+        ThreadPriority::High => libc::qos_class_t::QOS_CLASS_USER_INITIATED,
+        ThreadPriority::Realtime(_) => libc::qos_class_t::QOS_CLASS_USER_INTERACTIVE,
+    };
+    let rc = unsafe { libc::pthread_set_qos_class_self_np(class, 0) };
+    if rc != 0 {
+        return Err(CameraError::driver(
+            "setting thread QoS class",
+            std::io::Error::from_raw_os_error(rc),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn set_affinity(_core: usize) -> Result<(), CameraError> {
+    Err(CameraError::unsupported(
+        "Apple platforms don't let threads be pinned to a core",
+    ))
+}
+
+#[cfg(windows)]
+mod win32 {
+    pub type Handle = *mut core::ffi::c_void;
+
+    pub const THREAD_PRIORITY_HIGHEST: i32 = 2;
+    pub const THREAD_PRIORITY_TIME_CRITICAL: i32 = 15;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        pub fn GetCurrentThread() -> Handle;
+        pub fn SetThreadPriority(thread: Handle, priority: i32) -> i32;
+        pub fn SetThreadAffinityMask(thread: Handle, mask: usize) -> usize;
+    }
+}
+
+#[cfg(windows)]
+fn set_priority(priority: ThreadPriority) -> Result<(), CameraError> {
+    let level = match priority {
+        ThreadPriority::Normal => return Ok(()),
+        ThreadPriority::High => win32::THREAD_PRIORITY_HIGHEST,
+        ThreadPriority::Realtime(_) => win32::THREAD_PRIORITY_TIME_CRITICAL,
+    };
+    if unsafe { win32::SetThreadPriority(win32::GetCurrentThread(), level) } == 0 {
+        return Err(CameraError::driver(
+            "raising thread priority",
+            std::io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn set_affinity(core: usize) -> Result<(), CameraError> {
+    let mask = 1usize
+        .checked_shl(core as u32)
+        .ok_or_else(|| CameraError::invalid_config(format!("CPU core {core} is out of range")))?;
+    if unsafe { win32::SetThreadAffinityMask(win32::GetCurrentThread(), mask) } == 0 {
+        return Err(CameraError::driver(
+            "pinning thread to a core",
+            std::io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    windows
+)))]
+fn set_priority(_priority: ThreadPriority) -> Result<(), CameraError> {
+    Err(CameraError::unsupported(
+        "thread priorities are not supported on this platform",
+    ))
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    windows
+)))]
+fn set_affinity(_core: usize) -> Result<(), CameraError> {
+    Err(CameraError::unsupported(
+        "pinning threads is not supported on this platform",
+    ))
+}
@@ -0,0 +1,76 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::CameraError;
+use core::{fmt, str::FromStr};
+
+/// A raw sensor mode for libcamera cameras, as in `rpicam-vid --mode`.
+///
+/// Sensors bin or crop differently in each mode, so picking one fixes the
+/// field of view and maximum frame rate independently of the output size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SensorMode {
+    pub width: u32,
+    pub height: u32,
+    /// Bits per pixel; `None` lets libcamera choose.
+    pub bit_depth: Option<u8>,
+    /// Whether the raw stream is CSI-2 packed (`P`) rather than unpacked (`U`).
+    pub packed: bool,
+}
+
+impl fmt::Display for SensorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.width, self.height)?;
+        if let Some(bits) = self.bit_depth {
+            write!(f, ":{bits}:{}", if self.packed { 'P' } else { 'U' })?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SensorMode {
+    type Err = CameraError;
+
+    /// Parses `WIDTH:HEIGHT[:BITS[:P|U]]`, e.g. `2028:1520:12:P`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            CameraError::invalid_config(format!(
+                "invalid sensor mode '{}' (expected WIDTH:HEIGHT[:BITS[:P|U]], e.g. 2028:1520:12)",
+                s.trim()
+            ))
+        };
+        let mut parts = s.trim().split(':');
+        let mut dimension = || -> Result<u32, CameraError> {
+            parts
+                .next()
+                .and_then(|p| p.trim().parse().ok())
+                .filter(|&n| n > 0)
+                .ok_or_else(invalid)
+        };
+        let (width, height) = (dimension()?, dimension()?);
+        let bit_depth = match parts.next() {
+            Some(bits) => Some(
+                bits.trim()
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|b| (8..=16).contains(b))
+                    .ok_or_else(invalid)?,
+            ),
+            None => None,
+        };
+        let packed = match parts.next().map(|p| p.trim().to_ascii_uppercase()) {
+            None => true,
+            Some(p) if p == "P" => true,
+            Some(p) if p == "U" => false,
+            Some(_) => return Err(invalid()),
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            packed,
+        })
+    }
+}
@@ -0,0 +1,455 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Frames in a named POSIX shared-memory ring, so other processes on the
+//! same host can take them without going through a pipe.
+//!
+//! The segment starts with a `Header` followed by `slots` fixed-size
+//! slots, each a `SlotHeader` and the tightly packed pixels. Every slot is
+//! a seqlock: the publisher makes `seq` odd while writing and even once the
+//! frame is complete, and subscribers retry or skip a slot whose `seq`
+//! changed while they copied it. The publisher never waits for subscribers;
+//! a subscriber that falls more than `slots` frames behind skips ahead.
+
+use crate::shared::{CameraError, Frame, FrameSink, FrameStream, PixelFormat};
+use bytes::Bytes;
+use core::{
+    ptr,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering, fence},
+    time::Duration,
+};
+use std::{ffi::CString, io, sync::Arc, sync::Mutex, time::Instant};
+
+/// Device and `--publish` prefix naming a shared-memory ring.
+pub const SHM_PREFIX: &str = "shm:";
+
+const MAGIC: u64 = u64::from_le_bytes(*b"ASIMOVCM");
+const VERSION: u32 = 1;
+const ALIGN: usize = 64;
+
+#[repr(C)]
+struct Header {
+    magic: u64,
+    version: u32,
+    slots: u32,
+    slot_bytes: u64,
+    /// Frames published so far; frame `n` lives in slot `n % slots`.
+    head: AtomicU64,
+    /// Set when the publisher goes away.
+    closed: AtomicU32,
+}
+
+#[repr(C)]
+struct SlotHeader {
+    seq: AtomicU64,
+    /// Which frame the slot holds, to detect overwrites.
+    index: AtomicU64,
+    timestamp_ns: AtomicU64,
+    width: AtomicU32,
+    height: AtomicU32,
+    pixel_format: AtomicU32,
+    stream: AtomicU32,
+}
+
+const HEADER_BYTES: usize = size_of::<Header>().next_multiple_of(ALIGN);
+const SLOT_HEADER_BYTES: usize = size_of::<SlotHeader>().next_multiple_of(ALIGN);
+
+fn slot_stride(slot_bytes: usize) -> usize {
+    SLOT_HEADER_BYTES + slot_bytes.next_multiple_of(ALIGN)
+}
+
+/// Publishes frames into a shared-memory ring that [`ShmSubscriber`]s in
+/// other processes can open by name. The segment is removed on drop.
+#[derive(Debug)]
+pub struct ShmPublisher {
+    name: CString,
+    map: Mapping,
+    slots: u32,
+    slot_bytes: usize,
+    /// Serializes publishers sharing this handle.
+    head: Mutex<u64>,
+}
+
+impl ShmPublisher {
+    /// Creates the ring `name` (e.g. `camera0`) with `slots` slots of
+    /// `slot_bytes` pixel bytes each, replacing any stale segment left by a
+    /// publisher that didn't shut down.
+    pub fn create(name: &str, slot_bytes: usize, slots: u32) -> Result<Self, CameraError> {
+        if slot_bytes == 0 || slots == 0 {
+            return Err(CameraError::invalid_config(
+                "shared-memory rings need at least one non-empty slot",
+            ));
+        }
+        let name = segment_name(name)?;
+        let len = HEADER_BYTES + slots as usize * slot_stride(slot_bytes);
+        // SAFETY: `name` is NUL-terminated; the descriptor is closed below.
+        let map = unsafe {
+            libc::shm_unlink(name.as_ptr());
+            let fd = libc::shm_open(
+                name.as_ptr(),
+                libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+                0o600 as libc::c_uint,
+            );
+            if fd < 0 {
+                return Err(CameraError::driver(
+                    "creating shared memory",
+                    io::Error::last_os_error(),
+                ));
+            }
+            let mapped = if libc::ftruncate(fd, len as libc::off_t) == 0 {
+                Mapping::new(fd, len, libc::PROT_READ | libc::PROT_WRITE)
+            } else {
+                Err(io::Error::last_os_error())
+            };
+            libc::close(fd);
+            mapped.map_err(|e| {
+                libc::shm_unlink(name.as_ptr());
+                CameraError::driver("mapping shared memory", e)
+            })?
+        };
+
+        // SAFETY: the fresh mapping is zeroed and large enough for a header;
+        // nobody can have validated it before `magic` is written last.
+        unsafe {
+            let header = map.ptr as *mut Header;
+            (*header).version = VERSION;
+            (*header).slots = slots;
+            (*header).slot_bytes = slot_bytes as u64;
+            fence(Ordering::Release);
+            ptr::write_volatile(&mut (*header).magic, MAGIC);
+        }
+        Ok(Self {
+            name,
+            map,
+            slots,
+            slot_bytes,
+            head: Mutex::new(0),
+        })
+    }
+
+    /// The largest frame, in packed pixel bytes, a slot can hold.
+    pub fn slot_bytes(&self) -> usize {
+        self.slot_bytes
+    }
+
+    /// Copies `frame` into the next slot, packing its rows.
+    pub fn publish(&self, frame: &Frame) -> Result<(), CameraError> {
+        if !frame.validate() {
+            return Err(CameraError::other("refusing to publish a malformed frame"));
+        }
+        let row_len = frame.width as usize * frame.pixel_format.bytes_per_pixel() as usize;
+        let rows = frame.pixel_format.buffer_rows(frame.height) as usize;
+        let len = row_len * rows;
+        if len > self.slot_bytes {
+            return Err(CameraError::other(format!(
+                "{}x{} {} frame needs {len} bytes, but shared-memory slots hold {}",
+                frame.width,
+                frame.height,
+                frame.pixel_format.as_str(),
+                self.slot_bytes
+            )));
+        }
+
+        let mut head = self.head.lock().unwrap_or_else(|p| p.into_inner());
+        let index = *head;
+        let (slot, data) = self.map.slot(index % self.slots as u64, self.slot_bytes);
+        let seq = slot.seq.load(Ordering::Relaxed);
+        slot.seq.store(seq + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        slot.index.store(index, Ordering::Relaxed);
+        slot.timestamp_ns
+            .store(frame.timestamp_ns, Ordering::Relaxed);
+        slot.width.store(frame.width, Ordering::Relaxed);
+        slot.height.store(frame.height, Ordering::Relaxed);
+        slot.pixel_format
+            .store(format_code(frame.pixel_format), Ordering::Relaxed);
+        slot.stream
+            .store(stream_code(frame.stream), Ordering::Relaxed);
+        for (y, row) in frame
+            .data
+            .chunks(frame.stride as usize)
+            .take(rows)
+            .enumerate()
+        {
+            // SAFETY: `len` fits the slot, and subscribers only read it.
+            unsafe {
+                ptr::copy_nonoverlapping(row.as_ptr(), data.add(y * row_len), row_len);
+            }
+        }
+        slot.seq.store(seq + 2, Ordering::Release);
+        *head = index + 1;
+        self.map.header().head.store(index + 1, Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns a sink that publishes every frame it receives, discarding
+    /// frames that don't fit.
+    pub fn frame_sink(self: &Arc<Self>) -> FrameSink {
+        let this = Arc::clone(self);
+        Arc::new(move |frame: Frame| {
+            let _ = this.publish(&frame);
+        })
+    }
+}
+
+impl Drop for ShmPublisher {
+    fn drop(&mut self) {
+        self.map.header().closed.store(1, Ordering::Release);
+        // SAFETY: `name` is NUL-terminated. Subscribers keep their mappings.
+        unsafe { libc::shm_unlink(self.name.as_ptr()) };
+    }
+}
+
+/// Reads frames from a ring created by a [`ShmPublisher`].
+#[derive(Debug)]
+pub struct ShmSubscriber {
+    map: Mapping,
+    slots: u32,
+    slot_bytes: usize,
+    next: u64,
+    skipped: u64,
+}
+
+impl ShmSubscriber {
+    /// Opens the ring `name`, starting with the next frame published.
+    pub fn open(name: &str) -> Result<Self, CameraError> {
+        let segment = segment_name(name)?;
+        // SAFETY: `segment` is NUL-terminated; the descriptor is closed below.
+        let map = unsafe {
+            let fd = libc::shm_open(segment.as_ptr(), libc::O_RDONLY, 0 as libc::c_uint);
+            if fd < 0 {
+                let err = io::Error::last_os_error();
+                return Err(if err.kind() == io::ErrorKind::NotFound {
+                    CameraError::invalid_config(format!("nothing is publishing to shm:{name}"))
+                } else {
+                    CameraError::driver("opening shared memory", err)
+                });
+            }
+            let mut stat: libc::stat = core::mem::zeroed();
+            let mapped = if libc::fstat(fd, &mut stat) != 0 {
+                Err(io::Error::last_os_error())
+            } else if (stat.st_size as usize) < HEADER_BYTES {
+                Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+            } else {
+                Mapping::new(fd, stat.st_size as usize, libc::PROT_READ)
+            };
+            libc::close(fd);
+            mapped.map_err(|e| CameraError::driver("mapping shared memory", e))?
+        };
+
+        // SAFETY: the mapping holds at least a header.
+        let (magic, version, slots, slot_bytes) = unsafe {
+            let header = map.ptr as *const Header;
+            let magic = ptr::read_volatile(&(*header).magic);
+            fence(Ordering::Acquire);
+            (
+                magic,
+                (*header).version,
+                (*header).slots,
+                (*header).slot_bytes as usize,
+            )
+        };
+        if magic != MAGIC
+            || version != VERSION
+            || slots == 0
+            || map.len < HEADER_BYTES + slots as usize * slot_stride(slot_bytes)
+        {
+            return Err(CameraError::other(format!(
+                "shm:{name} isn't a camera frame ring this version understands"
+            )));
+        }
+        let next = map.header().head.load(Ordering::Acquire);
+        Ok(Self {
+            map,
+            slots,
+            slot_bytes,
+            next,
+            skipped: 0,
+        })
+    }
+
+    /// Frames overwritten before this subscriber got to them.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Returns the next frame if one has been published, or an error once
+    /// the publisher has gone away.
+    pub fn try_recv(&mut self) -> Result<Option<Frame>, CameraError> {
+        loop {
+            let header = self.map.header();
+            let head = header.head.load(Ordering::Acquire);
+            if self.next >= head {
+                if header.closed.load(Ordering::Acquire) != 0 {
+                    return Err(CameraError::other("the shared-memory publisher closed"));
+                }
+                return Ok(None);
+            }
+            if head - self.next > self.slots as u64 {
+                self.skipped += head - 1 - self.next;
+                self.next = head - 1;
+            }
+            let index = self.next;
+            match self.read_slot(index) {
+                Some(frame) => {
+                    self.next += 1;
+                    return Ok(Some(frame));
+                },
+                // Overwritten while we looked; catch up with the publisher.
+                None => {
+                    self.skipped += 1;
+                    self.next = index + 1;
+                },
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the next frame.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Frame>, CameraError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(frame) = self.try_recv()? {
+                return Ok(Some(frame));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            std::thread::sleep(Duration::from_micros(500));
+        }
+    }
+
+    fn read_slot(&self, index: u64) -> Option<Frame> {
+        let (slot, data) = self.map.slot(index % self.slots as u64, self.slot_bytes);
+        let seq = slot.seq.load(Ordering::Acquire);
+        if seq % 2 == 1 || slot.index.load(Ordering::Relaxed) != index {
+            return None;
+        }
+        let (width, height) = (
+            slot.width.load(Ordering::Relaxed),
+            slot.height.load(Ordering::Relaxed),
+        );
+        let pixel_format = format_from_code(slot.pixel_format.load(Ordering::Relaxed))?;
+        let stream = stream_from_code(slot.stream.load(Ordering::Relaxed))?;
+        let timestamp_ns = slot.timestamp_ns.load(Ordering::Relaxed);
+        let stride = width.checked_mul(pixel_format.bytes_per_pixel())?;
+        let len = (stride as usize).checked_mul(pixel_format.buffer_rows(height) as usize)?;
+        if len > self.slot_bytes {
+            return None;
+        }
+        let mut pixels = vec![0u8; len];
+        // SAFETY: `len` is within the slot. The copy may race the publisher,
+        // in which case `seq` changes and the bytes are thrown away.
+        unsafe { ptr::copy_nonoverlapping(data, pixels.as_mut_ptr(), len) };
+        fence(Ordering::Acquire);
+        if slot.seq.load(Ordering::Relaxed) != seq {
+            return None;
+        }
+        Some(
+            Frame::new(Bytes::from(pixels), width, height, stride, pixel_format)
+                .with_stream(stream)
+                .with_timestamp_ns(timestamp_ns),
+        )
+    }
+}
+
+/// A shared mapping, unmapped on drop.
+#[derive(Debug)]
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// SAFETY: the mapping is only accessed through atomics and seqlocked copies.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// # Safety
+    /// `fd` must be a shared-memory descriptor at least `len` bytes long.
+    unsafe fn new(fd: libc::c_int, len: usize, prot: libc::c_int) -> io::Result<Self> {
+        // SAFETY: guaranteed by the caller.
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, prot, libc::MAP_SHARED, fd, 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    fn header(&self) -> &Header {
+        // SAFETY: every mapping is at least `HEADER_BYTES` long.
+        unsafe { &*(self.ptr as *const Header) }
+    }
+
+    /// The header and pixel bytes of `slot`, which the caller keeps in range.
+    fn slot(&self, slot: u64, slot_bytes: usize) -> (&SlotHeader, *mut u8) {
+        let offset = HEADER_BYTES + slot as usize * slot_stride(slot_bytes);
+        debug_assert!(offset + slot_stride(slot_bytes) <= self.len);
+        // SAFETY: the ring's length was checked against its slot count.
+        unsafe {
+            let base = self.ptr.add(offset);
+            (&*(base as *const SlotHeader), base.add(SLOT_HEADER_BYTES))
+        }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` and `len` came from a successful `mmap`.
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// POSIX shared-memory names are a single `/`-prefixed path component.
+fn segment_name(name: &str) -> Result<CString, CameraError> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > 30 || name.contains(['/', '\0']) {
+        return Err(CameraError::invalid_config(format!(
+            "invalid shared-memory name '{name}' (expected 1-30 characters without '/')"
+        )));
+    }
+    Ok(CString::new(format!("/{name}")).expect("checked for NUL bytes"))
+}
+
+fn format_code(format: PixelFormat) -> u32 {
+    match format {
+        PixelFormat::Rgb8 => 1,
+        PixelFormat::Bgra8 => 2,
+        PixelFormat::Rgba8 => 3,
+        PixelFormat::Gray16 => 4,
+        PixelFormat::Z16 => 5,
+        PixelFormat::P010 => 6,
+        PixelFormat::Rgba1010102 => 7,
+    }
+}
+
+fn format_from_code(code: u32) -> Option<PixelFormat> {
+    Some(match code {
+        1 => PixelFormat::Rgb8,
+        2 => PixelFormat::Bgra8,
+        3 => PixelFormat::Rgba8,
+        4 => PixelFormat::Gray16,
+        5 => PixelFormat::Z16,
+        6 => PixelFormat::P010,
+        7 => PixelFormat::Rgba1010102,
+        _ => return None,
+    })
+}
+
+fn stream_code(stream: FrameStream) -> u32 {
+    match stream {
+        FrameStream::Color => 0,
+        FrameStream::Depth => 1,
+        FrameStream::Infrared => 2,
+    }
+}
+
+fn stream_from_code(code: u32) -> Option<FrameStream> {
+    Some(match code {
+        0 => FrameStream::Color,
+        1 => FrameStream::Depth,
+        2 => FrameStream::Infrared,
+        _ => return None,
+    })
+}
@@ -0,0 +1,262 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Telemetry recorded beside video files, such as the SRT subtitles drones
+//! write next to their footage or a JSON sidecar, matched to frames by
+//! time, so re-processed footage keeps its position and camera settings.
+
+use crate::shared::CameraError;
+use core::time::Duration;
+use serde_json::{Map, Number, Value};
+use std::{path::Path, sync::Arc};
+
+/// One telemetry reading, which applies from `start` until `end` after the
+/// source's first frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TelemetryEntry {
+    pub start: Duration,
+    pub end: Duration,
+    /// Readings such as `latitude`, `rel_alt` or `iso`, as numbers where
+    /// they parse as one; SRT cues with no `key: value` pairs keep their
+    /// text as `text`.
+    pub fields: Map<String, Value>,
+}
+
+/// The telemetry of a whole file, in time order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sidecar {
+    entries: Vec<Arc<TelemetryEntry>>,
+}
+
+impl Sidecar {
+    /// Reads an SRT (`.srt`) or JSON (any other extension) sidecar.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, CameraError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| CameraError::driver("reading sidecar metadata", e))?;
+        let srt = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("srt"));
+        let sidecar = if srt {
+            Self::parse_srt(&text)?
+        } else {
+            Self::parse_json(&text)?
+        };
+        if sidecar.entries.is_empty() {
+            return Err(CameraError::invalid_config(format!(
+                "{} has no telemetry entries",
+                path.display()
+            )));
+        }
+        Ok(sidecar)
+    }
+
+    /// Parses SubRip cues: each cue's bracketed `[key: value]` pairs, and
+    /// `key: value` pairs separated by commas, with a `GPS (lon, lat, alt)`
+    /// triple as older DJI drones write it.
+    pub fn parse_srt(text: &str) -> Result<Self, CameraError> {
+        let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+        let mut entries = Vec::new();
+        for cue in text.split("\n\n").map(str::trim).filter(|c| !c.is_empty()) {
+            let mut lines = cue.lines();
+            let mut timing = lines.next().unwrap_or_default();
+            // The cue number is optional in practice.
+            if !timing.contains("-->") {
+                timing = lines.next().unwrap_or_default();
+            }
+            let (start, end) = timing
+                .split_once("-->")
+                .and_then(|(start, end)| Some((srt_time(start)?, srt_time(end)?)))
+                .ok_or_else(|| {
+                    CameraError::invalid_config(format!("invalid SRT cue timing '{timing}'"))
+                })?;
+            let body = strip_tags(&lines.collect::<Vec<_>>().join("\n"));
+            entries.push(TelemetryEntry {
+                start,
+                end,
+                fields: srt_fields(&body),
+            });
+        }
+        Ok(Self::from_entries(entries))
+    }
+
+    /// Parses a JSON array of objects, or one object per line, each with a
+    /// `time` in seconds since the first frame and optionally a `duration`;
+    /// an entry without one lasts until the next.
+    pub fn parse_json(text: &str) -> Result<Self, CameraError> {
+        let invalid = |e: serde_json::Error| {
+            CameraError::invalid_config(format!("invalid JSON sidecar: {e}"))
+        };
+        let values = match serde_json::from_str::<Value>(text) {
+            Ok(Value::Array(values)) => values,
+            Ok(value) => vec![value],
+            Err(_) => text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()
+                .map_err(invalid)?,
+        };
+        let mut entries = Vec::with_capacity(values.len());
+        for value in values {
+            let Value::Object(mut fields) = value else {
+                return Err(CameraError::invalid_config(
+                    "JSON sidecar entries must be objects",
+                ));
+            };
+            let seconds = |value: Option<Value>| value.as_ref().and_then(Value::as_f64);
+            let start = seconds(fields.remove("time"))
+                .filter(|t| t.is_finite() && *t >= 0.0)
+                .ok_or_else(|| {
+                    CameraError::invalid_config("JSON sidecar entries need a 'time' in seconds")
+                })?;
+            let start = Duration::from_secs_f64(start);
+            let end = match seconds(fields.remove("duration")) {
+                Some(d) if d.is_finite() && d >= 0.0 => start + Duration::from_secs_f64(d),
+                _ => Duration::MAX,
+            };
+            entries.push(TelemetryEntry { start, end, fields });
+        }
+        entries.sort_by_key(|e| e.start);
+        // Open-ended entries last until the next one starts.
+        for i in 1..entries.len() {
+            let next = entries[i].start;
+            if entries[i - 1].end == Duration::MAX {
+                entries[i - 1].end = next;
+            }
+        }
+        Ok(Self::from_entries(entries))
+    }
+
+    fn from_entries(mut entries: Vec<TelemetryEntry>) -> Self {
+        entries.sort_by_key(|e| e.start);
+        Self {
+            entries: entries.into_iter().map(Arc::new).collect(),
+        }
+    }
+
+    pub fn entries(&self) -> &[Arc<TelemetryEntry>] {
+        &self.entries
+    }
+
+    /// The latest entry that started by `offset` and hasn't ended.
+    pub fn at(&self, offset: Duration) -> Option<&Arc<TelemetryEntry>> {
+        let i = self.entries.partition_point(|e| e.start <= offset);
+        self.entries[..i].last().filter(|e| offset < e.end)
+    }
+}
+
+/// Parses `HH:MM:SS,mmm` (or with a `.`).
+fn srt_time(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (hms, millis) = s.split_once([',', '.']).unwrap_or((s, "0"));
+    let mut parts = hms.split(':').map(|p| p.trim().parse::<u64>().ok());
+    let (h, m, sec) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() {
+        return None;
+    }
+    let millis: u64 = millis.trim().parse().ok()?;
+    Some(Duration::from_millis(
+        ((h * 60 + m) * 60 + sec) * 1000 + millis,
+    ))
+}
+
+/// Removes `<font ...>`-style markup.
+fn strip_tags(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {},
+        }
+    }
+    out
+}
+
+fn srt_fields(body: &str) -> Map<String, Value> {
+    let mut fields = Map::new();
+    let mut rest = String::new();
+    let mut s = body;
+    // `[rel_alt: 1.2 abs_alt: 10.3]` holds two pairs; `[iso : 100]` one.
+    while let Some(open) = s.find('[') {
+        rest.push_str(&s[..open]);
+        let Some(len) = s[open..].find(']') else {
+            s = &s[open..];
+            break;
+        };
+        bracket_pairs(&s[open + 1..open + len], &mut fields);
+        s = &s[open + len + 1..];
+    }
+    rest.push_str(s);
+
+    for line in rest.lines() {
+        let mut line = line.to_string();
+        if let Some(gps) = line.find("GPS")
+            && let Some(open) = line[gps..].find('(').map(|i| gps + i)
+            && let Some(close) = line[open..].find(')').map(|i| open + i)
+        {
+            let coords: Vec<_> = line[open + 1..close].split(',').map(value).collect();
+            for (key, coord) in ["longitude", "latitude", "altitude"].iter().zip(coords) {
+                fields.insert(key.to_string(), coord);
+            }
+            line.replace_range(gps..=close, "");
+        }
+        for pair in line.split(',') {
+            if let Some((key, val)) = pair.split_once(':')
+                && is_key(key.trim())
+            {
+                fields.insert(key.trim().to_string(), value(val));
+            }
+        }
+    }
+    if fields.is_empty() && !body.trim().is_empty() {
+        fields.insert("text".into(), body.trim().into());
+    }
+    fields
+}
+
+/// Splits `key: value key: value ...`, where values may hold spaces.
+fn bracket_pairs(content: &str, fields: &mut Map<String, Value>) {
+    let content = content.replace(" :", ":");
+    let mut key: Option<&str> = None;
+    let mut val: Vec<&str> = Vec::new();
+    for token in content.split_whitespace() {
+        let (name, first) = match token.split_once(':') {
+            Some((name, first)) if is_key(name) => (name, first),
+            _ => {
+                val.push(token);
+                continue;
+            },
+        };
+        if let Some(key) = key.take() {
+            fields.insert(key.to_string(), value(&val.join(" ")));
+        }
+        val.clear();
+        key = Some(name);
+        if !first.is_empty() {
+            val.push(first);
+        }
+    }
+    if let Some(key) = key {
+        fields.insert(key.to_string(), value(&val.join(" ")));
+    }
+}
+
+fn is_key(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic())
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A number if `s` is one, otherwise the trimmed string.
+fn value(s: &str) -> Value {
+    let s = s.trim();
+    if let Ok(n) = s.parse::<i64>() {
+        return n.into();
+    }
+    match s.parse::<f64>().ok().and_then(Number::from_f64) {
+        Some(n) => Value::Number(n),
+        None => s.into(),
+    }
+}
@@ -0,0 +1,359 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Block-matching stereo disparity for the paired frames of two cameras,
+//! without OpenCV: the left camera's blocks are found along the same row
+//! of the right camera's frame, so the frames have to be rectified.
+
+use crate::shared::{
+    BundleSink, CameraError, Frame, FrameBundle, FrameStream, PixelFormat, process::luma,
+};
+use bytes::Bytes;
+use std::sync::Arc;
+
+/// Disparities are stored in sixteenths of a pixel, as OpenCV does.
+pub const DISPARITY_SCALE: f32 = 16.0;
+
+/// The camera geometry that turns disparity into depth.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StereoCalibration {
+    /// The rectified cameras' focal length, in pixels.
+    pub focal_px: f32,
+    /// The distance between the cameras' optical centres, in metres.
+    pub baseline_m: f32,
+}
+
+impl StereoCalibration {
+    /// The depth of a point at `disparity` pixels, in metres.
+    pub fn depth_m(&self, disparity: f32) -> f32 {
+        self.focal_px * self.baseline_m / disparity
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StereoConfig {
+    /// Disparities searched, from 0 to one less than this, in pixels.
+    pub max_disparity: u32,
+    /// Side of the square blocks matched, in pixels; odd.
+    pub block_size: u32,
+    /// How much more every other disparity (but the neighbours of the best)
+    /// must cost than the best, as a fraction of it, for a match to count.
+    pub uniqueness: f32,
+    /// Mean horizontal luma gradient a block needs to be matched, so flat
+    /// areas, which match anywhere, are left out.
+    pub min_texture: f32,
+    /// With a calibration, a depth map is computed too.
+    pub calibration: Option<StereoCalibration>,
+}
+
+impl Default for StereoConfig {
+    fn default() -> Self {
+        Self {
+            max_disparity: 64,
+            block_size: 9,
+            uniqueness: 0.15,
+            min_texture: 2.0,
+            calibration: None,
+        }
+    }
+}
+
+impl StereoConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_disparity(mut self, max_disparity: u32) -> Self {
+        self.max_disparity = max_disparity;
+        self
+    }
+
+    pub fn with_block_size(mut self, block_size: u32) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    pub fn with_uniqueness(mut self, uniqueness: f32) -> Self {
+        self.uniqueness = uniqueness;
+        self
+    }
+
+    pub fn with_min_texture(mut self, min_texture: f32) -> Self {
+        self.min_texture = min_texture;
+        self
+    }
+
+    pub fn with_calibration(mut self, focal_px: f32, baseline_m: f32) -> Self {
+        self.calibration = Some(StereoCalibration {
+            focal_px,
+            baseline_m,
+        });
+        self
+    }
+}
+
+/// Summary of one disparity map, for users that want numbers, not images.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StereoStats {
+    /// Fraction of the pixels that have a disparity.
+    pub valid_fraction: f32,
+    /// Smallest, median and largest disparity, in pixels; `None` without
+    /// any valid pixel.
+    pub min_disparity: Option<f32>,
+    pub median_disparity: Option<f32>,
+    pub max_disparity: Option<f32>,
+    /// Depth of the median disparity, in metres, with a calibration.
+    pub median_depth_m: Option<f32>,
+}
+
+/// What a `StereoMatcher` makes of one pair of frames.
+#[derive(Clone, Debug)]
+pub struct StereoResult {
+    /// Gray16 (`FrameStream::Depth`) disparity in sixteenths of a pixel,
+    /// aligned with the left frame; 0 where nothing matched, and in the
+    /// columns nearer the left edge than the largest disparity.
+    pub disparity: Frame,
+    /// Z16 depth in millimetres, with a calibration; 0 where nothing
+    /// matched.
+    pub depth: Option<Frame>,
+    pub stats: StereoStats,
+}
+
+/// Called with each result of a `StereoMatcher::sink`.
+pub type StereoSink = Arc<dyn Fn(Result<StereoResult, CameraError>) + Send + Sync + 'static>;
+
+/// Sum-of-absolute-differences block matcher, with subpixel refinement.
+#[derive(Clone, Debug)]
+pub struct StereoMatcher {
+    config: StereoConfig,
+}
+
+impl StereoMatcher {
+    pub fn new(config: StereoConfig) -> Result<Self, CameraError> {
+        let StereoConfig {
+            max_disparity,
+            block_size,
+            ..
+        } = config;
+        if block_size < 3 || block_size.is_multiple_of(2) {
+            return Err(CameraError::invalid_config(format!(
+                "stereo block size must be odd and at least 3, not {block_size}"
+            )));
+        }
+        if !(2..=u16::MAX as u32 / DISPARITY_SCALE as u32).contains(&max_disparity) {
+            return Err(CameraError::invalid_config(format!(
+                "stereo disparities must be from 2 to 4095, not {max_disparity}"
+            )));
+        }
+        let positive = |c: StereoCalibration| c.focal_px > 0.0 && c.baseline_m > 0.0;
+        if config.calibration.is_some_and(|c| !positive(c)) {
+            return Err(CameraError::invalid_config(
+                "stereo focal length and baseline must be positive",
+            ));
+        }
+        Ok(Self { config })
+    }
+
+    pub fn config(&self) -> &StereoConfig {
+        &self.config
+    }
+
+    /// Matches a rectified pair of frames of the same size.
+    pub fn compute(&self, left: &Frame, right: &Frame) -> Result<StereoResult, CameraError> {
+        left.check_single_plane()?;
+        right.check_single_plane()?;
+        if (left.width, left.height) != (right.width, right.height) {
+            return Err(CameraError::invalid_config(format!(
+                "stereo frames differ in size ({}x{} and {}x{})",
+                left.width, left.height, right.width, right.height
+            )));
+        }
+        let (w, h) = (left.width as usize, left.height as usize);
+        let disparities = self.disparities(&gray(left), &gray(right), w, h);
+
+        let mut valid: Vec<u16> = disparities.iter().copied().filter(|&d| d != 0).collect();
+        valid.sort_unstable();
+        let px = |d: u16| d as f32 / DISPARITY_SCALE;
+        let median = valid.get(valid.len() / 2).copied().map(px);
+        let stats = StereoStats {
+            valid_fraction: valid.len() as f32 / (w * h) as f32,
+            min_disparity: valid.first().copied().map(px),
+            median_disparity: median,
+            max_disparity: valid.last().copied().map(px),
+            median_depth_m: self
+                .config
+                .calibration
+                .zip(median)
+                .map(|(c, d)| c.depth_m(d)),
+        };
+
+        let depth = self.config.calibration.map(|calibration| {
+            let depth = disparities.iter().map(|&d| match d {
+                0 => 0,
+                d => (calibration.depth_m(px(d)) * 1000.0)
+                    .round()
+                    .min(u16::MAX as f32) as u16,
+            });
+            derive16(left, depth, PixelFormat::Z16)
+        });
+        let disparity = derive16(left, disparities.into_iter(), PixelFormat::Gray16)
+            .with_stream(FrameStream::Depth);
+        Ok(StereoResult {
+            disparity,
+            depth,
+            stats,
+        })
+    }
+
+    /// A sink for `FrameSynchronizer` bundles of two cameras, left first,
+    /// handing each result to `on_result` on the thread that completed the
+    /// bundle.
+    pub fn sink(self, on_result: StereoSink) -> BundleSink {
+        Arc::new(move |bundle: FrameBundle| match bundle.frames.as_slice() {
+            [left, right] => on_result(self.compute(left, right)),
+            frames => on_result(Err(CameraError::invalid_config(format!(
+                "stereo matching needs bundles of two frames, not {}",
+                frames.len()
+            )))),
+        })
+    }
+
+    /// The disparity of every pixel, in sixteenths of a pixel.
+    fn disparities(&self, left: &[u8], right: &[u8], w: usize, h: usize) -> Vec<u16> {
+        let block = self.config.block_size as usize;
+        let r = block / 2;
+        let levels = (self.config.max_disparity as usize).min(w);
+        let mut out = vec![0u16; w * h];
+        if w < block || h < block {
+            return out;
+        }
+        let min_texture = (self.config.min_texture * (block * block) as f32) as u32;
+        let diff =
+            |d: usize, x: usize, y: usize| left[y * w + x].abs_diff(right[y * w + x - d]) as u32;
+        let grad = |x: usize, y: usize| match x {
+            0 => 0,
+            x => left[y * w + x].abs_diff(left[y * w + x - 1]) as u32,
+        };
+
+        // Column sums over the rows of the block, per disparity, slid down a
+        // row at a time; the last "disparity" holds the gradients.
+        let mut columns = vec![0u32; (levels + 1) * w];
+        let add_row = |columns: &mut [u32], y: usize, sign: bool| {
+            for d in 0..levels {
+                for x in d..w {
+                    let c = &mut columns[d * w + x];
+                    *c = if sign {
+                        *c + diff(d, x, y)
+                    } else {
+                        *c - diff(d, x, y)
+                    };
+                }
+            }
+            for x in 0..w {
+                let c = &mut columns[levels * w + x];
+                *c = if sign {
+                    *c + grad(x, y)
+                } else {
+                    *c - grad(x, y)
+                };
+            }
+        };
+        for y in 0..block - 1 {
+            add_row(&mut columns, y, true);
+        }
+
+        let mut costs = vec![u32::MAX; w * levels];
+        for y in r..h - r {
+            add_row(&mut columns, y + r, true);
+
+            costs.fill(u32::MAX);
+            for d in 0..levels {
+                let column = &columns[d * w..][..w];
+                // A block at x needs right-frame pixels from x - r - d.
+                let start = r + d;
+                if start + r >= w {
+                    continue;
+                }
+                let mut sum: u32 = column[start - r..=start + r].iter().sum();
+                for x in start..w - r {
+                    if x > start {
+                        sum = sum + column[x + r] - column[x - r - 1];
+                    }
+                    costs[x * levels + d] = sum;
+                }
+            }
+
+            let texture = &columns[levels * w..];
+            let mut sum: u32 = texture[..block].iter().sum();
+            for x in r..w - r {
+                if x > r {
+                    sum = sum + texture[x + r] - texture[x - r - 1];
+                }
+                // Nearer the left edge than the largest disparity, the match
+                // may lie outside the right frame, so those columns are left out.
+                if x + 1 >= r + levels && sum >= min_texture {
+                    out[y * w + x] = self.best(&costs[x * levels..][..levels]);
+                }
+            }
+
+            add_row(&mut columns, y - r, false);
+        }
+        out
+    }
+
+    /// The disparity a pixel's costs point to, refined by fitting a parabola
+    /// through the best and its neighbours; 0 if it's not unique enough.
+    fn best(&self, costs: &[u32]) -> u16 {
+        let Some((best, &cost)) = costs.iter().enumerate().min_by_key(|&(_, c)| *c) else {
+            return 0;
+        };
+        if cost == u32::MAX {
+            return 0;
+        }
+        let bound = cost as f32 * (1.0 + self.config.uniqueness);
+        let ambiguous = costs
+            .iter()
+            .enumerate()
+            .any(|(d, &c)| d.abs_diff(best) > 1 && (c as f32) < bound);
+        if ambiguous {
+            return 0;
+        }
+        let mut disparity = best as f32;
+        if best > 0 && best + 1 < costs.len() && costs[best + 1] != u32::MAX {
+            let (c0, c1, c2) = (costs[best - 1] as f32, cost as f32, costs[best + 1] as f32);
+            let curvature = c0 - 2.0 * c1 + c2;
+            if curvature > 0.0 {
+                disparity += (c0 - c2) / (2.0 * curvature);
+            }
+        }
+        (disparity * DISPARITY_SCALE).round().max(0.0) as u16
+    }
+}
+
+/// The frame's luma, one byte per pixel.
+fn gray(frame: &Frame) -> Vec<u8> {
+    let bpp = frame.pixel_format.bytes_per_pixel() as usize;
+    let stride = frame.stride as usize;
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let mut out = Vec::with_capacity(w * h);
+    for y in 0..h {
+        let row = &frame.data[y * stride..][..w * bpp];
+        out.extend(row.chunks_exact(bpp).map(|px| luma(px, frame.pixel_format)));
+    }
+    out
+}
+
+/// A 16-bit frame of `samples`, with the timing and metadata of `frame`.
+fn derive16(frame: &Frame, samples: impl Iterator<Item = u16>, format: PixelFormat) -> Frame {
+    let data: Vec<u8> = samples.flat_map(u16::to_le_bytes).collect();
+    let mut out = Frame::new(
+        Bytes::from(data),
+        frame.width,
+        frame.height,
+        frame.width * 2,
+        format,
+    )
+    .with_timestamp_ns(frame.timestamp_ns);
+    out.monotonic_ns = frame.monotonic_ns;
+    out.metadata = frame.metadata.clone();
+    out
+}
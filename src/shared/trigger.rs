@@ -0,0 +1,88 @@
+// This is free and unencumbered software released into the public domain.
+
+//! External trigger mode for machine-vision cameras, which expose a frame
+//! only when their trigger input fires (or software fires it for them),
+//! so several cameras, or a camera and a strobe, expose together.
+
+use crate::shared::devices::xu::VendorControl;
+use std::collections::VecDeque;
+
+/// How to put a camera in trigger mode and fire it. Cameras do this in
+/// vendor-specific ways, so on UVC devices it's a set of extension unit
+/// requests from the vendor's documentation; the mock backend needs none.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TriggerConfig {
+    /// Requests that put the device in trigger mode (and e.g. turn on its
+    /// strobe output), sent before capture starts.
+    pub enable: Vec<VendorControl>,
+    /// Requests that restore free-running capture, sent after it stops.
+    pub disable: Vec<VendorControl>,
+    /// The request that fires one software trigger.
+    pub fire: Option<VendorControl>,
+}
+
+impl TriggerConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_enable(mut self, request: VendorControl) -> Self {
+        self.enable.push(request);
+        self
+    }
+
+    pub fn with_disable(mut self, request: VendorControl) -> Self {
+        self.disable.push(request);
+        self
+    }
+
+    pub fn with_fire(mut self, request: VendorControl) -> Self {
+        self.fire = Some(request);
+        self
+    }
+}
+
+/// Marks a frame exposed in trigger mode (`FrameMetadata::trigger`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TriggeredExposure {
+    /// The frame's place among the triggered frames since capture started,
+    /// from 1, so rigs can pair up the frames of each trigger.
+    pub sequence: u64,
+    /// When `Camera::fire_trigger` fired the trigger this frame answers,
+    /// on the monotonic clock; `None` for the trigger input.
+    pub fired_ns: Option<u64>,
+}
+
+/// The dispatcher's count of triggered frames, and the software triggers
+/// still waiting for theirs.
+#[derive(Debug, Default)]
+pub(crate) struct TriggerTrack {
+    sequence: u64,
+    fired: VecDeque<u64>,
+}
+
+impl TriggerTrack {
+    /// Software triggers a camera never answered are forgotten past this.
+    const MAX_PENDING: usize = 64;
+
+    pub(crate) fn fired(&mut self, monotonic_ns: u64) {
+        if self.fired.len() == Self::MAX_PENDING {
+            self.fired.pop_front();
+        }
+        self.fired.push_back(monotonic_ns);
+    }
+
+    /// Marks the next frame, captured at `monotonic_ns`, answering the
+    /// oldest software trigger fired before it.
+    pub(crate) fn mark(&mut self, monotonic_ns: u64) -> TriggeredExposure {
+        self.sequence += 1;
+        let fired_ns = match self.fired.front() {
+            Some(&fired) if fired <= monotonic_ns => self.fired.pop_front(),
+            _ => None,
+        };
+        TriggeredExposure {
+            sequence: self.sequence,
+            fired_ns,
+        }
+    }
+}
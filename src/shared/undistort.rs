@@ -0,0 +1,129 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::{CameraError, Frame, LensCalibration, PixelFormat};
+
+/// Removes lens distortion by remapping frames through a calibration,
+/// keeping its camera matrix, so straight lines come out straight. The
+/// map is worked out once per frame size; frames of another size than the
+/// calibration's use it scaled.
+#[derive(Clone, Debug)]
+pub struct Undistorter {
+    calibration: LensCalibration,
+    map: Option<RemapTable>,
+}
+
+/// Where each output pixel comes from: the top-left source pixel's offset
+/// and the bilinear weights towards the next column and row, in 1/256.
+#[derive(Clone, Debug)]
+struct RemapTable {
+    width: u32,
+    height: u32,
+    sources: Vec<Option<(u32, u8, u8)>>,
+}
+
+impl Undistorter {
+    pub fn new(calibration: LensCalibration) -> Self {
+        Self {
+            calibration,
+            map: None,
+        }
+    }
+
+    pub fn calibration(&self) -> &LensCalibration {
+        &self.calibration
+    }
+
+    /// The undistorted frame, as a new packed frame. 8-bit formats are
+    /// interpolated; 16-bit and 10-bit ones take the nearest pixel, so
+    /// depth doesn't blend across edges. Pixels the lens never saw are 0.
+    pub fn apply(&mut self, frame: &Frame) -> Result<Frame, CameraError> {
+        frame.check_single_plane()?;
+        let (w, h) = (frame.width, frame.height);
+        if self
+            .map
+            .as_ref()
+            .is_some_and(|m| (m.width, m.height) != (w, h))
+        {
+            self.map = None;
+        }
+        let map = self
+            .map
+            .get_or_insert_with(|| RemapTable::new(&self.calibration.scaled_to(w, h)));
+
+        let bpp = frame.pixel_format.bytes_per_pixel() as usize;
+        let stride = frame.stride as usize;
+        let interpolate = matches!(
+            frame.pixel_format,
+            PixelFormat::Rgb8 | PixelFormat::Rgba8 | PixelFormat::Bgra8
+        );
+        let data = &frame.data;
+        let mut out = vec![0u8; w as usize * h as usize * bpp];
+        for (px, source) in out.chunks_exact_mut(bpp).zip(&map.sources) {
+            let Some((offset, fx, fy)) = *source else {
+                continue;
+            };
+            let (x, y) = (offset % w, offset / w);
+            let at = |x: u32, y: u32| y as usize * stride + x as usize * bpp;
+            let (x1, y1) = ((x + 1).min(w - 1), (y + 1).min(h - 1));
+            if !interpolate {
+                let (nx, ny) = (if fx < 128 { x } else { x1 }, if fy < 128 { y } else { y1 });
+                px.copy_from_slice(&data[at(nx, ny)..][..bpp]);
+                continue;
+            }
+            let (fx, fy) = (fx as u32, fy as u32);
+            let corners = [at(x, y), at(x1, y), at(x, y1), at(x1, y1)];
+            let weights = [
+                (256 - fx) * (256 - fy),
+                fx * (256 - fy),
+                (256 - fx) * fy,
+                fx * fy,
+            ];
+            for (c, v) in px.iter_mut().enumerate() {
+                let sum: u32 = corners
+                    .iter()
+                    .zip(weights)
+                    .map(|(&i, weight)| data[i + c] as u32 * weight)
+                    .sum();
+                *v = ((sum + (1 << 15)) >> 16) as u8;
+            }
+        }
+        Ok(frame.derive(out, w, h))
+    }
+}
+
+impl RemapTable {
+    fn new(calibration: &LensCalibration) -> Self {
+        let LensCalibration {
+            width,
+            height,
+            fx,
+            fy,
+            cx,
+            cy,
+            ..
+        } = *calibration;
+        let mut sources = Vec::with_capacity(width as usize * height as usize);
+        for v in 0..height {
+            for u in 0..width {
+                let (x, y) = ((u as f64 - cx) / fx, (v as f64 - cy) / fy);
+                let (sx, sy) = calibration.project(x, y);
+                let inside =
+                    sx >= 0.0 && sy >= 0.0 && sx <= (width - 1) as f64 && sy <= (height - 1) as f64;
+                sources.push(inside.then(|| {
+                    let (x0, y0) = (sx.floor(), sy.floor());
+                    let weight = |f: f64| ((f * 256.0).round() as u32).min(255) as u8;
+                    (
+                        y0 as u32 * width + x0 as u32,
+                        weight(sx - x0),
+                        weight(sy - y0),
+                    )
+                }));
+            }
+        }
+        Self {
+            width,
+            height,
+            sources,
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// This is free and unencumbered software released into the public domain.
+
+use crate::shared::CameraError;
+use crate::shared::{CameraBackend, CameraEvent, Frame};
+use core::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+};
+use std::sync::mpsc::SyncSender;
+
+/// What `try_send_frame` does with frames whose buffer doesn't match their
+/// dimensions, as truncated reads and stride mismatches produce.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FrameValidation {
+    /// Deliver every frame unchecked.
+    Off,
+    /// Deliver malformed frames, but report them as `CameraEvent::Warning`s.
+    Warn,
+    /// Report malformed frames and discard them.
+    #[default]
+    Drop,
+}
+
+impl FrameValidation {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => FrameValidation::Off,
+            1 => FrameValidation::Warn,
+            _ => FrameValidation::Drop,
+        }
+    }
+}
+
+impl FromStr for FrameValidation {
+    type Err = CameraError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(FrameValidation::Off),
+            "warn" => Ok(FrameValidation::Warn),
+            "drop" => Ok(FrameValidation::Drop),
+            other => Err(CameraError::invalid_config(format!(
+                "unknown frame validation '{other}' (expected off, warn or drop)"
+            ))),
+        }
+    }
+}
+
+/// Why `Frame::check` rejected a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameDefect {
+    /// Zero width, height or stride.
+    Empty {
+        width: u32,
+        height: u32,
+        stride: u32,
+    },
+    /// Rows shorter than `width` pixels need.
+    StrideTooSmall { stride: u32, row_bytes: u64 },
+    /// Fewer bytes than `stride * height`, as a short read leaves.
+    Truncated { len: usize, expected: usize },
+    /// A 4:2:0 frame with an odd width or height, which its chroma plane
+    /// can't cover.
+    OddSubsampled { width: u32, height: u32 },
+}
+
+impl fmt::Display for FrameDefect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameDefect::Empty {
+                width,
+                height,
+                stride,
+            } => write!(f, "empty {width}x{height} frame with stride {stride}"),
+            FrameDefect::StrideTooSmall { stride, row_bytes } => {
+                write!(f, "stride {stride} is shorter than a {row_bytes}-byte row")
+            },
+            FrameDefect::Truncated { len, expected } => {
+                write!(f, "truncated frame: {len} of {expected} bytes")
+            },
+            FrameDefect::OddSubsampled { width, height } => {
+                write!(f, "{width}x{height} 4:2:0 frame has an odd dimension")
+            },
+        }
+    }
+}
+
+/// A 64-bit FNV-1a hash of `data`, a word at a time, for telling whether a
+/// buffer changed; not for anything adversarial.
+pub fn frame_checksum(data: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut chunks = data.chunks_exact(8);
+    let mut hash = OFFSET;
+    for chunk in &mut chunks {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap_or_default());
+        hash = (hash ^ word).wrapping_mul(PRIME);
+    }
+    for &byte in chunks.remainder() {
+        hash = (hash ^ byte as u64).wrapping_mul(PRIME);
+    }
+    hash
+}
+
+/// The validation settings and counters a dispatcher shares with its
+/// drivers' `FrameSender`s.
+#[derive(Debug)]
+pub(crate) struct FrameChecks {
+    validation: AtomicU8,
+    checksums: AtomicBool,
+    malformed: AtomicU64,
+    torn: AtomicU64,
+}
+
+impl Default for FrameChecks {
+    fn default() -> Self {
+        Self {
+            validation: AtomicU8::new(FrameValidation::default() as u8),
+            checksums: AtomicBool::new(false),
+            malformed: AtomicU64::new(0),
+            torn: AtomicU64::new(0),
+        }
+    }
+}
+
+impl FrameChecks {
+    pub(crate) fn set_validation(&self, validation: FrameValidation) {
+        self.validation.store(validation as u8, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_checksums(&self, enabled: bool) {
+        self.checksums.store(enabled, Ordering::Relaxed);
+    }
+
+    pub(crate) fn malformed(&self) -> u64 {
+        self.malformed.load(Ordering::Relaxed)
+    }
+
+    /// Checks a frame on its way from the driver, stamping its checksum if
+    /// enabled; returns whether to send it on.
+    pub(crate) fn admit(
+        &self,
+        frame: &mut Frame,
+        events_tx: &SyncSender<CameraEvent>,
+        backend: CameraBackend,
+    ) -> bool {
+        let validation = FrameValidation::from_u8(self.validation.load(Ordering::Relaxed));
+        if validation != FrameValidation::Off
+            && let Err(defect) = frame.check()
+        {
+            let count = self.malformed.fetch_add(1, Ordering::Relaxed) + 1;
+            let dropped = validation == FrameValidation::Drop;
+            warn_sparsely(events_tx, backend, count, || {
+                let action = if dropped { "dropped" } else { "delivered" };
+                format!("{action} a malformed frame: {defect}")
+            });
+            if dropped {
+                return false;
+            }
+        }
+        if self.checksums.load(Ordering::Relaxed) {
+            frame.metadata.checksum = Some(frame_checksum(&frame.data));
+        }
+        true
+    }
+
+    /// Compares a frame about to be delivered against the checksum it was
+    /// sent with: a mismatch means the backend reused the buffer while the
+    /// frame was queued, i.e. a torn frame.
+    pub(crate) fn verify(
+        &self,
+        frame: &Frame,
+        events_tx: &SyncSender<CameraEvent>,
+        backend: CameraBackend,
+    ) {
+        let Some(sent) = frame.metadata.checksum else {
+            return;
+        };
+        let now = frame_checksum(&frame.data);
+        if now != sent {
+            let count = self.torn.fetch_add(1, Ordering::Relaxed) + 1;
+            warn_sparsely(events_tx, backend, count, || {
+                format!(
+                    "frame changed between capture and delivery (checksum {sent:016x}, now {now:016x}): the backend tore it"
+                )
+            });
+        }
+    }
+}
+
+/// Warns about the `count`th occurrence of a problem if `count` is a power
+/// of two, so a stream of bad frames doesn't flood the event queue.
+fn warn_sparsely(
+    events_tx: &SyncSender<CameraEvent>,
+    backend: CameraBackend,
+    count: u64,
+    message: impl FnOnce() -> String,
+) {
+    if !count.is_power_of_two() {
+        return;
+    }
+    let mut message = message();
+    if count > 1 {
+        message += &format!(" ({count} so far)");
+    }
+    let _ = events_tx.try_send(CameraEvent::Warning { backend, message });
+}
@@ -0,0 +1,229 @@
+// This is free and unencumbered software released into the public domain.
+
+//! POSTing JSON to webhooks, for chat (Slack, Discord) and home automation
+//! (Home Assistant, Node-RED, n8n) without glue in between.
+//!
+//! A webhook gets the payload itself, or a template filled in from it: each
+//! `{{field}}`, or `{{field.nested}}`, is replaced with the payload's value,
+//! strings escaped to fit inside a JSON string and anything else as JSON.
+//! Slack and Discord URLs get a template of their own unless another is
+//! given. Deliveries that fail on the way, or that the server is too busy
+//! for (5xx, 408, 429), are retried with exponential backoff.
+
+use crate::shared::{
+    CameraError,
+    http::{self, MAX_BACKOFF, Url},
+};
+use core::time::Duration;
+use serde_json::Value;
+use std::{io::Write, path::Path, sync::Arc};
+
+/// What Slack's incoming webhooks take.
+const SLACK_TEMPLATE: &str = r#"{"text":"{{message}}"}"#;
+
+/// What Discord's webhooks take.
+const DISCORD_TEMPLATE: &str = r#"{"content":"{{message}}"}"#;
+
+/// Why an attempt failed, and whether and when another might succeed.
+struct Failure {
+    error: CameraError,
+    retry: bool,
+    retry_after: Option<Duration>,
+}
+
+/// A URL to POST payloads to.
+pub struct Webhook {
+    raw: String,
+    url: Url,
+    template: Option<String>,
+    headers: Vec<(String, String)>,
+    retries: u32,
+    backoff: Duration,
+    tls: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl core::fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // The URL's path is often its secret, so only the host shows.
+        f.debug_struct("Webhook")
+            .field("host", &self.url.host)
+            .field("template", &self.template)
+            .finish()
+    }
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Result<Self, CameraError> {
+        let parsed = Url::parse(url)
+            .map_err(|e| CameraError::invalid_config(format!("webhook URL: {e}")))?;
+        let template = match parsed.host.as_str() {
+            "hooks.slack.com" => Some(SLACK_TEMPLATE.to_string()),
+            "discord.com" | "discordapp.com" if parsed.path.starts_with("/api/webhooks/") => {
+                Some(DISCORD_TEMPLATE.to_string())
+            },
+            _ => None,
+        };
+        let tls = match parsed.tls {
+            true => Some(http::tls_config(None)?),
+            false => None,
+        };
+        Ok(Self {
+            raw: url.trim().to_string(),
+            url: parsed,
+            template,
+            headers: Vec::new(),
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            tls,
+        })
+    }
+
+    /// Sends `template` filled in from each payload instead of the payload.
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.template = Some(template.into());
+        self
+    }
+
+    /// Adds a header to each request, e.g. `Authorization`; a
+    /// `Content-Type` replaces `application/json`.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Retries a failed delivery up to `retries` times, `backoff` after the
+    /// first attempt and twice as long after each one since.
+    pub fn with_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Verifies an `https://` server with the certificates in the PEM file
+    /// `ca` instead of the system's.
+    pub fn with_ca(mut self, ca: &Path) -> Result<Self, CameraError> {
+        if self.url.tls {
+            self.tls = Some(http::tls_config(Some(ca))?);
+        }
+        Ok(self)
+    }
+
+    /// The body that `payload` is sent as.
+    pub fn body(&self, payload: &Value) -> String {
+        match &self.template {
+            Some(template) => render_template(template, payload),
+            None => payload.to_string(),
+        }
+    }
+
+    /// POSTs `payload`, retrying as configured. Returns how many attempts
+    /// the delivery took.
+    pub fn post(&self, payload: &Value) -> Result<u32, CameraError> {
+        let body = self.body(payload);
+        let mut backoff = self.backoff;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match self.post_once(&body) {
+                Ok(()) => return Ok(attempts),
+                Err(failure) if !failure.retry || attempts > self.retries => {
+                    return Err(failure.error);
+                },
+                Err(failure) => {
+                    // Rate limits say how long to wait, up to a point.
+                    let wait = failure.retry_after.unwrap_or(backoff).min(MAX_BACKOFF);
+                    std::thread::sleep(wait);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                },
+            }
+        }
+    }
+
+    fn post_once(&self, body: &str) -> Result<(), Failure> {
+        let host = &self.url.host;
+        let failed = |reason: String| {
+            CameraError::other(format!("posting to the webhook at {host}: {reason}"))
+        };
+        let transient = |e: std::io::Error| Failure {
+            error: failed(e.to_string()),
+            retry: true,
+            retry_after: None,
+        };
+
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nhost: {}\r\ncontent-length: {}\r\n",
+            self.url.path,
+            self.url.host_header(),
+            body.len()
+        );
+        if !self
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        {
+            request.push_str("content-type: application/json\r\n");
+        }
+        for (name, value) in &self.headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("user-agent: asimov-camera-module\r\nconnection: close\r\n\r\n");
+        request.push_str(body);
+
+        let mut stream = http::connect(&self.url, self.tls.as_ref()).map_err(transient)?;
+        stream.write_all(request.as_bytes()).map_err(transient)?;
+        stream.flush().map_err(transient)?;
+        let response = http::read_response(&mut *stream).map_err(transient)?;
+        if response.is_success() {
+            return Ok(());
+        }
+        let message = String::from_utf8_lossy(&response.body);
+        let message = message.trim();
+        let reason = match message.is_empty() || message.len() > 200 {
+            true => format!("HTTP {}", response.status),
+            false => format!("HTTP {}: {message}", response.status),
+        };
+        Err(Failure {
+            error: failed(reason),
+            retry: http::is_transient(response.status),
+            retry_after: response
+                .header("retry-after")
+                .and_then(|secs| secs.parse::<u64>().ok())
+                .map(Duration::from_secs),
+        })
+    }
+
+    /// The URL as given.
+    pub fn url(&self) -> &str {
+        &self.raw
+    }
+}
+
+/// Fills in each `{{path}}` in `template` with the value at the dotted
+/// `path` in `payload`: strings JSON-escaped without their quotes, other
+/// values as JSON, and nothing for a missing one.
+pub fn render_template(template: &str, payload: &Value) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let path = rest[start + 2..start + 2 + end].trim();
+        let value = path.split('.').try_fold(payload, |value, key| match value {
+            Value::Array(items) => key.parse().ok().and_then(|i: usize| items.get(i)),
+            _ => value.get(key),
+        });
+        match value {
+            None | Some(Value::Null) => {},
+            Some(Value::String(s)) => {
+                let quoted = Value::from(s.as_str()).to_string();
+                out.push_str(&quoted[1..quoted.len() - 1]);
+            },
+            Some(value) => out.push_str(&value.to_string()),
+        }
+        rest = &rest[start + 2 + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
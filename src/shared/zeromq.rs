@@ -0,0 +1,189 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Frames published on a ZeroMQ PUB socket.
+//!
+//! Each frame is one four-part message: the topic (`frame.color`,
+//! `frame.depth` or `frame.infrared`), a [`FrameEnvelope`], the tightly
+//! packed pixels (empty for metadata-only publishing) and an optional
+//! metadata record (empty if none). Subscribers filter on the topic prefix,
+//! e.g. `frame.` for everything or `frame.depth` for depth only.
+
+use crate::shared::{CameraError, Frame, FrameStream, PixelFormat};
+use bytes::Bytes;
+use std::sync::Mutex;
+
+/// Outgoing messages queued per subscriber before PUB starts dropping.
+const SEND_HIGH_WATER_MARK: i32 = 4;
+
+/// The 32-byte header of a published frame, little-endian: magic `ACF1`,
+/// pixel format (1 rgb8, 2 bgra8, 3 rgba8, 4 gray16, 5 z16, 6 p010,
+/// 7 rgba1010102), stream (0 color, 1 depth, 2 infrared), two reserved
+/// bytes, width and height as `u32`, then the capture timestamp in ns and a sequence number as `u64`.
+/// Gaps in the sequence are frames the subscriber missed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameEnvelope {
+    pub pixel_format: PixelFormat,
+    pub stream: FrameStream,
+    pub width: u32,
+    pub height: u32,
+    pub timestamp_ns: u64,
+    pub sequence: u64,
+}
+
+impl FrameEnvelope {
+    pub const MAGIC: [u8; 4] = *b"ACF1";
+    pub const LEN: usize = 32;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut out = [0u8; Self::LEN];
+        out[0..4].copy_from_slice(&Self::MAGIC);
+        out[4] = match self.pixel_format {
+            PixelFormat::Rgb8 => 1,
+            PixelFormat::Bgra8 => 2,
+            PixelFormat::Rgba8 => 3,
+            PixelFormat::Gray16 => 4,
+            PixelFormat::Z16 => 5,
+            PixelFormat::P010 => 6,
+            PixelFormat::Rgba1010102 => 7,
+        };
+        out[5] = match self.stream {
+            FrameStream::Color => 0,
+            FrameStream::Depth => 1,
+            FrameStream::Infrared => 2,
+        };
+        out[8..12].copy_from_slice(&self.width.to_le_bytes());
+        out[12..16].copy_from_slice(&self.height.to_le_bytes());
+        out[16..24].copy_from_slice(&self.timestamp_ns.to_le_bytes());
+        out[24..32].copy_from_slice(&self.sequence.to_le_bytes());
+        out
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, CameraError> {
+        let invalid = || CameraError::other("not a camera frame envelope");
+        if bytes.len() != Self::LEN || bytes[0..4] != Self::MAGIC {
+            return Err(invalid());
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        Ok(Self {
+            pixel_format: match bytes[4] {
+                1 => PixelFormat::Rgb8,
+                2 => PixelFormat::Bgra8,
+                3 => PixelFormat::Rgba8,
+                4 => PixelFormat::Gray16,
+                5 => PixelFormat::Z16,
+                6 => PixelFormat::P010,
+                7 => PixelFormat::Rgba1010102,
+                _ => return Err(invalid()),
+            },
+            stream: match bytes[5] {
+                0 => FrameStream::Color,
+                1 => FrameStream::Depth,
+                2 => FrameStream::Infrared,
+                _ => return Err(invalid()),
+            },
+            width: u32_at(8),
+            height: u32_at(12),
+            timestamp_ns: u64_at(16),
+            sequence: u64_at(24),
+        })
+    }
+
+    /// Rebuilds the frame from the envelope and its pixel part.
+    pub fn into_frame(self, pixels: Bytes) -> Result<Frame, CameraError> {
+        let frame = Frame::new(
+            pixels,
+            self.width,
+            self.height,
+            self.width * self.pixel_format.bytes_per_pixel(),
+            self.pixel_format,
+        )
+        .with_stream(self.stream)
+        .with_timestamp_ns(self.timestamp_ns);
+        if !frame.validate() {
+            return Err(CameraError::other(
+                "pixel part doesn't match the frame envelope",
+            ));
+        }
+        Ok(frame)
+    }
+}
+
+/// Publishes frames on a ZeroMQ PUB socket bound to an endpoint such as
+/// `tcp://*:5555` or `ipc:///tmp/camera0`. PUB never blocks: subscribers
+/// that fall behind lose frames, which the envelope's sequence reveals.
+pub struct ZmqPublisher {
+    socket: Mutex<(zmq::Socket, u64)>,
+    endpoint: String,
+}
+
+impl core::fmt::Debug for ZmqPublisher {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ZmqPublisher")
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+impl ZmqPublisher {
+    pub fn bind(endpoint: &str) -> Result<Self, CameraError> {
+        let socket = zmq::Context::new()
+            .socket(zmq::PUB)
+            .map_err(|e| CameraError::driver("creating ZeroMQ socket", e))?;
+        socket
+            .set_sndhwm(SEND_HIGH_WATER_MARK)
+            .and_then(|()| socket.set_linger(0))
+            .map_err(|e| CameraError::driver("configuring ZeroMQ socket", e))?;
+        socket.bind(endpoint).map_err(|e| {
+            CameraError::invalid_config(format!("binding ZeroMQ endpoint '{endpoint}': {e}"))
+        })?;
+        Ok(Self {
+            socket: Mutex::new((socket, 0)),
+            endpoint: endpoint.to_string(),
+        })
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Sends `frame`, with its pixels if `pixels` is set and `metadata`
+    /// (e.g. an encoded JSON record) as the last part.
+    pub fn publish(
+        &self,
+        frame: &Frame,
+        pixels: bool,
+        metadata: Option<&[u8]>,
+    ) -> Result<(), CameraError> {
+        if !frame.validate() {
+            return Err(CameraError::other("refusing to publish a malformed frame"));
+        }
+        let packed = if pixels {
+            frame.clone().ensure_packed()?.data
+        } else {
+            Bytes::new()
+        };
+
+        let mut guard = self.socket.lock().unwrap_or_else(|p| p.into_inner());
+        let (socket, sequence) = &mut *guard;
+        let envelope = FrameEnvelope {
+            pixel_format: frame.pixel_format,
+            stream: frame.stream,
+            width: frame.width,
+            height: frame.height,
+            timestamp_ns: frame.timestamp_ns,
+            sequence: *sequence,
+        };
+        *sequence += 1;
+        let topic = format!("frame.{}", frame.stream.as_str());
+        let parts: [&[u8]; 4] = [
+            topic.as_bytes(),
+            &envelope.to_bytes(),
+            &packed,
+            metadata.unwrap_or_default(),
+        ];
+        socket
+            .send_multipart(parts, zmq::DONTWAIT)
+            .map_err(|e| CameraError::driver("publishing on ZeroMQ", e))
+    }
+}
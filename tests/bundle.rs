@@ -0,0 +1,93 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::{Frame, FrameSynchronizer, MAX_UNPAIRED_FRAMES, PixelFormat};
+use bytes::Bytes;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// A 1x1 frame captured `ms` milliseconds into the run.
+fn frame(ms: u64) -> Frame {
+    let mut frame = Frame::new(Bytes::from_static(&[0; 3]), 1, 1, 3, PixelFormat::Rgb8);
+    frame.monotonic_ns = ms * 1_000_000;
+    frame
+}
+
+fn captured_ms(frame: &Frame) -> u64 {
+    frame.monotonic_ns / 1_000_000
+}
+
+#[test]
+fn pairs_frames_within_the_tolerance() {
+    let sync = FrameSynchronizer::new(2, Duration::from_millis(5));
+    assert!(sync.push(0, frame(100)).is_none());
+    let bundle = sync.push(1, frame(103)).unwrap();
+    assert_eq!(
+        bundle.frames.iter().map(captured_ms).collect::<Vec<_>>(),
+        [100, 103]
+    );
+    assert_eq!(bundle.monotonic_ns(), 100_000_000);
+    assert_eq!(bundle.spread(), Duration::from_millis(3));
+    assert_eq!(sync.unpaired(), 0);
+}
+
+#[test]
+fn bundles_keep_the_source_order() {
+    let sync = FrameSynchronizer::new(3, Duration::from_millis(5));
+    assert!(sync.push(2, frame(101)).is_none());
+    assert!(sync.push(0, frame(102)).is_none());
+    let bundle = sync.push(1, frame(100)).unwrap();
+    assert_eq!(
+        bundle.frames.iter().map(captured_ms).collect::<Vec<_>>(),
+        [102, 100, 101]
+    );
+}
+
+#[test]
+fn drops_frames_the_other_cameras_missed() {
+    let sync = FrameSynchronizer::new(2, Duration::from_millis(5));
+    // Camera 1 dropped the frame matching camera 0's first.
+    assert!(sync.push(0, frame(100)).is_none());
+    assert!(sync.push(0, frame(133)).is_none());
+    let bundle = sync.push(1, frame(134)).unwrap();
+    assert_eq!(
+        bundle.frames.iter().map(captured_ms).collect::<Vec<_>>(),
+        [133, 134]
+    );
+    assert_eq!(sync.unpaired(), 1);
+}
+
+#[test]
+fn gives_up_on_a_silent_camera() {
+    let sync = FrameSynchronizer::new(2, Duration::from_millis(5));
+    let extra = 3;
+    for i in 0..(MAX_UNPAIRED_FRAMES + extra) as u64 {
+        assert!(sync.push(0, frame(i * 33)).is_none());
+    }
+    assert_eq!(sync.unpaired(), extra as u64);
+    // Frames from an unknown source are ignored.
+    assert!(sync.push(2, frame(0)).is_none());
+}
+
+#[test]
+fn sinks_hand_on_completed_bundles() {
+    let sync = Arc::new(FrameSynchronizer::new(2, Duration::from_millis(10)));
+    let bundles = Arc::new(Mutex::new(Vec::new()));
+    let on_bundle = {
+        let bundles = Arc::clone(&bundles);
+        Arc::new(move |bundle| bundles.lock().unwrap().push(bundle))
+    };
+    let (left, right) = (sync.sink(0, on_bundle.clone()), sync.sink(1, on_bundle));
+    for i in 0..4 {
+        left(frame(i * 33));
+        right(frame(i * 33 + 2));
+    }
+    let bundles = bundles.lock().unwrap();
+    assert_eq!(bundles.len(), 4);
+    assert!(
+        bundles
+            .iter()
+            .all(|b| b.spread() == Duration::from_millis(2))
+    );
+}
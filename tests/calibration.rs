@@ -0,0 +1,281 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::{
+    Calibrator, Checkerboard, Frame, LensCalibration, PixelFormat, Undistorter,
+};
+use bytes::Bytes;
+
+const BOARD: Checkerboard = Checkerboard { cols: 7, rows: 5 };
+
+/// A wide-angle camera with barrel distortion.
+fn lens() -> LensCalibration {
+    LensCalibration {
+        width: 320,
+        height: 240,
+        fx: 500.0,
+        fy: 505.0,
+        cx: 163.0,
+        cy: 118.0,
+        distortion: [-0.25, 0.08, 0.001, -0.002, 0.0],
+    }
+}
+
+type Pose = ([[f64; 3]; 3], [f64; 3]);
+
+/// The board tilted by `ax` and `ay` radians, its centre at `centre`.
+fn pose(ax: f64, ay: f64, centre: [f64; 3]) -> Pose {
+    let (sx, cx) = ax.sin_cos();
+    let (sy, cy) = ay.sin_cos();
+    let r = [
+        [cy, 0.0, sy],
+        [sx * sy, cx, -sx * cy],
+        [-cx * sy, sx, cx * cy],
+    ];
+    // Board coordinates put the inner corners at 0..cols and 0..rows.
+    let (mx, my) = ((BOARD.cols - 1) as f64 / 2.0, (BOARD.rows - 1) as f64 / 2.0);
+    let t = [0, 1, 2].map(|i| centre[i] - r[i][0] * mx - r[i][1] * my);
+    (r, t)
+}
+
+fn poses() -> Vec<Pose> {
+    vec![
+        pose(0.0, 0.0, [0.0, 0.0, 16.0]),
+        pose(0.45, 0.0, [0.5, 0.3, 17.0]),
+        pose(-0.4, 0.2, [-0.4, 0.2, 16.0]),
+        pose(0.0, 0.5, [0.6, -0.3, 17.0]),
+        pose(0.2, -0.45, [-0.6, 0.0, 16.5]),
+        pose(-0.3, -0.3, [1.5, 1.0, 18.0]),
+        pose(0.3, 0.35, [-1.5, -1.0, 18.0]),
+    ]
+}
+
+/// The board's inner corners as the lens sees them, row by row.
+fn corners(lens: &LensCalibration, (r, t): &Pose) -> Vec<[f64; 2]> {
+    (0..BOARD.rows)
+        .flat_map(|j| (0..BOARD.cols).map(move |i| (i as f64, j as f64)))
+        .map(|(x, y)| {
+            let p = [0, 1, 2].map(|k| r[k][0] * x + r[k][1] * y + t[k]);
+            let (u, v) = lens.project(p[0] / p[2], p[1] / p[2]);
+            [u, v]
+        })
+        .collect()
+}
+
+/// The point of the normalized image plane that `lens` distorts to
+/// `(xd, yd)`.
+fn undistort_point(lens: &LensCalibration, xd: f64, yd: f64) -> (f64, f64) {
+    let (mut x, mut y) = (xd, yd);
+    for _ in 0..20 {
+        let (dx, dy) = lens.distort(x, y);
+        x += xd - dx;
+        y += yd - dy;
+    }
+    (x, y)
+}
+
+/// Renders the board at `pose` through `lens`: black and white squares
+/// with a white margin, on grey, supersampled.
+fn render(lens: &LensCalibration, (r, t): &Pose) -> Frame {
+    let (w, h) = (lens.width, lens.height);
+    let shade = |u: f64, v: f64| -> f64 {
+        let (x, y) = undistort_point(lens, (u - lens.cx) / lens.fx, (v - lens.cy) / lens.fy);
+        // Solve s (x, y, 1) = X r0 + Y r1 + t for the board point (X, Y).
+        let m = [
+            [r[0][0], r[0][1], -x],
+            [r[1][0], r[1][1], -y],
+            [r[2][0], r[2][1], -1.0],
+        ];
+        let det = |m: &[[f64; 3]; 3]| {
+            m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+        };
+        let d = det(&m);
+        let solve = |c: usize| {
+            let mut mc = m;
+            for k in 0..3 {
+                mc[k][c] = -t[k];
+            }
+            det(&mc) / d
+        };
+        let (bx, by) = (solve(0), solve(1));
+        let (cols, rows) = (BOARD.cols as f64, BOARD.rows as f64);
+        if bx < -2.0 || by < -2.0 || bx > cols + 1.0 || by > rows + 1.0 {
+            return 128.0;
+        }
+        if bx < -1.0 || by < -1.0 || bx > cols || by > rows {
+            return 230.0;
+        }
+        if (bx.floor() as i64 + by.floor() as i64) % 2 == 0 {
+            20.0
+        } else {
+            230.0
+        }
+    };
+    let mut data = Vec::with_capacity((w * h * 3) as usize);
+    for v in 0..h {
+        for u in 0..w {
+            let offsets = [0.25, 0.75];
+            let sum: f64 = offsets
+                .iter()
+                .flat_map(|dy| offsets.iter().map(move |dx| (dx, dy)))
+                .map(|(dx, dy)| shade(u as f64 + dx - 0.5, v as f64 + dy - 0.5))
+                .sum();
+            data.extend([(sum / 4.0).round() as u8; 3]);
+        }
+    }
+    Frame::new(Bytes::from(data), w, h, w * 3, PixelFormat::Rgb8)
+}
+
+fn rms(a: &[[f64; 2]], b: &[[f64; 2]]) -> f64 {
+    let sum: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(p, q)| (p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2))
+        .sum();
+    (sum / a.len() as f64).sqrt()
+}
+
+/// The corners in the order `Checkerboard::find` picks, which starts at
+/// whichever end of the board is nearer the image's top left.
+fn oriented(mut points: Vec<[f64; 2]>) -> Vec<[f64; 2]> {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    if first[0] + first[1] > last[0] + last[1] {
+        points.reverse();
+    }
+    points
+}
+
+#[test]
+fn parses_lens_calibrations() {
+    let lens = lens();
+    assert_eq!(lens.to_string().parse::<LensCalibration>().unwrap(), lens);
+    let short: LensCalibration = "640x480:600,600,320,240:-0.1".parse().unwrap();
+    assert_eq!(short.distortion, [-0.1, 0.0, 0.0, 0.0, 0.0]);
+    let pinhole: LensCalibration = "640x480:600,600,320,240".parse().unwrap();
+    assert_eq!(pinhole.distortion, [0.0; 5]);
+    for invalid in [
+        "640x480",
+        "640x480:600,600,320",
+        "0x480:600,600,320,240",
+        "640x480:-600,600,320,240",
+        "640x480:600,600,320,240:1,2,3,4,5,6",
+        "640x480:600,600,320,240:0:0",
+    ] {
+        assert!(invalid.parse::<LensCalibration>().is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn parses_checkerboards() {
+    assert_eq!(
+        "9x6".parse::<Checkerboard>().unwrap(),
+        Checkerboard::new(9, 6)
+    );
+    assert_eq!(Checkerboard::new(9, 6).to_string(), "9x6");
+    assert!("1x6".parse::<Checkerboard>().is_err());
+    assert!("9".parse::<Checkerboard>().is_err());
+}
+
+#[test]
+fn finds_checkerboard_corners() {
+    let lens = lens();
+    for pose in poses() {
+        let found = BOARD.find(&render(&lens, &pose)).expect("board not found");
+        let expected = oriented(corners(&lens, &pose));
+        assert!(rms(&found, &expected) < 0.25, "{}", rms(&found, &expected));
+    }
+}
+
+#[test]
+fn finds_no_board_where_there_is_none() {
+    let flat = Frame::new(
+        Bytes::from(vec![128; 320 * 240 * 3]),
+        320,
+        240,
+        320 * 3,
+        PixelFormat::Rgb8,
+    );
+    assert!(BOARD.find(&flat).is_none());
+    // A board with more corners than the one shown isn't found either.
+    let frame = render(&lens(), &poses()[0]);
+    assert!(Checkerboard::new(8, 5).find(&frame).is_none());
+}
+
+#[test]
+fn calibrates_from_checkerboard_views() {
+    let lens = lens();
+    let mut calibrator = Calibrator::for_checkerboard(320, 240, BOARD.cols, BOARD.rows);
+    assert!(calibrator.calibrate().is_err());
+    for pose in poses() {
+        calibrator.add_view(corners(&lens, &pose)).unwrap();
+    }
+    let report = calibrator.calibrate().unwrap();
+    let found = report.calibration;
+    assert_eq!(report.views, 7);
+    assert!(report.rms_error < 1e-3, "{report:?}");
+    assert!((found.fx - lens.fx).abs() < 0.5, "{found:?}");
+    assert!((found.fy - lens.fy).abs() < 0.5, "{found:?}");
+    assert!((found.cx - lens.cx).abs() < 0.5, "{found:?}");
+    assert!((found.cy - lens.cy).abs() < 0.5, "{found:?}");
+    assert!(
+        (found.distortion[0] - lens.distortion[0]).abs() < 0.01,
+        "{found:?}"
+    );
+}
+
+#[test]
+fn calibrates_from_rendered_views() {
+    let lens = lens();
+    let mut calibrator = Calibrator::for_checkerboard(320, 240, BOARD.cols, BOARD.rows);
+    for pose in poses() {
+        let found = BOARD.find(&render(&lens, &pose)).expect("board not found");
+        calibrator.add_view(found).unwrap();
+    }
+    let report = calibrator.calibrate().unwrap();
+    let found = report.calibration;
+    assert!(report.rms_error < 0.25, "{report:?}");
+    assert!((found.fx - lens.fx).abs() / lens.fx < 0.03, "{found:?}");
+    assert!((found.cx - lens.cx).abs() < 6.0, "{found:?}");
+    assert!((found.cy - lens.cy).abs() < 6.0, "{found:?}");
+    assert!(found.distortion[0] < -0.1, "{found:?}");
+}
+
+#[test]
+fn rejects_views_of_another_target() {
+    let mut calibrator = Calibrator::for_checkerboard(320, 240, 7, 5);
+    assert!(calibrator.add_view(vec![[0.0, 0.0]; 34]).is_err());
+    assert_eq!(calibrator.views(), 0);
+}
+
+#[test]
+fn undistortion_straightens_the_board() {
+    let lens = lens();
+    let pose = poses()[0];
+    let mut undistorter = Undistorter::new(lens);
+    let straight = undistorter.apply(&render(&lens, &pose)).unwrap();
+    assert_eq!((straight.width, straight.height), (320, 240));
+
+    let pinhole = LensCalibration {
+        distortion: [0.0; 5],
+        ..lens
+    };
+    let found = BOARD.find(&straight).expect("board not found");
+    let expected = oriented(corners(&pinhole, &pose));
+    assert!(rms(&found, &expected) < 0.3, "{}", rms(&found, &expected));
+}
+
+#[test]
+fn undistortion_without_distortion_is_a_copy() {
+    let pinhole = LensCalibration {
+        distortion: [0.0; 5],
+        ..lens()
+    };
+    let frame = render(&lens(), &poses()[1]);
+    let copy = Undistorter::new(pinhole).apply(&frame).unwrap();
+    assert_eq!(copy.data, frame.data);
+    // Frames of another size use the calibration scaled.
+    let small = frame.scale(160, 120).unwrap();
+    let copy = Undistorter::new(pinhole).apply(&small).unwrap();
+    assert_eq!(copy.data, small.data);
+}
@@ -0,0 +1,117 @@
+// This is free and unencumbered software released into the public domain.
+
+//! Helpers shared by the integration tests.
+
+// Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use asimov_camera_module::shared::{Frame, FrameSink};
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// An empty directory named `name` under Cargo's scratch space for tests.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A sink keeping every frame it gets, with the frames it kept.
+pub fn collector() -> (FrameSink, Arc<Mutex<Vec<Frame>>>) {
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    let sink_frames = Arc::clone(&frames);
+    let sink: FrameSink = Arc::new(move |frame| sink_frames.lock().unwrap().push(frame));
+    (sink, frames)
+}
+
+/// Polls `done` until it holds, failing the test after five seconds.
+pub fn wait_for(mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// A request a fake server received: its head, blank line included, and
+/// its body.
+pub struct Request {
+    pub head: String,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Reads one request: the head up to its blank line, then exactly
+    /// `Content-Length` bytes of body, or whatever arrived when the
+    /// connection closed.
+    pub fn read(stream: &mut impl Read) -> Self {
+        let mut data = Vec::new();
+        let mut buf = [0; 4096];
+        let mut read = |data: &mut Vec<u8>| match stream.read(&mut buf) {
+            Ok(0) | Err(_) => false,
+            Ok(n) => {
+                data.extend_from_slice(&buf[..n]);
+                true
+            },
+        };
+        let end = loop {
+            if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+            if !read(&mut data) {
+                break data.len();
+            }
+        };
+        let body = data.split_off(end);
+        let mut request = Self {
+            head: String::from_utf8_lossy(&data).into_owned(),
+            body,
+        };
+        let length = request
+            .header("content-length")
+            .and_then(|len| len.parse().ok())
+            .unwrap_or(0);
+        while request.body.len() < length && read(&mut request.body) {}
+        request.body.truncate(length);
+        request
+    }
+
+    /// The path and query of the request line.
+    pub fn path(&self) -> &str {
+        self.head.split(' ').nth(1).unwrap_or("")
+    }
+
+    /// The value of the header `name`, in any case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.split("\r\n").skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+}
+
+/// Accepts `count` connections on `listener`, one request each, and
+/// answers each with `respond`; the thread returns the requests in order.
+pub fn serve(
+    listener: TcpListener,
+    count: usize,
+    mut respond: impl FnMut(&Request) -> String + Send + 'static,
+) -> JoinHandle<Vec<Request>> {
+    std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for _ in 0..count {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = Request::read(&mut stream);
+            let _ = stream.write_all(respond(&request).as_bytes());
+            requests.push(request);
+        }
+        requests
+    })
+}
@@ -0,0 +1,211 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::{
+    DebounceAlg, DebounceConfig, DebounceState, Debouncer, Frame, PixelFormat,
+};
+use bytes::Bytes;
+use std::time::{Duration, Instant};
+
+fn frame(width: u32, height: u32, pixel: impl Fn(u32, u32) -> u8) -> Frame {
+    let mut data = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let v = pixel(x, y);
+            data.extend_from_slice(&[v, v, v]);
+        }
+    }
+    Frame::new(
+        Bytes::from(data),
+        width,
+        height,
+        width * 3,
+        PixelFormat::Rgb8,
+    )
+}
+
+fn gradient() -> Frame {
+    frame(320, 240, |x, _| (x * 255 / 320) as u8)
+}
+
+fn inverted() -> Frame {
+    frame(320, 240, |x, _| 255 - (x * 255 / 320) as u8)
+}
+
+#[test]
+fn parses_algorithms() {
+    assert_eq!(
+        "gradient".parse::<DebounceAlg>().unwrap(),
+        DebounceAlg::Gradient
+    );
+    assert_eq!(
+        "Double_Gradient".parse::<DebounceAlg>().unwrap(),
+        DebounceAlg::DoubleGradient
+    );
+    assert_eq!(
+        "blockhash".parse::<DebounceAlg>().unwrap(),
+        DebounceAlg::Blockhash
+    );
+    assert!("sha256".parse::<DebounceAlg>().is_err());
+}
+
+#[test]
+fn disabled_config_accepts_everything() {
+    let config = DebounceConfig::default();
+    assert!(!config.is_enabled());
+    let mut debouncer = Debouncer::new(config);
+    let now = Instant::now();
+    let hash = debouncer.hash(&gradient());
+    assert!(debouncer.accept(hash.as_ref(), now));
+    assert!(debouncer.accept(hash.as_ref(), now));
+}
+
+#[test]
+fn identical_frames_are_suppressed() {
+    let mut debouncer = Debouncer::new(DebounceConfig::default().with_distance(4));
+    let now = Instant::now();
+    let a = debouncer.hash(&gradient()).unwrap();
+    let b = debouncer.hash(&inverted()).unwrap();
+    assert!(debouncer.accept(Some(&a), now));
+    assert!(!debouncer.accept(Some(&a), now));
+    assert!(debouncer.accept(Some(&b), now));
+    assert!(!debouncer.accept(Some(&b), now));
+}
+
+#[test]
+fn suppressed_frames_do_not_move_the_reference() {
+    let mut debouncer = Debouncer::new(DebounceConfig::default().with_distance(1));
+    let now = Instant::now();
+    let a = debouncer.hash(&gradient()).unwrap();
+    assert!(debouncer.accept(Some(&a), now));
+    for _ in 0..3 {
+        assert!(!debouncer.accept(Some(&a), now));
+    }
+    debouncer.reset();
+    assert!(debouncer.accept(Some(&a), now));
+}
+
+#[test]
+fn cooldown_suppresses_after_emit() {
+    let mut debouncer =
+        Debouncer::new(DebounceConfig::default().with_cooldown(Duration::from_secs(2)));
+    let start = Instant::now();
+    assert!(debouncer.accept(None, start));
+    assert!(!debouncer.accept(None, start + Duration::from_secs(1)));
+    assert!(debouncer.accept(None, start + Duration::from_secs(2)));
+    assert!(!debouncer.accept(None, start + Duration::from_millis(3500)));
+}
+
+#[test]
+fn forced_frames_restart_the_cooldown() {
+    let mut debouncer =
+        Debouncer::new(DebounceConfig::default().with_cooldown(Duration::from_secs(2)));
+    let start = Instant::now();
+    assert!(debouncer.accept(None, start));
+    debouncer.force(None, start + Duration::from_secs(1));
+    assert_eq!(debouncer.state().emitted, 2);
+    assert!(!debouncer.accept(None, start + Duration::from_millis(2500)));
+    assert!(debouncer.accept(None, start + Duration::from_secs(3)));
+}
+
+#[test]
+fn restored_state_suppresses_the_first_frame() {
+    let config = DebounceConfig::default().with_distance(4);
+    let mut before = Debouncer::new(config);
+    let a = before.hash(&gradient()).unwrap();
+    assert!(before.accept(Some(&a), Instant::now()));
+    let state = before.state();
+    assert_eq!(state.emitted, 1);
+
+    let mut after = Debouncer::new(config);
+    after.restore(&state);
+    assert!(!after.accept(Some(&a), Instant::now()));
+    let b = after.hash(&inverted()).unwrap();
+    assert!(after.accept(Some(&b), Instant::now()));
+    assert_eq!(after.state().emitted, 2);
+}
+
+#[test]
+fn restored_state_ignores_incomparable_hashes() {
+    let mut before = Debouncer::new(DebounceConfig::default().with_distance(4));
+    let a = before.hash(&gradient()).unwrap();
+    assert!(before.accept(Some(&a), Instant::now()));
+    let state = DebounceState {
+        alg: DebounceAlg::Mean,
+        ..before.state()
+    };
+
+    let mut after = Debouncer::new(DebounceConfig::default().with_distance(4));
+    after.restore(&state);
+    assert!(after.accept(Some(&a), Instant::now()));
+}
+
+#[test]
+fn hash_size_sets_hash_length() {
+    let small = Debouncer::new(DebounceConfig::default().with_hash_size(8));
+    let large = Debouncer::new(DebounceConfig::default().with_hash_size(16));
+    assert_eq!(small.hash(&gradient()).unwrap().as_bytes().len(), 8);
+    assert_eq!(large.hash(&gradient()).unwrap().as_bytes().len(), 32);
+}
+
+#[test]
+fn hashes_padded_and_bgra_frames() {
+    let debouncer = Debouncer::new(DebounceConfig::default());
+    let rgb = debouncer.hash(&gradient()).unwrap();
+
+    let src = gradient();
+    let (w, h) = (src.width, src.height);
+    let stride = w * 4 + 16;
+    let mut data = vec![0u8; (stride * h) as usize];
+    for y in 0..h as usize {
+        for x in 0..w as usize {
+            let px = &src.data[(y * w as usize + x) * 3..][..3];
+            let at = y * stride as usize + x * 4;
+            data[at..at + 4].copy_from_slice(&[px[2], px[1], px[0], 255]);
+        }
+    }
+    let bgra = Frame::new(Bytes::from(data), w, h, stride, PixelFormat::Bgra8);
+    assert_eq!(debouncer.hash(&bgra).unwrap(), rgb);
+}
+
+#[test]
+fn prescaling_keeps_the_hash_of_smooth_frames() {
+    let full = Debouncer::new(DebounceConfig::default().with_prescale(None));
+    let prescaled = Debouncer::new(DebounceConfig::default().with_prescale(Some(64)));
+    assert_eq!(full.config().prescale, None);
+    assert_eq!(
+        prescaled.hash(&gradient()).unwrap(),
+        full.hash(&gradient()).unwrap()
+    );
+    assert_ne!(
+        prescaled.hash(&gradient()).unwrap(),
+        prescaled.hash(&inverted()).unwrap()
+    );
+}
+
+#[test]
+fn prescaling_averages_away_pixel_noise() {
+    // Every other pixel flips between black and white, so sampling single
+    // pixels sees random noise while the averages are a flat grey.
+    let noisy = |phase: u32| {
+        frame(1024, 512, move |x, y| {
+            if (x + y + phase).is_multiple_of(2) {
+                0
+            } else {
+                255
+            }
+        })
+    };
+    let debouncer = Debouncer::new(DebounceConfig::default());
+    let (a, b) = (
+        debouncer.hash(&noisy(0)).unwrap(),
+        debouncer.hash(&noisy(1)).unwrap(),
+    );
+    assert_eq!(a.dist(&b), 0);
+}
+
+#[test]
+fn malformed_frames_have_no_hash() {
+    let debouncer = Debouncer::new(DebounceConfig::default());
+    let short = Frame::new(Bytes::from_static(&[0; 10]), 32, 32, 96, PixelFormat::Rgb8);
+    assert!(debouncer.hash(&short).is_none());
+}
@@ -0,0 +1,557 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::{
+    CameraMode, CapturePlan,
+    devices::parse::{self, ListedDevice},
+};
+use proptest::prelude::*;
+
+/// How a generation of ffmpeg prefixes its log lines.
+fn context() -> impl Strategy<Value = &'static str> {
+    prop_oneof![
+        Just(""),
+        Just("[AVFoundation indev @ 0x7f8e4c704a80] "),
+        Just("[AVFoundation input device @ 0x600003e1c000] "),
+        Just("[AVFoundation indev @ 0x7f8e4c704a80] [info] "),
+        Just("[dshow @ 000001e0c5e3a2c0] "),
+        Just("[dshow @ 000001e0c5e3a2c0] [info] "),
+        Just("[in#0 @ 0x12f6040f0] "),
+    ]
+}
+
+fn newline() -> impl Strategy<Value = &'static str> {
+    prop_oneof![Just("\n"), Just("\r\n")]
+}
+
+/// Device names as cameras report them: localized, with brackets, quotes
+/// and parentheses, but not blank or padded.
+fn name() -> impl Strategy<Value = String> {
+    "[A-Za-zÀ-ÿ0-9 ()\\[\\]\"'&#@._-]{0,30}".prop_filter_map("blank or padded", |s| {
+        let name = format!("C{s}");
+        (name.trim() == name).then_some(name)
+    })
+}
+
+fn alternative_name() -> impl Strategy<Value = Option<String>> {
+    proptest::option::of("[0-9a-f]{4}".prop_map(|id| {
+        format!("@device_pnp_\\\\?\\usb#vid_046d&pid_{id}&mi_00#{{65e8773d}}\\global")
+    }))
+}
+
+const AVF_HEADER: &str = "Input #0, avfoundation, from '':";
+
+proptest! {
+    #[test]
+    fn avfoundation_lists_every_device(
+        context in context(),
+        nl in newline(),
+        video in prop::collection::vec(name(), 0..5),
+        audio in prop::collection::vec(name(), 0..3),
+    ) {
+        let mut out = format!("{AVF_HEADER}{nl}{context}AVFoundation video devices:{nl}");
+        for (i, name) in video.iter().enumerate() {
+            out += &format!("{context}[{i}] {name}{nl}");
+        }
+        out += &format!("{context}AVFoundation audio devices:{nl}");
+        for (i, name) in audio.iter().enumerate() {
+            out += &format!("{context}[{i}] {name}{nl}");
+        }
+        out += &format!("[in#0 @ 0x12f6040f0] Error opening input: Input/output error{nl}");
+
+        let list = parse::avfoundation(&out);
+        let expected = |names: &[String]| {
+            names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| ListedDevice {
+                    index: Some(i as u32),
+                    name: name.clone(),
+                    alternative_name: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        prop_assert_eq!(&list.video, &expected(&video));
+        prop_assert_eq!(&list.audio, &expected(&audio));
+        prop_assert!(list.warnings.is_empty(), "{:?}", list.warnings);
+    }
+
+    #[test]
+    fn dshow_lists_every_device(
+        context in context(),
+        nl in newline(),
+        tagged in any::<bool>(),
+        video in prop::collection::vec((name(), alternative_name()), 0..5),
+        audio in prop::collection::vec((name(), alternative_name()), 0..3),
+    ) {
+        let mut out = String::new();
+        type Devices = [(String, Option<String>)];
+        let section = |out: &mut String, header: &str, kind: &str, devices: &Devices| {
+            if !tagged {
+                *out += &format!("{context}{header}{nl}");
+            }
+            for (name, alternative) in devices {
+                if tagged {
+                    *out += &format!("{context}\"{name}\" ({kind}){nl}");
+                } else {
+                    *out += &format!("{context} \"{name}\"{nl}");
+                }
+                if let Some(alternative) = alternative {
+                    *out += &format!("{context}    Alternative name \"{alternative}\"{nl}");
+                }
+            }
+        };
+        section(
+            &mut out,
+            "DirectShow video devices (some may be both video and audio devices)",
+            "video",
+            &video,
+        );
+        section(&mut out, "DirectShow audio devices", "audio", &audio);
+        out += &format!("dummy: Immediate exit requested{nl}");
+
+        let list = parse::dshow(&out);
+        let expected = |devices: &[(String, Option<String>)]| {
+            devices
+                .iter()
+                .map(|(name, alternative)| ListedDevice {
+                    index: None,
+                    name: name.clone(),
+                    alternative_name: alternative.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+        prop_assert_eq!(&list.video, &expected(&video));
+        prop_assert_eq!(&list.audio, &expected(&audio));
+        if tagged && video.is_empty() && audio.is_empty() {
+            // Nothing marks a tagged listing without devices as one.
+            prop_assert_eq!(list.warnings.len(), 1);
+        } else {
+            prop_assert!(list.warnings.is_empty(), "{:?}", list.warnings);
+        }
+    }
+
+    #[test]
+    fn parsers_never_panic(s in "(\\PC|[\\[\\]\" \\n])*") {
+        let _ = parse::avfoundation(&s);
+        let _ = parse::dshow(&s);
+    }
+
+    #[test]
+    fn output_without_a_listing_warns(s in "[a-z :\\n]*") {
+        prop_assume!(!s.contains("video devices") && !s.contains("audio devices"));
+        let list = parse::avfoundation(&s);
+        prop_assert!(list.video.is_empty());
+        prop_assert_eq!(list.warnings.len(), 1);
+    }
+}
+
+#[test]
+fn warns_about_missing_formats_with_ffmpeg_error() {
+    let list = parse::avfoundation("Unknown input format: 'avfoundation'\n");
+    assert!(list.video.is_empty());
+    assert_eq!(
+        list.warnings,
+        [
+            "found no AVFoundation device list in ffmpeg's output: Unknown input format: 'avfoundation'"
+        ]
+    );
+}
+
+#[test]
+fn warns_about_lines_it_cannot_parse() {
+    let list = parse::avfoundation(
+        "[AVFoundation indev @ 0x1] AVFoundation video devices:\n\
+         [AVFoundation indev @ 0x1] [0] FaceTime HD Camera\n\
+         [AVFoundation indev @ 0x1] [x] Frobnicator\n",
+    );
+    assert_eq!(list.video.len(), 1);
+    assert_eq!(
+        list.warnings,
+        ["unrecognized device list line: [x] Frobnicator"]
+    );
+}
+
+#[test]
+fn skips_dshow_devices_of_neither_kind() {
+    let list = parse::dshow(
+        "[dshow @ 01] \"OBS Virtual Camera\" (video)\n\
+         [dshow @ 01]   Alternative name \"@device_sw_{860BB310}\"\n\
+         [dshow @ 01] \"Placeholder\" (none)\n\
+         [dshow @ 01]   Alternative name \"@device_sw_{0}\"\n",
+    );
+    assert_eq!(list.video.len(), 1);
+    assert_eq!(
+        list.video[0].alternative_name.as_deref(),
+        Some("@device_sw_{860BB310}")
+    );
+    assert!(list.audio.is_empty() && list.warnings.is_empty());
+}
+
+fn mode(width: u32, height: u32, format: &str) -> CameraMode {
+    CameraMode::new(width, height).with_format(format)
+}
+
+#[test]
+fn parses_v4l2_formats() {
+    let list = parse::v4l2_modes(
+        "[video4linux2,v4l2 @ 0x5581c7c0] Compressed:       mjpeg :          Motion-JPEG : 640x480 1280x720 640x480\n\
+         [video4linux2,v4l2 @ 0x5581c7c0] Raw       :     yuyv422 :           YUYV 4:2:2 : 640x480 320x240\n\
+         [video4linux2,v4l2 @ 0x5581c7c0] Raw       :     yuv420p :     Planar YUV 4:2:0 : Emulated : {32-4096, 2}x{32-2304, 2}\n\
+         /dev/video0: Immediate exit requested\n",
+    );
+    assert_eq!(
+        list.modes,
+        [
+            mode(1280, 720, "mjpeg"),
+            mode(640, 480, "mjpeg"),
+            mode(4096, 2304, "yuv420p"),
+            mode(640, 480, "yuyv422"),
+            mode(320, 240, "yuyv422"),
+        ]
+    );
+    assert!(list.warnings.is_empty(), "{:?}", list.warnings);
+}
+
+#[test]
+fn parses_avfoundation_modes() {
+    let list = parse::avfoundation_modes(
+        "[avfoundation @ 0x7fa1c6004c00] Selected video size (1x1) is not supported by the device.\n\
+         [avfoundation @ 0x7fa1c6004c00] Supported modes:\n\
+         [avfoundation @ 0x7fa1c6004c00]   640x480@[1.000000 30.000000]fps\n\
+         [avfoundation @ 0x7fa1c6004c00]   1920x1080@[1.000000 30.000000]fps\n\
+         [avfoundation @ 0x7fa1c6004c00]   1920x1080@[60.000000]fps\n\
+         [in#0 @ 0x600001a0c000] Error opening input: Input/output error\n",
+    );
+    assert_eq!(
+        list.modes,
+        [
+            CameraMode::new(1920, 1080).with_fps(1.0, 60.0),
+            CameraMode::new(640, 480).with_fps(1.0, 30.0),
+        ]
+    );
+    assert!(list.warnings.is_empty(), "{:?}", list.warnings);
+}
+
+#[test]
+fn parses_dshow_modes() {
+    let list = parse::dshow_modes(
+        "[dshow @ 000001e0] DirectShow video device options (from video devices)\n\
+         [dshow @ 000001e0]  Pin \"Capture\" (alternative pin name \"0\")\n\
+         [dshow @ 000001e0]   pixel_format=yuyv422  min s=640x480 fps=5 max s=640x480 fps=30\n\
+         [dshow @ 000001e0]   pixel_format=yuyv422  min s=640x480 fps=5 max s=640x480 fps=30\n\
+         [dshow @ 000001e0]   vcodec=mjpeg  min s=1280x720 fps=5 max s=1280x720 fps=30.0003 (tv, bt470bg/bt709/unknown, topleft)\n\
+         [dshow @ 000001e0]   vcodec=mjpeg  min s=1280x720 fps=60\n\
+         video=Integrated Camera: Immediate exit requested\n",
+    );
+    assert_eq!(
+        list.modes,
+        [
+            mode(1280, 720, "mjpeg").with_fps(5.0, 30.0003),
+            mode(640, 480, "yuyv422").with_fps(5.0, 30.0),
+        ]
+    );
+    assert_eq!(
+        list.warnings,
+        ["unrecognized mode line: vcodec=mjpeg  min s=1280x720 fps=60"]
+    );
+}
+
+#[test]
+fn parses_rpicam_modes() {
+    let listing = "Available cameras\n\
+        -----------------\n\
+        0 : imx708_wide [4608x2592 10-bit RGGB] (/base/axi/pcie@120000/rp1/i2c@88000/imx708@1a)\n\
+        \x20   Modes: 'SRGGB10_CSI2P' : 1536x864 [120.13 fps - (768, 432)/3072x1728 crop]\n\
+        \x20                            4608x2592 [14.35 fps - (0, 0)/4608x2592 crop]\n\
+        \n\
+        1 : ov5647 [2592x1944 10-bit GBRG] (/base/soc/i2c0mux/i2c@1/ov5647@36)\n\
+        \x20   Modes: 'SGBRG10_CSI2P' : 640x480 [58.92 fps - (16, 0)/2560x1920 crop]\n\
+        \x20          'SGBRG8' : 640x480 [58.92 fps - (16, 0)/2560x1920 crop]\n";
+    let fastest = |mut mode: CameraMode, fps| {
+        mode.max_fps = Some(fps);
+        mode
+    };
+    let list = parse::rpicam_modes(listing, 0);
+    assert_eq!(
+        list.modes,
+        [
+            fastest(mode(4608, 2592, "SRGGB10_CSI2P"), 14.35),
+            fastest(mode(1536, 864, "SRGGB10_CSI2P"), 120.13),
+        ]
+    );
+    let list = parse::rpicam_modes(listing, 1);
+    assert_eq!(list.modes.len(), 2);
+    assert!(list.warnings.is_empty(), "{:?}", list.warnings);
+    let list = parse::rpicam_modes(listing, 2);
+    assert!(list.modes.is_empty());
+    assert_eq!(list.warnings.len(), 1);
+}
+
+#[test]
+fn warns_when_no_modes_are_listed() {
+    let list = parse::v4l2_modes("/dev/video9: No such file or directory\n");
+    assert!(list.modes.is_empty());
+    assert_eq!(
+        list.warnings,
+        ["found no V4L2 formats in ffmpeg's output: /dev/video9: No such file or directory"]
+    );
+}
+
+#[test]
+fn matches_requests_against_modes() {
+    let mode = mode(1280, 720, "mjpeg").with_fps(5.0, 29.97);
+    assert!(mode.accepts(1280, 720, 30.0));
+    assert!(mode.accepts(1280, 720, 5.0));
+    assert!(!mode.accepts(1280, 720, 60.0));
+    assert!(!mode.accepts(640, 480, 30.0));
+    assert!(CameraMode::new(640, 480).accepts(640, 480, 240.0));
+}
+
+#[test]
+fn quotes_planned_commands_for_the_shell() {
+    let plan = CapturePlan::Command(
+        [
+            "ffmpeg",
+            "-f",
+            "dshow",
+            "-i",
+            "video=Integrated Camera",
+            "-vf",
+            "",
+            "it's",
+        ]
+        .map(String::from)
+        .to_vec(),
+    );
+    assert_eq!(
+        plan.to_string(),
+        r"ffmpeg -f dshow -i 'video=Integrated Camera' -vf '' 'it'\''s'"
+    );
+}
+
+#[test]
+fn names_controls_like_v4l2_ctl() {
+    use asimov_camera_module::shared::devices::controls::{ControlKind, control_name};
+    assert_eq!(control_name("Exposure, Auto"), "exposure_auto");
+    assert_eq!(control_name("Focus, Absolute"), "focus_absolute");
+    assert_eq!(
+        control_name("White Balance Temperature, Auto"),
+        "white_balance_temperature_auto"
+    );
+    assert_eq!(
+        control_name(" Power Line Frequency "),
+        "power_line_frequency"
+    );
+    assert_eq!(ControlKind::from_v4l2(3), Some(ControlKind::Menu));
+    // Class headings aren't controls.
+    assert_eq!(ControlKind::from_v4l2(6), None);
+}
+
+#[test]
+fn parses_extension_unit_requests() {
+    use asimov_camera_module::shared::devices::xu::{VendorControl, XuQuery, uvc_device_path};
+    let parse = |s: &str| s.parse::<VendorControl>();
+    assert_eq!(parse("4:2").unwrap(), VendorControl::get(4, 2));
+    assert_eq!(
+        parse("0x04:0x0a=01 ff").unwrap(),
+        VendorControl::set(4, 10, [0x01, 0xff])
+    );
+    assert_eq!(
+        parse("4:2:GET_MAX").unwrap(),
+        VendorControl::get(4, 2).with_query(XuQuery::GetMax)
+    );
+    assert_eq!(parse("4:2:set=0100").unwrap().to_string(), "4:2:set=0100");
+    assert_eq!(XuQuery::GetLen.code(), 0x85);
+
+    for invalid in [
+        "4",
+        "256:1",
+        "4:2:set",
+        "4:2:cur=01",
+        "4:2=0",
+        "4:2:3:4",
+        "4:2:reset",
+    ] {
+        assert!(parse(invalid).is_err(), "{invalid}");
+    }
+
+    assert_eq!(uvc_device_path("file:/dev/video2").unwrap(), "/dev/video2");
+    assert_eq!(uvc_device_path("3").unwrap(), "/dev/video3");
+    assert!(uvc_device_path("rtsp://camera/stream").is_err());
+}
+
+#[cfg(feature = "cli")]
+mod usb {
+    use asimov_camera_module::cli::{UsbInfo, UsbSelector, parse_ioreg};
+
+    #[test]
+    fn parses_usb_selectors() {
+        let selector: UsbSelector = "046D:0893".parse().unwrap();
+        assert_eq!((selector.vendor_id, selector.product_id), (0x046d, 0x0893));
+        assert_eq!(selector.serial, None);
+        let selector: UsbSelector = "46d:893:1A2B3C4D".parse().unwrap();
+        assert_eq!(selector.to_string(), "046d:0893:1A2B3C4D");
+        for invalid in ["046d", "046d:", "046d:0893:", "1046d:0893", "zz:0893"] {
+            assert!(invalid.parse::<UsbSelector>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn matches_serials_only_when_given() {
+        let usb = UsbInfo {
+            vendor_id: 0x046d,
+            product_id: 0x0893,
+            serial: Some("1A2B3C4D".into()),
+            bus_path: None,
+        };
+        assert!(usb.matches(&"046d:0893".parse().unwrap()));
+        assert!(usb.matches(&"046d:0893:1A2B3C4D".parse().unwrap()));
+        assert!(!usb.matches(&"046d:0893:FFFF".parse().unwrap()));
+        assert!(!usb.matches(&"046d:0894".parse().unwrap()));
+    }
+
+    #[test]
+    fn reads_ids_from_interface_paths() {
+        let composite = UsbInfo::from_interface_path(
+            r"@device_pnp_\\?\usb#vid_046d&pid_0893&mi_00#7&1a2b3c&0&0000#{65e8773d-8f56-11d0-a3b9-00a0c9223196}\global",
+        )
+        .unwrap();
+        assert_eq!(
+            (composite.vendor_id, composite.product_id),
+            (0x046d, 0x0893)
+        );
+        assert_eq!(composite.serial, None);
+
+        let single = UsbInfo::from_interface_path(
+            r"\\?\USB#VID_04F2&PID_B6D9#SN0123abc#{65e8773d-8f56-11d0-a3b9-00a0c9223196}\global",
+        )
+        .unwrap();
+        assert_eq!((single.vendor_id, single.product_id), (0x04f2, 0xb6d9));
+        assert_eq!(single.serial.as_deref(), Some("SN0123abc"));
+
+        assert_eq!(
+            UsbInfo::from_interface_path(r"\\?\root#image#0000#{65e8773d}\global"),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_ioreg_usb_devices() {
+        let ioreg = r#"+-o Root  <class IORegistryEntry, id 0x100000100, retain 28>
+  +-o AppleT8103USBXHCI@01000000  <class AppleT8103USBXHCI, id 0x1000002f2, registered, matched, active, busy 0 (2 ms), retain 73>
+  | +-o HD Pro Webcam C920@01100000  <class IOUSBHostDevice, id 0x100000a1c, registered, matched, active, busy 0 (12 ms), retain 30>
+  |     {
+  |       "sessionID" = 4203771546
+  |       "idProduct" = 2195
+  |       "USB Product Name" = "HD Pro Webcam C920"
+  |       "idVendor" = 1133
+  |       "USB Serial Number" = "1A2B3C4D"
+  |       "locationID" = 17825792
+  |     }
+  |
+  +-o Keyboard Hub@02100000  <class IOUSBHostDevice, id 0x100000b2e, registered, matched, active, busy 0 (3 ms), retain 22>
+        {
+          "idProduct" = 4103
+          "idVendor" = 1452
+          "locationID" = 34603008
+        }
+"#;
+        assert_eq!(
+            parse_ioreg(ioreg),
+            vec![
+                (
+                    "HD Pro Webcam C920".to_string(),
+                    UsbInfo {
+                        vendor_id: 0x046d,
+                        product_id: 0x0893,
+                        serial: Some("1A2B3C4D".into()),
+                        bus_path: Some("0x01100000".into()),
+                    }
+                ),
+                (
+                    "Keyboard Hub".to_string(),
+                    UsbInfo {
+                        vendor_id: 0x05ac,
+                        product_id: 0x1007,
+                        serial: None,
+                        bus_path: Some("0x02100000".into()),
+                    }
+                ),
+            ]
+        );
+    }
+}
+
+#[cfg(feature = "cli")]
+mod selection {
+    use asimov_camera_module::cli::{DeviceInfo, DevicePreference, SelectionPolicy, UsbInfo};
+
+    fn device(id: &str, name: &str, usb: bool, builtin: Option<bool>) -> DeviceInfo {
+        DeviceInfo {
+            id: id.into(),
+            name: name.into(),
+            is_usb: usb,
+            unique_id: None,
+            usb: usb.then(|| UsbInfo {
+                vendor_id: 0x046d,
+                product_id: 0x0893,
+                ..UsbInfo::default()
+            }),
+            builtin,
+        }
+    }
+
+    fn laptop() -> Vec<DeviceInfo> {
+        vec![
+            device("file:/dev/video0", "Integrated Camera", true, Some(true)),
+            device("file:/dev/video2", "USB3 HDMI Capture", true, Some(false)),
+            device("file:/dev/video4", "Virtual Camera", false, None),
+        ]
+    }
+
+    fn selected(policy: &SelectionPolicy) -> Option<String> {
+        policy.select(&laptop()).map(|d| d.id.clone())
+    }
+
+    #[test]
+    fn picks_cameras_by_preference() {
+        let pick = |prefer| selected(&SelectionPolicy::new(prefer));
+        assert_eq!(pick(DevicePreference::Builtin).unwrap(), "file:/dev/video0");
+        assert_eq!(
+            pick(DevicePreference::External).unwrap(),
+            "file:/dev/video2"
+        );
+        assert_eq!(pick(DevicePreference::Usb).unwrap(), "file:/dev/video0");
+        assert_eq!(pick(DevicePreference::First).unwrap(), "file:/dev/video0");
+    }
+
+    #[test]
+    fn guesses_when_the_kind_is_unknown() {
+        let devices = vec![
+            device("avf:0", "Some Camera", true, None),
+            device("avf:1", "Other Camera", false, None),
+        ];
+        let pick = |prefer| {
+            SelectionPolicy::new(prefer)
+                .select(&devices)
+                .map(|d| d.id.clone())
+        };
+        assert_eq!(pick(DevicePreference::Builtin).unwrap(), "avf:1");
+        assert_eq!(pick(DevicePreference::External).unwrap(), "avf:0");
+    }
+
+    #[test]
+    fn allow_and_deny_lists_filter_candidates() {
+        let policy = SelectionPolicy::new(DevicePreference::Usb).with_deny(["integrated".into()]);
+        assert_eq!(selected(&policy).unwrap(), "file:/dev/video2");
+
+        let policy =
+            SelectionPolicy::new(DevicePreference::Usb).with_allow(["file:/dev/video4".into()]);
+        assert_eq!(selected(&policy).unwrap(), "file:/dev/video4");
+
+        let policy = SelectionPolicy::new(DevicePreference::First)
+            .with_allow(["camera".into()])
+            .with_deny(["camera".into()]);
+        assert_eq!(selected(&policy), None);
+    }
+}
@@ -0,0 +1,497 @@
+// This is free and unencumbered software released into the public domain.
+
+mod common;
+
+use asimov_camera_module::shared::{
+    CameraBackend, CameraEvent, Dispatcher, Flip, Frame, FrameDefect, FrameTime, FrameTransform,
+    FrameValidation, Pipeline, PixelFormat, Rotation, SinkRate, ThreadPriority, ThreadScheduling,
+    frame_checksum, try_send_frame,
+};
+use bytes::Bytes;
+use common::{collector, wait_for};
+use std::{
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, sync_channel},
+    },
+    time::{Duration, Instant},
+};
+
+fn frame(width: u32, height: u32) -> Frame {
+    let data: Vec<u8> = (0..width * height * 3).map(|i| i as u8).collect();
+    Frame::new(
+        Bytes::from(data),
+        width,
+        height,
+        width * 3,
+        PixelFormat::Rgb8,
+    )
+}
+
+fn dispatcher(capacity: usize) -> (Dispatcher, Receiver<CameraEvent>) {
+    let (events_tx, events_rx) = sync_channel(64);
+    (
+        Dispatcher::new(capacity, CameraBackend::Ffmpeg, events_tx),
+        events_rx,
+    )
+}
+
+#[test]
+fn delivers_every_frame_to_every_sink() {
+    let (dispatcher, _events) = dispatcher(8);
+    let (first, first_frames) = collector();
+    let (second, second_frames) = collector();
+    dispatcher.add_sink(first);
+    dispatcher.add_sink(second);
+    let tx = dispatcher.sender();
+    for _ in 0..5 {
+        tx.try_send(frame(4, 2)).unwrap();
+        wait_for(|| dispatcher.stats().frames_delivered == dispatcher.stats().frames_captured);
+    }
+    assert_eq!(first_frames.lock().unwrap().len(), 5);
+    assert_eq!(second_frames.lock().unwrap().len(), 5);
+    let stats = dispatcher.stats();
+    assert_eq!((stats.frames_captured, stats.frames_delivered), (5, 5));
+}
+
+#[test]
+fn fills_in_capture_times() {
+    let (dispatcher, _events) = dispatcher(2);
+    let (sink, frames) = collector();
+    dispatcher.add_sink(sink);
+    dispatcher.sender().try_send(frame(4, 2)).unwrap();
+    wait_for(|| !frames.lock().unwrap().is_empty());
+    let frame = frames.lock().unwrap()[0].clone();
+    assert_ne!(frame.timestamp_ns, 0);
+    assert_ne!(frame.monotonic_ns, 0);
+}
+
+#[test]
+fn stamps_pipeline_stages() {
+    let (dispatcher, _events) = dispatcher(2);
+    let (sink, frames) = collector();
+    dispatcher.add_sink(sink);
+    let sent = frame(4, 2);
+    assert_eq!(sent.stage_latency(), None);
+    dispatcher.sender().try_send(sent).unwrap();
+    wait_for(|| !frames.lock().unwrap().is_empty());
+    let frame = frames.lock().unwrap()[0].clone();
+    let stages = frame.metadata.stages;
+    let (enqueued, dispatched) = (stages.enqueued_ns.unwrap(), stages.dispatched_ns.unwrap());
+    assert!(frame.monotonic_ns <= enqueued && enqueued <= dispatched);
+    let latency = frame.stage_latency().unwrap();
+    assert_eq!(
+        latency.dispatch,
+        Duration::from_nanos(dispatched - enqueued)
+    );
+}
+
+#[test]
+fn replays_keep_capture_times_moving_forward() {
+    let (dispatcher, events) = dispatcher(16);
+    dispatcher.set_looping(true);
+    let (sink, frames) = collector();
+    dispatcher.add_sink(sink);
+    let tx = dispatcher.sender();
+    let at = |ms: u64| {
+        frame(4, 2).with_time(FrameTime {
+            wall_ns: 5_000_000_000 + ms * 1_000_000,
+            monotonic_ns: ms * 1_000_000,
+        })
+    };
+    for ms in [100, 200, 300] {
+        tx.try_send(at(ms)).unwrap();
+    }
+    tx.end_of_stream();
+    // The replay's clock starts over.
+    for ms in [100, 200] {
+        tx.try_send(at(ms)).unwrap();
+    }
+    wait_for(|| frames.lock().unwrap().len() == 5);
+    let times: Vec<_> = frames
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|f| (f.monotonic_ns / 1_000_000, f.timestamp_ns - f.monotonic_ns))
+        .collect();
+    assert_eq!(
+        times,
+        [100, 200, 300, 400, 500].map(|ms| (ms, 5_000_000_000))
+    );
+    assert!(dispatcher.has_ended());
+    assert!(
+        !events
+            .try_iter()
+            .any(|ev| matches!(ev, CameraEvent::EndOfStream { .. }))
+    );
+}
+
+#[test]
+fn counts_frames_dropped_when_full() {
+    let (dispatcher, _events) = dispatcher(1);
+    let (release_tx, release_rx) = sync_channel::<()>(0);
+    let release_rx = Mutex::new(release_rx);
+    // The sink blocks the dispatch thread, so the queue fills up.
+    dispatcher.add_sink(Arc::new(move |_| {
+        let _ = release_rx.lock().unwrap().recv();
+    }));
+    let tx = dispatcher.sender();
+    tx.try_send(frame(4, 2)).unwrap();
+    wait_for(|| dispatcher.stats().frames_delivered == 1);
+    tx.try_send(frame(4, 2)).unwrap();
+    assert!(tx.try_send(frame(4, 2)).is_err());
+    let stats = dispatcher.stats();
+    assert_eq!((stats.frames_captured, stats.frames_dropped), (2, 1));
+    drop(release_tx);
+}
+
+#[test]
+fn tracks_the_queue_and_reports_backpressure() {
+    let (dispatcher, events) = dispatcher(4);
+    let (release_tx, release_rx) = sync_channel::<()>(0);
+    let release_rx = Mutex::new(release_rx);
+    dispatcher.add_sink(Arc::new(move |_| {
+        let _ = release_rx.lock().unwrap().recv();
+    }));
+    let tx = dispatcher.sender();
+    tx.try_send(frame(4, 2)).unwrap();
+    wait_for(|| dispatcher.stats().frames_delivered == 1);
+    for _ in 0..3 {
+        tx.try_send(frame(4, 2)).unwrap();
+    }
+    assert_eq!(dispatcher.queue_depth(), 3);
+    std::thread::sleep(Duration::from_millis(1100));
+    drop(release_tx);
+    wait_for(|| dispatcher.stats().frames_delivered == 4);
+    let stats = dispatcher.stats();
+    assert_eq!(
+        (stats.queue_depth, stats.queue_peak, stats.queue_capacity),
+        (0, 3, 4)
+    );
+    assert_eq!(dispatcher.queue_peak(), 3);
+    let backpressure: Vec<_> = events
+        .try_iter()
+        .filter_map(|ev| match ev {
+            CameraEvent::Backpressure {
+                depth,
+                capacity,
+                after,
+                ..
+            } => Some((depth, capacity, after)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(backpressure.len(), 1, "{backpressure:?}");
+    assert_eq!((backpressure[0].0, backpressure[0].1), (3, 4));
+    assert!(backpressure[0].2 >= Duration::from_secs(1));
+}
+
+#[test]
+fn paused_frames_are_discarded() {
+    let (mut dispatcher, _events) = dispatcher(4);
+    let (sink, frames) = collector();
+    dispatcher.add_sink(sink);
+    dispatcher.set_paused(true);
+    dispatcher.sender().try_send(frame(4, 2)).unwrap();
+    // Stopping drains the queue first.
+    assert!(dispatcher.stop(Duration::from_secs(5)));
+    assert!(frames.lock().unwrap().is_empty());
+    let stats = dispatcher.stats();
+    assert_eq!((stats.frames_captured, stats.frames_delivered), (1, 0));
+}
+
+#[test]
+fn replaced_pipelines_get_the_following_frames() {
+    let (dispatcher, _events) = dispatcher(4);
+    let (fixed, fixed_frames) = collector();
+    let (old, old_frames) = collector();
+    let (new, new_frames) = collector();
+    dispatcher.add_sink(fixed);
+    assert_eq!(
+        dispatcher.replace_pipeline(Pipeline::new("old").with_sink(old)),
+        0
+    );
+    let tx = dispatcher.sender();
+    tx.try_send(frame(4, 2)).unwrap();
+    wait_for(|| old_frames.lock().unwrap().len() == 1);
+    assert_eq!(
+        dispatcher.replace_pipeline(Pipeline::new("new").with_sink(new)),
+        1
+    );
+    tx.try_send(frame(4, 2)).unwrap();
+    wait_for(|| new_frames.lock().unwrap().len() == 1);
+    assert_eq!(old_frames.lock().unwrap().len(), 1);
+    // Sinks added on their own stay through both.
+    wait_for(|| fixed_frames.lock().unwrap().len() == 2);
+}
+
+#[test]
+fn sinks_get_frames_at_their_own_rates() {
+    let (dispatcher, _events) = dispatcher(64);
+    let (all, all_frames) = collector();
+    let (strided, strided_frames) = collector();
+    dispatcher.add_sink(all);
+    dispatcher
+        .add_sink_with_rate(strided, SinkRate::every(3))
+        .unwrap();
+    let tx = dispatcher.sender();
+    for _ in 0..9 {
+        tx.try_send(frame(4, 2)).unwrap();
+    }
+    wait_for(|| all_frames.lock().unwrap().len() == 9);
+    assert_eq!(strided_frames.lock().unwrap().len(), 3);
+    assert!(
+        dispatcher
+            .add_sink_with_rate(collector().0, SinkRate::every(0))
+            .is_err()
+    );
+}
+
+#[test]
+fn limits_sinks_by_capture_time() {
+    // Two seconds of a 30 fps camera, with a millisecond of jitter.
+    let times = (0..60u64).map(|i| 1_000_000_000 + i * 33_333_333 + (i % 3) * 1_000_000);
+    let count = |rate: SinkRate| {
+        let (sink, frames) = collector();
+        let sink = rate.limit(sink);
+        for t in times.clone() {
+            sink(frame(4, 2).with_time(FrameTime::from_monotonic(t)));
+        }
+        frames.lock().unwrap().len()
+    };
+    assert_eq!(count(SinkRate::fps(30.0)), 60);
+    assert_eq!(count(SinkRate::fps(5.0)), 10);
+    assert_eq!(count(SinkRate::fps(10.0).with_stride(2)), 20);
+}
+
+#[test]
+fn applies_the_transform_of_every_format() {
+    for format in [
+        PixelFormat::Rgb8,
+        PixelFormat::Bgra8,
+        PixelFormat::Rgba8,
+        PixelFormat::Gray16,
+        PixelFormat::Z16,
+    ] {
+        let (dispatcher, _events) = dispatcher(2);
+        let (sink, frames) = collector();
+        dispatcher.add_sink(sink);
+        dispatcher.set_transform(FrameTransform {
+            rotation: Rotation::Cw90,
+            flip: Flip::None,
+        });
+        let bpp = format.bytes_per_pixel();
+        let data = vec![7u8; (6 * 2 * bpp) as usize];
+        let input = Frame::new(Bytes::from(data), 6, 2, 6 * bpp, format);
+        dispatcher.sender().try_send(input).unwrap();
+        wait_for(|| !frames.lock().unwrap().is_empty());
+        let output = frames.lock().unwrap()[0].clone();
+        assert_eq!((output.width, output.height), (2, 6), "{format:?}");
+        assert_eq!(output.pixel_format, format);
+    }
+}
+
+#[test]
+fn taps_copy_delivered_frames() {
+    let (dispatcher, _events) = dispatcher(4);
+    let (tap_tx, tap_rx) = sync_channel(4);
+    dispatcher.set_tap(Some(tap_tx));
+    dispatcher.sender().try_send(frame(4, 2)).unwrap();
+    let tapped = tap_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!((tapped.width, tapped.height), (4, 2));
+    dispatcher.set_tap(None);
+    dispatcher.sender().try_send(frame(4, 2)).unwrap();
+    wait_for(|| dispatcher.stats().frames_delivered == 2);
+    assert!(tap_rx.try_recv().is_err());
+}
+
+#[test]
+fn stop_reports_started_and_stopped() {
+    let (mut dispatcher, events) = dispatcher(2);
+    dispatcher.sender().try_send(frame(4, 2)).unwrap();
+    assert!(dispatcher.stop(Duration::from_secs(5)));
+    let events: Vec<_> = events.try_iter().collect();
+    assert!(matches!(events.first(), Some(CameraEvent::Started { .. })));
+    match events.last() {
+        Some(CameraEvent::Stopped { stats, .. }) => assert_eq!(stats.frames_delivered, 1),
+        other => panic!("expected Stopped, got {other:?}"),
+    }
+}
+
+#[test]
+fn restarts_after_stop() {
+    let (mut dispatcher, events) = dispatcher(4);
+    let (sink, frames) = collector();
+    dispatcher.add_sink(sink);
+    let tx = dispatcher.sender();
+    tx.try_send(frame(4, 2)).unwrap();
+    assert!(dispatcher.stop(Duration::from_secs(5)));
+    assert!(dispatcher.stop(Duration::from_secs(5)));
+    assert!(!dispatcher.is_running());
+    // Queued while stopped, so stale by the time it restarts.
+    tx.try_send(frame(4, 2)).unwrap();
+    dispatcher.start().unwrap();
+    dispatcher.start().unwrap();
+    assert!(dispatcher.is_running());
+    tx.try_send(frame(4, 2)).unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 2);
+    assert!(dispatcher.stop(Duration::from_secs(5)));
+    let started = events
+        .try_iter()
+        .filter(|ev| matches!(ev, CameraEvent::Started { .. }))
+        .count();
+    assert_eq!(started, 2);
+}
+
+#[test]
+fn stop_abandons_a_stuck_sink() {
+    let (mut dispatcher, _events) = dispatcher(2);
+    let (hold_tx, hold_rx) = sync_channel::<()>(0);
+    let hold_rx = Mutex::new(hold_rx);
+    dispatcher.add_sink(Arc::new(move |_| {
+        let _ = hold_rx.lock().unwrap().recv();
+    }));
+    dispatcher.sender().try_send(frame(4, 2)).unwrap();
+    wait_for(|| dispatcher.stats().frames_delivered == 1);
+    let started = Instant::now();
+    assert!(!dispatcher.stop(Duration::from_millis(100)));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    // It can't restart until the abandoned thread lets go of the queue.
+    assert!(dispatcher.start().is_err());
+    drop(hold_tx);
+    wait_for(|| dispatcher.start().is_ok());
+    assert!(dispatcher.stop(Duration::from_secs(5)));
+}
+
+/// A 4x2 frame whose buffer lost its last row.
+fn truncated_frame() -> Frame {
+    let mut frame = frame(4, 2);
+    frame.data = frame.data.slice(..12);
+    frame
+}
+
+#[test]
+fn describes_malformed_frames() {
+    assert_eq!(frame(4, 2).check(), Ok(()));
+    assert_eq!(
+        truncated_frame().check(),
+        Err(FrameDefect::Truncated {
+            len: 12,
+            expected: 24
+        })
+    );
+    let mut narrow = frame(4, 2);
+    narrow.stride = 10;
+    assert_eq!(
+        narrow.check(),
+        Err(FrameDefect::StrideTooSmall {
+            stride: 10,
+            row_bytes: 12
+        })
+    );
+}
+
+#[test]
+fn drops_malformed_frames_with_a_warning() {
+    let (dispatcher, events) = dispatcher(8);
+    let (sink, frames) = collector();
+    dispatcher.add_sink(sink);
+    let (tx, events_tx) = (dispatcher.sender(), sync_channel(8).0);
+    try_send_frame(&tx, &events_tx, CameraBackend::Ffmpeg, frame(4, 2));
+    for _ in 0..3 {
+        try_send_frame(&tx, &events_tx, CameraBackend::Ffmpeg, truncated_frame());
+    }
+    wait_for(|| dispatcher.stats().frames_delivered == 1);
+    let stats = dispatcher.stats();
+    assert_eq!((stats.frames_captured, stats.frames_malformed), (1, 3));
+    assert_eq!(frames.lock().unwrap().len(), 1);
+    drop(events);
+}
+
+#[test]
+fn warns_about_malformed_frames_sparsely() {
+    let (dispatcher, _events) = dispatcher(8);
+    dispatcher.set_frame_validation(FrameValidation::Warn);
+    let (events_tx, events_rx) = sync_channel(8);
+    let tx = dispatcher.sender();
+    for _ in 0..3 {
+        try_send_frame(&tx, &events_tx, CameraBackend::Ffmpeg, truncated_frame());
+    }
+    wait_for(|| dispatcher.stats().frames_delivered == 3);
+    let warnings: Vec<String> = events_rx
+        .try_iter()
+        .filter_map(|event| match event {
+            CameraEvent::Warning { message, .. } => Some(message),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            "delivered a malformed frame: truncated frame: 12 of 24 bytes",
+            "delivered a malformed frame: truncated frame: 12 of 24 bytes (2 so far)",
+        ]
+    );
+}
+
+#[test]
+fn stamps_checksums_as_frames_are_sent() {
+    let (dispatcher, _events) = dispatcher(8);
+    dispatcher.set_frame_checksums(true);
+    let (sink, frames) = collector();
+    dispatcher.add_sink(sink);
+    let (events_tx, events_rx) = sync_channel(8);
+    let sent = frame(4, 2);
+    let checksum = frame_checksum(&sent.data);
+    try_send_frame(
+        &dispatcher.sender(),
+        &events_tx,
+        CameraBackend::Ffmpeg,
+        sent,
+    );
+    wait_for(|| dispatcher.stats().frames_delivered == 1);
+    assert_eq!(frames.lock().unwrap()[0].metadata.checksum, Some(checksum));
+    assert!(events_rx.try_recv().is_err());
+    assert_ne!(frame_checksum(&[0; 9]), frame_checksum(&[0; 8]));
+}
+
+#[test]
+fn parses_thread_priorities() {
+    assert_eq!(
+        "normal".parse::<ThreadPriority>().unwrap(),
+        ThreadPriority::Normal
+    );
+    assert_eq!(
+        "High".parse::<ThreadPriority>().unwrap(),
+        ThreadPriority::High
+    );
+    assert_eq!(
+        "realtime".parse::<ThreadPriority>().unwrap(),
+        ThreadPriority::Realtime(10)
+    );
+    assert_eq!(
+        "rt:80".parse::<ThreadPriority>().unwrap(),
+        ThreadPriority::Realtime(80)
+    );
+    assert!("realtime:0".parse::<ThreadPriority>().is_err());
+    assert!("urgent".parse::<ThreadPriority>().is_err());
+}
+
+#[test]
+fn keeps_dispatching_when_scheduling_is_refused() {
+    let (events_tx, events_rx) = sync_channel(64);
+    let scheduling = ThreadScheduling {
+        priority: ThreadPriority::Normal,
+        core: Some(1 << 20),
+    };
+    let dispatcher = Dispatcher::with_scheduling(4, CameraBackend::Ffmpeg, events_tx, scheduling);
+    let (sink, frames) = collector();
+    dispatcher.add_sink(sink);
+    dispatcher.sender().try_send(frame(4, 2)).unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 1);
+    assert!(
+        events_rx
+            .try_iter()
+            .any(|ev| matches!(ev, CameraEvent::Warning { message, .. } if message.contains("dispatch thread")))
+    );
+}
@@ -0,0 +1,101 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::{FocusCheck, FocusStats, Frame, PixelFormat, Rect};
+use bytes::Bytes;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+/// A gray frame whose luma at each pixel is `shade(x, y)`.
+fn frame(shade: impl Fn(u32, u32) -> u8) -> Frame {
+    let mut data = Vec::with_capacity((WIDTH * HEIGHT * 3) as usize);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            data.extend([shade(x, y); 3]);
+        }
+    }
+    Frame::new(
+        Bytes::from(data),
+        WIDTH,
+        HEIGHT,
+        WIDTH * 3,
+        PixelFormat::Rgb8,
+    )
+}
+
+/// 4-pixel black and white squares.
+fn checkers(x: u32, y: u32) -> u8 {
+    if (x / 4 + y / 4).is_multiple_of(2) {
+        20
+    } else {
+        230
+    }
+}
+
+/// `checkers` through a box blur of `radius` pixels.
+fn blurred(radius: i32) -> Frame {
+    frame(|x, y| {
+        let mut sum = 0u32;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let sx = (x as i32 + dx).clamp(0, WIDTH as i32 - 1) as u32;
+                let sy = (y as i32 + dy).clamp(0, HEIGHT as i32 - 1) as u32;
+                sum += checkers(sx, sy) as u32;
+            }
+        }
+        (sum / ((2 * radius + 1) * (2 * radius + 1)) as u32) as u8
+    })
+}
+
+fn stats(frame: &Frame) -> FocusStats {
+    FocusStats::compute(frame, &FocusCheck::new()).unwrap()
+}
+
+#[test]
+fn sharper_frames_score_higher() {
+    let sharp = stats(&frame(checkers)).sharpness;
+    let soft = stats(&blurred(1)).sharpness;
+    let softer = stats(&blurred(2)).sharpness;
+    assert!(sharp > soft && soft > softer, "{sharp} {soft} {softer}");
+    assert!(softer > 0.0);
+}
+
+#[test]
+fn flat_frames_have_no_sharpness_or_contrast() {
+    let flat = stats(&frame(|_, _| 128));
+    assert_eq!(flat.sharpness, 0.0);
+    assert_eq!(flat.contrast, 0.0);
+    assert_eq!(flat.histogram[8], 1.0);
+    // Nor does a linear ramp have any edges to measure.
+    let ramp = stats(&frame(|x, _| (x * 3) as u8));
+    assert_eq!(ramp.sharpness, 0.0);
+    assert!(ramp.contrast > 0.1);
+}
+
+#[test]
+fn histogram_covers_the_region() {
+    let stats = stats(&frame(checkers));
+    assert!((stats.histogram.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+    assert_eq!(stats.histogram[1], 0.5);
+    assert_eq!(stats.histogram[14], 0.5);
+    assert!((stats.contrast - 105.0 / 255.0).abs() < 1e-3);
+}
+
+#[test]
+fn measures_only_the_region() {
+    // Checkers on the left half, flat on the right.
+    let half = frame(|x, y| if x < WIDTH / 2 { checkers(x, y) } else { 128 });
+    let right = FocusCheck::new().with_region(Rect::new(WIDTH / 2, 0, WIDTH / 2, HEIGHT));
+    let flat = FocusStats::compute(&half, &right).unwrap();
+    assert_eq!(flat.sharpness, 0.0);
+    assert_eq!(flat.histogram[8], 1.0);
+
+    let left = FocusCheck::new().with_region(Rect::new(0, 0, WIDTH / 2, HEIGHT));
+    assert!(FocusStats::compute(&half, &left).unwrap().sharpness > 0.0);
+
+    // A region that doesn't fit measures the whole frame.
+    let outside = FocusCheck::new().with_region(Rect::new(WIDTH, 0, 8, 8));
+    assert_eq!(FocusStats::compute(&half, &outside), Some(stats(&half)));
+    let tiny = FocusCheck::new().with_region(Rect::new(0, 0, 2, 2));
+    assert_eq!(FocusStats::compute(&half, &tiny), None);
+}
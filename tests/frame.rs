@@ -0,0 +1,231 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::{
+    ColorMatrix, ColorRange, Colorimetry, Frame, FrameDefect, PixelFormat, Rect, Rotation,
+};
+use bytes::Bytes;
+
+/// A BGRA frame whose rows carry `padding` junk bytes, as AVFoundation
+/// delivers them; pixel `(x, y)` is `[x, y, 0, 255]`.
+fn padded_frame(width: u32, height: u32, padding: u32) -> Frame {
+    let stride = width * 4 + padding;
+    let mut data = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            data.extend_from_slice(&[x as u8, y as u8, 0, 255]);
+        }
+        data.extend(std::iter::repeat_n(0xAA, padding as usize));
+    }
+    Frame::new(Bytes::from(data), width, height, stride, PixelFormat::Bgra8)
+}
+
+#[test]
+fn packs_padded_rows() {
+    let frame = padded_frame(3, 2, 20);
+    assert!(!frame.is_packed());
+    let packed = frame.to_packed().unwrap();
+    assert!(packed.is_packed());
+    assert_eq!((packed.width, packed.height, packed.stride), (3, 2, 12));
+    assert_eq!(packed.pixel_format, PixelFormat::Bgra8);
+    assert_eq!(
+        packed.data[..],
+        [
+            0, 0, 0, 255, 1, 0, 0, 255, 2, 0, 0, 255, //
+            0, 1, 0, 255, 1, 1, 0, 255, 2, 1, 0, 255,
+        ]
+    );
+    assert_eq!(frame.ensure_packed().unwrap().data, packed.data);
+}
+
+#[test]
+fn keeps_packed_frames() {
+    let frame = padded_frame(3, 2, 0);
+    let data = frame.data.clone();
+    let packed = frame.ensure_packed().unwrap();
+    assert_eq!(packed.data.as_ptr(), data.as_ptr());
+    assert_eq!(packed.data, data);
+
+    // Bytes past the last row are trimmed, not copied.
+    let mut long = padded_frame(3, 2, 0);
+    long.data = Bytes::from([&long.data[..], &[0xAA; 5]].concat());
+    assert_eq!(long.ensure_packed().unwrap().data, data);
+}
+
+#[test]
+fn refuses_to_pack_truncated_frames() {
+    let mut frame = padded_frame(3, 2, 20);
+    frame.data = frame.data.slice(..40);
+    assert!(frame.to_packed().is_err());
+    assert!(frame.ensure_packed().is_err());
+}
+
+#[test]
+fn converts_padded_frames_to_packed_images() {
+    let frame = padded_frame(3, 2, 20);
+    let rgb = frame.to_rgb8().unwrap();
+    assert!(rgb.is_packed());
+    assert_eq!(&rgb.data[..6], [0, 0, 0, 0, 0, 1]);
+    let rgba = frame.to_rgba8().unwrap();
+    assert_eq!(rgba.data.len(), 3 * 2 * 4);
+    assert_eq!(&rgba.data[12..16], [0, 1, 0, 255]);
+}
+
+#[test]
+fn conversions_keep_colorimetry() {
+    let frame = padded_frame(3, 2, 20).with_colorimetry(Colorimetry::JPEG);
+    assert_eq!(frame.to_packed().unwrap().colorimetry, Colorimetry::JPEG);
+    assert_eq!(frame.to_rgb8().unwrap().colorimetry, Colorimetry::JPEG);
+    assert_eq!(
+        frame.rotate(Rotation::Cw90).unwrap().colorimetry,
+        Colorimetry::JPEG
+    );
+}
+
+#[test]
+fn maps_platform_colorimetry() {
+    // V4L2_COLORSPACE_REC709 with default encoding and quantization.
+    let hd = Colorimetry::from_v4l2(3, 0, 0, false);
+    assert_eq!(hd.matrix, Some(ColorMatrix::Bt709));
+    assert_eq!(hd.range, Some(ColorRange::Limited));
+    assert!(!hd.srgb);
+    // V4L2_COLORSPACE_JPEG, as MJPEG webcams report.
+    assert_eq!(Colorimetry::from_v4l2(7, 0, 0, false), Colorimetry::JPEG);
+    // V4L2_COLORSPACE_SRGB RGB buffers default to full range.
+    assert_eq!(
+        Colorimetry::from_v4l2(8, 0, 0, true).range,
+        Some(ColorRange::Full)
+    );
+
+    // MFVideoTransferMatrix_BT601, MFNominalRange_16_235, MFVideoTransFunc_sRGB.
+    let mf = Colorimetry::from_media_foundation(2, 2, 7);
+    assert_eq!(
+        (mf.matrix, mf.range, mf.srgb),
+        (Some(ColorMatrix::Bt601), Some(ColorRange::Limited), true)
+    );
+
+    let cv = Colorimetry::from_core_video(Some("ITU_R_709_2"), Some("ITU_R_709_2"), Some(false));
+    assert_eq!(
+        (cv.matrix, cv.range, cv.srgb),
+        (Some(ColorMatrix::Bt709), Some(ColorRange::Limited), false)
+    );
+    assert!(Colorimetry::from_core_video(None, None, None).is_unknown());
+}
+
+/// A P010 frame of limited-range BT.709 grey at 10-bit luma `luma`.
+fn p010_frame(width: u32, height: u32, luma: u16) -> Frame {
+    let mut data = Vec::new();
+    for _ in 0..width * height {
+        data.extend_from_slice(&(luma << 6).to_le_bytes());
+    }
+    for _ in 0..width * height / 2 {
+        data.extend_from_slice(&(512u16 << 6).to_le_bytes());
+    }
+    Frame::new(
+        Bytes::from(data),
+        width,
+        height,
+        width * 2,
+        PixelFormat::P010,
+    )
+    .with_colorimetry(Colorimetry {
+        matrix: Some(ColorMatrix::Bt709),
+        range: Some(ColorRange::Limited),
+        srgb: false,
+    })
+}
+
+#[test]
+fn converts_p010_to_rgb() {
+    let frame = p010_frame(4, 2, 502);
+    assert_eq!(frame.bit_depth(), 10);
+    assert_eq!(frame.check(), Ok(()));
+    let rgb = frame.to_rgb8().unwrap();
+    assert_eq!(rgb.data.len(), 4 * 2 * 3);
+    assert!(rgb.data.iter().all(|&v| v == 128), "{:?}", rgb.data);
+    assert_eq!(rgb.colorimetry.range, Some(ColorRange::Full));
+    assert_eq!(p010_frame(4, 2, 940).to_rgb8().unwrap().data[0], 255);
+    assert_eq!(
+        p010_frame(4, 2, 64).to_rgba8().unwrap().data[..4],
+        [0, 0, 0, 255]
+    );
+}
+
+#[test]
+fn box_scaling_averages_each_box() {
+    // Each 2x2 box of the padded frame averages x and y over its pixels.
+    let small = padded_frame(6, 4, 20).box_scale(3, 2).unwrap();
+    assert!(small.is_packed());
+    assert_eq!(
+        small.data[..],
+        [
+            1, 1, 0, 255, 3, 1, 0, 255, 5, 1, 0, 255, //
+            1, 3, 0, 255, 3, 3, 0, 255, 5, 3, 0, 255,
+        ]
+    );
+    // Uneven boxes still cover every source pixel once.
+    let uneven = padded_frame(5, 1, 0).box_scale(2, 1).unwrap();
+    assert_eq!(uneven.data[..], [1, 0, 0, 255, 3, 0, 0, 255]);
+
+    assert!(padded_frame(6, 4, 0).box_scale(7, 4).is_err());
+    assert!(padded_frame(6, 4, 0).box_scale(0, 4).is_err());
+}
+
+#[test]
+fn encodes_padded_and_p010_frames_as_jpeg() {
+    let jpeg = padded_frame(64, 32, 20).to_jpeg(90).unwrap();
+    let decoded = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+    assert_eq!(decoded.dimensions(), (64, 32));
+    // BGRA [x, y, 0] is RGB [0, y, x].
+    let [r, g, b] = decoded.get_pixel(40, 20).0;
+    assert!(
+        r < 12 && g.abs_diff(20) < 12 && b.abs_diff(40) < 12,
+        "{r} {g} {b}"
+    );
+
+    let grey = image::load_from_memory(&p010_frame(16, 8, 502).to_jpeg(90).unwrap()).unwrap();
+    assert!(
+        grey.to_rgb8()
+            .pixels()
+            .all(|px| px.0.iter().all(|v| v.abs_diff(128) < 4))
+    );
+}
+
+#[test]
+fn refuses_planar_frames_where_rows_are_pixels() {
+    let frame = p010_frame(4, 2, 502);
+    assert!(frame.crop(Rect::new(0, 0, 2, 2)).is_err());
+    assert!(frame.rotate(Rotation::Cw90).is_err());
+    assert!(frame.box_scale(2, 1).is_err());
+
+    let mut short = frame.clone();
+    short.data = short.data.slice(..16);
+    assert!(short.check().is_err());
+
+    let odd = p010_frame(3, 3, 502);
+    assert_eq!(
+        odd.check(),
+        Err(FrameDefect::OddSubsampled {
+            width: 3,
+            height: 3
+        })
+    );
+}
+
+#[test]
+fn unpacks_rgba1010102() {
+    // Red at full scale, green at half, blue off, opaque.
+    let px: u32 = 1023 | 512 << 10 | 3 << 30;
+    let frame = Frame::new(
+        Bytes::from(px.to_le_bytes().repeat(2)),
+        2,
+        1,
+        8,
+        PixelFormat::Rgba1010102,
+    );
+    assert_eq!(frame.bit_depth(), 10);
+    assert_eq!(
+        frame.to_rgba8().unwrap().data[..],
+        [255, 128, 0, 255, 255, 128, 0, 255]
+    );
+    assert_eq!(frame.to_rgb8().unwrap().data[..3], [255, 128, 0]);
+}
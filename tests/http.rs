@@ -0,0 +1,57 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::HttpRequest;
+use std::io::{Cursor, ErrorKind};
+
+#[test]
+fn parses_request_heads() {
+    let head = b"POST /capture?now HTTP/1.1\r\nHost: x\r\ncontent-length: 5\r\n\r\nhello";
+    let (request, len) = HttpRequest::parse(head).unwrap().unwrap();
+    assert_eq!(
+        request,
+        HttpRequest {
+            method: "POST".into(),
+            path: "/capture".into(),
+            content_length: 5,
+        }
+    );
+    assert_eq!(&head[len..], b"hello");
+    // Bare newlines end a head too.
+    let (request, _) = HttpRequest::parse(b"GET / HTTP/1.1\n\n").unwrap().unwrap();
+    assert_eq!(
+        (request.method.as_str(), request.content_length),
+        ("GET", 0)
+    );
+    assert!(
+        HttpRequest::parse(b"GET / HTTP/1.1\r\nHost:")
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn refuses_endless_heads() {
+    let mut head = b"GET / HTTP/1.1\r\n".to_vec();
+    head.resize(32 * 1024, b'a');
+    assert!(HttpRequest::parse(&head).is_err());
+    let err = HttpRequest::read(&mut Cursor::new(head), 0).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn reads_bodies_up_to_a_limit() {
+    let request = b"POST /offer HTTP/1.1\r\nContent-Length: 4\r\n\r\nsdp!trailing";
+    let (head, body) = HttpRequest::read(&mut Cursor::new(request), 16).unwrap();
+    assert_eq!(
+        (head.path.as_str(), body.as_slice()),
+        ("/offer", &b"sdp!"[..])
+    );
+    let (head, body) = HttpRequest::read(&mut Cursor::new(request), 2).unwrap();
+    assert_eq!((head.content_length, body.len()), (4, 0));
+    // A connection closing early ends the body, or fails the head.
+    let short = b"POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\nab";
+    let (_, body) = HttpRequest::read(&mut Cursor::new(short), 16).unwrap();
+    assert_eq!(body, b"ab");
+    let err = HttpRequest::read(&mut Cursor::new(b"GET / HTTP/1.1\r\n"), 0).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,646 @@
+// This is free and unencumbered software released into the public domain.
+
+mod common;
+
+use asimov_camera_module::shared::{
+    AutoControl, AutoLock, Camera, CameraBackend, CameraConfig, CameraEvent, CameraState,
+    DebounceConfig, Frame, FrameAnalyzer, LoadGuard, MaskShape, MaskStyle, Observation, Overload,
+    Pipeline, PixelFormat, Rect, TriggerConfig,
+    drivers::mock::{MockCameraDriver, MockPattern, MockScript, MockStep},
+    open_camera,
+};
+use common::{collector, wait_for};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+fn config(script: &str) -> CameraConfig {
+    CameraConfig::new(16, 8, 100.0).with_device(format!("mock:{script}"))
+}
+
+fn open(config: CameraConfig) -> (Camera, Arc<Mutex<Vec<Frame>>>) {
+    let cam = open_camera("", config).unwrap();
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    let sink_frames = Arc::clone(&frames);
+    cam.add_sink(Arc::new(move |frame| {
+        sink_frames.lock().unwrap().push(frame)
+    }));
+    (cam, frames)
+}
+
+fn driver(cam: &Camera) -> &MockCameraDriver {
+    cam.driver_as::<MockCameraDriver>().unwrap()
+}
+
+#[test]
+fn cameras_move_between_threads() {
+    fn assert_send<T: Send>() {}
+    assert_send::<Camera>();
+    let (mut cam, _) = open(config("frames:2"));
+    cam.start().unwrap();
+    let cam = Arc::new(Mutex::new(cam));
+    let stopper = Arc::clone(&cam);
+    std::thread::spawn(move || stopper.lock().unwrap().stop().unwrap())
+        .join()
+        .unwrap();
+    assert!(!cam.lock().unwrap().is_running());
+    // Stopping again is harmless.
+    cam.lock().unwrap().stop().unwrap();
+}
+
+#[test]
+fn parses_scripts() {
+    let script: MockScript = "mock:noise, fps:50,frames:3,drop,warn:too dark,wait:1.5s,error:gone"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        script.steps,
+        [
+            MockStep::Pattern(MockPattern::Noise),
+            MockStep::Fps(50.0),
+            MockStep::Frames(3),
+            MockStep::Drop(1),
+            MockStep::Warn("too dark".into()),
+            MockStep::Wait(Duration::from_millis(1500)),
+            MockStep::Error("gone".into()),
+        ]
+    );
+    assert!(!script.repeat);
+    assert_eq!(
+        script.to_string(),
+        "noise,fps:50,frames:3,drop:1,warn:too dark,wait:1500ms,error:gone"
+    );
+    assert!(
+        "solid:12,frame,repeat"
+            .parse::<MockScript>()
+            .unwrap()
+            .repeat
+    );
+    for invalid in ["frames:x", "solid:300", "fps:0", "repeat,frame", "flash"] {
+        assert!(invalid.parse::<MockScript>().is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn selects_the_mock_backend_by_device_id() {
+    let cam = open_camera("", config("frame")).unwrap();
+    assert_eq!(cam.backend(), CameraBackend::Mock);
+}
+
+#[test]
+fn delivers_scripted_frames_in_every_format() {
+    for format in [
+        PixelFormat::Rgb8,
+        PixelFormat::Bgra8,
+        PixelFormat::Rgba8,
+        PixelFormat::Gray16,
+        PixelFormat::Z16,
+        PixelFormat::P010,
+        PixelFormat::Rgba1010102,
+    ] {
+        let (mut cam, frames) = open(config("gradient,frames:3").with_pixel_format(format));
+        cam.start().unwrap();
+        wait_for(|| frames.lock().unwrap().len() == 3);
+        let frames = frames.lock().unwrap();
+        for frame in frames.iter() {
+            assert!(frame.validate(), "{format:?}");
+            assert_eq!(frame.pixel_format, format);
+            assert_eq!((frame.width, frame.height), (16, 8));
+        }
+        // Bright on the right, and the same image in every frame.
+        let rgb = frames[0].to_rgb8().unwrap();
+        assert!(rgb.data[0] < rgb.data[rgb.data.len() - 1]);
+        assert_eq!(frames[0].data, frames[2].data);
+    }
+}
+
+#[test]
+fn converts_frames_between_pixel_formats() {
+    for format in [PixelFormat::Rgb8, PixelFormat::Bgra8, PixelFormat::Rgba8] {
+        let (mut cam, frames) = open(config("gradient,frames:1").with_pixel_format(format));
+        cam.start().unwrap();
+        wait_for(|| frames.lock().unwrap().len() == 1);
+        let frame = frames.lock().unwrap()[0].clone();
+        let rgb = frame.to_rgb8().unwrap();
+        for target in [PixelFormat::Rgb8, PixelFormat::Bgra8, PixelFormat::Rgba8] {
+            let converted = frame.to_pixel_format(target).unwrap();
+            assert_eq!(converted.pixel_format, target);
+            assert_eq!(
+                converted.to_rgb8().unwrap().data,
+                rgb.data,
+                "{format:?} to {target:?}"
+            );
+        }
+    }
+    let (mut cam, frames) = open(config("gradient,frames:1").with_pixel_format(PixelFormat::Z16));
+    cam.start().unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 1);
+    let depth = frames.lock().unwrap()[0].clone();
+    assert!(depth.to_pixel_format(PixelFormat::Bgra8).is_err());
+    assert_eq!(
+        depth
+            .to_pixel_format(PixelFormat::Rgb8)
+            .unwrap()
+            .pixel_format,
+        PixelFormat::Rgb8
+    );
+}
+
+#[test]
+fn noise_changes_every_frame() {
+    let (mut cam, frames) = open(config("noise,frames:2"));
+    cam.start().unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 2);
+    let frames = frames.lock().unwrap();
+    assert_ne!(frames[0].data, frames[1].data);
+}
+
+#[test]
+fn counts_and_reports_drops() {
+    let (mut cam, frames) = open(config("drop:3,frame"));
+    cam.start().unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 1);
+    let stats = cam.stats();
+    assert_eq!((stats.frames_captured, stats.frames_dropped), (1, 3));
+    let drops = cam
+        .events()
+        .try_iter()
+        .filter(|ev| matches!(ev, CameraEvent::FrameDropped { .. }))
+        .count();
+    assert_eq!(drops, 3);
+}
+
+#[test]
+fn reports_warnings_then_errors() {
+    let (mut cam, _) = open(config("warn:too dark,error:unplugged,frame"));
+    cam.start().unwrap();
+    let mut messages = Vec::new();
+    while messages.len() < 2 {
+        match cam.events().recv_timeout(Duration::from_secs(5)).unwrap() {
+            CameraEvent::Warning { message, .. } => messages.push(format!("warning: {message}")),
+            CameraEvent::Error { error, .. } => messages.push(format!("error: {error}")),
+            _ => {},
+        }
+    }
+    assert_eq!(messages[0], "warning: too dark");
+    assert!(messages[1].contains("unplugged"), "{}", messages[1]);
+    // The script ends at the error.
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(driver(&cam).frames_sent(), 0);
+}
+
+#[test]
+fn sizes_the_frame_queue_per_backend_and_warns_on_a_mismatch() {
+    let (cam, _) = open(config("frame"));
+    assert_eq!(
+        cam.stats().queue_capacity,
+        CameraBackend::Mock.default_buffer_frames()
+    );
+    let warned = |cam: &Camera| {
+        cam.events().try_iter().find_map(|ev| match ev {
+            CameraEvent::Warning { message, .. } => Some(message),
+            _ => None,
+        })
+    };
+    assert_eq!(warned(&cam), None);
+
+    // 500 frames at 100 fps is five seconds behind.
+    let (cam, _) = open(config("frame").with_buffer_frames(500));
+    assert_eq!(cam.stats().queue_capacity, 500);
+    let message = warned(&cam).unwrap();
+    assert!(message.contains("5.0s"), "{message}");
+    // One frame at 100 fps leaves 10 ms for a sink to stall.
+    let (cam, _) = open(config("frame").with_buffer_frames(1));
+    assert!(warned(&cam).is_some());
+    let (cam, _) = open(config("frame").with_buffer_frames(8));
+    assert_eq!(warned(&cam), None);
+}
+
+#[test]
+fn taken_events_arrive_on_another_thread() {
+    let (mut cam, _) = open(config("error:unplugged"));
+    let events = cam.take_events().unwrap();
+    assert!(cam.take_events().is_none());
+    let forwarded = std::thread::spawn(move || {
+        events
+            .iter()
+            .find_map(|event| match event {
+                CameraEvent::Error { error, .. } => Some(error.to_string()),
+                _ => None,
+            })
+            .unwrap()
+    });
+    cam.start().unwrap();
+    assert!(forwarded.join().unwrap().contains("unplugged"));
+    assert!(cam.events().try_recv().is_err());
+}
+
+#[test]
+fn starts_and_stops_once() {
+    let (mut cam, frames) = open(config("frames:2"));
+    assert_eq!(cam.state(), CameraState::Created);
+    cam.start().unwrap();
+    cam.start().unwrap();
+    assert_eq!(cam.state(), CameraState::Running);
+    wait_for(|| frames.lock().unwrap().len() == 2);
+    cam.stop().unwrap();
+    cam.stop().unwrap();
+    assert_eq!(cam.state(), CameraState::Stopped);
+    assert_eq!(driver(&cam).starts(), 1);
+    let started = cam
+        .events()
+        .try_iter()
+        .filter(|ev| matches!(ev, CameraEvent::Started { .. }))
+        .count();
+    assert_eq!(started, 1);
+}
+
+#[test]
+fn restarts_into_the_same_sinks() {
+    let (mut cam, frames) = open(config("frames:2"));
+    cam.start().unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 2);
+    cam.stop().unwrap();
+    cam.start().unwrap();
+    assert_eq!(cam.state(), CameraState::Running);
+    assert_eq!(driver(&cam).starts(), 2);
+    wait_for(|| frames.lock().unwrap().len() == 4);
+}
+
+#[test]
+fn watchdog_restarts_a_stalled_camera() {
+    let (mut cam, frames) = open(config("frames:2").with_watchdog(Duration::from_millis(100)));
+    cam.start().unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 2);
+    assert!(!cam.check_watchdog().unwrap());
+    std::thread::sleep(Duration::from_millis(150));
+    assert!(cam.check_watchdog().unwrap());
+    assert_eq!(driver(&cam).starts(), 2);
+    // The restarted driver replays its script.
+    wait_for(|| frames.lock().unwrap().len() == 4);
+    assert!(
+        cam.events()
+            .try_iter()
+            .any(|ev| matches!(ev, CameraEvent::Stalled { .. }))
+    );
+}
+
+#[test]
+fn locks_auto_controls_once_converged() {
+    let auto_lock = AutoLock::new()
+        .with_exposure(Duration::from_millis(100))
+        .with_white_balance(Duration::from_millis(250));
+    let (mut cam, frames) = open(config("frames:50").with_auto_lock(auto_lock));
+    assert!(!cam.check_auto_lock().unwrap());
+    cam.start().unwrap();
+    wait_for(|| !frames.lock().unwrap().is_empty());
+    assert!(!cam.check_auto_lock().unwrap());
+    wait_for(|| cam.check_auto_lock().unwrap());
+    assert_eq!(driver(&cam).locked(), [AutoControl::Exposure]);
+    wait_for(|| cam.check_auto_lock().unwrap());
+    assert_eq!(
+        driver(&cam).locked(),
+        [AutoControl::Exposure, AutoControl::WhiteBalance]
+    );
+    assert!(!cam.check_auto_lock().unwrap());
+
+    // Stopping releases the locks, and the next start converges afresh.
+    cam.stop().unwrap();
+    assert!(driver(&cam).locked().is_empty());
+    let changes: Vec<_> = cam
+        .events()
+        .try_iter()
+        .filter_map(|ev| match ev {
+            CameraEvent::AutoLockChanged {
+                control, locked, ..
+            } => Some((control, locked)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            (AutoControl::Exposure, true),
+            (AutoControl::WhiteBalance, true),
+            (AutoControl::Exposure, false),
+            (AutoControl::WhiteBalance, false),
+        ]
+    );
+    cam.start().unwrap();
+    wait_for(|| cam.check_auto_lock().unwrap());
+    assert_eq!(driver(&cam).locked(), [AutoControl::Exposure]);
+}
+
+#[test]
+fn ends_the_stream_after_its_last_frame() {
+    let (mut cam, frames) = open(config("frames:3,end").with_watchdog(Duration::from_millis(50)));
+    let ends = Arc::new(Mutex::new(Vec::new()));
+    let ends_cb = Arc::clone(&ends);
+    let frames_cb = Arc::clone(&frames);
+    cam.on_end(Arc::new(move || {
+        ends_cb
+            .lock()
+            .unwrap()
+            .push(frames_cb.lock().unwrap().len())
+    }));
+    cam.start().unwrap();
+    let event = cam
+        .events()
+        .iter()
+        .find(|ev| matches!(ev, CameraEvent::EndOfStream { .. }));
+    assert!(event.is_some());
+    assert_eq!(*ends.lock().unwrap(), [3]);
+    assert!(cam.has_ended());
+    // An ended stream isn't stalled.
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!cam.check_watchdog().unwrap());
+    assert_eq!(driver(&cam).starts(), 1);
+    assert_eq!(
+        "frames:3,end".parse::<MockScript>().unwrap().steps[1],
+        MockStep::End
+    );
+}
+
+#[test]
+fn watchdog_leaves_a_streaming_camera_alone() {
+    let (mut cam, frames) =
+        open(config("frames:1,repeat").with_watchdog(Duration::from_millis(200)));
+    cam.start().unwrap();
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_millis(400) {
+        assert!(!cam.check_watchdog().unwrap());
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(driver(&cam).starts(), 1);
+    assert!(frames.lock().unwrap().len() > 10);
+}
+
+#[test]
+fn privacy_releases_and_reopens_the_device() {
+    let (mut cam, frames) = open(config("frames:2"));
+    cam.start().unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 2);
+    cam.set_private(true).unwrap();
+    assert!(cam.is_private());
+    cam.set_private(false).unwrap();
+    assert_eq!(driver(&cam).starts(), 2);
+    wait_for(|| frames.lock().unwrap().len() == 4);
+}
+
+#[test]
+fn pause_holds_back_frames() {
+    let (mut cam, frames) = open(config("frames:1,repeat"));
+    cam.start().unwrap();
+    wait_for(|| !frames.lock().unwrap().is_empty());
+    cam.pause().unwrap();
+    // Let a frame already being delivered land.
+    std::thread::sleep(Duration::from_millis(50));
+    let paused_at = frames.lock().unwrap().len();
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(frames.lock().unwrap().len(), paused_at);
+    cam.resume().unwrap();
+    wait_for(|| frames.lock().unwrap().len() > paused_at);
+}
+
+#[test]
+fn switches_devices_and_formats_in_place() {
+    let (mut cam, frames) = open(config("solid:10,frame"));
+    cam.start().unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 1);
+    cam.switch_device("mock:solid:200,frame").unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 2);
+    cam.reconfigure(CameraConfig::new(8, 4, 100.0)).unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 3);
+    let frames = frames.lock().unwrap();
+    assert_eq!(frames[0].data[0], 10);
+    assert_eq!(frames[1].data[0], 200);
+    assert_eq!((frames[2].width, frames[2].height), (8, 4));
+    assert!(
+        cam.events()
+            .try_iter()
+            .any(|ev| matches!(ev, CameraEvent::DeviceChanged { device, .. } if device == "mock:solid:200,frame"))
+    );
+}
+
+struct Brightness;
+
+impl FrameAnalyzer for Brightness {
+    fn name(&self) -> &str {
+        "brightness"
+    }
+
+    fn analyze(&mut self, frame: &Frame) -> Vec<Observation> {
+        vec![Observation::new("brightness").with_value(frame.data[0] as f64)]
+    }
+}
+
+#[test]
+fn applies_pipelines_alongside_other_sinks() {
+    let (mut cam, frames) = open(config("solid:200,frame,wait:20ms,repeat"));
+    cam.add_analyzer(Brightness);
+    let (first, first_frames) = collector();
+    let (second, second_frames) = collector();
+    assert!(cam.apply_pipeline(Pipeline::new("empty")).is_err());
+    cam.apply_pipeline(Pipeline::new("first").with_sink(first))
+        .unwrap();
+    cam.start().unwrap();
+    wait_for(|| !first_frames.lock().unwrap().is_empty());
+    cam.apply_pipeline(
+        Pipeline::new("second")
+            .with_sink(Arc::clone(&second))
+            .with_sink(second)
+            .with_masks(
+                vec![MaskShape::Rect(Rect::new(0, 0, 4, 4))],
+                MaskStyle::Black,
+            ),
+    )
+    .unwrap();
+    wait_for(|| !second_frames.lock().unwrap().is_empty());
+    let first_count = first_frames.lock().unwrap().len();
+    let before = frames.lock().unwrap().len();
+    wait_for(|| frames.lock().unwrap().len() > before);
+    cam.stop().unwrap();
+    assert_eq!(first_frames.lock().unwrap().len(), first_count);
+
+    // The pipeline's sinks see the mask; the camera's own sink doesn't.
+    let masked = &second_frames.lock().unwrap()[0];
+    assert_eq!(
+        (masked.data[0], masked.data[masked.stride as usize * 5]),
+        (0, 200)
+    );
+    assert_eq!(frames.lock().unwrap().last().unwrap().data[0], 200);
+
+    let events: Vec<_> = cam.events().try_iter().collect();
+    let changes: Vec<_> = events
+        .iter()
+        .filter_map(|ev| match ev {
+            CameraEvent::PipelineChanged {
+                label,
+                previous_sinks,
+                sinks,
+                ..
+            } => Some((label.as_str(), *previous_sinks, *sinks)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(changes, [("first", 0, 1), ("second", 1, 2)]);
+    assert!(events.iter().any(
+        |ev| matches!(ev, CameraEvent::Observed { analyzer, .. } if analyzer == "brightness")
+    ));
+}
+
+#[test]
+fn pipelines_debounce_their_sinks() {
+    let (mut cam, frames) = open(config("solid:50,frame,wait:10ms,repeat"));
+    let (sink, debounced) = collector();
+    cam.apply_pipeline(
+        Pipeline::new("debounced")
+            .with_sink(sink)
+            .with_debounce(DebounceConfig {
+                distance: 1,
+                ..Default::default()
+            }),
+    )
+    .unwrap();
+    cam.start().unwrap();
+    wait_for(|| frames.lock().unwrap().len() >= 5);
+    cam.stop().unwrap();
+    // Every frame after the first is identical to it.
+    assert_eq!(debounced.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn pipelines_can_leave_the_debounce_to_their_sinks() {
+    let (mut cam, _) = open(config("solid:50,frame,wait:10ms,repeat"));
+    let (sink, delivered) = collector();
+    let debounce = DebounceConfig {
+        distance: 1,
+        ..Default::default()
+    };
+    let pipeline = Pipeline::new("workers")
+        .with_sink(sink)
+        .with_sink_debounce(debounce);
+    assert_eq!(pipeline.debounce(), Some(&debounce));
+    cam.apply_pipeline(pipeline).unwrap();
+    cam.start().unwrap();
+    // Identical frames all reach the sink.
+    wait_for(|| delivered.lock().unwrap().len() >= 5);
+    cam.stop().unwrap();
+}
+
+fn load_guard() -> LoadGuard {
+    LoadGuard {
+        max_cpu: None,
+        window: Duration::ZERO,
+        min_fps: 25.0,
+        ..LoadGuard::default()
+    }
+}
+
+#[test]
+fn downgrades_capture_under_sustained_drops() {
+    let (mut cam, _) = open(config("frame,drop:20").with_load_guard(load_guard()));
+    cam.start().unwrap();
+    wait_for(|| cam.stats().frames_dropped == 20);
+    assert!(cam.check_load().unwrap());
+    let downgrade = cam.events().try_iter().find_map(|ev| match ev {
+        CameraEvent::Downgraded {
+            reason,
+            previous_fps,
+            width,
+            height,
+            fps,
+            ..
+        } => Some((reason, previous_fps, width, height, fps)),
+        _ => None,
+    });
+    let Some((Overload::Drops { ratio }, 100.0, 16, 8, 50.0)) = downgrade else {
+        panic!("unexpected downgrade {downgrade:?}");
+    };
+    assert!(ratio > 0.9);
+
+    // The script replays after the restart: down to the floor, then no
+    // smaller mode to move to.
+    wait_for(|| cam.stats().frames_dropped == 40);
+    assert!(cam.check_load().unwrap());
+    wait_for(|| cam.stats().frames_dropped == 60);
+    assert!(!cam.check_load().unwrap());
+    let rates: Vec<_> = cam
+        .events()
+        .try_iter()
+        .filter_map(|ev| match ev {
+            CameraEvent::Downgraded { fps, .. } => Some(fps),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(rates, [25.0]);
+}
+
+#[test]
+fn downgrades_when_sinks_shed_frames() {
+    let (mut cam, frames) = open(config("frames:20").with_load_guard(load_guard()));
+    cam.start().unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 20);
+    assert!(!cam.check_load().unwrap());
+
+    let (mut cam, frames) = open(config("frames:20").with_load_guard(load_guard()));
+    cam.start().unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 20);
+    cam.drop_reporter().report(15);
+    assert_eq!(cam.stats().frames_shed, 15);
+    assert!(cam.check_load().unwrap());
+}
+
+#[test]
+fn captures_bursts_from_the_stream() {
+    let (mut cam, _) = open(config("frames:1,repeat"));
+    cam.start().unwrap();
+    let burst = cam.capture_burst(3, Duration::ZERO).unwrap();
+    assert_eq!(burst.len(), 3);
+    assert!(
+        burst
+            .windows(2)
+            .all(|w| w[0].monotonic_ns < w[1].monotonic_ns)
+    );
+}
+
+#[test]
+fn lists_modes() {
+    let mut cam = open_camera("", config("fps:240,frame")).unwrap();
+    let modes = cam.modes().unwrap();
+    assert_eq!(modes.len(), 7);
+    assert!(modes.iter().all(|m| (m.width, m.height) == (16, 8)));
+    assert!(modes.iter().all(|m| m.max_fps == Some(240.0)));
+}
+
+#[test]
+fn exposes_one_frame_per_software_trigger() {
+    let (mut cam, frames) = open(config("frames:10").with_trigger(TriggerConfig::new()));
+    // Triggers only fire while capturing.
+    assert!(cam.fire_trigger().is_err());
+    cam.start().unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(frames.lock().unwrap().is_empty());
+
+    cam.fire_trigger().unwrap();
+    cam.fire_trigger().unwrap();
+    wait_for(|| frames.lock().unwrap().len() == 2);
+    std::thread::sleep(Duration::from_millis(50));
+    let frames = frames.lock().unwrap();
+    assert_eq!(frames.len(), 2);
+    for (i, frame) in frames.iter().enumerate() {
+        let trigger = frame.metadata.trigger.unwrap();
+        assert_eq!(trigger.sequence, i as u64 + 1);
+        assert!(trigger.fired_ns.unwrap() <= frame.monotonic_ns);
+    }
+    drop(frames);
+    cam.stop().unwrap();
+
+    // Without trigger mode, frames aren't marked and triggers fail.
+    let (mut cam, frames) = open(config("frames:1"));
+    cam.start().unwrap();
+    wait_for(|| !frames.lock().unwrap().is_empty());
+    assert_eq!(frames.lock().unwrap()[0].metadata.trigger, None);
+    assert!(cam.fire_trigger().is_err());
+}
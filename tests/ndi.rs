@@ -0,0 +1,24 @@
+// This is free and unencumbered software released into the public domain.
+
+#![cfg(all(
+    feature = "ndi",
+    any(target_os = "macos", target_os = "linux", target_os = "windows")
+))]
+
+use asimov_camera_module::shared::{CameraBackend, CameraConfig, CameraError, open_camera};
+
+#[test]
+fn receives_ndi_devices_through_the_runtime() {
+    let config = CameraConfig::new(640, 360, 30.0).with_device("ndi:STUDIO-PC (OBS)");
+    let mut camera = open_camera("", config).unwrap();
+    assert_eq!(camera.backend(), CameraBackend::Ndi);
+
+    // Without the runtime installed, capture says where to get it.
+    match camera.start() {
+        Ok(()) => camera.stop().unwrap(),
+        Err(CameraError::Unsupported(message)) => {
+            assert!(message.contains("NDI runtime"), "{message}")
+        },
+        Err(err) => panic!("{err}"),
+    }
+}
@@ -0,0 +1,118 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::{RdfFormat, to_rdf};
+use know::traits::ToJsonLd;
+use serde_json::{Value, json};
+
+fn image() -> Value {
+    know::classes::Image {
+        id: Some("file:/dev/video0#1760000000000000000".into()),
+        width: Some(2),
+        height: Some(1),
+        data: vec![255, 0, 0, 0, 0, 255],
+        source: Some("file:/dev/video0".into()),
+    }
+    .to_jsonld()
+    .unwrap()
+}
+
+fn metadata() -> Value {
+    let mut record = image();
+    record["location"] = "Loading dock \"A\"".into();
+    record["tags"] = json!(["door", "north"]);
+    record["luminance"] = json!({ "mean": 0.41, "dark": 0.02, "histogram": [0.5, 0.25] });
+    record["lastError"] = Value::Null;
+    record["exposureOk"] = true.into();
+    record
+}
+
+fn schema_image() -> Value {
+    json!({
+        "@context": "https://schema.org",
+        "@type": "ImageObject",
+        "@id": "https://example.org/cameras/door/1760000000000000000",
+        "width": 640,
+        "height": 480,
+        "contentUrl": "file:///var/lib/asimov/camera/1760000000000000000.png",
+        "dateCreated": "2025-10-09T08:53:20Z",
+        "isBasedOn": "dshow:video=USB Video Device",
+        "encodingFormat": "image/png",
+    })
+}
+
+fn rdf(record: &Value, format: RdfFormat) -> String {
+    to_rdf(record, format).unwrap()
+}
+
+#[test]
+fn know_image_as_nquads() {
+    assert_eq!(
+        rdf(&image(), RdfFormat::NQuads),
+        include_str!("golden/image.nq")
+    );
+}
+
+#[test]
+fn know_image_as_turtle() {
+    assert_eq!(
+        rdf(&image(), RdfFormat::Turtle),
+        include_str!("golden/image.ttl")
+    );
+}
+
+#[test]
+fn nested_values_as_nquads() {
+    assert_eq!(
+        rdf(&metadata(), RdfFormat::NQuads),
+        include_str!("golden/metadata.nq")
+    );
+}
+
+#[test]
+fn nested_values_as_turtle() {
+    assert_eq!(
+        rdf(&metadata(), RdfFormat::Turtle),
+        include_str!("golden/metadata.ttl")
+    );
+}
+
+#[test]
+fn schema_image_as_nquads() {
+    assert_eq!(
+        rdf(&schema_image(), RdfFormat::NQuads),
+        include_str!("golden/schema.nq")
+    );
+}
+
+#[test]
+fn schema_image_as_turtle() {
+    assert_eq!(
+        rdf(&schema_image(), RdfFormat::Turtle),
+        include_str!("golden/schema.ttl")
+    );
+}
+
+#[test]
+fn relative_ids_become_blank_nodes() {
+    let record = json!({ "@type": "Status", "@id": "0#status-1", "mode": "private" });
+    assert_eq!(
+        rdf(&record, RdfFormat::NQuads),
+        "_:0_status_1 <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://know.dev/Status> .\n\
+         _:0_status_1 <https://know.dev/mode> \"private\" .\n"
+    );
+}
+
+#[test]
+fn rejects_non_objects() {
+    assert!(to_rdf(&json!([1, 2]), RdfFormat::NQuads).is_err());
+}
+
+#[test]
+fn percent_encodes_ids() {
+    let record = json!({ "@id": "dshow:video=USB Video Device#1", "width": 640 });
+    assert_eq!(
+        rdf(&record, RdfFormat::NQuads),
+        "<dshow:video=USB%20Video%20Device#1> <https://know.dev/width> \
+         \"640\"^^<http://www.w3.org/2001/XMLSchema#integer> .\n"
+    );
+}
@@ -0,0 +1,1133 @@
+// This is free and unencumbered software released into the public domain.
+
+//! The reader binary against the mock camera.
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::scratch_dir;
+use std::{path::PathBuf, process::Command};
+
+/// Ten frames a second for half a second, then an unplugged camera, so
+/// every run ends on its own.
+fn reader(script: &str, args: &[&str]) -> (i32, Vec<u8>) {
+    let output = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args([
+            "--device",
+            &format!("mock:fps:10,{script},wait:300ms,error:unplugged"),
+            "-s",
+            "160x120",
+            "-f",
+            "50",
+        ])
+        .args(args)
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .output()
+        .unwrap();
+    (output.status.code().unwrap(), output.stdout)
+}
+
+fn records(stdout: &[u8]) -> Vec<serde_json::Value> {
+    stdout
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn emits_one_record_per_frame() {
+    let (code, stdout) = reader("noise,frames:4", &[]);
+    let records = records(&stdout);
+    assert_eq!(records.len(), 4);
+    for record in &records {
+        assert_eq!(record["@type"], "Image");
+        assert_eq!(
+            (record["width"].as_u64(), record["height"].as_u64()),
+            (Some(160), Some(120))
+        );
+        assert!(record["data"].as_str().unwrap().starts_with("data:image/"));
+    }
+    // An unplugged camera is an I/O error.
+    assert_eq!(code, 74);
+}
+
+#[test]
+fn embeds_other_pixel_formats_as_rgb() {
+    let (_, stdout) = reader("solid:200,frames:1", &["--pixel-format", "gray16"]);
+    let records = records(&stdout);
+    let data = records[0]["data"].as_str().unwrap();
+    let base64 = data.strip_prefix("data:image/rgb;base64,").unwrap();
+    // 160x120 pixels of three bytes each.
+    assert_eq!(base64.len(), 160 * 120 * 3 / 3 * 4);
+}
+
+#[test]
+fn stride_keeps_every_nth_frame() {
+    let (_, stdout) = reader("frames:6", &["-o", "metadata", "--stride", "2"]);
+    assert_eq!(records(&stdout).len(), 3);
+}
+
+#[test]
+fn metadata_records_omit_pixels() {
+    let (_, stdout) = reader("frames:2", &["-o", "metadata"]);
+    let records = records(&stdout);
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| r.get("data").is_none()));
+    assert_eq!(
+        records[0]["colorimetry"],
+        serde_json::json!({"matrix": null, "range": "full", "srgb": true})
+    );
+    assert!(records[0].get("bitDepth").is_none());
+}
+
+#[test]
+fn metadata_records_carry_bit_depth() {
+    let (_, stdout) = reader("frames:1", &["-o", "metadata", "--pixel-format", "p010"]);
+    let records = records(&stdout);
+    assert_eq!(records[0]["format"], "p010");
+    assert_eq!(records[0]["bitDepth"], 10);
+}
+
+#[test]
+fn reports_stage_latencies_on_exit() {
+    let output = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args([
+            "--device",
+            "mock:fps:10,frames:3,wait:300ms,error:unplugged",
+        ])
+        .args([
+            "-s",
+            "160x120",
+            "-f",
+            "50",
+            "-o",
+            "metadata",
+            "--latency-report",
+        ])
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .output()
+        .unwrap();
+    assert_eq!(records(&output.stdout).len(), 3);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("latency: 3 frames"), "{stderr}");
+    for stage in ["driver", "dispatch", "sink", "total"] {
+        assert!(
+            stderr.lines().any(|l| l.trim_start().starts_with(stage)),
+            "{stderr}"
+        );
+    }
+}
+
+#[test]
+fn exits_cleanly_when_the_source_ends() {
+    let (code, stdout) = reader("frames:3,end", &["-o", "metadata"]);
+    assert_eq!(code, 0);
+    assert_eq!(records(&stdout).len(), 3);
+}
+
+#[test]
+fn attaches_sidecar_telemetry() {
+    let dir = scratch_dir("sidecar");
+    let srt = dir.join("flight.SRT");
+    std::fs::write(
+        &srt,
+        "1\n00:00:00,000 --> 00:00:00,050\n[iso : 100] [latitude: 52.5] [longitude: 13.4]\n\n\
+         2\n00:00:00,050 --> 00:01:00,000\n[iso : 200] [latitude: 52.6] [longitude: 13.5]\n",
+    )
+    .unwrap();
+    let (_, stdout) = reader(
+        "frames:3",
+        &["-o", "metadata", "--sidecar", srt.to_str().unwrap()],
+    );
+    let records = records(&stdout);
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0]["telemetry"]["iso"], 100);
+    assert_eq!(records[0]["telemetry"]["latitude"], 52.5);
+    for record in &records[1..] {
+        assert_eq!(record["telemetry"]["iso"], 200);
+    }
+}
+
+#[test]
+fn loops_finite_sources() {
+    let (code, stdout) = reader(
+        "frames:3,end",
+        &["-o", "metadata", "--loop", "--max-frames", "7"],
+    );
+    assert_eq!(code, 0);
+    let records = records(&stdout);
+    assert_eq!(records.len(), 7);
+    let times: Vec<u64> = records
+        .iter()
+        .map(|r| r["monotonicTimestamp"].as_u64().unwrap())
+        .collect();
+    assert!(times.windows(2).all(|w| w[0] < w[1]), "{times:?}");
+}
+
+#[test]
+fn cbor_records_decode() {
+    let (_, stdout) = reader("frames:2", &["-o", "cbor"]);
+    let mut stdout = stdout.as_slice();
+    let mut count = 0;
+    while !stdout.is_empty() {
+        let record: ciborium::Value = ciborium::from_reader(&mut stdout).unwrap();
+        assert!(record.is_map());
+        count += 1;
+    }
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn debounce_skips_unchanged_frames() {
+    let (_, stdout) = reader("solid:9,frames:4", &["-D", "-o", "metadata"]);
+    assert_eq!(records(&stdout).len(), 1);
+    let (_, stdout) = reader("noise,frames:4", &["-D", "-o", "metadata"]);
+    assert_eq!(records(&stdout).len(), 4);
+}
+
+#[test]
+fn length_prefixed_framing() {
+    let (_, stdout) = reader(
+        "frames:3",
+        &["-o", "metadata", "--framing", "length-prefixed"],
+    );
+    let mut rest = stdout
+        .strip_prefix(b"asimov-framing: length-prefixed/1\n".as_slice())
+        .unwrap();
+    let mut count = 0;
+    while let Some((len, tail)) = rest.split_first_chunk::<4>() {
+        let (record, tail) = tail.split_at(u32::from_be_bytes(*len) as usize);
+        serde_json::from_slice::<serde_json::Value>(record).unwrap();
+        rest = tail;
+        count += 1;
+    }
+    assert!(rest.is_empty());
+    assert_eq!(count, 3);
+}
+
+#[test]
+fn nquads_output() {
+    let (_, stdout) = reader("frames:1", &["-o", "nquads"]);
+    let stdout = String::from_utf8(stdout).unwrap();
+    assert!(stdout.lines().all(|line| line.ends_with(" .")));
+    assert!(stdout.contains("<https://know.dev/Image>"));
+}
+
+#[test]
+fn saves_frames_in_any_pixel_format() {
+    let dir = scratch_dir("reader-save-bgra8");
+    let (_, stdout) = reader(
+        "gradient,frames:2",
+        &[
+            "--pixel-format",
+            "bgra8",
+            "--save-dir",
+            dir.to_str().unwrap(),
+            "-o",
+            "jsonld-ref",
+        ],
+    );
+    let records = records(&stdout);
+    assert_eq!(records.len(), 2);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+}
+
+#[test]
+fn lists_formats_as_json() {
+    let (code, stdout) = reader("frame", &["--list-formats", "json"]);
+    assert_eq!(code, 0);
+    let modes = records(&stdout);
+    assert_eq!(modes.len(), 7);
+    assert!(
+        modes
+            .iter()
+            .all(|m| m["backend"] == "mock" && m["width"] == 160)
+    );
+}
+
+#[test]
+fn dry_run_prints_the_plan_without_capturing() {
+    let (code, stdout) = reader("frame", &["--dry-run", "--pixel-format", "gray16"]);
+    assert_eq!(code, 0);
+    assert_eq!(
+        String::from_utf8(stdout).unwrap(),
+        "device: mock:fps:10,frame,wait:300ms,error:unplugged\n\
+         backend: mock\n\
+         mode: 160x120 @ 1-50 fps (gray16)\n\
+         plan: play 'fps:10,frames:1,wait:300ms,error:unplugged' as 160x120 gray16 frames\n"
+    );
+}
+
+#[test]
+fn stops_after_max_frames() {
+    let (code, stdout) = reader(
+        "noise,frames:20",
+        &["-o", "metadata", "--max-frames", "3", "--workers", "2"],
+    );
+    assert_eq!(code, 0);
+    assert_eq!(records(&stdout).len(), 3);
+}
+
+#[test]
+fn stops_after_the_duration() {
+    let started = std::time::Instant::now();
+    let (code, stdout) = reader(
+        "noise,frames:100",
+        &["-o", "metadata", "--duration", "500ms"],
+    );
+    assert_eq!(code, 0);
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    let records = records(&stdout);
+    assert!(
+        (1..10).contains(&records.len()),
+        "{} records",
+        records.len()
+    );
+}
+
+#[test]
+fn probe_reports_the_first_frame() {
+    let (code, stdout) = reader("frame", &["--probe", "--pixel-format", "bgra8"]);
+    assert_eq!(code, 0);
+    let stdout = String::from_utf8(stdout).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "{stdout}");
+    assert_eq!(lines[1], "backend: mock");
+    assert!(lines[2].starts_with("first frame: ") && lines[2].ends_with(" ms"));
+    assert_eq!(lines[3], "format: 160x120 bgra8 (stride 640)");
+}
+
+#[test]
+fn probe_fails_without_a_frame() {
+    let (code, stdout) = reader("wait:2s", &["--probe", "200ms"]);
+    assert_eq!(code, 69);
+    assert!(stdout.is_empty());
+}
+
+#[test]
+fn photos_refuse_masks() {
+    let (code, stdout) = reader("frames:1", &["--photo", "still.jpg", "--mask", "0,0,8x8"]);
+    assert_eq!(code, 2);
+    assert!(stdout.is_empty());
+}
+
+#[test]
+fn undistorts_frames_with_a_calibration() {
+    let (code, stdout) = reader(
+        "noise,frames:2",
+        &[
+            "-o",
+            "metadata",
+            "--undistort",
+            "160x120:150,150,80,60:-0.2,0.05",
+        ],
+    );
+    assert_eq!(code, 74);
+    let frames = records(&stdout);
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0]["width"], 160);
+
+    // The file --calibrate writes works as a configuration file.
+    let dir = scratch_dir("undistort-config");
+    let config = dir.join("calibration.toml");
+    std::fs::write(&config, "undistort = \"160x120:150,150,80,60\"\n").unwrap();
+    let (code, stdout) = reader(
+        "noise,frames:2",
+        &["-o", "metadata", "--config", config.to_str().unwrap()],
+    );
+    assert_eq!(code, 74);
+    assert_eq!(records(&stdout).len(), 2);
+
+    let (code, stdout) = reader("frames:2", &["--undistort", "160x120:150,150"]);
+    assert_eq!(code, 2);
+    assert!(stdout.is_empty());
+}
+
+#[test]
+fn calibration_fails_without_a_checkerboard() {
+    let dir = scratch_dir("calibrate");
+    let output = dir.join("calibration.toml");
+    let (code, stdout) = reader(
+        "gradient,frames:3,end",
+        &[
+            "--calibrate",
+            output.to_str().unwrap(),
+            "--calibrate-board",
+            "7x5",
+        ],
+    );
+    assert_eq!(code, 69);
+    assert!(stdout.is_empty());
+    assert!(!output.exists());
+}
+
+#[test]
+fn adds_focus_metrics_to_records() {
+    let sharpness = |script: &str, args: &[&str]| {
+        let (code, stdout) = reader(
+            script,
+            &[&["-o", "metadata", "--focus-metrics"], args].concat(),
+        );
+        assert_eq!(code, 74);
+        let frames = records(&stdout);
+        assert_eq!(frames.len(), 2);
+        let histogram = frames[0]["focus"]["histogram"].as_array().unwrap();
+        assert_eq!(histogram.len(), 16);
+        frames[0]["focus"]["sharpness"].as_f64().unwrap()
+    };
+    let noise = sharpness("noise,frames:2", &[]);
+    let gradient = sharpness("gradient,frames:2", &[]);
+    assert!(noise > gradient, "{noise} {gradient}");
+    sharpness("noise,frames:2", &["--focus-region", "40,30,80x60"]);
+
+    let (code, _) = reader("frames:2", &["--focus-region", "40,30,80x60"]);
+    assert_eq!(code, 64);
+    let (code, _) = reader(
+        "frames:2",
+        &["--focus-metrics", "--focus-region", "100,0,80x60"],
+    );
+    assert_eq!(code, 64);
+}
+
+#[test]
+fn focus_assist_prints_the_score() {
+    let (code, stdout) = reader("noise,frames:5,end", &["--focus-assist"]);
+    assert_eq!(code, 0);
+    let stdout = String::from_utf8(stdout).unwrap();
+    assert!(!stdout.is_empty());
+    for line in stdout.lines() {
+        assert!(line.starts_with("sharpness "), "{line}");
+        assert!(line.contains(" peak ") && line.ends_with('|'), "{line}");
+    }
+}
+
+#[test]
+fn locks_auto_controls_after_convergence() {
+    let output = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--device", "mock:fps:10,frames:5,wait:300ms,end"])
+        .args(["-s", "160x120", "-o", "metadata", "--events"])
+        .args(["--ae-lock-after", "100ms", "--awb-lock-after", "200ms"])
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(records(&output.stdout).len(), 5);
+    let locks: Vec<_> = records(&output.stderr)
+        .into_iter()
+        .filter(|event| event["event"] == "AutoLockChanged")
+        .map(|event| (event["control"].clone(), event["locked"].clone()))
+        .collect();
+    assert_eq!(
+        locks,
+        [
+            ("exposure".into(), true.into()),
+            ("white balance".into(), true.into()),
+            ("exposure".into(), false.into()),
+            ("white balance".into(), false.into()),
+        ]
+    );
+}
+
+#[test]
+fn buffered_records_are_flushed_at_exit() {
+    for policy in ["never", "interval=10s"] {
+        let (code, stdout) = reader("noise,frames:3", &["-o", "metadata", "--flush", policy]);
+        assert_eq!(code, 74);
+        assert_eq!(records(&stdout).len(), 3, "--flush {policy}");
+    }
+}
+
+/// Records into a file through a stand-in ffmpeg that copies its stdin.
+#[cfg(unix)]
+#[test]
+fn records_alongside_stdout() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = scratch_dir("record");
+    let ffmpeg = dir.join("ffmpeg");
+    std::fs::write(
+        &ffmpeg,
+        "#!/bin/sh\nfor last; do :; done\ncat > \"$last\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let video = dir.join("out.mp4");
+    let output = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args([
+            "--device",
+            "mock:fps:10,noise,frames:4,wait:300ms,error:unplugged",
+        ])
+        .args(["-s", "160x120", "-o", "metadata", "--record"])
+        .arg(&video)
+        .env("PATH", format!("{}:/bin:/usr/bin", dir.display()))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(74));
+    assert_eq!(records(&output.stdout).len(), 4);
+    assert_eq!(std::fs::read(&video).unwrap().len(), 4 * 160 * 120 * 3);
+}
+
+/// Kills the reader outright while a stand-in ffmpeg captures for it.
+#[cfg(all(target_os = "linux", feature = "ffmpeg"))]
+#[test]
+fn ffmpeg_dies_with_a_killed_reader() {
+    use std::{os::unix::fs::PermissionsExt, time::Duration};
+
+    let dir = scratch_dir("orphan");
+    let ffmpeg = dir.join("ffmpeg");
+    let pid_file = dir.join("pid");
+    std::fs::write(
+        &ffmpeg,
+        format!(
+            "#!/bin/sh\necho $$ > '{}'\nexec sleep 30\n",
+            pid_file.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--backend", "ffmpeg", "--device", "/dev/video0"])
+        .args(["-s", "160x120"])
+        .env("PATH", format!("{}:/bin:/usr/bin", dir.display()))
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let pid = (0..50)
+        .find_map(|_| {
+            std::thread::sleep(Duration::from_millis(100));
+            let pid = std::fs::read_to_string(&pid_file).ok()?;
+            Some(pid.trim().to_string()).filter(|pid| !pid.is_empty())
+        })
+        .expect("ffmpeg was never started");
+    child.kill().unwrap();
+    child.wait().unwrap();
+    // Gone, or a zombie where nothing reaps orphans.
+    let ended = || {
+        std::fs::read_to_string(format!("/proc/{pid}/stat")).map_or(true, |stat| {
+            stat.rsplit(')')
+                .next()
+                .unwrap()
+                .trim_start()
+                .starts_with('Z')
+        })
+    };
+    assert!(
+        (0..20).any(|_| {
+            std::thread::sleep(Duration::from_millis(100));
+            ended()
+        }),
+        "ffmpeg outlived the reader"
+    );
+}
+
+#[test]
+fn serves_mjpeg_alongside_stdout() {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpStream,
+        process::Stdio,
+    };
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--device", "mock:fps:20,noise,frames:100", "-s", "160x120"])
+        .args(["-o", "metadata", "--serve-mjpeg", "127.0.0.1:0", "-v"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Kept open, since the reader goes on logging to it.
+    let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+    let addr = stderr
+        .by_ref()
+        .map_while(Result::ok)
+        .find_map(|line| {
+            let url = line.strip_prefix("INFO: MJPEG stream at http://")?;
+            Some(url.trim_end_matches('/').to_string())
+        })
+        .unwrap();
+    let mut stream = TcpStream::connect(&addr).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut received = Vec::new();
+    let mut buf = [0; 8192];
+    while received
+        .windows(4)
+        .filter(|w| w == b"\xff\xd8\xff\xe0")
+        .count()
+        < 2
+    {
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n > 0, "stream ended early");
+        received.extend_from_slice(&buf[..n]);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    let head = String::from_utf8_lossy(&received[..200]);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains("multipart/x-mixed-replace; boundary="));
+    assert!(head.contains("Content-Type: image/jpeg"));
+}
+
+#[test]
+fn emits_one_frame_per_trigger() {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpStream,
+        process::Stdio,
+    };
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--device", "mock:fps:10,noise,frames:20,error:unplugged"])
+        .args(["-s", "160x120", "-o", "metadata", "-v"])
+        .args(["--trigger", "stdin", "--trigger", "http:127.0.0.1:0"])
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+    let addr = stderr
+        .by_ref()
+        .map_while(Result::ok)
+        .find_map(|line| {
+            let url = line.strip_prefix("INFO: triggers at http://")?;
+            Some(url.trim_end_matches("/capture").to_string())
+        })
+        .unwrap();
+    std::thread::spawn(move || stderr.for_each(drop));
+
+    let mut stream = TcpStream::connect(&addr).unwrap();
+    stream
+        .write_all(b"POST /capture HTTP/1.1\r\nContent-Length: 0\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 202 Accepted\r\n"));
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"capture\n").unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(74));
+    assert_eq!(records(&output.stdout).len(), 2);
+}
+
+#[test]
+fn fires_the_cameras_trigger() {
+    use std::{io::Write, process::Stdio};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--device", "mock:noise,frames:2,error:unplugged"])
+        .args(["-s", "160x120", "-o", "metadata"])
+        .args(["--hardware-trigger", "--trigger", "stdin"])
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"capture\ncapture\n").unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(74));
+    let records = records(&output.stdout);
+    assert_eq!(records.len(), 2);
+    for (i, record) in records.iter().enumerate() {
+        assert_eq!(record["trigger"]["sequence"], i + 1);
+        assert!(record["trigger"]["firedAt"].is_u64());
+    }
+}
+
+/// A reader capturing noise with a `--control` socket in a scratch
+/// directory, and a client sending one command and reading the reply.
+#[cfg(unix)]
+fn control_session(
+    name: &str,
+    args: &[&str],
+) -> (
+    std::process::Child,
+    PathBuf,
+    impl FnMut(&str) -> String + use<>,
+) {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixStream,
+        process::Stdio,
+    };
+
+    let dir = scratch_dir(name);
+    let socket = dir.join("reader.sock");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--device", "mock:fps:20,noise,frames:1000", "-s", "160x120"])
+        .args(["-o", "metadata", "-v", "--control"])
+        .arg(&socket)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+    stderr
+        .by_ref()
+        .map_while(Result::ok)
+        .find(|line| line.starts_with("INFO: control socket at"))
+        .unwrap();
+    std::thread::spawn(move || stderr.for_each(drop));
+
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    let mut replies = BufReader::new(stream.try_clone().unwrap()).lines();
+    let send = move |command: &str| {
+        writeln!(stream, "{command}").unwrap();
+        replies.next().unwrap().unwrap()
+    };
+    (child, dir, send)
+}
+
+#[cfg(unix)]
+#[test]
+fn answers_control_commands() {
+    let (mut child, dir, mut send) =
+        control_session("control", &["--crop", "0,0,80x60", "--mask", "0,0,8x8"]);
+    let status = |reply: String| -> serde_json::Value {
+        serde_json::from_str(reply.strip_prefix("ok ").unwrap()).unwrap()
+    };
+
+    assert_eq!(status(send("status"))["mode"], "capturing");
+    assert_eq!(send("pause"), "ok");
+    assert_eq!(status(send("status"))["mode"], "paused");
+    assert_eq!(send("resume"), "ok");
+
+    // Snapshots are cropped (and masked) like the other outputs' frames.
+    let snapshot = dir.join("snapshot.png");
+    assert_eq!(
+        send(&format!("snapshot {}", snapshot.display())),
+        format!("ok {}", snapshot.display())
+    );
+    let png = std::fs::read(&snapshot).unwrap();
+    assert!(png.starts_with(b"\x89PNG"));
+    assert_eq!(
+        (&png[16..20], &png[20..24]),
+        (&80u32.to_be_bytes()[..], &60u32.to_be_bytes()[..])
+    );
+
+    assert!(send("reconfigure size=320x240").starts_with("ok 320x240 at "));
+    assert!(send("reconfigure size=big").starts_with("error: "));
+    assert!(send("rotate-recording").contains("not recording"));
+    assert!(send("reboot").starts_with("error: unknown command 'reboot'"));
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(all(unix, feature = "encryption"))]
+#[test]
+fn encrypts_control_snapshots() {
+    let key_dir = scratch_dir("control-encrypt-key");
+    let key = key_dir.join("camera.key");
+    std::fs::write(&key, "5a".repeat(32)).unwrap();
+    let frames = key_dir.join("frames");
+    let (mut child, dir, mut send) = control_session(
+        "control-encrypt",
+        &[
+            "--encrypt-key",
+            key.to_str().unwrap(),
+            "--save-dir",
+            frames.to_str().unwrap(),
+        ],
+    );
+    let snapshot = dir.join("snapshot.png");
+    let sealed = dir.join("snapshot.png.enc");
+    assert_eq!(
+        send(&format!("snapshot {}", snapshot.display())),
+        format!("ok {}", sealed.display())
+    );
+    assert!(!snapshot.exists());
+    assert!(!std::fs::read(&sealed).unwrap().starts_with(b"\x89PNG"));
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--encrypt-key", key.to_str().unwrap(), "--decrypt"])
+        .arg(&sealed)
+        .output()
+        .unwrap();
+    assert!(output.stdout.starts_with(b"\x89PNG"));
+}
+
+#[cfg(unix)]
+fn signal(child: &std::process::Child, name: &str) {
+    let status = Command::new("kill")
+        .args([&format!("-{name}"), &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn reloads_the_configuration_on_sighup() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::Stdio,
+    };
+
+    let dir = scratch_dir("reload");
+    let config = dir.join("asimov-camera.toml");
+    std::fs::write(
+        &config,
+        "[reader]\nsize = \"160x120\"\noutput = \"metadata\"\n",
+    )
+    .unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--device", "mock:fps:20,noise,frames:1000", "--config"])
+        .arg(&config)
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut width = || {
+        let line = stdout.next().unwrap().unwrap();
+        serde_json::from_str::<serde_json::Value>(&line).unwrap()["width"]
+            .as_u64()
+            .unwrap()
+    };
+    assert_eq!(width(), 160);
+
+    std::fs::write(
+        &config,
+        "[reader]\nsize = \"320x240\"\noutput = \"metadata\"\n",
+    )
+    .unwrap();
+    signal(&child, "HUP");
+    assert!((0..100).any(|_| width() == 320));
+
+    signal(&child, "USR2");
+    let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+    let stats = stderr
+        .by_ref()
+        .map_while(Result::ok)
+        .find(|line| line.starts_with("stats: "))
+        .unwrap();
+    assert!(stats.contains("320x240 at 30 fps"), "{stats}");
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(unix)]
+#[test]
+fn reloads_masks_on_sighup() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::Stdio,
+    };
+
+    let dir = scratch_dir("reload-masks");
+    let config = dir.join("asimov-camera.toml");
+    std::fs::write(&config, "[reader]\noutput = \"metadata\"\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args([
+            "--device",
+            "mock:fps:20,solid:200,frames:1000",
+            "-s",
+            "160x120",
+        ])
+        .arg("--config")
+        .arg(&config)
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut hash = || {
+        let line = stdout.next().unwrap().unwrap();
+        serde_json::from_str::<serde_json::Value>(&line).unwrap()["hash"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+    let unmasked = hash();
+
+    // Blacking out the left half gives the solid frames an edge to hash.
+    std::fs::write(
+        &config,
+        "[reader]\noutput = \"metadata\"\nmask = \"0,0,80x120\"\n",
+    )
+    .unwrap();
+    signal(&child, "HUP");
+    assert!((0..100).any(|_| hash() != unmasked));
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(unix)]
+#[test]
+fn reloads_the_debounce_on_sighup() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::Stdio,
+        sync::mpsc,
+        time::Duration,
+    };
+
+    let dir = scratch_dir("reload-debounce");
+    let config = dir.join("asimov-camera.toml");
+    std::fs::write(&config, "[reader]\noutput = \"metadata\"\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args([
+            "--device",
+            "mock:fps:20,solid:200,frames:1000",
+            "-s",
+            "160x120",
+            "-v",
+        ])
+        .arg("--config")
+        .arg(&config)
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let lines = |stream: Box<dyn std::io::Read + Send>| {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                let _ = tx.send(line);
+            }
+        });
+        rx
+    };
+    let records = lines(Box::new(child.stdout.take().unwrap()));
+    let log = lines(Box::new(child.stderr.take().unwrap()));
+    // Without a debounce, every identical frame is emitted.
+    for _ in 0..3 {
+        records.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    std::fs::write(
+        &config,
+        "[reader]\noutput = \"metadata\"\ndebounce-distance = 1\n",
+    )
+    .unwrap();
+    signal(&child, "HUP");
+    assert!(
+        log.iter()
+            .any(|line| line.ends_with("reloaded the configuration: debounce distance 1"))
+    );
+    // Frames already past the debounce may still be written.
+    std::thread::sleep(Duration::from_millis(300));
+    let _ = records.try_iter().count();
+    std::thread::sleep(Duration::from_secs(1));
+    assert_eq!(records.try_iter().count(), 0);
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(unix)]
+#[test]
+fn reloading_keeps_live_reconfigures() {
+    let (mut child, _, mut send) = control_session("reload-keeps", &[]);
+    assert!(send("reconfigure size=320x240").starts_with("ok 320x240 at "));
+    // The command line still says 160x120, but it didn't change.
+    signal(&child, "HUP");
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(send("reconfigure fps=20").starts_with("ok 320x240 at 20 fps"));
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(unix)]
+#[test]
+fn emits_a_debounced_frame_on_sigusr1() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::Stdio,
+    };
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args([
+            "--device",
+            "mock:fps:20,solid:9,frames:1000",
+            "-s",
+            "160x120",
+        ])
+        .args(["-D", "-o", "metadata"])
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    stdout.next().unwrap().unwrap();
+    // Identical frames are debounced until the signal forces one through.
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    signal(&child, "USR1");
+    stdout.next().unwrap().unwrap();
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encrypts_saved_frames_and_decrypts_them_again() {
+    let dir = scratch_dir("reader-encrypt");
+    let key = dir.join("camera.key");
+    std::fs::write(&key, format!("{}\n", "5a".repeat(32))).unwrap();
+    let frames = dir.join("frames");
+    let (_, stdout) = reader(
+        "gradient,frames:2",
+        &[
+            "--save-dir",
+            frames.to_str().unwrap(),
+            "--encrypt-key",
+            key.to_str().unwrap(),
+            "-o",
+            "metadata",
+        ],
+    );
+    let records = records(&stdout);
+    assert_eq!(records.len(), 2);
+    let file = records[0]["file"].as_str().unwrap();
+    assert!(file.ends_with(".png.enc"), "{file}");
+    assert!(!std::fs::read(file).unwrap().starts_with(b"\x89PNG"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--encrypt-key", key.to_str().unwrap(), "--decrypt", file])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.starts_with(b"\x89PNG"));
+}
+
+#[cfg(feature = "provenance")]
+#[test]
+fn signs_the_digest_of_each_record() {
+    use asimov_camera_module::shared::{frame_message, verify_signature};
+
+    let dir = scratch_dir("reader-provenance");
+    let key = dir.join("seed");
+    std::fs::write(&key, [3u8; 32]).unwrap();
+    let (_, stdout) = reader(
+        "gradient,frames:2",
+        &["--signing-key", key.to_str().unwrap(), "-o", "metadata"],
+    );
+    let records = records(&stdout);
+    assert_eq!(records.len(), 2);
+    let hex = |field: &str| -> Vec<u8> {
+        let hex = records[0][field].as_str().unwrap();
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    };
+    let digest: [u8; 32] = hex("sha256").try_into().unwrap();
+    let message = frame_message(records[0]["id"].as_str().unwrap(), &digest);
+    assert!(verify_signature(
+        &hex("publicKey").try_into().unwrap(),
+        message.as_bytes(),
+        &hex("signature").try_into().unwrap(),
+    ));
+}
+
+#[test]
+fn bundles_frames_of_synchronized_cameras() {
+    let output = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--device", "mock:fps:10,frames:4"])
+        .args(["--sync-device", "mock:fps:10,noise,frames:4"])
+        .args([
+            "-s",
+            "160x120",
+            "-o",
+            "metadata",
+            "--sync-tolerance",
+            "200ms",
+        ])
+        .args(["--max-frames", "2"])
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let records = records(&output.stdout);
+    assert_eq!(records.len(), 2);
+    for record in &records {
+        assert_eq!(record["@type"], "FrameBundle");
+        let frames = record["frames"].as_array().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|frame| frame.get("data").is_none()));
+        assert!(record["spread"].as_f64().unwrap() <= 0.2);
+    }
+}
+
+#[test]
+fn preprocesses_synchronized_frames() {
+    let output = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--device", "mock:fps:10,frames:4"])
+        .args(["--sync-device", "mock:fps:10,noise,frames:4"])
+        .args([
+            "-s",
+            "160x120",
+            "-o",
+            "metadata",
+            "--sync-tolerance",
+            "200ms",
+        ])
+        .args([
+            "--mask",
+            "0,0,40x40",
+            "--crop",
+            "0,0,80x60",
+            "--max-frames",
+            "1",
+        ])
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let records = records(&output.stdout);
+    assert_eq!(records.len(), 1);
+    for frame in records[0]["frames"].as_array().unwrap() {
+        assert_eq!(
+            (frame["width"].as_u64(), frame["height"].as_u64()),
+            (Some(80), Some(60))
+        );
+    }
+}
+
+#[cfg(feature = "stereo")]
+#[test]
+fn adds_stereo_statistics_to_bundles() {
+    let output = Command::new(env!("CARGO_BIN_EXE_asimov-camera-reader"))
+        .args(["--device", "mock:fps:10,frames:2"])
+        .args(["--sync-device", "mock:fps:10,frames:2"])
+        .args([
+            "-s",
+            "160x120",
+            "-o",
+            "metadata",
+            "--sync-tolerance",
+            "200ms",
+        ])
+        .args([
+            "--stereo",
+            "map",
+            "--stereo-disparities",
+            "16",
+            "--max-frames",
+            "1",
+        ])
+        .env_remove("ASIMOV_MODULE_FRAMING")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let records = records(&output.stdout);
+    assert_eq!(records.len(), 1);
+    let stereo = &records[0]["stereo"];
+    assert!(stereo["validFraction"].is_f64());
+    assert_eq!(stereo["map"]["format"], "gray16");
+    assert_eq!(stereo["map"]["width"], 160);
+}
@@ -0,0 +1,110 @@
+// This is free and unencumbered software released into the public domain.
+
+mod common;
+
+use asimov_camera_module::shared::{Retention, RetentionPolicy, available_space};
+use common::scratch_dir;
+use std::{
+    fs::File,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+/// Writes `len` bytes to `dir/name`, last modified `age` ago.
+fn write(dir: &Path, name: &str, len: usize, age: Duration) {
+    std::fs::write(dir.join(name), vec![0u8; len]).unwrap();
+    File::options()
+        .write(true)
+        .open(dir.join(name))
+        .unwrap()
+        .set_modified(SystemTime::now() - age)
+        .unwrap();
+}
+
+fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+fn pngs(dir: &Path) -> Retention {
+    let policy = RetentionPolicy {
+        min_free: 0,
+        ..Default::default()
+    };
+    Retention::new(policy).with_files(dir, |name| name.ends_with(".png"))
+}
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+#[test]
+fn deletes_expired_files_and_leaves_the_rest() {
+    let dir = scratch_dir("retention-age");
+    write(&dir, "1.png", 10, 3 * HOUR);
+    write(&dir, "2.png", 10, HOUR / 2);
+    write(&dir, "notes.txt", 10, 3 * HOUR);
+    let retention = Retention::new(RetentionPolicy {
+        max_age: Some(HOUR),
+        min_free: 0,
+        ..Default::default()
+    })
+    .with_files(&dir, |name| name.ends_with(".png"));
+
+    let sweep = retention.sweep().unwrap();
+    assert_eq!(
+        (sweep.deleted, sweep.freed_bytes, sweep.retained_bytes),
+        (1, 10, 10)
+    );
+    assert_eq!(names(&dir), ["2.png", "notes.txt"]);
+    assert_eq!(retention.sweep().unwrap().deleted, 0);
+}
+
+#[test]
+fn deletes_the_oldest_files_until_the_rest_fit() {
+    let dir = scratch_dir("retention-size");
+    write(&dir, "1.png", 100, 4 * HOUR);
+    write(&dir, "2.png", 100, 3 * HOUR);
+    write(&dir, "3.png", 100, 2 * HOUR);
+    write(&dir, "4.png", 100, HOUR);
+    let retention = Retention::new(RetentionPolicy {
+        max_bytes: Some(250),
+        min_free: 0,
+        ..Default::default()
+    })
+    .with_files(&dir, |name| name.ends_with(".png"));
+
+    let sweep = retention.sweep().unwrap();
+    assert_eq!(
+        (sweep.deleted, sweep.retained_bytes, sweep.failed),
+        (2, 200, 0)
+    );
+    assert_eq!(names(&dir), ["3.png", "4.png"]);
+}
+
+#[test]
+fn skips_directories_that_dont_exist_yet() {
+    let dir = scratch_dir("retention-missing").join("later");
+    let sweep = pngs(&dir).sweep().unwrap();
+    assert_eq!((sweep.deleted, sweep.retained_bytes), (0, 0));
+    assert!(sweep.low_space.is_empty());
+}
+
+#[test]
+fn reports_low_space_once_until_it_recovers() {
+    let dir = scratch_dir("retention-low");
+    assert!(available_space(&dir).unwrap() > 0);
+    let retention = Retention::new(RetentionPolicy {
+        min_free: u64::MAX,
+        ..Default::default()
+    })
+    .with_files(&dir, |_| true);
+
+    let sweep = retention.sweep().unwrap();
+    assert_eq!(sweep.low_space.len(), 1);
+    assert_eq!(sweep.low_space[0].0, dir);
+    assert!(retention.sweep().unwrap().low_space.is_empty());
+    assert!(pngs(&dir).sweep().unwrap().low_space.is_empty());
+}
@@ -0,0 +1,93 @@
+// This is free and unencumbered software released into the public domain.
+
+use asimov_camera_module::shared::Sidecar;
+use core::time::Duration;
+use serde_json::json;
+
+const DJI_SRT: &str = "\u{feff}1\r\n\
+00:00:00,000 --> 00:00:00,033\r\n\
+<font size=\"28\">FrameCnt: 1, DiffTime: 33ms\r\n\
+2024-05-01 10:00:00.000\r\n\
+[iso : 100] [shutter : 1/500.0] [fnum : 280] [ev : 0] [latitude: 52.520008] [longitude: 13.404954] [rel_alt: 1.200 abs_alt: 40.300] </font>\r\n\
+\r\n\
+2\r\n\
+00:00:00,033 --> 00:00:00,066\r\n\
+<font size=\"28\">FrameCnt: 2, DiffTime: 33ms\r\n\
+[iso : 110] [latitude: 52.520010] [longitude: 13.404960] [rel_alt: 1.300 abs_alt: 40.400] </font>\r\n";
+
+#[test]
+fn parses_dji_srt() {
+    let sidecar = Sidecar::parse_srt(DJI_SRT).unwrap();
+    let entries = sidecar.entries();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].start, Duration::ZERO);
+    assert_eq!(entries[0].end, Duration::from_millis(33));
+
+    let fields = &entries[0].fields;
+    assert_eq!(fields["iso"], 100);
+    assert_eq!(fields["shutter"], "1/500.0");
+    assert_eq!(fields["latitude"], 52.520008);
+    assert_eq!(fields["rel_alt"], 1.2);
+    assert_eq!(fields["abs_alt"], 40.3);
+    assert_eq!(fields["FrameCnt"], 1);
+    assert_eq!(fields["DiffTime"], "33ms");
+    assert_eq!(entries[1].fields["iso"], 110);
+}
+
+#[test]
+fn parses_gps_triples_and_plain_cues() {
+    let sidecar = Sidecar::parse_srt(
+        "1\n00:00:01,000 --> 00:00:02,000\nGPS (13.4049, 52.5200, 40), BAROMETER: 12.5\n\n\
+         2\n00:00:02,000 --> 00:00:03,000\nTake off\n",
+    )
+    .unwrap();
+    let gps = &sidecar.entries()[0].fields;
+    assert_eq!(gps["longitude"], 13.4049);
+    assert_eq!(gps["latitude"], 52.52);
+    assert_eq!(gps["altitude"], 40);
+    assert_eq!(gps["BAROMETER"], 12.5);
+    assert_eq!(sidecar.entries()[1].fields["text"], "Take off");
+
+    assert!(Sidecar::parse_srt("1\nnot a time\nhello\n").is_err());
+}
+
+#[test]
+fn parses_json_sidecars() {
+    let array = Sidecar::parse_json(
+        r#"[{"time": 1.0, "lat": 2}, {"time": 0, "lat": 1}, {"time": 2.0, "duration": 0.5, "lat": 3}]"#,
+    )
+    .unwrap();
+    let lines = Sidecar::parse_json(
+        "{\"time\": 0, \"lat\": 1}\n{\"time\": 1.0, \"lat\": 2}\n{\"time\": 2.0, \"duration\": 0.5, \"lat\": 3}\n",
+    )
+    .unwrap();
+    assert_eq!(array, lines);
+
+    let entries = array.entries();
+    assert_eq!(
+        entries[0].fields,
+        json!({"lat": 1}).as_object().unwrap().clone()
+    );
+    assert_eq!(entries[0].end, Duration::from_secs(1));
+    assert_eq!(entries[2].end, Duration::from_millis(2500));
+
+    assert!(Sidecar::parse_json(r#"[{"lat": 1}]"#).is_err());
+    assert!(Sidecar::parse_json("[1, 2]").is_err());
+}
+
+#[test]
+fn looks_up_entries_by_offset() {
+    let sidecar =
+        Sidecar::parse_json(r#"[{"time": 0, "n": 0}, {"time": 1, "duration": 1, "n": 1}]"#)
+            .unwrap();
+    let n = |ms| {
+        sidecar
+            .at(Duration::from_millis(ms))
+            .map(|e| e.fields["n"].clone())
+    };
+    assert_eq!(n(0), Some(json!(0)));
+    assert_eq!(n(999), Some(json!(0)));
+    assert_eq!(n(1000), Some(json!(1)));
+    assert_eq!(n(1999), Some(json!(1)));
+    assert_eq!(n(2000), None);
+}
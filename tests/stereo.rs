@@ -0,0 +1,139 @@
+// This is free and unencumbered software released into the public domain.
+
+#![cfg(feature = "stereo")]
+
+use asimov_camera_module::shared::{
+    DISPARITY_SCALE, Frame, FrameBundle, FrameStream, PixelFormat, StereoConfig, StereoMatcher,
+};
+use bytes::Bytes;
+use std::sync::{Arc, Mutex};
+
+/// Deterministic texture, so every block is distinct.
+fn texture(x: u32, y: u32) -> u8 {
+    let n = x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663);
+    (n.wrapping_mul(2_654_435_761) >> 24) as u8
+}
+
+/// A rectified pair of a flat scene `disparity` pixels away from the
+/// right camera.
+fn pair(width: u32, height: u32, disparity: u32) -> (Frame, Frame) {
+    let frame = |shift: u32| {
+        let data: Vec<u8> = (0..height)
+            .flat_map(|y| (0..width).flat_map(move |x| [texture(x + shift, y); 3]))
+            .collect();
+        Frame::new(
+            Bytes::from(data),
+            width,
+            height,
+            width * 3,
+            PixelFormat::Rgb8,
+        )
+    };
+    (frame(0), frame(disparity))
+}
+
+fn samples(frame: &Frame) -> Vec<u16> {
+    frame
+        .data
+        .chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect()
+}
+
+#[test]
+fn rejects_invalid_configs() {
+    assert!(StereoMatcher::new(StereoConfig::new().with_block_size(8)).is_err());
+    assert!(StereoMatcher::new(StereoConfig::new().with_max_disparity(1)).is_err());
+    assert!(StereoMatcher::new(StereoConfig::new().with_calibration(700.0, 0.0)).is_err());
+    assert!(StereoMatcher::new(StereoConfig::new()).is_ok());
+}
+
+#[test]
+fn finds_the_disparity_of_a_shifted_scene() {
+    let (left, right) = pair(96, 48, 7);
+    let matcher = StereoMatcher::new(StereoConfig::new().with_max_disparity(16)).unwrap();
+    let result = matcher.compute(&left, &right).unwrap();
+
+    let disparity = &result.disparity;
+    assert_eq!((disparity.width, disparity.height), (96, 48));
+    assert_eq!(disparity.pixel_format, PixelFormat::Gray16);
+    assert_eq!(disparity.stream, FrameStream::Depth);
+    assert!(result.depth.is_none());
+
+    let stats = result.stats;
+    assert!(stats.valid_fraction > 0.5, "{stats:?}");
+    // Subpixel refinement keeps a match within half a pixel of the best.
+    let near = |d: f32| (d - 7.0).abs() <= 0.5;
+    assert!(near(stats.median_disparity.unwrap()), "{stats:?}");
+    let valid = samples(disparity).into_iter().filter(|&d| d != 0);
+    assert!(valid.map(|d| d as f32 / DISPARITY_SCALE).all(near));
+}
+
+#[test]
+fn leaves_flat_areas_out() {
+    let flat = Frame::new(
+        Bytes::from(vec![128; 64 * 32 * 3]),
+        64,
+        32,
+        64 * 3,
+        PixelFormat::Rgb8,
+    );
+    let matcher = StereoMatcher::new(StereoConfig::new().with_max_disparity(16)).unwrap();
+    let stats = matcher.compute(&flat, &flat).unwrap().stats;
+    assert_eq!(stats.valid_fraction, 0.0);
+    assert_eq!(stats.median_disparity, None);
+}
+
+#[test]
+fn computes_depth_with_a_calibration() {
+    let (left, right) = pair(96, 48, 8);
+    let config = StereoConfig::new()
+        .with_max_disparity(16)
+        .with_calibration(400.0, 0.06);
+    let result = StereoMatcher::new(config)
+        .unwrap()
+        .compute(&left, &right)
+        .unwrap();
+    // 400 px * 0.06 m / 8 px = 3 m.
+    let stats = result.stats;
+    let expected = 24.0 / stats.median_disparity.unwrap();
+    assert!((stats.median_depth_m.unwrap() - expected).abs() < 1e-3);
+    assert!(
+        (stats.median_depth_m.unwrap() - 3.0).abs() < 0.2,
+        "{stats:?}"
+    );
+    let depth = result.depth.unwrap();
+    assert_eq!(depth.pixel_format, PixelFormat::Z16);
+    let depths = samples(&depth).into_iter().filter(|&mm| mm != 0);
+    assert!(depths.map(|mm| mm.abs_diff(3000)).all(|error| error < 200));
+}
+
+#[test]
+fn rejects_frames_of_different_sizes() {
+    let (left, _) = pair(96, 48, 0);
+    let (right, _) = pair(64, 48, 0);
+    let matcher = StereoMatcher::new(StereoConfig::new()).unwrap();
+    assert!(matcher.compute(&left, &right).is_err());
+}
+
+#[test]
+fn sinks_match_bundles() {
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let on_result = {
+        let results = Arc::clone(&results);
+        Arc::new(move |result| results.lock().unwrap().push(result))
+    };
+    let matcher = StereoMatcher::new(StereoConfig::new().with_max_disparity(16)).unwrap();
+    let sink = matcher.sink(on_result);
+
+    let (left, right) = pair(96, 48, 3);
+    sink(FrameBundle {
+        frames: vec![left.clone(), right],
+    });
+    sink(FrameBundle { frames: vec![left] });
+
+    let results = results.lock().unwrap();
+    let median = results[0].as_ref().unwrap().stats.median_disparity;
+    assert!((median.unwrap() - 3.0).abs() <= 0.5);
+    assert!(results[1].is_err());
+}
@@ -0,0 +1,105 @@
+// This is free and unencumbered software released into the public domain.
+
+#![cfg(feature = "webhook")]
+
+mod common;
+
+use asimov_camera_module::shared::{Webhook, render_template};
+use common::{Request, serve};
+use core::time::Duration;
+use serde_json::json;
+use std::{net::TcpListener, thread::JoinHandle};
+
+#[test]
+fn fills_templates_from_the_payload() {
+    let payload = json!({
+        "message": "motion on \"garage\"\n",
+        "score": 0.25,
+        "record": {"boundingBox": {"width": 128}},
+        "tags": ["a", "b"],
+    });
+    assert_eq!(
+        render_template(
+            r#"{"text":"{{message}}","score":{{ score }},"w":{{record.boundingBox.width}}}"#,
+            &payload
+        ),
+        r#"{"text":"motion on \"garage\"\n","score":0.25,"w":128}"#
+    );
+    assert_eq!(
+        render_template("{{tags.1}} {{tags}} [{{missing}}] {{open", &payload),
+        r#"b ["a","b"] [] {{open"#
+    );
+}
+
+#[test]
+fn knows_what_slack_and_discord_take() {
+    let payload = json!({"message": "Stalled on cam", "kind": "event"});
+    let slack = Webhook::new("https://hooks.slack.com/services/T0/B0/secret").unwrap();
+    assert_eq!(slack.body(&payload), r#"{"text":"Stalled on cam"}"#);
+    let discord = Webhook::new("https://discord.com/api/webhooks/1/secret").unwrap();
+    assert_eq!(discord.body(&payload), r#"{"content":"Stalled on cam"}"#);
+    let other = Webhook::new("http://homeassistant.local:8123/api/webhook/camera").unwrap();
+    assert_eq!(other.body(&payload), payload.to_string());
+    let custom = other.with_template("{{kind}}: {{message}}");
+    assert_eq!(custom.body(&payload), "event: Stalled on cam");
+
+    assert!(Webhook::new("ftp://example.com/").is_err());
+    // The path of a webhook URL is its secret.
+    assert!(!format!("{slack:?}").contains("secret"));
+}
+
+/// Answers one request per response, in order, returning what it got.
+fn server(responses: &[&'static str]) -> (String, JoinHandle<Vec<Request>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook?id=1", listener.local_addr().unwrap());
+    let mut responses = Vec::from(responses).into_iter();
+    let count = responses.len();
+    let thread = serve(listener, count, move |_| responses.next().unwrap().into());
+    (url, thread)
+}
+
+#[test]
+fn retries_until_delivered() {
+    let (url, server) = server(&[
+        "HTTP/1.1 429 Too Many Requests\r\nretry-after: 0\r\ncontent-length: 0\r\n\r\n",
+        "HTTP/1.1 502 Bad Gateway\r\ncontent-length: 0\r\n\r\n",
+        "HTTP/1.1 204 No Content\r\n\r\n",
+    ]);
+    let hook = Webhook::new(&url)
+        .unwrap()
+        .with_header("Authorization", "Bearer token")
+        .with_retries(3, Duration::from_millis(10));
+    assert_eq!(hook.post(&json!({"event": "Motion"})).unwrap(), 3);
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests.len(), 3);
+    let request = &requests[2];
+    assert!(
+        request.head.starts_with("POST /hook?id=1 HTTP/1.1\r\n"),
+        "{}",
+        request.head
+    );
+    assert_eq!(request.header("content-type"), Some("application/json"));
+    assert_eq!(request.header("Authorization"), Some("Bearer token"));
+    assert_eq!(request.body, b"{\"event\":\"Motion\"}");
+}
+
+#[test]
+fn does_not_retry_a_rejected_payload() {
+    let (url, server) =
+        server(&["HTTP/1.1 400 Bad Request\r\ncontent-length: 12\r\n\r\ninvalid_text"]);
+    let hook = Webhook::new(&url)
+        .unwrap()
+        .with_retries(3, Duration::from_millis(10));
+    let err = hook.post(&json!({})).unwrap_err().to_string();
+    assert!(err.contains("HTTP 400: invalid_text"), "{err}");
+    assert_eq!(server.join().unwrap().len(), 1);
+}
+
+#[test]
+fn posts_templated_bodies() {
+    let (url, server) = server(&["HTTP/1.1 204 No Content\r\n\r\n"]);
+    let hook = Webhook::new(&url).unwrap().with_template("{{event}} seen");
+    assert_eq!(hook.post(&json!({"event": "Motion"})).unwrap(), 1);
+    assert_eq!(server.join().unwrap()[0].body, b"Motion seen");
+}